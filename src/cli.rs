@@ -0,0 +1,671 @@
+//! Module related to the command line interface.
+//!
+//! The binary only calls [`main`], which parses the arguments and dispatches them to the
+//! handlers. This module is not part of the library API.
+
+use anyhow::Result;
+use atty::Stream;
+use clap::{self, AppSettings, ArgMatches};
+use env_logger;
+use std::{
+    convert::TryFrom,
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{compl, config, domain, man, output, ui};
+
+use config::{Account, Config};
+use domain::{
+    account::{account_arg, account_handler},
+    backend::{build_backend, build_sender, Backend, Sender},
+    backup::{backup_arg, backup_handler},
+    daemon::{daemon_arg, daemon_client, daemon_handler},
+    filter::{filter_arg, filter_handler},
+    imap::{imap_arg, imap_handler},
+    mbox::{mbox_arg, mbox_handler, Mbox},
+    msg::{
+        flag_arg, flag_handler, label_arg, label_handler, msg_arg, msg_handler, part_arg,
+        part_handler, tpl_arg, tpl_handler,
+    },
+    queue::{queue_arg, queue_handler},
+    refile::{refile_arg, refile_handler},
+    report::{report_arg, report_handler},
+    sent::{sent_arg, sent_handler},
+    sieve::{sieve_arg, sieve_handler},
+    snooze::{snooze_arg, snooze_handler},
+};
+use output::{output_error, output_interrupt, output_trace, OutputFmt, OutputService};
+use ui::{
+    theme,
+    tui::{tui_arg, tui_handler},
+};
+
+fn create_app<'a>() -> clap::App<'a, 'a> {
+    clap::App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .setting(AppSettings::GlobalVersion)
+        .args(&config::config_arg::args())
+        .args(&output::output_arg::args())
+        .args(&daemon_arg::args())
+        .arg(mbox_arg::source_arg())
+        .arg(msg_arg::use_seq_arg())
+        .subcommands(compl::compl_arg::subcmds())
+        .subcommands(config::config_arg::subcmds())
+        .subcommands(man::man_arg::subcmds())
+        .subcommands(account_arg::subcmds())
+        .subcommands(backup_arg::subcmds())
+        .subcommands(daemon_arg::subcmds())
+        .subcommands(filter_arg::subcmds())
+        .subcommands(imap_arg::subcmds())
+        .subcommands(mbox_arg::subcmds())
+        .subcommands(msg_arg::subcmds())
+        .subcommands(queue_arg::subcmds())
+        .subcommands(refile_arg::subcmds())
+        .subcommands(report_arg::subcmds())
+        .subcommands(sent_arg::subcmds())
+        .subcommands(sieve_arg::subcmds())
+        .subcommands(snooze_arg::subcmds())
+        .subcommands(tui_arg::subcmds())
+}
+
+fn init_logger(level: &str) {
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, level),
+    );
+}
+
+/// Initialize the logs and the protocol traces from the given arguments.
+fn init_logs(m: &ArgMatches) -> Result<()> {
+    // Logs are enabled by an explicit log level, or by the debug or verbose flags.
+    let level = if m.occurrences_of("log-level") > 0 {
+        m.value_of("log-level").unwrap_or("info")
+    } else if m.is_present("debug") {
+        "debug"
+    } else if m.is_present("verbose") {
+        "info"
+    } else {
+        "off"
+    };
+    init_logger(level);
+
+    if m.is_present("debug") || m.is_present("trace") {
+        output_trace::init(m.value_of("trace").map(Path::new))?;
+    }
+    Ok(())
+}
+
+/// Edit a message from the given mailto URL, with the account, mailbox and config of the other
+/// arguments.
+fn mailto(url: &str, m: clap::ArgMatches) -> Result<()> {
+    init_logs(&m)?;
+    let overrides: Vec<&str> = m
+        .values_of("set")
+        .map(Iterator::collect)
+        .unwrap_or_default();
+    let mbox = Mbox::try_from(m.value_of("mailbox"))?;
+    let config = Config::try_from((m.value_of("config"), overrides.as_slice()))?;
+    let account = Account::try_from((&config, m.value_of("account")))?;
+    let output = OutputService::from("plain");
+    let mut backend = build_backend(&account, &mbox, false);
+    let mut sender = build_sender(&account);
+    msg_handler::mailto(url, &account, &output, backend.as_mut(), sender.as_mut())
+}
+
+/// Run the CLI from the arguments of the process, exiting on error.
+pub fn main() {
+    // Check mailto match BEFORE app initialization, so that the URL can be combined with the
+    // global options (eg. `himalaya -a work mailto:…`).
+    let mut raw_args: Vec<String> = env::args().collect();
+    let mailto_pos = raw_args
+        .iter()
+        .skip(1)
+        .position(|arg| arg.to_lowercase().starts_with("mailto:"));
+    if let Some(pos) = mailto_pos {
+        let url = raw_args.remove(pos + 1);
+        let m = create_app().get_matches_from(raw_args);
+        if let Err(err) = mailto(&url, m) {
+            output_error::exit(err, &OutputFmt::Plain);
+        }
+        return;
+    }
+
+    let m = create_app().get_matches();
+    let fmt = OutputFmt::from(m.value_of("output").unwrap_or_default());
+    if let Err(err) = run(m) {
+        output_error::exit(err, &fmt);
+    }
+}
+
+fn run(m: clap::ArgMatches) -> Result<()> {
+    init_logs(&m)?;
+    output_interrupt::init();
+
+    // Colors are disabled by the no-color flag or by a non-empty NO_COLOR env var.
+    let no_color_env = env::var_os("NO_COLOR").map_or(false, |val| !val.is_empty());
+    if m.is_present("no-color") || no_color_env {
+        theme::disable_colors();
+    }
+
+    // Check completion match BEFORE entities and services initialization.
+    // Linked issue: https://github.com/soywod/himalaya/issues/115.
+    let compl_cmd = compl::compl_arg::matches(&m)?;
+    if let Some(compl::compl_arg::Command::Generate(shell)) = compl_cmd {
+        return compl::compl_handler::generate(create_app(), shell);
+    }
+
+    // Check man match BEFORE entities and services initialization, for packagers.
+    if let Some(man::man_arg::Command::Generate) = man::man_arg::matches(&m)? {
+        return man::man_handler::generate(create_app());
+    }
+
+    // Serve the command with the daemon when it is running, so that the sessions stay warm.
+    if let Some(res) = daemon_client::run(&m) {
+        return res;
+    }
+
+    let overrides: Vec<&str> = m
+        .values_of("set")
+        .map(Iterator::collect)
+        .unwrap_or_default();
+
+    // Check config matches BEFORE the config is parsed, so that errors end up in the report.
+    match config::config_arg::matches(&m)? {
+        Some(config::config_arg::Command::Check(connect)) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            return config::config_handler::check(
+                m.value_of("config"),
+                &overrides,
+                connect,
+                &output,
+            );
+        }
+        Some(config::config_arg::Command::Export(path, redact_secrets)) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            return config::config_handler::export(
+                m.value_of("config"),
+                path,
+                redact_secrets,
+                &output,
+            );
+        }
+        Some(config::config_arg::Command::Import(path, force)) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            return config::config_handler::import(m.value_of("config"), path, force, &output);
+        }
+        None => (),
+    }
+
+    // Launch the first-run wizard when no config exists.
+    if m.value_of("config").is_none() && atty::is(Stream::Stdin) {
+        if let Ok(path) = Config::path() {
+            if !path.exists() {
+                config::config_wizard::run(&path)?;
+            }
+        }
+    }
+
+    let mbox = Mbox::try_from(m.value_of("mailbox"))?;
+    let config = Config::try_from((m.value_of("config"), overrides.as_slice()))?;
+    if let Some(ref theme) = config.theme {
+        theme::init(theme)?;
+    }
+
+    // Check dynamic completion matches, called by the completion scripts.
+    match compl_cmd {
+        Some(compl::compl_arg::Command::Accounts) => {
+            return compl::compl_handler::accounts(&config);
+        }
+        Some(compl::compl_arg::Command::Flags) => {
+            return compl::compl_handler::flags();
+        }
+        Some(compl::compl_arg::Command::Mboxes) => {
+            let account = Account::try_from((&config, m.value_of("account")))?;
+            let mut backend = build_backend(&account, &mbox, false);
+            return compl::compl_handler::mboxes(&account, backend.as_mut());
+        }
+        _ => (),
+    }
+
+    // Check account matches not needing the selected account.
+    match account_arg::matches(&m)? {
+        Some(account_arg::Command::List) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            return account_handler::list(&config, &output);
+        }
+        Some(account_arg::Command::Add) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            let path = match m.value_of("config") {
+                Some(path) => PathBuf::from(path),
+                None => Config::path()?,
+            };
+            return account_handler::add(&path, &config, &output);
+        }
+        _ => (),
+    }
+
+    // Check daemon matches.
+    match daemon_arg::matches(&m)? {
+        Some(daemon_arg::Command::Start) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            return daemon_handler::start(
+                m.value_of("config"),
+                &config,
+                create_app,
+                dispatch,
+                &output,
+            );
+        }
+        Some(daemon_arg::Command::Stop) => {
+            let output = OutputService::try_from(m.value_of("output"))?;
+            return daemon_handler::stop(&output);
+        }
+        None => (),
+    }
+
+    let account = Account::try_from((&config, m.value_of("account")))?;
+    let output = OutputService::try_from(m.value_of("output"))?.with_tpl(
+        m.value_of("format")
+            .map(String::from)
+            .or_else(|| account.list_format.to_owned()),
+    );
+
+    // Check account matches.
+    match account_arg::matches(&m)? {
+        Some(account_arg::Command::Info(name)) => {
+            let account = match name {
+                Some(name) => Account::try_from((&config, Some(name)))?,
+                None => account,
+            };
+            let mut backend = build_backend(&account, &mbox, false);
+            return account_handler::info(&account, &output, backend.as_mut());
+        }
+        Some(account_arg::Command::SetPassword) => {
+            return account_handler::set_password(&account, &output);
+        }
+        Some(account_arg::Command::Doctor(name)) => {
+            let account = match name {
+                Some(name) => Account::try_from((&config, Some(name)))?,
+                None => account,
+            };
+            let mut backend = build_backend(&account, &mbox, false);
+            let mut sender = build_sender(&account);
+            return account_handler::doctor(&account, &output, backend.as_mut(), sender.as_mut());
+        }
+        _ => (),
+    }
+
+    // Check TUI matches.
+    match tui_arg::matches(&m)? {
+        Some(tui_arg::Command::Start) => {
+            return tui_handler::start(&mbox, &account, &output);
+        }
+        _ => (),
+    }
+
+    // Long-running commands leave the account unlocked, they would block all the other ones.
+    let _lock = match m.subcommand_name() {
+        _ if m.is_present("no-lock") => None,
+        Some("notify") | Some("watch") => None,
+        _ => Some(account.lock_commands(m.is_present("wait"))?),
+    };
+
+    // Check sieve matches.
+    match sieve_arg::matches(&m)? {
+        Some(sieve_arg::Command::List) => {
+            return sieve_handler::list(&account, &output);
+        }
+        Some(sieve_arg::Command::Get(name)) => {
+            return sieve_handler::get(name, &account, &output);
+        }
+        Some(sieve_arg::Command::Put(name, path)) => {
+            return sieve_handler::put(name, path, &account, &output);
+        }
+        Some(sieve_arg::Command::Activate(name)) => {
+            return sieve_handler::activate(name, &account, &output);
+        }
+        _ => (),
+    }
+
+    // Check backup matches.
+    match backup_arg::matches(&m)? {
+        Some(backup_arg::Command::Backup(glob, dir, rate)) => {
+            return backup_handler::backup(glob, dir, rate, &account, &output);
+        }
+        Some(backup_arg::Command::Restore(dir, rate)) => {
+            return backup_handler::restore(dir, rate, &account, &output);
+        }
+        _ => (),
+    }
+
+    // Check queue matches.
+    match queue_arg::matches(&m)? {
+        Some(queue_arg::Command::List) => {
+            return queue_handler::list(&account, &output);
+        }
+        Some(queue_arg::Command::Flush) => {
+            let mut backend = build_backend(&account, &mbox, m.is_present("use-seq"));
+            let mut sender = build_sender(&account);
+            return queue_handler::flush(&account, &output, backend.as_mut(), sender.as_mut());
+        }
+        Some(queue_arg::Command::Doctor(opts)) => {
+            let mut backend = build_backend(&account, &mbox, m.is_present("use-seq"));
+            let mut sender = build_sender(&account);
+            return queue_handler::doctor(
+                opts,
+                &config,
+                &account,
+                &output,
+                backend.as_mut(),
+                sender.as_mut(),
+            );
+        }
+        Some(queue_arg::Command::Release(id)) => {
+            return queue_handler::release(id, &account, &output);
+        }
+        None => (),
+    }
+
+    // Check refile matches.
+    match refile_arg::matches(&m)? {
+        Some(refile_arg::Command::Refile(rules_path, dry_run, override_hold)) => {
+            return refile_handler::refile(rules_path, dry_run, override_hold, &account, &output);
+        }
+        None => (),
+    }
+
+    // Check sent matches.
+    match sent_arg::matches(&m)? {
+        Some(sent_arg::Command::Log(query)) => {
+            return sent_handler::log(query, &account, &output);
+        }
+        Some(sent_arg::Command::Search(opts)) => {
+            let mbox = Mbox::from(account.sent_folder.as_str());
+            let mut backend = build_backend(&account, &mbox, m.is_present("use-seq"));
+            let mut sender = build_sender(&account);
+            return sent_handler::search(
+                opts,
+                &mbox,
+                &account,
+                &output,
+                backend.as_mut(),
+                sender.as_mut(),
+            );
+        }
+        None => (),
+    }
+
+    let mut backend = build_backend(&account, &mbox, m.is_present("use-seq"));
+    let mut sender = build_sender(&account);
+    dispatch(
+        &m,
+        &config,
+        &account,
+        &mbox,
+        &output,
+        backend.as_mut(),
+        sender.as_mut(),
+    )
+}
+
+/// Dispatch the commands talking to the backend or the sender of the account.
+fn dispatch(
+    m: &ArgMatches,
+    config: &Config,
+    account: &Account,
+    mbox: &Mbox,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    // Check IMAP matches.
+    match imap_arg::matches(m)? {
+        Some(imap_arg::Command::Notify(keepalive, standby)) => {
+            return imap_handler::notify(keepalive, standby, config, backend);
+        }
+        Some(imap_arg::Command::Watch(keepalive)) => {
+            return imap_handler::watch(keepalive, output, backend);
+        }
+        Some(imap_arg::Command::Exec(cmd)) => {
+            return imap_handler::exec(cmd, output, backend);
+        }
+        Some(imap_arg::Command::Capabilities) => {
+            return imap_handler::capabilities(output, backend);
+        }
+        _ => (),
+    }
+
+    // Check filter matches.
+    match filter_arg::matches(m)? {
+        Some(filter_arg::Command::Apply(target)) => {
+            return filter_handler::apply(target, mbox, account, output);
+        }
+        Some(filter_arg::Command::ExportSieve) => {
+            return filter_handler::export_sieve(account, output);
+        }
+        _ => (),
+    }
+
+    // Check report matches.
+    if let Some(report_arg::Command::InboxAge(top)) = report_arg::matches(m)? {
+        return report_handler::inbox_age(top, mbox, account, output, backend);
+    }
+
+    // Check mailbox matches.
+    match mbox_arg::matches(m)? {
+        Some(mbox_arg::Command::List) => {
+            return mbox_handler::list(account, output, backend);
+        }
+        Some(mbox_arg::Command::Expunge(target, override_hold)) => {
+            return mbox_handler::expunge(target, override_hold, mbox, account, output, backend);
+        }
+        Some(mbox_arg::Command::GetAcl(name)) => {
+            return mbox_handler::get_acl(name, output, backend);
+        }
+        Some(mbox_arg::Command::SetAcl(name, identifier, rights)) => {
+            return mbox_handler::set_acl(name, identifier, rights, output, backend);
+        }
+        Some(mbox_arg::Command::Stats(target, top)) => {
+            return mbox_handler::stats(target, top, mbox, account, output, backend);
+        }
+        Some(mbox_arg::Command::Quota(target)) => {
+            return mbox_handler::quota(target, mbox, output, backend);
+        }
+        _ => (),
+    }
+
+    // Check snooze matches.
+    match snooze_arg::matches(m)? {
+        Some(snooze_arg::Command::Snooze(seq, when, override_hold)) => {
+            return snooze_handler::snooze(
+                seq,
+                when,
+                override_hold,
+                mbox,
+                account,
+                output,
+                backend,
+            );
+        }
+        Some(snooze_arg::Command::Wake) => {
+            return snooze_handler::wake(account, output);
+        }
+        _ => (),
+    }
+
+    // Check message matches.
+    match msg_arg::matches(m)? {
+        Some(msg_arg::Command::Am(seq_range, dir)) => {
+            return msg_handler::am(seq_range, dir, output, backend);
+        }
+        Some(msg_arg::Command::Archive(seq_range, override_hold)) => {
+            return msg_handler::archive(seq_range, override_hold, account, output, backend);
+        }
+        Some(msg_arg::Command::Attachments(seq)) => {
+            return msg_handler::attachments(seq, account, output, backend);
+        }
+        Some(msg_arg::Command::Bounce(seq, addrs)) => {
+            return msg_handler::bounce(seq, addrs, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Copy(seq, target)) => {
+            return msg_handler::copy(seq, target, output, backend);
+        }
+        Some(msg_arg::Command::Count(unseen)) => {
+            return msg_handler::count(unseen, mbox, output, backend);
+        }
+        Some(msg_arg::Command::Delete(seq, permanent, override_hold)) => {
+            return msg_handler::delete(
+                seq,
+                permanent,
+                override_hold,
+                mbox,
+                account,
+                output,
+                backend,
+            );
+        }
+        Some(msg_arg::Command::Diff(seq, other_seq, headers)) => {
+            return msg_handler::diff(seq, other_seq, headers, output, backend);
+        }
+        Some(msg_arg::Command::Dedup(opts)) => {
+            return msg_handler::dedup(opts, mbox, account, output, backend);
+        }
+        Some(msg_arg::Command::Export(seq_range, opts)) => {
+            return msg_handler::export(seq_range, opts, mbox, account, output, backend);
+        }
+        Some(msg_arg::Command::Journal(dir, interval)) => {
+            return msg_handler::journal(dir, interval, mbox, output, backend);
+        }
+        Some(msg_arg::Command::Import(path)) => {
+            return msg_handler::import(path, mbox, output, backend);
+        }
+        Some(msg_arg::Command::Rewrite(seq_range, opts)) => {
+            return msg_handler::rewrite(seq_range, opts, mbox, account, output, backend);
+        }
+        Some(msg_arg::Command::Ham(seq_range, override_hold)) => {
+            return msg_handler::ham(seq_range, override_hold, mbox, account, output, backend);
+        }
+        Some(msg_arg::Command::Hold(seq_range, release)) => {
+            return msg_handler::hold(seq_range, release, account, output, backend);
+        }
+        Some(msg_arg::Command::Forward(seq, opts)) => {
+            return msg_handler::forward(seq, opts, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::List(opts)) => {
+            return msg_handler::list(opts, mbox, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Move(seq, target)) => {
+            return msg_handler::move_(seq, target, output, backend);
+        }
+        Some(msg_arg::Command::Pipe(seq, cmd, text)) => {
+            return msg_handler::pipe(seq, cmd, text, backend);
+        }
+        Some(msg_arg::Command::Read(seq, opts)) => {
+            return msg_handler::read(seq, opts, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Reply(seq, opts)) => {
+            return msg_handler::reply(seq, opts, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Rsvp(seq, partstat)) => {
+            return msg_handler::rsvp(seq, partstat, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Save(target, msg)) => {
+            return msg_handler::save(target, msg, account, backend);
+        }
+        Some(msg_arg::Command::Search(opts)) => {
+            return msg_handler::search(opts, mbox, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Send(raw_msg, idempotency_key)) => {
+            return msg_handler::send(raw_msg, idempotency_key, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Spam(seq_range, override_hold)) => {
+            return msg_handler::spam(seq_range, override_hold, mbox, account, output, backend);
+        }
+        Some(msg_arg::Command::Summarize(seq, thread)) => {
+            return msg_handler::summarize(seq, thread, account, output, backend);
+        }
+        Some(msg_arg::Command::Thread(seq, digest)) => {
+            return msg_handler::thread(seq, digest, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Unsubscribe(seq)) => {
+            return msg_handler::unsubscribe(seq, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Write(opts)) => {
+            return msg_handler::write(opts, account, output, backend, sender);
+        }
+        Some(msg_arg::Command::Flag(m)) => match m {
+            Some(flag_arg::Command::Set(seq_range, flags, override_hold)) => {
+                return flag_handler::set(
+                    seq_range,
+                    flags,
+                    override_hold,
+                    account,
+                    output,
+                    backend,
+                );
+            }
+            Some(flag_arg::Command::Add(seq_range, flags)) => {
+                return flag_handler::add(seq_range, flags, output, backend);
+            }
+            Some(flag_arg::Command::Remove(seq_range, flags, override_hold)) => {
+                return flag_handler::remove(
+                    seq_range,
+                    flags,
+                    override_hold,
+                    account,
+                    output,
+                    backend,
+                );
+            }
+            _ => (),
+        },
+        Some(msg_arg::Command::Label(m)) => match m {
+            Some(label_arg::Command::Add(seq_range, labels)) => {
+                return label_handler::add(seq_range, labels, output, backend);
+            }
+            Some(label_arg::Command::List(seq_range)) => {
+                return label_handler::list(seq_range, output, backend);
+            }
+            Some(label_arg::Command::Remove(seq_range, labels, override_hold)) => {
+                return label_handler::remove(
+                    seq_range,
+                    labels,
+                    override_hold,
+                    account,
+                    output,
+                    backend,
+                );
+            }
+            _ => (),
+        },
+        Some(msg_arg::Command::Part(m)) => match m {
+            Some(part_arg::Command::List(seq)) => {
+                return part_handler::list(seq, output, backend);
+            }
+            Some(part_arg::Command::Get(token)) => {
+                return part_handler::get(token, output, backend);
+            }
+            _ => (),
+        },
+        Some(msg_arg::Command::Tpl(m)) => match m {
+            Some(tpl_arg::Command::New(tpl)) => {
+                return tpl_handler::new(tpl, account, output);
+            }
+            Some(tpl_arg::Command::Reply(seq, all, quote_match, tpl)) => {
+                return tpl_handler::reply(seq, all, quote_match, tpl, account, output, backend);
+            }
+            Some(tpl_arg::Command::Forward(seq, tpl)) => {
+                return tpl_handler::forward(seq, tpl, account, output, backend);
+            }
+            Some(tpl_arg::Command::Send(tpl, stdin)) => {
+                return tpl_handler::send(tpl, stdin, account, output, backend, sender);
+            }
+            _ => (),
+        },
+        _ => (),
+    }
+
+    backend.logout()
+}
@@ -3,7 +3,7 @@
 //! This module provides subcommands and a command matcher related to completion.
 
 use anyhow::Result;
-use clap::{self, App, Arg, ArgMatches, Shell, SubCommand};
+use clap::{self, App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
 use log::debug;
 
 type OptionShell<'a> = Option<&'a str>;
@@ -12,11 +12,32 @@ type OptionShell<'a> = Option<&'a str>;
 pub enum Command<'a> {
     /// Generate completion script for the given shell slice.
     Generate(OptionShell<'a>),
+    /// Print the names of the accounts, for dynamic completion.
+    Accounts,
+    /// Print the flags, for dynamic completion.
+    Flags,
+    /// Print the names of the mailboxes, for dynamic completion.
+    Mboxes,
 }
 
 /// Completion command matcher.
 pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
     if let Some(m) = m.subcommand_matches("completion") {
+        match m.subcommand_name() {
+            Some("accounts") => {
+                debug!("completion accounts command matched");
+                return Ok(Some(Command::Accounts));
+            }
+            Some("flags") => {
+                debug!("completion flags command matched");
+                return Ok(Some(Command::Flags));
+            }
+            Some("mailboxes") => {
+                debug!("completion mailboxes command matched");
+                return Ok(Some(Command::Mboxes));
+            }
+            _ => (),
+        }
         debug!("completion command matched");
         let shell = m.value_of("shell");
         debug!("shell: `{:?}`", shell);
@@ -31,7 +52,20 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
     vec![SubCommand::with_name("completion")
         .aliases(&["completions", "compl", "compe", "comp"])
         .about("Generates the completion script for the given shell")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .args(&[Arg::with_name("shell")
             .possible_values(&Shell::variants()[..])
-            .required(true)])]
+            .required(true)])
+        .subcommands(vec![
+            // Called by the generated scripts to complete dynamic values.
+            SubCommand::with_name("accounts")
+                .about("Prints the names of the accounts")
+                .setting(AppSettings::Hidden),
+            SubCommand::with_name("flags")
+                .about("Prints the flags")
+                .setting(AppSettings::Hidden),
+            SubCommand::with_name("mailboxes")
+                .about("Prints the names of the mailboxes, cached for an hour")
+                .setting(AppSettings::Hidden),
+        ])]
 }
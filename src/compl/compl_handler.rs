@@ -1,16 +1,222 @@
 //! Module related to completion handling.
 //!
-//! This module gathers all completion commands.  
+//! This module gathers all completion commands.
+//!
+//! The generated zsh and fish scripts complete mailboxes, accounts and flags by calling the
+//! hidden `completion mailboxes`, `completion accounts` and `completion flags` subcommands. The
+//! names of the mailboxes are cached, so that completing them does not wait for the server.
 
 use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
 use clap::{App, Shell};
-use std::{io, str::FromStr};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use crate::{
+    config::{Account, Config},
+    domain::{backend::Backend, storage::build_storage},
+};
+
+/// Define the storage key of the cached names of the mailboxes.
+const MBOXES_KEY: &str = "completion-mailboxes.json";
+
+/// Define how long the cached names of the mailboxes are used, in seconds.
+const MBOXES_TTL: i64 = 3600;
+
+/// Define the flags completed, besides the custom ones.
+const FLAGS: &[&str] = &["seen", "answered", "flagged", "deleted", "draft"];
+
+/// Represents the cached names of the mailboxes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MboxesCache {
+    fetched_at: i64,
+    names: Vec<String>,
+}
+
+/// Define the zsh functions completing dynamic values. The account given on the command line, if
+/// any, is used to complete the mailboxes.
+const ZSH_FNS: &str = r#"
+_himalaya_accounts() {
+    local -a accounts
+    accounts=(${(f)"$(himalaya completion accounts 2>/dev/null)"})
+    compadd -a accounts
+}
+
+_himalaya_mailboxes() {
+    local -a mboxes account
+    local i=${words[(I)-a|--account]}
+    (( i )) && account=(--account "${words[i+1]}")
+    mboxes=(${(f)"$(himalaya $account completion mailboxes 2>/dev/null)"})
+    compadd -a mboxes
+}
+
+_himalaya_flags() {
+    local -a flags
+    flags=(${(f)"$(himalaya completion flags 2>/dev/null)"})
+    compadd -a flags
+}
+"#;
+
+/// Define the fish completion of flag values.
+const FISH_FLAGS: &str = r#"complete -c himalaya -n "__fish_seen_subcommand_from flag; and __fish_seen_subcommand_from add set remove" -f -a "(himalaya completion flags 2>/dev/null)""#;
+
+/// Get the name of the completion function of the values of the given argument, if dynamic.
+fn dynamic_arg(line: &str) -> Option<&'static str> {
+    if line.contains("-m+[") || line.contains("--mailbox=[") {
+        Some("mailboxes")
+    } else if line.contains("-a+[") || line.contains("--account=[") {
+        Some("accounts")
+    } else {
+        None
+    }
+}
+
+/// Wire the given zsh script to the dynamic completions.
+fn wire_zsh(script: &str) -> String {
+    let mut lines = script.lines();
+    let mut wired = String::new();
+    if let Some(first) = lines.next() {
+        wired.push_str(first);
+        wired.push('\n');
+    }
+    wired.push_str(ZSH_FNS);
+    for line in lines {
+        match (dynamic_arg(line), line.strip_suffix("]' \\")) {
+            (Some(name), Some(line)) => {
+                wired.push_str(&format!("{}]: :_himalaya_{}' \\", line, name));
+            }
+            _ if line.contains(":flags -- ") && line.ends_with(":_files' \\") => {
+                wired.push_str(&line.replace(":_files' \\", ":_himalaya_flags' \\"));
+            }
+            _ => wired.push_str(line),
+        }
+        wired.push('\n');
+    }
+    wired
+}
+
+/// Wire the given fish script to the dynamic completions.
+fn wire_fish(script: &str) -> String {
+    let mut wired = String::new();
+    for line in script.lines() {
+        wired.push_str(line);
+        if !line.contains(" -a ") {
+            if line.contains(" -l mailbox") {
+                wired.push_str(r#" -r -f -a "(himalaya completion mailboxes 2>/dev/null)""#);
+            } else if line.contains(" -l account") {
+                wired.push_str(r#" -r -f -a "(himalaya completion accounts 2>/dev/null)""#);
+            }
+        }
+        wired.push('\n');
+    }
+    wired.push_str(FISH_FLAGS);
+    wired.push('\n');
+    wired
+}
 
 /// Generate completion script from the given [`clap::App`] for the given shell slice.
 pub fn generate<'a>(mut app: App<'a, 'a>, shell: Option<&'a str>) -> Result<()> {
     let shell = Shell::from_str(shell.unwrap_or_default())
         .map_err(|err| anyhow!(err))
         .context("cannot parse shell")?;
-    app.gen_completions_to("himalaya", shell, &mut io::stdout());
+    let mut script = vec![];
+    app.gen_completions_to("himalaya", shell, &mut script);
+    let script = String::from_utf8(script).context("cannot decode completion script")?;
+    let script = match shell {
+        Shell::Zsh => wire_zsh(&script),
+        Shell::Fish => wire_fish(&script),
+        _ => script,
+    };
+    io::stdout()
+        .write_all(script.as_bytes())
+        .context("cannot print completion script")
+}
+
+/// Print the given completion candidates, one per line.
+fn print<S: AsRef<str>>(candidates: &[S]) -> Result<()> {
+    let mut stdout = io::stdout();
+    for candidate in candidates {
+        writeln!(stdout, "{}", candidate.as_ref()).context("cannot print completion")?;
+    }
     Ok(())
 }
+
+/// Print the names of the accounts.
+pub fn accounts(config: &Config) -> Result<()> {
+    let mut names: Vec<&String> = config.accounts.keys().collect();
+    names.sort();
+    print(&names)
+}
+
+/// Print the flags.
+pub fn flags() -> Result<()> {
+    print(FLAGS)
+}
+
+/// Print the names of the mailboxes, from the cache when fresh enough. When the server cannot be
+/// reached, a stale cache is still used.
+pub fn mboxes(account: &Account, backend: &mut dyn Backend) -> Result<()> {
+    let storage = build_storage(account)?;
+    let cache: MboxesCache = match storage.get(MBOXES_KEY)? {
+        Some(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+            warn!("cannot parse {}: {}", MBOXES_KEY, err);
+            MboxesCache::default()
+        }),
+        None => MboxesCache::default(),
+    };
+    let now = Utc::now().timestamp();
+    if now - cache.fetched_at < MBOXES_TTL {
+        debug!("complete mailboxes from cache");
+        return print(&cache.names);
+    }
+
+    let names: Vec<String> = match backend.list_mboxes() {
+        Ok(mboxes) => mboxes.0.into_iter().map(|mbox| mbox.name).collect(),
+        Err(err) if !cache.names.is_empty() => {
+            warn!("cannot list mailboxes, using stale cache: {}", err);
+            return print(&cache.names);
+        }
+        Err(err) => return Err(err),
+    };
+    let cache = MboxesCache {
+        fetched_at: now,
+        names,
+    };
+    let content = serde_json::to_vec(&cache).context("cannot serialize mailboxes cache")?;
+    storage.set(MBOXES_KEY, &content)?;
+    print(&cache.names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_wire_dynamic_completions() {
+        let zsh = wire_zsh(concat!(
+            "#compdef himalaya\n",
+            "'-m+[Selects a specific mailbox]' \\\n",
+            "'--account=[Selects a specific account]' \\\n",
+            "'*::flags -- IMAP flags:_files' \\\n",
+            "'-o+[Defines the output format]: :(plain json)' \\\n",
+        ));
+        assert!(zsh.starts_with("#compdef himalaya\n\n_himalaya_accounts() {"));
+        assert!(zsh.contains("'-m+[Selects a specific mailbox]: :_himalaya_mailboxes' \\\n"));
+        assert!(zsh.contains("'--account=[Selects a specific account]: :_himalaya_accounts' \\\n"));
+        assert!(zsh.contains("'*::flags -- IMAP flags:_himalaya_flags' \\\n"));
+        assert!(zsh.contains("'-o+[Defines the output format]: :(plain json)' \\\n"));
+
+        let fish =
+            wire_fish("complete -c himalaya -s m -l mailbox -d 'Selects a specific mailbox'\n");
+        assert!(fish.starts_with(concat!(
+            "complete -c himalaya -s m -l mailbox -d 'Selects a specific mailbox'",
+            r#" -r -f -a "(himalaya completion mailboxes 2>/dev/null)""#,
+            "\n",
+        )));
+        assert!(fish.ends_with(&format!("{}\n", FISH_FLAGS)));
+    }
+}
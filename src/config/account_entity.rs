@@ -1,29 +1,133 @@
 use anyhow::{anyhow, Context, Error, Result};
 use lettre::transport::smtp::authentication::Credentials as SmtpCredentials;
 use log::{debug, trace};
-use std::{convert::TryFrom, env, fs, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    convert::TryFrom,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    config::{Config, DEFAULT_PAGE_SIZE, DEFAULT_SIG_DELIM},
-    output::run_cmd,
+    config::{
+        parse_size,
+        passwd::PasswdSource,
+        proxy::Proxy,
+        secrets,
+        system_mode::{self, Lock},
+        BackendKind, Config, CrmRule, NotifyRule, ReceiptPolicy, RecipientTpl, ReplyQuote,
+        SigPosition, SigRotation, StorageKind, DEFAULT_ARCHIVE_FOLDER, DEFAULT_DATETIME_FMT,
+        DEFAULT_DRAFTS_FOLDER, DEFAULT_HAM_LEARN_CMD, DEFAULT_HOLD_KEYWORD, DEFAULT_JUNK_FOLDER,
+        DEFAULT_MAX_RETRIES, DEFAULT_PAGE_SIZE, DEFAULT_QUOTA_WARNING, DEFAULT_REPLY_ATTRIBUTION,
+        DEFAULT_REPLY_ATTRIBUTION_DATE_FORMAT, DEFAULT_REPLY_QUOTE_CONTEXT,
+        DEFAULT_REPLY_QUOTE_PREFIX, DEFAULT_SENT_FOLDER, DEFAULT_SHARE_ATTACHMENT_SIZE,
+        DEFAULT_SIEVE_PORT, DEFAULT_SIG_DELIM, DEFAULT_SNOOZE_FOLDER, DEFAULT_SPAM_LEARN_CMD,
+    },
+    domain::{
+        autoconfig::{self, Autoconfig},
+        filter::FilterRule,
+        msg::{msg_attachment_reminder, msg_html, msg_sig, msg_sig_rotation},
+    },
 };
 
 /// Represent a user account.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Account {
     pub name: String,
     pub from: String,
     pub downloads_dir: PathBuf,
+    /// The text signature, delimiter included.
     pub sig: Option<String>,
+    /// The HTML signature, delimiter included.
+    pub html_sig: Option<String>,
+    pub sig_pos: SigPosition,
+    /// The rotation of the account signatures, if any.
+    pub sig_rotation: Option<SigRotation>,
     pub default_page_size: usize,
+    pub list_format: Option<String>,
+    /// The format of the dates of message listings.
+    pub datetime_fmt: String,
+    pub reply_quote: ReplyQuote,
+    pub reply_quote_context: usize,
+    pub reply_quote_prefix: String,
+    pub reply_attribution: String,
+    pub reply_attribution_date_format: String,
+    /// Whether text is sent as `format=flowed`.
+    pub format_flowed: bool,
+    pub spellcheck_cmd: Option<String>,
+    /// Whether recipients are checked before sending.
+    pub send_safety_check: bool,
+    pub internal_domains: Vec<String>,
+    /// The regexes matching a mention of an attachment.
+    pub attachment_reminder_patterns: Vec<String>,
+    pub read_receipt_policy: ReceiptPolicy,
+    pub summarize_cmd: Option<String>,
+    pub draft_suggest_cmd: Option<String>,
+    /// Recipient templates, with their template and signature already loaded.
+    pub recipient_tpls: Vec<RecipientTpl>,
+    /// CRM rules, account ones first.
+    pub crm_rules: Vec<CrmRule>,
+    /// Notify rules of the account first, then the global ones.
+    pub notify_rules: Vec<NotifyRule>,
+    /// Account templates, already loaded.
+    pub new_tpl: Option<String>,
+    pub reply_tpl: Option<String>,
+    pub forward_tpl: Option<String>,
+    pub archive_folder: String,
+    pub snooze_folder: String,
+    pub trash_folder: Option<String>,
+    pub sent_folder: String,
+    pub drafts_folder: String,
+    pub junk_folder: String,
+    /// The commands learning from the messages reported as spam or ham.
+    pub spam_learn_cmd: String,
+    pub ham_learn_cmd: String,
+    /// Whether listings show the spam score of messages.
+    pub show_spam_score: bool,
+    /// Whether sent messages are appended to the sent folder.
+    pub save_sent_copy: bool,
+    /// Whether listed envelopes are cached.
+    pub envelope_cache: bool,
+    /// The number of sessions fetching messages in parallel.
+    pub fetch_jobs: usize,
+    /// Whether messages failing to be sent are queued in the outbox.
+    pub outbox: bool,
+    /// The used part of a quota, in percent, from which saving messages warns.
+    pub quota_warning: u64,
+    /// The storage of the cache and the state.
+    pub storage: StorageKind,
+    /// The keyword of messages on hold.
+    pub hold_keyword: String,
+    /// Filter rules, the account ones coming before the global ones.
+    pub filters: Vec<FilterRule>,
+    /// Named search queries, the account ones overriding the global ones.
+    pub queries: BTreeMap<String, String>,
+    /// The headers added to composed messages, by name.
+    pub default_headers: BTreeMap<String, String>,
+    /// The size in bytes above which attachments are split across several messages.
+    pub split_attachment_size: Option<usize>,
+    pub share_cmd: Option<String>,
+    /// The size in bytes above which attachments are shared as links.
+    pub share_attachment_size: usize,
+    pub pre_send_cmd: Option<String>,
+    pub post_send_cmd: Option<String>,
     pub watch_cmds: Vec<String>,
+    /// The URL mail events are posted to.
+    pub webhook_url: Option<String>,
+    /// The Prometheus textfile the account metrics are written to.
+    pub metrics_file: Option<PathBuf>,
     pub default: bool,
     pub email: String,
+    /// The other identities of the account, by alias, with their signature already loaded.
+    pub identities: BTreeMap<String, Identity>,
+    pub backend: BackendKind,
 
     pub imap_host: String,
     pub imap_port: u16,
     pub imap_starttls: bool,
     pub imap_insecure: bool,
+    pub imap_compress: bool,
     pub imap_login: String,
     pub imap_passwd_cmd: String,
 
@@ -33,9 +137,120 @@ pub struct Account {
     pub smtp_insecure: bool,
     pub smtp_login: String,
     pub smtp_passwd_cmd: String,
+    /// The proxy IMAP, SMTP and ManageSieve connections go through.
+    pub proxy: Option<Proxy>,
+    /// How many times operations are retried on network errors.
+    pub max_retries: u32,
+    pub tls_ca_cert: Option<PathBuf>,
+    pub tls_client_cert: Option<PathBuf>,
+    pub tls_client_key: Option<PathBuf>,
+    pub imap_cert_fingerprint: Option<String>,
+    pub smtp_cert_fingerprint: Option<String>,
+    /// The `service/user` keyring entry holding the password, when no password command is
+    /// defined.
+    pub passwd_keyring: Option<String>,
+    /// The key of the password in the secrets file, when no password command is defined.
+    pub passwd_secret: Option<String>,
+    /// The encrypted file holding the secrets, and the command decrypting it.
+    pub secrets_file: Option<PathBuf>,
+    pub secrets_decrypt_cmd: Option<String>,
+    pub sendmail_cmd: Option<String>,
+
+    pub sieve_host: String,
+    pub sieve_port: u16,
+    pub sieve_starttls: bool,
+
+    pub graph_client_id: Option<String>,
+    pub graph_tenant: String,
+    pub graph_refresh_token_secret: Option<String>,
+
+    /// The S/MIME certificate and key of the account, and the certificates used to verify and
+    /// encrypt messages.
+    pub smime_cert: Option<PathBuf>,
+    pub smime_key: Option<PathBuf>,
+    pub smime_ca_cert: Option<PathBuf>,
+    pub smime_certs_dir: Option<PathBuf>,
+    pub smime_sign: bool,
+    pub smime_encrypt: bool,
+
+    /// The DKIM selector, signing domain and private key of messages sent via SMTP.
+    pub dkim_selector: Option<String>,
+    pub dkim_domain: String,
+    pub dkim_key: Option<PathBuf>,
+
+    /// Whether the system mode is enabled.
+    pub system_mode: bool,
+}
+
+/// Represent an identity of an account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub from: String,
+    pub email: String,
+    /// The text signature, delimiter included.
+    pub sig: Option<String>,
 }
 
 impl Account {
+    /// Find the identity the first of the given addresses belonging to the account refers to,
+    /// `None` when it is the account address or when none belongs to the account.
+    pub fn find_identity<S: AsRef<str>>(&self, addrs: &[S]) -> Option<&Identity> {
+        addrs.iter().find_map(|addr| {
+            let addr = addr.as_ref();
+            if addr.eq_ignore_ascii_case(&self.email) {
+                Some(None)
+            } else {
+                self.identities
+                    .values()
+                    .find(|id| id.email.eq_ignore_ascii_case(addr))
+                    .map(Some)
+            }
+        })?
+    }
+
+    /// Get the account as the given identity, given by alias or by address. Only the account
+    /// address and the ones of its identities are allowed.
+    pub fn with_identity(&self, identity: Option<&str>) -> Result<Cow<Self>> {
+        let identity = match identity.map(str::trim) {
+            None => return Ok(Cow::Borrowed(self)),
+            Some(identity) if identity.eq_ignore_ascii_case(&self.email) => {
+                return Ok(Cow::Borrowed(self))
+            }
+            Some(identity) => self
+                .identities
+                .get(identity)
+                .or_else(|| {
+                    self.identities
+                        .values()
+                        .find(|id| id.email.eq_ignore_ascii_case(identity))
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        r#"cannot use identity "{}": it is neither an alias nor an address of account "{}""#,
+                        identity,
+                        self.name
+                    )
+                })?,
+        };
+        debug!("use identity {} <{}>", identity.from, identity.email);
+
+        let mut account = self.clone();
+        account.from = identity.from.to_owned();
+        account.email = identity.email.to_owned();
+        if let Some(sig) = identity.sig.as_ref() {
+            account.html_sig = Some(msg_sig::text_to_html(sig));
+            account.sig = Some(sig.to_owned());
+        }
+        Ok(Cow::Owned(account))
+    }
+
+    /// Find the first recipient template matching one of the given addresses.
+    pub fn recipient_tpl<S: AsRef<str>>(&self, addrs: &[S]) -> Option<&RecipientTpl> {
+        self.recipient_tpls
+            .iter()
+            .find(|tpl| addrs.iter().any(|addr| tpl.matches(addr.as_ref())))
+    }
+
     pub fn address(&self) -> String {
         let name = &self.from;
         let has_special_chars = "()<>[]:;@.,".contains(|special_char| name.contains(special_char));
@@ -50,20 +265,99 @@ impl Account {
         }
     }
 
-    pub fn imap_passwd(&self) -> Result<String> {
-        let passwd = run_cmd(&self.imap_passwd_cmd).context("cannot run IMAP passwd cmd")?;
-        let passwd = passwd
-            .trim_end_matches(|c| c == '\r' || c == '\n')
-            .to_owned();
+    /// Return the directory where the account keeps its local state (tokens, caches…). The
+    /// directory is created if it does not exist yet.
+    pub fn cache_dir(&self) -> Result<PathBuf> {
+        let path = env::var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".cache")));
+        let mut path = match path {
+            Ok(path) => path,
+            // The system mode never keeps state in the shared temporary directory.
+            Err(_) if self.system_mode => {
+                return Err(anyhow!(
+                    "cannot find cache dir: `XDG_CACHE_HOME` and `HOME` env vars are not set"
+                ))
+            }
+            Err(_) => env::temp_dir(),
+        };
+        path.push("himalaya");
+        path.push(&self.name);
+        if self.system_mode {
+            system_mode::create_private_dir(&path)?;
+            system_mode::check_private(&path)?;
+        } else {
+            fs::create_dir_all(&path).context(format!("cannot create cache dir {:?}", path))?;
+        }
+        Ok(path)
+    }
+
+    /// Lock the cache directory, so that concurrent processes of the same user do not overwrite
+    /// each other's state. The lock is released when dropped.
+    pub fn lock_cache(&self) -> Result<Lock> {
+        Lock::acquire(self.cache_dir()?.join("lock"))
+    }
 
-        Ok(passwd)
+    /// Lock the account for the duration of a command, so that concurrent commands neither
+    /// corrupt the cache nor exceed the connection limits of the server. Without `wait`, it fails
+    /// right away if another command holds the lock.
+    pub fn lock_commands(&self, wait: bool) -> Result<Lock> {
+        Lock::acquire_held(self.cache_dir()?.join("command.lock"), wait).context(format!(
+            r#"cannot run command: another command is running for account "{}", retry with `--wait` or `--no-lock`"#,
+            self.name
+        ))
+    }
+
+    /// Get the secrets file of the account.
+    fn secrets_file(&self) -> Result<&Path> {
+        self.secrets_file
+            .as_deref()
+            .ok_or_else(|| anyhow!(r#"cannot find "secrets-file" of account "{}""#, self.name))
+    }
+
+    /// Get the secret of the given key from the secrets file.
+    pub fn secret(&self, key: &str) -> Result<String> {
+        secrets::get(
+            self.secrets_file()?,
+            self.secrets_decrypt_cmd.as_deref(),
+            key,
+        )
+    }
+
+    /// Find where the password is looked up: the given command, the secrets file, or the keyring
+    /// entry.
+    fn passwd_source<'a>(&'a self, cmd: &'a str, cmd_key: &str) -> Result<PasswdSource<'a>> {
+        match (
+            cmd,
+            self.passwd_secret.as_deref(),
+            self.passwd_keyring.as_deref(),
+        ) {
+            ("", Some(key), _) => Ok(PasswdSource::Secret {
+                file: self.secrets_file()?,
+                decrypt_cmd: self.secrets_decrypt_cmd.as_deref(),
+                key,
+            }),
+            ("", None, Some(entry)) => Ok(PasswdSource::Keyring(entry)),
+            ("", None, None) => Err(anyhow!(
+                r#"cannot find "{}", "passwd-cmd", "passwd-secret" nor "passwd-keyring" in account "{}""#,
+                cmd_key,
+                self.name
+            )),
+            (cmd, _, _) => Ok(PasswdSource::Cmd(cmd)),
+        }
+    }
+
+    pub fn imap_passwd(&self) -> Result<String> {
+        self.passwd_source(&self.imap_passwd_cmd, "imap-passwd-cmd")?
+            .get()
+            .context("cannot get IMAP password")
     }
 
     pub fn smtp_creds(&self) -> Result<SmtpCredentials> {
-        let passwd = run_cmd(&self.smtp_passwd_cmd).context("cannot run SMTP passwd cmd")?;
-        let passwd = passwd
-            .trim_end_matches(|c| c == '\r' || c == '\n')
-            .to_owned();
+        let passwd = self
+            .passwd_source(&self.smtp_passwd_cmd, "smtp-passwd-cmd")?
+            .get()
+            .context("cannot get SMTP password")?;
 
         Ok(SmtpCredentials::new(self.smtp_login.to_owned(), passwd))
     }
@@ -102,7 +396,20 @@ impl<'a> TryFrom<(&'a Config, Option<&str>)> for Account {
                     .and_then(|dir| shellexpand::full(dir).ok())
                     .map(|dir| PathBuf::from(dir.to_string()))
             })
-            .unwrap_or_else(|| env::temp_dir());
+            .unwrap_or_else(|| {
+                if config.system_mode.unwrap_or_default() {
+                    system_mode::runtime_dir()
+                } else {
+                    env::temp_dir()
+                }
+            });
+
+        // Provider settings only fill in the options left out of the account.
+        let profile = account.provider.map(|provider| provider.profile());
+        let imap_host = match (account.imap_host.as_str(), &profile) {
+            ("", Some(profile)) => profile.imap_host,
+            (host, _) => host,
+        };
 
         let default_page_size = account
             .default_page_size
@@ -117,23 +424,394 @@ impl<'a> TryFrom<(&'a Config, Option<&str>)> for Account {
             .as_ref()
             .or_else(|| config.signature_delimiter.as_ref())
             .unwrap_or(&default_sig_delim);
-        let sig = account
-            .signature
-            .as_ref()
-            .or_else(|| config.signature.as_ref());
+        // Texts can be given either as a path to a file or as the text itself.
+        let read_text = |text: &str| {
+            shellexpand::full(text)
+                .ok()
+                .and_then(|path| fs::read_to_string(path.to_string()).ok())
+                .unwrap_or_else(|| text.to_owned())
+        };
+        let expand_path = |path: &Path| {
+            path.to_str()
+                .and_then(|path| shellexpand::full(path).ok())
+                .map(|path| PathBuf::from(path.to_string()))
+                .unwrap_or_else(|| path.to_owned())
+        };
+        let read_sig = |path: Option<&String>, sig: Option<&String>| -> Result<Option<String>> {
+            match (path, sig) {
+                (Some(path), _) => {
+                    let path = shellexpand::full(path)
+                        .context(format!("cannot expand signature path {:?}", path))?;
+                    fs::read_to_string(path.as_ref())
+                        .map(Some)
+                        .context(format!("cannot read signature at {:?}", path))
+                }
+                (None, sig) => Ok(sig.map(|sig| read_text(sig))),
+            }
+        };
+        // The account signature, from a path or not, takes precedence over the global one.
+        let sig = match read_sig(account.signature_path.as_ref(), account.signature.as_ref())? {
+            Some(sig) => Some(sig),
+            None => read_sig(config.signature_path.as_ref(), config.signature.as_ref())?,
+        };
+        let html_sig = account
+            .signature_html
+            .as_deref()
+            .or_else(|| config.signature_html.as_deref())
+            .map(read_text);
         let sig = sig
-            .and_then(|sig| shellexpand::full(sig).ok())
-            .map(String::from)
-            .and_then(|sig| fs::read_to_string(sig).ok())
-            .or_else(|| sig.map(|sig| sig.to_owned()))
-            .map(|sig| format!("{}{}", sig_delim, sig.trim_end()));
+            .or_else(|| html_sig.as_deref().map(msg_html::to_text))
+            .map(|sig| msg_sig::with_delim(sig_delim, &sig));
+        let html_sig = match html_sig {
+            Some(html) => Some(msg_sig::html_with_delim(sig_delim, &html)),
+            None => sig.as_deref().map(msg_sig::text_to_html),
+        };
+        // Rotated signatures of the account take precedence over its signature, which takes
+        // precedence over the global rotated signatures.
+        let account_sig_set = account.signature.is_some()
+            || account.signature_path.is_some()
+            || account.signature_html.is_some();
+        let (rotated_sig_cmd, rotated_sigs) =
+            if account.signature_cmd.is_some() || account.signatures.is_some() {
+                (
+                    account.signature_cmd.as_deref(),
+                    account.signatures.as_deref(),
+                )
+            } else if account_sig_set {
+                (None, None)
+            } else {
+                (
+                    config.signature_cmd.as_deref(),
+                    config.signatures.as_deref(),
+                )
+            };
+        let rotated_sigs: Vec<String> = rotated_sigs
+            .iter()
+            .flatten()
+            .map(|sig| read_text(sig))
+            .collect();
+        let sig_rotation = account
+            .signature_rotation
+            .or(config.signature_rotation)
+            .unwrap_or_default();
+
+        // Account recipient templates take precedence over the global ones.
+        let recipient_tpls = account
+            .recipient_templates
+            .iter()
+            .chain(config.recipient_templates.iter())
+            .flatten()
+            .map(|tpl| RecipientTpl {
+                pattern: tpl.pattern.to_owned(),
+                template: tpl.template.as_deref().map(read_text),
+                signature: tpl
+                    .signature
+                    .as_deref()
+                    .map(|sig| msg_sig::with_delim(sig_delim, &read_text(sig))),
+                lang: tpl.lang.to_owned(),
+            })
+            .collect();
 
-        let account = Account {
+        // Whether STARTTLS is set, to know if discovered settings can override it.
+        let starttls_set = (
+            account.imap_starttls.is_some(),
+            account.smtp_starttls.is_some(),
+        );
+
+        let mut account = Account {
             name,
             from: account.name.as_ref().unwrap_or(&config.name).to_owned(),
             downloads_dir,
             sig,
+            html_sig,
+            sig_pos: account
+                .signature_position
+                .or(config.signature_position)
+                .unwrap_or_default(),
+            sig_rotation: if rotated_sigs.is_empty() || rotated_sig_cmd.is_some() {
+                None
+            } else {
+                Some(sig_rotation)
+            },
             default_page_size,
+            list_format: account
+                .list_format
+                .as_ref()
+                .or_else(|| config.list_format.as_ref())
+                .cloned(),
+            datetime_fmt: account
+                .datetime_fmt
+                .as_deref()
+                .or_else(|| config.datetime_fmt.as_deref())
+                .unwrap_or(DEFAULT_DATETIME_FMT)
+                .to_owned(),
+            reply_quote: account
+                .reply_quote
+                .or(config.reply_quote)
+                .unwrap_or_default(),
+            reply_quote_context: account
+                .reply_quote_context
+                .or(config.reply_quote_context)
+                .unwrap_or(DEFAULT_REPLY_QUOTE_CONTEXT),
+            reply_quote_prefix: account
+                .reply_quote_prefix
+                .as_deref()
+                .or_else(|| config.reply_quote_prefix.as_deref())
+                .unwrap_or(DEFAULT_REPLY_QUOTE_PREFIX)
+                .to_owned(),
+            reply_attribution: account
+                .reply_attribution
+                .as_deref()
+                .or_else(|| config.reply_attribution.as_deref())
+                .unwrap_or(DEFAULT_REPLY_ATTRIBUTION)
+                .to_owned(),
+            reply_attribution_date_format: account
+                .reply_attribution_date_format
+                .as_deref()
+                .or_else(|| config.reply_attribution_date_format.as_deref())
+                .unwrap_or(DEFAULT_REPLY_ATTRIBUTION_DATE_FORMAT)
+                .to_owned(),
+            format_flowed: account
+                .format_flowed
+                .or(config.format_flowed)
+                .unwrap_or_default(),
+            spellcheck_cmd: account
+                .spellcheck_cmd
+                .as_ref()
+                .or_else(|| config.spellcheck_cmd.as_ref())
+                .cloned(),
+            send_safety_check: account
+                .send_safety_check
+                .or(config.send_safety_check)
+                .unwrap_or_default(),
+            read_receipt_policy: account
+                .read_receipt_policy
+                .or(config.read_receipt_policy)
+                .unwrap_or_default(),
+            internal_domains: account
+                .internal_domains
+                .as_ref()
+                .or_else(|| config.internal_domains.as_ref())
+                .cloned()
+                .unwrap_or_else(|| {
+                    account
+                        .email
+                        .rsplit_once('@')
+                        .map(|(_, domain)| vec![domain.to_owned()])
+                        .unwrap_or_default()
+                }),
+            attachment_reminder_patterns: account
+                .attachment_reminder_patterns
+                .as_ref()
+                .or_else(|| config.attachment_reminder_patterns.as_ref())
+                .cloned()
+                .unwrap_or_else(|| {
+                    msg_attachment_reminder::DEFAULT_PATTERNS
+                        .iter()
+                        .map(|pattern| pattern.to_string())
+                        .collect()
+                }),
+            summarize_cmd: account
+                .summarize_cmd
+                .as_ref()
+                .or_else(|| config.summarize_cmd.as_ref())
+                .cloned(),
+            draft_suggest_cmd: account
+                .draft_suggest_cmd
+                .as_ref()
+                .or_else(|| config.draft_suggest_cmd.as_ref())
+                .cloned(),
+            recipient_tpls,
+            crm_rules: account
+                .crm_rules
+                .iter()
+                .chain(config.crm_rules.iter())
+                .flatten()
+                .cloned()
+                .collect(),
+            notify_rules: account
+                .notify_rules
+                .iter()
+                .chain(config.notify_rules.iter())
+                .flatten()
+                .cloned()
+                .collect(),
+            new_tpl: account
+                .new_template
+                .as_deref()
+                .or_else(|| config.new_template.as_deref())
+                .map(read_text),
+            reply_tpl: account
+                .reply_template
+                .as_deref()
+                .or_else(|| config.reply_template.as_deref())
+                .map(read_text),
+            forward_tpl: account
+                .forward_template
+                .as_deref()
+                .or_else(|| config.forward_template.as_deref())
+                .map(read_text),
+            archive_folder: account
+                .archive_folder
+                .as_deref()
+                .or_else(|| profile.as_ref().map(|p| p.archive_folder))
+                .or_else(|| config.archive_folder.as_deref())
+                .unwrap_or(DEFAULT_ARCHIVE_FOLDER)
+                .to_owned(),
+            snooze_folder: account
+                .snooze_folder
+                .as_deref()
+                .or_else(|| config.snooze_folder.as_deref())
+                .unwrap_or(DEFAULT_SNOOZE_FOLDER)
+                .to_owned(),
+            hold_keyword: account
+                .hold_keyword
+                .as_deref()
+                .or_else(|| config.hold_keyword.as_deref())
+                .unwrap_or(DEFAULT_HOLD_KEYWORD)
+                .to_owned(),
+            trash_folder: account
+                .trash_folder
+                .as_deref()
+                .or_else(|| profile.as_ref().map(|p| p.trash_folder))
+                .or_else(|| config.trash_folder.as_deref())
+                .map(String::from),
+            sent_folder: account
+                .sent_folder
+                .as_deref()
+                .or_else(|| profile.as_ref().map(|p| p.sent_folder))
+                .or_else(|| config.sent_folder.as_deref())
+                .unwrap_or(DEFAULT_SENT_FOLDER)
+                .to_owned(),
+            drafts_folder: account
+                .drafts_folder
+                .as_deref()
+                .or_else(|| profile.as_ref().map(|p| p.drafts_folder))
+                .or_else(|| config.drafts_folder.as_deref())
+                .unwrap_or(DEFAULT_DRAFTS_FOLDER)
+                .to_owned(),
+            junk_folder: account
+                .junk_folder
+                .as_deref()
+                .or_else(|| profile.as_ref().map(|p| p.junk_folder))
+                .or_else(|| config.junk_folder.as_deref())
+                .unwrap_or(DEFAULT_JUNK_FOLDER)
+                .to_owned(),
+            spam_learn_cmd: account
+                .spam_learn_cmd
+                .as_deref()
+                .or_else(|| config.spam_learn_cmd.as_deref())
+                .unwrap_or(DEFAULT_SPAM_LEARN_CMD)
+                .to_owned(),
+            ham_learn_cmd: account
+                .ham_learn_cmd
+                .as_deref()
+                .or_else(|| config.ham_learn_cmd.as_deref())
+                .unwrap_or(DEFAULT_HAM_LEARN_CMD)
+                .to_owned(),
+            show_spam_score: account
+                .show_spam_score
+                .or(config.show_spam_score)
+                .unwrap_or_default(),
+            save_sent_copy: account
+                .save_sent_copy
+                .or_else(|| profile.as_ref().map(|p| p.save_sent_copy))
+                .or(config.save_sent_copy)
+                // Graph already saves the messages it sends to Sent Items.
+                .unwrap_or(
+                    account.backend != Some(BackendKind::Graph) || account.sendmail_cmd.is_some(),
+                ),
+            envelope_cache: account
+                .envelope_cache
+                .or(config.envelope_cache)
+                .unwrap_or(true),
+            fetch_jobs: account.fetch_jobs.or(config.fetch_jobs).unwrap_or(1).max(1),
+            outbox: account.outbox.or(config.outbox).unwrap_or_default(),
+            quota_warning: account
+                .quota_warning
+                .or(config.quota_warning)
+                .unwrap_or(DEFAULT_QUOTA_WARNING),
+            storage: account.storage.or(config.storage).unwrap_or_default(),
+            filters: account
+                .filters
+                .iter()
+                .chain(config.filters.iter())
+                .flatten()
+                .cloned()
+                .collect(),
+            identities: account
+                .identities
+                .iter()
+                .flatten()
+                .map(|(alias, identity)| {
+                    let id = Identity {
+                        from: identity
+                            .name
+                            .as_ref()
+                            .or(account.name.as_ref())
+                            .unwrap_or(&config.name)
+                            .to_owned(),
+                        email: identity.email.to_owned(),
+                        sig: identity
+                            .signature
+                            .as_deref()
+                            .map(|sig| msg_sig::with_delim(sig_delim, &read_text(sig))),
+                    };
+                    (alias.to_owned(), id)
+                })
+                .collect(),
+            queries: config
+                .queries
+                .iter()
+                .chain(account.queries.iter())
+                .flatten()
+                .map(|(name, query)| (name.to_owned(), query.to_owned()))
+                .collect(),
+            default_headers: config
+                .default_headers
+                .iter()
+                .chain(account.default_headers.iter())
+                .flatten()
+                .map(|(name, val)| (name.to_owned(), val.to_owned()))
+                .collect(),
+            split_attachment_size: account
+                .split_attachment_size
+                .as_deref()
+                .or_else(|| config.split_attachment_size.as_deref())
+                .map(parse_size)
+                .transpose()?,
+            share_cmd: account
+                .share_cmd
+                .as_ref()
+                .or_else(|| config.share_cmd.as_ref())
+                .cloned(),
+            pre_send_cmd: account
+                .pre_send_cmd
+                .as_ref()
+                .or_else(|| config.pre_send_cmd.as_ref())
+                .cloned(),
+            post_send_cmd: account
+                .post_send_cmd
+                .as_ref()
+                .or_else(|| config.post_send_cmd.as_ref())
+                .cloned(),
+            webhook_url: account
+                .webhook_url
+                .as_ref()
+                .or_else(|| config.webhook_url.as_ref())
+                .cloned(),
+            metrics_file: account
+                .metrics_file
+                .as_ref()
+                .or_else(|| config.metrics_file.as_ref())
+                .cloned(),
+            share_attachment_size: account
+                .share_attachment_size
+                .as_deref()
+                .or_else(|| config.share_attachment_size.as_deref())
+                .map(parse_size)
+                .transpose()?
+                .unwrap_or(DEFAULT_SHARE_ATTACHMENT_SIZE),
             watch_cmds: account
                 .watch_cmds
                 .as_ref()
@@ -142,20 +820,153 @@ impl<'a> TryFrom<(&'a Config, Option<&str>)> for Account {
                 .to_owned(),
             default: account.default.unwrap_or(false),
             email: account.email.to_owned(),
-            imap_host: account.imap_host.to_owned(),
-            imap_port: account.imap_port,
-            imap_starttls: account.imap_starttls.unwrap_or_default(),
+            backend: account.backend.unwrap_or_default(),
+            imap_host: imap_host.to_owned(),
+            imap_port: match (account.imap_port, &profile) {
+                (0, Some(profile)) => profile.imap_port,
+                (port, _) => port,
+            },
+            imap_starttls: account
+                .imap_starttls
+                .or_else(|| profile.as_ref().map(|p| p.imap_starttls))
+                .unwrap_or_default(),
             imap_insecure: account.imap_insecure.unwrap_or_default(),
-            imap_login: account.imap_login.to_owned(),
-            imap_passwd_cmd: account.imap_passwd_cmd.to_owned(),
-            smtp_host: account.smtp_host.to_owned(),
-            smtp_port: account.smtp_port,
-            smtp_starttls: account.smtp_starttls.unwrap_or_default(),
+            imap_compress: account.imap_compress.unwrap_or_default(),
+            imap_login: match account.imap_login.as_str() {
+                "" => account.email.to_owned(),
+                login => login.to_owned(),
+            },
+            imap_passwd_cmd: match account.imap_passwd_cmd.as_str() {
+                "" => account.passwd_cmd.to_owned().unwrap_or_default(),
+                cmd => cmd.to_owned(),
+            },
+            smtp_host: match (account.smtp_host.as_str(), &profile) {
+                ("", Some(profile)) => profile.smtp_host.to_owned(),
+                (host, _) => host.to_owned(),
+            },
+            smtp_port: match (account.smtp_port, &profile) {
+                (0, Some(profile)) => profile.smtp_port,
+                (port, _) => port,
+            },
+            smtp_starttls: account
+                .smtp_starttls
+                .or_else(|| profile.as_ref().map(|p| p.smtp_starttls))
+                .unwrap_or_default(),
             smtp_insecure: account.smtp_insecure.unwrap_or_default(),
-            smtp_login: account.smtp_login.to_owned(),
-            smtp_passwd_cmd: account.smtp_passwd_cmd.to_owned(),
+            smtp_login: match account.smtp_login.as_str() {
+                "" => account.email.to_owned(),
+                login => login.to_owned(),
+            },
+            smtp_passwd_cmd: match account.smtp_passwd_cmd.as_str() {
+                "" => account.passwd_cmd.to_owned().unwrap_or_default(),
+                cmd => cmd.to_owned(),
+            },
+            proxy: account
+                .proxy
+                .as_deref()
+                .or_else(|| config.proxy.as_deref())
+                .map(Proxy::parse)
+                .transpose()?,
+            max_retries: account
+                .max_retries
+                .or(config.max_retries)
+                .unwrap_or(DEFAULT_MAX_RETRIES),
+            tls_ca_cert: account.tls_ca_cert.as_deref().map(expand_path),
+            tls_client_cert: account.tls_client_cert.as_deref().map(expand_path),
+            tls_client_key: account.tls_client_key.as_deref().map(expand_path),
+            imap_cert_fingerprint: account.imap_cert_fingerprint.to_owned(),
+            smtp_cert_fingerprint: account.smtp_cert_fingerprint.to_owned(),
+            passwd_keyring: account.passwd_keyring.to_owned(),
+            passwd_secret: account.passwd_secret.to_owned(),
+            secrets_file: account
+                .secrets_file
+                .as_deref()
+                .or_else(|| config.secrets_file.as_deref())
+                .map(expand_path),
+            secrets_decrypt_cmd: account
+                .secrets_decrypt_cmd
+                .to_owned()
+                .or_else(|| config.secrets_decrypt_cmd.to_owned()),
+            sendmail_cmd: account.sendmail_cmd.to_owned(),
+            sieve_host: account
+                .sieve_host
+                .as_deref()
+                .unwrap_or(imap_host)
+                .to_owned(),
+            sieve_port: account.sieve_port.unwrap_or(DEFAULT_SIEVE_PORT),
+            sieve_starttls: account.sieve_starttls.unwrap_or(true),
+            graph_client_id: account.graph_client_id.to_owned(),
+            graph_tenant: account
+                .graph_tenant
+                .as_deref()
+                .unwrap_or("common")
+                .to_owned(),
+            graph_refresh_token_secret: account.graph_refresh_token_secret.to_owned(),
+            smime_cert: account.smime_cert.as_deref().map(expand_path),
+            smime_key: account.smime_key.as_deref().map(expand_path),
+            smime_ca_cert: account.smime_ca_cert.as_deref().map(expand_path),
+            smime_certs_dir: account.smime_certs_dir.as_deref().map(expand_path),
+            smime_sign: account.smime_sign.unwrap_or_default(),
+            smime_encrypt: account.smime_encrypt.unwrap_or_default(),
+            dkim_selector: account.dkim_selector.to_owned(),
+            dkim_domain: account
+                .dkim_domain
+                .as_deref()
+                .or_else(|| account.email.rsplit('@').next())
+                .unwrap_or_default()
+                .to_owned(),
+            dkim_key: account.dkim_key.as_deref().map(expand_path),
+            system_mode: config.system_mode.unwrap_or_default(),
         };
 
+        // Servers left out of both the account and its provider are discovered, so that a
+        // minimal account only needs an email and a password.
+        let needs_imap = account.backend == BackendKind::Imap && account.imap_host.is_empty();
+        let needs_smtp = account.backend == BackendKind::Imap
+            && account.sendmail_cmd.is_none()
+            && account.smtp_host.is_empty();
+        if needs_imap || needs_smtp {
+            let cache = account.cache_dir()?.join("autoconfig.toml");
+            let Autoconfig { imap, smtp } = autoconfig::discover_cached(&account.email, &cache)
+                .ok_or_else(|| {
+                    anyhow!(
+                        r#"cannot discover server settings of {}: set "imap-host" and "smtp-host""#,
+                        account.email
+                    )
+                })?;
+            debug!("discovered IMAP server: {:?}", imap);
+            debug!("discovered SMTP server: {:?}", smtp);
+            if needs_imap {
+                account.imap_host = imap.host;
+                if account.imap_port == 0 {
+                    account.imap_port = imap.port;
+                }
+                if !starttls_set.0 {
+                    account.imap_starttls = imap.starttls;
+                }
+                if account.sieve_host.is_empty() {
+                    account.sieve_host = account.imap_host.to_owned();
+                }
+            }
+            if needs_smtp {
+                account.smtp_host = smtp.host;
+                if account.smtp_port == 0 {
+                    account.smtp_port = smtp.port;
+                }
+                if !starttls_set.1 {
+                    account.smtp_starttls = smtp.starttls;
+                }
+            }
+        }
+
+        if let Some(sig) =
+            msg_sig_rotation::pick(&account, rotated_sig_cmd, &rotated_sigs, sig_rotation)?
+        {
+            let sig = msg_sig::with_delim(sig_delim, &sig);
+            account.html_sig = Some(msg_sig::text_to_html(&sig));
+            account.sig = Some(sig);
+        }
+
         trace!("{:#?}", account);
         Ok(account)
     }
@@ -1,8 +1,106 @@
 //! Module related to config CLI.
 //!
-//! This module provides arguments related to config.
+//! This module provides arguments, subcommands and a command matcher related to config.
 
-use clap::Arg;
+use anyhow::Result;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Connect = bool;
+type RedactSecrets = bool;
+type Force = bool;
+
+/// Config commands.
+pub enum Command<'a> {
+    /// Check the config, diagnosing the connections of the accounts when asked.
+    Check(Connect),
+    /// Export the config as a portable bundle, to the given path or else to the standard output.
+    Export(Option<&'a str>, RedactSecrets),
+    /// Import the config bundle of the given path.
+    Import(&'a str, Force),
+}
+
+/// Config command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("config") {
+        if let Some(m) = m.subcommand_matches("check") {
+            debug!("config check command matched");
+            let connect = m.is_present("connect");
+            trace!("connect: {}", connect);
+            return Ok(Some(Command::Check(connect)));
+        }
+
+        if let Some(m) = m.subcommand_matches("export") {
+            debug!("config export command matched");
+            let path = m.value_of("path");
+            trace!("path: {:?}", path);
+            let redact_secrets = m.is_present("redact-secrets");
+            trace!("redact secrets: {}", redact_secrets);
+            return Ok(Some(Command::Export(path, redact_secrets)));
+        }
+
+        if let Some(m) = m.subcommand_matches("import") {
+            debug!("config import command matched");
+            let path = m.value_of("path").unwrap();
+            trace!("path: {}", path);
+            let force = m.is_present("force");
+            trace!("force: {}", force);
+            return Ok(Some(Command::Import(path, force)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Config subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("config")
+        .about("Manages the config")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks the config")
+                .long_about("Checks the config: it is parsed, then each account is validated (unknown options, missing required ones, conflicting ones). The command exits with 0 when the config is valid (warnings aside), 2 when it cannot be read or parsed as TOML, 3 when it has errors, and 4 when the connection of an account failed.")
+                .arg(
+                    Arg::with_name("connect")
+                        .long("connect")
+                        .help("Diagnoses the connections of each account, like `account doctor`"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports the config as a portable bundle")
+                .long_about("Exports the config as a single portable file, its includes and drop-in files merged, to set up another machine with `config import`. Substitutions are kept as they are, to be resolved on the other machine.")
+                .arg(
+                    Arg::with_name("path")
+                        .help("Writes the bundle to the given file instead of the standard output")
+                        .value_name("PATH"),
+                )
+                .arg(
+                    Arg::with_name("redact-secrets")
+                        .long("redact-secrets")
+                        .help("Swaps the password commands for keyring entries")
+                        .long_help("Swaps the password commands of the accounts for a \"himalaya/<account>\" keyring entry, filled by `config import`."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Imports a config bundle")
+                .long_about("Imports a config bundle made by `config export`, as the config file. The passwords of the keyring entries of the accounts are then asked for, when they are not in the keyring yet.")
+                .arg(
+                    Arg::with_name("path")
+                        .help("Reads the bundle from the given file")
+                        .value_name("PATH")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .short("f")
+                        .help("Overwrites the existing config file"),
+                ),
+        )]
+}
 
 /// Config arguments.
 pub fn args<'a>() -> Vec<Arg<'a, 'a>> {
@@ -17,5 +115,20 @@ pub fn args<'a>() -> Vec<Arg<'a, 'a>> {
             .short("a")
             .help("Selects a specific account")
             .value_name("NAME"),
+        Arg::with_name("set")
+            .long("set")
+            .help("Overrides a config option for this invocation")
+            .long_help("Overrides a config option for this invocation, without editing the config file. KEY is a dotted path (eg. accounts.work.smtp-host, or work.smtp-host), VALUE is parsed as TOML and falls back to a string. Can be repeated.")
+            .value_name("KEY=VALUE")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("wait")
+            .long("wait")
+            .help("Waits for the other commands running for the account to finish")
+            .long_help("Commands talking to the server lock their account, so that concurrent invocations (eg. a cron job and an interactive session) do not corrupt the cache or exceed the connection limits of the server. By default, a command fails right away if another one holds the lock. With this flag, it waits for the lock instead.")
+            .conflicts_with("no-lock"),
+        Arg::with_name("no-lock")
+            .long("no-lock")
+            .help("Runs the command without locking the account"),
     ]
 }
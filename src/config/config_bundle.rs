@@ -0,0 +1,126 @@
+//! Module related to config bundles.
+//!
+//! A bundle is the config merged into a single portable file, its includes and drop-in files
+//! included, so that it can be moved to another machine. Substitutions are kept as they are,
+//! to be resolved on the other side. With redacted secrets, the password commands of the
+//! accounts are swapped for a keyring entry (`himalaya/<account>`), filled on import.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{convert::TryInto, fs, path::Path};
+use toml::Value;
+
+use crate::config::{config_include, Config};
+
+/// The options holding secrets, or the commands printing them.
+const SECRET_KEYS: &[&str] = &["imap-passwd-cmd", "smtp-passwd-cmd", "passwd-cmd"];
+const KEYRING_KEY: &str = "passwd-keyring";
+
+/// Get the names of the accounts of the given config.
+fn account_names(config: &Value) -> Result<Vec<String>> {
+    let config: Config = config
+        .clone()
+        .try_into()
+        .context("cannot parse config file")?;
+    let mut names: Vec<String> = config.accounts.keys().cloned().collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Swap the secrets of the accounts of the given config for keyring entries.
+fn redact(config: &mut Value, names: &[String]) {
+    for name in names {
+        let account = match config.get_mut(name).and_then(Value::as_table_mut) {
+            Some(account) => account,
+            None => continue,
+        };
+        let redacted = SECRET_KEYS
+            .iter()
+            .filter(|key| account.remove(**key).is_some())
+            .count();
+        if redacted > 0 && !account.contains_key(KEYRING_KEY) {
+            debug!("redact {} secret(s) of account {}", redacted, name);
+            account.insert(
+                KEYRING_KEY.to_owned(),
+                Value::String(format!("himalaya/{}", name)),
+            );
+        }
+    }
+}
+
+/// Build the bundle of the config at the given path.
+pub fn export(path: Option<&str>, redact_secrets: bool) -> Result<String> {
+    let (path, drop_in_dir) = match path {
+        Some(path) => (Path::new(path).to_owned(), None),
+        None => (Config::path()?, Config::drop_in_dir().ok()),
+    };
+    let (mut config, _) = config_include::load(&path, drop_in_dir.as_deref())?;
+    if redact_secrets {
+        let names = account_names(&config)?;
+        redact(&mut config, &names);
+    }
+    toml::to_string_pretty(&config).context("cannot serialize config bundle")
+}
+
+/// Write the given bundle to the given config path. Returns the keyring entries of its
+/// accounts, to be filled.
+pub fn import(bundle_path: &Path, path: &Path, force: bool) -> Result<Vec<(String, String)>> {
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "cannot import config bundle: {:?} already exists",
+            path
+        ));
+    }
+    let content = fs::read_to_string(bundle_path)
+        .context(format!("cannot read config bundle {:?}", bundle_path))?;
+    let config: Value = toml::from_str(&content)
+        .context(format!("cannot parse config bundle {:?}", bundle_path))?;
+    let names = account_names(&config)?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("cannot create config directory {:?}", dir))?;
+    }
+    fs::write(path, content).context(format!("cannot write config file {:?}", path))?;
+
+    Ok(names
+        .into_iter()
+        .filter_map(|name| {
+            let entry = config[name.as_str()].get(KEYRING_KEY)?.as_str()?.to_owned();
+            Some((name, entry))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_redact_secrets() {
+        let mut config: Value = toml::from_str(concat!(
+            "name = \"John\"\n",
+            "[work]\n",
+            "default = true\n",
+            "email = \"john@work\"\n",
+            "imap-passwd-cmd = \"pass show work/imap\"\n",
+            "smtp-passwd-cmd = \"pass show work/smtp\"\n",
+            "[personal]\n",
+            "email = \"john@personal\"\n",
+            "passwd-cmd = \"pass show personal\"\n",
+            "passwd-keyring = \"mail/personal\"\n",
+        ))
+        .unwrap();
+        let names = account_names(&config).unwrap();
+        assert_eq!(vec!["personal", "work"], names);
+        redact(&mut config, &names);
+
+        let work = config["work"].as_table().unwrap();
+        assert!(!work.contains_key("imap-passwd-cmd"));
+        assert!(!work.contains_key("smtp-passwd-cmd"));
+        assert_eq!(Some("himalaya/work"), work["passwd-keyring"].as_str());
+        let personal = config["personal"].as_table().unwrap();
+        assert!(!personal.contains_key("passwd-cmd"));
+        assert_eq!(Some("mail/personal"), personal["passwd-keyring"].as_str());
+        assert_eq!(Some("John"), config["name"].as_str());
+    }
+}
@@ -0,0 +1,363 @@
+//! Module related to config checks.
+//!
+//! The check parses the config, then validates each account: unknown options (often typos,
+//! silently ignored otherwise), missing required ones and conflicting ones. It can also diagnose
+//! the connections of the accounts, like `account doctor`. The exit code tells provisioning
+//! scripts what went wrong, see [`ExitCode`].
+
+use serde::{
+    de::{self, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer, Serialize,
+};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    path::PathBuf,
+};
+use toml::Value;
+
+use crate::{
+    config::{proxy::Proxy, Account, Config, ConfigAccountEntry},
+    domain::{account::account_doctor, backend::build_backend, backend::build_sender, mbox::Mbox},
+};
+
+/// Represents the outcome of a check, as the exit code of the command.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExitCode {
+    /// The config is valid, it may have warnings.
+    Valid = 0,
+    /// The config cannot be read, or is not valid TOML.
+    Unreadable = 2,
+    /// The config has errors.
+    Invalid = 3,
+    /// The config is valid, but a connection failed.
+    ConnectionFailed = 4,
+}
+
+impl Default for ExitCode {
+    fn default() -> Self {
+        Self::Valid
+    }
+}
+
+/// Represents the level of an issue.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+/// Represents an issue found in the config.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    /// The account of the issue, none for the whole config.
+    pub account: Option<String>,
+    pub level: Level,
+    pub message: String,
+}
+
+/// Represents the report of a config check.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CheckReport {
+    /// The files the config was read from.
+    pub files: Vec<PathBuf>,
+    pub accounts: Vec<String>,
+    pub issues: Vec<Issue>,
+    pub exit_code: ExitCode,
+}
+
+impl CheckReport {
+    fn push(&mut self, account: Option<&str>, level: Level, message: String) {
+        self.issues.push(Issue {
+            account: account.map(String::from),
+            level,
+            message,
+        });
+    }
+
+    fn errors(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.level == Level::Error)
+            .count()
+    }
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for file in &self.files {
+            writeln!(f, "Config file: {}", file.display())?;
+        }
+        for issue in &self.issues {
+            let level = match issue.level {
+                Level::Warning => "warning",
+                Level::Error => "error",
+            };
+            match issue.account {
+                Some(ref account) => writeln!(f, "{} [{}]: {}", level, account, issue.message)?,
+                None => writeln!(f, "{}: {}", level, issue.message)?,
+            }
+        }
+        let warnings = self.issues.len() - self.errors();
+        write!(
+            f,
+            "{} account(s) checked, {} error(s), {} warning(s)",
+            self.accounts.len(),
+            self.errors(),
+            warnings
+        )
+    }
+}
+
+/// Represents a deserializer recording the fields of the struct deserialized with it, so that
+/// the known options are the ones of [`ConfigAccountEntry`], without listing them twice.
+struct FieldsRecorder<'a>(&'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for FieldsRecorder<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+fn account_fields() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    ConfigAccountEntry::deserialize(FieldsRecorder(&mut fields)).ok();
+    fields
+}
+
+/// Check the options of the given account.
+fn check_account(report: &mut CheckReport, name: &str, raw: &Value, account: &ConfigAccountEntry) {
+    let fields = account_fields();
+    let mut error = |message: String| report.push(Some(name), Level::Error, message);
+    if let Some(table) = raw.as_table() {
+        for key in table.keys() {
+            if !fields.contains(&key.as_str()) {
+                error(format!(r#"unknown option "{}""#, key));
+            }
+        }
+    }
+
+    if account.email.trim().is_empty() {
+        error(String::from(r#"missing option "email""#));
+    }
+    if account.tls_client_cert.is_some() != account.tls_client_key.is_some() {
+        error(String::from(
+            r#"options "tls-client-cert" and "tls-client-key" go together"#,
+        ));
+    }
+    if account.smtp_cert_fingerprint.is_some() {
+        error(String::from(
+            r#"option "smtp-cert-fingerprint" is not supported by the SMTP client, use "tls-ca-cert""#,
+        ));
+    }
+    if let Some(ref proxy) = account.proxy {
+        if let Err(err) = Proxy::parse(proxy) {
+            error(format!("{:#}", err));
+        }
+    }
+
+    let mut warning = |message: String| report.push(Some(name), Level::Warning, message);
+    if account.signature.is_some() && account.signature_path.is_some() {
+        warning(String::from(
+            r#"option "signature-path" takes precedence over "signature""#,
+        ));
+    }
+    if account.signature_cmd.is_some() && account.signatures.is_some() {
+        warning(String::from(
+            r#"option "signature-cmd" takes precedence over "signatures""#,
+        ));
+    }
+    if account.sendmail_cmd.is_some() && !account.smtp_host.is_empty() {
+        warning(String::from(
+            r#"option "sendmail-cmd" is set, SMTP options are ignored"#,
+        ));
+    }
+    if let Some(ref cmd) = account.sendmail_cmd {
+        if cmd.split_whitespace().any(|arg| arg == "-t") {
+            warning(String::from(
+                r#"option "sendmail-cmd" reads recipients from the headers with "-t", Bcc and bounce recipients are passed as arguments instead"#,
+            ));
+        }
+    }
+    if account.imap_insecure.unwrap_or_default() && account.imap_cert_fingerprint.is_some() {
+        warning(String::from(
+            r#"option "imap-insecure" disables the check of "imap-cert-fingerprint""#,
+        ));
+    }
+}
+
+/// Check the given config, before it is parsed.
+fn check_value(report: &mut CheckReport, value: Value) -> Option<Config> {
+    let config: Config = match value.clone().try_into() {
+        Ok(config) => config,
+        Err(err) => {
+            report.push(None, Level::Error, format!("cannot parse config: {}", err));
+            // Find the accounts causing the error.
+            for (name, raw) in value.as_table().into_iter().flatten() {
+                if raw.is_table() {
+                    if let Err(err) = raw.clone().try_into::<ConfigAccountEntry>() {
+                        report.push(Some(name), Level::Error, err.to_string());
+                    }
+                }
+            }
+            return None;
+        }
+    };
+
+    let mut names: Vec<&String> = config.accounts.keys().collect();
+    names.sort();
+    for name in names {
+        check_account(report, name, &value[name.as_str()], &config.accounts[name]);
+    }
+    report.accounts = config.accounts.keys().cloned().collect();
+    report.accounts.sort();
+
+    match config
+        .accounts
+        .values()
+        .filter(|account| account.default.unwrap_or_default())
+        .count()
+    {
+        0 => report.push(
+            None,
+            Level::Error,
+            String::from(r#"no default account, set "default = true" to one of them"#),
+        ),
+        1 => (),
+        n => report.push(
+            None,
+            Level::Error,
+            format!("{} default accounts, only one can be the default", n),
+        ),
+    }
+    Some(config)
+}
+
+/// Check the config at the given path, with the given overrides. With `connect`, the
+/// connections of each account are diagnosed too.
+pub fn check(path: Option<&str>, overrides: &[&str], connect: bool) -> CheckReport {
+    let mut report = CheckReport::default();
+    let value = match Config::read_value(path, overrides) {
+        Ok((value, files)) => {
+            report.files = files;
+            value
+        }
+        Err(err) => {
+            report.push(None, Level::Error, format!("{:#}", err));
+            report.exit_code = ExitCode::Unreadable;
+            return report;
+        }
+    };
+
+    let mut accounts = vec![];
+    if let Some(config) = check_value(&mut report, value) {
+        for name in report.accounts.clone() {
+            match Account::try_from((&config, Some(name.as_str()))) {
+                Ok(account) => accounts.push(account),
+                Err(err) => report.push(Some(&name), Level::Error, format!("{:#}", err)),
+            }
+        }
+    }
+    if report.errors() > 0 {
+        report.exit_code = ExitCode::Invalid;
+        return report;
+    }
+
+    if connect {
+        for account in accounts.iter() {
+            diagnose(&mut report, account);
+        }
+        if report.errors() > 0 {
+            report.exit_code = ExitCode::ConnectionFailed;
+        }
+    }
+    report
+}
+
+/// Diagnose the connections of the given account, recording the failed steps.
+fn diagnose(report: &mut CheckReport, account: &Account) {
+    let mbox = Mbox::from("INBOX");
+    let mut backend = build_backend(account, &mbox, false);
+    let mut sender = build_sender(account);
+    let diagnosis = account_doctor::diagnose(account, backend.as_mut(), sender.as_mut());
+    for check in diagnosis.checks.iter().filter(|check| !check.ok) {
+        let hint = check
+            .hint
+            .as_ref()
+            .map(|hint| format!(" ({})", hint))
+            .unwrap_or_default();
+        report.push(
+            Some(&account.name),
+            Level::Error,
+            format!("{}: {}{}", check.name, check.detail, hint),
+        );
+    }
+    backend.logout().ok();
+    sender.close().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_check_accounts() {
+        let value: Value = toml::from_str(concat!(
+            "name = \"John\"\n",
+            "[work]\n",
+            "default = true\n",
+            "email = \"john@work\"\n",
+            "imap-prot = 993\n",
+            "tls-client-cert = \"~/cert.pem\"\n",
+            "[personal]\n",
+            "default = true\n",
+            "email = \"\"\n",
+        ))
+        .unwrap();
+        let mut report = CheckReport::default();
+        assert!(check_value(&mut report, value).is_some());
+        assert_eq!(vec!["personal", "work"], report.accounts);
+        let messages: Vec<(Option<&str>, &str)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.account.as_deref(), issue.message.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some("personal"), r#"missing option "email""#),
+                (Some("work"), r#"unknown option "imap-prot""#),
+                (
+                    Some("work"),
+                    r#"options "tls-client-cert" and "tls-client-key" go together"#
+                ),
+                (None, "2 default accounts, only one can be the default"),
+            ],
+            messages
+        );
+
+        let value: Value = toml::from_str("[work]\ndefault = true\n").unwrap();
+        let mut report = CheckReport::default();
+        assert!(check_value(&mut report, value).is_none());
+        assert_eq!(Some("work"), report.issues[1].account.as_deref());
+    }
+}
@@ -1,13 +1,58 @@
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
 use log::{debug, trace};
 use serde::Deserialize;
-use std::{collections::HashMap, convert::TryFrom, env, fs, path::PathBuf, thread};
+use std::{collections::HashMap, convert::TryFrom, env, path::PathBuf, thread};
 use toml;
 
-use crate::output::run_cmd;
+use crate::{
+    config::{config_include, config_override, config_subst, system_mode, Provider},
+    domain::filter::FilterRule,
+    output::run_cmd,
+};
 
 pub const DEFAULT_PAGE_SIZE: usize = 10;
+pub const DEFAULT_DATETIME_FMT: &str = "%Y-%m-%d %H:%M:%S";
 pub const DEFAULT_SIG_DELIM: &str = "-- \n";
+pub const DEFAULT_REPLY_QUOTE_CONTEXT: usize = 2;
+pub const DEFAULT_REPLY_QUOTE_PREFIX: &str = "> ";
+pub const DEFAULT_REPLY_ATTRIBUTION: &str = "On {date}, {name} wrote:";
+pub const DEFAULT_REPLY_ATTRIBUTION_DATE_FORMAT: &str = "%d %b %Y, at %H:%M";
+pub const DEFAULT_ARCHIVE_FOLDER: &str = "Archive";
+pub const DEFAULT_SHARE_ATTACHMENT_SIZE: usize = 10 << 20;
+pub const DEFAULT_SIEVE_PORT: u16 = 4190;
+pub const DEFAULT_SNOOZE_FOLDER: &str = "Snoozed";
+pub const DEFAULT_HOLD_KEYWORD: &str = "$Hold";
+pub const DEFAULT_SENT_FOLDER: &str = "Sent";
+pub const DEFAULT_DRAFTS_FOLDER: &str = "Drafts";
+pub const DEFAULT_JUNK_FOLDER: &str = "Junk";
+pub const DEFAULT_SPAM_LEARN_CMD: &str = "sa-learn --spam";
+pub const DEFAULT_HAM_LEARN_CMD: &str = "sa-learn --ham";
+pub const DEFAULT_QUOTA_WARNING: u64 = 90;
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Parse a size in bytes, with an optional K, M or G suffix (eg. `10M`).
+pub fn parse_size(size: &str) -> Result<usize> {
+    let size = size.trim();
+    let (num, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let unit = match unit
+        .trim()
+        .to_uppercase()
+        .trim_end_matches(|c| c == 'B' || c == 'I')
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(anyhow!(r#"cannot parse size "{}": unknown unit"#, size)),
+    };
+    let num: usize = num
+        .parse()
+        .context(format!(r#"cannot parse size "{}""#, size))?;
+    Ok(num * unit)
+}
 
 /// Represent the user config.
 #[derive(Debug, Default, Clone, Deserialize)]
@@ -17,14 +62,174 @@ pub struct Config {
     pub name: String,
     /// Define the downloads directory (eg. for attachments).
     pub downloads_dir: Option<PathBuf>,
-    /// Override the default signature delimiter "`--\n `".
+    /// Override the default signature delimiter "`-- \n`".
     pub signature_delimiter: Option<String>,
-    /// Define the signature.
+    /// Define the signature, either a path to a file or the text itself.
     pub signature: Option<String>,
+    /// Define the path of the signature file, failing when it cannot be read.
+    pub signature_path: Option<String>,
+    /// Define the HTML signature, either a path to a file or the HTML itself. The text signature
+    /// is rendered from it when none is defined.
+    pub signature_html: Option<String>,
+    /// Define where the signature goes in replies and forwards.
+    pub signature_position: Option<SigPosition>,
+    /// Define signatures used in turn, each one being either a path to a file or the text
+    /// itself (see [`msg_sig_rotation`](crate::domain::msg::msg_sig_rotation)).
+    pub signatures: Option<Vec<String>>,
+    /// Define how the signatures are rotated (default to round-robin).
+    pub signature_rotation: Option<SigRotation>,
+    /// Define a command whose output is the signature, taking precedence over the signatures.
+    pub signature_cmd: Option<String>,
     /// Define the default page size for listings.
     pub default_page_size: Option<usize>,
+    /// Define the format string of message listings (eg. `{id}\t{date:%Y-%m-%d}\t{subject}`).
+    pub list_format: Option<String>,
+    /// Define the format of the dates of message listings, in the local timezone (eg. `%d %b
+    /// %H:%M`). Default to `%Y-%m-%d %H:%M:%S`.
+    pub datetime_fmt: Option<String>,
+    /// Define the styles of the elements of tables (`header`, `separator`, `id`, `flags`,
+    /// `flagged`, `subject`, `sender`, `date`, `size`, `unseen`), as attributes and colors separated by
+    /// spaces (eg. `unseen = "bold bright-white"`). See [`theme`](crate::ui::theme).
+    pub theme: Option<HashMap<String, String>>,
+    /// Define which part of the original message is quoted in replies.
+    pub reply_quote: Option<ReplyQuote>,
+    /// Define the number of lines quoted around the text matched by `--quote-match`.
+    pub reply_quote_context: Option<usize>,
+    /// Define the prefix of quoted lines in replies.
+    pub reply_quote_prefix: Option<String>,
+    /// Define the format string of the attribution line of replies, exposing the `date`, `name`
+    /// and `email` of the original sender (eg. `{name} <{email}> wrote:`).
+    pub reply_attribution: Option<String>,
+    /// Define the strftime format of the date of the attribution line of replies.
+    pub reply_attribution_date_format: Option<String>,
+    /// Send text as `format=flowed`, so that long paragraphs reflow in the clients supporting it.
+    pub format_flowed: Option<bool>,
+    /// Define a command run over the message before sending, printing one misspelled word per
+    /// line. The `{lang}` placeholder is replaced by the detected language (eg. `aspell
+    /// --lang={lang} list`).
+    pub spellcheck_cmd: Option<String>,
+    /// Warn before sending to recipients never written to before, or to external recipients
+    /// alongside internal ones, according to the delivery log.
+    pub send_safety_check: Option<bool>,
+    /// Define what to do when reading a message asking for a read receipt.
+    pub read_receipt_policy: Option<ReceiptPolicy>,
+    /// Define the domains considered internal by the send safety check. Default to the domain of
+    /// the account email.
+    pub internal_domains: Option<Vec<String>>,
+    /// Define the regexes matching a mention of an attachment, to warn before sending a message
+    /// without attachments. Default to common English, French, German and Spanish words, an
+    /// empty list disables the reminder.
+    pub attachment_reminder_patterns: Option<Vec<String>>,
+    /// Define the command summarizing messages, reading their text on its standard input and
+    /// printing the summary.
+    pub summarize_cmd: Option<String>,
+    /// Define the command suggesting reply drafts with `reply --suggest`, reading the original
+    /// message on its standard input and printing the body of the reply.
+    pub draft_suggest_cmd: Option<String>,
+    /// Define templates, signatures and languages used when writing to some recipients.
+    pub recipient_templates: Option<Vec<RecipientTpl>>,
+    /// Define the blind copies and marker headers added when writing to some domains.
+    pub crm_rules: Option<Vec<CrmRule>>,
+    /// Define the template new messages start from, either a path to a file or the text itself
+    /// (see [`tpl_engine`](crate::domain::msg::tpl_engine)).
+    pub new_template: Option<String>,
+    /// Define the template of replies, replacing the default attribution line and quote.
+    pub reply_template: Option<String>,
+    /// Define the template of forwarded messages, replacing the default forward header.
+    pub forward_template: Option<String>,
+    /// Define the folder messages are archived in (default to "Archive"). The `{year}` and
+    /// `{month}` placeholders are replaced by the date of each message (eg. `Archive/{year}`).
+    pub archive_folder: Option<String>,
+    /// Define the folder snoozed messages wait in (default to "Snoozed").
+    pub snooze_folder: Option<String>,
+    /// Define the folder deleted messages are moved to. Messages are deleted permanently when
+    /// not set, or when they already are in this folder.
+    pub trash_folder: Option<String>,
+    /// Define the folder sent messages are saved in (default to "Sent").
+    pub sent_folder: Option<String>,
+    /// Define the folder remote drafts are saved in (default to "Drafts").
+    pub drafts_folder: Option<String>,
+    /// Define the folder messages reported as spam are moved to (default to "Junk").
+    pub junk_folder: Option<String>,
+    /// Define the commands the messages reported as spam or ham are piped to, so that the spam
+    /// filter learns from them (default to `sa-learn --spam` and `sa-learn --ham`, eg.
+    /// `rspamc learn_spam` and `rspamc learn_ham` for rspamd).
+    pub spam_learn_cmd: Option<String>,
+    pub ham_learn_cmd: Option<String>,
+    /// Define whether listings show the spam score of messages, from their `X-Spam-Score`
+    /// header (default to false).
+    pub show_spam_score: Option<bool>,
+    /// Define whether sent messages are appended to the sent folder (default to true, except for
+    /// Graph accounts, the Graph API saving them itself). Disable it for servers already saving
+    /// the messages sent over SMTP.
+    pub save_sent_copy: Option<bool>,
+    /// Define whether listed envelopes are cached, so that listing them again only fetches the
+    /// changes (default to true).
+    pub envelope_cache: Option<bool>,
+    /// Define the number of sessions fetching messages in parallel for bulk operations (export,
+    /// backup, body search), default to 1.
+    pub fetch_jobs: Option<usize>,
+    /// Define the file holding the secrets (passwords, refresh tokens), encrypted with age
+    /// (`.age`) or GPG (`.gpg`), see [`secrets`](crate::config::secrets).
+    pub secrets_file: Option<PathBuf>,
+    /// Define the command printing the decrypted secrets file, its path being given by the
+    /// `HIMALAYA_SECRETS_FILE` env var (default to `age --decrypt` or `gpg --decrypt`).
+    pub secrets_decrypt_cmd: Option<String>,
+    /// Define whether messages the sender fails to send are queued in the outbox, to be sent
+    /// again with `queue flush` (default to false).
+    pub outbox: Option<bool>,
+    /// Define the used part of a quota, in percent, from which saving a message to a mailbox
+    /// warns that it is near its quota (default to 90).
+    pub quota_warning: Option<u64>,
+    /// Define where the cache and the state are stored: `file` (default) or `sqlite`. State is
+    /// not migrated when switching.
+    pub storage: Option<StorageKind>,
+    /// Define the keyword of messages on hold, that destructive commands refuse to touch
+    /// without `--override-hold` (default to "$Hold").
+    pub hold_keyword: Option<String>,
+    /// Define the filter rules applied by `filter apply`, from the first to the last one.
+    pub filters: Option<Vec<FilterRule>>,
+    /// Define named search queries, run with `list --query NAME` and listed as virtual mailboxes
+    /// by `mailboxes list` (eg. `todo = "flag:flagged -flag:seen"`).
+    pub queries: Option<HashMap<String, String>>,
+    /// Define the headers added to the messages composed from templates, unless already set
+    /// (eg. `Organization = "ACME"`). Account headers override the global ones of the same name.
+    pub default_headers: Option<HashMap<String, String>>,
+    /// Define the size above which attachments are split across several messages, with an
+    /// optional K, M or G suffix (eg. `10M`).
+    pub split_attachment_size: Option<String>,
+    /// Define a command uploading attachments above `share-attachment-size`, and printing their
+    /// link. The `{path}` and `{filename}` placeholders are replaced by the path and the name of
+    /// the attachment (eg. `rclone copy {path} cloud:mail && rclone link cloud:mail/{filename}`).
+    pub share_cmd: Option<String>,
+    /// Define the size above which attachments are shared as links (default to `10M`).
+    pub share_attachment_size: Option<String>,
+    /// Define a command receiving the raw message on its standard input before sending. Sending
+    /// is aborted when it exits with a non-zero status (eg. to lint the message).
+    pub pre_send_cmd: Option<String>,
+    /// Define a command receiving the raw message on its standard input once sent (eg. to
+    /// archive it).
+    pub post_send_cmd: Option<String>,
+    /// Define the command run when a new message arrives. In notify standby mode, it can print
+    /// `read` or `delete` to apply the action to the message.
     pub notify_cmd: Option<String>,
+    /// Define the urgency and sound of the notifications of some mailboxes or messages.
+    pub notify_rules: Option<Vec<NotifyRule>>,
     pub watch_cmds: Option<Vec<String>>,
+    /// Define an HTTP(S) URL JSON events are posted to: new messages, sends and send failures.
+    pub webhook_url: Option<String>,
+    /// Define the Prometheus textfile new messages, sends, errors and connection state are
+    /// counted in, per account (eg. for the textfile collector of the node exporter).
+    pub metrics_file: Option<PathBuf>,
+    /// Define the proxy connections of all accounts go through, unless they define their own.
+    pub proxy: Option<String>,
+    /// Define how many times fetching, listing and searching are retried on network errors, and
+    /// how many times in a row `notify` and `watch` reconnect, with an exponential backoff.
+    /// Default to 3, 0 disabling retries.
+    pub max_retries: Option<u32>,
+    /// Enable the system mode, hardening himalaya for shared servers (see
+    /// [`system_mode`](crate::config::system_mode)).
+    pub system_mode: Option<bool>,
     #[serde(flatten)]
     pub accounts: ConfigAccountsMap,
 }
@@ -32,6 +237,218 @@ pub struct Config {
 /// Represent the accounts section of the config.
 pub type ConfigAccountsMap = HashMap<String, ConfigAccountEntry>;
 
+/// Represent the backend used by an account to manage its messages.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    /// IMAP for reading, SMTP for sending.
+    Imap,
+    /// Microsoft Graph API for both reading and sending (Exchange accounts).
+    Graph,
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        Self::Imap
+    }
+}
+
+/// Represent the storage of the cache and the state of an account.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageKind {
+    /// One plain file per item, in the account cache directory.
+    File,
+    /// A single SQLite database, in the account cache directory.
+    Sqlite,
+}
+
+impl Default for StorageKind {
+    fn default() -> Self {
+        Self::File
+    }
+}
+
+/// Represent the template, signature and language used when writing to some recipients.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RecipientTpl {
+    /// Match recipient addresses ending with the given pattern, either a full address or a domain
+    /// (eg. `@client.fr`). Matching is case-insensitive.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Define the body new messages start from, either a path to a file or the text itself. It
+    /// can start with a front-matter block of headers between two `---` lines, to declare Cc,
+    /// Bcc, custom headers or `Attachment: <path>` files.
+    pub template: Option<String>,
+    /// Override the account signature, either a path to a file or the text itself.
+    pub signature: Option<String>,
+    /// Define the language of the messages, passed to the spellcheck command.
+    pub lang: Option<String>,
+}
+
+impl RecipientTpl {
+    /// Check if the given address, with or without display name, matches the pattern.
+    pub fn matches(&self, addr: &str) -> bool {
+        let email = match (addr.rfind('<'), addr.rfind('>')) {
+            (Some(begin), Some(end)) if begin < end => &addr[begin + 1..end],
+            _ => addr,
+        };
+        email
+            .trim()
+            .to_lowercase()
+            .ends_with(&self.pattern.trim().to_lowercase())
+    }
+}
+
+/// Represent the blind copy and the marker header added to messages sent to some domains, so that
+/// a CRM or a ticketing system logs them.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrmRule {
+    /// Match recipients of the given domains (eg. `client.fr`). Matching is case-insensitive.
+    pub domains: Vec<String>,
+    /// Define the address receiving a blind copy, like the dropbox address of a CRM.
+    pub bcc: Option<String>,
+    /// Define the header marking the message (eg. `X-CRM-Ref: {domain}`), the `{domain}`
+    /// placeholder being replaced by the first matching domain.
+    pub header: Option<String>,
+}
+
+/// Represent the urgency of a notification, as understood by notification daemons.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyUrgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl NotifyUrgency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+/// Represent the urgency and sound of the notifications of the new messages matching a rule. A
+/// rule matches when all its conditions match, a rule without any condition matches all
+/// messages. The first matching rule wins.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NotifyRule {
+    /// Match messages arriving in the given mailbox. Matching is case-insensitive.
+    pub mailbox: Option<String>,
+    /// Match messages whose sender contains the given text, case-insensitively.
+    pub from: Option<String>,
+    /// Match messages whose subject contains the given text, case-insensitively.
+    pub subject: Option<String>,
+    pub urgency: Option<NotifyUrgency>,
+    /// Define the sound played, either a path to a sound file or the name of a sound of the
+    /// desktop theme (eg. `message-new-email`).
+    pub sound: Option<String>,
+}
+
+impl NotifyRule {
+    /// Check if the message with the given subject and sender, arrived in the given mailbox,
+    /// matches the rule.
+    pub fn matches(&self, mbox: &str, subject: &str, sender: &str) -> bool {
+        let contains =
+            |text: &str, pattern: &str| text.to_lowercase().contains(&pattern.to_lowercase());
+        self.mailbox
+            .as_ref()
+            .map_or(true, |mailbox| mailbox.eq_ignore_ascii_case(mbox))
+            && self
+                .from
+                .as_ref()
+                .map_or(true, |from| contains(sender, from))
+            && self
+                .subject
+                .as_ref()
+                .map_or(true, |pattern| contains(subject, pattern))
+    }
+}
+
+/// Represent which part of the original message is quoted in replies.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplyQuote {
+    /// Quote the whole message.
+    Full,
+    /// Quote only the last message of the thread, leaving out the previously quoted ones.
+    Last,
+}
+
+impl Default for ReplyQuote {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+/// Represent an identity of an account, picked with `--from ALIAS` when writing a message.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigIdentity {
+    /// Override the display name of the account.
+    pub name: Option<String>,
+    pub email: String,
+    /// Override the account signature, either a path to a file or the text itself.
+    pub signature: Option<String>,
+}
+
+/// Represent where the signature goes in replies and forwards.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigPosition {
+    /// Below everything, quoted message included.
+    BelowQuote,
+    /// Above the quoted message, right below the text of the reply.
+    AboveQuote,
+}
+
+impl Default for SigPosition {
+    fn default() -> Self {
+        Self::BelowQuote
+    }
+}
+
+/// Represent how the signatures of an account are rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigRotation {
+    /// Use the next signature for each sent message.
+    RoundRobin,
+    /// Use the signature of the day of the week, starting on Monday.
+    Weekday,
+}
+
+impl Default for SigRotation {
+    fn default() -> Self {
+        Self::RoundRobin
+    }
+}
+
+/// Represent what to do when reading a message asking for a read receipt.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReceiptPolicy {
+    /// Ask the user, when reading from a terminal.
+    Ask,
+    /// Send the receipt without asking.
+    Always,
+    /// Never send receipts.
+    Never,
+}
+
+impl Default for ReceiptPolicy {
+    fn default() -> Self {
+        Self::Ask
+    }
+}
+
 /// Represent an account in the accounts section.
 #[derive(Debug, Default, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -40,22 +457,163 @@ pub struct ConfigAccountEntry {
     pub downloads_dir: Option<PathBuf>,
     pub signature_delimiter: Option<String>,
     pub signature: Option<String>,
+    pub signature_path: Option<String>,
+    pub signature_html: Option<String>,
+    pub signature_position: Option<SigPosition>,
+    pub signatures: Option<Vec<String>>,
+    pub signature_rotation: Option<SigRotation>,
+    pub signature_cmd: Option<String>,
     pub default_page_size: Option<usize>,
+    pub list_format: Option<String>,
+    pub datetime_fmt: Option<String>,
+    pub max_retries: Option<u32>,
+    pub reply_quote: Option<ReplyQuote>,
+    pub reply_quote_context: Option<usize>,
+    pub reply_quote_prefix: Option<String>,
+    pub reply_attribution: Option<String>,
+    pub reply_attribution_date_format: Option<String>,
+    pub format_flowed: Option<bool>,
+    pub spellcheck_cmd: Option<String>,
+    pub send_safety_check: Option<bool>,
+    pub read_receipt_policy: Option<ReceiptPolicy>,
+    pub internal_domains: Option<Vec<String>>,
+    pub attachment_reminder_patterns: Option<Vec<String>>,
+    pub summarize_cmd: Option<String>,
+    pub draft_suggest_cmd: Option<String>,
+    pub recipient_templates: Option<Vec<RecipientTpl>>,
+    pub crm_rules: Option<Vec<CrmRule>>,
+    pub notify_rules: Option<Vec<NotifyRule>>,
+    pub new_template: Option<String>,
+    pub reply_template: Option<String>,
+    pub forward_template: Option<String>,
+    pub archive_folder: Option<String>,
+    pub snooze_folder: Option<String>,
+    pub trash_folder: Option<String>,
+    pub sent_folder: Option<String>,
+    pub drafts_folder: Option<String>,
+    pub junk_folder: Option<String>,
+    pub spam_learn_cmd: Option<String>,
+    pub ham_learn_cmd: Option<String>,
+    pub show_spam_score: Option<bool>,
+    pub save_sent_copy: Option<bool>,
+    pub envelope_cache: Option<bool>,
+    pub fetch_jobs: Option<usize>,
+    pub secrets_file: Option<PathBuf>,
+    pub secrets_decrypt_cmd: Option<String>,
+    pub outbox: Option<bool>,
+    pub quota_warning: Option<u64>,
+    pub storage: Option<StorageKind>,
+    pub hold_keyword: Option<String>,
+    pub filters: Option<Vec<FilterRule>>,
+    pub queries: Option<HashMap<String, String>>,
+    pub default_headers: Option<HashMap<String, String>>,
+    pub split_attachment_size: Option<String>,
+    pub share_cmd: Option<String>,
+    pub share_attachment_size: Option<String>,
+    pub pre_send_cmd: Option<String>,
+    pub post_send_cmd: Option<String>,
     pub watch_cmds: Option<Vec<String>>,
+    pub webhook_url: Option<String>,
+    pub metrics_file: Option<PathBuf>,
     pub default: Option<bool>,
     pub email: String,
+    /// Define the other identities messages can be sent from, by alias.
+    pub identities: Option<HashMap<String, ConfigIdentity>>,
+    pub backend: Option<BackendKind>,
+    /// Define the provider whose known settings are used as defaults (see
+    /// [`provider_entity`](crate::config::provider_entity)).
+    pub provider: Option<Provider>,
+
+    // IMAP and SMTP options are only required by the IMAP backend.
+    #[serde(default)]
     pub imap_host: String,
+    #[serde(default)]
     pub imap_port: u16,
     pub imap_starttls: Option<bool>,
     pub imap_insecure: Option<bool>,
+    /// Define whether the IMAP traffic is compressed, when the server supports the
+    /// COMPRESS=DEFLATE extension (default to false). Worth it on slow links.
+    pub imap_compress: Option<bool>,
+    #[serde(default)]
     pub imap_login: String,
+    #[serde(default)]
     pub imap_passwd_cmd: String,
+    #[serde(default)]
     pub smtp_host: String,
+    #[serde(default)]
     pub smtp_port: u16,
     pub smtp_starttls: Option<bool>,
     pub smtp_insecure: Option<bool>,
+    #[serde(default)]
     pub smtp_login: String,
+    #[serde(default)]
     pub smtp_passwd_cmd: String,
+    /// Define the proxy connections go through, like `socks5://127.0.0.1:9050` or
+    /// `http://proxy.corp:3128` (see [`proxy`](crate::config::proxy)).
+    pub proxy: Option<String>,
+    /// Define the PEM certificate of the CA signing the server certificates, when it is not
+    /// trusted by the system (eg. a private CA of a self-hosted server).
+    pub tls_ca_cert: Option<PathBuf>,
+    /// Define the PEM certificate and the PEM (PKCS#8) key sent to servers requiring client
+    /// certificates.
+    pub tls_client_cert: Option<PathBuf>,
+    pub tls_client_key: Option<PathBuf>,
+    /// Define the SHA-256 fingerprint of the IMAP server certificate (eg. `AB:CD:…`), trusted
+    /// whoever signed it, any other certificate being rejected.
+    pub imap_cert_fingerprint: Option<String>,
+    /// Define the SHA-256 fingerprint of the SMTP server certificate.
+    pub smtp_cert_fingerprint: Option<String>,
+    /// Define the command printing the password of both IMAP and SMTP, used when
+    /// `imap-passwd-cmd` or `smtp-passwd-cmd` is not defined.
+    pub passwd_cmd: Option<String>,
+    /// Define the system keyring entry holding the password, as `service/user` (eg.
+    /// `himalaya/work`), used when no password command is defined. See `account set-password`.
+    pub passwd_keyring: Option<String>,
+    /// Define the key of the password in the secrets file (eg. `work.passwd`), used when no
+    /// password command is defined.
+    pub passwd_secret: Option<String>,
+    /// Define a command messages are piped to instead of being sent via SMTP (eg. `msmtp`). The
+    /// recipients are appended as arguments, after `-i --`.
+    pub sendmail_cmd: Option<String>,
+    /// Define the ManageSieve host (default to the IMAP host). The IMAP credentials are used.
+    pub sieve_host: Option<String>,
+    /// Define the ManageSieve port (default to 4190).
+    pub sieve_port: Option<u16>,
+    /// Define whether the ManageSieve connection is upgraded with STARTTLS instead of starting
+    /// with TLS (default to true).
+    pub sieve_starttls: Option<bool>,
+
+    /// Define the Azure application (client) id used by the Graph backend.
+    pub graph_client_id: Option<String>,
+    /// Define the Azure tenant used by the Graph backend (default to "common").
+    pub graph_tenant: Option<String>,
+    /// Define the key of the Graph refresh token in the secrets file, used until the token is
+    /// refreshed for the first time.
+    pub graph_refresh_token_secret: Option<String>,
+
+    /// Define the S/MIME certificate of the account and its private key, as PEM files, used to
+    /// sign messages and to decrypt the ones received.
+    pub smime_cert: Option<PathBuf>,
+    pub smime_key: Option<PathBuf>,
+    /// Define the CA certificates S/MIME signatures are verified against, as a PEM file
+    /// (default to the system ones).
+    pub smime_ca_cert: Option<PathBuf>,
+    /// Define the directory holding the S/MIME certificates of the recipients, named after
+    /// their address (eg. `jane@doe.org.pem`), used to encrypt messages.
+    pub smime_certs_dir: Option<PathBuf>,
+    /// Define whether sent messages are signed with the S/MIME certificate (default to false).
+    pub smime_sign: Option<bool>,
+    /// Define whether sent messages are encrypted for their recipients with S/MIME (default to
+    /// false).
+    pub smime_encrypt: Option<bool>,
+
+    /// Define the DKIM selector messages sent via SMTP are signed with, the public key being
+    /// published at `<selector>._domainkey.<domain>`.
+    pub dkim_selector: Option<String>,
+    /// Define the DKIM signing domain (default to the domain of the account email).
+    pub dkim_domain: Option<String>,
+    /// Define the RSA private key of the DKIM selector, as a PEM file.
+    pub dkim_key: Option<PathBuf>,
 }
 
 impl Config {
@@ -98,6 +656,14 @@ impl Config {
         Ok(path)
     }
 
+    /// Find the directory of config files merged over the default config, see
+    /// [`config_include`](crate::config::config_include).
+    pub fn drop_in_dir() -> Result<PathBuf> {
+        let mut path = Self::path_from_xdg().or_else(|_| Self::path_from_xdg_alt())?;
+        path.set_file_name("config.d");
+        Ok(path)
+    }
+
     pub fn path() -> Result<PathBuf> {
         let path = Self::path_from_xdg()
             .or_else(|_| Self::path_from_xdg_alt())
@@ -107,22 +673,80 @@ impl Config {
         Ok(path)
     }
 
-    pub fn run_notify_cmd<S: AsRef<str>>(&self, subject: S, sender: S) -> Result<()> {
+    /// Build the notify command of the given message. When a notify rule matched, the custom
+    /// notify command also receives its urgency and sound (empty when not set).
+    pub fn build_notify_cmd<S: AsRef<str>>(
+        &self,
+        subject: S,
+        sender: S,
+        rule: Option<&NotifyRule>,
+    ) -> String {
         let subject = subject.as_ref();
         let sender = sender.as_ref();
+        let urgency = rule.and_then(|rule| rule.urgency);
+        let sound = rule.and_then(|rule| rule.sound.as_deref());
 
-        let default_cmd = format!(r#"notify-send "📫 {}" "{}""#, sender, subject);
-        let cmd = self
-            .notify_cmd
-            .as_ref()
-            .map(|cmd| format!(r#"{} {:?} {:?}"#, cmd, subject, sender))
-            .unwrap_or(default_cmd);
+        match (self.notify_cmd.as_ref(), rule) {
+            (Some(cmd), None) => format!(r#"{} {:?} {:?}"#, cmd, subject, sender),
+            (Some(cmd), Some(_)) => format!(
+                r#"{} {:?} {:?} {:?} {:?}"#,
+                cmd,
+                subject,
+                sender,
+                urgency.map(|urgency| urgency.as_str()).unwrap_or_default(),
+                sound.unwrap_or_default()
+            ),
+            (None, _) => {
+                let mut cmd = String::from("notify-send");
+                if let Some(urgency) = urgency {
+                    cmd.push_str(&format!(" --urgency={}", urgency.as_str()));
+                }
+                // Sounds are passed as hints, played by the notification daemon.
+                match sound {
+                    Some(sound) if sound.contains('/') => {
+                        cmd.push_str(&format!(r#" --hint="string:sound-file:{}""#, sound))
+                    }
+                    Some(sound) => {
+                        cmd.push_str(&format!(r#" --hint="string:sound-name:{}""#, sound))
+                    }
+                    None => (),
+                }
+                format!(r#"{} "📫 {}" "{}""#, cmd, sender, subject)
+            }
+        }
+    }
 
+    pub fn run_notify_cmd<S: AsRef<str>>(
+        &self,
+        subject: S,
+        sender: S,
+        rule: Option<&NotifyRule>,
+    ) -> Result<()> {
+        let cmd = self.build_notify_cmd(subject, sender, rule);
         run_cmd(&cmd).context("cannot run notify cmd")?;
-
         Ok(())
     }
 
+    /// Read the config file at the given path (or at the default one) with its includes, then
+    /// apply the given overrides and the substitutions. Returns the config before it is parsed,
+    /// with the files read.
+    pub fn read_value(
+        path: Option<&str>,
+        overrides: &[&str],
+    ) -> Result<(toml::Value, Vec<PathBuf>)> {
+        // Drop-in files only apply to the default config.
+        let (path, drop_in_dir) = match path {
+            Some(path) => (PathBuf::from(path), None),
+            None => (Config::path()?, Config::drop_in_dir().ok()),
+        };
+        let (mut config, files) = config_include::load(&path, drop_in_dir.as_deref())?;
+        for raw in overrides {
+            config_override::apply(&mut config, raw)?;
+        }
+        config_subst::subst(&mut config)?;
+        Ok((config, files))
+    }
+
     pub fn _exec_watch_cmds(&self, account: &ConfigAccountEntry) -> Result<()> {
         let cmds = account
             .watch_cmds
@@ -148,11 +772,23 @@ impl TryFrom<Option<&str>> for Config {
     type Error = Error;
 
     fn try_from(path: Option<&str>) -> Result<Self, Self::Error> {
+        Self::try_from((path, &[] as &[&str]))
+    }
+}
+
+impl TryFrom<(Option<&str>, &[&str])> for Config {
+    type Error = Error;
+
+    fn try_from((path, overrides): (Option<&str>, &[&str])) -> Result<Self, Self::Error> {
         debug!("init config from `{:?}`", path);
-        let path = path.map(|s| s.into()).unwrap_or(Config::path()?);
-        let content = fs::read_to_string(path).context("cannot read config file")?;
-        let config = toml::from_str(&content).context("cannot parse config file")?;
+        let (config, files) = Config::read_value(path, overrides)?;
+        let config: Config = config.try_into().context("cannot parse config file")?;
         trace!("{:#?}", config);
+        if config.system_mode.unwrap_or_default() {
+            for file in files {
+                system_mode::check_config(&file)?;
+            }
+        }
         Ok(config)
     }
 }
@@ -0,0 +1,86 @@
+//! Module related to config handling.
+//!
+//! This module gathers all config commands.
+
+use anyhow::{Context, Result};
+use atty::Stream;
+use log::{trace, warn};
+use std::{fs, path::PathBuf, process};
+
+use crate::{
+    config::{
+        config_bundle, config_check,
+        passwd::{self, PasswdSource},
+        Config,
+    },
+    output::{OutputService, OutputServiceInterface},
+    ui::prompt,
+};
+
+/// Check the config, then exit with the code of the check when it is not valid.
+pub fn check(
+    path: Option<&str>,
+    overrides: &[&str],
+    connect: bool,
+    output: &OutputService,
+) -> Result<()> {
+    let report = config_check::check(path, overrides, connect);
+    trace!("config check: {:#?}", report);
+    let code = report.exit_code as i32;
+    output.print(report)?;
+    if code != 0 {
+        process::exit(code);
+    }
+    Ok(())
+}
+
+/// Export the config as a bundle, to the given path or else to the standard output.
+pub fn export(
+    config_path: Option<&str>,
+    path: Option<&str>,
+    redact_secrets: bool,
+    output: &OutputService,
+) -> Result<()> {
+    let bundle = config_bundle::export(config_path, redact_secrets)?;
+    match path {
+        Some(path) => {
+            fs::write(path, bundle).context(format!("cannot write config bundle {:?}", path))?;
+            output.print(format!("Config successfully exported to {:?}", path))
+        }
+        None => output.print(bundle),
+    }
+}
+
+/// Import the given bundle as the config, then ask for the passwords missing from the keyring.
+pub fn import(
+    config_path: Option<&str>,
+    path: &str,
+    force: bool,
+    output: &OutputService,
+) -> Result<()> {
+    let config_path = match config_path {
+        Some(path) => PathBuf::from(path),
+        None => Config::path()?,
+    };
+    let entries = config_bundle::import(&PathBuf::from(path), &config_path, force)?;
+
+    for (name, entry) in entries {
+        if PasswdSource::Keyring(&entry).get().is_ok() {
+            trace!("keyring entry {} already filled", entry);
+            continue;
+        }
+        if !atty::is(Stream::Stdin) {
+            warn!(
+                r#"password of account "{}" missing, run `himalaya -a {} account set-password`"#,
+                name, name
+            );
+            continue;
+        }
+        let passwd = prompt::passwd(&format!("Password of account \"{}\"", name))?;
+        if !passwd.is_empty() {
+            passwd::keyring_set(&entry, &passwd)?;
+        }
+    }
+
+    output.print(format!("Config successfully imported to {:?}", config_path))
+}
@@ -0,0 +1,149 @@
+//! Module related to config includes.
+//!
+//! The config can be split across several files, so that secrets and per-machine overrides live
+//! apart from the shared config:
+//!
+//! - `include = ["work.toml", "personal.toml"]` merges the given files over the one including
+//!   them, in order. Relative paths are relative to the including file, and included files can
+//!   include others.
+//! - `*.toml` files of `$XDG_CONFIG_HOME/himalaya/config.d` are merged last, in alphabetical
+//!   order, when the config is read from its default location.
+//!
+//! Tables are merged key by key, any other value being replaced.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use toml::Value;
+
+const INCLUDE_KEY: &str = "include";
+
+/// Merge the given layer over the given base.
+pub fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Table(base), Value::Table(layer)) => {
+            for (key, val) in layer {
+                match base.get_mut(&key) {
+                    Some(base_val) => merge(base_val, val),
+                    None => {
+                        base.insert(key, val);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+fn resolve(path: &str, dir: &Path) -> Result<PathBuf> {
+    let path = shellexpand::full(path).context(format!("cannot expand include {:?}", path))?;
+    Ok(dir.join(path.as_ref()))
+}
+
+/// Read the given file and the ones it includes, recording the files read.
+fn load_file(path: &Path, files: &mut Vec<PathBuf>) -> Result<Value> {
+    let canonical = path
+        .canonicalize()
+        .context(format!("cannot read config file {:?}", path))?;
+    if files.contains(&canonical) {
+        return Err(anyhow!("cannot include {:?}: it is already included", path));
+    }
+    debug!("read config file {:?}", path);
+    files.push(canonical);
+
+    let content =
+        fs::read_to_string(path).context(format!("cannot read config file {:?}", path))?;
+    let mut config: Value =
+        toml::from_str(&content).context(format!("cannot parse config file {:?}", path))?;
+    let includes = match config.as_table_mut().and_then(|t| t.remove(INCLUDE_KEY)) {
+        None => vec![],
+        Some(Value::Array(includes)) => includes,
+        Some(_) => {
+            return Err(anyhow!(
+                r#"cannot parse config file {:?}: "include" must be an array of paths"#,
+                path
+            ))
+        }
+    };
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for include in includes {
+        let include = include.as_str().ok_or_else(|| {
+            anyhow!(
+                r#"cannot parse config file {:?}: "include" must be an array of paths"#,
+                path
+            )
+        })?;
+        let layer = load_file(&resolve(include, dir)?, files)?;
+        merge(&mut config, layer);
+    }
+    Ok(config)
+}
+
+/// Read the given config file with its includes, then the files of the given drop-in
+/// directory. Returns the merged config with the files read.
+pub fn load(path: &Path, drop_in_dir: Option<&Path>) -> Result<(Value, Vec<PathBuf>)> {
+    let mut files = vec![];
+    let mut config = load_file(path, &mut files)?;
+
+    if let Some(dir) = drop_in_dir.filter(|dir| dir.is_dir()) {
+        let mut drop_ins = fs::read_dir(dir)
+            .context(format!("cannot read config directory {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "toml"))
+            .collect::<Vec<_>>();
+        drop_ins.sort();
+        for drop_in in drop_ins {
+            let layer = load_file(&drop_in, &mut files)?;
+            merge(&mut config, layer);
+        }
+    }
+
+    Ok((config, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn it_should_load_includes_and_drop_ins() {
+        let dir = env::temp_dir().join(format!("himalaya-config-include-{}", process::id()));
+        let drop_in_dir = dir.join("config.d");
+        fs::create_dir_all(&drop_in_dir).unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "name = \"John\"\ninclude = [\"secrets.toml\"]\n[work]\nemail = \"john@work\"\nimap-port = 993\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("secrets.toml"),
+            "[work]\nimap-passwd-cmd = \"pass work\"\n",
+        )
+        .unwrap();
+        fs::write(
+            drop_in_dir.join("10-laptop.toml"),
+            "[work]\nimap-port = 1143\n",
+        )
+        .unwrap();
+        fs::write(drop_in_dir.join("README"), "not a config").unwrap();
+
+        let (config, files) = load(&dir.join("config.toml"), Some(&drop_in_dir)).unwrap();
+        assert_eq!(3, files.len());
+        assert_eq!(None, config.get("include"));
+        assert_eq!(Some("John"), config["name"].as_str());
+        let work = &config["work"];
+        assert_eq!(Some("john@work"), work["email"].as_str());
+        assert_eq!(Some("pass work"), work["imap-passwd-cmd"].as_str());
+        assert_eq!(Some(1143), work["imap-port"].as_integer());
+
+        fs::write(dir.join("secrets.toml"), "include = [\"config.toml\"]\n").unwrap();
+        assert!(load(&dir.join("config.toml"), None).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
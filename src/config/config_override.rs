@@ -0,0 +1,85 @@
+//! Module related to config overrides.
+//!
+//! Overrides are given with `--set KEY=VALUE` and applied to the config file content before it
+//! is parsed, so that a one-off invocation can change any option without editing the file (eg.
+//! `--set accounts.work.smtp-host=localhost` to send through a local relay). Keys are dotted
+//! paths, accounts being reachable with or without the `accounts.` prefix. Values are parsed as
+//! TOML (`2525`, `false`, `["a", "b"]`), and fall back to plain strings.
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use toml::{value::Table, Value};
+
+/// Parse the value of an override, as TOML when possible.
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+/// Apply the given `KEY=VALUE` override to the given config.
+pub fn apply(config: &mut Value, raw: &str) -> Result<()> {
+    let (key, val) = raw.split_once('=').ok_or_else(|| {
+        anyhow!(
+            r#"cannot parse config override "{}": expected KEY=VALUE"#,
+            raw
+        )
+    })?;
+    let key = key.trim();
+    // Accounts are flattened at the root of the config.
+    let key = key.strip_prefix("accounts.").unwrap_or(key);
+    let path: Vec<&str> = key.split('.').collect();
+    if path.iter().any(|segment| segment.is_empty()) {
+        return Err(anyhow!(
+            r#"cannot parse config override "{}": empty key"#,
+            raw
+        ));
+    }
+    debug!("override config key {:?}", path);
+
+    let (last, parents) = path.split_last().unwrap();
+    let mut table = config
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("cannot override config: it is not a table"))?;
+    for segment in parents {
+        table = table
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| {
+                anyhow!(
+                    r#"cannot apply config override "{}": "{}" is not a table"#,
+                    raw,
+                    segment
+                )
+            })?;
+    }
+    table.insert(last.to_string(), parse_value(val.trim()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_apply_overrides() {
+        let mut config: Value =
+            toml::from_str("name = \"John\"\n[work]\nsmtp-port = 465\n").unwrap();
+        apply(&mut config, "accounts.work.smtp-host=localhost").unwrap();
+        apply(&mut config, "work.smtp-port=2525").unwrap();
+        apply(&mut config, "work.smtp-starttls = false").unwrap();
+        apply(&mut config, "downloads-dir=/tmp").unwrap();
+
+        let work = &config["work"];
+        assert_eq!(Some("localhost"), work["smtp-host"].as_str());
+        assert_eq!(Some(2525), work["smtp-port"].as_integer());
+        assert_eq!(Some(false), work["smtp-starttls"].as_bool());
+        assert_eq!(Some("/tmp"), config["downloads-dir"].as_str());
+
+        assert!(apply(&mut config, "work.smtp-host").is_err());
+        assert!(apply(&mut config, "name.first=John").is_err());
+        assert!(apply(&mut config, "work..smtp-host=localhost").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Module related to config substitutions.
+//!
+//! String values of the config can contain substitutions, resolved when the config is loaded so
+//! that a config shared across machines needs no hard-coded secret or host:
+//!
+//! - `${NAME}` is replaced by the value of the `NAME` environment variable, `${NAME:-default}`
+//!   falling back to `default` when it is not set,
+//! - `$(command)` is replaced by the output of the shell command, without trailing new lines,
+//! - `$$` is replaced by a single `$`.
+//!
+//! Options holding commands (the ones ending with `-cmd` or `-cmds`) are left as they are: they
+//! go through the shell, which performs its own substitutions when they run.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::env;
+use toml::Value;
+
+use crate::output::pipe_cmd;
+
+fn is_cmd_key(key: &str) -> bool {
+    key.ends_with("-cmd") || key.ends_with("-cmds")
+}
+
+/// Find the end of the substitution starting at the given position, the one of its closing
+/// delimiter.
+fn find_closing(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        if *c == open {
+            depth += 1;
+        } else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn subst_var(expr: &str) -> Result<String> {
+    let (name, default) = match expr.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (expr, None),
+    };
+    match (env::var(name.trim()), default) {
+        (Ok(val), _) => Ok(val),
+        (Err(_), Some(default)) => Ok(default.to_owned()),
+        (Err(err), None) => Err(anyhow!("cannot substitute ${{{}}}: {}", name.trim(), err)),
+    }
+}
+
+fn subst_cmd(cmd: &str) -> Result<String> {
+    debug!("substitute config value with output of cmd {:?}", cmd);
+    let output = pipe_cmd(cmd, &[]).context(format!("cannot substitute $({})", cmd))?;
+    let output = String::from_utf8(output).context(format!("cannot substitute $({})", cmd))?;
+    Ok(output.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+/// Resolve the substitutions of the given string.
+pub fn subst_str(text: &str) -> Result<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut subst = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('$', Some('$')) => {
+                subst.push('$');
+                i += 2;
+            }
+            ('$', Some(open @ '{')) | ('$', Some(open @ '(')) => {
+                let close = if *open == '{' { '}' } else { ')' };
+                let end = find_closing(&chars, i + 1, *open, close)
+                    .ok_or_else(|| anyhow!("cannot substitute {:?}: missing {:?}", text, close))?;
+                let expr: String = chars[i + 2..end].iter().collect();
+                if close == '}' {
+                    subst.push_str(&subst_var(&expr)?);
+                } else {
+                    subst.push_str(&subst_cmd(&expr)?);
+                }
+                i = end + 1;
+            }
+            (c, _) => {
+                subst.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(subst)
+}
+
+/// Resolve the substitutions of the string values of the given config.
+pub fn subst(config: &mut Value) -> Result<()> {
+    match config {
+        Value::String(text) if text.contains('$') => {
+            *text = subst_str(text)?;
+        }
+        Value::Array(vals) => {
+            for val in vals {
+                subst(val)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, val) in table.iter_mut() {
+                if !is_cmd_key(key) {
+                    subst(val).context(format!(r#"cannot substitute option "{}""#, key))?;
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_substitute_config_values() {
+        env::set_var("HIMALAYA_TEST_SUBST_HOST", "imap.example.com");
+        env::remove_var("HIMALAYA_TEST_SUBST_UNSET");
+        let mut config: Value = toml::from_str(concat!(
+            "[work]\n",
+            "imap-host = \"${HIMALAYA_TEST_SUBST_HOST}\"\n",
+            "smtp-host = \"${HIMALAYA_TEST_SUBST_UNSET:-smtp.example.com}\"\n",
+            "signature = \"$(echo Regards)\"\n",
+            "downloads-dir = \"/tmp/$$HOME\"\n",
+            "imap-passwd-cmd = \"echo $(pass work)\"\n",
+        ))
+        .unwrap();
+        subst(&mut config).unwrap();
+
+        let work = &config["work"];
+        assert_eq!(Some("imap.example.com"), work["imap-host"].as_str());
+        assert_eq!(Some("smtp.example.com"), work["smtp-host"].as_str());
+        assert_eq!(Some("Regards"), work["signature"].as_str());
+        assert_eq!(Some("/tmp/$HOME"), work["downloads-dir"].as_str());
+        assert_eq!(Some("echo $(pass work)"), work["imap-passwd-cmd"].as_str());
+
+        assert!(subst_str("${HIMALAYA_TEST_SUBST_UNSET}").is_err());
+        assert!(subst_str("$(echo").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Module related to the first-run configuration wizard.
+//!
+//! When no config file exists, the wizard asks for the few settings a new account needs and
+//! writes them to the default config path. It also appends new accounts to an existing config
+//! with `account add`. The provider is detected from the email address, so that servers and
+//! folders of known providers only need to be confirmed, and the servers of other providers are
+//! discovered with [autoconfig](crate::domain::autoconfig). Outlook accounts can
+//! sign in with OAuth through the Graph backend, other accounts need a command printing their
+//! password. A test message can finally be sent to self to check the whole setup.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{
+    convert::TryFrom,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+use toml::{value::Table, Value};
+
+use crate::{
+    config::{Account, Config, Provider},
+    domain::{
+        autoconfig::{self, Autoconfig, ServerSettings},
+        backend::build_sender,
+        msg::{Msg, Part, TextPlainPart},
+    },
+};
+
+const DEFAULT_IMAP_PORT: u16 = 993;
+const DEFAULT_SMTP_PORT: u16 = 465;
+
+/// Ask the given question, falling back to the default answer when the input is empty. Without
+/// default answer, the question is asked again until it is answered.
+fn prompt(question: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(default) => print!("{} [{}]: ", question, default),
+            None => print!("{}: ", question),
+        }
+        io::stdout().flush().context("cannot flush stdout")?;
+
+        let mut buf = String::new();
+        io::stdin()
+            .read_line(&mut buf)
+            .context("cannot read stdin")?;
+        if buf.is_empty() {
+            return Err(anyhow!("cannot read answer: end of input"));
+        }
+        match (buf.trim(), default) {
+            ("", Some(default)) => return Ok(default.to_owned()),
+            ("", None) => continue,
+            (answer, _) => return Ok(answer.to_owned()),
+        }
+    }
+}
+
+/// Ask the given yes/no question.
+fn confirm(question: &str, default: bool) -> Result<bool> {
+    let answer = prompt(question, Some(if default { "Y/n" } else { "y/N" }))?;
+    Ok(match answer.to_lowercase().chars().next() {
+        Some('y') => true,
+        Some('n') => false,
+        _ => default,
+    })
+}
+
+fn prompt_port(question: &str, default: u16) -> Result<i64> {
+    loop {
+        match prompt(question, Some(&default.to_string()))?.parse::<u16>() {
+            Ok(port) => return Ok(port.into()),
+            Err(_) => println!("The port must be a number between 0 and 65535."),
+        }
+    }
+}
+
+/// Build the config entry of a new account by asking the user.
+fn build_account(email: &str, default: bool) -> Result<Table> {
+    let mut account = Table::new();
+    if default {
+        account.insert("default".into(), Value::Boolean(true));
+    }
+    account.insert("email".into(), Value::String(email.to_owned()));
+
+    let provider = Provider::detect(email);
+    let profile = provider.map(|provider| provider.profile());
+    let domain = email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or_default();
+
+    match provider {
+        Some(provider) => {
+            println!("Known provider detected: {:?}.", provider);
+            account.insert("provider".into(), Value::try_from(provider)?);
+        }
+        None => {
+            println!("Unknown provider, looking for its server settings…");
+            let (imap, smtp) = match autoconfig::discover(email) {
+                Some(Autoconfig { imap, smtp }) => {
+                    println!("Server settings found, please confirm them.");
+                    (imap, smtp)
+                }
+                None => {
+                    println!("No server settings found, please enter them.");
+                    let guess = |kind: &str, port: u16| ServerSettings {
+                        host: format!("{}.{}", kind, domain),
+                        port,
+                        starttls: false,
+                    };
+                    (
+                        guess("imap", DEFAULT_IMAP_PORT),
+                        guess("smtp", DEFAULT_SMTP_PORT),
+                    )
+                }
+            };
+            for (kind, server, default_port) in [
+                ("imap", imap, DEFAULT_IMAP_PORT),
+                ("smtp", smtp, DEFAULT_SMTP_PORT),
+            ] {
+                let name = kind.to_uppercase();
+                let host = prompt(&format!("{} host", name), Some(&server.host))?;
+                let port = prompt_port(&format!("{} port", name), server.port)?;
+                // STARTTLS is kept from the discovered settings when their port is confirmed.
+                // Otherwise, only the implicit TLS ports are known to need no STARTTLS.
+                let starttls = if port == i64::from(server.port) {
+                    server.starttls
+                } else {
+                    port != i64::from(default_port)
+                };
+                account.insert(format!("{}-host", kind), Value::String(host));
+                account.insert(format!("{}-port", kind), Value::Integer(port));
+                if starttls {
+                    account.insert(format!("{}-starttls", kind), Value::Boolean(true));
+                }
+            }
+        }
+    }
+
+    let oauth = provider == Some(Provider::Outlook)
+        && confirm("Sign in with OAuth (Microsoft Graph)?", true)?;
+    if oauth {
+        let client_id = prompt("Azure application (client) id", None)?;
+        account.insert("backend".into(), Value::String("graph".into()));
+        account.insert("graph-client-id".into(), Value::String(client_id));
+    } else {
+        if provider.is_some() {
+            println!("This provider requires an app password, generated from its web interface.");
+        }
+        let passwd_cmd = prompt("Command printing the password (eg. pass show mail)", None)?;
+        account.insert("imap-login".into(), Value::String(email.to_owned()));
+        account.insert("smtp-login".into(), Value::String(email.to_owned()));
+        account.insert("passwd-cmd".into(), Value::String(passwd_cmd));
+    }
+
+    println!("Folders mapping:");
+    let folders = [
+        (
+            "sent-folder",
+            "Sent folder",
+            profile.as_ref().map(|p| p.sent_folder),
+        ),
+        (
+            "drafts-folder",
+            "Drafts folder",
+            profile.as_ref().map(|p| p.drafts_folder),
+        ),
+        (
+            "trash-folder",
+            "Trash folder",
+            profile.as_ref().map(|p| p.trash_folder),
+        ),
+    ];
+    for (key, question, profile_folder) in folders {
+        let default = profile_folder.unwrap_or(match key {
+            "sent-folder" => "Sent",
+            "drafts-folder" => "Drafts",
+            _ => "Trash",
+        });
+        let folder = prompt(question, Some(default))?;
+        // Folders matching the provider profile are left to it.
+        if Some(folder.as_str()) != profile_folder {
+            account.insert(key.into(), Value::String(folder));
+        }
+    }
+
+    Ok(account)
+}
+
+/// Send a test message from the new account to itself.
+fn send_test_msg(path: &Path) -> Result<()> {
+    let config = Config::try_from(path.to_str())?;
+    let account = Account::try_from((&config, None))?;
+    let addr: lettre::message::Mailbox = account.address().parse()?;
+
+    let mut msg = Msg {
+        from: Some(vec![addr.to_owned()]),
+        to: Some(vec![addr]),
+        subject: String::from("Himalaya test message"),
+        ..Msg::default()
+    };
+    msg.parts.push(Part::TextPlain(TextPlainPart {
+        content: String::from("Your account is ready to use.\n"),
+    }));
+    build_sender(&account)
+        .send(&msg)
+        .context("cannot send test message")?;
+    Ok(())
+}
+
+fn prompt_email() -> Result<String> {
+    loop {
+        let email = prompt("Email address", None)?;
+        match email.parse::<lettre::Address>() {
+            Ok(_) => return Ok(email),
+            Err(err) => println!("Invalid email address: {}.", err),
+        }
+    }
+}
+
+fn prompt_account_name(email: &str, config: Option<&Config>) -> Result<String> {
+    loop {
+        let name = prompt(
+            "Account name",
+            email.rsplit_once('@').map(|(_, domain)| domain),
+        )?;
+        match config {
+            Some(config) if config.accounts.contains_key(&name) => {
+                println!(r#"Account "{}" already exists."#, name)
+            }
+            _ => return Ok(name),
+        }
+    }
+}
+
+/// Run the wizard, then write the config to the given path.
+pub fn run(path: &Path) -> Result<()> {
+    println!("No config file found at {:?}, let's create one.", path);
+    let email = prompt_email()?;
+    let name = prompt("Full name", None)?;
+    let account_name = prompt_account_name(&email, None)?;
+
+    let mut config = Table::new();
+    config.insert("name".into(), Value::String(name));
+    config.insert(account_name, Value::Table(build_account(&email, true)?));
+    let content = toml::to_string(&Value::Table(config)).context("cannot serialize config")?;
+    debug!("config: {}", content);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("cannot create config directory {:?}", dir))?;
+    }
+    fs::write(path, content).context(format!("cannot write config file {:?}", path))?;
+    println!("Config file written to {:?}.", path);
+
+    if confirm("Send a test message to yourself?", true)? {
+        send_test_msg(path)?;
+        println!("Test message sent, check your inbox.");
+    }
+    Ok(())
+}
+
+/// Ask for a new account, then append it to the config of the given path. Returns the name of
+/// the new account.
+pub fn add_account(path: &Path, config: &Config) -> Result<String> {
+    let email = prompt_email()?;
+    let account_name = prompt_account_name(&email, Some(config))?;
+
+    let mut entry = Table::new();
+    entry.insert(
+        account_name.to_owned(),
+        Value::Table(build_account(&email, config.accounts.is_empty())?),
+    );
+    let content = toml::to_string(&Value::Table(entry)).context("cannot serialize account")?;
+    debug!("account: {}", content);
+
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .and_then(|mut file| write!(file, "\n{}", content))
+        .context(format!("cannot append account to config file {:?}", path))?;
+    Ok(account_name)
+}
@@ -1,9 +1,29 @@
 //! Module related to the user's configuration.
 
+#[cfg(feature = "cli")]
 pub mod config_arg;
+pub mod config_bundle;
+pub mod config_check;
+#[cfg(feature = "cli")]
+pub mod config_handler;
+pub mod config_include;
+pub mod config_override;
+pub mod config_subst;
+#[cfg(feature = "cli")]
+pub mod config_wizard;
 
 pub mod account_entity;
 pub use account_entity::*;
 
 pub mod config_entity;
 pub use config_entity::*;
+
+pub mod passwd;
+pub mod proxy;
+pub mod secrets;
+
+pub mod provider_entity;
+pub use provider_entity::*;
+
+pub mod system_mode;
+pub mod tls;
@@ -0,0 +1,207 @@
+//! Module related to password lookup.
+//!
+//! Passwords come from a command printing them (`imap-passwd-cmd`, `smtp-passwd-cmd`, or
+//! `passwd-cmd` for both), or from the system keyring (`passwd-keyring = "himalaya/work"`, a
+//! service and a user separated by a slash). The keyring is reached through `secret-tool` on
+//! Linux (libsecret) and `security` on macOS, so that no secret service library is linked.
+//! Passwords can also be kept in the encrypted secrets file (`passwd-secret = "work.passwd"`,
+//! see [`secrets`](crate::config::secrets)).
+//!
+//! Passwords are cached for the duration of the run: a command opening the IMAP and the SMTP
+//! sessions, or reconnecting, does not run the password command (and maybe prompt for a GPG
+//! passphrase) again.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use crate::{config::secrets, output::run_cmd};
+
+/// The passwords already looked up, by source.
+static CACHE: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Represents where a password is looked up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswdSource<'a> {
+    /// A command printing the password.
+    Cmd(&'a str),
+    /// A `service/user` keyring entry.
+    Keyring(&'a str),
+    /// A key of the given secrets file, decrypted by the given command or by default.
+    Secret {
+        file: &'a Path,
+        decrypt_cmd: Option<&'a str>,
+        key: &'a str,
+    },
+}
+
+impl<'a> PasswdSource<'a> {
+    fn cache_key(&self) -> String {
+        match self {
+            Self::Cmd(cmd) => format!("cmd:{}", cmd),
+            Self::Keyring(entry) => format!("keyring:{}", entry),
+            Self::Secret { file, key, .. } => format!("secret:{:?}:{}", file, key),
+        }
+    }
+
+    /// Look the password up, or get it from the cache of the run.
+    pub fn get(&self) -> Result<String> {
+        let key = self.cache_key();
+        if let Some((_, passwd)) = CACHE.lock().unwrap().iter().find(|(k, _)| *k == key) {
+            debug!("password found in cache");
+            return Ok(passwd.to_owned());
+        }
+
+        let passwd = match self {
+            Self::Cmd(cmd) => run_cmd(cmd).context("cannot run passwd cmd")?,
+            Self::Keyring(entry) => keyring_get(entry)?,
+            Self::Secret {
+                file,
+                decrypt_cmd,
+                key,
+            } => secrets::get(file, *decrypt_cmd, key)?,
+        };
+        let passwd = passwd
+            .trim_end_matches(|c| c == '\r' || c == '\n')
+            .to_owned();
+        CACHE.lock().unwrap().push((key, passwd.to_owned()));
+        Ok(passwd)
+    }
+}
+
+/// Split a keyring entry into its service and its user.
+fn parse_entry(entry: &str) -> Result<(&str, &str)> {
+    entry
+        .split_once('/')
+        .filter(|(service, user)| !service.is_empty() && !user.is_empty())
+        .ok_or_else(|| {
+            anyhow!(
+                r#"cannot parse keyring entry "{}": expected SERVICE/USER"#,
+                entry
+            )
+        })
+}
+
+fn keyring_get(entry: &str) -> Result<String> {
+    let (service, user) = parse_entry(entry)?;
+    let output = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(&["find-generic-password", "-s", service, "-a", user, "-w"])
+            .output()
+    } else if cfg!(target_family = "unix") {
+        Command::new("secret-tool")
+            .args(&["lookup", "service", service, "username", user])
+            .output()
+    } else {
+        return Err(anyhow!(
+            "cannot use keyring: not supported on this platform"
+        ));
+    }
+    .context("cannot run keyring lookup")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            r#"cannot find password of keyring entry "{}""#,
+            entry
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Quote the given argument of a command read by `security -i`.
+fn security_quote(arg: &str) -> String {
+    format!(r#""{}""#, arg.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// Store the given password in the keyring entry. The password is written to the standard input
+/// of the keyring command, never passed as an argument, where other users could read it.
+pub fn keyring_set(entry: &str, passwd: &str) -> Result<()> {
+    let (service, user) = parse_entry(entry)?;
+    if passwd.contains(|c| c == '\r' || c == '\n') {
+        return Err(anyhow!("cannot store password: it contains a line break"));
+    }
+    let (mut cmd, input) = if cfg!(target_os = "macos") {
+        // The interactive mode reads the commands to run on its standard input.
+        let mut cmd = Command::new("security");
+        cmd.arg("-i");
+        let input = format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            security_quote(service),
+            security_quote(user),
+            security_quote(passwd)
+        );
+        (cmd, input)
+    } else if cfg!(target_family = "unix") {
+        let label = format!("himalaya: {}", entry);
+        let mut cmd = Command::new("secret-tool");
+        cmd.args(&[
+            "store", "--label", &label, "service", service, "username", user,
+        ]);
+        (cmd, passwd.to_owned())
+    } else {
+        return Err(anyhow!(
+            "cannot use keyring: not supported on this platform"
+        ));
+    };
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("cannot run keyring store")?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("cannot open stdin of keyring store"))?
+        .write_all(input.as_bytes())
+        .context("cannot write password to keyring store")?;
+    let output = child
+        .wait_with_output()
+        .context("cannot wait for keyring store")?;
+
+    // The interactive mode of `security` exits successfully even when its commands fail, only
+    // reporting errors on its standard error.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || (cfg!(target_os = "macos") && !stderr.trim().is_empty()) {
+        return Err(anyhow!(
+            r#"cannot store password in keyring entry "{}": keyring exited with {}: {}"#,
+            entry,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_cache_passwords() {
+        assert_eq!(
+            Ok(("himalaya", "work")),
+            parse_entry("himalaya/work").map_err(|_| ())
+        );
+        assert!(parse_entry("himalaya").is_err());
+        assert!(parse_entry("/work").is_err());
+        assert_eq!(r#""a \"b\" \\c""#, security_quote(r#"a "b" \c"#));
+
+        if cfg!(target_family = "unix") {
+            let path = std::env::temp_dir().join(format!("himalaya-passwd-{}", std::process::id()));
+            std::fs::write(&path, "secret\n").unwrap();
+            let cmd = format!("cat {:?}", path);
+            assert_eq!("secret", PasswdSource::Cmd(&cmd).get().unwrap());
+
+            // The second lookup does not run the command.
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!("secret", PasswdSource::Cmd(&cmd).get().unwrap());
+        }
+    }
+}
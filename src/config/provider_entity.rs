@@ -0,0 +1,165 @@
+//! Module related to provider profiles.
+//!
+//! A provider profile holds the known settings and quirks of a mail provider, selected with
+//! `provider = "gmail"` in the account config: server addresses, folder names and whether sent
+//! messages need to be copied to the sent folder. Account options always take precedence over
+//! the profile, which takes precedence over the global config.
+//!
+//! OAuth is only supported by the Graph backend, so profiles do not configure it: Gmail, Yahoo,
+//! iCloud and Fastmail accounts need an app password in `imap-passwd-cmd` and `smtp-passwd-cmd`.
+
+use serde::{Deserialize, Serialize};
+
+/// Represents the mail providers himalaya ships a profile for.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    Gmail,
+    Outlook,
+    Yahoo,
+    Icloud,
+    Fastmail,
+}
+
+/// Represents the known settings and quirks of a provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderProfile {
+    pub imap_host: &'static str,
+    pub imap_port: u16,
+    pub imap_starttls: bool,
+    pub smtp_host: &'static str,
+    pub smtp_port: u16,
+    pub smtp_starttls: bool,
+    pub sent_folder: &'static str,
+    pub drafts_folder: &'static str,
+    pub trash_folder: &'static str,
+    pub archive_folder: &'static str,
+    pub junk_folder: &'static str,
+    /// Whether sent messages need to be appended to the sent folder. Some providers store a copy
+    /// of the messages sent over SMTP by themselves, appending them would duplicate them.
+    pub save_sent_copy: bool,
+}
+
+impl Provider {
+    /// Detect the provider of the given email address from its domain.
+    pub fn detect(email: &str) -> Option<Self> {
+        let domain = email.rsplit_once('@')?.1.trim().to_lowercase();
+        match domain.as_str() {
+            "gmail.com" | "googlemail.com" => Some(Self::Gmail),
+            "outlook.com" | "hotmail.com" | "live.com" | "msn.com" => Some(Self::Outlook),
+            "icloud.com" | "me.com" | "mac.com" => Some(Self::Icloud),
+            "fastmail.com" | "fastmail.fm" => Some(Self::Fastmail),
+            domain if domain.starts_with("yahoo.") || domain.starts_with("ymail.") => {
+                Some(Self::Yahoo)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn profile(&self) -> ProviderProfile {
+        match self {
+            Self::Gmail => ProviderProfile {
+                imap_host: "imap.gmail.com",
+                imap_port: 993,
+                imap_starttls: false,
+                smtp_host: "smtp.gmail.com",
+                smtp_port: 465,
+                smtp_starttls: false,
+                sent_folder: "[Gmail]/Sent Mail",
+                drafts_folder: "[Gmail]/Drafts",
+                trash_folder: "[Gmail]/Trash",
+                // Archiving a Gmail message only removes its Inbox label.
+                archive_folder: "[Gmail]/All Mail",
+                junk_folder: "[Gmail]/Spam",
+                save_sent_copy: false,
+            },
+            Self::Outlook => ProviderProfile {
+                imap_host: "outlook.office365.com",
+                imap_port: 993,
+                imap_starttls: false,
+                smtp_host: "smtp.office365.com",
+                smtp_port: 587,
+                smtp_starttls: true,
+                sent_folder: "Sent Items",
+                drafts_folder: "Drafts",
+                trash_folder: "Deleted Items",
+                archive_folder: "Archive",
+                junk_folder: "Junk Email",
+                save_sent_copy: false,
+            },
+            Self::Yahoo => ProviderProfile {
+                imap_host: "imap.mail.yahoo.com",
+                imap_port: 993,
+                imap_starttls: false,
+                smtp_host: "smtp.mail.yahoo.com",
+                smtp_port: 465,
+                smtp_starttls: false,
+                sent_folder: "Sent",
+                drafts_folder: "Draft",
+                trash_folder: "Trash",
+                archive_folder: "Archive",
+                junk_folder: "Bulk",
+                save_sent_copy: true,
+            },
+            Self::Icloud => ProviderProfile {
+                imap_host: "imap.mail.me.com",
+                imap_port: 993,
+                imap_starttls: false,
+                smtp_host: "smtp.mail.me.com",
+                smtp_port: 587,
+                smtp_starttls: true,
+                sent_folder: "Sent Messages",
+                drafts_folder: "Drafts",
+                trash_folder: "Deleted Messages",
+                archive_folder: "Archive",
+                junk_folder: "Junk",
+                save_sent_copy: true,
+            },
+            Self::Fastmail => ProviderProfile {
+                imap_host: "imap.fastmail.com",
+                imap_port: 993,
+                imap_starttls: false,
+                smtp_host: "smtp.fastmail.com",
+                smtp_port: 465,
+                smtp_starttls: false,
+                sent_folder: "Sent",
+                drafts_folder: "Drafts",
+                trash_folder: "Trash",
+                archive_folder: "Archive",
+                junk_folder: "Spam",
+                save_sent_copy: true,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_deserialize_providers() {
+        #[derive(Deserialize)]
+        struct Entry {
+            provider: Provider,
+        }
+
+        let entry: Entry = toml::from_str(r#"provider = "gmail""#).unwrap();
+        assert_eq!(Provider::Gmail, entry.provider);
+        assert_eq!("[Gmail]/Sent Mail", entry.provider.profile().sent_folder);
+        assert!(!entry.provider.profile().save_sent_copy);
+        assert!(toml::from_str::<Entry>(r#"provider = "aol""#).is_err());
+    }
+
+    #[test]
+    fn it_should_detect_providers() {
+        assert_eq!(Some(Provider::Gmail), Provider::detect("john@GMail.com"));
+        assert_eq!(Some(Provider::Yahoo), Provider::detect("john@yahoo.co.uk"));
+        assert_eq!(
+            Some(Provider::Outlook),
+            Provider::detect("john@hotmail.com")
+        );
+        assert_eq!(None, Provider::detect("john@localhost"));
+        assert_eq!(None, Provider::detect("john"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Module related to the secrets file.
+//!
+//! Passwords and OAuth refresh tokens can be kept in a file encrypted with [age] or GPG,
+//! referenced by the `secrets-file` option, so that the config itself holds no credentials. Once
+//! decrypted, the file is a TOML table of secrets, referenced by their key, nested tables being
+//! reached with dots (eg. `passwd-secret = "work.passwd"`):
+//!
+//! ```toml
+//! [work]
+//! passwd = "…"
+//! graph-refresh-token = "…"
+//! ```
+//!
+//! The file is decrypted on demand, at most once per run, with `age --decrypt` or `gpg
+//! --decrypt` depending on its extension, or with the `secrets-decrypt-cmd` option. The
+//! passphrase is asked on the terminal, or by the agent of the tool.
+//!
+//! [age]: https://age-encryption.org
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+/// The secrets files already decrypted, by path.
+static CACHE: Mutex<Vec<(PathBuf, toml::Value)>> = Mutex::new(Vec::new());
+
+/// Build the command printing the decrypted content of the given file.
+fn decrypt_cmd(path: &Path, cmd: Option<&str>) -> Result<Command> {
+    if let Some(cmd) = cmd {
+        let mut decrypt = if cfg!(target_os = "windows") {
+            let mut decrypt = Command::new("cmd");
+            decrypt.args(&["/C", cmd]);
+            decrypt
+        } else {
+            let mut decrypt = Command::new("sh");
+            decrypt.args(&["-c", cmd]);
+            decrypt
+        };
+        decrypt.env("HIMALAYA_SECRETS_FILE", path);
+        return Ok(decrypt);
+    }
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("age") => {
+            let mut decrypt = Command::new("age");
+            decrypt.arg("--decrypt").arg(path);
+            Ok(decrypt)
+        }
+        Some("gpg") | Some("asc") => {
+            let mut decrypt = Command::new("gpg");
+            decrypt.args(&["--quiet", "--decrypt"]).arg(path);
+            Ok(decrypt)
+        }
+        _ => Err(anyhow!(
+            r#"cannot decrypt secrets file {:?}: expected an .age or .gpg file, or a "secrets-decrypt-cmd""#,
+            path
+        )),
+    }
+}
+
+/// Decrypt and parse the given secrets file.
+fn decrypt(path: &Path, cmd: Option<&str>) -> Result<toml::Value> {
+    debug!("decrypt secrets file {:?}", path);
+    // The prompts of the tool are left on the terminal.
+    let output = decrypt_cmd(path, cmd)?
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .context(format!("cannot run decryption of secrets file {:?}", path))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "cannot decrypt secrets file {:?}: decryption exited with {}",
+            path,
+            output.status
+        ));
+    }
+    let content = String::from_utf8(output.stdout)
+        .context(format!("cannot decode secrets file {:?}", path))?;
+    // The error would quote the content.
+    toml::from_str(&content).map_err(|_| anyhow!("cannot parse secrets file {:?}", path))
+}
+
+/// Find the secret of the given dotted key.
+fn lookup<'a>(secrets: &'a toml::Value, key: &str) -> Option<&'a str> {
+    key.split('.')
+        .try_fold(secrets, |secrets, key| secrets.get(key))
+        .and_then(toml::Value::as_str)
+}
+
+/// Get the secret of the given key, decrypting the given secrets file unless it already was
+/// during the run.
+pub fn get(path: &Path, cmd: Option<&str>, key: &str) -> Result<String> {
+    let mut cache = CACHE.lock().unwrap();
+    let pos = match cache.iter().position(|(p, _)| p == path) {
+        Some(pos) => pos,
+        None => {
+            cache.push((path.to_owned(), decrypt(path, cmd)?));
+            cache.len() - 1
+        }
+    };
+    lookup(&cache[pos].1, key)
+        .map(String::from)
+        .ok_or_else(|| anyhow!(r#"cannot find secret "{}" in {:?}"#, key, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_get_secrets() {
+        let secrets: toml::Value =
+            toml::from_str("root = \"a\"\n[work]\npasswd = \"b\"\nport = 993\n").unwrap();
+        assert_eq!(Some("a"), lookup(&secrets, "root"));
+        assert_eq!(Some("b"), lookup(&secrets, "work.passwd"));
+        assert_eq!(None, lookup(&secrets, "work.port"));
+        assert_eq!(None, lookup(&secrets, "home.passwd"));
+        assert!(decrypt_cmd(Path::new("secrets.toml"), None).is_err());
+
+        if cfg!(target_family = "unix") {
+            let path =
+                std::env::temp_dir().join(format!("himalaya-secrets-{}", std::process::id()));
+            std::fs::write(&path, "[work]\npasswd = \"secret\"\n").unwrap();
+            let cmd = Some(r#"cat "$HIMALAYA_SECRETS_FILE""#);
+            assert_eq!("secret", get(&path, cmd, "work.passwd").unwrap());
+
+            // The file is only decrypted once.
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!("secret", get(&path, cmd, "work.passwd").unwrap());
+            assert!(get(&path, cmd, "work.login").is_err());
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Module related to the system mode.
+//!
+//! The system mode hardens himalaya for shared servers, where other users may read whatever is
+//! left readable. When enabled with the `system-mode` config option:
+//!
+//! - the config file and the account cache directories must not be accessible by other users,
+//! - the cache never falls back to the shared temporary directory,
+//! - temporary files holding message content (drafts, attachments being shared or downloaded)
+//!   only live in the private runtime directory `$XDG_RUNTIME_DIR/himalaya`.
+//!
+//! Cache files updated by several processes are guarded by per-user lock files in any mode, and
+//! commands talking to the server lock their account so that concurrent invocations (eg. a cron
+//! job and an interactive session) do not trip over each other.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Time after which a lock is considered left behind by a crashed process. Locks only guard
+/// short reads and writes of cache files, so this is generous.
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(30);
+/// Time to wait for a lock before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// Interval at which long-held locks are refreshed, so that they never look stale.
+const LOCK_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Check that the given file or directory is accessible by its owner only.
+#[cfg(unix)]
+pub fn check_private(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .context(format!("cannot get permissions of {:?}", path))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(anyhow!(
+            "cannot use {:?}: it is accessible by other users (mode {:o}), run `chmod go= {:?}`",
+            path,
+            mode & 0o777,
+            path
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_private(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Check that the given file or directory is owned by the user. Symlinks are not followed, so
+/// that a path planted by another user is never trusted.
+#[cfg(unix)]
+pub fn check_owned(path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let owner = fs::symlink_metadata(path)
+        .context(format!("cannot get owner of {:?}", path))?
+        .uid();
+    // SAFETY: getuid has no precondition and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if owner != uid {
+        return Err(anyhow!(
+            "cannot use {:?}: it is owned by another user (uid {})",
+            path,
+            owner
+        ));
+    }
+    Ok(())
+}
+
+/// Create the given directory and its missing parents, accessible by the user only.
+pub fn create_private_dir(path: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(path)
+        .context(format!("cannot create private dir {:?}", path))
+}
+
+/// Get the directory temporary files holding message content are written to:
+/// `$XDG_RUNTIME_DIR/himalaya` when `XDG_RUNTIME_DIR` is set, the system temporary directory
+/// otherwise. The system mode refuses to start without `XDG_RUNTIME_DIR`, so that it never falls
+/// back to the latter.
+pub fn runtime_dir() -> PathBuf {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => {
+            let path = PathBuf::from(dir).join("himalaya");
+            if let Err(err) = create_private_dir(&path) {
+                warn!("{:?}", err);
+            }
+            path
+        }
+        Err(_) => env::temp_dir(),
+    }
+}
+
+/// Check the requirements of the system mode on the given config file.
+pub fn check_config(path: &Path) -> Result<()> {
+    debug!("check system mode requirements");
+    check_private(path)?;
+    env::var("XDG_RUNTIME_DIR")
+        .context("cannot find `XDG_RUNTIME_DIR` env var, required by system mode")?;
+    Ok(())
+}
+
+/// Represents a lock file, released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    heartbeat: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl Lock {
+    /// Acquire the lock file at the given path, waiting for the process holding it if any.
+    pub fn acquire(path: PathBuf) -> Result<Self> {
+        Self::acquire_timeout(path, Some(LOCK_TIMEOUT))
+    }
+
+    /// Acquire the lock file at the given path for as long as needed, refreshing it in the
+    /// background. Without `wait`, it fails right away if another process holds it.
+    pub fn acquire_held(path: PathBuf, wait: bool) -> Result<Self> {
+        let timeout = if wait { None } else { Some(Duration::ZERO) };
+        let mut lock = Self::acquire_timeout(path, timeout)?;
+        let (tx, rx) = mpsc::channel();
+        let path = lock.path.clone();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(LOCK_HEARTBEAT_INTERVAL) {
+                if let Err(err) = fs::write(&path, format!("{}\n", process::id())) {
+                    warn!("cannot refresh lock {:?}: {}", path, err);
+                }
+            }
+        });
+        lock.heartbeat = Some((tx, handle));
+        Ok(lock)
+    }
+
+    fn acquire_timeout(path: PathBuf, timeout: Option<Duration>) -> Result<Self> {
+        let start = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    debug!("acquire lock {:?}", path);
+                    writeln!(file, "{}", process::id()).ok();
+                    return Ok(Self {
+                        path,
+                        heartbeat: None,
+                    });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    let is_stale = fs::metadata(&path)
+                        .and_then(|meta| meta.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .map(|age| age > LOCK_STALE_AFTER)
+                        .unwrap_or_default();
+                    if is_stale {
+                        warn!("remove stale lock {:?}", path);
+                        fs::remove_file(&path).ok();
+                    } else if timeout
+                        .map(|timeout| start.elapsed() >= timeout)
+                        .unwrap_or_default()
+                    {
+                        return Err(anyhow!(
+                            "cannot acquire lock {:?}: it is held by another process",
+                            path
+                        ));
+                    } else {
+                        thread::sleep(LOCK_RETRY_INTERVAL);
+                    }
+                }
+                Err(err) => {
+                    return Err(err).context(format!("cannot create lock {:?}", path));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Stop the heartbeat first, so that it cannot write the lock back once removed.
+        if let Some((tx, handle)) = self.heartbeat.take() {
+            drop(tx);
+            handle.join().ok();
+        }
+        debug!("release lock {:?}", self.path);
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_guard_private_dirs() {
+        let dir = env::temp_dir().join(format!("himalaya-test-{}", process::id()));
+        let private_dir = dir.join("private");
+        create_private_dir(&private_dir).unwrap();
+        assert!(check_private(&private_dir).is_ok());
+
+        let lock_path = private_dir.join("lock");
+        let lock = Lock::acquire(lock_path.clone()).unwrap();
+        assert!(lock_path.exists());
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        let lock = Lock::acquire_held(lock_path.clone(), false).unwrap();
+        assert!(Lock::acquire_held(lock_path.clone(), false).is_err());
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let shared_path = dir.join("shared");
+            fs::write(&shared_path, "secret").unwrap();
+            fs::set_permissions(&shared_path, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(check_private(&shared_path).is_err());
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,139 @@
+//! Module related to TLS settings.
+//!
+//! Servers whose certificate is signed by a private CA are trusted with `tls-ca-cert`, the PEM
+//! certificate of the CA. A server certificate can also be pinned by its SHA-256 fingerprint
+//! with `imap-cert-fingerprint` or `smtp-cert-fingerprint`: it is then trusted whoever signed it,
+//! and any other certificate is rejected. Servers requiring client certificates get the one of
+//! `tls-client-cert` and `tls-client-key`.
+//!
+//! The SMTP client only accepts a custom CA: a pinned SMTP fingerprint is rejected rather than
+//! silently ignored, and the client certificate is not sent to the SMTP server.
+
+use anyhow::{anyhow, Context, Result};
+use lettre::transport::smtp::client::{Certificate as SmtpCertificate, TlsParameters};
+use log::warn;
+use native_tls::{Certificate, Identity, TlsConnector, TlsStream};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use crate::config::Account;
+
+fn read(path: &Path, what: &str) -> Result<Vec<u8>> {
+    fs::read(path).context(format!("cannot read {} {:?}", what, path))
+}
+
+/// Format the SHA-256 fingerprint of the given DER certificate, like `AB:CD:…`.
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Compare fingerprints, ignoring case and separators.
+fn same_fingerprint(a: &str, b: &str) -> bool {
+    let normalize = |fp: &str| {
+        fp.chars()
+            .filter(char::is_ascii_hexdigit)
+            .map(|c| c.to_ascii_lowercase())
+            .collect::<String>()
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Build the TLS connector of the account, trusting any certificate when insecure or when a
+/// fingerprint is pinned, in which case [`check_fingerprint`] must be called once connected.
+pub fn connector(account: &Account, insecure: bool, pinned: bool) -> Result<TlsConnector> {
+    let mut builder = TlsConnector::builder();
+    builder
+        .danger_accept_invalid_certs(insecure || pinned)
+        .danger_accept_invalid_hostnames(insecure || pinned);
+
+    if let Some(ref path) = account.tls_ca_cert {
+        let cert = Certificate::from_pem(&read(path, "CA certificate")?)
+            .context(format!("cannot parse CA certificate {:?}", path))?;
+        builder.add_root_certificate(cert);
+    }
+
+    match (&account.tls_client_cert, &account.tls_client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let identity = Identity::from_pkcs8(
+                &read(cert_path, "client certificate")?,
+                &read(key_path, "client key")?,
+            )
+            .context(format!("cannot parse client certificate {:?}", cert_path))?;
+            builder.identity(identity);
+        }
+        (None, None) => (),
+        _ => {
+            return Err(anyhow!(
+                r#"cannot use client certificate: "tls-client-cert" and "tls-client-key" go together"#
+            ))
+        }
+    }
+
+    builder.build().context("cannot create TLS connector")
+}
+
+/// Check the certificate of the given stream against the pinned fingerprint.
+pub fn check_fingerprint<S: Read + Write>(stream: &TlsStream<S>, expected: &str) -> io::Result<()> {
+    let to_io = |err: native_tls::Error| io::Error::new(io::ErrorKind::Other, err);
+    let der = stream
+        .peer_certificate()
+        .map_err(to_io)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "server sent no certificate"))?
+        .to_der()
+        .map_err(to_io)?;
+    let actual = fingerprint(&der);
+    if same_fingerprint(&actual, expected) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "certificate fingerprint {} does not match the pinned one {}",
+                actual, expected
+            ),
+        ))
+    }
+}
+
+/// Build the TLS parameters of the SMTP client.
+pub fn smtp_parameters(account: &Account) -> Result<TlsParameters> {
+    if account.smtp_cert_fingerprint.is_some() {
+        return Err(anyhow!(
+            "cannot pin SMTP certificate: not supported by the SMTP client, use \"tls-ca-cert\""
+        ));
+    }
+    if account.tls_client_cert.is_some() {
+        warn!("client certificate not sent to SMTP server: not supported by the SMTP client");
+    }
+
+    let mut builder = TlsParameters::builder(account.smtp_host.to_owned())
+        .dangerous_accept_invalid_hostnames(account.smtp_insecure)
+        .dangerous_accept_invalid_certs(account.smtp_insecure);
+    if let Some(ref path) = account.tls_ca_cert {
+        let cert = SmtpCertificate::from_pem(&read(path, "CA certificate")?)
+            .context(format!("cannot parse CA certificate {:?}", path))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_compare_fingerprints() {
+        let fp = fingerprint(b"cert");
+        assert_eq!(95, fp.len());
+        assert!(same_fingerprint(&fp, &fp.replace(':', "").to_lowercase()));
+        assert!(!same_fingerprint(&fp, &fingerprint(b"other cert")));
+    }
+}
@@ -0,0 +1,100 @@
+//! Module related to account CLI.
+//!
+//! This module provides subcommands and a command matcher related to accounts.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Name<'a> = Option<&'a str>;
+
+/// Account commands.
+pub enum Command<'a> {
+    /// List the accounts of the config.
+    List,
+    /// Add an account to the config with the wizard.
+    Add,
+    /// Print what is known about the given account, or about the selected one.
+    Info(Name<'a>),
+    /// Store the password of the selected account in its keyring entry.
+    SetPassword,
+    /// Diagnose the connections of the given account, or of the selected one.
+    Doctor(Name<'a>),
+}
+
+/// Account command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("account") {
+        if m.subcommand_matches("list").is_some() {
+            debug!("list command matched");
+            return Ok(Some(Command::List));
+        }
+
+        if m.subcommand_matches("add").is_some() {
+            debug!("add command matched");
+            return Ok(Some(Command::Add));
+        }
+
+        if let Some(m) = m.subcommand_matches("info") {
+            debug!("info command matched");
+            let name = m.value_of("name");
+            trace!("name: {:?}", name);
+            return Ok(Some(Command::Info(name)));
+        }
+
+        if m.subcommand_matches("set-password").is_some() {
+            debug!("set password command matched");
+            return Ok(Some(Command::SetPassword));
+        }
+
+        if let Some(m) = m.subcommand_matches("doctor") {
+            debug!("doctor command matched");
+            let name = m.value_of("name");
+            trace!("name: {:?}", name);
+            return Ok(Some(Command::Doctor(name)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Account subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("account")
+        .about("Manages accounts")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("list")
+                .aliases(&["lst", "l"])
+                .about("Lists the accounts of the config"),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Adds an account to the config interactively")
+                .long_about("Adds an account to the config interactively: the provider is detected from the email address, the servers of unknown providers are discovered with autoconfig, then the connections of the new account are checked like with `account doctor`."),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Prints the resolved connection settings, folder roles, capabilities and quota of an account")
+                .arg(
+                    Arg::with_name("name")
+                        .help("Specifies the account name, defaults to the selected account")
+                        .value_name("NAME"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set-password")
+                .about("Stores the password of an account in the system keyring")
+                .long_about("Stores the password of the selected account in the system keyring entry defined by its `passwd-keyring` config option. The password is asked without echo, or read from the first line of stdin when it is not a terminal."),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Diagnoses the connections of an account")
+                .long_about("Diagnoses the connections of an account step by step (name resolution, TCP connection, TLS handshake, password lookup and authentication), with a hint on how to fix the first failing step of each server.")
+                .arg(
+                    Arg::with_name("name")
+                        .help("Specifies the account name, defaults to the selected account")
+                        .value_name("NAME"),
+                ),
+        )]
+}
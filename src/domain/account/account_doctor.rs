@@ -0,0 +1,413 @@
+//! Module related to account diagnosis.
+//!
+//! The doctor checks the connections of an account step by step: name resolution, TCP
+//! connection, TLS handshake (after STARTTLS when configured), password lookup, then
+//! authentication. A failure is reported at the step it happens, with a hint on the option to
+//! fix, and skips the next steps of the same server.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    fmt::{self, Display},
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    config::{tls, Account, BackendKind},
+    domain::backend::{Backend, Sender},
+};
+
+const DOCTOR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Represents the outcome of one diagnosis step.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+    /// What to do when the check failed.
+    pub hint: Option<String>,
+}
+
+/// Represents the diagnosis of an account.
+#[derive(Debug, Default, Serialize)]
+pub struct Diagnosis {
+    pub account: String,
+    pub checks: Vec<Check>,
+}
+
+impl Diagnosis {
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| !check.ok).count()
+    }
+
+    /// Record the outcome of a step, telling if the next steps can run.
+    fn record<T>(&mut self, name: &str, res: Result<T>, ok: &str, hint: String) -> Option<T> {
+        match res {
+            Ok(val) => {
+                self.checks.push(Check {
+                    name: name.to_owned(),
+                    ok: true,
+                    detail: ok.to_owned(),
+                    hint: None,
+                });
+                Some(val)
+            }
+            Err(err) => {
+                self.checks.push(Check {
+                    name: name.to_owned(),
+                    ok: false,
+                    detail: format!("{:#}", err),
+                    hint: Some(hint),
+                });
+                None
+            }
+        }
+    }
+}
+
+impl Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Account: {}", self.account)?;
+        for check in &self.checks {
+            let mark = if check.ok { "✓" } else { "✗" };
+            writeln!(f, "{} {}: {}", mark, check.name, check.detail)?;
+            if let Some(ref hint) = check.hint {
+                writeln!(f, "  → {}", hint)?;
+            }
+        }
+        match self.failures() {
+            0 => write!(f, "No problem found"),
+            n => write!(f, "{} problem(s) found", n),
+        }
+    }
+}
+
+/// Represents the protocol spoken before STARTTLS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Protocol {
+    Imap,
+    Smtp,
+}
+
+/// Represents the connection settings of a server.
+struct Server<'a> {
+    host: &'a str,
+    port: u16,
+    starttls: bool,
+    insecure: bool,
+    fingerprint: Option<&'a str>,
+}
+
+impl Protocol {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Imap => "imap",
+            Self::Smtp => "smtp",
+        }
+    }
+
+    /// Get the connection settings of the server of the account speaking this protocol.
+    fn server<'a>(&self, account: &'a Account) -> Server<'a> {
+        match self {
+            Self::Imap => Server {
+                host: &account.imap_host,
+                port: account.imap_port,
+                starttls: account.imap_starttls,
+                insecure: account.imap_insecure,
+                fingerprint: account.imap_cert_fingerprint.as_deref(),
+            },
+            Self::Smtp => Server {
+                host: &account.smtp_host,
+                port: account.smtp_port,
+                starttls: account.smtp_starttls,
+                insecure: account.smtp_insecure,
+                fingerprint: account.smtp_cert_fingerprint.as_deref(),
+            },
+        }
+    }
+
+    fn usual_ports(&self) -> &'static str {
+        match self {
+            Self::Imap => "993 with TLS, 143 with STARTTLS",
+            Self::Smtp => "465 with TLS, 587 with STARTTLS",
+        }
+    }
+
+    /// Read a reply of the server, the last line being returned.
+    fn read_reply(&self, reader: &mut impl BufRead, tag: &str) -> Result<String> {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(anyhow!("connection closed by the server"));
+            }
+            let done = match self {
+                Self::Imap => tag.is_empty() || line.starts_with(tag),
+                // Multiline SMTP replies continue with a dash after the code.
+                Self::Smtp => line.as_bytes().get(3) != Some(&b'-'),
+            };
+            if done {
+                return Ok(line.trim_end().to_owned());
+            }
+        }
+    }
+
+    /// Upgrade the given plain connection with STARTTLS.
+    fn starttls(&self, stream: &TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut writer = stream;
+        let reply = match self {
+            Self::Imap => {
+                self.read_reply(&mut reader, "")?;
+                writer.write_all(b"A1 STARTTLS\r\n")?;
+                self.read_reply(&mut reader, "A1 ")?
+            }
+            Self::Smtp => {
+                self.read_reply(&mut reader, "")?;
+                writer.write_all(b"EHLO himalaya\r\n")?;
+                self.read_reply(&mut reader, "")?;
+                writer.write_all(b"STARTTLS\r\n")?;
+                self.read_reply(&mut reader, "")?
+            }
+        };
+        let accepted = match self {
+            Self::Imap => reply.starts_with("A1 OK"),
+            Self::Smtp => reply.starts_with("220"),
+        };
+        if !accepted {
+            return Err(anyhow!("STARTTLS refused: {}", reply));
+        }
+        Ok(())
+    }
+}
+
+/// Resolve the given host, then connect to it.
+fn connect(
+    diagnosis: &mut Diagnosis,
+    protocol: Protocol,
+    host: &str,
+    port: u16,
+) -> Option<TcpStream> {
+    let name = protocol.key().to_uppercase();
+    let key = protocol.key();
+
+    let addr: SocketAddr = diagnosis.record(
+        &format!("{} name resolution", name),
+        (host, port)
+            .to_socket_addrs()
+            .map_err(Into::into)
+            .and_then(|mut addrs| {
+                addrs
+                    .next()
+                    .ok_or_else(|| anyhow!("no address found for {}", host))
+            }),
+        &format!("{} resolved", host),
+        format!(r#"Check the "{}-host" option and your network"#, key),
+    )?;
+
+    diagnosis.record(
+        &format!("{} connection", name),
+        TcpStream::connect_timeout(&addr, DOCTOR_TIMEOUT)
+            .and_then(|stream| {
+                stream.set_read_timeout(Some(DOCTOR_TIMEOUT))?;
+                stream.set_write_timeout(Some(DOCTOR_TIMEOUT))?;
+                Ok(stream)
+            })
+            .map_err(Into::into),
+        &format!("{} reachable", addr),
+        format!(
+            r#"Check the "{}-port" option (usually {}) and that no firewall blocks it"#,
+            key,
+            protocol.usual_ports()
+        ),
+    )
+}
+
+/// Check the connection to a server, until the TLS handshake.
+fn check_server(diagnosis: &mut Diagnosis, account: &Account, protocol: Protocol) -> Option<()> {
+    let Server {
+        host,
+        port,
+        starttls,
+        insecure,
+        fingerprint,
+    } = protocol.server(account);
+    let name = protocol.key().to_uppercase();
+    let key = protocol.key();
+
+    let stream = match account.proxy {
+        // Names are resolved by the proxy.
+        Some(ref proxy) => diagnosis.record(
+            &format!("{} connection", name),
+            proxy.connect(host, port).and_then(|stream| {
+                stream.set_read_timeout(Some(DOCTOR_TIMEOUT))?;
+                stream.set_write_timeout(Some(DOCTOR_TIMEOUT))?;
+                Ok(stream)
+            }),
+            &format!("{}:{} reachable through proxy", host, port),
+            format!(
+                r#"Check the "proxy" option, and the "{}-host" and "{}-port" ones"#,
+                key, key
+            ),
+        )?,
+        None => connect(diagnosis, protocol, host, port)?,
+    };
+
+    if starttls {
+        diagnosis.record(
+            &format!("{} STARTTLS", name),
+            protocol.starttls(&stream),
+            "accepted",
+            format!(
+                r#"The server may expect TLS right away: check "{}-port" and "{}-starttls""#,
+                key, key
+            ),
+        )?;
+    }
+
+    let handshake =
+        tls::connector(account, insecure, fingerprint.is_some()).and_then(|connector| {
+            let stream = connector
+                .connect(host, stream)
+                .map_err(|err| anyhow!("{}", err))?;
+            if let Some(fingerprint) = fingerprint {
+                tls::check_fingerprint(&stream, fingerprint)?;
+            }
+            Ok(stream)
+        });
+    let hint = match handshake {
+        Err(ref err) if err.to_string().contains("fingerprint") => format!(
+            r#"The certificate of the server changed: check "{}-cert-fingerprint""#,
+            key
+        ),
+        Err(ref err) if err.to_string().to_lowercase().contains("certificate") => format!(
+            r#"The certificate of the server is not trusted: check "{}-host", set "tls-ca-cert" for a private CA, or "{}-cert-fingerprint" to pin a self-signed certificate"#,
+            key, key
+        ),
+        _ if starttls => format!(
+            r#"Check the "{}-port" option (usually {})"#,
+            key,
+            protocol.usual_ports()
+        ),
+        _ => format!(
+            r#"The server may expect STARTTLS on this port: check "{}-port" and "{}-starttls""#,
+            key, key
+        ),
+    };
+    diagnosis.record(
+        &format!("{} TLS handshake", name),
+        handshake.map(|_| ()),
+        "certificate trusted",
+        hint,
+    )
+}
+
+/// Diagnose the connections of the given account.
+pub fn diagnose(
+    account: &Account,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Diagnosis {
+    let mut diagnosis = Diagnosis {
+        account: account.name.to_owned(),
+        ..Diagnosis::default()
+    };
+    let app_passwd_hint =
+        "Providers like Gmail, Yahoo, iCloud and Fastmail require an app password";
+
+    match account.backend {
+        BackendKind::Imap => {
+            let imap = check_server(&mut diagnosis, account, Protocol::Imap)
+            .and_then(|_| {
+                diagnosis.record(
+                    "IMAP password",
+                    account.imap_passwd(),
+                    "found",
+                    String::from(r#"Check "imap-passwd-cmd", "passwd-cmd", "passwd-secret" or "passwd-keyring""#),
+                )
+            });
+            if imap.is_some() {
+                diagnosis.record(
+                    "IMAP authentication",
+                    backend.get_caps(),
+                    &format!("logged in as {}", account.imap_login),
+                    format!(
+                        r#"Check "imap-login" and the password. {}"#,
+                        app_passwd_hint
+                    ),
+                );
+            }
+        }
+        BackendKind::Graph => {
+            diagnosis.record(
+                "Graph authentication",
+                backend.list_mboxes(),
+                &format!("signed in as {}", account.email),
+                String::from(r#"Check "graph-client-id" and "graph-tenant", then sign in again"#),
+            );
+        }
+    }
+
+    match account.sendmail_cmd {
+        Some(ref cmd) => diagnosis.checks.push(Check {
+            name: String::from("Sendmail"),
+            ok: true,
+            detail: format!("messages are piped to `{}`, not checked", cmd),
+            hint: None,
+        }),
+        None if account.backend == BackendKind::Imap => {
+            let smtp = check_server(&mut diagnosis, account, Protocol::Smtp)
+            .and_then(|_| {
+                diagnosis.record(
+                    "SMTP password",
+                    account.smtp_creds(),
+                    "found",
+                    String::from(r#"Check "smtp-passwd-cmd", "passwd-cmd", "passwd-secret" or "passwd-keyring""#),
+                )
+            });
+            if smtp.is_some() {
+                diagnosis.record(
+                    "SMTP authentication",
+                    sender.open(),
+                    &format!("logged in as {}", account.smtp_login),
+                    format!(
+                        r#"Check "smtp-login" and the password. {}"#,
+                        app_passwd_hint
+                    ),
+                );
+            }
+        }
+        // The Graph backend sends with the session checked above.
+        None => (),
+    }
+
+    diagnosis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn it_should_read_replies() {
+        let mut reader = Cursor::new("250-localhost\r\n250-STARTTLS\r\n250 SIZE 1000\r\n");
+        assert_eq!(
+            "250 SIZE 1000",
+            Protocol::Smtp.read_reply(&mut reader, "").unwrap()
+        );
+
+        let mut reader = Cursor::new("* OK ready\r\nA1 OK Begin TLS\r\n");
+        assert_eq!(
+            "* OK ready",
+            Protocol::Imap.read_reply(&mut reader, "").unwrap()
+        );
+        assert_eq!(
+            "A1 OK Begin TLS",
+            Protocol::Imap.read_reply(&mut reader, "A1 ").unwrap()
+        );
+        assert!(Protocol::Imap.read_reply(&mut reader, "A1 ").is_err());
+    }
+}
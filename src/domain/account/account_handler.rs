@@ -0,0 +1,101 @@
+//! Module related to account handling.
+//!
+//! This module gathers all account actions triggered by the CLI.
+
+use anyhow::{anyhow, Result};
+use log::{trace, warn};
+use std::{convert::TryFrom, path::Path};
+
+use crate::{
+    config::{config_wizard, passwd, Account, Config},
+    domain::{
+        account::{account_doctor, AccountInfo, AccountList, FolderRole},
+        backend::{build_backend, build_sender, Backend, Sender},
+        mbox::Mbox,
+    },
+    output::{OutputService, OutputServiceInterface},
+    ui::prompt,
+};
+
+/// Print what himalaya knows about the account: resolved settings, then what the server
+/// advertises. Server errors are logged and leave the related fields empty, so that the command
+/// remains usable to debug a broken account.
+pub fn info(account: &Account, output: &OutputService, backend: &mut dyn Backend) -> Result<()> {
+    let mut info = AccountInfo::from(account);
+
+    match backend.list_mboxes() {
+        Ok(mboxes) => info.folder_roles = FolderRole::detect(&mboxes),
+        Err(err) => warn!("{:?}", err),
+    }
+    match backend.get_caps() {
+        Ok(caps) => info.caps = caps,
+        Err(err) => warn!("{:?}", err),
+    }
+    match backend.get_quotas() {
+        Ok(quotas) => info.quotas = quotas,
+        Err(err) => warn!("{:?}", err),
+    }
+    trace!("account info: {:#?}", info);
+
+    output.print(info)?;
+    backend.logout()
+}
+
+/// Ask for the password of the account, then store it in its keyring entry.
+pub fn set_password(account: &Account, output: &OutputService) -> Result<()> {
+    let entry = account.passwd_keyring.as_deref().ok_or_else(|| {
+        anyhow!(
+            r#"cannot find "passwd-keyring" in account "{}""#,
+            account.name
+        )
+    })?;
+    let passwd = prompt::passwd(&format!("Password of account \"{}\"", account.name))?;
+    if passwd.is_empty() {
+        return Err(anyhow!("cannot store password: it is empty"));
+    }
+    passwd::keyring_set(entry, &passwd)?;
+    output.print(format!(
+        r#"Password successfully stored in keyring entry "{}""#,
+        entry
+    ))
+}
+
+/// List the accounts of the config.
+pub fn list(config: &Config, output: &OutputService) -> Result<()> {
+    output.print(AccountList::from(config))
+}
+
+/// Diagnose the connections of the account, failing when a problem is found.
+pub fn doctor(
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let diagnosis = account_doctor::diagnose(account, backend, sender);
+    trace!("diagnosis: {:#?}", diagnosis);
+    let failures = diagnosis.failures();
+    output.print(diagnosis)?;
+    if failures > 0 {
+        return Err(anyhow!(
+            r#"cannot use account "{}": {} problem(s) found"#,
+            account.name,
+            failures
+        ));
+    }
+    backend.logout()?;
+    sender.close()
+}
+
+/// Add an account to the config of the given path with the wizard, then diagnose it.
+pub fn add(path: &Path, config: &Config, output: &OutputService) -> Result<()> {
+    let name = config_wizard::add_account(path, config)?;
+    println!(r#"Account "{}" added, checking its connections…"#, name);
+
+    let config = Config::try_from(path.to_str())?;
+    let account = Account::try_from((&config, Some(name.as_str())))?;
+    let mbox = Mbox::from("INBOX");
+    let mut backend = build_backend(&account, &mbox, false);
+    let mut sender = build_sender(&account);
+    doctor(&account, output, backend.as_mut(), sender.as_mut())
+}
@@ -0,0 +1,296 @@
+//! Module related to account information.
+//!
+//! This module gathers what himalaya believes about an account, from its resolved configuration
+//! and from what the server advertises.
+
+use imap::types::NameAttribute;
+use serde::Serialize;
+use std::fmt;
+
+use crate::{
+    config::{Account, BackendKind},
+    domain::mbox::Mboxes,
+};
+
+/// Represent the resolved settings of a server connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerInfo {
+    pub host: String,
+    pub port: u16,
+    /// Either `starttls` or `tls`.
+    pub tls: String,
+    /// Whether invalid certificates and hostnames are accepted.
+    pub insecure: bool,
+    pub auth: String,
+    pub login: String,
+}
+
+impl fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} ({}{}), {} as {}",
+            self.host,
+            self.port,
+            self.tls,
+            if self.insecure { ", insecure" } else { "" },
+            self.auth,
+            self.login
+        )
+    }
+}
+
+/// Represent a folder role, like the sent or the trash folder.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FolderRole {
+    pub role: String,
+    pub mbox: String,
+    /// Either `special-use` when advertised by the server, or `name` when guessed from the
+    /// mailbox name.
+    pub detected_by: String,
+}
+
+/// Folder roles of the [special-use] extension, with the usual names of their mailboxes.
+///
+/// [special-use]: https://datatracker.ietf.org/doc/html/rfc6154
+const FOLDER_ROLES: &[(&str, &[&str])] = &[
+    ("all", &["all mail", "all"]),
+    ("archive", &["archive", "archives"]),
+    ("drafts", &["drafts", "draft"]),
+    ("flagged", &["flagged", "starred"]),
+    ("junk", &["junk", "spam", "junk email", "junk e-mail"]),
+    (
+        "sent",
+        &["sent", "sent items", "sent messages", "sent mail"],
+    ),
+    (
+        "trash",
+        &["trash", "deleted items", "deleted messages", "bin"],
+    ),
+];
+
+impl FolderRole {
+    /// Detect the folder roles of the given mailboxes. Special-use attributes win over names.
+    pub fn detect(mboxes: &Mboxes) -> Vec<Self> {
+        FOLDER_ROLES
+            .iter()
+            .filter_map(|(role, names)| {
+                let attr = format!("\\{}", role);
+                let by_attr = mboxes.0.iter().find(|mbox| {
+                    mbox.attributes.0.iter().any(|attribute| match attribute {
+                        NameAttribute::Custom(custom) => custom.eq_ignore_ascii_case(&attr),
+                        _ => false,
+                    })
+                });
+                if let Some(mbox) = by_attr {
+                    return Some(Self {
+                        role: role.to_string(),
+                        mbox: mbox.name.to_owned(),
+                        detected_by: String::from("special-use"),
+                    });
+                }
+
+                mboxes
+                    .0
+                    .iter()
+                    .find(|mbox| {
+                        let leaf = if mbox.delim.is_empty() {
+                            mbox.name.as_str()
+                        } else {
+                            mbox.name
+                                .rsplit(mbox.delim.as_str())
+                                .next()
+                                .unwrap_or_default()
+                        };
+                        names.iter().any(|name| leaf.eq_ignore_ascii_case(name))
+                    })
+                    .map(|mbox| Self {
+                        role: role.to_string(),
+                        mbox: mbox.name.to_owned(),
+                        detected_by: String::from("name"),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Represent a quota resource of a quota root, as defined by the [quota] extension. Storage
+/// resources are expressed in kibibytes.
+///
+/// [quota]: https://datatracker.ietf.org/doc/html/rfc2087
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Quota {
+    pub root: String,
+    pub resource: String,
+    pub usage: u64,
+    pub limit: u64,
+}
+
+impl Quota {
+    /// Get the used part of the limit, in percent. An unlimited resource is never used.
+    pub fn percent(&self) -> u64 {
+        if self.limit > 0 {
+            self.usage * 100 / self.limit
+        } else {
+            0
+        }
+    }
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}/{} ({}%)",
+            self.resource,
+            self.usage,
+            self.limit,
+            self.percent()
+        )?;
+        if !self.root.is_empty() {
+            write!(f, r#" in root "{}""#, self.root)?;
+        }
+        Ok(())
+    }
+}
+
+/// Represent what himalaya believes about an account.
+#[derive(Debug, Serialize)]
+pub struct AccountInfo {
+    pub name: String,
+    pub email: String,
+    pub backend: String,
+    pub incoming: ServerInfo,
+    pub outgoing: Option<ServerInfo>,
+    pub sendmail_cmd: Option<String>,
+    pub archive_folder: String,
+    pub snooze_folder: String,
+    pub folder_roles: Vec<FolderRole>,
+    pub caps: Vec<String>,
+    pub quotas: Vec<Quota>,
+}
+
+impl From<&Account> for AccountInfo {
+    fn from(account: &Account) -> Self {
+        let tls = |starttls: bool| String::from(if starttls { "starttls" } else { "tls" });
+        let (backend, incoming, outgoing) = match account.backend {
+            BackendKind::Imap => (
+                String::from("imap"),
+                ServerInfo {
+                    host: account.imap_host.to_owned(),
+                    port: account.imap_port,
+                    tls: tls(account.imap_starttls),
+                    insecure: account.imap_insecure,
+                    auth: String::from("LOGIN"),
+                    login: account.imap_login.to_owned(),
+                },
+                Some(ServerInfo {
+                    host: account.smtp_host.to_owned(),
+                    port: account.smtp_port,
+                    tls: tls(account.smtp_starttls),
+                    insecure: account.smtp_insecure,
+                    auth: String::from("PLAIN or LOGIN"),
+                    login: account.smtp_login.to_owned(),
+                }),
+            ),
+            BackendKind::Graph => {
+                let server = ServerInfo {
+                    host: String::from("graph.microsoft.com"),
+                    port: 443,
+                    tls: tls(false),
+                    insecure: false,
+                    auth: format!("OAuth2 device code (tenant {})", account.graph_tenant),
+                    login: account.email.to_owned(),
+                };
+                (String::from("graph"), server.clone(), Some(server))
+            }
+        };
+
+        Self {
+            name: account.name.to_owned(),
+            email: account.email.to_owned(),
+            backend,
+            incoming,
+            // The sendmail command replaces the SMTP or Graph sender.
+            outgoing: outgoing.filter(|_| account.sendmail_cmd.is_none()),
+            sendmail_cmd: account.sendmail_cmd.to_owned(),
+            archive_folder: account.archive_folder.to_owned(),
+            snooze_folder: account.snooze_folder.to_owned(),
+            folder_roles: vec![],
+            caps: vec![],
+            quotas: vec![],
+        }
+    }
+}
+
+impl fmt::Display for AccountInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Account: {} <{}>", self.name, self.email)?;
+        writeln!(f, "Backend: {}", self.backend)?;
+        writeln!(f, "Incoming: {}", self.incoming)?;
+        if let Some(ref outgoing) = self.outgoing {
+            writeln!(f, "Outgoing: {}", outgoing)?;
+        }
+        if let Some(ref cmd) = self.sendmail_cmd {
+            writeln!(f, "Outgoing: sendmail cmd `{}`", cmd)?;
+        }
+        writeln!(f, "Archive folder: {}", self.archive_folder)?;
+        writeln!(f, "Snooze folder: {}", self.snooze_folder)?;
+
+        writeln!(f, "Folder roles:")?;
+        for role in &self.folder_roles {
+            writeln!(
+                f,
+                "  {}: {} (by {})",
+                role.role, role.mbox, role.detected_by
+            )?;
+        }
+
+        writeln!(f, "Capabilities: {}", self.caps.join(" "))?;
+
+        if self.quotas.is_empty() {
+            writeln!(f, "Quota: unknown")?;
+        } else {
+            writeln!(f, "Quota:")?;
+            for quota in &self.quotas {
+                writeln!(f, "  {}", quota)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::mbox::{Attributes, Mbox};
+
+    #[test]
+    fn it_should_detect_folder_roles() {
+        let mbox = |name: &str, attrs: &[NameAttribute]| Mbox {
+            delim: String::from("/"),
+            name: name.to_owned(),
+            attributes: Attributes::from(attrs),
+            ..Mbox::default()
+        };
+        let mboxes = Mboxes(vec![
+            mbox("INBOX", &[]),
+            mbox("INBOX/Sent", &[]),
+            mbox("Bin", &[]),
+            mbox("Corbeille", &[NameAttribute::Custom("\\Trash".into())]),
+        ]);
+        let roles = FolderRole::detect(&mboxes);
+        assert_eq!(2, roles.len());
+        assert_eq!(
+            ("sent", "INBOX/Sent"),
+            (roles[0].role.as_str(), roles[0].mbox.as_str())
+        );
+        assert_eq!("name", roles[0].detected_by);
+        assert_eq!(
+            ("trash", "Corbeille"),
+            (roles[1].role.as_str(), roles[1].mbox.as_str())
+        );
+        assert_eq!("special-use", roles[1].detected_by);
+    }
+}
@@ -0,0 +1,66 @@
+//! Module related to account listing.
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+use crate::{
+    config::{BackendKind, Config},
+    ui::table::{Cell, Row, Table},
+};
+
+/// Represents an account of the config, as listed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountListItem {
+    pub name: String,
+    pub email: String,
+    pub backend: String,
+    pub default: bool,
+}
+
+/// Represents the accounts of the config, sorted by name.
+#[derive(Debug, Default, Serialize)]
+pub struct AccountList(pub Vec<AccountListItem>);
+
+impl From<&Config> for AccountList {
+    fn from(config: &Config) -> Self {
+        let mut accounts: Vec<AccountListItem> = config
+            .accounts
+            .iter()
+            .map(|(name, account)| AccountListItem {
+                name: name.to_owned(),
+                email: account.email.to_owned(),
+                backend: String::from(match account.backend.unwrap_or_default() {
+                    BackendKind::Imap => "imap",
+                    BackendKind::Graph => "graph",
+                }),
+                default: account.default.unwrap_or_default(),
+            })
+            .collect();
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        Self(accounts)
+    }
+}
+
+impl Display for AccountList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", Table::render(&self.0))
+    }
+}
+
+impl Table for AccountListItem {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("NAME").bold().underline().white())
+            .cell(Cell::new("EMAIL").shrinkable().bold().underline().white())
+            .cell(Cell::new("BACKEND").bold().underline().white())
+            .cell(Cell::new("DEFAULT").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.name).green())
+            .cell(Cell::new(&self.email).shrinkable().blue())
+            .cell(Cell::new(&self.backend).yellow())
+            .cell(Cell::new(if self.default { "yes" } else { "" }).white())
+    }
+}
@@ -0,0 +1,13 @@
+//! Module related to accounts.
+
+#[cfg(feature = "cli")]
+pub mod account_arg;
+pub mod account_doctor;
+#[cfg(feature = "cli")]
+pub mod account_handler;
+
+pub mod account_info_entity;
+pub use account_info_entity::*;
+
+pub mod account_list_entity;
+pub use account_list_entity::*;
@@ -0,0 +1,57 @@
+//! Module related to discovered server settings.
+//!
+//! The settings of a domain are first looked up in its [autoconfig
+//! documents](super::autoconfig_ispdb), then in its [SRV records](super::autoconfig_srv). Since
+//! the lookup goes through the network, accounts keep the discovered settings in their cache
+//! directory.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::domain::autoconfig::{autoconfig_ispdb, autoconfig_srv};
+
+/// Represents the settings of a server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    pub starttls: bool,
+}
+
+/// Represents the discovered settings of a domain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Autoconfig {
+    pub imap: ServerSettings,
+    pub smtp: ServerSettings,
+}
+
+/// Discover the settings of the given email address.
+pub fn discover(email: &str) -> Option<Autoconfig> {
+    autoconfig_ispdb::discover(email).or_else(|| {
+        let domain = email.rsplit_once('@')?.1;
+        autoconfig_srv::discover(domain)
+    })
+}
+
+/// Discover the settings of the given email address, or read them from the given cache file.
+pub fn discover_cached(email: &str, path: &Path) -> Option<Autoconfig> {
+    if let Ok(cached) = fs::read_to_string(path) {
+        match toml::from_str(&cached) {
+            Ok(config) => return Some(config),
+            Err(err) => debug!("cannot parse cached autoconfig {:?}: {}", path, err),
+        }
+    }
+
+    let config = discover(email)?;
+    let res = toml::to_string(&config)
+        .context("cannot serialize autoconfig")
+        .and_then(|toml| {
+            fs::write(path, toml).context(format!("cannot write autoconfig cache {:?}", path))
+        });
+    if let Err(err) = res {
+        warn!("{:#}", err);
+    }
+    Some(config)
+}
@@ -0,0 +1,130 @@
+//! Module related to server settings discovery from autoconfig documents.
+//!
+//! The IMAP and SMTP settings of a domain are looked up in the [autoconfig] XML files served by
+//! the domain itself, then in the Thunderbird ISP database. XML documents are only scanned for
+//! the few elements needed, no XML parser is involved.
+//!
+//! [autoconfig]: https://wiki.mozilla.org/Thunderbird:Autoconfiguration:ConfigFileFormat
+
+use log::debug;
+use regex::Regex;
+use std::time::Duration;
+
+use crate::domain::autoconfig::{Autoconfig, ServerSettings};
+
+const AUTOCONFIG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Find the settings of the first server of the given type in the given autoconfig document.
+fn parse_server(xml: &str, tag: &str, kind: &str, email: &str) -> Option<ServerSettings> {
+    let server = Regex::new(&format!(
+        r#"(?s)<{tag}\s+type="{kind}"\s*>(.*?)</{tag}>"#,
+        tag = tag,
+        kind = kind
+    ))
+    .ok()?;
+    let body = server.captures(xml)?.get(1)?.as_str();
+    let field = |name: &str| {
+        Regex::new(&format!(r"(?s)<{name}>\s*(.*?)\s*</{name}>", name = name))
+            .ok()?
+            .captures(body)
+            .map(|captures| captures[1].to_owned())
+    };
+
+    let (local_part, domain) = email.rsplit_once('@')?;
+    let host = field("hostname")?
+        .replace("%EMAILDOMAIN%", domain)
+        .replace("%EMAILLOCALPART%", local_part);
+    Some(ServerSettings {
+        host,
+        port: field("port")?.parse().ok()?,
+        starttls: field("socketType")?.eq_ignore_ascii_case("STARTTLS"),
+    })
+}
+
+/// Parse the given autoconfig document.
+pub fn parse(xml: &str, email: &str) -> Option<Autoconfig> {
+    Some(Autoconfig {
+        imap: parse_server(xml, "incomingServer", "imap", email)?,
+        smtp: parse_server(xml, "outgoingServer", "smtp", email)?,
+    })
+}
+
+/// Discover the settings of the given email address. Network errors and unusable documents are
+/// only logged, to fall back to the next source.
+pub fn discover(email: &str) -> Option<Autoconfig> {
+    let domain = email.rsplit_once('@')?.1;
+    let urls = [
+        format!(
+            "https://autoconfig.{}/mail/config-v1.1.xml?emailaddress={}",
+            domain, email
+        ),
+        format!(
+            "https://{}/.well-known/autoconfig/mail/config-v1.1.xml",
+            domain
+        ),
+        format!("https://autoconfig.thunderbird.net/v1.1/{}", domain),
+    ];
+
+    urls.iter().find_map(|url| {
+        debug!("fetch autoconfig from {}", url);
+        let xml = ureq::get(url)
+            .timeout(AUTOCONFIG_TIMEOUT)
+            .call()
+            .map_err(|err| err.to_string())
+            .and_then(|res| res.into_string().map_err(|err| err.to_string()));
+        match xml {
+            Ok(xml) => parse(&xml, email),
+            Err(err) => {
+                debug!("cannot fetch autoconfig from {}: {}", url, err);
+                None
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_autoconfig() {
+        let xml = r#"<?xml version="1.0"?>
+<clientConfig version="1.1">
+  <emailProvider id="example.com">
+    <incomingServer type="pop3">
+      <hostname>pop.example.com</hostname>
+      <port>995</port>
+      <socketType>SSL</socketType>
+    </incomingServer>
+    <incomingServer type="imap">
+      <hostname>imap.%EMAILDOMAIN%</hostname>
+      <port>993</port>
+      <socketType>SSL</socketType>
+    </incomingServer>
+    <outgoingServer type="smtp">
+      <hostname>smtp.example.com</hostname>
+      <port>587</port>
+      <socketType>STARTTLS</socketType>
+    </outgoingServer>
+  </emailProvider>
+</clientConfig>"#;
+        let config = parse(xml, "john@example.com").unwrap();
+        assert_eq!(
+            ServerSettings {
+                host: String::from("imap.example.com"),
+                port: 993,
+                starttls: false,
+            },
+            config.imap
+        );
+        assert_eq!(
+            ServerSettings {
+                host: String::from("smtp.example.com"),
+                port: 587,
+                starttls: true,
+            },
+            config.smtp
+        );
+        assert_eq!(None, parse("<clientConfig/>", "john@example.com"));
+    }
+}
@@ -0,0 +1,241 @@
+//! Module related to server settings discovery from DNS SRV records.
+//!
+//! Domains can announce their mail servers with [RFC 6186] SRV records: `_imaps._tcp` and
+//! `_submissions._tcp` for implicit TLS ([RFC 8314]), `_imap._tcp` and `_submission._tcp` for
+//! STARTTLS. Records are queried over UDP from the first name server of `/etc/resolv.conf`, with
+//! a minimal DNS client, so that no resolver library is linked.
+//!
+//! [RFC 6186]: https://datatracker.ietf.org/doc/html/rfc6186
+//! [RFC 8314]: https://datatracker.ietf.org/doc/html/rfc8314
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    process,
+    time::Duration,
+};
+
+use crate::domain::autoconfig::{Autoconfig, ServerSettings};
+
+const SRV_TIMEOUT: Duration = Duration::from_secs(3);
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// Names can be compressed with pointers, bounded to prevent loops.
+const MAX_NAME_POINTERS: usize = 16;
+
+/// Represents a SRV record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// Find the first name server of the system resolver.
+fn nameserver() -> Result<SocketAddr> {
+    let conf = fs::read_to_string("/etc/resolv.conf").context("cannot read /etc/resolv.conf")?;
+    conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .find_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .ok_or_else(|| anyhow!("cannot find name server in /etc/resolv.conf"))
+}
+
+/// Build a recursive query of the SRV records of the given name.
+fn build_query(id: u16, name: &str) -> Vec<u8> {
+    let mut query = Vec::with_capacity(name.len() + 18);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question.
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    query
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Result<u16> {
+    msg.get(pos..pos + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| anyhow!("cannot parse DNS response: truncated"))
+}
+
+/// Read the name at the given position, returning it with the position following it.
+fn read_name(msg: &[u8], mut pos: usize) -> Result<(String, usize)> {
+    let truncated = || anyhow!("cannot parse DNS response: truncated name");
+    let mut labels = vec![];
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *msg.get(pos).ok_or_else(truncated)? as usize;
+        if len & 0xc0 == 0xc0 {
+            pointers += 1;
+            if pointers > MAX_NAME_POINTERS {
+                return Err(anyhow!("cannot parse DNS response: too many name pointers"));
+            }
+            end.get_or_insert(pos + 2);
+            pos = (len & 0x3f) << 8 | *msg.get(pos + 1).ok_or_else(truncated)? as usize;
+        } else if len == 0 {
+            let end = end.unwrap_or(pos + 1);
+            return Ok((labels.join("."), end));
+        } else {
+            let label = msg.get(pos + 1..pos + 1 + len).ok_or_else(truncated)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+    }
+}
+
+/// Parse the SRV records of the given response, sorted by priority then weight.
+fn parse_response(id: u16, msg: &[u8]) -> Result<Vec<SrvRecord>> {
+    if read_u16(msg, 0)? != id {
+        return Err(anyhow!("cannot parse DNS response: unexpected id"));
+    }
+    // Name errors (3) only mean that the domain has no such record.
+    match read_u16(msg, 2)? & 0x000f {
+        0 | 3 => (),
+        rcode => {
+            return Err(anyhow!(
+                "cannot query DNS: server replied with code {}",
+                rcode
+            ))
+        }
+    }
+    let questions = read_u16(msg, 4)?;
+    let answers = read_u16(msg, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+
+    let mut records = vec![];
+    for _ in 0..answers {
+        pos = read_name(msg, pos)?.1;
+        let kind = read_u16(msg, pos)?;
+        let len = read_u16(msg, pos + 8)? as usize;
+        let data = pos + 10;
+        if kind == TYPE_SRV {
+            records.push(SrvRecord {
+                priority: read_u16(msg, data)?,
+                weight: read_u16(msg, data + 2)?,
+                port: read_u16(msg, data + 4)?,
+                target: read_name(msg, data + 6)?.0,
+            });
+        }
+        pos = data + len;
+    }
+
+    records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+    Ok(records)
+}
+
+/// Query the SRV records of the given name.
+pub fn lookup(name: &str) -> Result<Vec<SrvRecord>> {
+    let server = nameserver()?;
+    let socket = match server {
+        SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0"),
+        SocketAddr::V6(_) => UdpSocket::bind("[::]:0"),
+    }
+    .context("cannot bind DNS socket")?;
+    socket.set_read_timeout(Some(SRV_TIMEOUT))?;
+
+    let id = process::id() as u16;
+    socket
+        .send_to(&build_query(id, name), server)
+        .context(format!("cannot send DNS query to {}", server))?;
+    let mut buf = [0; 4096];
+    let (len, _) = socket
+        .recv_from(&mut buf)
+        .context(format!("cannot receive DNS response from {}", server))?;
+    parse_response(id, &buf[..len])
+}
+
+/// Find the best server of the first service announced, telling for each service if it expects
+/// STARTTLS. A `.` target means the service is not provided.
+fn find_server(domain: &str, services: &[(&str, bool)]) -> Option<ServerSettings> {
+    services.iter().find_map(|(service, starttls)| {
+        let name = format!("_{}._tcp.{}", service, domain);
+        debug!("look up SRV records of {}", name);
+        match lookup(&name) {
+            Ok(records) => records
+                .into_iter()
+                .next()
+                .filter(|record| !record.target.is_empty())
+                .map(|record| ServerSettings {
+                    host: record.target,
+                    port: record.port,
+                    starttls: *starttls,
+                }),
+            Err(err) => {
+                debug!("cannot look up SRV records of {}: {:#}", name, err);
+                None
+            }
+        }
+    })
+}
+
+/// Discover the settings of the given domain, implicit TLS being preferred.
+pub fn discover(domain: &str) -> Option<Autoconfig> {
+    Some(Autoconfig {
+        imap: find_server(domain, &[("imaps", false), ("imap", true)])?,
+        smtp: find_server(domain, &[("submissions", false), ("submission", true)])?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_srv_responses() {
+        let mut msg = build_query(42, "_imaps._tcp.example.com");
+        // Turn the query into a response with two answers.
+        msg[2] = 0x81;
+        msg[3] = 0x80;
+        msg[7] = 2;
+        for (priority, weight, port, target) in [
+            (10u16, 0u16, 993u16, &b"\x04imap"[..]),
+            (5, 0, 143, b"\x04mail"),
+        ] {
+            // The name points to the question, the target ends with a pointer to `example.com`.
+            msg.extend_from_slice(&[0xc0, 12]);
+            msg.extend_from_slice(&TYPE_SRV.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&[0, 0, 0x0e, 0x10]);
+            msg.extend_from_slice(&(6 + target.len() as u16 + 2).to_be_bytes());
+            msg.extend_from_slice(&priority.to_be_bytes());
+            msg.extend_from_slice(&weight.to_be_bytes());
+            msg.extend_from_slice(&port.to_be_bytes());
+            msg.extend_from_slice(target);
+            msg.extend_from_slice(&[0xc0, 24]);
+        }
+
+        let records = parse_response(42, &msg).unwrap();
+        assert_eq!(
+            vec![
+                SrvRecord {
+                    priority: 5,
+                    weight: 0,
+                    port: 143,
+                    target: String::from("mail.example.com"),
+                },
+                SrvRecord {
+                    priority: 10,
+                    weight: 0,
+                    port: 993,
+                    target: String::from("imap.example.com"),
+                },
+            ],
+            records
+        );
+        assert!(parse_response(43, &msg).is_err());
+        assert!(parse_response(42, &msg[..msg.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,7 @@
+//! Module related to server settings autodiscovery.
+
+pub mod autoconfig_ispdb;
+pub mod autoconfig_srv;
+
+pub mod autoconfig_entity;
+pub use autoconfig_entity::*;
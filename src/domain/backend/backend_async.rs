@@ -0,0 +1,94 @@
+//! Module related to the async entry points.
+//!
+//! With the `async` feature, embedders running a [tokio] runtime can list and send without
+//! blocking it. The IMAP and SMTP clients stay blocking: each call opens its own session on the
+//! blocking thread pool of the runtime, like the workers of the
+//! [`FetchPool`](crate::domain::backend::FetchPool) do on threads, so that calls on several
+//! accounts or mailboxes run concurrently.
+//!
+//! [tokio]: https://tokio.rs
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use tokio::task;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{build_backend, build_sender, Backend},
+        mbox::{Mbox, Mboxes},
+        msg::{Envelopes, Msg},
+    },
+};
+
+/// Run the given closure with a backend of the given account and mailbox, on the blocking thread
+/// pool. The session is logged out once the closure returns.
+async fn with_backend<T, F>(account: Account, mbox: String, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn Backend) -> Result<T> + Send + 'static,
+{
+    task::spawn_blocking(move || {
+        let mbox = Mbox::from(mbox.as_str());
+        let mut backend = build_backend(&account, &mbox, false);
+        let res = f(backend.as_mut());
+        if let Err(err) = backend.logout() {
+            warn!("cannot logout from {:?}: {}", mbox.name, err);
+        }
+        res
+    })
+    .await
+    .context("cannot join backend task")?
+}
+
+/// List the envelopes of the given page of the given mailbox.
+pub async fn list_envelopes(
+    account: Account,
+    mbox: String,
+    page_size: usize,
+    page: usize,
+) -> Result<Envelopes> {
+    with_backend(account, mbox, move |backend| {
+        backend.list_envelopes(&page_size, &page)
+    })
+    .await
+}
+
+/// List the envelopes of the given page of the given mailbox, for all the given accounts at
+/// once. Results are in the order of the accounts.
+pub async fn list_envelopes_of_accounts(
+    accounts: Vec<Account>,
+    mbox: String,
+    page_size: usize,
+    page: usize,
+) -> Vec<Result<Envelopes>> {
+    debug!("list envelopes of {} account(s)", accounts.len());
+    let tasks: Vec<_> = accounts
+        .into_iter()
+        .map(|account| task::spawn(list_envelopes(account, mbox.clone(), page_size, page)))
+        .collect();
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(
+            task.await
+                .context("cannot join listing task")
+                .and_then(|res| res),
+        );
+    }
+    results
+}
+
+/// List the mailboxes of the given account.
+pub async fn list_mboxes(account: Account) -> Result<Mboxes> {
+    with_backend(account, String::from("INBOX"), |backend| {
+        backend.list_mboxes()
+    })
+    .await
+}
+
+/// Send the given message from the given account, returning it as sent.
+pub async fn send(account: Account, msg: Msg) -> Result<Vec<u8>> {
+    task::spawn_blocking(move || build_sender(&account).send(&msg))
+        .await
+        .context("cannot join sending task")?
+}
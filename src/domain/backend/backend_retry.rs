@@ -0,0 +1,152 @@
+//! Module related to retries.
+//!
+//! Network blips should neither fail the commands that can safely run again, nor kill the
+//! long-lived sessions. Operations only reading from the server (fetching, listing, searching)
+//! are retried up to `max-retries` times when they fail on a transient error, the session being
+//! reopened in between, after a delay doubling at each attempt. The `notify` and `watch`
+//! sessions reconnect and resume the same way, their retries starting over once they ran for
+//! a while.
+//!
+//! Other operations, like appending, moving or flagging messages, are never retried: the server
+//! may have applied them before the connection dropped.
+
+use anyhow::{Error, Result};
+use log::warn;
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+use crate::output::output_interrupt;
+
+/// Define the delay before the first retry, doubled at each attempt.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// Define the longest delay before a retry. Sessions running longer than it before failing
+/// start their retries over.
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Check if the given error is transient, caused by the connection rather than by the command.
+pub fn is_transient(err: &Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<imap::Error>() {
+            return matches!(err, imap::Error::Io(_) | imap::Error::ConnectionLost);
+        }
+        if let Some(err) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        false
+    })
+}
+
+/// Get the delay before the given retry, counted from 0.
+pub fn backoff(attempt: u32) -> Duration {
+    BACKOFF_BASE
+        .checked_mul(1 << attempt.min(16))
+        .unwrap_or(BACKOFF_MAX)
+        .min(BACKOFF_MAX)
+}
+
+/// Run the given operation on the given state until it succeeds, fails on a permanent error or
+/// runs out of retries. The reset function runs before each retry, eg. to drop the broken
+/// session. When resumable, the retries start over once the operation ran for a while.
+fn run<S, T>(
+    state: &mut S,
+    desc: &str,
+    max_retries: u32,
+    reset: fn(&mut S),
+    resumable: bool,
+    op: &mut dyn FnMut(&mut S) -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let err = match op(state) {
+            Ok(res) => return Ok(res),
+            Err(err) => err,
+        };
+        if resumable && started.elapsed() > BACKOFF_MAX {
+            attempt = 0;
+        }
+        if attempt >= max_retries || !is_transient(&err) || output_interrupt::is_interrupted() {
+            return Err(err);
+        }
+
+        let delay = backoff(attempt);
+        warn!(
+            "cannot {}, retrying in {}s ({}/{}): {:#}",
+            desc,
+            delay.as_secs(),
+            attempt + 1,
+            max_retries,
+            err
+        );
+        thread::sleep(delay);
+        reset(state);
+        attempt += 1;
+    }
+}
+
+/// Run the given operation, retrying it when it fails on a transient error.
+pub fn retry<S, T>(
+    state: &mut S,
+    desc: &str,
+    max_retries: u32,
+    reset: fn(&mut S),
+    mut op: impl FnMut(&mut S) -> Result<T>,
+) -> Result<T> {
+    run(state, desc, max_retries, reset, false, &mut op)
+}
+
+/// Run the given long-lived operation, resuming it when it fails on a transient error.
+pub fn resume<S>(
+    state: &mut S,
+    desc: &str,
+    max_retries: u32,
+    reset: fn(&mut S),
+    mut op: impl FnMut(&mut S) -> Result<()>,
+) -> Result<()> {
+    run(state, desc, max_retries, reset, true, &mut op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn it_should_retry_transient_errors() {
+        assert_eq!(Duration::from_secs(1), backoff(0));
+        assert_eq!(Duration::from_secs(8), backoff(3));
+        assert_eq!(BACKOFF_MAX, backoff(10));
+        assert_eq!(BACKOFF_MAX, backoff(u32::MAX));
+
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer");
+        let err = Err::<(), _>(reset).context("cannot fetch messages");
+        assert!(is_transient(&err.unwrap_err()));
+        assert!(!is_transient(&anyhow!("cannot find message 42")));
+
+        // Permanent errors are not retried.
+        let mut calls = 0;
+        let res: Result<()> = retry(
+            &mut calls,
+            "fetch",
+            3,
+            |_| (),
+            |calls| {
+                *calls += 1;
+                Err(anyhow!("cannot find message 42"))
+            },
+        );
+        assert!(res.is_err());
+        assert_eq!(1, calls);
+    }
+}
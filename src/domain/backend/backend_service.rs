@@ -0,0 +1,296 @@
+//! Module related to backend servicing.
+//!
+//! This module exposes the backend and sender traits, as well as the factories that build them
+//! from the account configuration. Adding a new backend only requires implementing those traits
+//! and registering it in the factories.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+    config::{Account, BackendKind, Config},
+    domain::{
+        account::Quota,
+        graph::{GraphSendService, GraphService},
+        imap::{ImapService, WatchEvent},
+        mbox::{Acl, Mbox, MboxStatus, Mboxes},
+        msg::{find_part, Envelope, Envelopes, Flags, Msg, PartMetas, SortCriteria},
+        sendmail::SendmailService,
+        smtp::SmtpService,
+    },
+};
+
+/// Represents a backend, able to manage mailboxes and messages of an account.
+///
+/// Features that some backends cannot provide come with a default implementation returning an
+/// error.
+pub trait Backend {
+    fn notify(&mut self, _config: &Config, _keepalive: u64, _standby: bool) -> Result<()> {
+        Err(anyhow!("the notify mode is not supported by this backend"))
+    }
+    /// Watch the mailbox, passing each change the server reports to the given callback.
+    fn watch(
+        &mut self,
+        _keepalive: u64,
+        _on_event: &mut dyn FnMut(WatchEvent) -> Result<()>,
+    ) -> Result<()> {
+        Err(anyhow!("the watch mode is not supported by this backend"))
+    }
+    /// Send the given raw command as is, returning the raw response lines.
+    fn exec(&mut self, _cmd: &str) -> Result<Vec<u8>> {
+        Err(anyhow!("raw commands are not supported by this backend"))
+    }
+    /// Get the capabilities advertised by the server, if any.
+    fn get_caps(&mut self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+    /// Get the quotas of the account, if the server reports any.
+    fn get_quotas(&mut self) -> Result<Vec<Quota>> {
+        Ok(vec![])
+    }
+    /// Get the quotas of the roots the given mailbox belongs to.
+    fn get_mbox_quotas(&mut self, _mbox: &Mbox) -> Result<Vec<Quota>> {
+        Err(anyhow!("quotas are not supported by this backend"))
+    }
+    fn list_mboxes(&mut self) -> Result<Mboxes>;
+    /// Get the numbers of messages of the given mailbox, without selecting it.
+    fn get_mbox_status(&mut self, _mbox: &Mbox) -> Result<MboxStatus> {
+        Err(anyhow!("mailbox status is not supported by this backend"))
+    }
+    /// Get the UIDVALIDITY of the current mailbox, for backends whose message identifiers are
+    /// only valid together with it. Other backends have none.
+    fn get_uid_validity(&mut self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+    /// Get the access control list of the given mailbox.
+    fn get_acl(&mut self, _mbox: &Mbox) -> Result<Acl> {
+        Err(anyhow!(
+            "access control lists are not supported by this backend"
+        ))
+    }
+    /// Grant the given rights to the given identifier on the given mailbox. Rights prefixed with
+    /// `+` or `-` are added to or removed from the current ones.
+    fn set_acl(&mut self, _mbox: &Mbox, _identifier: &str, _rights: &str) -> Result<()> {
+        Err(anyhow!(
+            "access control lists are not supported by this backend"
+        ))
+    }
+    /// Create the given mailbox, unless it already exists.
+    fn create_mbox(&mut self, mbox: &Mbox) -> Result<()>;
+    fn list_envelopes(&mut self, page_size: &usize, page: &usize) -> Result<Envelopes>;
+    /// Pass the envelopes of the given page to the given callback as soon as they are fetched,
+    /// so that huge listings can be consumed incrementally. Backends fetching a page at once
+    /// pass them once the whole page is fetched.
+    fn stream_envelopes(
+        &mut self,
+        page_size: &usize,
+        page: &usize,
+        on_envelope: &mut dyn FnMut(&Envelope) -> Result<()>,
+    ) -> Result<()> {
+        for envelope in self.list_envelopes(page_size, page)?.iter() {
+            on_envelope(envelope)?;
+        }
+        Ok(())
+    }
+    fn list_sorted_envelopes(
+        &mut self,
+        _sort: &SortCriteria,
+        _page_size: &usize,
+        _page: &usize,
+    ) -> Result<Envelopes> {
+        Err(anyhow!("sorting is not supported by this backend"))
+    }
+    fn get_thread(&mut self, _seq: &str) -> Result<Envelopes> {
+        Err(anyhow!("threads are not supported by this backend"))
+    }
+    fn search_envelopes(
+        &mut self,
+        _query: &str,
+        _page_size: &usize,
+        _page: &usize,
+    ) -> Result<Envelopes> {
+        Err(anyhow!("searching is not supported by this backend"))
+    }
+    fn get_msg(&mut self, seq: &str) -> Result<Msg>;
+    fn get_raw_msg(&mut self, seq: &str) -> Result<Vec<u8>>;
+    /// Get all messages within the given sequence range.
+    fn get_msgs(&mut self, seq_range: &str) -> Result<Vec<Msg>>;
+    /// Get all raw messages within the given sequence range.
+    fn get_raw_msgs(&mut self, seq_range: &str) -> Result<Vec<Vec<u8>>>;
+    /// Get the metadata of the parts of the given message. The default implementation fetches
+    /// the whole message.
+    fn get_part_metas(&mut self, seq: &str) -> Result<PartMetas> {
+        let raw_msg = self.get_raw_msg(seq)?;
+        let parsed_mail = mailparse::parse_mail(&raw_msg)
+            .context(format!(r#"cannot parse message "{}""#, seq))?;
+        Ok(PartMetas::from_parsed_mail(
+            seq.parse().unwrap_or_default(),
+            &parsed_mail,
+        ))
+    }
+    /// Get the MIME type and the decoded content of a message part. The default implementation
+    /// fetches the whole message.
+    fn get_part(&mut self, seq: &str, section: &str) -> Result<(String, Vec<u8>)> {
+        let raw_msg = self.get_raw_msg(seq)?;
+        let parsed_mail = mailparse::parse_mail(&raw_msg)
+            .context(format!(r#"cannot parse message "{}""#, seq))?;
+        let mime = PartMetas::from_parsed_mail(0, &parsed_mail)
+            .0
+            .into_iter()
+            .find(|meta| meta.section == section)
+            .map(|meta| meta.mime)
+            .ok_or_else(|| anyhow!(r#"cannot find part "{}" of message "{}""#, section, seq))?;
+        Ok((mime, find_part(&parsed_mail, section)?))
+    }
+    /// Get the ids and internal dates of all messages within the given sequence range.
+    fn get_internal_dates(&mut self, seq_range: &str) -> Result<Vec<(u32, DateTime<FixedOffset>)>> {
+        Ok(self
+            .get_msgs(seq_range)?
+            .into_iter()
+            .filter_map(|msg| msg.date.map(|date| (msg.id, date)))
+            .collect())
+    }
+    /// Get the ids and flags of all messages within the given sequence range. The default
+    /// implementation fetches the whole messages.
+    fn get_flags(&mut self, seq_range: &str) -> Result<Vec<(u32, Flags)>> {
+        Ok(self
+            .get_msgs(seq_range)?
+            .into_iter()
+            .map(|msg| (msg.id, msg.flags))
+            .collect())
+    }
+    /// Get the ids and raw headers of all messages within the given sequence range. The default
+    /// implementation fetches the whole messages.
+    fn get_raw_headers(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<u8>)>> {
+        let ids = self.get_msgs(seq_range)?.into_iter().map(|msg| msg.id);
+        Ok(ids.zip(self.get_raw_msgs(seq_range)?).collect())
+    }
+    /// Get the ids and raw messages of all messages within the given sequence range, without
+    /// flagging them as seen. The default implementation relies on [`Backend::get_raw_msgs`].
+    fn peek_raw_msgs(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<u8>)>> {
+        let ids = self.get_msgs(seq_range)?.into_iter().map(|msg| msg.id);
+        Ok(ids.zip(self.get_raw_msgs(seq_range)?).collect())
+    }
+    /// Get the ids, the first `max_size` bytes and the whole size of the raw messages within the
+    /// given sequence range, without flagging them as seen. The default implementation fetches
+    /// the whole messages, then truncates them.
+    fn peek_partial_raw_msgs(
+        &mut self,
+        seq_range: &str,
+        max_size: usize,
+    ) -> Result<Vec<(u32, Vec<u8>, usize)>> {
+        Ok(self
+            .peek_raw_msgs(seq_range)?
+            .into_iter()
+            .map(|(id, mut raw_msg)| {
+                let size = raw_msg.len();
+                raw_msg.truncate(max_size);
+                (id, raw_msg, size)
+            })
+            .collect())
+    }
+    fn append(&mut self, mbox: &Mbox, msg: Msg) -> Result<()>;
+    fn append_raw(&mut self, mbox: &Mbox, msg: &[u8], flags: Flags) -> Result<()>;
+    /// Append the given raw message with the given internal date. The default implementation
+    /// lets the backend date the message.
+    fn append_raw_dated(
+        &mut self,
+        mbox: &Mbox,
+        msg: &[u8],
+        flags: Flags,
+        _date: DateTime<FixedOffset>,
+    ) -> Result<()> {
+        self.append_raw(mbox, msg, flags)
+    }
+    fn move_msg(&mut self, seq: &str, mbox: &Mbox) -> Result<()>;
+    /// Copy all messages within the given sequence range to the given mailbox.
+    fn copy_msgs(&mut self, seq_range: &str, mbox: &Mbox) -> Result<()>;
+    fn expunge(&mut self) -> Result<()>;
+    /// Permanently remove the deleted messages within the given sequence range only, leaving the
+    /// other deleted messages of the mailbox. Returns false when the backend cannot expunge
+    /// single messages, which the default implementation cannot.
+    fn expunge_msgs(&mut self, _seq_range: &str) -> Result<bool> {
+        Ok(false)
+    }
+    fn logout(&mut self) -> Result<()>;
+    /// Keep the session alive between two commands, eg. in daemon mode. A dead session is
+    /// replaced on the next command. Stateless backends have nothing to do.
+    fn keepalive(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Add flags to all messages within the given sequence range.
+    fn add_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()>;
+    /// Replace flags of all messages within the given sequence range.
+    fn set_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()>;
+    /// Remove flags from all messages within the given sequence range.
+    fn remove_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()>;
+
+    /// Get the ids and labels of all messages within the given sequence range, leaving out the
+    /// hold keyword of the account.
+    fn get_labels(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<String>)>>;
+    /// Add labels to all messages within the given sequence range. The default implementation
+    /// adds them as keywords.
+    fn add_labels(&mut self, seq_range: &str, labels: &[String]) -> Result<()> {
+        self.add_flags(seq_range, &Flags::from_labels(labels)?)
+    }
+    /// Remove labels from all messages within the given sequence range. The default
+    /// implementation removes them as keywords.
+    fn remove_labels(&mut self, seq_range: &str, labels: &[String]) -> Result<()> {
+        self.remove_flags(seq_range, &Flags::from_labels(labels)?)
+    }
+}
+
+/// Represents a sender, able to send messages of an account.
+///
+/// Senders may keep their session open between sends, so that bulk operations do not pay the
+/// connection cost for each message.
+pub trait Sender {
+    /// Send the message and return it as sent, so that it can be saved in the Sent folder.
+    fn send(&mut self, msg: &Msg) -> Result<Vec<u8>>;
+    fn send_raw(&mut self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()>;
+
+    /// Get the final response of the server to the last send, for senders talking to one.
+    fn last_response(&self) -> Option<String> {
+        None
+    }
+    /// Open the session ahead of the first send, to check the connection and the credentials.
+    /// Stateless senders have nothing to do.
+    fn open(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Keep the session alive between two sends. Stateless senders have nothing to do.
+    fn keepalive(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Close the session, if any.
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Build the backend matching the account `backend` config field. Messages are identified by
+/// sequence number instead of UID when `use_seq` is true, for backends making a difference.
+pub fn build_backend<'a>(
+    account: &'a Account,
+    mbox: &'a Mbox,
+    use_seq: bool,
+) -> Box<dyn Backend + 'a> {
+    match account.backend {
+        BackendKind::Imap => Box::new(ImapService::from((account, mbox)).use_seq(use_seq)),
+        BackendKind::Graph => Box::new(GraphService::from((account, mbox))),
+    }
+}
+
+/// Build the sender matching the account `backend` config field, unless a `sendmail-cmd` is
+/// defined.
+pub fn build_sender<'a>(account: &'a Account) -> Box<dyn Sender + 'a> {
+    if account.sendmail_cmd.is_some() {
+        return Box::new(SendmailService::from(account));
+    }
+
+    match account.backend {
+        BackendKind::Imap => Box::new(SmtpService::from(account)),
+        BackendKind::Graph => Box::new(GraphSendService::from(account)),
+    }
+}
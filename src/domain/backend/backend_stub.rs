@@ -0,0 +1,164 @@
+//! Module related to the backend stub.
+//!
+//! This module exposes an in-memory backend for handler tests: it holds the flags of the messages
+//! of a single mailbox and records the moves, copies and expunges asked by the handlers.
+
+use anyhow::Result;
+use imap::types::Flag;
+
+use crate::domain::{
+    backend::Backend,
+    mbox::{Mbox, Mboxes},
+    msg::{Envelope, Envelopes, Flags, Msg},
+};
+
+/// Represents the in-memory backend stub.
+#[derive(Debug, Default)]
+pub struct BackendStub {
+    /// The ids and flags of the messages of the selected mailbox.
+    pub msgs: Vec<(u32, Flags)>,
+    /// Whether single messages can be expunged, like IMAP servers supporting UIDPLUS.
+    pub uid_expunge: bool,
+    /// The sequence ranges and target mailboxes of the moves.
+    pub moved: Vec<(String, String)>,
+    /// The sequence ranges and target mailboxes of the copies.
+    pub copied: Vec<(String, String)>,
+    /// The ids of the expunged messages.
+    pub expunged: Vec<u32>,
+}
+
+impl BackendStub {
+    /// Build a stub holding messages with the given ids and flags.
+    pub fn new(msgs: Vec<(u32, Vec<&str>)>) -> Self {
+        Self {
+            msgs: msgs
+                .into_iter()
+                .map(|(id, flags)| (id, Flags::from(flags)))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    fn matching(&mut self, seq_range: &str) -> impl Iterator<Item = &mut (u32, Flags)> {
+        let ranges: Vec<(u32, u32)> = seq_range
+            .split(',')
+            .map(|seq| match seq.split_once(':') {
+                Some((start, "*")) => (start.parse().unwrap(), u32::MAX),
+                Some((start, end)) => (start.parse().unwrap(), end.parse().unwrap()),
+                None => (seq.parse().unwrap(), seq.parse().unwrap()),
+            })
+            .collect();
+        self.msgs.iter_mut().filter(move |(id, _)| {
+            ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(id))
+        })
+    }
+}
+
+impl Backend for BackendStub {
+    fn get_caps(&mut self) -> Result<Vec<String>> {
+        Ok(if self.uid_expunge {
+            vec![String::from("UIDPLUS")]
+        } else {
+            vec![]
+        })
+    }
+    fn list_mboxes(&mut self) -> Result<Mboxes> {
+        Ok(Mboxes(vec![]))
+    }
+    fn create_mbox(&mut self, _mbox: &Mbox) -> Result<()> {
+        Ok(())
+    }
+    fn list_envelopes(&mut self, page_size: &usize, _page: &usize) -> Result<Envelopes> {
+        Ok(Envelopes(
+            self.msgs
+                .iter()
+                .take(*page_size)
+                .map(|(id, flags)| Envelope {
+                    id: *id,
+                    flags: flags.clone(),
+                    ..Envelope::default()
+                })
+                .collect(),
+        ))
+    }
+    fn get_msg(&mut self, _seq: &str) -> Result<Msg> {
+        unimplemented!()
+    }
+    fn get_raw_msg(&mut self, _seq: &str) -> Result<Vec<u8>> {
+        unimplemented!()
+    }
+    fn get_msgs(&mut self, _seq_range: &str) -> Result<Vec<Msg>> {
+        unimplemented!()
+    }
+    fn get_raw_msgs(&mut self, _seq_range: &str) -> Result<Vec<Vec<u8>>> {
+        unimplemented!()
+    }
+    fn get_flags(&mut self, seq_range: &str) -> Result<Vec<(u32, Flags)>> {
+        Ok(self
+            .matching(seq_range)
+            .map(|(id, flags)| (*id, flags.clone()))
+            .collect())
+    }
+    fn append(&mut self, _mbox: &Mbox, _msg: Msg) -> Result<()> {
+        unimplemented!()
+    }
+    fn append_raw(&mut self, _mbox: &Mbox, _msg: &[u8], _flags: Flags) -> Result<()> {
+        unimplemented!()
+    }
+    fn move_msg(&mut self, seq: &str, mbox: &Mbox) -> Result<()> {
+        self.moved.push((seq.to_owned(), mbox.name.to_owned()));
+        Ok(())
+    }
+    fn copy_msgs(&mut self, seq_range: &str, mbox: &Mbox) -> Result<()> {
+        self.copied
+            .push((seq_range.to_owned(), mbox.name.to_owned()));
+        Ok(())
+    }
+    fn expunge(&mut self) -> Result<()> {
+        let (deleted, msgs): (Vec<_>, Vec<_>) = self
+            .msgs
+            .drain(..)
+            .partition(|(_, flags)| flags.contains(&Flag::Deleted));
+        self.msgs = msgs;
+        self.expunged.extend(deleted.into_iter().map(|(id, _)| id));
+        Ok(())
+    }
+    fn expunge_msgs(&mut self, seq_range: &str) -> Result<bool> {
+        if !self.uid_expunge {
+            return Ok(false);
+        }
+        let ids: Vec<u32> = self.matching(seq_range).map(|(id, _)| *id).collect();
+        self.msgs.retain(|(id, _)| !ids.contains(id));
+        self.expunged.extend(ids);
+        Ok(true)
+    }
+    fn logout(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn add_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        for (_, msg_flags) in self.matching(seq_range) {
+            msg_flags.extend(flags.iter().cloned());
+        }
+        Ok(())
+    }
+    fn set_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        for (_, msg_flags) in self.matching(seq_range) {
+            *msg_flags = flags.clone();
+        }
+        Ok(())
+    }
+    fn remove_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        for (_, msg_flags) in self.matching(seq_range) {
+            msg_flags.retain(|flag| !flags.contains(flag));
+        }
+        Ok(())
+    }
+    fn get_labels(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<String>)>> {
+        Ok(self
+            .matching(seq_range)
+            .map(|(id, _)| (*id, vec![]))
+            .collect())
+    }
+}
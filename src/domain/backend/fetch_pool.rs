@@ -0,0 +1,166 @@
+//! Module related to the fetch worker pool.
+//!
+//! Commands fetching many full messages (export, backup, body search) can split them between
+//! `fetch-jobs` workers, each opening its own session to the mailbox. Workers pick the chunks of
+//! messages one after the other, so that a slow chunk does not hold the others back. With a
+//! single job, messages are fetched by the backend of the command, without any additional
+//! session.
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    config::{Account, BackendKind},
+    domain::{
+        backend::{build_backend, Backend},
+        mbox::Mbox,
+    },
+    output::output_interrupt,
+};
+
+type RawMsgs = Vec<(u32, Vec<u8>)>;
+
+fn to_id_set(ids: &[u32]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Represents a pool of fetch workers, started on the first fetch and logged out when dropped.
+pub struct FetchPool {
+    account: Account,
+    mbox_name: String,
+    use_seq: bool,
+    jobs: usize,
+    chunks: Option<mpsc::Sender<Vec<u32>>>,
+    results: Option<mpsc::Receiver<Result<RawMsgs>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl FetchPool {
+    /// Prepare `jobs` workers fetching messages of the given mailbox. Messages are identified by
+    /// sequence number instead of UID when `use_seq` is true.
+    pub fn new(account: &Account, mbox: &Mbox, use_seq: bool, jobs: usize) -> Self {
+        Self {
+            account: account.clone(),
+            mbox_name: mbox.name.to_owned(),
+            use_seq,
+            jobs: jobs.max(1),
+            chunks: None,
+            results: None,
+            workers: vec![],
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        // Workers connect at once: the password is looked up first, so that its command runs
+        // only once.
+        if self.account.backend == BackendKind::Imap {
+            self.account.imap_passwd()?;
+        }
+        debug!("start {} fetch workers", self.jobs);
+
+        let (chunks, chunks_rx) = mpsc::channel::<Vec<u32>>();
+        let (results_tx, results) = mpsc::channel();
+        let chunks_rx = Arc::new(Mutex::new(chunks_rx));
+        for _ in 0..self.jobs {
+            let account = self.account.clone();
+            let mbox_name = self.mbox_name.to_owned();
+            let use_seq = self.use_seq;
+            let chunks_rx = chunks_rx.clone();
+            let results_tx = results_tx.clone();
+            self.workers.push(thread::spawn(move || {
+                let mbox = Mbox::from(mbox_name.as_str());
+                let mut backend = build_backend(&account, &mbox, use_seq);
+                loop {
+                    let chunk = match chunks_rx.lock().ok().and_then(|rx| rx.recv().ok()) {
+                        Some(chunk) => chunk,
+                        None => break,
+                    };
+                    let res = output_interrupt::check()
+                        .and_then(|()| backend.peek_raw_msgs(&to_id_set(&chunk)));
+                    if results_tx.send(res).is_err() {
+                        break;
+                    }
+                }
+                backend.logout().ok();
+            }));
+        }
+        self.chunks = Some(chunks);
+        self.results = Some(results);
+        Ok(())
+    }
+
+    /// Get the number of workers, 1 meaning that the backend of the command fetches alone.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Fetch the raw messages of the given ids, by chunks of `chunk_size` messages, without
+    /// marking them as seen. Messages are returned in the order of the given ids.
+    pub fn peek_raw_msgs(
+        &mut self,
+        ids: &[u32],
+        chunk_size: usize,
+        backend: &mut dyn Backend,
+    ) -> Result<RawMsgs> {
+        let chunks = ids.chunks(chunk_size.max(1));
+        let mut raw_msgs: HashMap<u32, Vec<u8>> = HashMap::new();
+
+        if self.jobs == 1 {
+            for chunk in chunks {
+                output_interrupt::check()?;
+                raw_msgs.extend(backend.peek_raw_msgs(&to_id_set(chunk))?);
+            }
+        } else {
+            if self.workers.is_empty() {
+                self.start()?;
+            }
+            let stopped = || anyhow!("cannot fetch messages: the fetch workers stopped");
+            let (sender, results) = match (self.chunks.as_ref(), self.results.as_ref()) {
+                (Some(sender), Some(results)) => (sender, results),
+                _ => return Err(stopped()),
+            };
+            let mut count = 0;
+            for chunk in chunks {
+                sender.send(chunk.to_vec()).map_err(|_| stopped())?;
+                count += 1;
+            }
+            // All results are received, even after an error, so that none of them ends up in
+            // the results of the next call.
+            let mut err = None;
+            for _ in 0..count {
+                match results.recv().map_err(|_| stopped())? {
+                    Ok(chunk) => raw_msgs.extend(chunk),
+                    Err(chunk_err) => {
+                        err.get_or_insert(chunk_err);
+                    }
+                }
+            }
+            if let Some(err) = err {
+                return Err(err);
+            }
+        }
+
+        Ok(ids
+            .iter()
+            .filter_map(|id| raw_msgs.remove(id).map(|raw_msg| (*id, raw_msg)))
+            .collect())
+    }
+}
+
+impl Drop for FetchPool {
+    fn drop(&mut self) {
+        // Workers stop once there is no more chunk to pick.
+        self.chunks = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! Module related to backends.
+//!
+//! A backend manages mailboxes and messages of an account, a sender sends its messages. Handlers
+//! only deal with the traits exposed here, the concrete implementations are picked at runtime
+//! from the account `backend` config field.
+
+pub mod backend_service;
+pub use backend_service::*;
+
+#[cfg(feature = "async")]
+pub mod backend_async;
+
+pub mod backend_retry;
+
+#[cfg(test)]
+pub mod backend_stub;
+
+pub mod fetch_pool;
+pub use fetch_pool::*;
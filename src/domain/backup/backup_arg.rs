@@ -0,0 +1,92 @@
+//! Module related to backup CLI.
+//!
+//! This module provides subcommands and a command matcher related to account backups.
+
+use anyhow::{Context, Result};
+use clap::{self, App, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Glob<'a> = Option<&'a str>;
+type Dir<'a> = &'a str;
+type Rate = Option<f64>;
+
+/// Backup commands.
+pub enum Command<'a> {
+    /// Back up the mailboxes matching the optional glob to the given directory.
+    Backup(Glob<'a>, Dir<'a>, Rate),
+
+    /// Restore the backup of the given directory.
+    Restore(Dir<'a>, Rate),
+}
+
+fn parse_rate(m: &ArgMatches) -> Result<Rate> {
+    m.value_of("rate")
+        .map(|rate| {
+            rate.parse::<f64>()
+                .context(format!(r#"cannot parse rate "{}""#, rate))
+        })
+        .transpose()
+}
+
+/// Backup command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("backup") {
+        debug!("backup command matched");
+        let glob = m.value_of("mailbox-glob");
+        trace!("mailbox glob: {:?}", glob);
+        let dir = m.value_of("dir").unwrap();
+        trace!("dir: {}", dir);
+        let rate = parse_rate(m)?;
+        trace!("rate: {:?}", rate);
+        return Ok(Some(Command::Backup(glob, dir, rate)));
+    }
+
+    if let Some(m) = m.subcommand_matches("restore") {
+        debug!("restore command matched");
+        let dir = m.value_of("dir").unwrap();
+        trace!("dir: {}", dir);
+        let rate = parse_rate(m)?;
+        trace!("rate: {:?}", rate);
+        return Ok(Some(Command::Restore(dir, rate)));
+    }
+
+    Ok(None)
+}
+
+fn dir_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("dir")
+        .help("Specifies the backup directory")
+        .value_name("DIR")
+        .required(true)
+}
+
+fn rate_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("rate")
+        .help("Limits the number of messages transferred per second")
+        .long("rate")
+        .short("r")
+        .value_name("MSGS")
+}
+
+/// Backup subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![
+        SubCommand::with_name("backup")
+            .about("Backs up all messages and flags of the account")
+            .long_about("Backs up all messages and flags of the account into one Maildir per mailbox, plus a manifest. Running it again resumes an interrupted backup, or backs up the new messages and flags changes.")
+            .arg(
+                Arg::with_name("mailbox-glob")
+                    .help("Backs up only the mailboxes matching the glob, like `INBOX*`")
+                    .long("mailbox")
+                    .short("m")
+                    .value_name("GLOB"),
+            )
+            .arg(rate_arg())
+            .arg(dir_arg()),
+        SubCommand::with_name("restore")
+            .about("Restores a backup to the account")
+            .long_about("Restores a backup to the account, skipping messages the mailbox already holds according to their Message-ID. Running it again resumes an interrupted restore.")
+            .arg(rate_arg())
+            .arg(dir_arg()),
+    ]
+}
@@ -0,0 +1,298 @@
+//! Module related to backup handling.
+//!
+//! This module gathers all backup commands.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use imap::types::NameAttribute;
+use log::{debug, info};
+use mailparse::MailHeaderMap;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{build_backend, FetchPool},
+        backup::{glob_matches, BackupManifest, BackupMsg},
+        mbox::Mbox,
+        msg::{msg_export, Flags},
+    },
+    output::OutputServiceInterface,
+};
+
+/// Number of messages fetched per FETCH command by backups.
+const FETCH_CHUNK_SIZE: usize = 10;
+
+/// Spaces out transfers, since IMAP servers throttle clients downloading or uploading too much.
+struct RateLimiter {
+    interval: Option<Duration>,
+    last: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Build a limiter allowing the given number of transfers per second, unlimited if `None`.
+    fn new(rate: Option<f64>) -> Self {
+        Self {
+            interval: rate
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            last: None,
+        }
+    }
+
+    fn wait(&mut self) {
+        if let (Some(interval), Some(last)) = (self.interval, self.last) {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+/// Create the `cur`, `new` and `tmp` directories of the given Maildir.
+fn create_maildir(dir: &Path) -> Result<()> {
+    for subdir in ["cur", "new", "tmp"].iter() {
+        let path = dir.join(subdir);
+        fs::create_dir_all(&path).context(format!("cannot create Maildir directory {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Back up all messages and flags of the mailboxes matching the given glob, all of them by
+/// default. Messages already backed up are skipped, only their flags are updated.
+pub fn backup<OutputService: OutputServiceInterface>(
+    glob: Option<&str>,
+    dir: &str,
+    rate: Option<f64>,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir).context(format!("cannot create backup directory {:?}", dir))?;
+    let mut manifest = BackupManifest::load(&dir)?;
+    manifest.account = account.name.to_owned();
+    let mut limiter = RateLimiter::new(rate);
+
+    let inbox = Mbox::from("INBOX");
+    let mut backend = build_backend(account, &inbox, false);
+    let mboxes = backend.list_mboxes()?.0;
+    backend.logout()?;
+
+    let mut count = 0;
+    for mbox in mboxes.iter() {
+        if mbox.attributes.0.contains(&NameAttribute::NoSelect) {
+            continue;
+        }
+        if let Some(glob) = glob {
+            if !glob_matches(glob, &mbox.name) {
+                continue;
+            }
+        }
+
+        info!(r#"back up mailbox "{}""#, mbox.name);
+        let mut backend = build_backend(account, mbox, false);
+        let uid_validity = backend.get_uid_validity()?;
+        let envelopes = backend.list_envelopes(&0, &0)?;
+        let maildir = dir.join(&manifest.mbox(&mbox.name, &mbox.delim).dir);
+        create_maildir(&maildir)?;
+
+        // Messages backed up under a previous UIDVALIDITY are kept in the Maildir, renamed so
+        // that the messages now holding their UIDs do not overwrite them.
+        let reset = manifest
+            .mbox(&mbox.name, &mbox.delim)
+            .set_uid_validity(uid_validity);
+        if let Some((prev, msgs)) = reset {
+            eprintln!(
+                r#"warning: UIDVALIDITY of mailbox "{}" changed, backing it up again, the previous backup is kept with the prefix "{}.""#,
+                mbox.name, prev
+            );
+            for msg in msgs {
+                let path = maildir.join("cur").join(&msg.filename);
+                let new_path = maildir
+                    .join("cur")
+                    .join(format!("{}.{}", prev, msg.filename));
+                // Already renamed by an interrupted backup.
+                if !path.exists() {
+                    continue;
+                }
+                fs::rename(&path, &new_path)
+                    .context(format!("cannot rename stale backup {:?}", path))?;
+            }
+            manifest.save(&dir)?;
+        }
+        let mut progress =
+            output.progress(&format!(r#"Backing up "{}""#, mbox.name), envelopes.0.len());
+
+        // Messages not backed up yet are fetched ahead, by batches split between the fetch jobs.
+        // Rate-limited backups fetch them one by one.
+        let (jobs, chunk_size) = match rate {
+            Some(_) => (1, 1),
+            None => (account.fetch_jobs, FETCH_CHUNK_SIZE),
+        };
+        let mut pool = FetchPool::new(account, mbox, false, jobs);
+        let backed_up = &manifest.mbox(&mbox.name, &mbox.delim).msgs;
+        let missing_ids: Vec<u32> = envelopes
+            .iter()
+            .rev()
+            .map(|envelope| envelope.id)
+            .filter(|id| !backed_up.contains_key(id))
+            .collect();
+        let mut missing_batches = missing_ids.chunks(chunk_size * jobs);
+        let mut raw_msgs: HashMap<u32, Vec<u8>> = HashMap::new();
+
+        // Oldest messages first, so that an interrupted backup resumes in order.
+        for envelope in envelopes.iter().rev() {
+            let filename = format!(
+                "{}.himalaya{}",
+                envelope.id,
+                msg_export::maildir_info(&envelope.flags)
+            );
+            let path = maildir.join("cur").join(&filename);
+            let backup_mbox = manifest.mbox(&mbox.name, &mbox.delim);
+            // The manifest is saved after each message, the backup resumes from there.
+            progress.inc(1)?;
+
+            match backup_mbox.msgs.get_mut(&envelope.id) {
+                Some(msg) if msg.filename == filename => continue,
+                Some(msg) => {
+                    debug!("update flags of backed up message {}", envelope.id);
+                    let prev_path = maildir.join("cur").join(&msg.filename);
+                    fs::rename(&prev_path, &path)
+                        .context(format!("cannot update flags of {:?}", prev_path))?;
+                    msg.filename = filename;
+                }
+                None => {
+                    // Missing messages come in the order of the batches.
+                    if !raw_msgs.contains_key(&envelope.id) {
+                        limiter.wait();
+                        let ids = missing_batches.next().unwrap_or_default();
+                        raw_msgs.extend(pool.peek_raw_msgs(ids, chunk_size, backend.as_mut())?);
+                    }
+                    let id = envelope.id.to_string();
+                    let raw_msg = raw_msgs
+                        .remove(&envelope.id)
+                        .ok_or_else(|| anyhow!("cannot find message {}", id))?;
+                    let tmp_path = maildir.join("tmp").join(&filename);
+                    fs::write(&tmp_path, &raw_msg)
+                        .and_then(|_| fs::rename(&tmp_path, &path))
+                        .context(format!("cannot back up message {} to {:?}", id, path))?;
+
+                    let message_id = mailparse::parse_headers(&raw_msg)
+                        .ok()
+                        .and_then(|(headers, _)| headers.get_first_value("Message-ID"));
+                    backup_mbox.msgs.insert(
+                        envelope.id,
+                        BackupMsg {
+                            filename,
+                            message_id,
+                        },
+                    );
+                    count += 1;
+                }
+            }
+
+            manifest.updated_at = Local::now().timestamp();
+            manifest.save(&dir)?;
+        }
+        progress.finish();
+
+        backend.logout()?;
+    }
+
+    manifest.updated_at = Local::now().timestamp();
+    manifest.save(&dir)?;
+    output.print(format!(
+        "{} message(s) successfully backed up to {:?}",
+        count, dir
+    ))
+}
+
+/// Restore the backup of the given directory. Messages already restored to the account, or
+/// whose Message-ID is already in the mailbox, are skipped.
+pub fn restore<OutputService: OutputServiceInterface>(
+    dir: &str,
+    rate: Option<f64>,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let dir = PathBuf::from(dir);
+    let mut manifest = BackupManifest::load(&dir)?;
+    if manifest.mboxes.is_empty() {
+        return Err(anyhow!("cannot find backup manifest in {:?}", dir));
+    }
+    let mut limiter = RateLimiter::new(rate);
+
+    let (mut count, mut skipped) = (0, 0);
+    let names: Vec<String> = manifest.mboxes.keys().cloned().collect();
+    for name in names {
+        info!(r#"restore mailbox "{}""#, name);
+        let mbox = Mbox::from(name.as_str());
+        let mut backend = build_backend(account, &mbox, false);
+        backend.create_mbox(&mbox)?;
+
+        let backup_mbox = manifest.mboxes[&name].clone();
+        let maildir = dir.join(&backup_mbox.dir);
+        let mut progress =
+            output.progress(&format!(r#"Restoring "{}""#, name), backup_mbox.msgs.len());
+        for (uid, msg) in backup_mbox.msgs.iter() {
+            progress.inc(1)?;
+            let is_restored = backup_mbox
+                .restored
+                .get(&account.name)
+                .map(|uids| uids.contains(uid))
+                .unwrap_or_default();
+            if is_restored {
+                continue;
+            }
+
+            limiter.wait();
+            let is_duplicate = match msg.message_id {
+                Some(ref message_id) => {
+                    let query = format!(
+                        r#"HEADER Message-ID "{}""#,
+                        message_id.replace('"', r#"\""#)
+                    );
+                    !backend.search_envelopes(&query, &10, &0)?.is_empty()
+                }
+                None => false,
+            };
+
+            if is_duplicate {
+                debug!("skip duplicate message {:?}", msg.message_id);
+                skipped += 1;
+            } else {
+                let path = maildir.join("cur").join(&msg.filename);
+                let raw_msg = fs::read(&path).context(format!("cannot read message {:?}", path))?;
+                let flags = Flags::try_from(msg_export::maildir_flags(&msg.filename))?;
+                backend.append_raw(&mbox, &msg_export::to_crlf(&raw_msg), flags)?;
+                count += 1;
+            }
+
+            if let Some(backup_mbox) = manifest.mboxes.get_mut(&name) {
+                backup_mbox
+                    .restored
+                    .entry(account.name.to_owned())
+                    .or_default()
+                    .insert(*uid);
+            }
+            manifest.save(&dir)?;
+        }
+        progress.finish();
+
+        backend.logout()?;
+    }
+
+    output.print(format!(
+        "{} message(s) successfully restored, {} duplicate(s) skipped",
+        count, skipped
+    ))
+}
@@ -0,0 +1,200 @@
+//! Module related to backup manifests.
+//!
+//! A backup is a directory holding one Maildir per mailbox, plus a `manifest.json` recording
+//! which messages were backed up and restored. The manifest is saved after each message, so that
+//! an interrupted backup or restore resumes where it stopped.
+//!
+//! Messages are indexed by UID, so each mailbox also records its UIDVALIDITY: when the server
+//! resets it, the recorded UIDs may point to other messages and the mailbox entries are
+//! discarded.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Represents a backed up message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupMsg {
+    /// The name of the message file, in the `cur` directory of the mailbox Maildir.
+    pub filename: String,
+    pub message_id: Option<String>,
+}
+
+/// Represents a backed up mailbox.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupMbox {
+    /// The directory of the mailbox Maildir, relative to the backup directory.
+    pub dir: String,
+    /// The backed up messages, indexed by UID.
+    pub msgs: BTreeMap<u32, BackupMsg>,
+    /// The UIDs of the messages restored to each account, indexed by account name.
+    #[serde(default)]
+    pub restored: BTreeMap<String, BTreeSet<u32>>,
+    /// The UIDVALIDITY of the mailbox the UIDs belong to.
+    #[serde(default)]
+    pub uid_validity: Option<u32>,
+}
+
+impl BackupMbox {
+    /// Record the given UIDVALIDITY of the mailbox. When it changed, the backed up and restored
+    /// messages are discarded, and the previous UIDVALIDITY is returned with the discarded
+    /// messages.
+    pub fn set_uid_validity(&mut self, uid_validity: Option<u32>) -> Option<(u32, Vec<BackupMsg>)> {
+        let prev = match (self.uid_validity, uid_validity) {
+            (Some(prev), Some(curr)) if prev != curr => Some(prev),
+            _ => None,
+        };
+        if uid_validity.is_some() {
+            self.uid_validity = uid_validity;
+        }
+        let prev = prev?;
+        self.restored.clear();
+        let msgs = std::mem::take(&mut self.msgs);
+        Some((prev, msgs.into_iter().map(|(_, msg)| msg).collect()))
+    }
+}
+
+/// Represents the manifest of a backup.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupManifest {
+    /// The name of the backed up account.
+    pub account: String,
+    /// The last backup time, as a UNIX timestamp.
+    pub updated_at: i64,
+    /// The backed up mailboxes, indexed by name.
+    pub mboxes: BTreeMap<String, BackupMbox>,
+}
+
+impl BackupManifest {
+    fn path(dir: &Path) -> PathBuf {
+        dir.join("manifest.json")
+    }
+
+    /// Load the manifest of the given backup directory, or an empty one for a new backup.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = Self::path(dir);
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .context(format!("cannot parse backup manifest at {:?}", path)),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = Self::path(dir);
+        let tmp_path = dir.join("manifest.json.tmp");
+        let content =
+            serde_json::to_string_pretty(self).context("cannot serialize backup manifest")?;
+        // Write then rename, so that an interruption never leaves a truncated manifest.
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .context(format!("cannot save backup manifest at {:?}", path))
+    }
+
+    /// Get the mailbox entry of the given mailbox, creating it if needed.
+    pub fn mbox(&mut self, name: &str, delim: &str) -> &mut BackupMbox {
+        self.mboxes
+            .entry(name.to_owned())
+            .or_insert_with(|| BackupMbox {
+                dir: mbox_dir(name, delim),
+                ..BackupMbox::default()
+            })
+    }
+}
+
+/// Build the Maildir directory name of the given mailbox, with Maildir++ dots as hierarchy
+/// delimiter and without path separators.
+pub fn mbox_dir(name: &str, delim: &str) -> String {
+    let name = if delim.is_empty() {
+        name.to_owned()
+    } else {
+        name.replace(delim, ".")
+    };
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_owned()
+}
+
+/// Check if the given mailbox name matches the given glob, where `*` matches any sequence of
+/// characters and `?` matches any single character.
+pub fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut g, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g, n));
+                g += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the last star match one more character.
+                Some((star_g, star_n)) => {
+                    g = star_g + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_g, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_match_mailbox_globs() {
+        assert!(glob_matches("*", "INBOX"));
+        assert!(glob_matches("INBOX*", "INBOX/Archives"));
+        assert!(glob_matches("*/Arch?ves", "INBOX/Archives"));
+        assert!(!glob_matches("Sent", "INBOX"));
+        assert!(!glob_matches("INBOX/*", "INBOX"));
+
+        assert_eq!("INBOX.Archives", mbox_dir("INBOX/Archives", "/"));
+        assert_eq!("Work.2021_12", mbox_dir(".Work.2021/12", "."));
+    }
+
+    #[test]
+    fn it_should_discard_msgs_on_uid_validity_change() {
+        let msg = BackupMsg {
+            filename: String::from("1.himalaya:2,S"),
+            message_id: None,
+        };
+        let mut mbox = BackupMbox::default();
+        assert_eq!(None, mbox.set_uid_validity(Some(42)));
+        mbox.msgs.insert(1, msg.clone());
+        mbox.restored
+            .insert(String::from("work"), vec![1].into_iter().collect());
+
+        assert_eq!(None, mbox.set_uid_validity(Some(42)));
+        assert_eq!(None, mbox.set_uid_validity(None));
+        assert_eq!(1, mbox.msgs.len());
+
+        assert_eq!(Some((42, vec![msg])), mbox.set_uid_validity(Some(43)));
+        assert_eq!(Some(43), mbox.uid_validity);
+        assert!(mbox.msgs.is_empty());
+        assert!(mbox.restored.is_empty());
+    }
+}
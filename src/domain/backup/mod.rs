@@ -0,0 +1,9 @@
+//! Module related to account backups.
+
+#[cfg(feature = "cli")]
+pub mod backup_arg;
+#[cfg(feature = "cli")]
+pub mod backup_handler;
+
+pub mod backup_manifest_entity;
+pub use backup_manifest_entity::*;
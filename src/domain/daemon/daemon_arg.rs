@@ -0,0 +1,49 @@
+//! Module related to daemon CLI.
+//!
+//! This module provides arguments, subcommands and a command matcher related to the daemon.
+
+use anyhow::Result;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::debug;
+
+/// Daemon commands.
+pub enum Command {
+    /// Serve the commands until stopped.
+    Start,
+    /// Stop the running daemon.
+    Stop,
+}
+
+/// Daemon command matcher.
+pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
+    if let Some(m) = m.subcommand_matches("daemon") {
+        if m.is_present("stop") {
+            debug!("daemon stop command matched");
+            return Ok(Some(Command::Stop));
+        }
+
+        debug!("daemon command matched");
+        return Ok(Some(Command::Start));
+    }
+
+    Ok(None)
+}
+
+/// Daemon subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("daemon")
+        .about("Serves the commands, keeping the sessions warm")
+        .long_about("Serves the commands over a unix socket (JSON-RPC), keeping the IMAP and SMTP sessions of the accounts warm. While the daemon runs, the commands not needing the terminal (list, search, read, thread, mailboxes, flag, copy, move, delete, save, and send with the JSON output) are transparently served by it, unless `--no-daemon` is given, `--set` is used, or the daemon runs another config.")
+        .arg(
+            Arg::with_name("stop")
+                .long("stop")
+                .help("Stops the running daemon"),
+        )]
+}
+
+/// Daemon arguments.
+pub fn args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![Arg::with_name("no-daemon")
+        .long("no-daemon")
+        .help("Runs the command without the daemon, even when it is running")]
+}
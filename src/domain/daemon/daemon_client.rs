@@ -0,0 +1,129 @@
+//! Module related to the daemon client.
+//!
+//! While the daemon runs, commands are transparently served by it: their command line
+//! arguments are sent to the daemon, then its output is printed back. Commands needing the
+//! terminal (editor, prompts, pickers), running another config, or sent when the daemon cannot
+//! be reached run locally.
+
+use anyhow::{anyhow, Context, Error, Result};
+use clap::ArgMatches;
+use log::debug;
+use std::io::{self, Write};
+
+use crate::{
+    domain::daemon::{Request, Response},
+    output::OutputFmt,
+};
+
+/// Check if the matched command can be served by the daemon.
+fn is_served(m: &ArgMatches) -> bool {
+    // Traces record the exchanges of the sessions of the process.
+    if m.is_present("no-daemon")
+        || m.is_present("set")
+        || m.is_present("trace")
+        || m.is_present("debug")
+    {
+        return false;
+    }
+    match m.subcommand() {
+        ("list", Some(m)) | ("search", Some(m)) => !m.is_present("interactive"),
+        ("mailboxes", Some(m)) => m.subcommand_name().is_none(),
+        ("read", Some(_))
+        | ("thread", Some(_))
+        | ("copy", Some(_))
+        | ("move", Some(_))
+        | ("delete", Some(_))
+        | ("flag", Some(_))
+        | ("label", Some(_)) => true,
+        ("save", Some(sub_m)) => sub_m.is_present("message"),
+        // Without the JSON output, the message can come from the standard input.
+        ("send", Some(sub_m)) => {
+            sub_m.is_present("message")
+                && OutputFmt::from(m.value_of("output").unwrap_or_default()) == OutputFmt::Json
+        }
+        _ => false,
+    }
+}
+
+fn print(output: &str) -> Result<()> {
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(output.as_bytes())
+        .and_then(|()| stdout.flush())
+        .context("cannot print daemon output")
+}
+
+/// Serve the matched command with the daemon. Returns `None` when the command is not served,
+/// so that it runs locally.
+#[cfg(unix)]
+pub fn run(m: &ArgMatches) -> Option<Result<()>> {
+    use crate::domain::daemon::{self, RunParams, COMMAND_FAILED};
+    use std::{env, os::unix::net::UnixStream};
+
+    if !is_served(m) {
+        return None;
+    }
+    let path = daemon::socket_path();
+    if !path.exists() {
+        debug!("cannot find daemon socket at {:?}", path);
+        return None;
+    }
+    if let Err(err) = daemon::check_socket(&path) {
+        return Some(Err(err));
+    }
+    let stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(err) => {
+            debug!("cannot connect to daemon at {:?}: {}", path, err);
+            return None;
+        }
+    };
+
+    // Once the request is sent, the command may have run: it cannot run locally anymore.
+    let req = Request::run(RunParams {
+        args: env::args().skip(1).collect(),
+        config: m.value_of("config").map(String::from),
+    });
+    let res: Response = match daemon::write(&stream, &req).and_then(|()| daemon::read(&stream)) {
+        Ok(res) => res,
+        Err(err) => return Some(Err(err)),
+    };
+    match (res.result, res.error) {
+        (Some(result), _) => Some(print(&result.output)),
+        (None, Some(err)) if err.code == COMMAND_FAILED => Some(Err(err
+            .data
+            .map(Error::from)
+            .unwrap_or_else(|| anyhow!("{}", err.message)))),
+        (None, Some(err)) => {
+            debug!("command not served by daemon: {}", err.message);
+            None
+        }
+        (None, None) => Some(Err(anyhow!("cannot parse daemon message: empty response"))),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn run(_m: &ArgMatches) -> Option<Result<()>> {
+    None
+}
+
+/// Send the given request to the daemon.
+#[cfg(unix)]
+pub fn request(req: &Request) -> Result<Response> {
+    use crate::domain::daemon;
+    use std::os::unix::net::UnixStream;
+
+    let path = daemon::socket_path();
+    daemon::check_socket(&path)?;
+    let stream =
+        UnixStream::connect(&path).context(format!("cannot connect to daemon at {:?}", path))?;
+    daemon::write(&stream, req)?;
+    daemon::read(&stream)
+}
+
+#[cfg(not(unix))]
+pub fn request(_req: &Request) -> Result<Response> {
+    Err(anyhow!(
+        "cannot connect to daemon: not supported on this platform"
+    ))
+}
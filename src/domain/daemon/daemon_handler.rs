@@ -0,0 +1,313 @@
+//! Module related to daemon handling.
+//!
+//! The daemon keeps one backend per account and mailbox, and one sender per account, so that
+//! the commands it serves reuse warm sessions instead of connecting (and handshaking) each
+//! time. Requests are served one at a time, the sessions being kept alive in between.
+
+use anyhow::{anyhow, Result};
+use clap::{App, ArgMatches};
+use log::{debug, warn};
+use std::{collections::HashMap, convert::TryFrom, iter, time::Duration};
+
+use crate::{
+    config::{Account, Config, ReceiptPolicy},
+    domain::{
+        backend::{build_backend, build_sender, Backend, Sender},
+        daemon::{
+            daemon_client, Request, Response, RunParams, COMMAND_FAILED, INVALID_PARAMS,
+            METHOD_NOT_FOUND, NOT_SERVED,
+        },
+        mbox::Mbox,
+    },
+    output::{output_error::ErrorJson, output_interrupt, OutputService, OutputServiceInterface},
+};
+
+/// Define the interval between two keepalives of the sessions.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Define the interval between two checks for incoming connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+type CreateApp = fn() -> App<'static, 'static>;
+type Dispatch = fn(
+    &ArgMatches,
+    &Config,
+    &Account,
+    &Mbox,
+    &OutputService,
+    &mut dyn Backend,
+    &mut dyn Sender,
+) -> Result<()>;
+
+type BackendKey = (String, String, bool);
+
+struct Daemon<'a> {
+    config_path: Option<&'a str>,
+    config: &'a Config,
+    accounts: &'a [Account],
+    create_app: CreateApp,
+    dispatch: Dispatch,
+    backends: HashMap<BackendKey, (&'a Mbox, Box<dyn Backend + 'a>)>,
+    senders: HashMap<String, Box<dyn Sender + 'a>>,
+}
+
+impl<'a> Daemon<'a> {
+    fn find_account(&self, name: Option<&str>) -> Result<&'a Account> {
+        let accounts = self.accounts;
+        match name.map(str::trim) {
+            Some("default") | Some("") | None => accounts
+                .iter()
+                .find(|account| account.default)
+                .ok_or_else(|| anyhow!("cannot find default account")),
+            Some(name) => accounts
+                .iter()
+                .find(|account| account.name == name)
+                .ok_or_else(|| anyhow!(r#"cannot find account "{}""#, name)),
+        }
+    }
+
+    /// Run the given command line arguments, returning their output.
+    fn run(&mut self, params: RunParams) -> Result<String> {
+        let args = iter::once(String::from(env!("CARGO_PKG_NAME"))).chain(params.args);
+        let m = (self.create_app)()
+            .get_matches_from_safe(args)
+            .map_err(|err| anyhow!("cannot parse arguments: {}", err.message))?;
+        let account = self.find_account(m.value_of("account"))?;
+        let output = OutputService::try_from(m.value_of("output"))?
+            .with_tpl(
+                m.value_of("format")
+                    .map(String::from)
+                    .or_else(|| account.list_format.to_owned()),
+            )
+            .captured();
+
+        let mbox = Mbox::try_from(m.value_of("mailbox"))?;
+        let use_seq = m.is_present("use-seq");
+        let key = (account.name.to_owned(), mbox.name.to_owned(), use_seq);
+        let (mbox, backend) = self.backends.entry(key).or_insert_with(|| {
+            // Backends borrow their mailbox, both are kept for as long as the daemon runs.
+            let mbox: &'a Mbox = Box::leak(Box::new(mbox));
+            (mbox, build_backend(account, mbox, use_seq))
+        });
+        let sender = self
+            .senders
+            .entry(account.name.to_owned())
+            .or_insert_with(|| build_sender(account));
+
+        // Requests wait for the commands running locally for the account.
+        let _lock = if m.is_present("no-lock") {
+            None
+        } else {
+            Some(account.lock_commands(true)?)
+        };
+        (self.dispatch)(
+            &m,
+            self.config,
+            account,
+            *mbox,
+            &output,
+            backend.as_mut(),
+            sender.as_mut(),
+        )?;
+        Ok(output.take_captured())
+    }
+
+    fn handle(&mut self, req: Request) -> Response {
+        let id = req.id;
+        match (req.method.as_str(), req.params) {
+            ("run", Some(params)) if params.config.as_deref() != self.config_path => {
+                Response::error(
+                    id,
+                    NOT_SERVED,
+                    "cannot serve command: the daemon runs another config",
+                    None,
+                )
+            }
+            ("run", Some(params)) => match self.run(params) {
+                Ok(output) => Response::ok(id, output),
+                Err(err) => Response::error(
+                    id,
+                    COMMAND_FAILED,
+                    err.to_string(),
+                    Some(ErrorJson::from(&err)),
+                ),
+            },
+            ("run", None) => Response::error(
+                id,
+                INVALID_PARAMS,
+                "cannot run command: missing params",
+                None,
+            ),
+            ("shutdown", _) => Response::ok(id, String::new()),
+            (method, _) => Response::error(
+                id,
+                METHOD_NOT_FOUND,
+                format!(r#"cannot find method "{}""#, method),
+                None,
+            ),
+        }
+    }
+
+    fn keepalive(&mut self) {
+        debug!("keep daemon sessions alive");
+        for (_, backend) in self.backends.values_mut() {
+            if let Err(err) = backend.keepalive() {
+                warn!("cannot keep backend session alive: {:#}", err);
+            }
+        }
+        for sender in self.senders.values_mut() {
+            if let Err(err) = sender.keepalive() {
+                warn!("cannot keep sender session alive: {:#}", err);
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        for (_, backend) in self.backends.values_mut() {
+            backend.logout().ok();
+        }
+        for sender in self.senders.values_mut() {
+            sender.close().ok();
+        }
+    }
+}
+
+/// Make the given account non-interactive: the daemon cannot prompt on behalf of its clients, so
+/// read receipts are left for the next local read instead of being asked for.
+fn non_interactive(mut account: Account) -> Account {
+    if account.read_receipt_policy == ReceiptPolicy::Ask {
+        account.read_receipt_policy = ReceiptPolicy::Never;
+    }
+    account
+}
+
+/// Serve the commands on the daemon socket until the daemon is stopped.
+#[cfg(unix)]
+pub fn start(
+    config_path: Option<&str>,
+    config: &Config,
+    create_app: CreateApp,
+    dispatch: Dispatch,
+    output: &OutputService,
+) -> Result<()> {
+    use anyhow::Context;
+    use std::{
+        fs, io,
+        os::unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+        thread,
+        time::Instant,
+    };
+
+    use crate::domain::daemon::{self, PARSE_ERROR};
+
+    let mut names: Vec<&String> = config.accounts.keys().collect();
+    names.sort();
+    let accounts = names
+        .into_iter()
+        .map(|name| Account::try_from((config, Some(name.as_str()))).map(non_interactive))
+        .collect::<Result<Vec<_>>>()?;
+
+    daemon::create_socket_dir()?;
+    let path = daemon::socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        return Err(anyhow!(
+            "cannot start daemon: it is already running at {:?}",
+            path
+        ));
+    }
+    // The socket of a daemon that did not stop cleanly is stale.
+    if path.exists() {
+        fs::remove_file(&path).context(format!("cannot remove daemon socket {:?}", path))?;
+    }
+    let listener =
+        UnixListener::bind(&path).context(format!("cannot bind daemon socket {:?}", path))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+        .context(format!("cannot restrict daemon socket {:?}", path))?;
+    listener
+        .set_nonblocking(true)
+        .context("cannot set daemon socket non-blocking")?;
+    output.print(format!("Daemon listening at {:?}", path))?;
+
+    let mut daemon = Daemon {
+        config_path,
+        config,
+        accounts: &accounts,
+        create_app,
+        dispatch,
+        backends: HashMap::new(),
+        senders: HashMap::new(),
+    };
+    // Ctrl-C stops the daemon cleanly, its sessions logged out.
+    let _cancellable = output_interrupt::cancellable();
+    let mut last_keepalive = Instant::now();
+    loop {
+        if output_interrupt::is_interrupted() {
+            break;
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream
+                    .set_nonblocking(false)
+                    .context("cannot set daemon connection blocking")?;
+                let res = match daemon::read::<Request, _>(&stream) {
+                    Ok(req) => {
+                        debug!("daemon request: {}", req.method);
+                        let shutdown = req.method == "shutdown";
+                        let res = daemon.handle(req);
+                        if shutdown {
+                            daemon::write(&stream, &res).ok();
+                            break;
+                        }
+                        res
+                    }
+                    Err(err) => Response::error(
+                        serde_json::Value::Null,
+                        PARSE_ERROR,
+                        format!("{:#}", err),
+                        None,
+                    ),
+                };
+                if let Err(err) = daemon::write(&stream, &res) {
+                    warn!("{:#}", err);
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+                    daemon.keepalive();
+                    last_keepalive = Instant::now();
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(err) => return Err(err).context("cannot accept daemon connection"),
+        }
+    }
+
+    daemon.close();
+    fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn start(
+    _config_path: Option<&str>,
+    _config: &Config,
+    _create_app: CreateApp,
+    _dispatch: Dispatch,
+    _output: &OutputService,
+) -> Result<()> {
+    Err(anyhow!(
+        "cannot start daemon: not supported on this platform"
+    ))
+}
+
+/// Stop the running daemon.
+pub fn stop(output: &OutputService) -> Result<()> {
+    let res = daemon_client::request(&Request::shutdown())?;
+    if let Some(err) = res.error {
+        return Err(anyhow!("cannot stop daemon: {}", err.message));
+    }
+    output.print("Daemon successfully stopped")
+}
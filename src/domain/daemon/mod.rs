@@ -0,0 +1,11 @@
+//! Module related to the daemon.
+
+#[cfg(feature = "cli")]
+pub mod daemon_arg;
+#[cfg(feature = "cli")]
+pub mod daemon_client;
+#[cfg(feature = "cli")]
+pub mod daemon_handler;
+
+pub mod rpc_entity;
+pub use rpc_entity::*;
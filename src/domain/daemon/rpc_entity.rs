@@ -0,0 +1,207 @@
+//! Module related to the daemon protocol.
+//!
+//! The daemon speaks JSON-RPC 2.0 over a unix socket, one request per connection and one
+//! message per line. The `run` method runs the given command line arguments and returns their
+//! output, the `shutdown` method stops the daemon.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{config::system_mode, output::output_error::ErrorJson};
+
+const VERSION: &str = "2.0";
+
+/// The request cannot be parsed.
+pub const PARSE_ERROR: i64 = -32700;
+/// The method does not exist.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// The params of the method are missing.
+pub const INVALID_PARAMS: i64 = -32602;
+/// The command failed, the data of the error holding its kind.
+pub const COMMAND_FAILED: i64 = -32000;
+/// The command is not served by the daemon, eg. because it runs another config.
+pub const NOT_SERVED: i64 = -32001;
+
+/// Get the directory of the socket of the daemon. The system temporary directory being shared,
+/// the directory is per user there.
+fn socket_dir() -> PathBuf {
+    if env::var("XDG_RUNTIME_DIR").is_ok() {
+        return system_mode::runtime_dir();
+    }
+    let user = env::var("USER").unwrap_or_default();
+    env::temp_dir().join(format!("himalaya-daemon-{}", user))
+}
+
+/// Get the path of the socket of the daemon.
+pub fn socket_path() -> PathBuf {
+    socket_dir().join("daemon.sock")
+}
+
+#[cfg(unix)]
+fn check_socket_dir(dir: &Path) -> Result<()> {
+    system_mode::check_owned(dir)?;
+    system_mode::check_private(dir)
+}
+
+/// Create the directory of the socket of the daemon, accessible by the user only, so that the
+/// socket is out of reach of other users from the moment it is bound.
+#[cfg(unix)]
+pub fn create_socket_dir() -> Result<()> {
+    let dir = socket_dir();
+    system_mode::create_private_dir(&dir)?;
+    check_socket_dir(&dir).context("cannot use daemon socket directory")
+}
+
+/// Check that the given socket of the daemon and its directory belong to the user, so that
+/// clients never talk to a socket planted by another user.
+#[cfg(unix)]
+pub fn check_socket(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        check_socket_dir(dir)?;
+    }
+    system_mode::check_owned(path).context("cannot trust daemon socket")
+}
+
+/// Represents the params of the `run` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunParams {
+    /// The command line arguments, the program name excluded.
+    pub args: Vec<String>,
+    /// The config path given to the command, if any.
+    pub config: Option<String>,
+}
+
+/// Represents a request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<RunParams>,
+}
+
+impl Request {
+    fn new(method: &str, params: Option<RunParams>) -> Self {
+        Self {
+            jsonrpc: VERSION.to_owned(),
+            id: Value::from(1),
+            method: method.to_owned(),
+            params,
+        }
+    }
+
+    pub fn run(params: RunParams) -> Self {
+        Self::new("run", Some(params))
+    }
+
+    pub fn shutdown() -> Self {
+        Self::new("shutdown", None)
+    }
+}
+
+/// Represents the result of the `run` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunResult {
+    pub output: String,
+}
+
+/// Represents an error response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<ErrorJson>,
+}
+
+/// Represents a response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<RunResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn ok(id: Value, output: String) -> Self {
+        Self {
+            jsonrpc: VERSION.to_owned(),
+            id,
+            result: Some(RunResult { output }),
+            error: None,
+        }
+    }
+
+    pub fn error<S: ToString>(id: Value, code: i64, message: S, data: Option<ErrorJson>) -> Self {
+        Self {
+            jsonrpc: VERSION.to_owned(),
+            id,
+            result: None,
+            error: Some(RpcError {
+                code,
+                message: message.to_string(),
+                data,
+            }),
+        }
+    }
+}
+
+/// Read a message from the given stream.
+pub fn read<T: DeserializeOwned, R: Read>(stream: R) -> Result<T> {
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("cannot read daemon message")?;
+    if line.is_empty() {
+        return Err(anyhow!("cannot read daemon message: connection closed"));
+    }
+    serde_json::from_str(&line).context("cannot parse daemon message")
+}
+
+/// Write the given message to the given stream.
+pub fn write<T: Serialize, W: Write>(mut stream: W, msg: &T) -> Result<()> {
+    let mut line = serde_json::to_string(msg).context("cannot serialize daemon message")?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .and_then(|()| stream.flush())
+        .context("cannot write daemon message")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_exchange_messages() {
+        let req = Request::run(RunParams {
+            args: vec![String::from("read"), String::from("42")],
+            config: None,
+        });
+        let mut buf = vec![];
+        write(&mut buf, &req).unwrap();
+        assert!(buf.ends_with(b"}\n"));
+        let req: Request = read(buf.as_slice()).unwrap();
+        assert_eq!("run", req.method);
+        assert_eq!(vec!["read", "42"], req.params.unwrap().args);
+
+        let res: Response = read(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32001,"message":"not served"}}"#.as_bytes(),
+        )
+        .unwrap();
+        assert!(res.result.is_none());
+        assert_eq!(NOT_SERVED, res.error.unwrap().code);
+        assert!(read::<Response, _>("".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Module related to filter CLI.
+//!
+//! This module provides subcommands and a command matcher related to mail filtering.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Target<'a> = Option<&'a str>;
+
+/// Filter commands.
+pub enum Command<'a> {
+    /// Apply the filter rules to the messages of the given mailbox, or of the selected one.
+    Apply(Target<'a>),
+
+    /// Print the filter rules as a Sieve script.
+    ExportSieve,
+}
+
+/// Filter command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("filter") {
+        if let Some(m) = m.subcommand_matches("apply") {
+            debug!("apply command matched");
+            let target = m.value_of("target");
+            trace!("target: {:?}", target);
+            return Ok(Some(Command::Apply(target)));
+        }
+
+        if m.subcommand_matches("export-sieve").is_some() {
+            debug!("export sieve command matched");
+            return Ok(Some(Command::ExportSieve));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Filter subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("filter")
+        .about("Manages filter rules")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Applies the filter rules to the existing messages of a mailbox")
+                .arg(
+                    Arg::with_name("target")
+                        .help("Specifies the mailbox to filter, defaults to the selected one")
+                        .value_name("TARGET"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export-sieve")
+                .about("Prints the filter rules as a Sieve script, to run them server-side"),
+        )]
+}
@@ -0,0 +1,155 @@
+//! Module related to filter rules.
+//!
+//! This module exposes the filter rules read from the config, how they match messages, and their
+//! conversion to a [Sieve] script so that the server can run them on incoming mail.
+//!
+//! [Sieve]: https://datatracker.ietf.org/doc/html/rfc5228
+
+use mailparse::{MailHeader, MailHeaderMap};
+use serde::Deserialize;
+
+/// Represent a filter rule. A rule matches a message when all its conditions match, a rule
+/// without any condition never matches. Conditions are case-insensitive substring matches.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FilterRule {
+    /// Match messages whose From header contains the given text.
+    pub from: Option<String>,
+    /// Match messages whose Subject header contains the given text.
+    pub subject: Option<String>,
+    /// Match messages whose List-Id header contains the given text.
+    pub list_id: Option<String>,
+    #[serde(flatten)]
+    pub action: FilterAction,
+}
+
+/// Represent the action applied to the messages matching a filter rule.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum FilterAction {
+    /// Move messages to the given mailbox.
+    Move { mailbox: String },
+    /// Add the given flags to messages.
+    Flag { flags: Vec<String> },
+    /// Delete messages, like the `delete` command does.
+    Delete,
+}
+
+impl FilterRule {
+    fn conditions(&self) -> Vec<(&str, &str)> {
+        let mut conditions = vec![];
+        if let Some(ref from) = self.from {
+            conditions.push(("From", from.as_str()));
+        }
+        if let Some(ref subject) = self.subject {
+            conditions.push(("Subject", subject.as_str()));
+        }
+        if let Some(ref list_id) = self.list_id {
+            conditions.push(("List-Id", list_id.as_str()));
+        }
+        conditions
+    }
+
+    /// Check if the message with the given headers matches the rule.
+    pub fn matches(&self, headers: &[MailHeader]) -> bool {
+        let conditions = self.conditions();
+        !conditions.is_empty()
+            && conditions.iter().all(|(key, val)| {
+                headers
+                    .get_first_value(key)
+                    .map(|header| header.to_lowercase().contains(&val.to_lowercase()))
+                    .unwrap_or(false)
+            })
+    }
+}
+
+/// Quote a string for a Sieve script.
+fn sieve_str(s: &str) -> String {
+    format!(r#""{}""#, s.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// Convert a flag name, as given to the `flag` commands, to an IMAP flag.
+fn sieve_flag(flag: &str) -> String {
+    let lower = flag.to_lowercase();
+    match lower.as_str() {
+        "answered" | "deleted" | "draft" | "flagged" | "seen" => {
+            format!("\\{}{}", lower[..1].to_uppercase(), &lower[1..])
+        }
+        _ => flag.to_owned(),
+    }
+}
+
+/// Convert the filter rules to a Sieve script. The first matching rule wins, like when applying
+/// them with himalaya. Deleted messages go to the given trash folder, or are discarded.
+pub fn to_sieve(rules: &[FilterRule], trash_folder: Option<&str>) -> String {
+    let mut script = String::from("require [\"fileinto\", \"imap4flags\"];\n");
+
+    for rule in rules {
+        let conditions = rule.conditions();
+        if conditions.is_empty() {
+            continue;
+        }
+
+        let tests: Vec<String> = conditions
+            .iter()
+            .map(|(key, val)| match *key {
+                "From" => format!("address :contains \"from\" {}", sieve_str(val)),
+                key => format!(
+                    "header :contains {} {}",
+                    sieve_str(&key.to_lowercase()),
+                    sieve_str(val)
+                ),
+            })
+            .collect();
+        let action = match (&rule.action, trash_folder) {
+            (FilterAction::Move { mailbox }, _) => format!("fileinto {};", sieve_str(mailbox)),
+            (FilterAction::Flag { flags }, _) => {
+                let flags: Vec<String> = flags
+                    .iter()
+                    .map(|flag| sieve_str(&sieve_flag(flag)))
+                    .collect();
+                format!("addflag [{}];", flags.join(", "))
+            }
+            (FilterAction::Delete, Some(trash)) => format!("fileinto {};", sieve_str(trash)),
+            (FilterAction::Delete, None) => String::from("discard;"),
+        };
+
+        script.push_str(&format!(
+            "\nif allof ({}) {{\n    {}\n    stop;\n}}\n",
+            tests.join(", "),
+            action
+        ));
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_match_and_export_rules() {
+        let rule = FilterRule {
+            from: Some(String::from("@news.org")),
+            subject: None,
+            list_id: Some(String::from("weekly")),
+            action: FilterAction::Flag {
+                flags: vec![String::from("seen")],
+            },
+        };
+        let (headers, _) = mailparse::parse_headers(
+            b"From: News <hello@news.org>\r\nList-Id: <Weekly.news.org>\r\n\r\n",
+        )
+        .unwrap();
+        assert!(rule.matches(&headers));
+        let (headers, _) = mailparse::parse_headers(b"From: hello@news.org\r\n\r\n").unwrap();
+        assert!(!rule.matches(&headers));
+
+        let script = to_sieve(&[rule], None);
+        assert!(script.contains(
+            r#"if allof (address :contains "from" "@news.org", header :contains "list-id" "weekly")"#
+        ));
+        assert!(script.contains(r#"addflag ["\\Seen"];"#));
+    }
+}
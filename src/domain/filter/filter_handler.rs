@@ -0,0 +1,159 @@
+//! Module related to filter handling.
+//!
+//! This module gathers all filter actions triggered by the CLI.
+
+use anyhow::{Context, Result};
+use imap::types::Flag;
+use log::{debug, trace, warn};
+use std::convert::TryFrom;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{build_backend, Backend},
+        filter::{self, FilterAction},
+        mbox::Mbox,
+        msg::{msg_hold, Flags},
+    },
+    output::{OutputService, OutputServiceInterface},
+};
+
+/// Apply the action of a rule to the given messages.
+fn apply_action(
+    action: &FilterAction,
+    ids: &[u32],
+    mbox: &Mbox,
+    account: &Account,
+    backend: &mut dyn Backend,
+) -> Result<bool> {
+    let join = |ids: &[u32]| {
+        ids.iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut ids = ids.to_vec();
+
+    // Messages on hold are never deleted by filters.
+    if let FilterAction::Delete = action {
+        let held_ids = msg_hold::held_ids(&join(&ids), false, account, backend)?;
+        if !held_ids.is_empty() {
+            warn!("skip deletion of messages on hold {:?}", held_ids);
+            ids.retain(|id| !held_ids.contains(id));
+            if ids.is_empty() {
+                return Ok(false);
+            }
+        }
+    }
+
+    let ids = join(&ids);
+    debug!("apply filter action {:?} to messages {}", action, ids);
+
+    match action {
+        // Messages already in the target mailbox stay there.
+        FilterAction::Move { mailbox } if mailbox == &mbox.name => (),
+        FilterAction::Move { mailbox } => {
+            let target = Mbox::from(mailbox.as_str());
+            backend.create_mbox(&target)?;
+            backend.move_msg(&ids, &target)?;
+        }
+        FilterAction::Flag { flags } => {
+            let flags = Flags::from(flags.iter().map(String::as_str).collect::<Vec<_>>());
+            backend.add_flags(&ids, &flags)?;
+        }
+        FilterAction::Delete => match account.trash_folder {
+            Some(ref trash) if trash != &mbox.name => {
+                let trash = Mbox::from(trash.as_str());
+                backend.create_mbox(&trash)?;
+                backend.move_msg(&ids, &trash)?;
+            }
+            _ => {
+                let flags = Flags::try_from(vec![Flag::Seen, Flag::Deleted])?;
+                backend.add_flags(&ids, &flags)?;
+                return Ok(true);
+            }
+        },
+    }
+
+    Ok(false)
+}
+
+/// Apply the filter rules to the messages of the given mailbox, or of the selected one. Each
+/// message gets the action of the first rule it matches.
+pub fn apply(
+    target: Option<&str>,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let mbox = Mbox::from(target.unwrap_or(&mbox.name));
+    // Messages are identified by UID, so that moving some does not shift the others.
+    let mut backend = build_backend(account, &mbox, false);
+
+    let mut matched: Vec<(u32, usize)> = vec![];
+    for (id, raw_headers) in backend.get_raw_headers("1:*")? {
+        let (headers, _) = mailparse::parse_headers(&raw_headers)
+            .context(format!("cannot parse headers of message {}", id))?;
+        if let Some(rule) = account
+            .filters
+            .iter()
+            .position(|rule| rule.matches(&headers))
+        {
+            matched.push((id, rule));
+        }
+    }
+    trace!("matching messages: {:?}", matched);
+
+    // Flags do not move messages, they are applied all at once per rule.
+    for (i, rule) in account.filters.iter().enumerate() {
+        if let FilterAction::Flag { .. } = rule.action {
+            let ids: Vec<u32> = matched
+                .iter()
+                .filter(|(_, j)| *j == i)
+                .map(|(id, _)| *id)
+                .collect();
+            if !ids.is_empty() {
+                apply_action(&rule.action, &ids, &mbox, account, backend.as_mut())?;
+            }
+        }
+    }
+
+    // Other actions run from the most recent message to the oldest one, by consecutive runs of
+    // the same rule: backends without UIDs only shift the messages already handled.
+    let mut others: Vec<(u32, usize)> = matched
+        .iter()
+        .filter(|(_, i)| !matches!(account.filters[*i].action, FilterAction::Flag { .. }))
+        .cloned()
+        .collect();
+    others.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut expunge = false;
+    let mut begin = 0;
+    while begin < others.len() {
+        let rule = others[begin].1;
+        let end = others[begin..]
+            .iter()
+            .position(|(_, i)| *i != rule)
+            .map(|len| begin + len)
+            .unwrap_or_else(|| others.len());
+        let ids: Vec<u32> = others[begin..end].iter().map(|(id, _)| *id).collect();
+        let action = &account.filters[rule].action;
+        expunge |= apply_action(action, &ids, &mbox, account, backend.as_mut())?;
+        begin = end;
+    }
+    if expunge {
+        backend.expunge()?;
+    }
+    backend.logout()?;
+
+    output.print(format!(
+        r#"{} message(s) of folder "{}" successfully filtered"#,
+        matched.len(),
+        mbox
+    ))
+}
+
+/// Print the filter rules as a Sieve script.
+pub fn export_sieve(account: &Account, output: &OutputService) -> Result<()> {
+    let script = filter::to_sieve(&account.filters, account.trash_folder.as_deref());
+    output.print(script)
+}
@@ -0,0 +1,9 @@
+//! Module related to mail filtering.
+
+#[cfg(feature = "cli")]
+pub mod filter_arg;
+#[cfg(feature = "cli")]
+pub mod filter_handler;
+
+pub mod filter_entity;
+pub use filter_entity::*;
@@ -0,0 +1,180 @@
+//! Module related to Microsoft Graph authentication.
+//!
+//! This module implements the [OAuth2 device code flow] and keeps the refresh token in the
+//! account cache directory, so the user only needs to log in once.
+//!
+//! [OAuth2 device code flow]: https://docs.microsoft.com/en-us/azure/active-directory/develop/v2-oauth2-device-code
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use serde::Deserialize;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use crate::config::Account;
+
+const AUTHORITY_URL: &str = "https://login.microsoftonline.com";
+const SCOPES: &str = "offline_access https://graph.microsoft.com/Mail.ReadWrite https://graph.microsoft.com/Mail.Send";
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    message: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tokens {
+    access_token: String,
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+fn refresh_token_path(account: &Account) -> Result<PathBuf> {
+    Ok(account.cache_dir()?.join("graph-refresh-token"))
+}
+
+fn token_url(account: &Account) -> String {
+    format!(
+        "{}/{}/oauth2/v2.0/token",
+        AUTHORITY_URL, account.graph_tenant
+    )
+}
+
+fn client_id(account: &Account) -> Result<&str> {
+    account.graph_client_id.as_deref().ok_or_else(|| {
+        anyhow!(
+            r#"cannot find "graph-client-id" in account "{}""#,
+            account.name
+        )
+    })
+}
+
+fn save_refresh_token(account: &Account, tokens: &Tokens) -> Result<()> {
+    if let Some(ref refresh_token) = tokens.refresh_token {
+        let path = refresh_token_path(account)?;
+        let mut opts = OpenOptions::new();
+        opts.write(true).create(true).truncate(true);
+        // The token is never readable by others, not even before it is written.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut file = opts
+            .open(&path)
+            .context(format!("cannot open Graph refresh token file {:?}", path))?;
+
+        // The mode only applies to new files, older ones are restricted too.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))
+                .context(format!("cannot restrict permissions of {:?}", path))?;
+        }
+        file.write_all(refresh_token.as_bytes())
+            .context(format!("cannot save Graph refresh token at {:?}", path))?;
+    }
+    Ok(())
+}
+
+fn refresh(account: &Account, refresh_token: &str) -> Result<Tokens> {
+    debug!("refresh Graph access token");
+    ureq::post(&token_url(account))
+        .send_form(&[
+            ("client_id", client_id(account)?),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("scope", SCOPES),
+        ])
+        .context("cannot refresh Graph access token")?
+        .into_json()
+        .context("cannot parse Graph tokens")
+}
+
+fn login(account: &Account) -> Result<Tokens> {
+    debug!("start Graph device code flow");
+    let code: DeviceCode = ureq::post(&format!(
+        "{}/{}/oauth2/v2.0/devicecode",
+        AUTHORITY_URL, account.graph_tenant
+    ))
+    .send_form(&[("client_id", client_id(account)?), ("scope", SCOPES)])
+    .context("cannot request Graph device code")?
+    .into_json()
+    .context("cannot parse Graph device code")?;
+
+    // The message tells the user which URL to visit and which code to enter.
+    eprintln!("{}", code.message);
+
+    let mut interval = code.interval;
+    let mut elapsed = 0;
+    while elapsed < code.expires_in {
+        thread::sleep(Duration::from_secs(interval));
+        elapsed += interval;
+
+        let res = ureq::post(&token_url(account)).send_form(&[
+            ("client_id", client_id(account)?),
+            ("grant_type", DEVICE_CODE_GRANT_TYPE),
+            ("device_code", &code.device_code),
+        ]);
+
+        match res {
+            Ok(res) => return res.into_json().context("cannot parse Graph tokens"),
+            Err(ureq::Error::Status(400, res)) => {
+                let err: TokenError = res.into_json().context("cannot parse Graph error")?;
+                match err.error.as_str() {
+                    "authorization_pending" => continue,
+                    "slow_down" => interval += 5,
+                    _ => {
+                        return Err(anyhow!(
+                            "cannot log in to Graph: {}",
+                            err.error_description.unwrap_or(err.error)
+                        ))
+                    }
+                }
+            }
+            Err(err) => return Err(anyhow!(err).context("cannot log in to Graph")),
+        }
+    }
+
+    Err(anyhow!("cannot log in to Graph: device code expired"))
+}
+
+/// Get a fresh access token, either from the cached refresh token or by starting the device code
+/// flow when no valid refresh token is available. Until a refresh token is cached, the one of
+/// the secrets file is used, if any.
+pub fn access_token(account: &Account) -> Result<String> {
+    let path = refresh_token_path(account)?;
+    let refresh_token = match fs::read_to_string(&path) {
+        Ok(refresh_token) => Some(refresh_token),
+        Err(_) => match account.graph_refresh_token_secret.as_deref() {
+            Some(key) => Some(account.secret(key)?),
+            None => None,
+        },
+    };
+    if let Some(refresh_token) = refresh_token {
+        match refresh(account, refresh_token.trim()) {
+            Ok(tokens) => {
+                save_refresh_token(account, &tokens)?;
+                return Ok(tokens.access_token);
+            }
+            Err(err) => warn!("{:?}, starting a new login", err),
+        }
+    }
+
+    let tokens = login(account)?;
+    save_refresh_token(account, &tokens)?;
+    Ok(tokens.access_token)
+}
@@ -0,0 +1,642 @@
+//! Module related to Microsoft Graph servicing.
+//!
+//! This module exposes services that can interact with Exchange accounts through the [Microsoft
+//! Graph API]: one for managing mailboxes and messages, one for sending messages.
+//!
+//! [Microsoft Graph API]: https://docs.microsoft.com/en-us/graph/api/resources/mail-api-overview
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use imap::types::Flag;
+use log::{debug, trace};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::{TryFrom, TryInto},
+    io::Read,
+};
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{Backend, Sender},
+        graph::graph_auth,
+        mbox::{Mbox, MboxStatus, Mboxes},
+        msg::{msg_addr, Envelope, Envelopes, Flags, Msg},
+    },
+};
+
+const GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+
+/// The maximum number of messages Graph returns per page.
+const PAGE_SIZE: u32 = 1000;
+
+#[derive(Debug, Deserialize)]
+struct GraphList<T> {
+    value: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphFolder {
+    id: String,
+    display_name: String,
+    total_item_count: Option<usize>,
+    unread_item_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphEmailAddress {
+    name: Option<String>,
+    address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphRecipient {
+    email_address: GraphEmailAddress,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphFollowupFlag {
+    flag_status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphMsg {
+    id: String,
+    subject: Option<String>,
+    from: Option<GraphRecipient>,
+    received_date_time: Option<String>,
+    is_read: Option<bool>,
+    flag: Option<GraphFollowupFlag>,
+    has_attachments: Option<bool>,
+}
+
+impl GraphMsg {
+    fn flags(&self) -> Flags {
+        let mut flags = Flags::default();
+        if self.is_read.unwrap_or_default() {
+            flags.insert(Flag::Seen);
+        }
+        if let Some("flagged") = self.flag.as_ref().map(|flag| flag.flag_status.as_str()) {
+            flags.insert(Flag::Flagged);
+        }
+        flags
+    }
+
+    fn into_envelope(self, id: u32) -> Envelope {
+        let flags = self.flags();
+        let sender = self
+            .from
+            .map(|from| from.email_address)
+            .and_then(|addr| addr.name.or(addr.address))
+            .unwrap_or_default();
+        let date = self
+            .received_date_time
+            .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+            .map(|date| date.with_timezone(&Local).naive_local().to_string());
+
+        Envelope {
+            id,
+            flags,
+            labels: vec![],
+            thread_id: None,
+            subject: self.subject.unwrap_or_default(),
+            sender,
+            date,
+            display_date: None,
+            size: None,
+            has_attachment: self.has_attachments.unwrap_or_default(),
+            list: None,
+            spam_score: None,
+        }
+    }
+}
+
+/// Map well-known mailbox names to Graph well-known folder names.
+fn well_known_folder(mbox: &str) -> Option<&'static str> {
+    match mbox.to_lowercase().as_str() {
+        "inbox" => Some("inbox"),
+        "sent" | "sent items" | "sentitems" => Some("sentitems"),
+        "drafts" => Some("drafts"),
+        "trash" | "deleted items" | "deleteditems" => Some("deleteditems"),
+        "junk" | "spam" | "junk email" | "junkemail" => Some("junkemail"),
+        "archive" => Some("archive"),
+        _ => None,
+    }
+}
+
+/// Expand a sequence set like `1:3,5` into sequence numbers, `*` standing for the last message.
+fn expand_seq_set(seq_set: &str, last_seq: u32) -> Result<Vec<u32>> {
+    let parse = |seq: &str| -> Result<u32> {
+        if seq == "*" {
+            Ok(last_seq)
+        } else {
+            seq.parse()
+                .context(format!(r#"cannot parse sequence number "{}""#, seq))
+        }
+    };
+
+    let mut seqs = vec![];
+    for range in seq_set.split(',') {
+        match range.split_once(':') {
+            Some((begin, end)) => {
+                let (begin, end) = (parse(begin)?, parse(end)?);
+                seqs.extend(begin.min(end)..=begin.max(end));
+            }
+            None => seqs.push(parse(range)?),
+        }
+    }
+    Ok(seqs)
+}
+
+fn get<T: DeserializeOwned>(token: &str, path: &str, query: &[(&str, &str)]) -> Result<T> {
+    debug!("GET {}", path);
+    let req = query.iter().fold(
+        ureq::get(&format!("{}{}", GRAPH_URL, path))
+            .set("Authorization", &format!("Bearer {}", token)),
+        |req, (key, val)| req.query(key, val),
+    );
+    req.call()
+        .context(format!("cannot send Graph request {}", path))?
+        .into_json()
+        .context(format!("cannot parse Graph response {}", path))
+}
+
+/// Check that the recipients of the envelope are the ones of the headers. Graph delivers MIME
+/// messages to the recipients of their headers, so a message whose envelope differs (Bcc
+/// recipients dropped from the headers, bounce recipients) would reach the wrong people.
+fn check_recipients(envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()> {
+    let header_rcpts: HashSet<String> = msg_addr::recipients(msg).into_iter().collect();
+    let envelope_rcpts: HashSet<String> = envelope
+        .to()
+        .iter()
+        .map(|addr| addr.to_string().to_lowercase())
+        .collect();
+    if header_rcpts != envelope_rcpts {
+        return Err(anyhow!(
+            "cannot send message via Graph: its recipients differ from the ones of its headers (Bcc or bounce recipients are not supported)"
+        ));
+    }
+    Ok(())
+}
+
+fn send_mime(token: &str, path: &str, msg: &[u8]) -> Result<ureq::Response> {
+    debug!("POST {}", path);
+    ureq::post(&format!("{}{}", GRAPH_URL, path))
+        .set("Authorization", &format!("Bearer {}", token))
+        .set("Content-Type", "text/plain")
+        .send_string(&base64::encode(msg))
+        .context(format!("cannot send Graph request {}", path))
+}
+
+pub struct GraphService<'a> {
+    account: &'a Account,
+    mbox: &'a Mbox,
+    token: Option<String>,
+    /// Folder ids indexed by mailbox name.
+    folders: HashMap<String, GraphFolder>,
+}
+
+impl<'a> GraphService<'a> {
+    fn token(&mut self) -> Result<String> {
+        if self.token.is_none() {
+            self.token = Some(graph_auth::access_token(self.account)?);
+        }
+        self.token
+            .to_owned()
+            .ok_or_else(|| anyhow!("cannot get Graph access token"))
+    }
+
+    fn folder(&mut self, mbox: &Mbox) -> Result<&GraphFolder> {
+        if !self.folders.contains_key(&mbox.name) {
+            let token = self.token()?;
+            let folder: GraphFolder = match well_known_folder(&mbox.name) {
+                Some(name) => get(&token, &format!("/me/mailFolders/{}", name), &[])?,
+                None => {
+                    let filter = format!("displayName eq '{}'", mbox.name.replace('\'', "''"));
+                    get::<GraphList<GraphFolder>>(
+                        &token,
+                        "/me/mailFolders",
+                        &[("$filter", &filter)],
+                    )?
+                    .value
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!(r#"cannot find mailbox "{}""#, mbox.name))?
+                }
+            };
+            trace!("folder: {:?}", folder);
+            self.folders.insert(mbox.name.to_owned(), folder);
+        }
+
+        self.folders
+            .get(&mbox.name)
+            .ok_or_else(|| anyhow!(r#"cannot find mailbox "{}""#, mbox.name))
+    }
+
+    /// Get the number of messages of the current mailbox. The folder is fetched again, so that
+    /// sequence numbers do not drift in long-lived sessions after messages are added or removed.
+    fn last_seq(&mut self) -> Result<u32> {
+        let mbox = self.mbox;
+        self.folders.remove(&mbox.name);
+        Ok(self.folder(mbox)?.total_item_count.unwrap_or_default() as u32)
+    }
+
+    /// Find Graph message ids matching the given sequence set. Like IMAP, sequence numbers are
+    /// attributed from the oldest received message (1) to the most recent one. The ids of the
+    /// range covered by the set are fetched at once, by pages.
+    fn find_ids(&mut self, seq_set: &str) -> Result<Vec<String>> {
+        let token = self.token()?;
+        let last_seq = self.last_seq()?;
+        let mbox = self.mbox;
+        let folder_id = self.folder(mbox)?.id.to_owned();
+
+        let seqs = expand_seq_set(seq_set, last_seq)?;
+        let (first, last) = match (seqs.iter().min(), seqs.iter().max()) {
+            (Some(first), Some(last)) => ((*first).max(1), *last),
+            _ => return Ok(vec![]),
+        };
+        let mut range_ids: Vec<String> = vec![];
+        while first + (range_ids.len() as u32) <= last {
+            let skip = first - 1 + range_ids.len() as u32;
+            let top = (last + 1 - first - range_ids.len() as u32).min(PAGE_SIZE);
+            let page = get::<GraphList<GraphMsg>>(
+                &token,
+                &format!("/me/mailFolders/{}/messages", folder_id),
+                &[
+                    ("$orderby", "receivedDateTime asc"),
+                    ("$select", "id"),
+                    ("$skip", &skip.to_string()),
+                    ("$top", &top.to_string()),
+                ],
+            )?
+            .value;
+            if page.is_empty() {
+                break;
+            }
+            range_ids.extend(page.into_iter().map(|msg| msg.id));
+        }
+
+        seqs.into_iter()
+            .map(|seq| {
+                range_ids
+                    .get((seq.max(1) - first) as usize)
+                    .cloned()
+                    .ok_or_else(|| anyhow!(r#"cannot find message "{}""#, seq))
+            })
+            .collect()
+    }
+
+    fn get_raw_msg_by_id(&mut self, id: &str) -> Result<Vec<u8>> {
+        let token = self.token()?;
+        debug!("GET /me/messages/{}/$value", id);
+        let mut raw_msg = vec![];
+        ureq::get(&format!("{}/me/messages/{}/$value", GRAPH_URL, id))
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+            .context(format!(r#"cannot fetch message "{}""#, id))?
+            .into_reader()
+            .read_to_end(&mut raw_msg)
+            .context(format!(r#"cannot read message "{}""#, id))?;
+        Ok(raw_msg)
+    }
+
+    /// Move the given messages to Deleted Items, as Graph has no deleted flag. Messages already
+    /// in Deleted Items are deleted for good.
+    fn delete_msgs(&mut self, ids: &[String]) -> Result<()> {
+        let token = self.token()?;
+        let in_trash = well_known_folder(&self.mbox.name) == Some("deleteditems");
+        for id in ids {
+            if in_trash {
+                debug!("DELETE /me/messages/{}", id);
+                ureq::delete(&format!("{}/me/messages/{}", GRAPH_URL, id))
+                    .set("Authorization", &format!("Bearer {}", token))
+                    .call()
+                    .context(format!(r#"cannot delete message "{}""#, id))?;
+            } else {
+                debug!("POST /me/messages/{}/move", id);
+                ureq::post(&format!("{}/me/messages/{}/move", GRAPH_URL, id))
+                    .set("Authorization", &format!("Bearer {}", token))
+                    .send_json(json!({ "destinationId": "deleteditems" }))
+                    .context(format!(r#"cannot move message "{}" to Deleted Items"#, id))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_msgs(&mut self, seq_set: &str, patch: serde_json::Value) -> Result<()> {
+        let token = self.token()?;
+        for id in self.find_ids(seq_set)? {
+            debug!("PATCH /me/messages/{}", id);
+            ureq::patch(&format!("{}/me/messages/{}", GRAPH_URL, id))
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(patch.to_owned())
+                .context(format!(r#"cannot update message "{}""#, id))?;
+        }
+        Ok(())
+    }
+
+    fn update_flags(&mut self, seq_set: &str, flags: &Flags, enabled: bool) -> Result<()> {
+        let mut patch = serde_json::Map::new();
+        if flags.contains(&Flag::Seen) {
+            patch.insert("isRead".into(), json!(enabled));
+        }
+        if flags.contains(&Flag::Flagged) {
+            let status = if enabled { "flagged" } else { "notFlagged" };
+            patch.insert("flag".into(), json!({ "flagStatus": status }));
+        }
+        if !patch.is_empty() {
+            self.update_msgs(seq_set, serde_json::Value::Object(patch))?;
+        }
+
+        // Deleted messages are moved right away: they cannot be flagged back as not deleted.
+        if enabled && flags.contains(&Flag::Deleted) {
+            let ids = self.find_ids(seq_set)?;
+            self.delete_msgs(&ids)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Backend for GraphService<'a> {
+    fn list_mboxes(&mut self) -> Result<Mboxes> {
+        let token = self.token()?;
+        let folders: GraphList<GraphFolder> = get(&token, "/me/mailFolders", &[("$top", "250")])?;
+        Ok(Mboxes(
+            folders
+                .value
+                .iter()
+                .map(|folder| Mbox {
+                    delim: String::from("/"),
+                    ..Mbox::from(folder.display_name.as_str())
+                })
+                .collect(),
+        ))
+    }
+
+    fn get_mbox_status(&mut self, mbox: &Mbox) -> Result<MboxStatus> {
+        // The cached folder may be outdated, the status being polled from long-lived sessions.
+        self.folders.remove(&mbox.name);
+        let folder = self.folder(mbox)?;
+        Ok(MboxStatus {
+            mbox: mbox.name.to_owned(),
+            messages: folder.total_item_count.unwrap_or_default(),
+            unseen: folder.unread_item_count.unwrap_or_default(),
+        })
+    }
+
+    /// Graph folders are not addressed by path: nested names like `Archive/2024` create a
+    /// top-level folder named after the whole path.
+    fn create_mbox(&mut self, mbox: &Mbox) -> Result<()> {
+        if self.folder(mbox).is_ok() {
+            return Ok(());
+        }
+
+        let token = self.token()?;
+        debug!("POST /me/mailFolders");
+        ureq::post(&format!("{}/me/mailFolders", GRAPH_URL))
+            .set("Authorization", &format!("Bearer {}", token))
+            .send_json(json!({ "displayName": mbox.name }))
+            .context(format!(r#"cannot create mailbox "{}""#, mbox.name))?;
+        Ok(())
+    }
+
+    fn list_envelopes(&mut self, page_size: &usize, page: &usize) -> Result<Envelopes> {
+        let token = self.token()?;
+        let last_seq = self.last_seq()? as usize;
+        let mbox = self.mbox;
+        let folder_id = self.folder(mbox)?.id.to_owned();
+
+        let skip = page * page_size;
+        let top = if *page_size > 0 { *page_size } else { last_seq };
+        let msgs: GraphList<GraphMsg> = get(
+            &token,
+            &format!("/me/mailFolders/{}/messages", folder_id),
+            &[
+                ("$orderby", "receivedDateTime desc"),
+                (
+                    "$select",
+                    "id,subject,from,receivedDateTime,isRead,flag,hasAttachments",
+                ),
+                ("$skip", &skip.to_string()),
+                ("$top", &top.to_string()),
+            ],
+        )?;
+
+        Ok(Envelopes(
+            msgs.value
+                .into_iter()
+                .enumerate()
+                .map(|(i, msg)| msg.into_envelope(last_seq.saturating_sub(skip + i) as u32))
+                .collect(),
+        ))
+    }
+
+    fn get_msg(&mut self, seq: &str) -> Result<Msg> {
+        let raw_msg = self.get_raw_msg(seq)?;
+        let parsed_mail =
+            mailparse::parse_mail(&raw_msg).context(format!("cannot parse message {}", seq))?;
+        let mut msg = Msg::try_from(&parsed_mail)?;
+        msg.id = seq.parse().unwrap_or_default();
+        Ok(msg)
+    }
+
+    fn get_raw_msg(&mut self, seq: &str) -> Result<Vec<u8>> {
+        let id = self
+            .find_ids(seq)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!(r#"cannot find message "{}""#, seq))?;
+        self.get_raw_msg_by_id(&id)
+    }
+
+    fn get_msgs(&mut self, seq_range: &str) -> Result<Vec<Msg>> {
+        let last_seq = self.last_seq()?;
+        let seqs = expand_seq_set(seq_range, last_seq)?;
+        let raw_msgs = self.get_raw_msgs(seq_range)?;
+        seqs.into_iter()
+            .zip(raw_msgs)
+            .map(|(seq, raw_msg)| {
+                let parsed_mail = mailparse::parse_mail(&raw_msg)
+                    .context(format!("cannot parse message {}", seq))?;
+                let mut msg = Msg::try_from(&parsed_mail)?;
+                msg.id = seq;
+                Ok(msg)
+            })
+            .collect()
+    }
+
+    fn get_raw_msgs(&mut self, seq_range: &str) -> Result<Vec<Vec<u8>>> {
+        self.find_ids(seq_range)?
+            .iter()
+            .map(|id| self.get_raw_msg_by_id(id))
+            .collect()
+    }
+
+    fn append(&mut self, mbox: &Mbox, msg: Msg) -> Result<()> {
+        let raw_msg: Vec<u8> = (&msg).try_into()?;
+        let flags = msg.flags.to_owned();
+        self.append_raw(mbox, &raw_msg, flags)
+    }
+
+    fn append_raw(&mut self, mbox: &Mbox, msg: &[u8], flags: Flags) -> Result<()> {
+        let token = self.token()?;
+        let folder_id = self.folder(mbox)?.id.to_owned();
+        let created: GraphMsg = send_mime(
+            &token,
+            &format!("/me/mailFolders/{}/messages", folder_id),
+            msg,
+        )?
+        .into_json()
+        .context(format!(r#"cannot append message to "{}""#, mbox.name))?;
+
+        if flags.contains(&Flag::Seen) {
+            ureq::patch(&format!("{}/me/messages/{}", GRAPH_URL, created.id))
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(json!({ "isRead": true }))
+                .context(format!(r#"cannot update message "{}""#, created.id))?;
+        }
+
+        Ok(())
+    }
+
+    fn move_msg(&mut self, seq: &str, mbox: &Mbox) -> Result<()> {
+        let token = self.token()?;
+        let folder_id = self.folder(mbox)?.id.to_owned();
+        for id in self.find_ids(seq)? {
+            debug!("POST /me/messages/{}/move", id);
+            ureq::post(&format!("{}/me/messages/{}/move", GRAPH_URL, id))
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(json!({ "destinationId": folder_id }))
+                .context(format!(
+                    r#"cannot move message "{}" to "{}""#,
+                    id, mbox.name
+                ))?;
+        }
+        Ok(())
+    }
+
+    fn copy_msgs(&mut self, seq_range: &str, mbox: &Mbox) -> Result<()> {
+        let token = self.token()?;
+        let folder_id = self.folder(mbox)?.id.to_owned();
+        for id in self.find_ids(seq_range)? {
+            debug!("POST /me/messages/{}/copy", id);
+            ureq::post(&format!("{}/me/messages/{}/copy", GRAPH_URL, id))
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(json!({ "destinationId": folder_id }))
+                .context(format!(
+                    r#"cannot copy message "{}" to "{}""#,
+                    id, mbox.name
+                ))?;
+        }
+        Ok(())
+    }
+
+    /// Messages flagged as deleted are already moved to Deleted Items, there is nothing left to
+    /// expunge.
+    fn expunge(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_labels(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<String>)>> {
+        let account = self.account;
+        Ok(self
+            .get_flags(seq_range)?
+            .into_iter()
+            .map(|(id, flags)| (id, flags.labels(&account.hold_keyword)))
+            .collect())
+    }
+
+    fn expunge_msgs(&mut self, _seq_range: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn logout(&mut self) -> Result<()> {
+        // The Graph API is stateless, there is no session to close.
+        Ok(())
+    }
+
+    fn add_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        self.update_flags(seq_range, flags, true)
+    }
+
+    fn set_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        let mut removed = Flags::default();
+        for flag in [Flag::Seen, Flag::Flagged, Flag::Deleted].iter() {
+            if !flags.contains(flag) {
+                removed.insert(flag.to_owned());
+            }
+        }
+        self.update_flags(seq_range, &removed, false)?;
+        self.update_flags(seq_range, flags, true)
+    }
+
+    fn remove_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        self.update_flags(seq_range, flags, false)
+    }
+}
+
+impl<'a> From<(&'a Account, &'a Mbox)> for GraphService<'a> {
+    fn from((account, mbox): (&'a Account, &'a Mbox)) -> Self {
+        debug!("init Graph service");
+        Self {
+            account,
+            mbox,
+            token: None,
+            folders: HashMap::new(),
+        }
+    }
+}
+
+pub struct GraphSendService<'a> {
+    account: &'a Account,
+    token: Option<String>,
+}
+
+impl<'a> GraphSendService<'a> {
+    fn token(&mut self) -> Result<String> {
+        if self.token.is_none() {
+            self.token = Some(graph_auth::access_token(self.account)?);
+        }
+        self.token
+            .to_owned()
+            .ok_or_else(|| anyhow!("cannot get Graph access token"))
+    }
+}
+
+impl<'a> Sender for GraphSendService<'a> {
+    fn send(&mut self, msg: &Msg) -> Result<Vec<u8>> {
+        debug!("sending message…");
+        let sendable_msg: lettre::Message = msg.try_into()?;
+        let raw_msg = msg.format_sendable_msg(&sendable_msg);
+        check_recipients(sendable_msg.envelope(), &raw_msg)?;
+        let token = self.token()?;
+        send_mime(&token, "/me/sendMail", &raw_msg)?;
+        Ok(raw_msg)
+    }
+
+    fn send_raw(&mut self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()> {
+        debug!("sending raw message…");
+        check_recipients(envelope, msg)?;
+        let token = self.token()?;
+        send_mime(&token, "/me/sendMail", msg)?;
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a Account> for GraphSendService<'a> {
+    fn from(account: &'a Account) -> Self {
+        debug!("init Graph send service");
+        Self {
+            account,
+            token: None,
+        }
+    }
+}
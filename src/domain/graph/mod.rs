@@ -0,0 +1,7 @@
+//! Module related to the Microsoft Graph API, used by Exchange accounts that do not expose IMAP
+//! nor SMTP.
+
+pub mod graph_auth;
+
+pub mod graph_service;
+pub use graph_service::*;
@@ -0,0 +1,149 @@
+//! Module related to IMAP capability reports.
+//!
+//! The report lists the capabilities advertised by the server, then how himalaya behaves with
+//! each extension it knows about, whether the server supports it or not.
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+use crate::ui::table::{Cell, Row, Table};
+
+/// Define the extensions himalaya branches on: their capability, then its behavior with and
+/// without them.
+const EXTENSIONS: [(&str, &str, &str); 10] = [
+    (
+        "ACL",
+        "access control lists are managed",
+        "access control lists cannot be managed",
+    ),
+    (
+        "APPENDLIMIT",
+        "oversized messages are refused before upload",
+        "oversized messages are refused by the server, after upload",
+    ),
+    (
+        "COMPRESS=DEFLATE",
+        "the traffic is compressed when `imap-compress` is enabled",
+        "the traffic is not compressed",
+    ),
+    (
+        "IDLE",
+        "notify and watch wait for the changes pushed by the server",
+        "notify and watch poll the mailbox",
+    ),
+    (
+        "MOVE",
+        "messages are moved natively",
+        "messages are copied, then deleted and expunged",
+    ),
+    (
+        "NAMESPACE",
+        "mailbox names are resolved against the personal namespace",
+        "mailbox names are used as is",
+    ),
+    ("QUOTA", "quotas are reported", "quotas are not reported"),
+    (
+        "SORT",
+        "messages are sorted server-side",
+        "messages are sorted client-side, after fetching all envelopes",
+    ),
+    (
+        "THREAD=REFERENCES",
+        "messages are threaded server-side",
+        "messages are threaded client-side, after fetching all headers",
+    ),
+    (
+        "UIDPLUS",
+        "moves without MOVE only expunge the moved messages",
+        "moves without MOVE expunge all the deleted messages",
+    ),
+];
+
+/// Represents the behavior of himalaya with an extension.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtensionUse {
+    pub cap: String,
+    pub supported: bool,
+    pub behavior: String,
+}
+
+/// Represents the capabilities of a server, and what himalaya does with them.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CapsReport {
+    pub caps: Vec<String>,
+    pub extensions: Vec<ExtensionUse>,
+}
+
+impl From<Vec<String>> for CapsReport {
+    fn from(caps: Vec<String>) -> Self {
+        let extensions = EXTENSIONS
+            .iter()
+            .map(|(cap, with, without)| {
+                let supported = caps.iter().any(|c| c.eq_ignore_ascii_case(cap));
+                let behavior = if supported { with } else { without };
+                ExtensionUse {
+                    cap: cap.to_string(),
+                    supported,
+                    behavior: behavior.to_string(),
+                }
+            })
+            .collect();
+        Self { caps, extensions }
+    }
+}
+
+impl Display for CapsReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Capabilities: {}", self.caps.join(" "))?;
+        writeln!(f, "\n{}", Table::render(&self.extensions))
+    }
+}
+
+impl Table for ExtensionUse {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("EXTENSION").bold().underline().white())
+            .cell(Cell::new("SUPPORTED").bold().underline().white())
+            .cell(
+                Cell::new("BEHAVIOR")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+    }
+
+    fn row(&self) -> Row {
+        let supported = Cell::new(if self.supported { "yes" } else { "no" });
+        Row::new()
+            .cell(Cell::new(&self.cap).blue())
+            .cell(if self.supported {
+                supported.green()
+            } else {
+                supported.red()
+            })
+            .cell(Cell::new(&self.behavior).shrinkable().white())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_report_extensions() {
+        let report = CapsReport::from(vec![
+            String::from("IDLE"),
+            String::from("IMAP4REV1"),
+            String::from("MOVE"),
+        ]);
+        let ext = |cap: &str| report.extensions.iter().find(|ext| ext.cap == cap).unwrap();
+        assert!(ext("IDLE").supported);
+        assert_eq!("messages are moved natively", ext("MOVE").behavior);
+        assert!(!ext("SORT").supported);
+        assert_eq!(
+            "messages are sorted client-side, after fetching all envelopes",
+            ext("SORT").behavior
+        );
+    }
+}
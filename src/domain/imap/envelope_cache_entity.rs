@@ -0,0 +1,131 @@
+//! Module related to the IMAP envelope cache.
+//!
+//! Listings keep the envelopes they fetched in the mailbox cache, so that listing the
+//! same page again only fetches what changed since: the messages that arrived after the greatest
+//! known UID, and the flags of the listed messages. The cache is bound to the UIDVALIDITY of the
+//! mailbox, and is dropped as soon as it changes.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    config::Account,
+    domain::{
+        msg::{Envelope, Flags},
+        storage::{build_storage, mbox_key},
+    },
+};
+
+/// Define the maximum number of envelopes kept per mailbox, the most recent ones being kept.
+const MAX_ENVELOPES: usize = 10_000;
+
+/// Represents the cached envelopes of a mailbox, indexed by UID.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EnvelopeCache {
+    uid_validity: u32,
+    /// The greatest UID known, new messages are fetched from the next one.
+    last_uid: u32,
+    envelopes: HashMap<u32, Envelope>,
+}
+
+impl EnvelopeCache {
+    /// Load the cached envelopes of the given mailbox. A missing or corrupted cache, or one
+    /// written for another UIDVALIDITY, is treated as empty.
+    pub fn load(account: &Account, mbox: &str, uid_validity: u32) -> Result<Self> {
+        let key = mbox_key(mbox, "envelopes.json");
+        let cache = match build_storage(account)?.get(&key)? {
+            Some(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+                warn!("cannot parse {}: {}", key, err);
+                Self::default()
+            }),
+            None => Self::default(),
+        };
+        if cache.uid_validity != uid_validity {
+            debug!("envelope cache of {:?} is empty or stale", mbox);
+            return Ok(Self {
+                uid_validity,
+                ..Self::default()
+            });
+        }
+        Ok(cache)
+    }
+
+    pub fn save(&mut self, account: &Account, mbox: &str) -> Result<()> {
+        self.prune();
+        let _lock = account.lock_cache()?;
+        let content = serde_json::to_vec(self).context("cannot serialize envelope cache")?;
+        build_storage(account)?
+            .set(&mbox_key(mbox, "envelopes.json"), &content)
+            .context("cannot save envelope cache")
+    }
+
+    pub fn last_uid(&self) -> u32 {
+        self.last_uid
+    }
+
+    pub fn get(&self, uid: u32) -> Option<&Envelope> {
+        self.envelopes.get(&uid)
+    }
+
+    pub fn contains(&self, uid: u32) -> bool {
+        self.envelopes.contains_key(&uid)
+    }
+
+    /// Cache the given envelope, identified by its UID.
+    pub fn insert(&mut self, envelope: Envelope) {
+        self.last_uid = self.last_uid.max(envelope.id);
+        self.envelopes.insert(envelope.id, envelope);
+    }
+
+    /// Refresh the flags of the given cached envelope.
+    pub fn set_flags(&mut self, uid: u32, flags: Flags) {
+        if let Some(envelope) = self.envelopes.get_mut(&uid) {
+            envelope.flags = flags;
+        }
+    }
+
+    /// Drop the oldest envelopes beyond the maximum.
+    fn prune(&mut self) {
+        if self.envelopes.len() <= MAX_ENVELOPES {
+            return;
+        }
+        let mut uids: Vec<u32> = self.envelopes.keys().cloned().collect();
+        uids.sort_unstable();
+        for uid in &uids[..uids.len() - MAX_ENVELOPES] {
+            self.envelopes.remove(uid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_cache_envelopes() {
+        let mut cache = EnvelopeCache::default();
+        for uid in 1..=MAX_ENVELOPES as u32 + 2 {
+            cache.insert(Envelope {
+                id: uid,
+                subject: format!("Subject {}", uid),
+                ..Envelope::default()
+            });
+        }
+        assert_eq!(MAX_ENVELOPES as u32 + 2, cache.last_uid());
+        cache.set_flags(42, Flags::from(vec!["Seen", "$Hold"]));
+        cache.prune();
+        assert!(!cache.contains(1));
+        assert!(!cache.contains(2));
+        assert!(cache.contains(3));
+
+        let cache: EnvelopeCache =
+            serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+        assert_eq!(MAX_ENVELOPES, cache.envelopes.len());
+        assert_eq!(MAX_ENVELOPES as u32 + 2, cache.last_uid());
+        let envelope = cache.get(42).unwrap();
+        assert_eq!("Subject 42", envelope.subject);
+        assert_eq!(2, envelope.flags.len());
+    }
+}
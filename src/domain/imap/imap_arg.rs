@@ -7,23 +7,34 @@ use clap::{App, ArgMatches};
 use log::debug;
 
 type Keepalive = u64;
+type Standby = bool;
+type RawCmd<'a> = &'a str;
 
 /// IMAP commands.
-pub enum Command {
-    /// Start the IMAP notify mode with the give keepalive duration.
-    Notify(Keepalive),
+pub enum Command<'a> {
+    /// Start the IMAP notify mode with the give keepalive duration, optionally keeping a standby
+    /// connection for notification actions.
+    Notify(Keepalive, Standby),
 
     /// Start the IMAP watch mode with the give keepalive duration.
     Watch(Keepalive),
+
+    /// Send the given raw command on the authenticated session.
+    Exec(RawCmd<'a>),
+
+    /// Print the capabilities of the server, and how they are used.
+    Capabilities,
 }
 
 /// IMAP command matcher.
-pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
     if let Some(m) = m.subcommand_matches("notify") {
         debug!("notify command matched");
         let keepalive = clap::value_t_or_exit!(m.value_of("keepalive"), u64);
         debug!("keepalive: {}", keepalive);
-        return Ok(Some(Command::Notify(keepalive)));
+        let standby = m.is_present("standby");
+        debug!("standby: {}", standby);
+        return Ok(Some(Command::Notify(keepalive, standby)));
     }
 
     if let Some(m) = m.subcommand_matches("watch") {
@@ -33,6 +44,20 @@ pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
         return Ok(Some(Command::Watch(keepalive)));
     }
 
+    if let Some(m) = m.subcommand_matches("imap") {
+        if let Some(m) = m.subcommand_matches("exec") {
+            debug!("imap exec command matched");
+            let cmd = m.value_of("cmd").unwrap();
+            debug!("raw command: {}", cmd);
+            return Ok(Some(Command::Exec(cmd)));
+        }
+
+        if m.subcommand_matches("capabilities").is_some() {
+            debug!("imap capabilities command matched");
+            return Ok(Some(Command::Capabilities));
+        }
+    }
+
     Ok(None)
 }
 
@@ -49,6 +74,13 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
                     .long("keepalive")
                     .value_name("SECS")
                     .default_value("500"),
+            )
+            .arg(
+                clap::Arg::with_name("standby")
+                    .help("Keeps a standby connection for notification actions")
+                    .long_help("Keeps a second authenticated connection, so that notification actions run instantly without interrupting the IDLE session. The notify command runs in the background, and it can print `read` or `delete` to apply the action to the notified message.")
+                    .short("s")
+                    .long("standby"),
             ),
         clap::SubCommand::with_name("watch")
             .about("Watches IMAP server changes")
@@ -60,5 +92,24 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
                     .value_name("SECS")
                     .default_value("500"),
             ),
+        clap::SubCommand::with_name("imap")
+            .about("Talks to the IMAP server directly")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("exec")
+                    .about("Sends a raw command on the authenticated session")
+                    .long_about("Sends a raw command as is on the authenticated session, the current mailbox being selected, then prints the untagged response lines untouched. Useful to debug server quirks or to use extensions not supported yet. Commands spanning several lines (literals, IDLE, AUTHENTICATE) are not supported.")
+                    .arg(
+                        clap::Arg::with_name("cmd")
+                            .help("Raw command, without tag")
+                            .value_name("COMMAND")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("capabilities")
+                    .about("Prints the capabilities of the server, and how they are used")
+                    .aliases(&["caps"]),
+            ),
     ]
 }
@@ -0,0 +1,255 @@
+//! Module related to IMAP compression.
+//!
+//! The [COMPRESS] extension compresses the whole connection with raw deflate, in both
+//! directions, from the response to the `COMPRESS DEFLATE` command on. Sessions are opened on a
+//! [`DeflateStream`], that passes the traffic through until its [`DeflateSwitch`] is turned on:
+//! commands and responses then go through the codec, transparently for the session.
+//!
+//! [COMPRESS]: https://datatracker.ietf.org/doc/html/rfc4978
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use imap::extensions::idle::SetReadTimeout;
+use native_tls::TlsStream;
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Define the size of the buffers of compressed data.
+const BUF_SIZE: usize = 16 * 1024;
+
+/// Represents the switch turning the compression of a stream on, once the server accepted it.
+#[derive(Debug, Clone, Default)]
+pub struct DeflateSwitch(Arc<AtomicBool>);
+
+impl DeflateSwitch {
+    pub fn turn_on(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Represents the codec of a compressed stream.
+struct Deflate {
+    compress: Compress,
+    decompress: Decompress,
+    /// Compressed data read from the stream, not decompressed yet.
+    input: Vec<u8>,
+    input_pos: usize,
+    output: Vec<u8>,
+}
+
+impl Deflate {
+    fn new() -> Self {
+        Self {
+            // RFC4978 streams have no zlib header.
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            input: Vec::with_capacity(BUF_SIZE),
+            input_pos: 0,
+            output: Vec::with_capacity(BUF_SIZE),
+        }
+    }
+
+    fn read<R: Read>(&mut self, stream: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Pending output is decompressed before reading more from the stream.
+            let (total_in, total_out) = (self.decompress.total_in(), self.decompress.total_out());
+            let status = self
+                .decompress
+                .decompress(&self.input[self.input_pos..], buf, FlushDecompress::None)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            self.input_pos += (self.decompress.total_in() - total_in) as usize;
+            let len = (self.decompress.total_out() - total_out) as usize;
+            if len > 0 || buf.is_empty() || status == Status::StreamEnd {
+                return Ok(len);
+            }
+
+            self.input.drain(..self.input_pos);
+            self.input_pos = 0;
+            let start = self.input.len();
+            self.input.resize(start + BUF_SIZE, 0);
+            let len = match stream.read(&mut self.input[start..]) {
+                Ok(len) => len,
+                Err(err) => {
+                    self.input.truncate(start);
+                    return Err(err);
+                }
+            };
+            self.input.truncate(start + len);
+            if len == 0 {
+                return Ok(0);
+            }
+        }
+    }
+
+    fn write(&mut self, buf: &[u8], flush: FlushCompress) -> io::Result<usize> {
+        let mut pos = 0;
+        loop {
+            self.output.reserve(BUF_SIZE);
+            let total_in = self.compress.total_in();
+            self.compress
+                .compress_vec(&buf[pos..], &mut self.output, flush)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            pos += (self.compress.total_in() - total_in) as usize;
+            // The output is only left with spare room once all the input is compressed.
+            if pos == buf.len() && self.output.len() < self.output.capacity() {
+                return Ok(pos);
+            }
+        }
+    }
+}
+
+/// Represents a stream that is compressed once its switch is turned on.
+pub struct DeflateStream<S> {
+    stream: S,
+    switch: DeflateSwitch,
+    deflate: Option<Deflate>,
+}
+
+impl<S: Read + Write> DeflateStream<S> {
+    pub fn new(stream: S, switch: DeflateSwitch) -> Self {
+        Self {
+            stream,
+            switch,
+            deflate: None,
+        }
+    }
+
+    /// Start the codec once the switch is turned on.
+    fn check_switch(&mut self) {
+        if self.deflate.is_none() && self.switch.is_on() {
+            self.deflate = Some(Deflate::new());
+        }
+    }
+
+    /// Write the compressed data waiting in the codec to the stream.
+    fn write_output(&mut self) -> io::Result<()> {
+        if let Some(ref mut deflate) = self.deflate {
+            self.stream.write_all(&deflate.output)?;
+            deflate.output.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Read for DeflateStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check_switch();
+        match self.deflate {
+            Some(ref mut deflate) => deflate.read(&mut self.stream, buf),
+            None => self.stream.read(buf),
+        }
+    }
+}
+
+impl<S: Read + Write> Write for DeflateStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_switch();
+        let deflate = match self.deflate {
+            Some(ref mut deflate) => deflate,
+            None => return self.stream.write(buf),
+        };
+        let len = deflate.write(buf, FlushCompress::None)?;
+        if deflate.output.len() >= BUF_SIZE {
+            self.write_output()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Commands are flushed once complete: a sync flush makes them decodable at once.
+        self.check_switch();
+        if let Some(ref mut deflate) = self.deflate {
+            deflate.write(&[], FlushCompress::Sync)?;
+        }
+        self.write_output()?;
+        self.stream.flush()
+    }
+}
+
+impl<S> fmt::Debug for DeflateStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeflateStream")
+            .field("compressed", &self.deflate.is_some())
+            .finish()
+    }
+}
+
+impl SetReadTimeout for DeflateStream<TlsStream<TcpStream>> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> imap::Result<()> {
+        self.stream
+            .get_ref()
+            .set_read_timeout(timeout)
+            .map_err(imap::Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Represents one end of a connection, reading what the other end wrote.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_should_compress_once_switched_on() {
+        let switch = DeflateSwitch::default();
+        let pipe = Pipe {
+            input: Cursor::new(vec![]),
+            output: vec![],
+        };
+        let mut client = DeflateStream::new(pipe, switch.clone());
+        client.write_all(b"A1 COMPRESS DEFLATE\r\n").unwrap();
+        client.flush().unwrap();
+        switch.turn_on();
+        let body = "Hello, world!\r\n".repeat(10_000);
+        client.write_all(b"A2 NOOP\r\n").unwrap();
+        client.write_all(body.as_bytes()).unwrap();
+        client.flush().unwrap();
+
+        let output = client.stream.output;
+        let (plain, compressed) = output.split_at(b"A1 COMPRESS DEFLATE\r\n".len());
+        assert_eq!(b"A1 COMPRESS DEFLATE\r\n", plain);
+        assert!(compressed.len() < body.len() / 10);
+
+        // The server end reads what the client wrote, compressed.
+        let pipe = Pipe {
+            input: Cursor::new(compressed.to_vec()),
+            output: vec![],
+        };
+        let mut server = DeflateStream::new(pipe, switch);
+        let mut received = String::new();
+        server.read_to_string(&mut received).unwrap();
+        assert_eq!(format!("A2 NOOP\r\n{}", body), received);
+    }
+}
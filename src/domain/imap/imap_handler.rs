@@ -4,21 +4,49 @@
 
 use anyhow::Result;
 
-use crate::{config::Config, domain::imap::ImapServiceInterface};
+use crate::{
+    config::Config,
+    domain::{
+        backend::Backend,
+        imap::{CapsReport, RawResponse},
+    },
+    output::OutputServiceInterface,
+};
 
 /// Notify handler.
-pub fn notify<ImapService: ImapServiceInterface>(
+pub fn notify(
     keepalive: u64,
+    standby: bool,
     config: &Config,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
-    imap.notify(&config, keepalive)
+    backend.notify(&config, keepalive, standby)
 }
 
-/// Watch handler.
-pub fn watch<ImapService: ImapServiceInterface>(
+/// Watch handler, printing the changes of the mailbox as they come.
+pub fn watch<OutputService: OutputServiceInterface>(
     keepalive: u64,
-    imap: &mut ImapService,
+    output: &OutputService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
-    imap.watch(keepalive)
+    backend.watch(keepalive, &mut |event| output.print(event))
+}
+
+/// Raw command handler, printing the untagged response lines of the given raw command.
+pub fn exec<OutputService: OutputServiceInterface>(
+    cmd: &str,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let res = backend.exec(cmd)?;
+    output.print(RawResponse::from(res.as_slice()))
+}
+
+/// Capabilities handler, printing the capabilities of the server and how they are used.
+pub fn capabilities<OutputService: OutputServiceInterface>(
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let caps = backend.get_caps()?;
+    output.print(CapsReport::from(caps))
 }
@@ -3,86 +3,290 @@
 //! This module exposes a service that can interact with IMAP servers.
 
 use anyhow::{anyhow, Context, Result};
-use log::{debug, trace};
-use native_tls::{TlsConnector, TlsStream};
+use chrono::{DateTime, FixedOffset};
+use imap::{
+    extensions::idle::SetReadTimeout,
+    types::{Mailbox, UnsolicitedResponse},
+};
+use imap_proto::types::{Capability, SectionPath};
+use log::{debug, info, trace, warn};
+use mailparse::{MailHeader, MailHeaderMap};
+use native_tls::TlsStream;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
+    io::{BufRead, BufReader, Write},
     iter::FromIterator,
     net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::Duration,
 };
 
 use crate::{
-    config::{Account, Config},
+    config::{proxy, tls, Account, Config},
     domain::{
-        mbox::Mbox,
-        msg::{Envelopes, Flags, Msg},
+        account::Quota,
+        backend::{backend_retry, Backend},
+        imap::{
+            check_raw_cmd, check_uid_validity,
+            imap_compress::{DeflateStream, DeflateSwitch},
+            EnvelopeCache, WatchEvent,
+        },
+        mbox::{Acl, AclEntry, Mbox, MboxStatus, Mboxes, NamespaceKind},
+        metrics::{self, Metric},
+        msg::{
+            decode_part, msg_hold, msg_utils, Envelope, Envelopes, Flags, Msg, PartMetas,
+            SortCriteria,
+        },
+        webhook::{self, WebhookEvent},
+    },
+    output::{
+        output_interrupt,
+        output_trace::{Dir, TraceStream, Tracer},
+        run_cmd,
     },
 };
 
-type ImapSession = imap::Session<TlsStream<TcpStream>>;
-type ImapMboxes = imap::types::ZeroCopy<Vec<imap::types::Name>>;
-
-pub trait ImapServiceInterface {
-    fn notify(&mut self, config: &Config, keepalive: u64) -> Result<()>;
-    fn watch(&mut self, keepalive: u64) -> Result<()>;
-    fn get_mboxes(&mut self) -> Result<ImapMboxes>;
-    fn get_msgs(&mut self, page_size: &usize, page: &usize) -> Result<Envelopes>;
-    fn find_msgs(&mut self, query: &str, page_size: &usize, page: &usize) -> Result<Envelopes>;
-    fn find_msg(&mut self, seq: &str) -> Result<Msg>;
-    fn find_raw_msg(&mut self, seq: &str) -> Result<Vec<u8>>;
-    fn append_msg(&mut self, mbox: &Mbox, msg: Msg) -> Result<()>;
-    fn append_raw_msg_with_flags(&mut self, mbox: &Mbox, msg: &[u8], flags: Flags) -> Result<()>;
-    fn expunge(&mut self) -> Result<()>;
-    fn logout(&mut self) -> Result<()>;
-
-    /// Add flags to all messages within the given sequence range.
-    fn add_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()>;
-    /// Replace flags of all messages within the given sequence range.
-    fn set_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()>;
-    /// Remove flags from all messages within the given sequence range.
-    fn remove_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()>;
+type ImapSession = imap::Session<TraceStream<DeflateStream<TlsStream<TcpStream>>>>;
+
+impl<S: SetReadTimeout> SetReadTimeout for TraceStream<S> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> imap::Result<()> {
+        self.get_mut().set_read_timeout(timeout)
+    }
+}
+
+/// Compress the given session with the COMPRESS extension, when the server supports it.
+fn compress(sess: &mut ImapSession, switch: &DeflateSwitch) -> Result<()> {
+    let supported = sess
+        .capabilities()
+        .context("cannot get IMAP capabilities")?
+        .iter()
+        .any(|cap| matches!(cap, Capability::Atom(atom) if atom.eq_ignore_ascii_case("COMPRESS=DEFLATE")));
+    if !supported {
+        debug!("server lacks COMPRESS=DEFLATE, the traffic is not compressed");
+        return Ok(());
+    }
+    sess.run_command_and_check_ok("COMPRESS DEFLATE")
+        .context("cannot compress IMAP session")?;
+    // The server compresses everything it sends after its response.
+    switch.turn_on();
+    debug!("IMAP session compressed");
+    Ok(())
+}
+
+/// Upgrade the given plain connection with STARTTLS, the greeting of the server included.
+fn starttls(tcp: &TcpStream, tracer: &mut Option<Tracer>) -> Result<()> {
+    let mut reader = BufReader::new(tcp);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    trace!("greeting: {}", line.trim_end());
+    let mut record = |dir, line: &str| {
+        if let Some(ref mut tracer) = tracer {
+            tracer.line(dir, line);
+        }
+    };
+    record(Dir::Server, &line);
+    (&*tcp).write_all(b"A0 STARTTLS\r\n")?;
+    record(Dir::Client, "A0 STARTTLS");
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("connection closed by the server"));
+        }
+        record(Dir::Server, &line);
+        // Untagged responses may come before the tagged one.
+        if let Some(status) = line.strip_prefix("A0 ") {
+            return match status.get(..2) {
+                Some(ok) if ok.eq_ignore_ascii_case("OK") => Ok(()),
+                _ => Err(anyhow!("STARTTLS refused: {}", line.trim_end())),
+            };
+        }
+    }
+}
+
+/// Number of messages fetched per FETCH command in listings.
+const FETCH_BATCH_SIZE: usize = 500;
+
+/// Items fetched for listings: only what envelopes need, never bodies.
+const ENVELOPE_ITEMS: &str = "ENVELOPE FLAGS INTERNALDATE RFC822.SIZE BODYSTRUCTURE";
+
+/// Represents an action requested from a notification, printed by the notify command.
+#[derive(Debug, PartialEq)]
+enum NotifyAction {
+    Read,
+    Delete,
+}
+
+impl NotifyAction {
+    fn parse(output: &str) -> Option<Self> {
+        match output.trim().to_lowercase().as_str() {
+            "read" => Some(Self::Read),
+            "delete" => Some(Self::Delete),
+            _ => None,
+        }
+    }
+
+    /// Apply the action to the given message. Messages on hold are never deleted, and deleted
+    /// messages are only expunged with UIDPLUS, so that the other deleted messages of the
+    /// mailbox are left as is.
+    fn apply(
+        &self,
+        sess: &mut ImapSession,
+        uid: u32,
+        hold_keyword: &str,
+        uidplus: bool,
+    ) -> Result<()> {
+        match self {
+            Self::Read => {
+                sess.uid_store(uid.to_string(), "+FLAGS (\\Seen)")
+                    .context(format!("cannot mark message {} as read", uid))?;
+            }
+            Self::Delete => {
+                let fetches = sess
+                    .uid_fetch(uid.to_string(), "FLAGS")
+                    .context(format!("cannot fetch flags of message {}", uid))?;
+                for fetch in fetches.iter() {
+                    if msg_hold::is_held(&Flags::try_from(fetch.flags())?, hold_keyword) {
+                        return Err(anyhow!("cannot delete message {}: it is on hold", uid));
+                    }
+                }
+                sess.uid_store(uid.to_string(), "+FLAGS (\\Seen \\Deleted)")
+                    .context(format!("cannot delete message {}", uid))?;
+                if uidplus {
+                    sess.uid_expunge(uid.to_string())
+                        .context(format!("cannot expunge message {}", uid))?;
+                } else {
+                    warn!(
+                        "server lacks UIDPLUS, message {} is left flagged as deleted",
+                        uid
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 pub struct ImapService<'a> {
     account: &'a Account,
     mbox: &'a Mbox,
     sess: Option<ImapSession>,
+    /// Capabilities advertised by the server, cached after the first lookup.
+    caps: Option<HashSet<String>>,
+    /// Namespace prefixes advertised by the server, cached after the first lookup.
+    namespaces: Option<Vec<(NamespaceKind, String)>>,
+    /// Whether the current mailbox is selected in read-write mode, so that consecutive commands
+    /// of the same invocation do not select it again.
+    selected: bool,
+    /// Whether message identifiers are sequence numbers instead of UIDs. Sequence numbers shift
+    /// when messages are expunged, so UIDs are used by default.
+    use_seq: bool,
 }
 
 impl<'a> ImapService<'a> {
+    /// Interpret message identifiers as sequence numbers instead of UIDs.
+    pub fn use_seq(mut self, use_seq: bool) -> Self {
+        self.use_seq = use_seq;
+        self
+    }
+
+    /// Open a new authenticated session.
+    fn connect(&self) -> Result<ImapSession> {
+        debug!("create TLS builder");
+        debug!("insecure: {}", self.account.imap_insecure);
+        let fingerprint = self.account.imap_cert_fingerprint.as_deref();
+        let builder = tls::connector(
+            self.account,
+            self.account.imap_insecure,
+            fingerprint.is_some(),
+        )?;
+
+        debug!("create client");
+        debug!("host: {}", self.account.imap_host);
+        debug!("port: {}", self.account.imap_port);
+        debug!("starttls: {}", self.account.imap_starttls);
+        let host = self.account.imap_host.as_str();
+        let tcp = proxy::connect(self.account.proxy.as_ref(), host, self.account.imap_port)
+            .context("cannot connect to IMAP server")?;
+        let mut tracer = Tracer::start("imap");
+        if let Some(ref tracer) = tracer {
+            tracer.event(&format!("connected to {}:{}", host, self.account.imap_port));
+        }
+        if self.account.imap_starttls {
+            starttls(&tcp, &mut tracer).context("cannot start TLS with IMAP server")?;
+        }
+        let stream = builder
+            .connect(host, tcp)
+            .context("cannot connect to IMAP server")?;
+        if let Some(fingerprint) = fingerprint {
+            tls::check_fingerprint(&stream, fingerprint)
+                .context("cannot trust IMAP server certificate")?;
+        }
+        let switch = DeflateSwitch::default();
+        let stream = DeflateStream::new(stream, switch.clone());
+        let mut client = imap::Client::new(TraceStream::new(stream, tracer));
+        // The greeting was already read before STARTTLS.
+        if !self.account.imap_starttls {
+            client
+                .read_greeting()
+                .context("cannot read IMAP server greeting")?;
+        }
+
+        debug!("create session");
+        debug!("login: {}", self.account.imap_login);
+        debug!("passwd cmd: {}", self.account.imap_passwd_cmd);
+        let mut sess = client
+            .login(&self.account.imap_login, &self.account.imap_passwd()?)
+            .map_err(|res| res.0)
+            .context("cannot login to IMAP server")?;
+
+        debug!("compress: {}", self.account.imap_compress);
+        if self.account.imap_compress {
+            compress(&mut sess, &switch)?;
+        }
+        Ok(sess)
+    }
+
+    /// Open a standby session on the current mailbox, and spawn a thread applying the
+    /// notification actions it receives. The session is kept alive with NOOP between actions, so
+    /// that actions run instantly without interrupting the IDLE session.
+    fn spawn_standby(&mut self, keepalive: u64) -> Result<mpsc::Sender<(u32, NotifyAction)>> {
+        let mbox = self.mbox;
+        let name = self.resolve_mbox(mbox)?;
+        debug!("open standby session");
+        let mut sess = self.connect()?;
+        sess.select(&name).context(format!(
+            r#"cannot select mailbox "{}" in standby session"#,
+            self.mbox.name
+        ))?;
+
+        let hold_keyword = self.account.hold_keyword.to_owned();
+        let uidplus = self.has_cap("UIDPLUS")?;
+        let (tx, rx) = mpsc::channel::<(u32, NotifyAction)>();
+        thread::spawn(move || loop {
+            match rx.recv_timeout(Duration::new(keepalive, 0)) {
+                Ok((uid, action)) => {
+                    debug!("apply notify action {:?} to message {}", action, uid);
+                    if let Err(err) = action.apply(&mut sess, uid, &hold_keyword, uidplus) {
+                        warn!("{:?}", err);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Err(err) = sess.noop() {
+                        warn!("cannot keep standby session alive: {}", err);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        });
+
+        Ok(tx)
+    }
+
     fn sess(&mut self) -> Result<&mut ImapSession> {
         if let None = self.sess {
-            debug!("create TLS builder");
-            debug!("insecure: {}", self.account.imap_insecure);
-            let builder = TlsConnector::builder()
-                .danger_accept_invalid_certs(self.account.imap_insecure)
-                .danger_accept_invalid_hostnames(self.account.imap_insecure)
-                .build()
-                .context("cannot create TLS connector")?;
-
-            debug!("create client");
-            debug!("host: {}", self.account.imap_host);
-            debug!("port: {}", self.account.imap_port);
-            debug!("starttls: {}", self.account.imap_starttls);
-            let mut client_builder =
-                imap::ClientBuilder::new(&self.account.imap_host, self.account.imap_port);
-            if self.account.imap_starttls {
-                client_builder.starttls();
-            }
-            let client = client_builder
-                .connect(|domain, tcp| Ok(TlsConnector::connect(&builder, domain, tcp)?))
-                .context("cannot connect to IMAP server")?;
-
-            debug!("create session");
-            debug!("login: {}", self.account.imap_login);
-            debug!("passwd cmd: {}", self.account.imap_passwd_cmd);
-            self.sess = Some(
-                client
-                    .login(&self.account.imap_login, &self.account.imap_passwd()?)
-                    .map_err(|res| res.0)
-                    .context("cannot login to IMAP server")?,
-            );
+            self.sess = Some(self.connect()?);
         }
 
         match self.sess {
@@ -91,170 +295,420 @@ impl<'a> ImapService<'a> {
         }
     }
 
-    fn search_new_msgs(&mut self) -> Result<Vec<u32>> {
-        let uids: Vec<u32> = self
-            .sess()?
-            .uid_search("NEW")
-            .context("cannot search new messages")?
-            .into_iter()
-            .collect();
-        debug!("found {} new messages", uids.len());
-        trace!("uids: {:?}", uids);
+    /// Check if the server advertises the given capability (case-insensitive).
+    fn has_cap(&mut self, cap: &str) -> Result<bool> {
+        if self.caps.is_none() {
+            let caps: HashSet<String> = self
+                .sess()?
+                .capabilities()
+                .context("cannot get IMAP capabilities")?
+                .iter()
+                .map(|cap| match cap {
+                    Capability::Imap4rev1 => String::from("IMAP4REV1"),
+                    Capability::Auth(auth) => format!("AUTH={}", auth).to_uppercase(),
+                    Capability::Atom(atom) => atom.to_uppercase(),
+                })
+                .collect();
+            debug!("capabilities: {:?}", caps);
+            self.caps = Some(caps);
+        }
 
-        Ok(uids)
+        Ok(self
+            .caps
+            .as_ref()
+            .map(|caps| caps.contains(&cap.to_uppercase()))
+            .unwrap_or_default())
     }
-}
 
-impl<'a> ImapServiceInterface for ImapService<'a> {
-    fn get_mboxes(&mut self) -> Result<ImapMboxes> {
-        let mboxes = self
+    /// Get the namespaces advertised by the server, or none when it lacks the NAMESPACE extension.
+    fn namespaces(&mut self) -> Result<&[(NamespaceKind, String)]> {
+        if self.namespaces.is_none() {
+            let namespaces = if self.has_cap("NAMESPACE")? {
+                let res = self
+                    .sess()?
+                    .run_command_and_read_response("NAMESPACE")
+                    .context("cannot get namespaces")?;
+                parse_namespace_res(&res)
+            } else {
+                vec![]
+            };
+            debug!("namespaces: {:?}", namespaces);
+            self.namespaces = Some(namespaces);
+        }
+        Ok(self.namespaces.as_deref().unwrap_or_default())
+    }
+
+    /// Get the server name of the given mailbox, resolved against the personal namespace.
+    fn resolve_mbox(&mut self, mbox: &Mbox) -> Result<String> {
+        let name = resolve_mbox_name(&mbox.name, self.namespaces()?);
+        if name != mbox.name {
+            debug!(r#"mailbox "{}" resolved to "{}""#, mbox.name, name);
+        }
+        Ok(name)
+    }
+
+    /// Wait for the server to report mailbox changes, using the IDLE extension when available or
+    /// polling otherwise, and return them.
+    fn wait_for_changes(&mut self, keepalive: u64) -> Result<Vec<UnsolicitedResponse>> {
+        let mut changes = vec![];
+        if self.has_cap("IDLE")? {
+            self.sess()?
+                .idle()
+                .and_then(|mut idle| {
+                    idle.set_keepalive(Duration::new(keepalive, 0));
+                    idle.wait_keepalive_while(|res| {
+                        trace!("idle response: {:?}", res);
+                        changes.push(res);
+                        false
+                    })
+                })
+                .context("cannot start the idle mode")?;
+        } else {
+            debug!("polling again in {}s", keepalive);
+            thread::sleep(Duration::new(keepalive, 0));
+            let sess = self.sess()?;
+            sess.noop().context("cannot poll mailbox changes")?;
+            changes.extend(sess.unsolicited_responses.try_iter());
+        }
+        Ok(changes)
+    }
+
+    /// Select the current mailbox and check its UIDVALIDITY, so that stale local state is never
+    /// applied to the wrong messages.
+    fn select_mbox(&mut self) -> Result<Mailbox> {
+        let mbox = self.mbox;
+        let name = self.resolve_mbox(mbox)?;
+        let mailbox = self
             .sess()?
-            .list(Some(""), Some("*"))
-            .context("cannot list mailboxes")?;
-        Ok(mboxes)
+            .select(&name)
+            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
+        self.check_uid_validity(&mailbox)?;
+        self.selected = true;
+        Ok(mailbox)
     }
 
-    fn get_msgs(&mut self, page_size: &usize, page: &usize) -> Result<Envelopes> {
-        let mbox = self.mbox.to_owned();
-        let last_seq = self
+    /// Select the current mailbox, unless it is already selected.
+    fn ensure_selected(&mut self) -> Result<()> {
+        if !self.selected {
+            self.select_mbox()?;
+        }
+        Ok(())
+    }
+
+    /// Same as `select_mbox`, but opens the mailbox in read-only mode.
+    fn examine_mbox(&mut self) -> Result<Mailbox> {
+        let mbox = self.mbox;
+        let name = self.resolve_mbox(mbox)?;
+        debug!("examine mailbox: {}", name);
+        let mailbox = self
             .sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?
-            .exists as i64;
+            .examine(&name)
+            .context(format!("cannot examine mailbox `{}`", &self.mbox.name))?;
+        self.check_uid_validity(&mailbox)?;
+        self.selected = false;
+        Ok(mailbox)
+    }
 
-        if last_seq == 0 {
-            return Ok(Envelopes::default());
+    fn check_uid_validity(&self, mailbox: &Mailbox) -> Result<()> {
+        match mailbox.uid_validity {
+            Some(uid_validity) => check_uid_validity(self.account, &self.mbox.name, uid_validity),
+            None => {
+                warn!(
+                    r#"server did not send the UIDVALIDITY of "{}""#,
+                    self.mbox.name
+                );
+                Ok(())
+            }
         }
+    }
 
-        // TODO: add tests, improve error management when empty page
-        let range = if *page_size > 0 {
-            let cursor = (page * page_size) as i64;
-            let begin = 1.max(last_seq - cursor);
-            let end = begin - begin.min(*page_size as i64) + 1;
-            format!("{}:{}", begin, end)
-        } else {
-            String::from("1:*")
-        };
+    /// Get the maximum size of a message that can be appended to the given mailbox, as advertised
+    /// by the [APPENDLIMIT](https://datatracker.ietf.org/doc/html/rfc7889) extension.
+    fn append_limit(&mut self, mbox: &Mbox) -> Result<Option<usize>> {
+        if !self.has_cap("APPENDLIMIT")? {
+            // The limit can also be advertised directly in the capability.
+            return Ok(self.caps.as_ref().and_then(|caps| {
+                caps.iter()
+                    .find_map(|cap| cap.strip_prefix("APPENDLIMIT="))
+                    .and_then(|limit| limit.parse().ok())
+            }));
+        }
 
-        let fetches = self
+        // A bare APPENDLIMIT capability means that limits are set per mailbox.
+        let cmd = format!("STATUS {} (APPENDLIMIT)", quote(&self.resolve_mbox(mbox)?));
+        let res = self
             .sess()?
-            .fetch(range, "(ENVELOPE FLAGS INTERNALDATE)")
-            .context(r#"cannot fetch messages within range "{}""#)?;
+            .run_command_and_read_response(&cmd)
+            .context(format!(r#"cannot get append limit of "{}""#, mbox.name))?;
+        Ok(parse_append_limit_res(&res))
+    }
 
-        Ok(Envelopes::try_from(fetches)?)
+    fn check_append_limit(&mut self, mbox: &Mbox, msg: &[u8]) -> Result<()> {
+        match self.append_limit(mbox)? {
+            Some(limit) if msg.len() > limit => Err(anyhow!(
+                r#"cannot append message to "{}": its size ({} bytes) exceeds the server limit ({} bytes)"#,
+                mbox.name,
+                msg.len(),
+                limit
+            )),
+            _ => Ok(()),
+        }
     }
 
-    fn find_msgs(&mut self, query: &str, page_size: &usize, page: &usize) -> Result<Envelopes> {
-        let mbox = self.mbox.to_owned();
-        self.sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
+    /// Fetch envelopes of the given sequence numbers, using batches of compact sequence sets.
+    /// Envelopes are returned in the order of the given sequence numbers.
+    ///
+    /// UIDs are fetched along, so that envelopes are identified by their UID unless sequence
+    /// numbers are used.
+    fn fetch_envelopes(&mut self, seqs: &[u32]) -> Result<Vec<Envelope>> {
+        let items = if self.use_seq {
+            format!("({})", ENVELOPE_ITEMS)
+        } else {
+            format!("(UID {})", ENVELOPE_ITEMS)
+        };
+        let mut envelopes = HashMap::new();
 
-        let begin = page * page_size;
-        let end = begin + (page_size - 1);
-        let seqs: Vec<String> = self
-            .sess()?
-            .search(query)
-            .context(format!(
-                r#"cannot search in "{}" with query: "{}""#,
-                self.mbox.name, query
-            ))?
+        for batch in seqs.chunks(FETCH_BATCH_SIZE) {
+            output_interrupt::check()?;
+            let seq_set = to_seq_set(batch);
+            debug!("fetch envelopes within range {}", seq_set);
+            let fetches = self.sess()?.fetch(&seq_set, &items).context(format!(
+                r#"cannot fetch messages within range "{}""#,
+                seq_set
+            ))?;
+            for fetch in fetches.iter() {
+                envelopes.insert(fetch.message, Envelope::try_from(fetch)?);
+            }
+        }
+
+        let mut envelopes: Vec<Envelope> = seqs
             .iter()
-            .map(|seq| seq.to_string())
+            .filter_map(|seq| envelopes.remove(seq))
             .collect();
+        self.fill_labels(&mut envelopes)?;
+        Ok(envelopes)
+    }
 
-        if seqs.is_empty() {
-            return Ok(Envelopes::default());
-        }
+    /// Same as `fetch_envelopes`, but with the envelope cache: the messages arrived since the
+    /// greatest known UID are fetched first, then only the flags of the given sequence numbers,
+    /// along with the envelopes still missing from the cache.
+    fn fetch_cached_envelopes(&mut self, seqs: &[u32], uid_validity: u32) -> Result<Vec<Envelope>> {
+        let mut cache = EnvelopeCache::load(self.account, &self.mbox.name, uid_validity)?;
+        let items = format!("(UID {})", ENVELOPE_ITEMS);
 
-        // FIXME: panic if begin > end
-        let range = seqs[begin..end.min(seqs.len())].join(",");
-        let fetches = self
-            .sess()?
-            .fetch(&range, "(ENVELOPE FLAGS INTERNALDATE)")
-            .context(r#"cannot fetch messages within range "{}""#)?;
+        let last_uid = cache.last_uid();
+        if last_uid > 0 {
+            let uid_set = format!("{}:*", last_uid + 1);
+            debug!("fetch new envelopes within UID range {}", uid_set);
+            let fetches = self.sess()?.uid_fetch(&uid_set, &items).context(format!(
+                r#"cannot fetch messages within UID range "{}""#,
+                uid_set
+            ))?;
+            for fetch in fetches.iter() {
+                // The range matches the last message even when its UID is lower.
+                if fetch.uid.unwrap_or_default() > last_uid {
+                    cache.insert(Envelope::try_from(fetch)?);
+                }
+            }
+        }
 
-        Ok(Envelopes::try_from(fetches)?)
-    }
+        let mut uids = HashMap::new();
+        for batch in seqs.chunks(FETCH_BATCH_SIZE) {
+            output_interrupt::check()?;
+            let seq_set = to_seq_set(batch);
+            debug!("fetch flags within range {}", seq_set);
+            let fetches = self
+                .sess()?
+                .fetch(&seq_set, "(UID FLAGS)")
+                .context(format!(r#"cannot fetch flags within range "{}""#, seq_set))?;
+            for fetch in fetches.iter() {
+                if let Some(uid) = fetch.uid {
+                    cache.set_flags(uid, Flags::try_from(fetch.flags())?);
+                    uids.insert(fetch.message, uid);
+                }
+            }
+        }
 
-    /// Find a message by sequence number.
-    fn find_msg(&mut self, seq: &str) -> Result<Msg> {
-        let mbox = self.mbox.to_owned();
-        self.sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
-        let fetches = self
-            .sess()?
-            .fetch(seq, "(ENVELOPE FLAGS INTERNALDATE BODY[])")
-            .context(r#"cannot fetch messages "{}""#)?;
-        let fetch = fetches
-            .first()
-            .ok_or(anyhow!(r#"cannot find message "{}"#, seq))?;
+        let missing_uids: Vec<u32> = uids
+            .values()
+            .filter(|uid| !cache.contains(**uid))
+            .cloned()
+            .collect();
+        for batch in missing_uids.chunks(FETCH_BATCH_SIZE) {
+            output_interrupt::check()?;
+            let uid_set = to_seq_set(batch);
+            debug!("fetch envelopes within UID range {}", uid_set);
+            let fetches = self.sess()?.uid_fetch(&uid_set, &items).context(format!(
+                r#"cannot fetch messages within UID range "{}""#,
+                uid_set
+            ))?;
+            for fetch in fetches.iter() {
+                cache.insert(Envelope::try_from(fetch)?);
+            }
+        }
 
-        Ok(Msg::try_from(fetch)?)
+        let mut envelopes: Vec<Envelope> = seqs
+            .iter()
+            .filter_map(|seq| uids.get(seq))
+            .filter_map(|uid| cache.get(*uid).cloned())
+            .collect();
+        // The listing does not depend on the cache, failing to save it only slows the next one.
+        if let Err(err) = cache.save(self.account, &self.mbox.name) {
+            warn!("{:#}", err);
+        }
+        // Labels are not cached: they are computed again, like flags.
+        self.fill_labels(&mut envelopes)?;
+        Ok(envelopes)
     }
 
-    fn find_raw_msg(&mut self, seq: &str) -> Result<Vec<u8>> {
-        let mbox = self.mbox.to_owned();
-        self.sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
-        let fetches = self
+    /// Get the Gmail attributes of the given messages.
+    fn fetch_gm_attrs(&mut self, ids: &str) -> Result<Vec<GmAttrs>> {
+        let use_seq = self.use_seq;
+        let cmd = if use_seq {
+            format!("FETCH {} (X-GM-LABELS X-GM-THRID)", ids)
+        } else {
+            format!("UID FETCH {} (X-GM-LABELS X-GM-THRID)", ids)
+        };
+        let res = self
             .sess()?
-            .fetch(seq, "BODY[]")
-            .context(r#"cannot fetch raw messages "{}""#)?;
-        let fetch = fetches
-            .first()
-            .ok_or(anyhow!(r#"cannot find raw message "{}"#, seq))?;
+            .run_command_and_read_response(&cmd)
+            .context(format!(r#"cannot fetch labels of message(s) "{}""#, ids))?;
+        Ok(parse_gm_attrs_res(&res, use_seq))
+    }
 
-        Ok(fetch.body().map(Vec::from).unwrap_or_default())
+    /// Add (`+`) or remove (`-`) the given Gmail labels of the given messages.
+    fn store_gm_labels(&mut self, ids: &str, op: char, labels: &[String]) -> Result<()> {
+        // System labels, like `\Important`, are atoms.
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|label| {
+                if label.starts_with('\\') {
+                    label.to_owned()
+                } else {
+                    quote(label)
+                }
+            })
+            .collect();
+        let cmd = format!(
+            "{}STORE {} {}X-GM-LABELS.SILENT ({})",
+            if self.use_seq { "" } else { "UID " },
+            ids,
+            op,
+            labels.join(" ")
+        );
+        self.sess()?.run_command_and_read_response(&cmd)?;
+        Ok(())
     }
 
-    fn append_raw_msg_with_flags(&mut self, mbox: &Mbox, msg: &[u8], flags: Flags) -> Result<()> {
-        self.sess()?
-            .append(&mbox.name, &msg)
-            .flags(flags.0)
-            .finish()
-            .context(format!(r#"cannot append message to "{}""#, mbox.name))?;
+    /// Set the labels of the given envelopes, leaving out the hold keyword: their keywords, or
+    /// their Gmail labels along with their thread ids when the server has the Gmail extensions.
+    fn fill_labels(&mut self, envelopes: &mut [Envelope]) -> Result<()> {
+        let account = self.account;
+        let hold_keyword = &account.hold_keyword;
+        if envelopes.is_empty() || !self.has_cap(GMAIL_EXT)? {
+            for envelope in envelopes {
+                envelope.labels = envelope.flags.labels(hold_keyword);
+            }
+            return Ok(());
+        }
+        let ids: Vec<u32> = envelopes.iter().map(|envelope| envelope.id).collect();
+        let mut attrs = HashMap::new();
+        for batch in ids.chunks(FETCH_BATCH_SIZE) {
+            output_interrupt::check()?;
+            for attr in self.fetch_gm_attrs(&to_seq_set(batch))? {
+                attrs.insert(attr.id, attr);
+            }
+        }
+        for envelope in envelopes {
+            let attr = attrs.remove(&envelope.id).unwrap_or_default();
+            envelope.labels = attr.labels;
+            envelope
+                .labels
+                .retain(|label| !label.eq_ignore_ascii_case(hold_keyword));
+            envelope.thread_id = attr.thread_id;
+        }
         Ok(())
     }
 
-    fn append_msg(&mut self, mbox: &Mbox, msg: Msg) -> Result<()> {
-        let msg_raw: Vec<u8> = (&msg).try_into()?;
+    /// Get the sequence number of the message matching the given identifier.
+    fn to_seq(&mut self, id: &str) -> Result<u32> {
+        if self.use_seq {
+            return id
+                .parse()
+                .context(format!(r#"cannot parse sequence number "{}""#, id));
+        }
+
+        let uid: u32 = id
+            .parse()
+            .context(format!(r#"cannot parse UID "{}""#, id))?;
         self.sess()?
-            .append(&mbox.name, &msg_raw)
-            .flags(msg.flags.0)
-            .finish()
-            .context(format!(r#"cannot append message to "{}""#, mbox.name))?;
+            .search(format!("UID {}", uid))
+            .context(format!(r#"cannot search message with UID "{}""#, uid))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!(r#"cannot find message with UID "{}""#, uid))
+    }
+
+    /// Store flags of the given messages, using UID STORE unless sequence numbers are used.
+    fn store(&mut self, ids: &str, query: &str) -> Result<()> {
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        if use_seq {
+            sess.store(ids, query)?;
+        } else {
+            sess.uid_store(ids, query)?;
+        }
         Ok(())
     }
 
-    fn notify(&mut self, config: &Config, keepalive: u64) -> Result<()> {
-        let mbox = self.mbox.to_owned();
+    fn search_new_msgs(&mut self) -> Result<Vec<u32>> {
+        let uids: Vec<u32> = self
+            .sess()?
+            .uid_search("NEW")
+            .context("cannot search new messages")?
+            .into_iter()
+            .collect();
+        debug!("found {} new messages", uids.len());
+        trace!("uids: {:?}", uids);
+
+        Ok(uids)
+    }
+
+    /// Notify new messages until an error occurs. The messages already notified are kept in the
+    /// given set, initialized with the new messages of the mailbox, so that they are not notified
+    /// again when the session resumes.
+    fn notify_loop(
+        &mut self,
+        config: &Config,
+        keepalive: u64,
+        standby: bool,
+        msgs_set: &mut Option<HashSet<u32>>,
+    ) -> Result<()> {
+        self.examine_mbox()?;
+        metrics::set_connected(self.account, true);
+        let actions = if standby {
+            Some(self.spawn_standby(keepalive)?)
+        } else {
+            None
+        };
 
-        debug!("examine mailbox: {}", mbox.name);
-        self.sess()?
-            .examine(&mbox.name)
-            .context(format!("cannot examine mailbox `{}`", &self.mbox.name))?;
+        if !self.has_cap("IDLE")? {
+            info!(
+                "server lacks IDLE, falling back to polling every {}s",
+                keepalive
+            );
+        }
 
-        debug!("init messages hashset");
-        let mut msgs_set: HashSet<u32> =
-            HashSet::from_iter(self.search_new_msgs()?.iter().cloned());
+        if msgs_set.is_none() {
+            debug!("init messages hashset");
+            *msgs_set = Some(HashSet::from_iter(self.search_new_msgs()?.iter().cloned()));
+        }
+        let msgs_set = msgs_set.get_or_insert_with(HashSet::new);
         trace!("messages hashset: {:?}", msgs_set);
 
         loop {
             debug!("begin loop");
-            self.sess()?
-                .idle()
-                .and_then(|mut idle| {
-                    idle.set_keepalive(std::time::Duration::new(keepalive, 0));
-                    idle.wait_keepalive_while(|res| {
-                        // TODO: handle response
-                        trace!("idle response: {:?}", res);
-                        false
-                    })
-                })
-                .context("cannot start the idle mode")?;
+            self.wait_for_changes(keepalive)?;
 
             let uids: Vec<u32> = self
                 .search_new_msgs()?
@@ -275,6 +729,7 @@ impl<'a> ImapServiceInterface for ImapService<'a> {
                     .uid_fetch(uids, "(ENVELOPE)")
                     .context("cannot fetch new messages enveloppe")?;
 
+                metrics::incr(self.account, Metric::NewMsgs, fetches.len() as u64);
                 for fetch in fetches.iter() {
                     let msg = Msg::try_from(fetch)?;
                     let uid = fetch.uid.ok_or_else(|| {
@@ -287,7 +742,38 @@ impl<'a> ImapServiceInterface for ImapService<'a> {
                         .and_then(|addrs| addrs.iter().next())
                         .map(|addr| addr.to_string())
                         .unwrap_or(String::from("unknown"));
-                    config.run_notify_cmd(&msg.subject, &from)?;
+                    webhook::emit(
+                        self.account,
+                        &WebhookEvent::new_mail(
+                            &self.account.name,
+                            &self.mbox.name,
+                            Some(uid),
+                            &msg.subject,
+                            &from,
+                        ),
+                    );
+                    let rule = self
+                        .account
+                        .notify_rules
+                        .iter()
+                        .find(|rule| rule.matches(&self.mbox.name, &msg.subject, &from));
+                    match actions {
+                        // The notify command may wait for the user, so it runs in the
+                        // background and its action is applied by the standby session.
+                        Some(ref actions) => {
+                            let cmd = config.build_notify_cmd(&msg.subject, &from, rule);
+                            let actions = actions.clone();
+                            thread::spawn(move || match run_cmd(&cmd) {
+                                Ok(output) => {
+                                    if let Some(action) = NotifyAction::parse(&output) {
+                                        actions.send((uid, action)).ok();
+                                    }
+                                }
+                                Err(err) => warn!("cannot run notify cmd: {}", err),
+                            });
+                        }
+                        None => config.run_notify_cmd(&msg.subject, &from, rule)?,
+                    }
 
                     debug!("notify message: {}", uid);
                     trace!("message: {:?}", msg);
@@ -301,83 +787,878 @@ impl<'a> ImapServiceInterface for ImapService<'a> {
             debug!("end loop");
         }
     }
+}
 
-    fn watch(&mut self, keepalive: u64) -> Result<()> {
-        debug!("examine mailbox: {}", &self.mbox.name);
-        let mbox = self.mbox.to_owned();
+impl<'a> ImapService<'a> {
+    /// Drop the session, so that the next command opens a new one.
+    fn reset(&mut self) {
+        debug!("reset IMAP session");
+        self.sess = None;
+        self.selected = false;
+    }
 
-        self.sess()?
-            .examine(&mbox.name)
-            .context(format!("cannot examine mailbox `{}`", &self.mbox.name))?;
+    /// Run the given operation, only reading from the server, retrying it on transient errors.
+    fn retry<T>(&mut self, desc: &str, op: impl FnMut(&mut Self) -> Result<T>) -> Result<T> {
+        let max_retries = self.account.max_retries;
+        backend_retry::retry(self, desc, max_retries, Self::reset, op)
+    }
 
-        loop {
-            debug!("begin loop");
-            self.sess()?
-                .idle()
-                .and_then(|mut idle| {
-                    idle.set_keepalive(std::time::Duration::new(keepalive, 0));
-                    idle.wait_keepalive_while(|res| {
-                        // TODO: handle response
-                        trace!("idle response: {:?}", res);
-                        false
-                    })
-                })
-                .context("cannot start the idle mode")?;
-            // FIXME
-            // ctx.config.exec_watch_cmds(&ctx.account)?;
-            debug!("end loop");
-        }
+    /// Run the given long-lived operation, resuming it on transient errors.
+    fn resume(&mut self, desc: &str, op: impl FnMut(&mut Self) -> Result<()>) -> Result<()> {
+        let max_retries = self.account.max_retries;
+        backend_retry::resume(self, desc, max_retries, Self::reset, op)
     }
 
-    fn logout(&mut self) -> Result<()> {
-        if let Some(ref mut sess) = self.sess {
-            debug!("logout from IMAP server");
-            sess.logout().context("cannot logout from IMAP server")?;
+    fn list_mboxes_once(&mut self) -> Result<Mboxes> {
+        let names = self
+            .sess()?
+            .list(Some(""), Some("*"))
+            .context("cannot list mailboxes")?;
+        let mut mboxes = Mboxes::from(&names);
+        for (namespace, prefix) in self.namespaces()?.to_vec() {
+            if namespace == NamespaceKind::Personal || prefix.is_empty() {
+                continue;
+            }
+            // Servers do not always list shared mailboxes along with the personal ones.
+            let names = self
+                .sess()?
+                .list(Some(""), Some(&format!("{}*", prefix)))
+                .context(format!(
+                    r#"cannot list mailboxes of namespace "{}""#,
+                    prefix
+                ))?;
+            for mbox in names.iter().map(Mbox::from) {
+                if !mboxes.0.iter().any(|known| known.name == mbox.name) {
+                    mboxes.0.push(mbox);
+                }
+            }
+            for mbox in mboxes.0.iter_mut() {
+                if mbox.name.starts_with(&prefix) {
+                    mbox.namespace = namespace;
+                }
+            }
         }
-        Ok(())
+        Ok(mboxes)
     }
 
-    fn add_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
-        let mbox = self.mbox.to_owned();
-        let flags: String = flags.to_string();
-        self.sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
-        self.sess()?
-            .store(seq_range, format!("+FLAGS ({})", flags))
-            .context(format!(r#"cannot add flags "{}""#, &flags))?;
-        Ok(())
-    }
+    fn list_envelopes_once(&mut self, page_size: &usize, page: &usize) -> Result<Envelopes> {
+        let mailbox = self.select_mbox()?;
+        let last_seq = mailbox.exists as i64;
 
-    fn set_flags(&mut self, uid_seq: &str, flags: &Flags) -> Result<()> {
-        let mbox = self.mbox.to_owned();
-        self.sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
-        self.sess()?
-            .store(uid_seq, format!("FLAGS ({})", flags))
-            .context(format!(r#"cannot set flags "{}""#, &flags))?;
-        Ok(())
+        if last_seq == 0 {
+            return Ok(Envelopes::default());
+        }
+
+        let seqs = page_seqs(last_seq, page_size, page);
+        match mailbox.uid_validity {
+            // Cached envelopes are identified by their UID.
+            Some(uid_validity) if self.account.envelope_cache && !self.use_seq => {
+                Ok(Envelopes(self.fetch_cached_envelopes(&seqs, uid_validity)?))
+            }
+            _ => Ok(Envelopes(self.fetch_envelopes(&seqs)?)),
+        }
     }
 
-    fn remove_flags(&mut self, uid_seq: &str, flags: &Flags) -> Result<()> {
-        let mbox = self.mbox.to_owned();
-        let flags = flags.to_string();
+    fn search_envelopes_once(
+        &mut self,
+        query: &str,
+        page_size: &usize,
+        page: &usize,
+    ) -> Result<Envelopes> {
+        self.ensure_selected()?;
+        let query = if self.has_cap(GMAIL_EXT)? {
+            query.to_owned()
+        } else if find_key(query, GM_RAW_KEY).is_some() {
+            return Err(anyhow!(
+                "cannot search with a Gmail query: the server lacks the {} extension",
+                GMAIL_EXT
+            ));
+        } else {
+            to_keyword_criteria(query)
+        };
+
+        let begin = page * page_size;
+        let end = (begin + page_size).saturating_sub(1);
+        let mut seqs: Vec<u32> = self
+            .sess()?
+            .search(&query)
+            .context(format!(
+                r#"cannot search in "{}" with query: "{}""#,
+                self.mbox.name, query
+            ))?
+            .into_iter()
+            .collect();
+        seqs.sort_unstable();
+
+        if seqs.is_empty() {
+            return Ok(Envelopes::default());
+        }
+
+        // A page size of 0 means all the matching messages, like for listings.
+        let mut seqs = if *page_size == 0 {
+            seqs
+        } else {
+            // FIXME: panic if begin > end
+            seqs[begin..end.min(seqs.len())].to_vec()
+        };
+        seqs.reverse();
+        Ok(Envelopes(self.fetch_envelopes(&seqs)?))
+    }
+
+    fn get_msg_once(&mut self, id: &str) -> Result<Msg> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let items = "(ENVELOPE FLAGS INTERNALDATE BODY[])";
+        let fetches = if use_seq {
+            sess.fetch(id, items)
+        } else {
+            sess.uid_fetch(id, items)
+        }
+        .context(format!(r#"cannot fetch message "{}""#, id))?;
+        let fetch = fetches
+            .first()
+            .ok_or(anyhow!(r#"cannot find message "{}""#, id))?;
+
+        Ok(Msg::try_from(fetch)?)
+    }
+
+    fn get_raw_msg_once(&mut self, id: &str) -> Result<Vec<u8>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(id, "BODY[]")
+        } else {
+            sess.uid_fetch(id, "BODY[]")
+        }
+        .context(format!(r#"cannot fetch raw message "{}""#, id))?;
+        let fetch = fetches
+            .first()
+            .ok_or(anyhow!(r#"cannot find raw message "{}""#, id))?;
+
+        Ok(fetch.body().map(Vec::from).unwrap_or_default())
+    }
+
+    fn get_msgs_once(&mut self, seq_range: &str) -> Result<Vec<Msg>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let items = "(ENVELOPE FLAGS INTERNALDATE BODY[])";
+        let fetches = if use_seq {
+            sess.fetch(seq_range, items)
+        } else {
+            sess.uid_fetch(seq_range, items)
+        }
+        .context(format!(r#"cannot fetch messages "{}""#, seq_range))?;
+
+        fetches.iter().map(Msg::try_from).collect()
+    }
+
+    fn get_raw_msgs_once(&mut self, seq_range: &str) -> Result<Vec<Vec<u8>>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq_range, "BODY[]")
+        } else {
+            sess.uid_fetch(seq_range, "BODY[]")
+        }
+        .context(format!(r#"cannot fetch raw messages "{}""#, seq_range))?;
+
+        Ok(fetches
+            .iter()
+            .map(|fetch| fetch.body().map(Vec::from).unwrap_or_default())
+            .collect())
+    }
+
+    /// Report the changes of the mailbox until an error occurs.
+    fn watch_loop(
+        &mut self,
+        keepalive: u64,
+        on_event: &mut dyn FnMut(WatchEvent) -> Result<()>,
+    ) -> Result<()> {
+        self.examine_mbox()?;
+
+        if !self.has_cap("IDLE")? {
+            info!(
+                "server lacks IDLE, falling back to polling every {}s",
+                keepalive
+            );
+        }
+
+        loop {
+            debug!("begin loop");
+            for res in self.wait_for_changes(keepalive)? {
+                on_event(WatchEvent::new(&self.mbox.name, &res))?;
+            }
+            // FIXME
+            // ctx.config.exec_watch_cmds(&ctx.account)?;
+            debug!("end loop");
+        }
+    }
+}
+
+impl<'a> Backend for ImapService<'a> {
+    fn get_caps(&mut self) -> Result<Vec<String>> {
+        self.has_cap("IMAP4REV1")?;
+        let mut caps: Vec<String> = self.caps.iter().flatten().cloned().collect();
+        caps.sort();
+        Ok(caps)
+    }
+
+    fn exec(&mut self, cmd: &str) -> Result<Vec<u8>> {
+        check_raw_cmd(cmd)?;
+        self.ensure_selected()?;
+        debug!("raw IMAP command: {}", cmd);
+        let res = self
+            .sess()?
+            .run_command_and_read_response(cmd)
+            .context(format!(r#"cannot run raw IMAP command "{}""#, cmd));
+        // The command may have selected another mailbox, or none.
+        self.selected = false;
+        res
+    }
+
+    fn get_quotas(&mut self) -> Result<Vec<Quota>> {
+        if !self.has_cap("QUOTA")? {
+            return Ok(vec![]);
+        }
+        let res = self
+            .sess()?
+            .run_command_and_read_response("GETQUOTAROOT INBOX")
+            .context("cannot get quota")?;
+        Ok(parse_quota_res(&res))
+    }
+
+    fn get_mbox_status(&mut self, mbox: &Mbox) -> Result<MboxStatus> {
+        let cmd = format!(
+            "STATUS {} (MESSAGES UNSEEN)",
+            quote(&self.resolve_mbox(mbox)?)
+        );
+        let res = self
+            .sess()?
+            .run_command_and_read_response(&cmd)
+            .context(format!(r#"cannot get status of "{}""#, mbox.name))?;
+        let (messages, unseen) = parse_status_res(&res)
+            .ok_or_else(|| anyhow!(r#"cannot parse status of "{}""#, mbox.name))?;
+        Ok(MboxStatus {
+            mbox: mbox.name.to_owned(),
+            messages,
+            unseen,
+        })
+    }
+
+    fn get_uid_validity(&mut self) -> Result<Option<u32>> {
+        Ok(self.examine_mbox()?.uid_validity)
+    }
+
+    fn get_mbox_quotas(&mut self, mbox: &Mbox) -> Result<Vec<Quota>> {
+        if !self.has_cap("QUOTA")? {
+            return Err(anyhow!(
+                r#"cannot get quota of "{}": the server does not support the QUOTA extension"#,
+                mbox.name
+            ));
+        }
+        let cmd = format!("GETQUOTAROOT {}", quote(&self.resolve_mbox(mbox)?));
+        let res = self
+            .sess()?
+            .run_command_and_read_response(&cmd)
+            .context(format!(r#"cannot get quota of "{}""#, mbox.name))?;
+        Ok(parse_quota_res(&res))
+    }
+
+    fn list_mboxes(&mut self) -> Result<Mboxes> {
+        self.retry("list mailboxes", |imap| imap.list_mboxes_once())
+    }
+
+    fn get_acl(&mut self, mbox: &Mbox) -> Result<Acl> {
+        if !self.has_cap("ACL")? {
+            return Err(anyhow!(
+                r#"cannot get access control list of "{}": the server does not support the ACL extension"#,
+                mbox.name
+            ));
+        }
+        let cmd = format!("GETACL {}", quote(&self.resolve_mbox(mbox)?));
+        let res = self
+            .sess()?
+            .run_command_and_read_response(&cmd)
+            .context(format!(
+                r#"cannot get access control list of "{}""#,
+                mbox.name
+            ))?;
+        Ok(Acl {
+            mbox: mbox.name.to_owned(),
+            entries: parse_acl_res(&res),
+        })
+    }
+
+    fn set_acl(&mut self, mbox: &Mbox, identifier: &str, rights: &str) -> Result<()> {
+        if !self.has_cap("ACL")? {
+            return Err(anyhow!(
+                r#"cannot set access control list of "{}": the server does not support the ACL extension"#,
+                mbox.name
+            ));
+        }
+        let cmd = format!(
+            "SETACL {} {} {}",
+            quote(&self.resolve_mbox(mbox)?),
+            quote(identifier),
+            quote(rights)
+        );
         self.sess()?
-            .select(&mbox.name)
-            .context(format!(r#"cannot select mailbox "{}""#, self.mbox.name))?;
+            .run_command_and_read_response(&cmd)
+            .context(format!(
+                r#"cannot set rights of "{}" on "{}""#,
+                identifier, mbox.name
+            ))?;
+        Ok(())
+    }
+
+    fn create_mbox(&mut self, mbox: &Mbox) -> Result<()> {
+        let name = self.resolve_mbox(mbox)?;
+        let exists = !self
+            .sess()?
+            .list(Some(""), Some(&name))
+            .context(format!(r#"cannot list mailbox "{}""#, mbox.name))?
+            .is_empty();
+        if !exists {
+            debug!("create mailbox: {}", name);
+            self.sess()?
+                .create(&name)
+                .context(format!(r#"cannot create mailbox "{}""#, mbox.name))?;
+        }
+        Ok(())
+    }
+
+    fn list_envelopes(&mut self, page_size: &usize, page: &usize) -> Result<Envelopes> {
+        self.retry("list envelopes", |imap| {
+            imap.list_envelopes_once(page_size, page)
+        })
+    }
+
+    fn stream_envelopes(
+        &mut self,
+        page_size: &usize,
+        page: &usize,
+        on_envelope: &mut dyn FnMut(&Envelope) -> Result<()>,
+    ) -> Result<()> {
+        let last_seq = self.select_mbox()?.exists as i64;
+        if last_seq == 0 {
+            return Ok(());
+        }
+
+        for batch in page_seqs(last_seq, page_size, page).chunks(FETCH_BATCH_SIZE) {
+            for envelope in self.fetch_envelopes(batch)?.iter() {
+                on_envelope(envelope)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn list_sorted_envelopes(
+        &mut self,
+        sort: &SortCriteria,
+        page_size: &usize,
+        page: &usize,
+    ) -> Result<Envelopes> {
+        let last_seq = self.select_mbox()?.exists;
+
+        let envelopes = if self.has_cap("SORT")? {
+            info!("server supports SORT, sorting messages server-side");
+            let res = self
+                .sess()?
+                .run_command_and_read_response(format!("SORT ({}) UTF-8 ALL", sort))
+                .context(format!(r#"cannot sort messages by "{}""#, sort))?;
+            let seqs = paginate(&parse_sort_res(&res), page_size, page).to_vec();
+            if seqs.is_empty() {
+                return Ok(Envelopes::default());
+            }
+
+            self.fetch_envelopes(&seqs)?
+        } else {
+            info!("server lacks SORT, falling back to client-side sorting");
+            let seqs: Vec<u32> = (1..=last_seq).collect();
+            let mut envelopes = self.fetch_envelopes(&seqs)?;
+            envelopes.sort_by(|a, b| sort.cmp_envelopes(a, b));
+            if *page_size > 0 {
+                envelopes = envelopes
+                    .into_iter()
+                    .skip(page * page_size)
+                    .take(*page_size)
+                    .collect();
+            }
+            envelopes
+        };
+
+        Ok(Envelopes(envelopes))
+    }
+
+    fn get_thread(&mut self, id: &str) -> Result<Envelopes> {
+        self.ensure_selected()?;
+        let seq = self.to_seq(id)?;
+
+        let threads = if self.has_cap("THREAD=REFERENCES")? {
+            info!("server supports THREAD=REFERENCES, threading messages server-side");
+            let res = self
+                .sess()?
+                .run_command_and_read_response("THREAD REFERENCES UTF-8 ALL")
+                .context("cannot thread messages")?;
+            parse_thread_res(&res)
+        } else {
+            info!("server lacks THREAD=REFERENCES, falling back to client-side threading");
+            let fetches = self
+                .sess()?
+                .fetch("1:*", "BODY.PEEK[HEADER]")
+                .context("cannot fetch messages headers")?;
+            let mut refs = vec![];
+            for fetch in fetches.iter() {
+                let (headers, _) = mailparse::parse_headers(fetch.header().unwrap_or_default())
+                    .context(format!("cannot parse headers of message {}", fetch.message))?;
+                refs.push(MsgRefs::from((fetch.message, headers.as_slice())));
+            }
+            thread_by_refs(&refs)
+        };
+
+        let thread = threads
+            .into_iter()
+            .find(|thread| thread.contains(&seq))
+            .unwrap_or_else(|| vec![seq]);
+        let mut seqs = thread;
+        seqs.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(Envelopes(self.fetch_envelopes(&seqs)?))
+    }
+
+    fn search_envelopes(
+        &mut self,
+        query: &str,
+        page_size: &usize,
+        page: &usize,
+    ) -> Result<Envelopes> {
+        self.retry("search envelopes", |imap| {
+            imap.search_envelopes_once(query, page_size, page)
+        })
+    }
+
+    /// Find a message by UID, or by sequence number if sequence numbers are used.
+    fn get_msg(&mut self, id: &str) -> Result<Msg> {
+        self.retry("fetch message", |imap| imap.get_msg_once(id))
+    }
+
+    fn get_raw_msg(&mut self, id: &str) -> Result<Vec<u8>> {
+        self.retry("fetch raw message", |imap| imap.get_raw_msg_once(id))
+    }
+
+    fn get_msgs(&mut self, seq_range: &str) -> Result<Vec<Msg>> {
+        self.retry("fetch messages", |imap| imap.get_msgs_once(seq_range))
+    }
+
+    fn get_raw_msgs(&mut self, seq_range: &str) -> Result<Vec<Vec<u8>>> {
+        self.retry("fetch raw messages", |imap| {
+            imap.get_raw_msgs_once(seq_range)
+        })
+    }
+
+    fn get_internal_dates(&mut self, seq_range: &str) -> Result<Vec<(u32, DateTime<FixedOffset>)>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq_range, "INTERNALDATE")
+        } else {
+            sess.uid_fetch(seq_range, "INTERNALDATE")
+        }
+        .context(format!(r#"cannot fetch dates of messages "{}""#, seq_range))?;
+
+        Ok(fetches
+            .iter()
+            .filter_map(|fetch| {
+                let id = fetch.uid.unwrap_or(fetch.message);
+                fetch.internal_date().map(|date| (id, date))
+            })
+            .collect())
+    }
+
+    fn get_flags(&mut self, seq_range: &str) -> Result<Vec<(u32, Flags)>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq_range, "FLAGS")
+        } else {
+            sess.uid_fetch(seq_range, "FLAGS")
+        }
+        .context(format!(r#"cannot fetch flags of messages "{}""#, seq_range))?;
+
+        fetches
+            .iter()
+            .map(|fetch| {
+                let id = fetch.uid.unwrap_or(fetch.message);
+                Ok((id, Flags::try_from(fetch.flags())?))
+            })
+            .collect()
+    }
+
+    fn get_raw_headers(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<u8>)>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq_range, "BODY.PEEK[HEADER]")
+        } else {
+            sess.uid_fetch(seq_range, "BODY.PEEK[HEADER]")
+        }
+        .context(format!(
+            r#"cannot fetch headers of messages "{}""#,
+            seq_range
+        ))?;
+
+        Ok(fetches
+            .iter()
+            .map(|fetch| {
+                let id = fetch.uid.unwrap_or(fetch.message);
+                (id, fetch.header().map(Vec::from).unwrap_or_default())
+            })
+            .collect())
+    }
+
+    fn peek_raw_msgs(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<u8>)>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq_range, "BODY.PEEK[]")
+        } else {
+            sess.uid_fetch(seq_range, "BODY.PEEK[]")
+        }
+        .context(format!(r#"cannot fetch raw messages "{}""#, seq_range))?;
+
+        Ok(fetches
+            .iter()
+            .map(|fetch| {
+                let id = fetch.uid.unwrap_or(fetch.message);
+                (id, fetch.body().map(Vec::from).unwrap_or_default())
+            })
+            .collect())
+    }
+
+    fn peek_partial_raw_msgs(
+        &mut self,
+        seq_range: &str,
+        max_size: usize,
+    ) -> Result<Vec<(u32, Vec<u8>, usize)>> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let items = format!("(RFC822.SIZE BODY.PEEK[]<0.{}>)", max_size);
+        let fetches = if use_seq {
+            sess.fetch(seq_range, &items)
+        } else {
+            sess.uid_fetch(seq_range, &items)
+        }
+        .context(format!(r#"cannot fetch raw messages "{}""#, seq_range))?;
+
+        Ok(fetches
+            .iter()
+            .map(|fetch| {
+                let id = fetch.uid.unwrap_or(fetch.message);
+                let raw_msg = fetch.body().map(Vec::from).unwrap_or_default();
+                let size = fetch
+                    .size
+                    .map(|size| size as usize)
+                    .unwrap_or(raw_msg.len());
+                (id, raw_msg, size)
+            })
+            .collect())
+    }
+
+    fn get_part_metas(&mut self, seq: &str) -> Result<PartMetas> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq, "BODYSTRUCTURE")
+        } else {
+            sess.uid_fetch(seq, "BODYSTRUCTURE")
+        }
+        .context(format!(r#"cannot fetch structure of message "{}""#, seq))?;
+        let fetch = fetches
+            .first()
+            .ok_or_else(|| anyhow!(r#"cannot find message "{}""#, seq))?;
+        let structure = fetch
+            .bodystructure()
+            .ok_or_else(|| anyhow!(r#"cannot get structure of message "{}""#, seq))?;
+
+        Ok(PartMetas::from_bodystructure(
+            fetch.uid.unwrap_or(fetch.message),
+            structure,
+        ))
+    }
+
+    fn get_part(&mut self, seq: &str, section: &str) -> Result<(String, Vec<u8>)> {
+        self.ensure_selected()?;
+        let path: Vec<u32> = section
+            .split('.')
+            .map(|n| n.parse())
+            .collect::<Result<_, _>>()
+            .context(format!(r#"cannot parse part section "{}""#, section))?;
+        let items = format!("(BODYSTRUCTURE BODY.PEEK[{}])", section);
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        let fetches = if use_seq {
+            sess.fetch(seq, &items)
+        } else {
+            sess.uid_fetch(seq, &items)
+        }
+        .context(format!(
+            r#"cannot fetch part "{}" of message "{}""#,
+            section, seq
+        ))?;
+        let fetch = fetches
+            .first()
+            .ok_or_else(|| anyhow!(r#"cannot find message "{}""#, seq))?;
+
+        let meta = fetch
+            .bodystructure()
+            .map(|structure| PartMetas::from_bodystructure(0, structure))
+            .and_then(|metas| metas.0.into_iter().find(|meta| meta.section == section))
+            .ok_or_else(|| anyhow!(r#"cannot find part "{}" of message "{}""#, section, seq))?;
+        let content = fetch
+            .section(&SectionPath::Part(path, None))
+            .unwrap_or_default();
+
+        Ok((meta.mime, decode_part(&meta.encoding, content)?))
+    }
+
+    fn append_raw(&mut self, mbox: &Mbox, msg: &[u8], flags: Flags) -> Result<()> {
+        self.check_append_limit(mbox, msg)?;
+        let name = self.resolve_mbox(mbox)?;
         self.sess()?
-            .store(uid_seq, format!("-FLAGS ({})", flags))
+            .append(&name, &msg)
+            .flags(flags.0)
+            .finish()
+            .context(format!(r#"cannot append message to "{}""#, mbox.name))?;
+        Ok(())
+    }
+
+    fn append_raw_dated(
+        &mut self,
+        mbox: &Mbox,
+        msg: &[u8],
+        flags: Flags,
+        date: DateTime<FixedOffset>,
+    ) -> Result<()> {
+        self.check_append_limit(mbox, msg)?;
+        let name = self.resolve_mbox(mbox)?;
+        self.sess()?
+            .append(&name, &msg)
+            .flags(flags.0)
+            .internal_date(date)
+            .finish()
+            .context(format!(r#"cannot append message to "{}""#, mbox.name))?;
+        Ok(())
+    }
+
+    fn append(&mut self, mbox: &Mbox, msg: Msg) -> Result<()> {
+        let msg_raw: Vec<u8> = (&msg).try_into()?;
+        self.check_append_limit(mbox, &msg_raw)?;
+        let name = self.resolve_mbox(mbox)?;
+        self.sess()?
+            .append(&name, &msg_raw)
+            .flags(msg.flags.0)
+            .finish()
+            .context(format!(r#"cannot append message to "{}""#, mbox.name))?;
+        Ok(())
+    }
+
+    fn notify(&mut self, config: &Config, keepalive: u64, standby: bool) -> Result<()> {
+        let mut msgs_set = None;
+        self.resume("notify new messages", |imap| {
+            let res = imap.notify_loop(config, keepalive, standby, &mut msgs_set);
+            if res.is_err() {
+                metrics::incr(imap.account, Metric::Errors, 1);
+                metrics::set_connected(imap.account, false);
+            }
+            res
+        })
+    }
+
+    fn watch(
+        &mut self,
+        keepalive: u64,
+        on_event: &mut dyn FnMut(WatchEvent) -> Result<()>,
+    ) -> Result<()> {
+        self.resume("watch mailbox", |imap| imap.watch_loop(keepalive, on_event))
+    }
+
+    fn logout(&mut self) -> Result<()> {
+        if let Some(ref mut sess) = self.sess {
+            debug!("logout from IMAP server");
+            sess.logout().context("cannot logout from IMAP server")?;
+        }
+        Ok(())
+    }
+
+    fn add_flags(&mut self, seq_range: &str, flags: &Flags) -> Result<()> {
+        let flags = flags.to_string();
+        self.ensure_selected()?;
+        self.store(seq_range, &format!("+FLAGS ({})", flags))
+            .context(format!(r#"cannot add flags "{}""#, &flags))?;
+        Ok(())
+    }
+
+    fn set_flags(&mut self, uid_seq: &str, flags: &Flags) -> Result<()> {
+        self.ensure_selected()?;
+        self.store(uid_seq, &format!("FLAGS ({})", flags))
+            .context(format!(r#"cannot set flags "{}""#, &flags))?;
+        Ok(())
+    }
+
+    fn remove_flags(&mut self, uid_seq: &str, flags: &Flags) -> Result<()> {
+        let flags = flags.to_string();
+        self.ensure_selected()?;
+        self.store(uid_seq, &format!("-FLAGS ({})", flags))
             .context(format!(r#"cannot remove flags "{}""#, &flags))?;
         Ok(())
     }
 
+    fn get_labels(&mut self, seq_range: &str) -> Result<Vec<(u32, Vec<String>)>> {
+        let account = self.account;
+        let hold_keyword = &account.hold_keyword;
+        if !self.has_cap(GMAIL_EXT)? {
+            return Ok(self
+                .get_flags(seq_range)?
+                .into_iter()
+                .map(|(id, flags)| (id, flags.labels(hold_keyword)))
+                .collect());
+        }
+        self.ensure_selected()?;
+        Ok(self
+            .fetch_gm_attrs(seq_range)?
+            .into_iter()
+            .map(|mut attr| {
+                attr.labels
+                    .retain(|label| !label.eq_ignore_ascii_case(hold_keyword));
+                (attr.id, attr.labels)
+            })
+            .collect())
+    }
+
+    fn add_labels(&mut self, seq_range: &str, labels: &[String]) -> Result<()> {
+        if !self.has_cap(GMAIL_EXT)? {
+            return self.add_flags(seq_range, &Flags::from_labels(labels)?);
+        }
+        self.ensure_selected()?;
+        self.store_gm_labels(seq_range, '+', labels)
+            .context(format!(r#"cannot add labels "{}""#, labels.join(" ")))
+    }
+
+    fn remove_labels(&mut self, seq_range: &str, labels: &[String]) -> Result<()> {
+        if !self.has_cap(GMAIL_EXT)? {
+            return self.remove_flags(seq_range, &Flags::from_labels(labels)?);
+        }
+        self.ensure_selected()?;
+        self.store_gm_labels(seq_range, '-', labels)
+            .context(format!(r#"cannot remove labels "{}""#, labels.join(" ")))
+    }
+
+    fn move_msg(&mut self, seq: &str, mbox: &Mbox) -> Result<()> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let name = self.resolve_mbox(mbox)?;
+
+        if self.has_cap("MOVE")? {
+            info!("server supports MOVE, moving message(s) natively");
+            let sess = self.sess()?;
+            if use_seq {
+                sess.mv(seq, &name)
+            } else {
+                sess.uid_mv(seq, &name)
+            }
+            .context(format!(
+                r#"cannot move message(s) "{}" to "{}""#,
+                seq, mbox.name
+            ))?;
+        } else {
+            info!("server lacks MOVE, falling back to COPY+STORE+EXPUNGE");
+            let account = self.account;
+            msg_hold::move_by_copy(seq, mbox, account, self)?;
+        }
+
+        Ok(())
+    }
+
+    fn copy_msgs(&mut self, seq_range: &str, mbox: &Mbox) -> Result<()> {
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let name = self.resolve_mbox(mbox)?;
+        let sess = self.sess()?;
+        if use_seq {
+            sess.copy(seq_range, &name)
+        } else {
+            sess.uid_copy(seq_range, &name)
+        }
+        .context(format!(
+            r#"cannot copy message(s) "{}" to "{}""#,
+            seq_range, mbox.name
+        ))?;
+        Ok(())
+    }
+
     fn expunge(&mut self) -> Result<()> {
+        self.ensure_selected()?;
         self.sess()?
             .expunge()
             .context(format!(r#"cannot expunge mailbox "{}""#, self.mbox.name))?;
         Ok(())
     }
+
+    fn expunge_msgs(&mut self, seq_range: &str) -> Result<bool> {
+        if !self.has_cap("UIDPLUS")? {
+            return Ok(false);
+        }
+        self.ensure_selected()?;
+        let use_seq = self.use_seq;
+        let sess = self.sess()?;
+        // UID EXPUNGE only takes UIDs.
+        let uids = if use_seq {
+            let fetches = sess
+                .fetch(seq_range, "UID")
+                .context(format!(r#"cannot fetch UIDs of messages "{}""#, seq_range))?;
+            let uids: Vec<u32> = fetches.iter().filter_map(|fetch| fetch.uid).collect();
+            to_seq_set(&uids)
+        } else {
+            seq_range.to_owned()
+        };
+        if !uids.is_empty() {
+            sess.uid_expunge(&uids)
+                .context(format!(r#"cannot expunge message(s) "{}""#, seq_range))?;
+        }
+        Ok(true)
+    }
+
+    fn keepalive(&mut self) -> Result<()> {
+        if let Some(ref mut sess) = self.sess {
+            if sess.noop().is_err() {
+                debug!("IMAP session closed by the server");
+                self.sess = None;
+                self.selected = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Log out when an operation is interrupted, instead of leaving the session hanging.
+impl Drop for ImapService<'_> {
+    fn drop(&mut self) {
+        if output_interrupt::is_interrupted() {
+            if let Some(mut sess) = self.sess.take() {
+                debug!("logout from IMAP server after interruption");
+                sess.logout().ok();
+            }
+        }
+    }
 }
 
 impl<'a> From<(&'a Account, &'a Mbox)> for ImapService<'a> {
@@ -386,6 +1667,710 @@ impl<'a> From<(&'a Account, &'a Mbox)> for ImapService<'a> {
             account,
             mbox,
             sess: None,
+            caps: None,
+            namespaces: None,
+            selected: false,
+            use_seq: false,
+        }
+    }
+}
+
+/// Get the sequence numbers of the given page of a mailbox holding `last_seq` messages, most
+/// recent messages first. A page size of 0 means no pagination.
+fn page_seqs(last_seq: i64, page_size: &usize, page: &usize) -> Vec<u32> {
+    // TODO: add tests, improve error management when empty page
+    let (begin, end) = if *page_size > 0 {
+        let cursor = (page * page_size) as i64;
+        let begin = 1.max(last_seq - cursor);
+        let end = begin - begin.min(*page_size as i64) + 1;
+        (begin, end)
+    } else {
+        (last_seq, 1)
+    };
+    (end as u32..=begin as u32).rev().collect()
+}
+
+/// Paginate the given items. A page size of 0 means no pagination.
+fn paginate<'a, T>(items: &'a [T], page_size: &usize, page: &usize) -> &'a [T] {
+    if *page_size == 0 {
+        return items;
+    }
+    let begin = (page * page_size).min(items.len());
+    let end = (begin + page_size).min(items.len());
+    &items[begin..end]
+}
+
+/// Build a compact sequence set from the given sequence numbers, for example `1:3,5`.
+fn to_seq_set(seqs: &[u32]) -> String {
+    let mut seqs = seqs.to_vec();
+    seqs.sort_unstable();
+    seqs.dedup();
+
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for seq in seqs {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == seq => *end = seq,
+            _ => ranges.push((seq, seq)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|(begin, end)| {
+            if begin == end {
+                begin.to_string()
+            } else {
+                format!("{}:{}", begin, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quote the given string, so that it can be sent as an IMAP argument.
+fn quote(s: &str) -> String {
+    format!(r#""{}""#, s.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// Split the given IMAP response line into its strings, quoted or not, unquoting them.
+fn split_strings(line: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => continue,
+            '"' => {
+                let mut string = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => string.extend(chars.next()),
+                        '"' => break,
+                        c => string.push(c),
+                    }
+                }
+                strings.push(string);
+            }
+            c => {
+                let mut string = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    string.push(c);
+                }
+                strings.push(string);
+            }
+        }
+    }
+    strings
+}
+
+/// Parse the entries from a raw GETACL response.
+///
+/// [RFC4314]: https://datatracker.ietf.org/doc/html/rfc4314#section-3.6
+fn parse_acl_res(res: &[u8]) -> Vec<AclEntry> {
+    String::from_utf8_lossy(res)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("* ACL "))
+        .flat_map(|acl| {
+            let strings = split_strings(acl);
+            // The first string is the mailbox name.
+            strings
+                .get(1..)
+                .unwrap_or_default()
+                .chunks(2)
+                .filter_map(|entry| match entry {
+                    [identifier, rights] => Some(AclEntry {
+                        identifier: identifier.to_owned(),
+                        rights: rights.to_owned(),
+                    }),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Parse the prefixes of the personal, other users and shared namespaces from a raw NAMESPACE
+/// response.
+///
+/// [RFC2342]: https://datatracker.ietf.org/doc/html/rfc2342#section-5
+fn parse_namespace_res(res: &[u8]) -> Vec<(NamespaceKind, String)> {
+    let res = String::from_utf8_lossy(res);
+    let line = match res
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("* NAMESPACE "))
+    {
+        Some(line) => line,
+        None => return vec![],
+    };
+
+    let kinds = [
+        NamespaceKind::Personal,
+        NamespaceKind::OtherUsers,
+        NamespaceKind::Shared,
+    ];
+    let mut namespaces = vec![];
+    let mut group = 0;
+    let mut depth = 0;
+    // The prefix is the first string of each namespace description.
+    let mut expects_prefix = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => {
+                depth += 1;
+                expects_prefix = depth == 2;
+            }
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    group += 1;
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => string.extend(chars.next()),
+                        '"' => break,
+                        c => string.push(c),
+                    }
+                }
+                if expects_prefix {
+                    if let Some(kind) = kinds.get(group) {
+                        namespaces.push((*kind, string));
+                    }
+                    expects_prefix = false;
+                }
+            }
+            // A NIL group means no namespace of this kind.
+            'N' | 'n' if depth == 0 => {
+                chars.nth(1);
+                group += 1;
+            }
+            _ => (),
+        }
+    }
+    namespaces
+}
+
+/// Resolve the given mailbox name against the personal namespace: on servers nesting personal
+/// mailboxes under a prefix, like `INBOX.` on Courier, `Sent` resolves to `INBOX.Sent`. INBOX,
+/// names already prefixed and names of other namespaces are left untouched.
+fn resolve_mbox_name(name: &str, namespaces: &[(NamespaceKind, String)]) -> String {
+    let starts_with = |prefix: &str| {
+        name.get(..prefix.len())
+            .map(|start| start.eq_ignore_ascii_case(prefix))
+            .unwrap_or_default()
+    };
+    let personal = namespaces
+        .iter()
+        .find(|(kind, _)| *kind == NamespaceKind::Personal)
+        .map(|(_, prefix)| prefix.as_str())
+        .unwrap_or_default();
+    if personal.is_empty()
+        || name.eq_ignore_ascii_case("INBOX")
+        || namespaces
+            .iter()
+            .any(|(_, prefix)| !prefix.is_empty() && starts_with(prefix))
+    {
+        return name.to_owned();
+    }
+    format!("{}{}", personal, name)
+}
+
+/// Parse the append limit from a raw STATUS response. A `NIL` limit means no limit.
+///
+/// [RFC7889]: https://datatracker.ietf.org/doc/html/rfc7889#section-3.2
+fn parse_append_limit_res(res: &[u8]) -> Option<usize> {
+    let res = String::from_utf8_lossy(res).to_uppercase();
+    let (_, limit) = res.split_once("APPENDLIMIT ")?;
+    limit
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|limit| limit.parse().ok())
+}
+
+/// Parse the numbers of messages and of unseen messages from a raw STATUS response. The items
+/// are read after the last parenthesis, the mailbox name being able to contain any of them.
+///
+/// [RFC3501]: https://datatracker.ietf.org/doc/html/rfc3501#section-7.2.4
+fn parse_status_res(res: &[u8]) -> Option<(usize, usize)> {
+    let res = String::from_utf8_lossy(res).to_uppercase();
+    let line = res.lines().find(|line| line.starts_with("* STATUS "))?;
+    let (_, items) = line.rsplit_once('(')?;
+    let mut items = items.trim_end().trim_end_matches(')').split_whitespace();
+    let (mut messages, mut unseen) = (None, None);
+    while let (Some(name), Some(value)) = (items.next(), items.next()) {
+        match name {
+            "MESSAGES" => messages = value.parse().ok(),
+            "UNSEEN" => unseen = value.parse().ok(),
+            _ => (),
+        }
+    }
+    Some((messages?, unseen?))
+}
+
+/// Parse the quota resources from a raw GETQUOTAROOT response.
+///
+/// [RFC2087]: https://datatracker.ietf.org/doc/html/rfc2087#section-5.1
+fn parse_quota_res(res: &[u8]) -> Vec<Quota> {
+    let mut quotas = vec![];
+    for line in String::from_utf8_lossy(res).lines() {
+        let quota = match line.trim().strip_prefix("* QUOTA ") {
+            Some(quota) => quota,
+            None => continue,
+        };
+        let (root, resources) = match quota.split_once('(') {
+            Some(quota) => quota,
+            None => continue,
+        };
+        let root = root.trim().trim_matches('"');
+        let resources: Vec<&str> = resources.trim_end_matches(')').split_whitespace().collect();
+        for resource in resources.chunks(3) {
+            if let [name, usage, limit] = resource {
+                if let (Ok(usage), Ok(limit)) = (usage.parse(), limit.parse()) {
+                    quotas.push(Quota {
+                        root: root.to_owned(),
+                        resource: name.to_uppercase(),
+                        usage,
+                        limit,
+                    });
+                }
+            }
+        }
+    }
+    quotas
+}
+
+/// Parse the sequence numbers from a raw SORT response.
+///
+/// [RFC5256]: https://datatracker.ietf.org/doc/html/rfc5256#section-4
+fn parse_sort_res(res: &[u8]) -> Vec<u32> {
+    String::from_utf8_lossy(res)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("* SORT"))
+        .flat_map(|seqs| seqs.split_whitespace())
+        .filter_map(|seq| seq.parse().ok())
+        .collect()
+}
+
+/// Parse the threads from a raw THREAD response. Each thread is flattened into the list of its
+/// sequence numbers.
+///
+/// [RFC5256]: https://datatracker.ietf.org/doc/html/rfc5256#section-4
+fn parse_thread_res(res: &[u8]) -> Vec<Vec<u32>> {
+    let mut threads = vec![];
+
+    for line in String::from_utf8_lossy(res).lines() {
+        let line = match line.trim().strip_prefix("* THREAD") {
+            Some(line) => line,
+            None => continue,
+        };
+
+        let mut depth = 0;
+        let mut thread = vec![];
+        let mut seq = String::new();
+        for c in line.chars() {
+            if c.is_ascii_digit() {
+                seq.push(c);
+                continue;
+            }
+            if let Ok(n) = seq.parse() {
+                thread.push(n);
+            }
+            seq.clear();
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        threads.push(thread);
+                        thread = vec![];
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    threads
+}
+
+/// Define the capability of the Gmail extensions, see [Gmail IMAP extensions].
+///
+/// [Gmail IMAP extensions]: https://developers.google.com/gmail/imap/imap-extensions
+pub const GMAIL_EXT: &str = "X-GM-EXT-1";
+
+/// Define the search key of Gmail labels, see [X-GM-LABELS].
+///
+/// [X-GM-LABELS]: https://developers.google.com/gmail/imap/imap-extensions#access_to_gmail_labels_x-gm-labels
+const GM_LABELS_KEY: &str = "X-GM-LABELS";
+
+/// Define the search key of Gmail queries, see [X-GM-RAW].
+///
+/// [X-GM-RAW]: https://developers.google.com/gmail/imap/imap-extensions#extension_of_the_search_command_x-gm-raw
+const GM_RAW_KEY: &str = "X-GM-RAW";
+
+/// Represents the Gmail attributes of a message.
+#[derive(Debug, Default, PartialEq)]
+struct GmAttrs {
+    /// The UID of the message, or its sequence number when sequence numbers are used.
+    id: u32,
+    labels: Vec<String>,
+    /// The [X-GM-THRID] of the message, kept as a string since it does not fit in a JSON number.
+    ///
+    /// [X-GM-THRID]: https://developers.google.com/gmail/imap/imap-extensions#access_to_the_gmail_thread_id_x-gm-thrid
+    thread_id: Option<String>,
+}
+
+/// Parse the Gmail attributes from a raw X-GM-LABELS and X-GM-THRID fetch response.
+fn parse_gm_attrs_res(res: &[u8], use_seq: bool) -> Vec<GmAttrs> {
+    let prefix = format!("{} (", GM_LABELS_KEY);
+    String::from_utf8_lossy(res)
+        .lines()
+        .filter_map(|line| {
+            let (seq, items) = line.trim().strip_prefix("* ")?.split_once(' ')?;
+            let items = items.strip_prefix("FETCH (")?;
+            let start = items.find(&prefix)? + prefix.len();
+            // Labels with parentheses are quoted.
+            let (mut in_quotes, mut escaped) = (false, false);
+            let (len, _) = items[start..].char_indices().find(|(_, c)| {
+                match *c {
+                    _ if escaped => escaped = false,
+                    '\\' if in_quotes => escaped = true,
+                    '"' => in_quotes = !in_quotes,
+                    ')' if !in_quotes => return true,
+                    _ => (),
+                }
+                false
+            })?;
+            let labels = split_strings(&items[start..start + len]);
+
+            // The other items are numbers.
+            let rest = format!("{} {}", &items[..start], &items[start + len..]);
+            let item = |name: &str| {
+                let mut words = rest.split_whitespace().skip_while(|word| *word != name);
+                words
+                    .nth(1)
+                    .map(|value| value.trim_end_matches(')').to_owned())
+            };
+            let id = if use_seq {
+                seq.parse().ok()?
+            } else {
+                item("UID")?.parse().ok()?
+            };
+            Some(GmAttrs {
+                id,
+                labels,
+                thread_id: item("X-GM-THRID"),
+            })
+        })
+        .collect()
+}
+
+/// Find the position of the given search key in the given search criteria, outside of quoted
+/// strings.
+fn find_key(criteria: &str, key: &str) -> Option<usize> {
+    let (mut in_quotes, mut escaped, mut word_start) = (false, false, true);
+    for (i, c) in criteria.char_indices() {
+        let is_key = criteria
+            .get(i..i + key.len())
+            .map_or(false, |word| word.eq_ignore_ascii_case(key))
+            && criteria[i + key.len()..].starts_with(' ');
+        if word_start && !in_quotes && is_key {
+            return Some(i);
+        }
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            _ => (),
         }
+        word_start = !in_quotes && (c.is_whitespace() || c == '(');
+    }
+    None
+}
+
+/// Replace the Gmail label criteria of the given search criteria by keyword criteria, for the
+/// servers lacking the X-GM-EXT-1 extension. Quoted strings are left as is.
+fn to_keyword_criteria(criteria: &str) -> String {
+    let mut criteria = criteria.to_owned();
+    while let Some(pos) = find_key(&criteria, GM_LABELS_KEY) {
+        criteria.replace_range(pos..pos + GM_LABELS_KEY.len(), "KEYWORD");
+    }
+    criteria
+}
+
+/// Represents the threading headers of a message.
+#[derive(Debug, Default)]
+struct MsgRefs {
+    seq: u32,
+    message_id: Option<String>,
+    refs: Vec<String>,
+    /// The subject without its reply and forward prefixes.
+    base_subject: String,
+    is_reply: bool,
+}
+
+impl From<(u32, &[MailHeader<'_>])> for MsgRefs {
+    fn from((seq, headers): (u32, &[MailHeader<'_>])) -> Self {
+        let ids = |key: &str| {
+            headers
+                .get_all_values(key)
+                .iter()
+                .flat_map(|val| val.split_whitespace().map(String::from).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        let mut refs = ids("References");
+        refs.extend(ids("In-Reply-To"));
+        let subject = headers.get_first_value("Subject").unwrap_or_default();
+        Self {
+            seq,
+            message_id: ids("Message-ID").into_iter().next(),
+            refs,
+            base_subject: msg_utils::base_subject(&subject).to_lowercase(),
+            is_reply: msg_utils::is_reply_subject(&subject),
+        }
+    }
+}
+
+/// Group messages into threads using their Message-ID, In-Reply-To and References headers. Replies
+/// without any reference are attached to the first message sharing their base subject. This is
+/// used when the server does not support the THREAD extension.
+fn thread_by_refs(msgs: &[MsgRefs]) -> Vec<Vec<u32>> {
+    fn root(parents: &mut [usize], i: usize) -> usize {
+        let mut i = i;
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let ids: HashMap<&str, usize> = msgs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, msg)| msg.message_id.as_deref().map(|id| (id, i)))
+        .collect();
+    let mut parents: Vec<usize> = (0..msgs.len()).collect();
+
+    for (i, msg) in msgs.iter().enumerate() {
+        for id in msg.refs.iter() {
+            if let Some(j) = ids.get(id.as_str()) {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, *j));
+                parents[a] = b;
+            }
+        }
+    }
+
+    let mut subjects: HashMap<&str, usize> = HashMap::new();
+    for (i, msg) in msgs.iter().enumerate() {
+        if msg.base_subject.is_empty() {
+            continue;
+        }
+        match subjects.get(msg.base_subject.as_str()) {
+            Some(j) if msg.refs.is_empty() && msg.is_reply => {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, *j));
+                parents[a] = b;
+            }
+            Some(_) => (),
+            None => {
+                subjects.insert(&msg.base_subject, i);
+            }
+        }
+    }
+
+    let mut threads: Vec<Vec<u32>> = vec![];
+    let mut thread_idx: HashMap<usize, usize> = HashMap::new();
+    for (i, msg) in msgs.iter().enumerate() {
+        let root = root(&mut parents, i);
+        match thread_idx.get(&root) {
+            Some(idx) => threads[*idx].push(msg.seq),
+            None => {
+                thread_idx.insert(root, threads.len());
+                threads.push(vec![msg.seq]);
+            }
+        }
+    }
+
+    threads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sort_response() {
+        let res = b"* SORT 2 84 882\r\n";
+        assert_eq!(vec![2, 84, 882], parse_sort_res(res));
+        assert_eq!(Vec::<u32>::new(), parse_sort_res(b"* SORT\r\n"));
+    }
+
+    #[test]
+    fn parse_notify_action() {
+        assert_eq!(Some(NotifyAction::Read), NotifyAction::parse("read\n"));
+        assert_eq!(Some(NotifyAction::Delete), NotifyAction::parse(" Delete "));
+        assert_eq!(None, NotifyAction::parse(""));
+    }
+
+    #[test]
+    fn build_seq_set() {
+        assert_eq!("1:3,5,7:8", to_seq_set(&[8, 2, 1, 3, 5, 7, 2]));
+        assert_eq!("", to_seq_set(&[]));
+    }
+
+    #[test]
+    fn parse_quota_response() {
+        let res = b"* QUOTAROOT INBOX \"\"\r\n* QUOTA \"\" (STORAGE 10 512 MESSAGE 2 100)\r\n";
+        let quotas = parse_quota_res(res);
+        assert_eq!(2, quotas.len());
+        assert_eq!(
+            ("STORAGE", 10, 512),
+            (
+                quotas[0].resource.as_str(),
+                quotas[0].usage,
+                quotas[0].limit
+            )
+        );
+        assert_eq!("MESSAGE", quotas[1].resource);
+        assert!(parse_quota_res(b"* QUOTAROOT INBOX\r\n").is_empty());
+    }
+
+    #[test]
+    fn parse_acl_and_namespace_responses() {
+        let res = b"* ACL \"Shared/Team\" alice lrswipkxtea \"team leads\" lr\r\nA0001 OK done\r\n";
+        assert_eq!(
+            vec![
+                AclEntry {
+                    identifier: String::from("alice"),
+                    rights: String::from("lrswipkxtea"),
+                },
+                AclEntry {
+                    identifier: String::from("team leads"),
+                    rights: String::from("lr"),
+                },
+            ],
+            parse_acl_res(res)
+        );
+
+        let res = b"* NAMESPACE ((\"\" \"/\")) NIL ((\"Shared/\" \"/\")(\"#public/\" \"/\" \"X-PARAM\" (\"x\")))\r\n";
+        assert_eq!(
+            vec![
+                (NamespaceKind::Personal, String::from("")),
+                (NamespaceKind::Shared, String::from("Shared/")),
+                (NamespaceKind::Shared, String::from("#public/")),
+            ],
+            parse_namespace_res(res)
+        );
+        assert_eq!(r#""a \"b\"""#, quote(r#"a "b""#));
+    }
+
+    #[test]
+    fn resolve_mbox_names() {
+        let namespaces = vec![
+            (NamespaceKind::Personal, String::from("INBOX.")),
+            (NamespaceKind::Shared, String::from("Shared.")),
+        ];
+        assert_eq!("INBOX.Sent", resolve_mbox_name("Sent", &namespaces));
+        assert_eq!("INBOX.Sent", resolve_mbox_name("INBOX.Sent", &namespaces));
+        assert_eq!("inbox.Sent", resolve_mbox_name("inbox.Sent", &namespaces));
+        assert_eq!("INBOX", resolve_mbox_name("INBOX", &namespaces));
+        assert_eq!("Shared.Team", resolve_mbox_name("Shared.Team", &namespaces));
+        assert_eq!("Sent", resolve_mbox_name("Sent", &[]));
+    }
+
+    #[test]
+    fn parse_append_limit_response() {
+        let res = b"* STATUS INBOX (APPENDLIMIT 257890)\r\nA0001 OK done\r\n";
+        assert_eq!(Some(257890), parse_append_limit_res(res));
+        let res = b"* STATUS INBOX (APPENDLIMIT NIL)\r\nA0001 OK done\r\n";
+        assert_eq!(None, parse_append_limit_res(res));
+    }
+
+    #[test]
+    fn parse_status_response() {
+        let res = b"* STATUS \"Unseen (MESSAGES 1)\" (MESSAGES 231 UNSEEN 12)\r\nA0001 OK done\r\n";
+        assert_eq!(Some((231, 12)), parse_status_res(res));
+        assert_eq!(None, parse_status_res(b"A0001 NO no such mailbox\r\n"));
+    }
+
+    #[test]
+    fn parse_thread_response() {
+        let res = b"* THREAD (2)(3 6 (4 23)(44 7 96))\r\n";
+        assert_eq!(
+            vec![vec![2], vec![3, 6, 4, 23, 44, 7, 96]],
+            parse_thread_res(res)
+        );
+    }
+
+    #[test]
+    fn parse_gm_attrs_response() {
+        let res = concat!(
+            "* 1 FETCH (X-GM-THRID 1278455344230334865 X-GM-LABELS (\\Inbox \"Work (2021)\" $label1) UID 42)\r\n",
+            "* 2 FETCH (UID 43 X-GM-LABELS ())\r\n",
+            "A1 OK Success\r\n",
+        );
+        assert_eq!(
+            vec![
+                GmAttrs {
+                    id: 42,
+                    labels: vec![
+                        String::from("\\Inbox"),
+                        String::from("Work (2021)"),
+                        String::from("$label1")
+                    ],
+                    thread_id: Some(String::from("1278455344230334865")),
+                },
+                GmAttrs {
+                    id: 43,
+                    labels: vec![],
+                    thread_id: None,
+                },
+            ],
+            parse_gm_attrs_res(res.as_bytes(), false)
+        );
+        assert_eq!(1, parse_gm_attrs_res(res.as_bytes(), true)[0].id);
+
+        assert_eq!(
+            r#"KEYWORD todo NOT SUBJECT "X-GM-LABELS todo""#,
+            to_keyword_criteria(r#"X-GM-LABELS todo NOT SUBJECT "X-GM-LABELS todo""#)
+        );
+        assert_eq!(
+            Some(8),
+            find_key(r#"NOT SEEN X-GM-RAW "has:attachment""#, GM_RAW_KEY)
+        );
+        assert_eq!(None, find_key(r#"SUBJECT "X-GM-RAW test""#, GM_RAW_KEY));
+    }
+
+    #[test]
+    fn thread_messages_by_refs() {
+        let msg = |seq, id: &str, refs: &[&str], subject: &str| MsgRefs {
+            seq,
+            message_id: Some(id.to_owned()),
+            refs: refs.iter().map(|id| id.to_string()).collect(),
+            base_subject: msg_utils::base_subject(subject).to_lowercase(),
+            is_reply: msg_utils::is_reply_subject(subject),
+        };
+        let msgs = vec![
+            msg(1, "<a@x>", &[], "Hello"),
+            msg(2, "<b@x>", &[], "Other"),
+            msg(3, "<c@x>", &["<a@x>"], "Re: Hello"),
+            msg(4, "<d@x>", &["<a@x>", "<c@x>"], "AW: Re: Hello"),
+            msg(5, "<e@x>", &[], "SV: other"),
+            msg(6, "<f@x>", &[], "Other"),
+        ];
+        assert_eq!(
+            vec![vec![1, 3, 4], vec![2, 5], vec![6]],
+            thread_by_refs(&msgs)
+        );
+    }
+
+    #[test]
+    fn paginate_items() {
+        let items = vec![1, 2, 3, 4, 5];
+        assert_eq!(&[3, 4], paginate(&items, &2, &1));
+        assert_eq!(&[5], paginate(&items, &2, &2));
+        assert!(paginate(&items, &2, &3).is_empty());
+        assert_eq!(&items[..], paginate(&items, &0, &3));
     }
 }
@@ -1,7 +1,25 @@
 //! Module related to IMAP.
 
+#[cfg(feature = "cli")]
 pub mod imap_arg;
+pub mod imap_compress;
+#[cfg(feature = "cli")]
 pub mod imap_handler;
 
+pub mod caps_report_entity;
+pub use caps_report_entity::*;
+
+pub mod envelope_cache_entity;
+pub use envelope_cache_entity::*;
+
 pub mod imap_service;
 pub use imap_service::*;
+
+pub mod raw_response_entity;
+pub use raw_response_entity::*;
+
+pub mod uid_validity_entity;
+pub use uid_validity_entity::*;
+
+pub mod watch_event_entity;
+pub use watch_event_entity::*;
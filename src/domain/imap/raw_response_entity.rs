@@ -0,0 +1,85 @@
+//! Module related to raw IMAP responses.
+//!
+//! Raw commands are sent as is on the authenticated session, with the current mailbox selected.
+//! Their untagged response lines are printed untouched, so that server quirks and extensions not
+//! modelled yet can be explored without another client.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Define the commands that cannot run raw, since they expect continuations or change the
+/// session in ways the backend does not track.
+const UNSUPPORTED_CMDS: [&str; 6] = [
+    "AUTHENTICATE",
+    "COMPRESS",
+    "IDLE",
+    "LOGIN",
+    "LOGOUT",
+    "STARTTLS",
+];
+
+/// Check that the given raw command can run on the session: a single line, without literal.
+pub fn check_raw_cmd(cmd: &str) -> Result<()> {
+    let verb = cmd.split_whitespace().next().unwrap_or_default();
+    if verb.is_empty() {
+        return Err(anyhow!("cannot run raw IMAP command: the command is empty"));
+    }
+    if cmd.contains(|c| c == '\r' || c == '\n') {
+        return Err(anyhow!(
+            "cannot run raw IMAP command: the command must fit on a single line"
+        ));
+    }
+    if cmd.trim_end().ends_with('}') {
+        return Err(anyhow!(
+            "cannot run raw IMAP command: literals are not supported"
+        ));
+    }
+    if UNSUPPORTED_CMDS.contains(&verb.to_uppercase().as_str()) {
+        return Err(anyhow!(
+            "cannot run raw IMAP command: {} is not supported",
+            verb.to_uppercase()
+        ));
+    }
+    Ok(())
+}
+
+/// Represents the untagged response lines of a raw command.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct RawResponse(pub Vec<String>);
+
+impl From<&[u8]> for RawResponse {
+    fn from(res: &[u8]) -> Self {
+        Self(
+            String::from_utf8_lossy(res)
+                .lines()
+                .map(|line| line.trim_end_matches('\r').to_owned())
+                .collect(),
+        )
+    }
+}
+
+impl Display for RawResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_check_raw_cmds() {
+        assert!(check_raw_cmd("GETMETADATA \"\" /shared/comment").is_ok());
+        assert!(check_raw_cmd("uid fetch 1:* (FLAGS)").is_ok());
+        assert!(check_raw_cmd("  ").is_err());
+        assert!(check_raw_cmd("NOOP\r\nLOGOUT").is_err());
+        assert!(check_raw_cmd("APPEND INBOX {42}").is_err());
+        assert!(check_raw_cmd("idle").is_err());
+
+        let res = RawResponse::from(&b"* ID (\"name\" \"Dovecot\")\r\n* OK\r\n"[..]);
+        assert_eq!(vec!["* ID (\"name\" \"Dovecot\")", "* OK"], res.0);
+        assert_eq!("* ID (\"name\" \"Dovecot\")\n* OK", res.to_string());
+    }
+}
@@ -0,0 +1,114 @@
+//! Module related to IMAP UIDVALIDITY.
+//!
+//! UIDs are only meaningful together with the UIDVALIDITY of their mailbox: when the server
+//! changes it, every UID known so far may point to a different message (or to none). This module
+//! keeps track of the last UIDVALIDITY seen for each mailbox, so that stale local state can be
+//! dropped instead of being applied to the wrong messages.
+//!
+//! See [RFC3501](https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.1).
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    config::Account,
+    domain::storage::{build_storage, mbox_prefix},
+};
+
+const KEY: &str = "uid-validity.json";
+
+/// Represents the result of a UIDVALIDITY check.
+#[derive(Debug, PartialEq)]
+pub enum UidValidityStatus {
+    /// The mailbox has never been seen before.
+    New,
+    /// The UIDVALIDITY did not change, known UIDs are still valid.
+    Unchanged,
+    /// The UIDVALIDITY changed, known UIDs are stale. Holds the previous value.
+    Changed(u32),
+}
+
+/// Represents the last known UIDVALIDITY of each mailbox, indexed by mailbox name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UidValidities(HashMap<String, u32>);
+
+impl UidValidities {
+    /// Load the known UIDVALIDITY values of the given account. A missing or corrupted item is
+    /// treated as empty, which only means that the next check reports mailboxes as new.
+    pub fn load(account: &Account) -> Result<Self> {
+        match build_storage(account)?.get(KEY)? {
+            Some(content) => Ok(serde_json::from_slice(&content).unwrap_or_else(|err| {
+                warn!("cannot parse {}: {}", KEY, err);
+                Self::default()
+            })),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, account: &Account) -> Result<()> {
+        let content = serde_json::to_vec(self).context("cannot serialize UIDVALIDITY")?;
+        build_storage(account)?
+            .set(KEY, &content)
+            .context("cannot save UIDVALIDITY")
+    }
+
+    /// Compare the given UIDVALIDITY with the known one, then remember it.
+    pub fn check(&mut self, mbox: &str, uid_validity: u32) -> UidValidityStatus {
+        match self.0.insert(mbox.to_owned(), uid_validity) {
+            None => UidValidityStatus::New,
+            Some(prev) if prev == uid_validity => UidValidityStatus::Unchanged,
+            Some(prev) => UidValidityStatus::Changed(prev),
+        }
+    }
+}
+
+/// Check the UIDVALIDITY of the given mailbox. When it changed, the mailbox cache is invalidated
+/// and the user is warned, so that no stale UID is ever reused.
+pub fn check_uid_validity(account: &Account, mbox: &str, uid_validity: u32) -> Result<()> {
+    let _lock = account.lock_cache()?;
+    let mut uid_validities = UidValidities::load(account)?;
+    let status = uid_validities.check(mbox, uid_validity);
+    debug!("UIDVALIDITY of {:?}: {} ({:?})", mbox, uid_validity, status);
+
+    match status {
+        UidValidityStatus::Unchanged => return Ok(()),
+        UidValidityStatus::New => (),
+        UidValidityStatus::Changed(prev) => {
+            build_storage(account)?
+                .remove_all(&mbox_prefix(mbox))
+                .context(format!(r#"cannot invalidate cache of mailbox "{}""#, mbox))?;
+            eprintln!(
+                r#"warning: UIDVALIDITY of mailbox "{}" changed ({} → {}), local state about its messages has been discarded"#,
+                mbox, prev, uid_validity
+            );
+        }
+    }
+
+    uid_validities.save(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_detect_uid_validity_changes() {
+        let mut uid_validities = UidValidities::default();
+        assert_eq!(UidValidityStatus::New, uid_validities.check("INBOX", 42));
+        assert_eq!(
+            UidValidityStatus::Unchanged,
+            uid_validities.check("INBOX", 42)
+        );
+        assert_eq!(
+            UidValidityStatus::Changed(42),
+            uid_validities.check("INBOX", 43)
+        );
+        assert_eq!(
+            UidValidityStatus::Unchanged,
+            uid_validities.check("INBOX", 43)
+        );
+        assert_eq!(UidValidityStatus::New, uid_validities.check("Sent", 43));
+    }
+}
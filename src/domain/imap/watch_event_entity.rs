@@ -0,0 +1,62 @@
+//! Module related to watch events.
+//!
+//! Watch events are the changes the server reports while a mailbox is watched, printed as they
+//! come by the watch command.
+
+use imap::types::UnsolicitedResponse;
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Represents a change of the watched mailbox.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WatchEvent {
+    pub mbox: String,
+    /// The kind of change: `exists`, `recent`, `expunge` or `other`.
+    pub kind: String,
+    /// The message count for `exists` and `recent`, the expunged sequence number for `expunge`.
+    pub seq: Option<u32>,
+}
+
+impl WatchEvent {
+    pub fn new(mbox: &str, res: &UnsolicitedResponse) -> Self {
+        let (kind, seq) = match res {
+            UnsolicitedResponse::Exists(count) => ("exists", Some(*count)),
+            UnsolicitedResponse::Recent(count) => ("recent", Some(*count)),
+            UnsolicitedResponse::Expunge(seq) => ("expunge", Some(*seq)),
+            _ => ("other", None),
+        };
+        Self {
+            mbox: mbox.to_owned(),
+            kind: kind.to_owned(),
+            seq,
+        }
+    }
+}
+
+impl Display for WatchEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.seq {
+            Some(seq) => write!(f, "{}: {} {}", self.mbox, self.kind, seq),
+            None => write!(f, "{}: {}", self.mbox, self.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_build_watch_events() {
+        let event = WatchEvent::new("INBOX", &UnsolicitedResponse::Exists(42));
+        assert_eq!("exists", event.kind);
+        assert_eq!(Some(42), event.seq);
+        assert_eq!("INBOX: exists 42", event.to_string());
+        assert_eq!(
+            r#"{"mbox":"INBOX","kind":"expunge","seq":3}"#,
+            serde_json::to_string(&WatchEvent::new("INBOX", &UnsolicitedResponse::Expunge(3)))
+                .unwrap()
+        );
+    }
+}
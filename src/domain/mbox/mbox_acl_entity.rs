@@ -0,0 +1,79 @@
+//! Module related to mailbox access control lists.
+//!
+//! Access control lists are defined by the [ACL] extension: each entry grants rights, one letter
+//! each, to an identifier (a user, a group, or `anyone`). Shared mailboxes of corporate servers
+//! are managed this way.
+//!
+//! [ACL]: https://datatracker.ietf.org/doc/html/rfc4314
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+use crate::ui::table::{Cell, Row, Table};
+
+/// Represents the rights granted to an identifier.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AclEntry {
+    pub identifier: String,
+    pub rights: String,
+}
+
+impl AclEntry {
+    /// Describe the rights of the entry, as defined by
+    /// [RFC4314](https://datatracker.ietf.org/doc/html/rfc4314#section-2.1).
+    pub fn describe_rights(&self) -> String {
+        self.rights
+            .chars()
+            .filter_map(|right| match right {
+                'l' => Some("lookup"),
+                'r' => Some("read"),
+                's' => Some("write seen"),
+                'w' => Some("write flags"),
+                'i' => Some("insert"),
+                'p' => Some("post"),
+                'k' => Some("create mailboxes"),
+                'x' => Some("delete mailbox"),
+                't' => Some("delete messages"),
+                'e' => Some("expunge"),
+                'a' => Some("administer"),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Represents the access control list of a mailbox.
+#[derive(Debug, Default, Serialize)]
+pub struct Acl {
+    pub mbox: String,
+    pub entries: Vec<AclEntry>,
+}
+
+impl Display for Acl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", Table::render(&self.entries))
+    }
+}
+
+impl Table for AclEntry {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("IDENTIFIER").bold().underline().white())
+            .cell(Cell::new("RIGHTS").bold().underline().white())
+            .cell(
+                Cell::new("DESCRIPTION")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.identifier).green())
+            .cell(Cell::new(&self.rights).yellow())
+            .cell(Cell::new(&self.describe_rights()).shrinkable().blue())
+    }
+}
@@ -3,18 +3,89 @@
 //! This module provides subcommands, arguments and a command matcher related to mailbox.
 
 use anyhow::Result;
-use clap::{App, Arg, ArgMatches, SubCommand};
-use log::debug;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+use crate::domain::msg::msg_arg::{self, OverrideHold};
+
+type Target<'a> = Option<&'a str>;
+type Name<'a> = &'a str;
+type Identifier<'a> = &'a str;
+type Rights<'a> = &'a str;
 
 /// Mailbox commands.
-pub enum Command {
+pub enum Command<'a> {
     /// List all available mailboxes.
     List,
+
+    /// Permanently remove the deleted messages of the given mailbox, or of the selected one.
+    Expunge(Target<'a>, OverrideHold),
+
+    /// Print the access control list of the given mailbox.
+    GetAcl(Name<'a>),
+
+    /// Grant the given rights to the given identifier on the given mailbox.
+    SetAcl(Name<'a>, Identifier<'a>, Rights<'a>),
+
+    /// Report statistics of the given mailbox, or of the selected one, with the given number of
+    /// top senders and largest messages.
+    Stats(Target<'a>, usize),
+
+    /// Print the quotas of the given mailbox, or of the selected one.
+    Quota(Target<'a>),
 }
 
 /// Mailbox command matcher.
-pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
-    if let Some(_) = m.subcommand_matches("mailboxes") {
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("stats") {
+        debug!("stats command matched");
+        let target = m.value_of("target");
+        trace!("target: {:?}", target);
+        let top = m
+            .value_of("top")
+            .and_then(|top| top.parse().ok())
+            .unwrap_or(10);
+        trace!("top: {}", top);
+        return Ok(Some(Command::Stats(target, top)));
+    }
+
+    if let Some(m) = m.subcommand_matches("mailboxes") {
+        if let Some(m) = m.subcommand_matches("expunge") {
+            debug!("expunge command matched");
+            let target = m.value_of("target");
+            trace!("target: {:?}", target);
+            let override_hold = m.is_present("override-hold");
+            trace!("override hold: {}", override_hold);
+            return Ok(Some(Command::Expunge(target, override_hold)));
+        }
+
+        if let Some(m) = m.subcommand_matches("quota") {
+            debug!("quota command matched");
+            let target = m.value_of("target");
+            trace!("target: {:?}", target);
+            return Ok(Some(Command::Quota(target)));
+        }
+
+        if let Some(m) = m.subcommand_matches("acl") {
+            if let Some(m) = m.subcommand_matches("get") {
+                debug!("get acl command matched");
+                let name = m.value_of("name").unwrap();
+                trace!("name: {}", name);
+                return Ok(Some(Command::GetAcl(name)));
+            }
+
+            if let Some(m) = m.subcommand_matches("set") {
+                debug!("set acl command matched");
+                let name = m.value_of("name").unwrap();
+                trace!("name: {}", name);
+                let identifier = m.value_of("identifier").unwrap();
+                trace!("identifier: {}", identifier);
+                let rights = m.value_of("rights").unwrap();
+                trace!("rights: {}", rights);
+                return Ok(Some(Command::SetAcl(name, identifier, rights)));
+            }
+        }
+
         debug!("mailboxes command matched");
         return Ok(Some(Command::List));
     }
@@ -24,9 +95,83 @@ pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
 
 /// Mailbox subcommands.
 pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
-    vec![SubCommand::with_name("mailboxes")
-        .aliases(&["mailbox", "mboxes", "mbox", "mb", "m"])
-        .about("Lists all mailboxes")]
+    vec![
+        SubCommand::with_name("mailboxes")
+            .aliases(&["mailbox", "mboxes", "mbox", "mb", "m"])
+            .about("Lists all mailboxes")
+            .subcommand(
+                SubCommand::with_name("expunge")
+                    .about("Permanently removes the deleted messages of a mailbox")
+                    .arg(
+                        Arg::with_name("target")
+                            .help("Specifies the mailbox to expunge, defaults to the selected one")
+                            .value_name("TARGET"),
+                    )
+                    .arg(msg_arg::override_hold_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("quota")
+                    .about("Shows the used and available storage of a mailbox")
+                    .long_about("Shows the usage and the limit of each resource (storage in KiB, message count) of the quota roots a mailbox belongs to, as reported by the QUOTA extension.")
+                    .arg(
+                        Arg::with_name("target")
+                            .help("Specifies the mailbox, defaults to the selected one")
+                            .value_name("TARGET"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("acl")
+                    .about("Manages the access control lists of mailboxes")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("get")
+                            .about("Lists the rights granted on a mailbox")
+                            .arg(acl_name_arg()),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("set")
+                            .about("Grants rights on a mailbox")
+                            .arg(acl_name_arg())
+                            .arg(
+                                Arg::with_name("identifier")
+                                    .help("Specifies the user or group the rights are granted to")
+                                    .value_name("IDENTIFIER")
+                                    .required(true),
+                            )
+                            .arg(
+                                Arg::with_name("rights")
+                                    .help("Specifies the rights, one letter each")
+                                    .long_help("Specifies the rights, one letter each (eg. `lrs` to read, or `lrswipkxtea` for all the rights). Rights prefixed with `+` or `-` are added to or removed from the current ones, empty rights remove them all.")
+                                    .value_name("RIGHTS")
+                                    .allow_hyphen_values(true)
+                                    .required(true),
+                            ),
+                    ),
+            ),
+        SubCommand::with_name("stats")
+            .about("Reports message counts by sender and by month, and the largest messages")
+            .arg(
+                Arg::with_name("target")
+                    .help("Specifies the mailbox to report, defaults to the selected one")
+                    .value_name("TARGET"),
+            )
+            .arg(
+                Arg::with_name("top")
+                    .help("Defines the number of top senders and largest messages to report")
+                    .long("top")
+                    .short("t")
+                    .value_name("N")
+                    .default_value("10"),
+            ),
+    ]
+}
+
+/// Mailbox name argument of access control list commands.
+fn acl_name_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("name")
+        .help("Specifies the mailbox")
+        .value_name("NAME")
+        .required(true)
 }
 
 /// Source mailbox argument.
@@ -83,6 +83,37 @@ impl ser::Serialize for Attributes {
     }
 }
 
+// --- Namespace ---
+/// Represents the kind of [namespace] a mailbox belongs to.
+///
+/// [namespace]: https://datatracker.ietf.org/doc/html/rfc2342
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamespaceKind {
+    /// The mailboxes of the user.
+    Personal,
+    /// The mailboxes other users share with the user.
+    OtherUsers,
+    /// The mailboxes shared by everyone.
+    Shared,
+}
+
+impl Default for NamespaceKind {
+    fn default() -> Self {
+        Self::Personal
+    }
+}
+
+impl fmt::Display for NamespaceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Personal => write!(f, "personal"),
+            Self::OtherUsers => write!(f, "other users"),
+            Self::Shared => write!(f, "shared"),
+        }
+    }
+}
+
 // --- Mailbox ---
 /// Represents a general mailbox.
 #[derive(Debug, Serialize)]
@@ -97,6 +128,9 @@ pub struct Mbox {
 
     /// Its attributes.
     pub attributes: Attributes,
+
+    /// The kind of namespace it belongs to.
+    pub namespace: NamespaceKind,
 }
 
 impl Default for Mbox {
@@ -105,6 +139,25 @@ impl Default for Mbox {
             delim: String::default(),
             name: String::default(),
             attributes: Attributes::from(&[] as &[NameAttribute]),
+            namespace: NamespaceKind::default(),
+        }
+    }
+}
+
+impl Mbox {
+    /// Build the virtual mailbox of the given saved query. Virtual mailboxes cannot be selected,
+    /// their messages are listed with `list --query NAME`.
+    pub fn virtual_(query: &str) -> Self {
+        Self {
+            delim: String::default(),
+            name: query.to_owned(),
+            attributes: Attributes::from(
+                &[
+                    NameAttribute::NoSelect,
+                    NameAttribute::Custom(Cow::Borrowed("\\Virtual")),
+                ][..],
+            ),
+            namespace: NamespaceKind::default(),
         }
     }
 }
@@ -131,6 +184,7 @@ impl<'a> From<&'a imap::types::Name> for Mbox {
             delim: name.delimiter().unwrap_or_default().to_owned(),
             name: name.name().to_owned(),
             attributes: Attributes::from(name.attributes()),
+            namespace: NamespaceKind::default(),
         }
     }
 }
@@ -154,6 +208,7 @@ impl Table for Mbox {
         Row::new()
             .cell(Cell::new("DELIM").bold().underline().white())
             .cell(Cell::new("NAME").bold().underline().white())
+            .cell(Cell::new("NAMESPACE").bold().underline().white())
             .cell(
                 Cell::new("ATTRIBUTES")
                     .shrinkable()
@@ -167,6 +222,14 @@ impl Table for Mbox {
         Row::new()
             .cell(Cell::new(&self.delim).white())
             .cell(Cell::new(&self.name).green())
+            .cell(
+                Cell::new(&match self.namespace {
+                    // Only shared mailboxes stand out.
+                    NamespaceKind::Personal => String::new(),
+                    namespace => namespace.to_string(),
+                })
+                .yellow(),
+            )
             .cell(Cell::new(&self.attributes.to_string()).shrinkable().blue())
     }
 }
@@ -3,22 +3,145 @@
 //! This module gathers all mailboxes actions triggered by the CLI.
 
 use anyhow::Result;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 
 use crate::{
-    domain::{imap::ImapServiceInterface, mbox::Mboxes},
+    config::Account,
+    domain::{
+        backend::{build_backend, Backend},
+        mbox::{Mbox, MboxQuotas, MboxStats},
+        msg::msg_hold,
+    },
     output::{OutputService, OutputServiceInterface},
 };
 
-/// List all mailboxes.
-pub fn list<ImapService: ImapServiceInterface>(
-    output: &OutputService,
-    imap: &mut ImapService,
-) -> Result<()> {
-    let names = imap.get_mboxes()?;
-    let mboxes = Mboxes::from(&names);
+/// List all mailboxes, followed by the virtual mailboxes of the saved queries.
+pub fn list(account: &Account, output: &OutputService, backend: &mut dyn Backend) -> Result<()> {
+    let mut mboxes = backend.list_mboxes()?;
+    mboxes
+        .0
+        .extend(account.queries.keys().map(|name| Mbox::virtual_(name)));
     debug!("mailboxes len: {}", mboxes.0.len());
     trace!("mailboxes: {:#?}", mboxes);
     output.print(mboxes)?;
     Ok(())
 }
+
+/// Permanently remove the deleted messages of the given mailbox, or of the selected one. Refuses
+/// when some deleted messages are on hold, unless `override_hold` is set.
+pub fn expunge(
+    target: Option<&str>,
+    override_hold: bool,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let name = match target {
+        Some(target) if target != mbox.name => {
+            let target = Mbox::from(target);
+            let mut backend = build_backend(account, &target, false);
+            msg_hold::check_expunge_hold(override_hold, account, backend.as_mut())?;
+            backend.expunge()?;
+            backend.logout()?;
+            target.name
+        }
+        _ => {
+            msg_hold::check_expunge_hold(override_hold, account, backend)?;
+            backend.expunge()?;
+            mbox.name.to_owned()
+        }
+    };
+    output.print(format!(r#"Mailbox "{}" successfully expunged"#, name))
+}
+
+/// Print the access control list of the given mailbox.
+pub fn get_acl(name: &str, output: &OutputService, backend: &mut dyn Backend) -> Result<()> {
+    let acl = backend.get_acl(&Mbox::from(name))?;
+    trace!("acl: {:#?}", acl);
+    output.print(acl)
+}
+
+/// Grant the given rights to the given identifier on the given mailbox.
+pub fn set_acl(
+    name: &str,
+    identifier: &str,
+    rights: &str,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    backend.set_acl(&Mbox::from(name), identifier, rights)?;
+    output.print(format!(
+        r#"Rights of "{}" on mailbox "{}" successfully set"#,
+        identifier, name
+    ))
+}
+
+/// Report statistics of the given mailbox, or of the selected one.
+pub fn stats(
+    target: Option<&str>,
+    top: usize,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let envelopes = match target {
+        Some(target) if target != mbox.name => {
+            let target = Mbox::from(target);
+            let mut backend = build_backend(account, &target, false);
+            let envelopes = backend.list_envelopes(&0, &0)?;
+            backend.logout()?;
+            envelopes
+        }
+        _ => backend.list_envelopes(&0, &0)?,
+    };
+    debug!("envelopes len: {}", envelopes.0.len());
+    let name = target.unwrap_or(&mbox.name);
+    output.print(MboxStats::new(name, &envelopes.0, top))
+}
+
+/// Print the quotas of the given mailbox, or of the selected one.
+pub fn quota(
+    target: Option<&str>,
+    mbox: &Mbox,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let mbox = Mbox::from(target.unwrap_or(&mbox.name));
+    let quotas = backend.get_mbox_quotas(&mbox)?;
+    debug!("quotas len: {}", quotas.len());
+    trace!("quotas: {:#?}", quotas);
+    output.print(MboxQuotas {
+        mbox: mbox.name,
+        quotas,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::backend::backend_stub::BackendStub;
+
+    #[test]
+    fn it_should_refuse_to_expunge_held_messages() {
+        let account = Account {
+            hold_keyword: String::from("$Hold"),
+            ..Account::default()
+        };
+        let output = OutputService::default().captured();
+        let mut backend =
+            BackendStub::new(vec![(1, vec!["$Hold", "deleted"]), (2, vec!["deleted"])]);
+        let mbox = Mbox::from("INBOX");
+
+        assert!(expunge(None, false, &mbox, &account, &output, &mut backend).is_err());
+        assert!(backend.expunged.is_empty());
+
+        expunge(None, true, &mbox, &account, &output, &mut backend).unwrap();
+        assert_eq!(vec![1, 2], backend.expunged);
+        assert_eq!(
+            "Mailbox \"INBOX\" successfully expunged\n",
+            output.take_captured()
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Module related to mailbox quotas.
+//!
+//! Quotas are defined by the [QUOTA] extension: a mailbox belongs to quota roots, each one
+//! limiting resources shared by its mailboxes, like the storage (in KiB) or the number of
+//! messages.
+//!
+//! [QUOTA]: https://datatracker.ietf.org/doc/html/rfc2087
+
+use log::{debug, warn};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+use crate::{
+    config::Account,
+    domain::{account::Quota, backend::Backend, mbox::Mbox, msg::msg_share::human_size},
+    ui::table::{Cell, Row, Table},
+};
+
+/// Represents the quotas of the roots a mailbox belongs to.
+#[derive(Debug, Default, Serialize)]
+pub struct MboxQuotas {
+    pub mbox: String,
+    pub quotas: Vec<Quota>,
+}
+
+impl MboxQuotas {
+    /// Get the quotas used up to the given percent, or beyond.
+    pub fn near_limit(&self, percent: u64) -> impl Iterator<Item = &Quota> {
+        self.quotas
+            .iter()
+            .filter(move |quota| quota.limit > 0 && quota.percent() >= percent)
+    }
+}
+
+impl Display for MboxQuotas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.quotas.is_empty() {
+            return write!(f, r#"Mailbox "{}" has no quota"#, self.mbox);
+        }
+        writeln!(f, "\n{}", Table::render(&self.quotas))
+    }
+}
+
+/// Format an amount of the given resource, storage being counted in KiB.
+fn amount(resource: &str, amount: u64) -> String {
+    match resource {
+        "STORAGE" => human_size(amount as usize * 1024),
+        _ => amount.to_string(),
+    }
+}
+
+impl Table for Quota {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("ROOT").bold().underline().white())
+            .cell(Cell::new("RESOURCE").bold().underline().white())
+            .cell(Cell::new("USED").bold().underline().white())
+            .cell(Cell::new("AVAILABLE").bold().underline().white())
+            .cell(Cell::new("LIMIT").bold().underline().white())
+            .cell(Cell::new("USE%").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        let available = self.limit.saturating_sub(self.usage);
+        Row::new()
+            .cell(Cell::new(&self.root).shrinkable().green())
+            .cell(Cell::new(&self.resource).blue())
+            .cell(Cell::new(&amount(&self.resource, self.usage)).yellow())
+            .cell(Cell::new(&amount(&self.resource, available)).yellow())
+            .cell(Cell::new(&amount(&self.resource, self.limit)).white())
+            .cell(Cell::new(&format!("{}%", self.percent())).white())
+    }
+}
+
+/// Warn when the given mailbox is near one of its quotas, before saving a message to it. The
+/// quotas are only a hint: failing to get them is not an error.
+pub fn warn_near_quota(mbox: &Mbox, account: &Account, backend: &mut dyn Backend) {
+    let quotas = match backend.get_mbox_quotas(mbox) {
+        Ok(quotas) => MboxQuotas {
+            mbox: mbox.name.to_owned(),
+            quotas,
+        },
+        Err(err) => {
+            debug!("skip quota check: {:#}", err);
+            return;
+        }
+    };
+    for quota in quotas.near_limit(account.quota_warning) {
+        warn!(r#"Mailbox "{}" is near its quota: {}"#, mbox.name, quota);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_find_quotas_near_limit() {
+        let quota = |resource: &str, usage, limit| Quota {
+            root: String::new(),
+            resource: resource.to_owned(),
+            usage,
+            limit,
+        };
+        let quotas = MboxQuotas {
+            mbox: String::from("INBOX"),
+            quotas: vec![
+                quota("STORAGE", 950, 1000),
+                quota("MESSAGE", 10, 1000),
+                quota("X-UNLIMITED", 10, 0),
+            ],
+        };
+        let near: Vec<_> = quotas.near_limit(90).map(|q| q.resource.as_str()).collect();
+        assert_eq!(vec!["STORAGE"], near);
+        assert_eq!("950.0 KiB", amount("STORAGE", 950));
+        assert_eq!("10", amount("MESSAGE", 10));
+    }
+}
@@ -0,0 +1,230 @@
+//! Module related to mailbox statistics.
+//!
+//! Statistics are computed from the envelopes of the mailbox, so that only headers are fetched.
+
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use crate::{
+    domain::msg::{msg_share::human_size, Envelope},
+    ui::table::{Cell, Row, Table},
+};
+
+/// Represents the messages of a sender.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SenderStats {
+    pub sender: String,
+    pub count: usize,
+    pub size: usize,
+}
+
+/// Represents the messages of a month, like `2021-12`.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MonthStats {
+    pub month: String,
+    pub count: usize,
+    pub size: usize,
+}
+
+/// Represents one of the largest messages.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct LargestMsg {
+    pub id: u32,
+    pub size: usize,
+    pub sender: String,
+    pub subject: String,
+}
+
+/// Represents the statistics of a mailbox.
+#[derive(Debug, Default, Serialize)]
+pub struct MboxStats {
+    pub mbox: String,
+    pub count: usize,
+    /// The total size in bytes, only counting messages whose size is known.
+    pub size: usize,
+    /// The top senders, most messages first.
+    pub senders: Vec<SenderStats>,
+    /// The messages per month, oldest first.
+    pub months: Vec<MonthStats>,
+    /// The largest messages, largest first.
+    pub largest: Vec<LargestMsg>,
+}
+
+impl MboxStats {
+    /// Compute the statistics of the given envelopes, keeping the given number of top senders and
+    /// largest messages.
+    pub fn new(mbox: &str, envelopes: &[Envelope], top: usize) -> Self {
+        let mut senders: HashMap<&str, SenderStats> = HashMap::new();
+        let mut months: HashMap<&str, MonthStats> = HashMap::new();
+
+        for envelope in envelopes.iter() {
+            let size = envelope.size.unwrap_or_default();
+
+            let sender = senders
+                .entry(&envelope.sender)
+                .or_insert_with(|| SenderStats {
+                    sender: envelope.sender.to_owned(),
+                    ..SenderStats::default()
+                });
+            sender.count += 1;
+            sender.size += size;
+
+            // Dates are stored as printed by NaiveDateTime, like `2021-12-01 08:00:00`.
+            let month = envelope
+                .date
+                .as_deref()
+                .and_then(|date| date.get(..7))
+                .unwrap_or("unknown");
+            let month = months.entry(month).or_insert_with(|| MonthStats {
+                month: month.to_owned(),
+                ..MonthStats::default()
+            });
+            month.count += 1;
+            month.size += size;
+        }
+
+        let mut senders: Vec<SenderStats> = senders.into_values().collect();
+        senders.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.sender.cmp(&b.sender)));
+        senders.truncate(top);
+
+        let mut months: Vec<MonthStats> = months.into_values().collect();
+        months.sort_by(|a, b| a.month.cmp(&b.month));
+
+        let mut largest: Vec<LargestMsg> = envelopes
+            .iter()
+            .filter_map(|envelope| {
+                envelope.size.map(|size| LargestMsg {
+                    id: envelope.id,
+                    size,
+                    sender: envelope.sender.to_owned(),
+                    subject: envelope.subject.to_owned(),
+                })
+            })
+            .collect();
+        largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.id.cmp(&b.id)));
+        largest.truncate(top);
+
+        Self {
+            mbox: mbox.to_owned(),
+            count: envelopes.len(),
+            size: envelopes.iter().filter_map(|envelope| envelope.size).sum(),
+            senders,
+            months,
+            largest,
+        }
+    }
+}
+
+impl Display for MboxStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "\n{}: {} message(s), {}",
+            self.mbox,
+            self.count,
+            human_size(self.size)
+        )?;
+        writeln!(f, "\n{}", Table::render(&self.senders))?;
+        writeln!(f, "\n{}", Table::render(&self.months))?;
+        writeln!(f, "\n{}", Table::render(&self.largest))
+    }
+}
+
+impl Table for SenderStats {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("SENDER").shrinkable().bold().underline().white())
+            .cell(Cell::new("MESSAGES").bold().underline().white())
+            .cell(Cell::new("SIZE").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.sender).shrinkable().blue())
+            .cell(Cell::new(&self.count.to_string()).red())
+            .cell(Cell::new(&human_size(self.size)).yellow())
+    }
+}
+
+impl Table for MonthStats {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("MONTH").bold().underline().white())
+            .cell(Cell::new("MESSAGES").bold().underline().white())
+            .cell(Cell::new("SIZE").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.month).green())
+            .cell(Cell::new(&self.count.to_string()).red())
+            .cell(Cell::new(&human_size(self.size)).yellow())
+    }
+}
+
+impl Table for LargestMsg {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("ID").bold().underline().white())
+            .cell(Cell::new("SIZE").bold().underline().white())
+            .cell(Cell::new("SUBJECT").shrinkable().bold().underline().white())
+            .cell(Cell::new("SENDER").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.id.to_string()).red())
+            .cell(Cell::new(&human_size(self.size)).yellow())
+            .cell(Cell::new(&self.subject).shrinkable().green())
+            .cell(Cell::new(&self.sender).blue())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(id: u32, sender: &str, date: &str, size: usize) -> Envelope {
+        Envelope {
+            id,
+            sender: sender.to_owned(),
+            date: Some(date.to_owned()),
+            size: Some(size),
+            ..Envelope::default()
+        }
+    }
+
+    #[test]
+    fn it_should_compute_mbox_stats() {
+        let envelopes = vec![
+            envelope(1, "alice", "2021-11-30 08:00:00", 100),
+            envelope(2, "bob", "2021-12-01 08:00:00", 3000),
+            envelope(3, "alice", "2021-12-02 08:00:00", 200),
+        ];
+        let stats = MboxStats::new("INBOX", &envelopes, 1);
+
+        assert_eq!(3, stats.count);
+        assert_eq!(3300, stats.size);
+        assert_eq!(
+            vec![SenderStats {
+                sender: String::from("alice"),
+                count: 2,
+                size: 300,
+            }],
+            stats.senders
+        );
+        assert_eq!(
+            vec!["2021-11", "2021-12"],
+            stats
+                .months
+                .iter()
+                .map(|m| m.month.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(3200, stats.months[1].size);
+        assert_eq!(2, stats.largest[0].id);
+    }
+}
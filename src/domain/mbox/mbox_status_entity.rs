@@ -0,0 +1,27 @@
+//! Module related to mailbox status.
+//!
+//! The status gives the numbers of messages of a mailbox without selecting it, as reported by
+//! the IMAP [STATUS] command, so that it stays cheap enough to be polled by status bars.
+//!
+//! [STATUS]: https://datatracker.ietf.org/doc/html/rfc3501#section-6.3.10
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Represents the numbers of messages of a mailbox.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MboxStatus {
+    pub mbox: String,
+    pub messages: usize,
+    pub unseen: usize,
+}
+
+impl Display for MboxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            r#"Mailbox "{}" has {} message(s), {} unseen"#,
+            self.mbox, self.messages, self.unseen
+        )
+    }
+}
@@ -1,7 +1,21 @@
 //! Module related to mailbox.
 
+#[cfg(feature = "cli")]
 pub mod mbox_arg;
+#[cfg(feature = "cli")]
 pub mod mbox_handler;
 
 pub mod mbox_entity;
 pub use mbox_entity::*;
+
+pub mod mbox_acl_entity;
+pub use mbox_acl_entity::*;
+
+pub mod mbox_stats_entity;
+pub use mbox_stats_entity::*;
+
+pub mod mbox_quota_entity;
+pub use mbox_quota_entity::*;
+
+pub mod mbox_status_entity;
+pub use mbox_status_entity::*;
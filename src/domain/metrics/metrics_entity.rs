@@ -0,0 +1,176 @@
+//! Module related to metrics.
+//!
+//! Metrics are rendered in the Prometheus [text format], as expected by the textfile collector of
+//! the node exporter. The file is also the only state: it is parsed back before each update, so
+//! that counters survive across invocations and are shared by all accounts.
+//!
+//! [text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+/// Represents the metrics exposed per account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Metric {
+    NewMsgs,
+    SentMsgs,
+    SendErrors,
+    Errors,
+    Connected,
+}
+
+impl Metric {
+    const ALL: [Metric; 5] = [
+        Metric::NewMsgs,
+        Metric::SentMsgs,
+        Metric::SendErrors,
+        Metric::Errors,
+        Metric::Connected,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::NewMsgs => "himalaya_new_messages_total",
+            Metric::SentMsgs => "himalaya_sent_messages_total",
+            Metric::SendErrors => "himalaya_send_errors_total",
+            Metric::Errors => "himalaya_errors_total",
+            Metric::Connected => "himalaya_connected",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Metric::NewMsgs => "Number of new messages notified.",
+            Metric::SentMsgs => "Number of messages sent.",
+            Metric::SendErrors => "Number of messages that failed to be sent.",
+            Metric::Errors => "Number of errors that stopped the notify mode.",
+            Metric::Connected => "Whether the notify mode is connected to the server.",
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Metric::Connected => "gauge",
+            _ => "counter",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|metric| metric.name() == name)
+    }
+}
+
+/// Escape the given label value, as required by the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push(c),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Parse a sample line, like `himalaya_sent_messages_total{account="work"} 3`.
+fn parse_sample(line: &str) -> Option<(Metric, String, f64)> {
+    let (name, rest) = line.split_once("{account=\"")?;
+    let (account, value) = rest.rsplit_once("\"}")?;
+    let metric = Metric::from_name(name.trim())?;
+    let value = value.trim().parse().ok()?;
+    Some((metric, unescape(account), value))
+}
+
+/// Represents the metrics of all accounts, indexed by metric then by account name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Metrics(pub BTreeMap<Metric, BTreeMap<String, f64>>);
+
+impl Metrics {
+    /// Parse the given textfile. Unknown lines are ignored.
+    pub fn parse(content: &str) -> Self {
+        let mut metrics = Self::default();
+        for line in content.lines().filter(|line| !line.starts_with('#')) {
+            if let Some((metric, account, value)) = parse_sample(line) {
+                metrics.set(metric, &account, value);
+            }
+        }
+        metrics
+    }
+
+    pub fn get(&self, metric: Metric, account: &str) -> f64 {
+        self.0
+            .get(&metric)
+            .and_then(|samples| samples.get(account))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, metric: Metric, account: &str, value: f64) {
+        self.0
+            .entry(metric)
+            .or_default()
+            .insert(account.to_owned(), value);
+    }
+
+    pub fn incr(&mut self, metric: Metric, account: &str, by: f64) {
+        let value = self.get(metric, account) + by;
+        self.set(metric, account, value);
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (metric, samples) in self.0.iter() {
+            writeln!(f, "# HELP {} {}", metric.name(), metric.help())?;
+            writeln!(f, "# TYPE {} {}", metric.name(), metric.kind())?;
+            for (account, value) in samples.iter() {
+                writeln!(
+                    f,
+                    "{}{{account=\"{}\"}} {}",
+                    metric.name(),
+                    escape(account),
+                    value
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_round_trip_metrics() {
+        let mut metrics = Metrics::default();
+        metrics.incr(Metric::SentMsgs, "work", 1.0);
+        metrics.incr(Metric::SentMsgs, "work", 2.0);
+        metrics.incr(Metric::NewMsgs, r#"my "perso""#, 1.0);
+        metrics.set(Metric::Connected, "work", 1.0);
+
+        let content = metrics.to_string();
+        assert!(content.contains("# TYPE himalaya_connected gauge\n"));
+        assert!(content.contains("himalaya_sent_messages_total{account=\"work\"} 3\n"));
+        assert!(content.contains(r#"himalaya_new_messages_total{account="my \"perso\""} 1"#));
+        assert_eq!(metrics, Metrics::parse(&content));
+        assert_eq!(0.0, metrics.get(Metric::Errors, "work"));
+    }
+}
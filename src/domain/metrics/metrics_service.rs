@@ -0,0 +1,48 @@
+//! Module related to metrics servicing.
+//!
+//! This module updates the metrics textfile of the account, if any. Like webhooks, updating is
+//! best effort: a failing update is only logged.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::{fs, path::Path};
+
+use crate::{
+    config::Account,
+    domain::metrics::{Metric, Metrics},
+};
+
+fn update(path: &Path, f: impl FnOnce(&mut Metrics)) -> Result<()> {
+    let mut metrics = Metrics::parse(&fs::read_to_string(path).unwrap_or_default());
+    f(&mut metrics);
+    // Write then rename, so that the collector never reads a partial file.
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, metrics.to_string())
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .context(format!("cannot write metrics file {:?}", path))
+}
+
+fn update_account(account: &Account, f: impl FnOnce(&mut Metrics)) {
+    if let Some(ref path) = account.metrics_file {
+        if let Err(err) = update(path, f) {
+            warn!("{:?}", err);
+        }
+    }
+}
+
+/// Increment the given counter of the given account.
+pub fn incr(account: &Account, metric: Metric, by: u64) {
+    debug!("increment metric {} by {}", metric.name(), by);
+    update_account(account, |metrics| {
+        metrics.incr(metric, &account.name, by as f64)
+    });
+}
+
+/// Set the connection state of the given account.
+pub fn set_connected(account: &Account, connected: bool) {
+    debug!("set metric {} to {}", Metric::Connected.name(), connected);
+    update_account(account, |metrics| {
+        metrics.set(Metric::Connected, &account.name, connected as u8 as f64)
+    });
+}
@@ -0,0 +1,7 @@
+//! Module related to metrics.
+
+pub mod metrics_entity;
+pub use metrics_entity::*;
+
+pub mod metrics_service;
+pub use metrics_service::*;
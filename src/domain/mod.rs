@@ -1,13 +1,64 @@
 //! Domain-specific modules.
 
+pub mod account;
+pub use account::*;
+
+pub mod autoconfig;
+pub use autoconfig::*;
+
+pub mod backend;
+pub use backend::*;
+
+pub mod backup;
+pub use backup::*;
+
+pub mod daemon;
+pub use daemon::*;
+
+pub mod filter;
+pub use filter::*;
+
+pub mod graph;
+pub use graph::*;
+
 pub mod imap;
 pub use self::imap::*;
 
 pub mod mbox;
 pub use mbox::*;
 
+pub mod metrics;
+pub use metrics::*;
+
 pub mod msg;
 pub use msg::*;
 
+pub mod queue;
+pub use queue::*;
+
+pub mod refile;
+pub use refile::*;
+
+pub mod report;
+pub use report::*;
+
+pub mod sendmail;
+pub use sendmail::*;
+
+pub mod sent;
+pub use sent::*;
+
+pub mod sieve;
+pub use sieve::*;
+
 pub mod smtp;
 pub use smtp::*;
+
+pub mod snooze;
+pub use snooze::*;
+
+pub mod storage;
+pub use storage::*;
+
+pub mod webhook;
+pub use webhook::*;
@@ -1,34 +1,125 @@
 use anyhow::{anyhow, Context, Error, Result};
-use serde::Serialize;
-use std::convert::TryFrom;
+use chrono::{Local, NaiveDateTime};
+use mailparse::MailHeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, str::FromStr};
 
 use crate::{
-    domain::msg::{Flag, Flags},
+    domain::msg::{msg_share, Flag, Flags, PartMetas},
+    output::{TplFields, TplValue},
     ui::table::{Cell, Row, Table},
 };
 
 /// Representation of an envelope. An envelope gathers basic information related to a message. It
 /// is mostly used for listings.
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Envelope {
-    /// The sequence number of the message.
+    /// The [UID] of the message, or its [sequence number] when UIDs were not fetched.
     ///
-    /// [RFC3501]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.2
+    /// [UID]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.1
+    /// [sequence number]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.2
     pub id: u32,
 
     /// The flags attached to the message.
     pub flags: Flags,
 
+    /// The labels of the message: its keywords, or its Gmail labels on Gmail, except the hold
+    /// keyword.
+    #[serde(default)]
+    pub labels: Vec<String>,
+
+    /// The Gmail thread id of the message, on Gmail.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+
     /// The subject of the message.
     pub subject: String,
 
     /// The sender of the message.
     pub sender: String,
 
-    /// The internal date of the message.
+    /// The internal date of the message, in the local timezone.
     ///
     /// [RFC3501]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.3
     pub date: Option<String>,
+
+    /// The date of the message as shown in listings, once formatted with `format_date`.
+    #[serde(skip)]
+    pub display_date: Option<String>,
+
+    /// The size of the message in bytes, when the backend tells it.
+    pub size: Option<usize>,
+
+    /// Whether the message has attachments.
+    #[serde(default)]
+    pub has_attachment: bool,
+
+    /// The name of the mailing list the message was distributed by, when listed with `--lists`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list: Option<String>,
+
+    /// The spam score of the message, when listed with the `show-spam-score` option.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spam_score: Option<String>,
+}
+
+/// Define the format of the stored dates, as printed by `NaiveDateTime`.
+const DATE_FMT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// Format the given date relatively to the given time (eg. `2h ago`). Dates older than a week,
+/// or in the future, are formatted with the given format.
+fn relative_date(date: NaiveDateTime, now: NaiveDateTime, fmt: &str) -> String {
+    let secs = (now - date).num_seconds();
+    match secs {
+        secs if secs < 0 => date.format(fmt).to_string(),
+        secs if secs < 60 => String::from("just now"),
+        secs if secs < 3600 => format!("{}m ago", secs / 60),
+        secs if secs < 86400 => format!("{}h ago", secs / 3600),
+        secs if secs < 7 * 86400 => format!("{}d ago", secs / 86400),
+        _ => date.format(fmt).to_string(),
+    }
+}
+
+/// Get the spam score from the given headers, as written by SpamAssassin or rspamd.
+pub fn spam_score(headers: &[mailparse::MailHeader]) -> Option<String> {
+    let score = headers
+        .get_first_value("X-Spam-Score")
+        .or_else(|| headers.get_first_value("X-Rspamd-Score"));
+    if let Some(score) = score {
+        return Some(score.trim().to_owned());
+    }
+    // SpamAssassin may only write the status, eg. `No, score=-0.1 required=5.0 tests=…`.
+    let status = headers.get_first_value("X-Spam-Status")?;
+    status
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .find_map(|token| token.strip_prefix("score="))
+        .map(String::from)
+}
+
+impl Envelope {
+    /// Parse the date of the message.
+    pub fn parse_date(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(self.date.as_deref()?, DATE_FMT).ok()
+    }
+
+    /// Get the date shown in listings: the formatted one, or else the stored one.
+    pub fn listed_date(&self) -> &str {
+        self.display_date
+            .as_deref()
+            .or_else(|| self.date.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Format the date shown in listings with the given format, or relatively to now.
+    pub fn format_date(&mut self, fmt: &str, relative: bool) {
+        self.display_date = self.parse_date().map(|date| {
+            if relative {
+                relative_date(date, Local::now().naive_local(), fmt)
+            } else {
+                date.format(fmt).to_string()
+            }
+        });
+    }
 }
 
 impl<'a> TryFrom<&'a imap::types::Fetch> for Envelope {
@@ -40,7 +131,7 @@ impl<'a> TryFrom<&'a imap::types::Fetch> for Envelope {
             .ok_or(anyhow!("cannot get envelope of message {}", fetch.message))?;
 
         // Get the sequence number
-        let id = fetch.message;
+        let id = fetch.uid.unwrap_or(fetch.message);
 
         // Get the flags
         let flags = Flags::try_from(fetch.flags())?;
@@ -99,47 +190,276 @@ impl<'a> TryFrom<&'a imap::types::Fetch> for Envelope {
             format!("{}@{}", mbox, host)
         };
 
-        // Get the internal date
+        // Get the internal date, in the local timezone
         let date = fetch
             .internal_date()
-            .map(|date| date.naive_local().to_string());
+            .map(|date| date.with_timezone(&Local).naive_local().to_string());
+
+        // Get the size
+        let size = fetch.size.map(|size| size as usize);
+
+        // Get the attachment indicator, from the parts having a filename
+        let has_attachment = fetch
+            .bodystructure()
+            .map(|structure| {
+                PartMetas::from_bodystructure(id, structure)
+                    .0
+                    .iter()
+                    .any(|meta| meta.filename.is_some())
+            })
+            .unwrap_or_default();
 
         Ok(Self {
             id,
+            // Labels are filled by the service, once the hold keyword and the Gmail labels are
+            // known.
+            labels: vec![],
+            thread_id: None,
             flags,
             subject,
             sender,
             date,
+            display_date: None,
+            size,
+            has_attachment,
+            list: None,
+            spam_score: None,
         })
     }
 }
 
+/// Represents a column of envelope listings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Id,
+    Flags,
+    Subject,
+    Sender,
+    Date,
+    Size,
+    Labels,
+    /// The Gmail thread id.
+    Thread,
+    /// The mailing list, shown when listed with `--lists`.
+    List,
+    /// The spam score, shown with the `show-spam-score` option.
+    Score,
+}
+
+/// Define the columns of envelope listings shown by default.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::Flags,
+    Column::Subject,
+    Column::Sender,
+    Column::Date,
+];
+
+impl FromStr for Column {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "uid" | "id" => Ok(Self::Id),
+            "flags" => Ok(Self::Flags),
+            "subject" => Ok(Self::Subject),
+            "from" | "sender" => Ok(Self::Sender),
+            "date" => Ok(Self::Date),
+            "size" => Ok(Self::Size),
+            "labels" | "label" => Ok(Self::Labels),
+            "thread" | "thrid" => Ok(Self::Thread),
+            name => Err(anyhow!(
+                r#"cannot parse column "{}", expected one of uid, flags, date, from, subject, size, labels, thread"#,
+                name
+            )),
+        }
+    }
+}
+
+impl Column {
+    /// Parse the given comma-separated list of columns.
+    pub fn parse_list(columns: &str) -> Result<Vec<Self>> {
+        columns.split(',').map(Self::from_str).collect()
+    }
+
+    /// Get the head cell of the column.
+    fn head(&self) -> Cell {
+        let cell = match self {
+            Self::Id => Cell::new("ID"),
+            Self::Flags => Cell::new("FLAGS"),
+            Self::Subject => Cell::new("SUBJECT").shrinkable(),
+            Self::Sender => Cell::new("SENDER"),
+            Self::Date => Cell::new("DATE"),
+            Self::Size => Cell::new("SIZE"),
+            Self::Labels => Cell::new("LABELS"),
+            Self::Thread => Cell::new("THREAD"),
+            Self::List => Cell::new("LIST"),
+            Self::Score => Cell::new("SCORE"),
+        };
+        cell.bold().underline().white()
+    }
+
+    /// Get the head row of the given columns.
+    pub fn head_row(columns: &[Self]) -> Row {
+        columns
+            .iter()
+            .fold(Row::new(), |row, column| row.cell(column.head()))
+    }
+}
+
+impl Envelope {
+    /// Get the flags of the message as symbols, followed by the attachment indicator.
+    pub fn symbols(&self) -> String {
+        let attachment = if self.has_attachment { "@" } else { " " };
+        format!("{}{}", self.flags.to_symbols_string(), attachment)
+    }
+
+    /// Get the cell of the given column.
+    fn cell(&self, column: Column) -> Cell {
+        let unseen = !self.flags.contains(&Flag::Seen);
+        let cell = match column {
+            Column::Id => Cell::new(self.id.to_string()).red().themed("id"),
+            Column::Flags => Cell::new(self.symbols()).white().themed("flags").themed_if(
+                self.flags.contains(&Flag::Flagged),
+                "flagged",
+                |cell| cell,
+            ),
+            Column::Subject => Cell::new(&self.subject)
+                .shrinkable()
+                .green()
+                .themed("subject"),
+            Column::Sender => Cell::new(&self.sender).blue().themed("sender"),
+            Column::Date => Cell::new(self.listed_date()).yellow().themed("date"),
+            Column::Size => Cell::new(self.size.map(msg_share::human_size).unwrap_or_default())
+                .white()
+                .themed("size"),
+            Column::Labels => Cell::new(self.labels.join(", ")).ext(5).themed("labels"),
+            Column::Thread => Cell::new(self.thread_id.as_deref().unwrap_or_default()).ext(8),
+            Column::List => Cell::new(self.list.as_deref().unwrap_or_default()).ext(6),
+            Column::Score => Cell::new(self.spam_score.as_deref().unwrap_or_default()).red(),
+        };
+        cell.themed_if(unseen, "unseen", Cell::bold)
+    }
+}
+
 impl Table for Envelope {
     fn head() -> Row {
-        Row::new()
-            .cell(Cell::new("ID").bold().underline().white())
-            .cell(Cell::new("FLAGS").bold().underline().white())
-            .cell(Cell::new("SUBJECT").shrinkable().bold().underline().white())
-            .cell(Cell::new("SENDER").bold().underline().white())
-            .cell(Cell::new("DATE").bold().underline().white())
+        Column::head_row(DEFAULT_COLUMNS)
     }
 
     fn row(&self) -> Row {
-        let id = self.id.to_string();
-        let flags = self.flags.to_symbols_string();
-        let unseen = !self.flags.contains(&Flag::Seen);
-        let subject = &self.subject;
-        let sender = &self.sender;
-        let date = self
-            .date
-            .as_ref()
-            .map(|date| date.as_str())
-            .unwrap_or_default();
-        Row::new()
-            .cell(Cell::new(id).bold_if(unseen).red())
-            .cell(Cell::new(flags).bold_if(unseen).white())
-            .cell(Cell::new(subject).shrinkable().bold_if(unseen).green())
-            .cell(Cell::new(sender).bold_if(unseen).blue())
-            .cell(Cell::new(date).bold_if(unseen).yellow())
+        ColumnedEnvelope(DEFAULT_COLUMNS, self).row()
+    }
+}
+
+/// Represents an envelope listed with the given columns. Its table has no head of its own: it is
+/// rendered with the head of the columns, see [`Column::head_row`].
+pub(crate) struct ColumnedEnvelope<'a>(pub &'a [Column], pub &'a Envelope);
+
+impl Table for ColumnedEnvelope<'_> {
+    fn head() -> Row {
+        Envelope::head()
+    }
+
+    fn row(&self) -> Row {
+        self.0
+            .iter()
+            .fold(Row::new(), |row, column| row.cell(self.1.cell(*column)))
+    }
+}
+
+impl TplFields for Envelope {
+    fn tpl_field(&self, name: &str) -> Option<TplValue> {
+        match name {
+            "id" | "uid" => Some(TplValue::Text(self.id.to_string())),
+            "flags" => Some(TplValue::Text(self.symbols())),
+            "size" => Some(TplValue::Text(
+                self.size.map(msg_share::human_size).unwrap_or_default(),
+            )),
+            "labels" => Some(TplValue::Text(self.labels.join(", "))),
+            "thread" => Some(TplValue::Text(
+                self.thread_id.to_owned().unwrap_or_default(),
+            )),
+            "subject" => Some(TplValue::Text(self.subject.to_owned())),
+            "from" | "sender" => Some(TplValue::Text(self.sender.to_owned())),
+            "list" => Some(TplValue::Text(self.list.to_owned().unwrap_or_default())),
+            "spam-score" => Some(TplValue::Text(
+                self.spam_score.to_owned().unwrap_or_default(),
+            )),
+            "date" => {
+                let date = self.date.as_deref().unwrap_or_default();
+                Some(
+                    self.parse_date()
+                        .map(TplValue::Date)
+                        .unwrap_or_else(|| TplValue::Text(date.to_owned())),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_get_spam_scores() {
+        let score = |raw: &str| spam_score(&mailparse::parse_headers(raw.as_bytes()).unwrap().0);
+        assert_eq!(
+            Some(String::from("5.3")),
+            score("X-Spam-Score: 5.3\r\n\r\n")
+        );
+        assert_eq!(
+            Some(String::from("2.5")),
+            score("X-Rspamd-Score: 2.5\r\n\r\n")
+        );
+        assert_eq!(
+            Some(String::from("-0.1")),
+            score("X-Spam-Status: No, score=-0.1 required=5.0\r\n\ttests=NONE\r\n\r\n")
+        );
+        assert_eq!(None, score("Subject: Hello\r\n\r\n"));
+    }
+
+    #[test]
+    fn it_should_format_dates() {
+        let now = NaiveDateTime::parse_from_str("2021-06-10 12:00:00", DATE_FMT).unwrap();
+        let fmt = "%d/%m %H:%M";
+        let ago = |secs| relative_date(now - chrono::Duration::seconds(secs), now, fmt);
+        assert_eq!("just now", ago(30));
+        assert_eq!("5m ago", ago(300));
+        assert_eq!("2h ago", ago(7200));
+        assert_eq!("3d ago", ago(3 * 86400));
+        assert_eq!("01/06 12:00", ago(9 * 86400));
+        assert_eq!("10/06 13:00", ago(-3600));
+
+        let mut envelope = Envelope {
+            date: Some(String::from("2021-06-10 08:30:00")),
+            ..Envelope::default()
+        };
+        envelope.format_date(fmt, false);
+        assert_eq!(Some(String::from("10/06 08:30")), envelope.display_date);
+    }
+
+    #[test]
+    fn it_should_select_columns() {
+        assert_eq!(
+            vec![Column::Id, Column::Date, Column::Sender, Column::Size],
+            Column::parse_list("uid,date, from,size").unwrap()
+        );
+        assert!(Column::parse_list("uid,cc").is_err());
+
+        let envelope = Envelope {
+            id: 42,
+            size: Some(2048),
+            has_attachment: true,
+            ..Envelope::default()
+        };
+        let row = ColumnedEnvelope(&[Column::Size, Column::Id], &envelope).row();
+        let values: Vec<String> = row.0.iter().map(ToString::to_string).collect();
+        assert_eq!(2, values.len());
+        assert!(values[0].contains("2.0 KiB"));
+        assert!(values[1].contains("42"));
+        assert!(envelope.symbols().ends_with('@'));
     }
 }
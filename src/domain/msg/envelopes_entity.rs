@@ -1,13 +1,17 @@
 use anyhow::{Error, Result};
 use imap::types::{Fetch, ZeroCopy};
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use std::{
     convert::TryFrom,
     fmt::{self, Display},
     ops::Deref,
 };
 
-use crate::{domain::msg::Envelope, ui::Table};
+use crate::{
+    domain::msg::{Column, ColumnedEnvelope, Envelope, DEFAULT_COLUMNS},
+    output::{TplFields, TplItems},
+    ui::Table,
+};
 
 /// Representation of a list of envelopes.
 #[derive(Debug, Default, Serialize)]
@@ -35,8 +39,58 @@ impl TryFrom<ZeroCopy<Vec<Fetch>>> for Envelopes {
     }
 }
 
+impl TplItems for Envelopes {
+    fn tpl_items(&self) -> Vec<&dyn TplFields> {
+        self.iter()
+            .map(|envelope| envelope as &dyn TplFields)
+            .collect()
+    }
+}
+
+/// Render the given envelopes with the given columns, followed by the mailing list and spam
+/// score ones when any envelope has them.
+fn render(envelopes: &[Envelope], columns: &[Column]) -> String {
+    let mut columns = columns.to_vec();
+    if envelopes.iter().any(|envelope| envelope.list.is_some()) {
+        columns.push(Column::List);
+    }
+    if envelopes
+        .iter()
+        .any(|envelope| envelope.spam_score.is_some())
+    {
+        columns.push(Column::Score);
+    }
+    let rows: Vec<ColumnedEnvelope> = envelopes
+        .iter()
+        .map(|envelope| ColumnedEnvelope(&columns, envelope))
+        .collect();
+    ColumnedEnvelope::render_with_head(Column::head_row(&columns), &rows)
+}
+
 impl Display for Envelopes {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "\n{}", Table::render(&self))
+        writeln!(f, "\n{}", render(self, DEFAULT_COLUMNS))
+    }
+}
+
+/// Represents a list of envelopes shown with the given columns. The JSON output includes all
+/// the fields of the envelopes, whatever the columns.
+pub struct ColumnedEnvelopes<'a>(pub &'a [Column], pub Envelopes);
+
+impl Serialize for ColumnedEnvelopes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.1.serialize(serializer)
+    }
+}
+
+impl TplItems for ColumnedEnvelopes<'_> {
+    fn tpl_items(&self) -> Vec<&dyn TplFields> {
+        self.1.tpl_items()
+    }
+}
+
+impl Display for ColumnedEnvelopes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", render(&self.1, self.0))
     }
 }
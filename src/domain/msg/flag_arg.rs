@@ -6,16 +6,16 @@ use anyhow::Result;
 use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
 use log::{debug, trace};
 
-use crate::domain::msg::msg_arg;
+use crate::domain::msg::msg_arg::{self, OverrideHold};
 
 type SeqRange<'a> = &'a str;
 type Flags<'a> = Vec<&'a str>;
 
 /// Message flag commands.
 pub enum Command<'a> {
-    Set(SeqRange<'a>, Flags<'a>),
+    Set(SeqRange<'a>, Flags<'a>, OverrideHold),
     Add(SeqRange<'a>, Flags<'a>),
-    Remove(SeqRange<'a>, Flags<'a>),
+    Remove(SeqRange<'a>, Flags<'a>, OverrideHold),
 }
 
 /// Message flag command matcher.
@@ -35,7 +35,9 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         trace!(r#"seq range: "{:?}""#, seq_range);
         let flags: Vec<&str> = m.values_of("flags").unwrap_or_default().collect();
         trace!(r#"flags: "{:?}""#, flags);
-        return Ok(Some(Command::Set(seq_range, flags)));
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Set(seq_range, flags, override_hold)));
     }
 
     if let Some(m) = m.subcommand_matches("remove") {
@@ -44,7 +46,9 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         trace!(r#"seq range: "{:?}""#, seq_range);
         let flags: Vec<&str> = m.values_of("flags").unwrap_or_default().collect();
         trace!(r#"flags: "{:?}""#, flags);
-        return Ok(Some(Command::Remove(seq_range, flags)));
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Remove(seq_range, flags, override_hold)));
     }
 
     Ok(None)
@@ -76,13 +80,15 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
             SubCommand::with_name("set")
                 .about("Replaces all message flags")
                 .arg(msg_arg::seq_range_arg())
-                .arg(flags_arg()),
+                .arg(flags_arg())
+                .arg(msg_arg::override_hold_arg()),
         )
         .subcommand(
             SubCommand::with_name("remove")
                 .aliases(&["rm"])
                 .about("Removes flags from a message")
                 .arg(msg_arg::seq_range_arg())
-                .arg(flags_arg()),
+                .arg(flags_arg())
+                .arg(msg_arg::override_hold_arg()),
         )]
 }
@@ -5,20 +5,24 @@
 use anyhow::Result;
 
 use crate::{
-    domain::{imap::ImapServiceInterface, msg::Flags},
+    config::Account,
+    domain::{
+        backend::Backend,
+        msg::{msg_hold, Flags},
+    },
     output::OutputServiceInterface,
 };
 
 /// Add flags to all messages within the given sequence range.
 /// Flags are case-insensitive, and they do not need to be prefixed with `\`.
-pub fn add<'a, OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+pub fn add<'a, OutputService: OutputServiceInterface>(
     seq_range: &'a str,
     flags: Vec<&'a str>,
     output: &'a OutputService,
-    imap: &'a mut ImapService,
+    backend: &'a mut dyn Backend,
 ) -> Result<()> {
     let flags = Flags::from(flags);
-    imap.add_flags(seq_range, &flags)?;
+    backend.add_flags(seq_range, &flags)?;
     output.print(format!(
         r#"Flag(s) "{}" successfully added to message(s) "{}""#,
         flags, seq_range
@@ -26,15 +30,19 @@ pub fn add<'a, OutputService: OutputServiceInterface, ImapService: ImapServiceIn
 }
 
 /// Remove flags from all messages within the given sequence range.
-/// Flags are case-insensitive, and they do not need to be prefixed with `\`.
-pub fn remove<'a, OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+/// Flags are case-insensitive, and they do not need to be prefixed with `\`. The hold keyword
+/// is only removed with `override_hold`.
+pub fn remove<'a, OutputService: OutputServiceInterface>(
     seq_range: &'a str,
     flags: Vec<&'a str>,
+    override_hold: bool,
+    account: &'a Account,
     output: &'a OutputService,
-    imap: &'a mut ImapService,
+    backend: &'a mut dyn Backend,
 ) -> Result<()> {
+    msg_hold::check_release(flags.iter().copied(), override_hold, account)?;
     let flags = Flags::from(flags);
-    imap.remove_flags(seq_range, &flags)?;
+    backend.remove_flags(seq_range, &flags)?;
     output.print(format!(
         r#"Flag(s) "{}" successfully removed from message(s) "{}""#,
         flags, seq_range
@@ -42,15 +50,30 @@ pub fn remove<'a, OutputService: OutputServiceInterface, ImapService: ImapServic
 }
 
 /// Replace flags of all messages within the given sequence range.
-/// Flags are case-insensitive, and they do not need to be prefixed with `\`.
-pub fn set<'a, OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+/// Flags are case-insensitive, and they do not need to be prefixed with `\`. Messages on hold
+/// keep the hold keyword, unless `override_hold` is true.
+pub fn set<'a, OutputService: OutputServiceInterface>(
     seq_range: &'a str,
     flags: Vec<&'a str>,
+    override_hold: bool,
+    account: &'a Account,
     output: &'a OutputService,
-    imap: &'a mut ImapService,
+    backend: &'a mut dyn Backend,
 ) -> Result<()> {
+    let keeps_hold = flags
+        .iter()
+        .any(|flag| flag.eq_ignore_ascii_case(&account.hold_keyword));
+    if !keeps_hold {
+        msg_hold::check_hold(
+            seq_range,
+            "replace the flags of",
+            override_hold,
+            account,
+            backend,
+        )?;
+    }
     let flags = Flags::from(flags);
-    imap.set_flags(seq_range, &flags)?;
+    backend.set_flags(seq_range, &flags)?;
     output.print(format!(
         r#"Flag(s) "{}" successfully set for message(s) "{}""#,
         flags, seq_range
@@ -1,5 +1,8 @@
 use anyhow::{anyhow, Error, Result};
-use serde::ser::{Serialize, SerializeSeq, Serializer};
+use serde::{
+    de::{Deserialize, Deserializer},
+    ser::{Serialize, SerializeSeq, Serializer},
+};
 use std::{
     borrow::Cow,
     collections::HashSet,
@@ -8,7 +11,7 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use crate::domain::msg::{Flag, SerializableFlag};
+use crate::domain::msg::{label_entity, Flag, SerializableFlag};
 
 /// Wrapper arround [`imap::types::Flag`]s.
 #[derive(Debug, Clone, Default)]
@@ -35,6 +38,31 @@ impl Flags {
         });
         flags
     }
+
+    /// Get the labels of the message: its keywords, that is its custom flags not prefixed with
+    /// `\`, sorted. The given hold keyword is left out: it is managed by the hold command only.
+    pub fn labels(&self, hold_keyword: &str) -> Vec<String> {
+        let mut labels: Vec<String> = self
+            .iter()
+            .filter_map(|flag| match flag {
+                Flag::Custom(cow) if cow.eq_ignore_ascii_case(hold_keyword) => None,
+                Flag::Custom(cow) if !cow.starts_with('\\') => Some(cow.to_string()),
+                _ => None,
+            })
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    /// Build the keywords storing the given labels.
+    pub fn from_labels(labels: &[String]) -> Result<Self> {
+        let mut set = HashSet::new();
+        for label in labels {
+            label_entity::check_keyword(label)?;
+            set.insert(Flag::Custom(Cow::Owned(label.to_owned())));
+        }
+        Ok(Self(set))
+    }
 }
 
 impl Display for Flags {
@@ -120,6 +148,18 @@ impl Serialize for Flags {
     }
 }
 
+impl<'de> Deserialize<'de> for Flags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let flags: Vec<String> = Vec::deserialize(deserializer)?;
+        Ok(Self::from(
+            flags.iter().map(String::as_str).collect::<Vec<_>>(),
+        ))
+    }
+}
+
 ///// Converst a string of flags into their appropriate flag representation. For example `"Seen"` is
 ///// gonna be convertred to `Flag::Seen`.
 /////
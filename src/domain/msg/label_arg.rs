@@ -0,0 +1,89 @@
+//! Module related to message label CLI.
+//!
+//! This module provides subcommands, arguments and a command matcher related to message labels.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+use crate::domain::msg::msg_arg::{self, OverrideHold};
+
+type SeqRange<'a> = &'a str;
+type Labels<'a> = Vec<&'a str>;
+
+/// Message label commands.
+pub enum Command<'a> {
+    Add(SeqRange<'a>, Labels<'a>),
+    List(SeqRange<'a>),
+    Remove(SeqRange<'a>, Labels<'a>, OverrideHold),
+}
+
+/// Message label command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("add") {
+        debug!("add command matched");
+        let seq_range = m.value_of("seq-range").unwrap();
+        trace!(r#"seq range: "{:?}""#, seq_range);
+        let labels: Vec<&str> = m.values_of("labels").unwrap_or_default().collect();
+        trace!(r#"labels: "{:?}""#, labels);
+        return Ok(Some(Command::Add(seq_range, labels)));
+    }
+
+    if let Some(m) = m.subcommand_matches("list") {
+        debug!("list command matched");
+        let seq_range = m.value_of("seq-range").unwrap();
+        trace!(r#"seq range: "{:?}""#, seq_range);
+        return Ok(Some(Command::List(seq_range)));
+    }
+
+    if let Some(m) = m.subcommand_matches("remove") {
+        debug!("remove command matched");
+        let seq_range = m.value_of("seq-range").unwrap();
+        trace!(r#"seq range: "{:?}""#, seq_range);
+        let labels: Vec<&str> = m.values_of("labels").unwrap_or_default().collect();
+        trace!(r#"labels: "{:?}""#, labels);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Remove(seq_range, labels, override_hold)));
+    }
+
+    Ok(None)
+}
+
+/// Message label labels argument.
+fn labels_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("labels")
+        .help("Labels")
+        .long_help("Labels, stored as IMAP keywords (eg. `$label1`, `todo`). On Gmail, they are the Gmail labels of the message.")
+        .value_name("LABELS…")
+        .multiple(true)
+        .required(true)
+}
+
+/// Message label subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("label")
+        .aliases(&["labels", "lbl"])
+        .about("Handles labels")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Adds labels to a message")
+                .arg(msg_arg::seq_range_arg())
+                .arg(labels_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .aliases(&["lst", "l"])
+                .about("Lists the labels of a message")
+                .arg(msg_arg::seq_range_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .aliases(&["rm"])
+                .about("Removes labels from a message")
+                .arg(msg_arg::seq_range_arg())
+                .arg(labels_arg())
+                .arg(msg_arg::override_hold_arg()),
+        )]
+}
@@ -0,0 +1,70 @@
+//! Module related to message labels.
+//!
+//! Labels are the IMAP keywords of messages, that is their custom flags not prefixed with `\`
+//! (eg. `$label1`, `todo`). On Gmail, whose server has the [X-GM-EXT-1] extension, they are the
+//! Gmail labels instead, system ones (eg. `\Important`) included. The hold keyword is not a label:
+//! it is only managed by the hold command.
+//!
+//! [X-GM-EXT-1]: https://developers.google.com/gmail/imap/imap-extensions
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Check if the given label can be stored as an IMAP keyword: keywords are atoms, made of ASCII
+/// letters, digits and symbols, without spaces nor any of `(){%*"\]`.
+pub fn is_keyword(label: &str) -> bool {
+    !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_graphic() && !r#"(){%*"\]"#.contains(c))
+}
+
+/// Check that the given label can be stored as an IMAP keyword.
+pub fn check_keyword(label: &str) -> Result<()> {
+    if is_keyword(label) {
+        return Ok(());
+    }
+    Err(anyhow!(
+        r#"cannot use label "{}" as IMAP keyword: keywords are made of ASCII letters, digits and symbols, without spaces nor any of (){{%*"\]"#,
+        label
+    ))
+}
+
+/// Represents the labels of a message.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MsgLabels {
+    pub id: u32,
+    pub labels: Vec<String>,
+}
+
+/// Represents the labels of a list of messages.
+#[derive(Debug, Default, Serialize)]
+pub struct MsgLabelsList(pub Vec<MsgLabels>);
+
+impl Display for MsgLabelsList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self
+            .0
+            .iter()
+            .map(|msg| format!("{}: {}", msg.id, msg.labels.join(", ")))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_check_keywords() {
+        assert!(is_keyword("$label1"));
+        assert!(is_keyword("todo"));
+        assert!(!is_keyword(""));
+        assert!(!is_keyword("to do"));
+        assert!(!is_keyword(r"\Seen"));
+        assert!(!is_keyword("café"));
+        assert!(check_keyword("(work)").is_err());
+    }
+}
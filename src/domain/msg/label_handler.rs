@@ -0,0 +1,64 @@
+//! Module related to message label handling.
+//!
+//! This module gathers all message label commands.
+
+use anyhow::Result;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::Backend,
+        msg::{msg_hold, MsgLabels, MsgLabelsList},
+    },
+    output::OutputServiceInterface,
+};
+
+/// Add labels to all messages within the given sequence range.
+pub fn add<'a, OutputService: OutputServiceInterface>(
+    seq_range: &'a str,
+    labels: Vec<&'a str>,
+    output: &'a OutputService,
+    backend: &'a mut dyn Backend,
+) -> Result<()> {
+    let labels: Vec<String> = labels.into_iter().map(String::from).collect();
+    backend.add_labels(seq_range, &labels)?;
+    output.print(format!(
+        r#"Label(s) "{}" successfully added to message(s) "{}""#,
+        labels.join(" "),
+        seq_range
+    ))
+}
+
+/// List the labels of all messages within the given sequence range.
+pub fn list<'a, OutputService: OutputServiceInterface>(
+    seq_range: &'a str,
+    output: &'a OutputService,
+    backend: &'a mut dyn Backend,
+) -> Result<()> {
+    let labels = backend
+        .get_labels(seq_range)?
+        .into_iter()
+        .map(|(id, labels)| MsgLabels { id, labels })
+        .collect();
+    output.print(MsgLabelsList(labels))
+}
+
+/// Remove labels from all messages within the given sequence range. The hold keyword is only
+/// removed with `override_hold`.
+pub fn remove<'a, OutputService: OutputServiceInterface>(
+    seq_range: &'a str,
+    labels: Vec<&'a str>,
+    override_hold: bool,
+    account: &'a Account,
+    output: &'a OutputService,
+    backend: &'a mut dyn Backend,
+) -> Result<()> {
+    msg_hold::check_release(labels.iter().copied(), override_hold, account)?;
+    let labels: Vec<String> = labels.into_iter().map(String::from).collect();
+    backend.remove_labels(seq_range, &labels)?;
+    output.print(format!(
+        r#"Label(s) "{}" successfully removed from message(s) "{}""#,
+        labels.join(" "),
+        seq_range
+    ))
+}
@@ -2,6 +2,8 @@
 //! structs which **represent the data** in Msgs/Mails.
 
 /// Includes the following subcommands:
+/// - `am`
+/// - `archive`
 /// - `list`
 /// - `search`
 /// - `write`
@@ -9,21 +11,67 @@
 /// - `save`
 /// - `read`
 /// - `attachments`
+/// - `bounce`
 /// - `reply`
+/// - `accept`, `decline` and `tentative`
+/// - `unsubscribe`
 /// - `forward`
 /// - `copy`
 /// - `move`
+/// - `pipe`
+/// - `dedup`
 /// - `delete`
+/// - `export`
+/// - `import`
+/// - `summarize`
+/// - `thread`
 /// - `template`
+/// - `part`
 ///
 /// Execute `himalaya help <cmd>` where `<cmd>` is one entry of this list above
 /// to get more information about them.
+#[cfg(feature = "cli")]
 pub mod msg_arg;
 
+pub mod msg_addr;
+pub mod msg_attachment_reminder;
+pub mod msg_body_search;
+pub mod msg_bounce;
+pub mod msg_compliance;
+pub mod msg_crm;
+pub mod msg_dedup;
+pub mod msg_diff;
+pub mod msg_digest;
+pub mod msg_export;
+pub mod msg_flowed;
+#[cfg(feature = "cli")]
 pub mod msg_handler;
+pub mod msg_hold;
+pub mod msg_hook;
+pub mod msg_html;
+pub mod msg_ical;
+pub mod msg_journal;
+pub mod msg_mailing_list;
+pub mod msg_mailto;
+pub mod msg_mdn;
+pub mod msg_patch;
+pub mod msg_query;
+pub mod msg_rewrite;
+pub mod msg_safety;
+pub mod msg_sandbox;
+pub mod msg_schedule;
+pub mod msg_share;
+pub mod msg_sig;
+pub mod msg_sig_rotation;
+pub mod msg_smime;
+pub mod msg_spellcheck;
+pub mod msg_split;
+pub mod msg_summary;
 pub mod msg_utils;
 
+#[cfg(feature = "cli")]
 pub mod flag_arg;
+#[cfg(feature = "cli")]
 pub mod flag_handler;
 
 pub mod flag_entity;
@@ -32,15 +80,25 @@ pub use flag_entity::*;
 pub mod flags_entity;
 pub use flags_entity::*;
 
+#[cfg(feature = "cli")]
+pub mod label_arg;
+#[cfg(feature = "cli")]
+pub mod label_handler;
+
+pub mod label_entity;
+pub use label_entity::*;
+
 pub mod envelope_entity;
 pub use envelope_entity::*;
 
 pub mod envelopes_entity;
 pub use envelopes_entity::*;
 
+#[cfg(feature = "cli")]
 pub mod tpl_arg;
-pub use tpl_arg::TplOverride;
 
+pub mod tpl_engine;
+#[cfg(feature = "cli")]
 pub mod tpl_handler;
 
 pub mod tpl_entity;
@@ -51,3 +109,14 @@ pub use msg_entity::*;
 
 pub mod parts_entity;
 pub use parts_entity::*;
+
+#[cfg(feature = "cli")]
+pub mod part_arg;
+#[cfg(feature = "cli")]
+pub mod part_handler;
+
+pub mod part_meta_entity;
+pub use part_meta_entity::*;
+
+pub mod sort_entity;
+pub use sort_entity::*;
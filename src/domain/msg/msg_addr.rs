@@ -0,0 +1,168 @@
+//! Module related to address headers validation.
+//!
+//! Address headers are parsed with an [RFC5322] parser before sending, so that a typo is
+//! reported with the header it is in, instead of being dropped or turning into an opaque SMTP
+//! error.
+//!
+//! [RFC5322]: https://datatracker.ietf.org/doc/html/rfc5322#section-3.4
+
+use anyhow::{anyhow, Result};
+use std::fmt::{self, Display};
+
+/// The headers holding addresses.
+const ADDR_HEADERS: [&str; 6] = ["From", "Sender", "Reply-To", "To", "Cc", "Bcc"];
+
+/// The headers holding recipients.
+const RCPT_HEADERS: [&str; 3] = ["To", "Cc", "Bcc"];
+
+/// Represents a malformed address header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddrError {
+    pub header: String,
+    /// The malformed address, or the whole header value when it cannot be split.
+    pub addr: String,
+    pub reason: String,
+}
+
+impl Display for AddrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            r#"Malformed "{}" header: "{}" ({})"#,
+            self.header, self.addr, self.reason
+        )
+    }
+}
+
+/// Validate the address headers of the given raw message or template.
+pub fn validate(raw: &str) -> Vec<AddrError> {
+    let headers = match mailparse::parse_headers(raw.as_bytes()) {
+        Ok((headers, _)) => headers,
+        // Unparsable headers are reported by the message parser.
+        Err(_) => return vec![],
+    };
+    let mut errors = vec![];
+
+    for header in headers.iter() {
+        let key = header.get_key();
+        let header_name = match ADDR_HEADERS
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(&key))
+        {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let val = header.get_value();
+        if val.trim().is_empty() {
+            continue;
+        }
+
+        let addrs = match mailparse::addrparse(&val) {
+            Ok(addrs) => addrs,
+            Err(err) => {
+                errors.push(AddrError {
+                    header: header_name,
+                    addr: val.trim().to_owned(),
+                    reason: err.to_string(),
+                });
+                continue;
+            }
+        };
+        for addr in addrs.iter() {
+            let singles = match addr {
+                mailparse::MailAddr::Single(single) => vec![single.to_owned()],
+                mailparse::MailAddr::Group(group) => group.addrs.to_owned(),
+            };
+            for single in singles {
+                if let Err(err) = single.addr.parse::<lettre::Address>() {
+                    errors.push(AddrError {
+                        header: header_name.to_owned(),
+                        addr: single.addr,
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// Check the address headers of the given raw message or template, failing with all the
+/// malformed ones.
+pub fn check(raw: &str) -> Result<()> {
+    let errors = validate(raw);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "cannot send message with malformed address headers:\n{}",
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    ))
+}
+
+/// Get the lowercased email of the recipients of the given raw message, as written in its
+/// headers. Addresses that cannot be parsed are skipped.
+pub fn recipients(raw_msg: &[u8]) -> Vec<String> {
+    let headers = match mailparse::parse_headers(raw_msg) {
+        Ok((headers, _)) => headers,
+        Err(_) => return vec![],
+    };
+    let mut rcpts = vec![];
+
+    for header in headers.iter() {
+        let key = header.get_key();
+        if !RCPT_HEADERS
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&key))
+        {
+            continue;
+        }
+        let addrs = match mailparse::addrparse(&header.get_value()) {
+            Ok(addrs) => addrs,
+            Err(_) => continue,
+        };
+        for addr in addrs.iter() {
+            match addr {
+                mailparse::MailAddr::Single(single) => rcpts.push(single.addr.to_lowercase()),
+                mailparse::MailAddr::Group(group) => {
+                    rcpts.extend(group.addrs.iter().map(|single| single.addr.to_lowercase()))
+                }
+            }
+        }
+    }
+
+    rcpts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_validate_address_headers() {
+        let tpl = "From: Alice <alice@localhost>\nTo: \"Doe, John\" <john@localhost>, bob@\nSubject: bob@\n\nHello";
+        let errors = validate(tpl);
+        assert_eq!(1, errors.len());
+        assert_eq!("To", errors[0].header);
+        assert_eq!("bob@", errors[0].addr);
+        assert!(check(tpl).is_err());
+
+        let tpl = "From: Alice <alice@localhost>\nTo: \"Doe, John\" <john@localhost>\nCc:\n\nHello";
+        assert!(validate(tpl).is_empty());
+        assert!(check(tpl).is_ok());
+    }
+
+    #[test]
+    fn it_should_get_recipients() {
+        let raw_msg = b"From: alice@localhost\r\nTo: Bob <Bob@localhost>\r\nResent-To: dave@localhost\r\nCc: friends: carol@localhost;\r\n\r\nHello";
+        assert_eq!(
+            vec!["bob@localhost", "carol@localhost"],
+            recipients(raw_msg)
+        );
+    }
+}
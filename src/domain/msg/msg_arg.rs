@@ -2,47 +2,222 @@
 //!
 //! This module provides subcommands, arguments and a command matcher related to message.
 
-use anyhow::Result;
-use clap::{self, App, Arg, ArgMatches, SubCommand};
+use anyhow::{Context, Result};
+use clap::{self, App, Arg, ArgGroup, ArgMatches, SubCommand};
 use log::{debug, trace};
+use std::convert::TryFrom;
 
-use crate::domain::{
-    mbox::mbox_arg,
-    msg::{flag_arg, msg_arg, tpl_arg},
+use crate::{
+    config::parse_size,
+    domain::{
+        mbox::mbox_arg,
+        msg::{
+            flag_arg, label_arg, msg_arg,
+            msg_body_search::BodySearch,
+            msg_export::ExportFormat,
+            msg_ical::PartStat,
+            msg_query::{SearchCriterion, SearchQuery, SearchTerm},
+            part_arg, tpl_arg, Column,
+        },
+    },
 };
 
 type Seq<'a> = &'a str;
+type SeqRange<'a> = &'a str;
 type PageSize = usize;
 type Page = usize;
 type Mbox<'a> = Option<&'a str>;
 type Mime = String;
 type Raw = bool;
+type Sandbox = bool;
+type MaxSize = Option<usize>;
+type Permanent = bool;
+type DryRun = bool;
+pub(crate) type OverrideHold = bool;
+type Release = bool;
+type AsAttachment = bool;
+type Interactive = bool;
+type Lists = bool;
+type Patches = bool;
+type RelativeDates = bool;
+type Unseen = bool;
+type Columns = Option<Vec<Column>>;
 type All = bool;
 type RawMsg<'a> = &'a str;
+type IdempotencyKey<'a> = Option<&'a str>;
+type Dir<'a> = &'a str;
+type WatchInterval = Option<u64>;
+type Path<'a> = &'a str;
 type Query = String;
+type QueryName<'a> = Option<&'a str>;
+type Sort<'a> = Option<&'a str>;
+type WholeThread = bool;
+type Digest = bool;
+type Headers = bool;
+type Cmd<'a> = &'a str;
+type Text = bool;
+type Identity<'a> = Option<&'a str>;
+type Suggest = bool;
+type ProposedTimes<'a> = Option<&'a str>;
+type Receipt = bool;
+type ToList = bool;
 type AttachmentsPaths<'a> = Vec<&'a str>;
+type Addrs<'a> = Vec<&'a str>;
+type AddedHeaders<'a> = Vec<&'a str>;
+type RemovedHeaders<'a> = Vec<&'a str>;
+pub(crate) type QuoteMatch<'a> = Option<&'a str>;
+type UseSeq = bool;
+
+/// Options of the dedup command.
+pub struct DedupOpts<'a> {
+    pub target: Mbox<'a>,
+    pub dry_run: DryRun,
+    pub override_hold: OverrideHold,
+    pub use_seq: UseSeq,
+}
+
+/// Options of the export command.
+pub struct ExportOpts<'a> {
+    pub format: ExportFormat,
+    pub dir: Option<Dir<'a>>,
+    pub use_seq: UseSeq,
+}
+
+/// Options of the forward command.
+#[derive(Default)]
+pub struct ForwardOpts<'a> {
+    pub attachments_paths: AttachmentsPaths<'a>,
+    pub headers: AddedHeaders<'a>,
+    pub as_attachment: AsAttachment,
+    pub identity: Identity<'a>,
+    pub receipt: Receipt,
+}
+
+/// Options of the list command.
+#[derive(Default)]
+pub struct ListOpts<'a> {
+    pub page_size: Option<PageSize>,
+    pub page: Page,
+    pub sort: Sort<'a>,
+    pub query: QueryName<'a>,
+    pub interactive: Interactive,
+    pub lists: Lists,
+    pub patches: Patches,
+    pub relative_dates: RelativeDates,
+    pub columns: Columns,
+}
+
+/// Options of the read command.
+pub struct ReadOpts {
+    pub mime: Mime,
+    pub raw: Raw,
+    pub sandbox: Sandbox,
+    pub headers: Headers,
+    pub max_size: MaxSize,
+}
+
+/// Options of the reply command.
+#[derive(Default)]
+pub struct ReplyOpts<'a> {
+    pub all: All,
+    pub quote_match: QuoteMatch<'a>,
+    pub attachments_paths: AttachmentsPaths<'a>,
+    pub headers: AddedHeaders<'a>,
+    pub identity: Identity<'a>,
+    pub suggest: Suggest,
+    pub proposed_times: ProposedTimes<'a>,
+    pub receipt: Receipt,
+    pub to_list: ToList,
+}
+
+/// Options of the rewrite command.
+pub struct RewriteOpts<'a> {
+    pub add: AddedHeaders<'a>,
+    pub remove: RemovedHeaders<'a>,
+    pub override_hold: OverrideHold,
+}
+
+/// Options of the search command.
+#[derive(Default)]
+pub struct SearchOpts {
+    pub query: Query,
+    pub body: Option<BodySearch>,
+    pub page_size: Option<PageSize>,
+    pub page: Page,
+    pub interactive: Interactive,
+    pub relative_dates: RelativeDates,
+    pub columns: Columns,
+}
+
+/// Options of the write command.
+#[derive(Default)]
+pub struct WriteOpts<'a> {
+    pub attachments_paths: AttachmentsPaths<'a>,
+    pub headers: AddedHeaders<'a>,
+    pub identity: Identity<'a>,
+    pub proposed_times: ProposedTimes<'a>,
+    pub receipt: Receipt,
+}
 
 /// Message commands.
 pub enum Command<'a> {
+    Am(SeqRange<'a>, Option<Dir<'a>>),
+    Archive(SeqRange<'a>, OverrideHold),
     Attachments(Seq<'a>),
-    Copy(Seq<'a>, Mbox<'a>),
-    Delete(Seq<'a>),
-    Forward(Seq<'a>, AttachmentsPaths<'a>),
-    List(Option<PageSize>, Page),
-    Move(Seq<'a>, Mbox<'a>),
-    Read(Seq<'a>, Mime, Raw),
-    Reply(Seq<'a>, All, AttachmentsPaths<'a>),
+    Bounce(Seq<'a>, Addrs<'a>),
+    Copy(SeqRange<'a>, Mbox<'a>),
+    Count(Unseen),
+    Dedup(DedupOpts<'a>),
+    Delete(SeqRange<'a>, Permanent, OverrideHold),
+    Diff(Seq<'a>, Seq<'a>, Headers),
+    Export(SeqRange<'a>, ExportOpts<'a>),
+    Forward(SeqRange<'a>, ForwardOpts<'a>),
+    Ham(SeqRange<'a>, OverrideHold),
+    Hold(SeqRange<'a>, Release),
+    Import(Path<'a>),
+    Journal(Dir<'a>, WatchInterval),
+    List(ListOpts<'a>),
+    Move(SeqRange<'a>, Mbox<'a>),
+    Pipe(Seq<'a>, Cmd<'a>, Text),
+    Read(SeqRange<'a>, ReadOpts),
+    Reply(Seq<'a>, ReplyOpts<'a>),
+    Rewrite(SeqRange<'a>, RewriteOpts<'a>),
+    Rsvp(Seq<'a>, PartStat),
     Save(Mbox<'a>, RawMsg<'a>),
-    Search(Query, Option<PageSize>, Page),
-    Send(RawMsg<'a>),
-    Write(AttachmentsPaths<'a>),
+    Search(SearchOpts),
+    Send(RawMsg<'a>, IdempotencyKey<'a>),
+    Spam(SeqRange<'a>, OverrideHold),
+    Summarize(Seq<'a>, WholeThread),
+    Thread(Seq<'a>, Digest),
+    Unsubscribe(Seq<'a>),
+    Write(WriteOpts<'a>),
 
     Flag(Option<flag_arg::Command<'a>>),
+    Label(Option<label_arg::Command<'a>>),
+    Part(Option<part_arg::Command<'a>>),
     Tpl(Option<tpl_arg::Command<'a>>),
 }
 
 /// Message command matcher.
 pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("am") {
+        debug!("am command matched");
+        let seq_range = m.value_of("seq-range").unwrap();
+        trace!("seq range: {}", seq_range);
+        let dir = m.value_of("dir");
+        trace!("dir: {:?}", dir);
+        return Ok(Some(Command::Am(seq_range, dir)));
+    }
+
+    if let Some(m) = m.subcommand_matches("archive") {
+        debug!("archive command matched");
+        let seq = m.value_of("seq-range").unwrap();
+        trace!("seq: {}", seq);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Archive(seq, override_hold)));
+    }
+
     if let Some(m) = m.subcommand_matches("attachments") {
         debug!("attachments command matched");
         let seq = m.value_of("seq").unwrap();
@@ -50,29 +225,149 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         return Ok(Some(Command::Attachments(seq)));
     }
 
+    if let Some(m) = m.subcommand_matches("bounce") {
+        debug!("bounce command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        let addrs: Vec<&str> = m.values_of("addrs").unwrap_or_default().collect();
+        trace!("addrs: {:?}", addrs);
+        return Ok(Some(Command::Bounce(seq, addrs)));
+    }
+
     if let Some(m) = m.subcommand_matches("copy") {
         debug!("copy command matched");
-        let seq = m.value_of("seq").unwrap();
+        let seq = m.value_of("seq-range").unwrap();
         trace!("seq: {}", seq);
         let target = m.value_of("target");
         trace!(r#"target mailbox: "{:?}""#, target);
         return Ok(Some(Command::Copy(seq, target)));
     }
 
+    if let Some(m) = m.subcommand_matches("count") {
+        debug!("count command matched");
+        let unseen = m.is_present("unseen");
+        trace!("unseen: {}", unseen);
+        return Ok(Some(Command::Count(unseen)));
+    }
+
+    let use_seq = m.is_present("use-seq");
+    if let Some(m) = m.subcommand_matches("dedup") {
+        debug!("dedup command matched");
+        let target = m.value_of("target");
+        trace!(r#"target mailbox: "{:?}""#, target);
+        let dry_run = m.is_present("dry-run");
+        trace!("dry run: {}", dry_run);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Dedup(DedupOpts {
+            target,
+            dry_run,
+            override_hold,
+            use_seq,
+        })));
+    }
+
     if let Some(m) = m.subcommand_matches("delete") {
-        debug!("copy command matched");
+        debug!("delete command matched");
+        let seq = m.value_of("seq-range").unwrap();
+        trace!("seq: {}", seq);
+        let permanent = m.is_present("permanent");
+        trace!("permanent: {}", permanent);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Delete(seq, permanent, override_hold)));
+    }
+
+    if let Some(m) = m.subcommand_matches("diff") {
+        debug!("diff command matched");
         let seq = m.value_of("seq").unwrap();
         trace!("seq: {}", seq);
-        return Ok(Some(Command::Delete(seq)));
+        let other_seq = m.value_of("other-seq").unwrap();
+        trace!("other seq: {}", other_seq);
+        let headers = m.is_present("headers");
+        trace!("headers: {}", headers);
+        return Ok(Some(Command::Diff(seq, other_seq, headers)));
+    }
+
+    if let Some(m) = m.subcommand_matches("ham") {
+        debug!("ham command matched");
+        let seq = m.value_of("seq-range").unwrap();
+        trace!("seq: {}", seq);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Ham(seq, override_hold)));
+    }
+
+    if let Some(m) = m.subcommand_matches("hold") {
+        debug!("hold command matched");
+        let seq = m.value_of("seq-range").unwrap();
+        trace!("seq: {}", seq);
+        let release = m.is_present("release");
+        trace!("release: {}", release);
+        return Ok(Some(Command::Hold(seq, release)));
+    }
+
+    if let Some(m) = m.subcommand_matches("export") {
+        debug!("export command matched");
+        if let Some(dir) = m.value_of("journal") {
+            trace!("journal dir: {}", dir);
+            let interval = match m.value_of("watch") {
+                Some(secs) => Some(
+                    secs.parse()
+                        .context(format!(r#"cannot parse watch interval "{}""#, secs))?,
+                ),
+                None => None,
+            };
+            trace!("watch interval: {:?}", interval);
+            return Ok(Some(Command::Journal(dir, interval)));
+        }
+        let seq = m.value_of("seq-range").unwrap();
+        trace!("seq: {}", seq);
+        let format = ExportFormat::try_from(m.value_of("export-format").unwrap_or("eml"))?;
+        trace!("format: {:?}", format);
+        let dir = m.value_of("dir");
+        trace!("dir: {:?}", dir);
+        return Ok(Some(Command::Export(
+            seq,
+            ExportOpts {
+                format,
+                dir,
+                use_seq,
+            },
+        )));
     }
 
     if let Some(m) = m.subcommand_matches("forward") {
         debug!("forward command matched");
         let seq = m.value_of("seq").unwrap();
         trace!("seq: {}", seq);
-        let paths: Vec<&str> = m.values_of("attachments").unwrap_or_default().collect();
-        trace!("attachments paths: {:?}", paths);
-        return Ok(Some(Command::Forward(seq, paths)));
+        let attachments_paths: Vec<&str> = m.values_of("attachments").unwrap_or_default().collect();
+        trace!("attachments paths: {:?}", attachments_paths);
+        let headers: Vec<&str> = m.values_of("header").unwrap_or_default().collect();
+        trace!("headers: {:?}", headers);
+        let as_attachment = m.is_present("as-attachment");
+        trace!("as attachment: {}", as_attachment);
+        let identity = m.value_of("identity");
+        trace!("identity: {:?}", identity);
+        let receipt = m.is_present("receipt");
+        trace!("receipt: {}", receipt);
+        return Ok(Some(Command::Forward(
+            seq,
+            ForwardOpts {
+                attachments_paths,
+                headers,
+                as_attachment,
+                identity,
+                receipt,
+            },
+        )));
+    }
+
+    if let Some(m) = m.subcommand_matches("import") {
+        debug!("import command matched");
+        let path = m.value_of("path").unwrap();
+        trace!("path: {}", path);
+        return Ok(Some(Command::Import(path)));
     }
 
     if let Some(m) = m.subcommand_matches("list") {
@@ -87,27 +382,93 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
             .map(|page| 1.max(page) - 1)
             .unwrap_or_default();
         trace!(r#"page: "{:?}""#, page);
-        return Ok(Some(Command::List(page_size, page)));
+        let sort = m.value_of("sort");
+        trace!(r#"sort: "{:?}""#, sort);
+        let query = m.value_of("query");
+        trace!(r#"query: "{:?}""#, query);
+        let interactive = m.is_present("interactive");
+        trace!("interactive: {}", interactive);
+        let lists = m.is_present("lists");
+        trace!("lists: {}", lists);
+        let patches = m.is_present("patches");
+        trace!("patches: {}", patches);
+        let relative_dates = m.is_present("relative-dates");
+        trace!("relative dates: {}", relative_dates);
+        let columns = m.value_of("columns").map(Column::parse_list).transpose()?;
+        trace!("columns: {:?}", columns);
+        return Ok(Some(Command::List(ListOpts {
+            page_size,
+            page,
+            sort,
+            query,
+            interactive,
+            lists,
+            patches,
+            relative_dates,
+            columns,
+        })));
     }
 
     if let Some(m) = m.subcommand_matches("move") {
         debug!("move command matched");
-        let seq = m.value_of("seq").unwrap();
+        let seq = m.value_of("seq-range").unwrap();
         trace!("seq: {}", seq);
         let target = m.value_of("target");
         trace!(r#"target mailbox: "{:?}""#, target);
         return Ok(Some(Command::Move(seq, target)));
     }
 
+    if let Some(m) = m.subcommand_matches("pipe") {
+        debug!("pipe command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        let cmd = m.value_of("cmd").unwrap();
+        trace!("cmd: {}", cmd);
+        let text = m.is_present("text");
+        trace!("text: {}", text);
+        return Ok(Some(Command::Pipe(seq, cmd, text)));
+    }
+
+    for (name, partstat) in [
+        ("accept", PartStat::Accepted),
+        ("decline", PartStat::Declined),
+        ("tentative", PartStat::Tentative),
+    ] {
+        if let Some(m) = m.subcommand_matches(name) {
+            debug!("{} command matched", name);
+            let seq = m.value_of("seq").unwrap();
+            trace!("seq: {}", seq);
+            return Ok(Some(Command::Rsvp(seq, partstat)));
+        }
+    }
+
     if let Some(m) = m.subcommand_matches("read") {
         debug!("read command matched");
-        let seq = m.value_of("seq").unwrap();
+        let seq = m.value_of("seq-range").unwrap();
         trace!("seq: {}", seq);
         let mime = format!("text/{}", m.value_of("mime-type").unwrap());
         trace!("mime: {}", mime);
         let raw = m.is_present("raw");
         trace!("raw: {}", raw);
-        return Ok(Some(Command::Read(seq, mime, raw)));
+        let sandbox = m.is_present("sandbox");
+        trace!("sandbox: {}", sandbox);
+        let headers = m.is_present("headers");
+        trace!("headers: {}", headers);
+        let max_size = m
+            .value_of("max-size")
+            .map(|size| parse_size(size).context(format!(r#"cannot parse max size "{}""#, size)))
+            .transpose()?;
+        trace!("max size: {:?}", max_size);
+        return Ok(Some(Command::Read(
+            seq,
+            ReadOpts {
+                mime,
+                raw,
+                sandbox,
+                headers,
+                max_size,
+            },
+        )));
     }
 
     if let Some(m) = m.subcommand_matches("reply") {
@@ -116,9 +477,56 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         trace!("seq: {}", seq);
         let all = m.is_present("reply-all");
         trace!("reply all: {}", all);
-        let paths: Vec<&str> = m.values_of("attachments").unwrap_or_default().collect();
-        trace!("attachments paths: {:#?}", paths);
-        return Ok(Some(Command::Reply(seq, all, paths)));
+        let quote_match = m.value_of("quote-match");
+        trace!("quote match: {:?}", quote_match);
+        let attachments_paths: Vec<&str> = m.values_of("attachments").unwrap_or_default().collect();
+        trace!("attachments paths: {:#?}", attachments_paths);
+        let headers: Vec<&str> = m.values_of("header").unwrap_or_default().collect();
+        trace!("headers: {:?}", headers);
+        let identity = m.value_of("identity");
+        trace!("identity: {:?}", identity);
+        let suggest = m.is_present("suggest");
+        trace!("suggest: {}", suggest);
+        let proposed_times = m.value_of("propose-times");
+        trace!("proposed times: {:?}", proposed_times);
+        let receipt = m.is_present("receipt");
+        trace!("receipt: {}", receipt);
+        let to_list = m.is_present("list");
+        trace!("to list: {}", to_list);
+        return Ok(Some(Command::Reply(
+            seq,
+            ReplyOpts {
+                all,
+                quote_match,
+                attachments_paths,
+                headers,
+                identity,
+                suggest,
+                proposed_times,
+                receipt,
+                to_list,
+            },
+        )));
+    }
+
+    if let Some(m) = m.subcommand_matches("rewrite") {
+        debug!("rewrite command matched");
+        let seq_range = m.value_of("seq-range").unwrap();
+        trace!("seq range: {}", seq_range);
+        let add: Vec<&str> = m.values_of("add-header").unwrap_or_default().collect();
+        trace!("added headers: {:?}", add);
+        let remove: Vec<&str> = m.values_of("remove-header").unwrap_or_default().collect();
+        trace!("removed headers: {:?}", remove);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Rewrite(
+            seq_range,
+            RewriteOpts {
+                add,
+                remove,
+                override_hold,
+            },
+        )));
     }
 
     if let Some(m) = m.subcommand_matches("save") {
@@ -142,46 +550,138 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
             .map(|page| 1.max(page) - 1)
             .unwrap_or_default();
         trace!(r#"page: "{:?}""#, page);
-        let query = m
-            .values_of("query")
-            .unwrap_or_default()
-            .fold((false, vec![]), |(escape, mut cmds), cmd| {
-                match (cmd, escape) {
-                    // Next command is an arg and needs to be escaped
-                    ("subject", _) | ("body", _) | ("text", _) => {
-                        cmds.push(cmd.to_string());
-                        (true, cmds)
+        let imap = m.is_present("imap");
+        trace!("imap: {}", imap);
+        let query = if imap {
+            m.values_of("query")
+                .unwrap_or_default()
+                .fold((false, vec![]), |(escape, mut cmds), cmd| {
+                    match (cmd, escape) {
+                        // Next command is an arg and needs to be escaped
+                        ("subject", _) | ("body", _) | ("text", _) => {
+                            cmds.push(cmd.to_string());
+                            (true, cmds)
+                        }
+                        // Escaped arg commands
+                        (_, true) => {
+                            cmds.push(format!("\"{}\"", cmd));
+                            (false, cmds)
+                        }
+                        // Regular commands
+                        (_, false) => {
+                            cmds.push(cmd.to_string());
+                            (false, cmds)
+                        }
                     }
-                    // Escaped arg commands
-                    (_, true) => {
-                        cmds.push(format!("\"{}\"", cmd));
-                        (false, cmds)
-                    }
-                    // Regular commands
-                    (_, false) => {
-                        cmds.push(cmd.to_string());
-                        (false, cmds)
-                    }
-                }
-            })
-            .1
-            .join(" ");
+                })
+                .1
+                .join(" ")
+        } else {
+            let mut query = SearchQuery::from_args(m.values_of("query").unwrap_or_default())?;
+            let gmail = m.value_of("gmail");
+            trace!("gmail query: {:?}", gmail);
+            if let Some(gmail) = gmail {
+                query.0.push(SearchTerm {
+                    criterion: SearchCriterion::GmailRaw(gmail.to_owned()),
+                    negated: false,
+                });
+            }
+            query.to_imap()
+        };
         trace!(r#"query: "{:?}""#, query);
-        return Ok(Some(Command::Search(query, page_size, page)));
+        let body = m.value_of("body").map(|terms| {
+            let concurrency = m
+                .value_of("concurrency")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(10);
+            BodySearch::new(terms, concurrency, !use_seq)
+        });
+        trace!("body search: {:?}", body);
+        // The body search narrows the messages matching the query, all of them by default.
+        let query = if query.is_empty() && body.is_some() {
+            String::from("ALL")
+        } else {
+            query
+        };
+        let interactive = m.is_present("interactive");
+        trace!("interactive: {}", interactive);
+        let relative_dates = m.is_present("relative-dates");
+        trace!("relative dates: {}", relative_dates);
+        let columns = m.value_of("columns").map(Column::parse_list).transpose()?;
+        trace!("columns: {:?}", columns);
+        return Ok(Some(Command::Search(SearchOpts {
+            query,
+            body,
+            page_size,
+            page,
+            interactive,
+            relative_dates,
+            columns,
+        })));
     }
 
     if let Some(m) = m.subcommand_matches("send") {
         debug!("send command matched");
         let msg = m.value_of("message").unwrap_or_default();
         trace!("message: {}", msg);
-        return Ok(Some(Command::Send(msg)));
+        let idempotency_key = m.value_of("idempotency-key");
+        trace!("idempotency key: {:?}", idempotency_key);
+        return Ok(Some(Command::Send(msg, idempotency_key)));
+    }
+
+    if let Some(m) = m.subcommand_matches("spam") {
+        debug!("spam command matched");
+        let seq = m.value_of("seq-range").unwrap();
+        trace!("seq: {}", seq);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Spam(seq, override_hold)));
+    }
+
+    if let Some(m) = m.subcommand_matches("summarize") {
+        debug!("summarize command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        let thread = m.is_present("thread");
+        trace!("thread: {}", thread);
+        return Ok(Some(Command::Summarize(seq, thread)));
+    }
+
+    if let Some(m) = m.subcommand_matches("thread") {
+        debug!("thread command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        let digest = m.is_present("digest");
+        trace!("digest: {}", digest);
+        return Ok(Some(Command::Thread(seq, digest)));
+    }
+
+    if let Some(m) = m.subcommand_matches("unsubscribe") {
+        debug!("unsubscribe command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        return Ok(Some(Command::Unsubscribe(seq)));
     }
 
     if let Some(m) = m.subcommand_matches("write") {
         debug!("write command matched");
-        let attachment_paths: Vec<&str> = m.values_of("attachments").unwrap_or_default().collect();
-        trace!("attachments paths: {:?}", attachment_paths);
-        return Ok(Some(Command::Write(attachment_paths)));
+        let attachments_paths: Vec<&str> = m.values_of("attachments").unwrap_or_default().collect();
+        trace!("attachments paths: {:?}", attachments_paths);
+        let headers: Vec<&str> = m.values_of("header").unwrap_or_default().collect();
+        trace!("headers: {:?}", headers);
+        let identity = m.value_of("identity");
+        trace!("identity: {:?}", identity);
+        let proposed_times = m.value_of("propose-times");
+        trace!("proposed times: {:?}", proposed_times);
+        let receipt = m.is_present("receipt");
+        trace!("receipt: {}", receipt);
+        return Ok(Some(Command::Write(WriteOpts {
+            attachments_paths,
+            headers,
+            identity,
+            proposed_times,
+            receipt,
+        })));
     }
 
     if let Some(m) = m.subcommand_matches("template") {
@@ -192,24 +692,49 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         return Ok(Some(Command::Flag(flag_arg::matches(&m)?)));
     }
 
+    if let Some(m) = m.subcommand_matches("label") {
+        return Ok(Some(Command::Label(label_arg::matches(&m)?)));
+    }
+
+    if let Some(m) = m.subcommand_matches("part") {
+        return Ok(Some(Command::Part(part_arg::matches(&m)?)));
+    }
+
     debug!("default list command matched");
-    Ok(Some(Command::List(None, 0)))
+    Ok(Some(Command::List(ListOpts::default())))
 }
 
 /// Message sequence number argument.
 pub(crate) fn seq_arg<'a>() -> Arg<'a, 'a> {
     Arg::with_name("seq")
         .help("Specifies the targetted message")
-        .value_name("SEQ")
+        .long_help("Specifies the targetted message by its UID, or by its sequence number when `--seq` is given.")
+        .value_name("ID")
         .required(true)
 }
 
+/// Sequence numbers argument.
+pub fn use_seq_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("use-seq")
+        .long("seq")
+        .help("Identifies messages by sequence number instead of UID")
+        .long_help("Identifies messages by sequence number instead of UID, in arguments and in listings. Sequence numbers shift when messages are expunged.")
+        .global(true)
+}
+
+/// Override hold argument, letting destructive commands touch messages on hold.
+pub(crate) fn override_hold_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("override-hold")
+        .help("Proceeds even with messages on hold")
+        .long("override-hold")
+}
+
 /// Message sequence range argument.
 pub(crate) fn seq_range_arg<'a>() -> Arg<'a, 'a> {
     Arg::with_name("seq-range")
         .help("Specifies targetted message(s)")
-        .long_help("Specifies a range of targetted messages. The range follows the [RFC3501](https://datatracker.ietf.org/doc/html/rfc3501#section-9) format: `1:5` matches messages with sequence number between 1 and 5, `1,5` matches messages with sequence number 1 or 5, * matches all messages.")
-        .value_name("SEQ")
+        .long_help("Specifies a range of targetted messages. The range follows the [RFC3501](https://datatracker.ietf.org/doc/html/rfc3501#section-9) format: `1:5` matches messages with UID between 1 and 5, `1,5` matches messages with UID 1 or 5, * matches all messages. Sequence numbers are used instead of UIDs when `--seq` is given.")
+        .value_name("IDS")
         .required(true)
 }
 
@@ -221,6 +746,41 @@ pub(crate) fn reply_all_arg<'a>() -> Arg<'a, 'a> {
         .long("all")
 }
 
+/// Message reply quote match argument.
+pub(crate) fn quote_match_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("quote-match")
+        .help("Quotes only the lines around the given text")
+        .long_help("Quotes only the lines of the original message containing the given text (case-insensitive), surrounded by `reply-quote-context` lines (default to 2). All the lines are quoted if none matches.")
+        .long("quote-match")
+        .value_name("TEXT")
+}
+
+/// Message identity argument.
+fn identity_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("identity")
+        .help("Writes the message as the given identity")
+        .long_help("Writes the message as the given identity of the account, given by its alias or by its address, using its name, address and signature.")
+        .long("from")
+        .value_name("IDENTITY")
+}
+
+/// Message proposed times argument.
+fn propose_times_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("propose-times")
+        .help("Proposes the given time slots")
+        .long_help("Starts the message with a block proposing the given time slots, separated by commas, like `mon 10-12, tue 14:30-16`. A slot starts with a weekday, `today`, `tomorrow` or a `YYYY-MM-DD` date. When replying, the slots are also given in the time zone of the original message.")
+        .long("propose-times")
+        .value_name("SLOTS")
+}
+
+/// Message read receipt argument.
+fn receipt_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("receipt")
+        .help("Asks for a read receipt")
+        .long_help("Asks the recipients for a read receipt, sent back to the account address, by setting the `Disposition-Notification-To` header.")
+        .long("receipt")
+}
+
 /// Message page size argument.
 fn page_size_arg<'a>() -> Arg<'a, 'a> {
     Arg::with_name("page-size")
@@ -240,6 +800,40 @@ fn page_arg<'a>() -> Arg<'a, 'a> {
         .default_value("0")
 }
 
+/// Message sort argument.
+fn sort_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("sort")
+        .help("Sorts messages by the given criteria")
+        .long_help("Sorts messages by the given criteria. Available criteria are arrival, date, from and subject, each of them can be prefixed by reverse. The syntax follows the [RFC5256](https://datatracker.ietf.org/doc/html/rfc5256#section-3), for example: `reverse date subject`. When the server does not support the SORT extension, messages are sorted client-side.")
+        .long("sort")
+        .value_name("CRITERIA")
+}
+
+/// Message relative dates argument.
+fn relative_dates_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("relative-dates")
+        .help("Shows dates relatively to now")
+        .long_help("Shows the dates of the last week relatively to now, for example `2h ago`, older ones with the datetime-fmt of the account.")
+        .long("relative-dates")
+}
+
+/// Message columns argument.
+fn columns_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("columns")
+        .help("Selects the columns of the listing")
+        .long_help("Selects the columns of the listing and their order, separated by commas. Columns are uid, flags, date, from, subject, size, labels and thread (Gmail thread ids), the flags showing an @ for messages with attachments. The JSON output includes all the fields, whatever the columns.")
+        .long("columns")
+        .value_name("COLUMNS")
+}
+
+/// Message interactive argument.
+fn interactive_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("interactive")
+        .help("Picks a message with a fuzzy selector, then an action to run on it")
+        .short("i")
+        .long("interactive")
+}
+
 /// Message attachment argument.
 fn attachment_arg<'a>() -> Arg<'a, 'a> {
     Arg::with_name("attachments")
@@ -250,10 +844,23 @@ fn attachment_arg<'a>() -> Arg<'a, 'a> {
         .multiple(true)
 }
 
+/// Message header argument.
+fn header_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("header")
+        .help("Sets a header of the message")
+        .long_help("Sets a header of the message, replacing the default header of the same name (eg. `--header \"X-Clacks-Overhead: GNU Terry Pratchett\"`). Can be repeated.")
+        .long("header")
+        .value_name("NAME: VALUE")
+        .multiple(true)
+        .number_of_values(1)
+}
+
 /// Message subcommands.
 pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
     vec![
         flag_arg::subcmds(),
+        label_arg::subcmds(),
+        part_arg::subcmds(),
         tpl_arg::subcmds(),
         vec![
             SubCommand::with_name("attachments")
@@ -264,32 +871,101 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
                 .aliases(&["lst", "l"])
                 .about("Lists all messages")
                 .arg(page_size_arg())
-                .arg(page_arg()),
+                .arg(page_arg())
+                .arg(sort_arg())
+                .arg(
+                    Arg::with_name("query")
+                        .help("Lists the messages matching the given saved query")
+                        .long_help("Lists the messages of the selected mailbox matching the given query, saved in the `queries` section of the config.")
+                        .long("query")
+                        .short("q")
+                        .value_name("NAME")
+                        .conflicts_with("sort"),
+                )
+                .arg(interactive_arg())
+                .arg(relative_dates_arg())
+                .arg(columns_arg())
+                .arg(
+                    Arg::with_name("lists")
+                        .help("Shows the mailing list of each message")
+                        .long_help("Shows the mailing list each message was distributed by, from its `List-Id` header, in a LIST column.")
+                        .long("lists")
+                        .conflicts_with("query"),
+                )
+                .arg(
+                    Arg::with_name("patches")
+                        .help("Lists only the messages looking like patches")
+                        .long_help("Lists only the messages looking like patches generated by `git format-patch`, whose subject starts with a tag like [PATCH] or [RFC PATCH v2 1/3]. Replies to patches are left out.")
+                        .long("patches")
+                        .conflicts_with_all(&["query", "sort"]),
+                ),
             SubCommand::with_name("search")
                 .aliases(&["s", "query", "q"])
-                .about("Lists messages matching the given IMAP query")
+                .about("Lists messages matching the given query")
                 .arg(page_size_arg())
                 .arg(page_arg())
+                .arg(interactive_arg())
+                .arg(relative_dates_arg())
+                .arg(columns_arg())
                 .arg(
                     Arg::with_name("query")
-                        .help("IMAP query")
-                        .long_help("The IMAP query format follows the [RFC3501](https://tools.ietf.org/html/rfc3501#section-6.4.4). The query is case-insensitive.")
+                        .help("Search query")
+                        .long_help("Terms all messages must match, either `key:value` or a free word searched in the whole message, negated with a leading `-`. Keys are from, to, cc, bcc, subject, body, text, after, before, on (YYYY-MM-DD dates), flag, label, has (attachment), larger and smaller (sizes with an optional K, M or G suffix). Example: `from:alice subject:\"quarterly report\" after:2024-01-01 has:attachment -flag:seen`. Matching is case-insensitive.")
                         .value_name("QUERY")
                         .multiple(true)
-                        .required(true),
+                        .required_unless_one(&["body", "gmail"]),
+                )
+                .arg(
+                    Arg::with_name("imap")
+                        .help("Passes the query as is to the IMAP server")
+                        .long_help("Passes the query as is to the IMAP server. The IMAP query format follows the [RFC3501](https://tools.ietf.org/html/rfc3501#section-6.4.4).")
+                        .long("imap"),
+                )
+                .arg(
+                    Arg::with_name("gmail")
+                        .help("Searches with the given Gmail query")
+                        .long_help("Searches with the given query in the Gmail search syntax, like `from:boss has:attachment`, narrowing the messages matching the other terms. Requires the Gmail IMAP extensions (X-GM-EXT-1).")
+                        .long("gmail")
+                        .value_name("QUERY")
+                        .conflicts_with("imap"),
+                )
+                .arg(
+                    Arg::with_name("body")
+                        .help("Scans message bodies client-side for the given terms")
+                        .long_help("Downloads the messages matching the query, all of them by default, and keeps the ones whose text parts contain all the given terms (case-insensitive). Useful with servers only searching headers. Downloaded messages are cached, so that next searches run offline.")
+                        .long("body")
+                        .value_name("TERMS"),
+                )
+                .arg(
+                    Arg::with_name("concurrency")
+                        .help("Defines the maximum number of messages fetched at once by the body search")
+                        .long("concurrency")
+                        .value_name("N")
+                        .default_value("10"),
                 ),
             SubCommand::with_name("write")
                 .about("Writes a new message")
-                .arg(attachment_arg()),
+                .arg(attachment_arg())
+                .arg(header_arg())
+                .arg(identity_arg())
+                .arg(propose_times_arg())
+                .arg(receipt_arg()),
             SubCommand::with_name("send")
                 .about("Sends a raw message")
+                .arg(
+                    Arg::with_name("idempotency-key")
+                        .help("Sends the message only if no message was sent with this key before")
+                        .long_help("Sends the message only if no message was sent with this key before, according to the delivery log, so that scripted retries never send the same message twice.")
+                        .long("idempotency-key")
+                        .value_name("KEY"),
+                )
                 .arg(Arg::with_name("message").raw(true).last(true)),
             SubCommand::with_name("save")
                 .about("Saves a raw message")
                 .arg(Arg::with_name("message").raw(true)),
             SubCommand::with_name("read")
-                .about("Reads text bodies of a message")
-                .arg(seq_arg())
+                .about("Reads text bodies of messages")
+                .arg(seq_range_arg())
                 .arg(
                     Arg::with_name("mime-type")
                         .help("MIME type to use")
@@ -304,32 +980,299 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
                         .help("Reads raw message")
                         .long("raw")
                         .short("r"),
+                )
+                .arg(
+                    Arg::with_name("sandbox")
+                        .help("Reads suspicious messages safely")
+                        .long_help("Reads suspicious messages safely: only text is rendered, links are listed as footnotes, attachments are not decoded, messages are not flagged as seen and no read receipt is sent.")
+                        .long("sandbox")
+                        .conflicts_with("raw"),
+                )
+                .arg(
+                    Arg::with_name("headers")
+                        .help("Reads only the headers of messages")
+                        .long_help("Reads only the headers of messages: the bodies are not fetched, and messages are not flagged as seen.")
+                        .long("headers")
+                        .conflicts_with_all(&["raw", "sandbox"]),
+                )
+                .arg(
+                    Arg::with_name("max-size")
+                        .help("Fetches at most the given size of each message")
+                        .long_help("Fetches at most the given size of each message, in bytes, with an optional K, M or G suffix (eg. 100K). The text is rendered from the beginning of the message, with a notice when it is truncated. Messages are not flagged as seen.")
+                        .long("max-size")
+                        .value_name("SIZE")
+                        .conflicts_with_all(&["headers", "sandbox"]),
                 ),
+            SubCommand::with_name("accept")
+                .about("Accepts the invitation of a message")
+                .arg(seq_arg()),
+            SubCommand::with_name("decline")
+                .about("Declines the invitation of a message")
+                .arg(seq_arg()),
+            SubCommand::with_name("tentative")
+                .about("Tentatively accepts the invitation of a message")
+                .arg(seq_arg()),
             SubCommand::with_name("reply")
                 .aliases(&["rep", "r"])
                 .about("Answers to a message")
                 .arg(seq_arg())
                 .arg(reply_all_arg())
-                .arg(attachment_arg()),
+                .arg(quote_match_arg())
+                .arg(attachment_arg())
+                .arg(header_arg())
+                .arg(identity_arg())
+                .arg(
+                    Arg::with_name("suggest")
+                        .help("Starts the reply from a suggested draft")
+                        .long_help("Starts the reply from the draft printed by the `draft-suggest-cmd` of the account, which receives the original message on its standard input.")
+                        .long("suggest"),
+                )
+                .arg(propose_times_arg())
+                .arg(receipt_arg())
+                .arg(
+                    Arg::with_name("list")
+                        .help("Replies to the mailing list instead of the author")
+                        .long_help("Replies to the posting address of the mailing list the message was distributed by, from its `List-Post` header, instead of the author.")
+                        .long("list")
+                        .conflicts_with("reply-all"),
+                ),
             SubCommand::with_name("forward")
                 .aliases(&["fwd", "f"])
                 .about("Forwards a message")
+                .arg(
+                    seq_arg()
+                        .long_help("Specifies the targetted message by its UID, or by its sequence number when `--seq` is given. Several messages, like `3,5,9`, can be forwarded at once with `--as-attachment`.")
+                        .value_name("IDS"),
+                )
+                .arg(attachment_arg())
+                .arg(header_arg())
+                .arg(
+                    Arg::with_name("as-attachment")
+                        .help("Attaches the original messages instead of quoting them")
+                        .long_help("Attaches the original messages as message/rfc822 instead of quoting them, keeping their headers and signatures intact (eg. to report phishing).")
+                        .long("as-attachment"),
+                )
+                .arg(identity_arg())
+                .arg(receipt_arg()),
+            SubCommand::with_name("bounce")
+                .aliases(&["redirect"])
+                .about("Resends a message unchanged to new recipients")
+                .long_about("Resends a message unchanged to new recipients, adding only the Resent-From, Resent-To, Resent-Date and Resent-Message-ID headers. Unlike forwarding, the original sender, subject and body are kept, so that replies go to the original sender.")
                 .arg(seq_arg())
-                .arg(attachment_arg()),
+                .arg(
+                    Arg::with_name("addrs")
+                        .help("Addresses the message is resent to")
+                        .value_name("ADDR")
+                        .multiple(true)
+                        .required(true),
+                ),
             SubCommand::with_name("copy")
                 .aliases(&["cp", "c"])
-                .about("Copies a message to the targetted mailbox")
-                .arg(seq_arg())
+                .about("Copies messages to the targetted mailbox")
+                .arg(seq_range_arg())
                 .arg(mbox_arg::target_arg()),
+            SubCommand::with_name("count")
+                .about("Prints the number of messages of the mailbox")
+                .long_about("Prints the number of messages of the selected mailbox, without listing nor selecting it, for status bars. The JSON output gives both the numbers of messages and of unseen messages. Run from a status bar every few seconds, starting the daemon keeps the session open between runs.")
+                .arg(
+                    Arg::with_name("unseen")
+                        .help("Prints the number of unseen messages")
+                        .long("unseen")
+                        .short("u"),
+                ),
             SubCommand::with_name("move")
                 .aliases(&["mv"])
-                .about("Moves a message to the targetted mailbox")
-                .arg(seq_arg())
+                .about("Moves messages to the targetted mailbox")
+                .arg(seq_range_arg())
                 .arg(mbox_arg::target_arg()),
+            SubCommand::with_name("am")
+                .about("Applies patches with git am")
+                .long_about("Applies the patches of the given messages with `git am`, in the order of their number in the series. Cover letters and messages not looking like patches are skipped.")
+                .arg(seq_range_arg())
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Defines the repository the patches are applied to, the current directory by default")
+                        .long("dir")
+                        .short("d")
+                        .value_name("DIR"),
+                ),
+            SubCommand::with_name("pipe")
+                .aliases(&["print"])
+                .about("Pipes a message into a command")
+                .long_about("Pipes the raw message into the standard input of the given shell command, eg. `lp` or `git am`. The output of the command is left on the terminal.")
+                .arg(seq_arg())
+                .arg(
+                    Arg::with_name("cmd")
+                        .help("Shell command the message is piped into")
+                        .value_name("COMMAND")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("text")
+                        .help("Pipes the decoded text parts instead of the raw message")
+                        .short("t")
+                        .long("text"),
+                ),
+            SubCommand::with_name("archive")
+                .aliases(&["arch", "ar"])
+                .about("Moves messages to the archive folder")
+                .arg(seq_range_arg())
+                .arg(override_hold_arg()),
+            SubCommand::with_name("spam")
+                .about("Reports messages as spam and moves them to the junk folder")
+                .long_about("Reports messages as spam: each message is piped to the spam-learn-cmd of the account (default to `sa-learn --spam`), so that the spam filter learns from it, then moved to the junk folder.")
+                .arg(seq_range_arg())
+                .arg(override_hold_arg()),
+            SubCommand::with_name("ham")
+                .about("Reports messages as legitimate")
+                .long_about("Reports messages as legitimate: each message is piped to the ham-learn-cmd of the account (default to `sa-learn --ham`), so that the spam filter learns from it. Messages of the junk folder are moved back to the inbox.")
+                .arg(seq_range_arg())
+                .arg(override_hold_arg()),
+            SubCommand::with_name("thread")
+                .aliases(&["thr"])
+                .about("Lists messages of the thread the given message belongs to")
+                .arg(seq_arg())
+                .arg(
+                    Arg::with_name("digest")
+                        .help("Compiles the thread into a digest message to forward")
+                        .long_help("Compiles the thread into a single message ready to forward, from the oldest message to the newest. Quoted content already present in the digest is removed.")
+                        .short("d")
+                        .long("digest"),
+                ),
+            SubCommand::with_name("unsubscribe")
+                .about("Unsubscribes from the mailing list of a message")
+                .long_about("Unsubscribes from the mailing list of a message, using its `List-Unsubscribe` header: one-click URLs are posted to, mailto URLs are written to, other URLs are printed to be opened in a browser.")
+                .arg(seq_arg()),
+            SubCommand::with_name("summarize")
+                .aliases(&["sum"])
+                .about("Summarizes a message with the summarize command")
+                .long_about("Summarizes a message by piping its text to the `summarize-cmd` of the account, printing what the command prints")
+                .arg(seq_arg())
+                .arg(
+                    Arg::with_name("thread")
+                        .help("Summarizes the whole thread the message belongs to")
+                        .short("t")
+                        .long("thread"),
+                ),
             SubCommand::with_name("delete")
                 .aliases(&["del", "d", "remove", "rm"])
-                .about("Deletes a message")
-                .arg(seq_arg()),
+                .about("Deletes messages")
+                .long_about("Deletes messages, by moving them to the trash folder when one is configured")
+                .arg(seq_range_arg())
+                .arg(
+                    Arg::with_name("permanent")
+                        .help("Deletes messages permanently, even when a trash folder is configured")
+                        .short("p")
+                        .long("permanent"),
+                )
+                .arg(override_hold_arg()),
+            SubCommand::with_name("diff")
+                .about("Compares two messages")
+                .long_about("Compares the decoded text parts of two messages, and prints the differences as a unified diff, eg. to spot what changed between two versions of a report. The messages are not marked as seen.")
+                .arg(seq_arg())
+                .arg(
+                    Arg::with_name("other-seq")
+                        .help("Specifies the targetted message to compare with")
+                        .value_name("OTHER-SEQ")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("headers")
+                        .help("Compares the decoded headers too")
+                        .long("headers"),
+                ),
+            SubCommand::with_name("hold")
+                .about("Puts messages on hold")
+                .long_about("Puts messages on hold, by adding the hold keyword (`$Hold` by default). Destructive commands refuse to touch messages on hold without --override-hold.")
+                .arg(seq_range_arg())
+                .arg(
+                    Arg::with_name("release")
+                        .help("Releases messages from hold instead")
+                        .short("r")
+                        .long("release"),
+                ),
+            SubCommand::with_name("rewrite")
+                .about("Rewrites the headers of messages")
+                .long_about("Rewrites the headers of messages, eg. to fix broken List-Id or threading headers in archives. Each message is appended again with the rewritten headers, its flags and its internal date, then the original is deleted. Headers are removed before being added, so that a header can be replaced.")
+                .arg(seq_range_arg())
+                .arg(override_hold_arg())
+                .arg(
+                    Arg::with_name("add-header")
+                        .help("Adds a header")
+                        .long("add-header")
+                        .value_name("NAME: VALUE")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("remove-header")
+                        .help("Removes all the headers of the given name")
+                        .long("remove-header")
+                        .value_name("NAME")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .group(
+                    ArgGroup::with_name("headers")
+                        .args(&["add-header", "remove-header"])
+                        .multiple(true)
+                        .required(true),
+                ),
+            SubCommand::with_name("dedup")
+                .about("Deletes duplicate messages")
+                .long_about("Deletes duplicate messages of the selected mailbox, or of the given one: messages sharing the same Message-ID, or the same headers and body. The oldest message is kept.")
+                .arg(mbox_arg::target_arg().required(false))
+                .arg(
+                    Arg::with_name("dry-run")
+                        .help("Lists the duplicate messages instead of deleting them")
+                        .long("dry-run"),
+                )
+                .arg(override_hold_arg()),
+            SubCommand::with_name("export")
+                .about("Exports messages to files")
+                .long_about("Exports messages to files, as one .eml file per message or as a single mbox file. With `--journal`, appends the messages not journaled yet to the dated mbox files of an append-only journal instead.")
+                .arg(seq_range_arg().required(false).required_unless("journal"))
+                .arg(
+                    Arg::with_name("export-format")
+                        .help("Defines the format of the exported files")
+                        .long("format")
+                        .short("f")
+                        .value_name("FMT")
+                        .possible_values(&["eml", "mbox"])
+                        .default_value("eml"),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Defines the directory the files are exported to, the downloads directory by default")
+                        .long("dir")
+                        .short("d")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::with_name("journal")
+                        .help("Appends new messages to the journal of the given directory")
+                        .long_help("Appends the messages of the selected mailbox not journaled yet to the dated mbox files of the given directory, and records their SHA-256 hash in its manifest. Each manifest line is chained to the previous one, so that the journal is verified before appending.")
+                        .long("journal")
+                        .value_name("DIR")
+                        .conflicts_with_all(&["seq-range", "dir"]),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .help("Keeps appending new messages to the journal")
+                        .long_help("Keeps appending new messages to the journal, checking the mailbox every given number of seconds.")
+                        .long("watch")
+                        .value_name("SECS")
+                        .requires("journal"),
+                ),
+            SubCommand::with_name("import")
+                .about("Imports messages from files to the selected mailbox")
+                .long_about("Imports messages from files to the selected mailbox. The path can be a single .eml file, an mbox file, a directory of .eml files or a Maildir, whose flags are kept.")
+                .arg(
+                    Arg::with_name("path")
+                        .help("Defines the file or directory to import")
+                        .value_name("PATH")
+                        .required(true),
+                ),
         ],
     ]
     .concat()
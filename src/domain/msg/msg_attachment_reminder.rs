@@ -0,0 +1,61 @@
+//! Module related to the attachment reminder.
+//!
+//! Before sending, the text of a message without attachments is matched against the
+//! `attachment-reminder-patterns` regexes, so that the user is warned when the body mentions an
+//! attachment that was forgotten. Quoted lines are ignored: a reply should not be blocked by the
+//! attachment of the message it answers.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// Patterns used when the config does not define any, for English, French, German and Spanish.
+pub const DEFAULT_PATTERNS: [&str; 4] = [
+    r"\battach(ed|ing|ments?)\b",
+    r"\b(pi[eè]ces? jointes?|ci-joint(e|s|es)?)\b",
+    r"\b(anhang|anh[aä]nge|angeh[aä]ngt|anbei)\b",
+    r"\badjunt(o|a|os|as)\b",
+];
+
+/// Find the first mention of an attachment in the given text, skipping quoted lines. Patterns
+/// are matched case-insensitively.
+pub fn find_mention(text: &str, patterns: &[String]) -> Result<Option<String>> {
+    let regexes = patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(&format!("(?i){}", pattern)).context(format!(
+                r#"cannot parse attachment reminder pattern "{}""#,
+                pattern
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .find_map(|line| regexes.iter().find_map(|regex| regex.find(line)))
+        .map(|mention| mention.as_str().to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_find_attachment_mentions() {
+        let patterns: Vec<String> = DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            Some(String::from("Attached")),
+            find_mention("Hello,\n\nAttached is the report.", &patterns).unwrap()
+        );
+        assert_eq!(
+            Some(String::from("pièce jointe")),
+            find_mention("Voir la pièce jointe.", &patterns).unwrap()
+        );
+        assert_eq!(
+            None,
+            find_mention("Thanks!\n\n> See the attached report.", &patterns).unwrap()
+        );
+        assert_eq!(None, find_mention("Attached.", &[]).unwrap());
+        assert!(find_mention("Attached.", &[String::from("(")]).is_err());
+    }
+}
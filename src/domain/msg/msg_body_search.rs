@@ -0,0 +1,203 @@
+//! Module related to client-side body search.
+//!
+//! Some servers only search headers, whatever the query says. The body search downloads the
+//! candidate messages and scans their text parts locally. Downloaded messages are kept in the
+//! mailbox cache, so that next searches run offline. Like the rest of the mailbox state, this
+//! cache is dropped as soon as the mailbox UIDVALIDITY changes.
+
+use anyhow::Result;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{Backend, FetchPool},
+        mbox::Mbox,
+        msg::{msg_export, Envelope},
+        storage::{build_storage, mbox_key, Storage},
+    },
+};
+
+/// Represents a body search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodySearch {
+    /// The terms that must all appear in the body, case-insensitively.
+    pub terms: Vec<String>,
+    /// The maximum number of messages fetched at once.
+    pub concurrency: usize,
+    /// Whether messages can be cached, which is only possible when they are identified by UID.
+    pub use_cache: bool,
+}
+
+impl BodySearch {
+    pub fn new(terms: &str, concurrency: usize, use_cache: bool) -> Self {
+        Self {
+            terms: terms.split_whitespace().map(str::to_lowercase).collect(),
+            concurrency: concurrency.max(1),
+            use_cache,
+        }
+    }
+
+    /// Check if the text parts of the given raw message contain all the terms.
+    pub fn matches(&self, raw_msg: &[u8]) -> bool {
+        let text = body_text(raw_msg).to_lowercase();
+        self.terms.iter().all(|term| text.contains(term.as_str()))
+    }
+}
+
+/// Get the decoded text parts of the given raw message, or the raw message itself when it cannot
+/// be parsed.
+fn body_text(raw_msg: &[u8]) -> String {
+    fn collect(part: &mailparse::ParsedMail, text: &mut String) {
+        if part.subparts.is_empty() {
+            if part.ctype.mimetype.starts_with("text/") {
+                if let Ok(body) = part.get_body() {
+                    text.push_str(&body);
+                    text.push('\n');
+                }
+            }
+        } else {
+            for part in part.subparts.iter() {
+                collect(part, text);
+            }
+        }
+    }
+
+    match mailparse::parse_mail(raw_msg) {
+        Ok(parsed_mail) => {
+            let mut text = String::new();
+            collect(&parsed_mail, &mut text);
+            text
+        }
+        Err(_) => String::from_utf8_lossy(raw_msg).to_string(),
+    }
+}
+
+/// Represents the cache of downloaded messages of a mailbox.
+struct MsgCache {
+    storage: Option<Box<dyn Storage>>,
+    mbox: String,
+}
+
+impl MsgCache {
+    fn new(account: &Account, mbox: &Mbox, enabled: bool) -> Self {
+        let storage = if enabled {
+            build_storage(account)
+                .map_err(|err| warn!("{:?}", err))
+                .ok()
+        } else {
+            None
+        };
+        Self {
+            storage,
+            mbox: mbox.name.to_owned(),
+        }
+    }
+
+    fn key(&self, id: u32) -> String {
+        mbox_key(&self.mbox, &format!("msgs/{}.eml", id))
+    }
+
+    fn get(&self, id: u32) -> Option<Vec<u8>> {
+        let storage = self.storage.as_ref()?;
+        storage.get(&self.key(id)).ok().flatten()
+    }
+
+    fn put(&self, id: u32, raw_msg: &[u8]) {
+        if let Some(ref storage) = self.storage {
+            if let Err(err) = storage.set(&self.key(id), raw_msg) {
+                warn!("cannot cache message {}: {:#}", id, err);
+            }
+        }
+    }
+}
+
+/// Keep the envelopes whose message body matches the given search. Messages missing from the
+/// cache are fetched in chunks of `concurrency` messages, split between the fetch jobs of the
+/// account, without flagging them as seen. The
+/// number of scanned messages is passed to the given callback, which can abort the search.
+pub fn search(
+    search: &BodySearch,
+    envelopes: Vec<Envelope>,
+    mbox: &Mbox,
+    account: &Account,
+    backend: &mut dyn Backend,
+    on_progress: &mut dyn FnMut(usize) -> Result<()>,
+) -> Result<Vec<Envelope>> {
+    let cache = MsgCache::new(account, mbox, search.use_cache);
+    // Messages can only be cached when identified by UID.
+    let mut pool = FetchPool::new(account, mbox, !search.use_cache, account.fetch_jobs);
+    let mut matches = HashSet::new();
+    let mut scanned = 0;
+
+    for batch in envelopes.chunks(search.concurrency * pool.jobs()) {
+        let mut raw_msgs: HashMap<u32, Vec<u8>> = HashMap::new();
+        let missing: Vec<u32> = batch
+            .iter()
+            .filter(|envelope| match cache.get(envelope.id) {
+                Some(raw_msg) => {
+                    raw_msgs.insert(envelope.id, raw_msg);
+                    false
+                }
+                None => true,
+            })
+            .map(|envelope| envelope.id)
+            .collect();
+
+        if !missing.is_empty() {
+            debug!("fetch {} message bodies", missing.len());
+            for (id, raw_msg) in pool.peek_raw_msgs(&missing, search.concurrency, backend)? {
+                let raw_msg = msg_export::to_crlf(&raw_msg);
+                cache.put(id, &raw_msg);
+                raw_msgs.insert(id, raw_msg);
+            }
+        }
+
+        for envelope in batch.iter() {
+            let is_match = raw_msgs
+                .get(&envelope.id)
+                .map(|raw_msg| search.matches(raw_msg))
+                .unwrap_or_default();
+            if is_match {
+                matches.insert(envelope.id);
+            }
+        }
+
+        scanned += batch.len();
+        on_progress(scanned)?;
+    }
+
+    Ok(envelopes
+        .into_iter()
+        .filter(|envelope| matches.contains(&envelope.id))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_match_body_terms() {
+        let raw_msg = concat!(
+            "Subject: Quarterly report\r\n",
+            "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n",
+            "\r\n",
+            "The invoice is attached, see the r=C3=A9sum=C3=A9.\r\n",
+            "--b\r\n",
+            "Content-Type: application/pdf\r\n",
+            "\r\n",
+            "secret\r\n",
+            "--b--\r\n",
+        );
+        let search = BodySearch::new("INVOICE résumé", 10, false);
+        assert!(search.matches(raw_msg.as_bytes()));
+        assert!(!BodySearch::new("invoice quarterly", 10, false).matches(raw_msg.as_bytes()));
+        assert!(!BodySearch::new("secret", 10, false).matches(raw_msg.as_bytes()));
+    }
+}
@@ -0,0 +1,160 @@
+//! Module related to message bouncing.
+//!
+//! Bouncing (or redirecting, as [RFC5322] calls it) resends a message unchanged to new
+//! recipients: unlike forwarding, the original sender, subject and body are kept as they are, so
+//! that replies go to the original sender. Only the `Resent-*` header fields are prepended,
+//! telling who resent the message, to whom and when.
+//!
+//! [RFC5322]: https://datatracker.ietf.org/doc/html/rfc5322#section-3.6.6
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use log::debug;
+use uuid::Uuid;
+
+use crate::{config::Account, domain::backend::Sender};
+
+type Addr = lettre::message::Mailbox;
+
+/// Prepend the resent header fields to the given raw message.
+fn resent(raw_msg: &[u8], from: &str, to: &[Addr], date: &str, domain: &str) -> Vec<u8> {
+    let to: Vec<String> = to.iter().map(Addr::to_string).collect();
+    let mut resent_msg = format!(
+        "Resent-From: {}\r\nResent-To: {}\r\nResent-Date: {}\r\nResent-Message-ID: <{}@{}>\r\n",
+        from,
+        to.join(", "),
+        date,
+        Uuid::new_v4(),
+        domain
+    )
+    .into_bytes();
+    resent_msg.extend_from_slice(raw_msg);
+    resent_msg
+}
+
+/// Resend the given raw message to the given addresses, from the account address.
+pub fn bounce(
+    raw_msg: &[u8],
+    addrs: &[&str],
+    account: &Account,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let to = addrs
+        .iter()
+        .map(|addr| {
+            addr.parse::<Addr>()
+                .context(format!(r#"cannot parse address "{}""#, addr))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if to.is_empty() {
+        return Err(anyhow!("cannot bounce message: no recipient given"));
+    }
+    debug!("bounce message to {:?}", addrs);
+
+    let domain = account
+        .email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or("localhost");
+    let raw_msg = resent(
+        raw_msg,
+        &account.address(),
+        &to,
+        &Local::now().to_rfc2822(),
+        domain,
+    );
+    let envelope = lettre::address::Envelope::new(
+        Some(
+            account
+                .email
+                .parse()
+                .context("cannot parse account address")?,
+        ),
+        to.into_iter().map(|addr| addr.email).collect(),
+    )
+    .context("cannot create bounce envelope")?;
+    sender
+        .send_raw(&envelope, &raw_msg)
+        .context("cannot bounce message")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::msg::Msg;
+
+    /// Represents a sender recording the envelope of the raw messages it sends.
+    #[derive(Default)]
+    struct SenderStub {
+        envelopes: Vec<lettre::address::Envelope>,
+    }
+
+    impl Sender for SenderStub {
+        fn send(&mut self, _msg: &Msg) -> Result<Vec<u8>> {
+            Err(anyhow!("cannot send message with the stub"))
+        }
+
+        fn send_raw(&mut self, envelope: &lettre::address::Envelope, _msg: &[u8]) -> Result<()> {
+            self.envelopes.push(envelope.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_should_send_to_bounce_recipients() {
+        let raw_msg =
+            b"From: alice@localhost\r\nTo: me@localhost\r\nCc: dave@localhost\r\n\r\nHi!\r\n";
+        let account = Account {
+            email: String::from("me@localhost"),
+            ..Account::default()
+        };
+        let mut sender = SenderStub::default();
+        bounce(
+            raw_msg,
+            &["bob@localhost", "Carol <carol@localhost>"],
+            &account,
+            &mut sender,
+        )
+        .unwrap();
+
+        assert_eq!(1, sender.envelopes.len());
+        let envelope = &sender.envelopes[0];
+        assert_eq!(
+            Some(String::from("me@localhost")),
+            envelope.from().map(ToString::to_string)
+        );
+        assert_eq!(
+            vec!["bob@localhost", "carol@localhost"],
+            envelope
+                .to()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_should_prepend_resent_headers() {
+        let raw_msg = b"From: alice@localhost\r\nSubject: Hello\r\n\r\nHi!\r\n";
+        let to = vec![
+            "bob@localhost".parse().unwrap(),
+            "Carol <carol@localhost>".parse().unwrap(),
+        ];
+        let resent_msg = resent(
+            raw_msg,
+            "Me <me@localhost>",
+            &to,
+            "Tue, 1 Jul 2003 10:52:37 +0200",
+            "localhost",
+        );
+        let resent_msg = String::from_utf8(resent_msg).unwrap();
+        assert!(resent_msg.starts_with(concat!(
+            "Resent-From: Me <me@localhost>\r\n",
+            "Resent-To: bob@localhost, Carol <carol@localhost>\r\n",
+            "Resent-Date: Tue, 1 Jul 2003 10:52:37 +0200\r\n",
+            "Resent-Message-ID: <",
+        )));
+        assert!(resent_msg
+            .ends_with("@localhost>\r\nFrom: alice@localhost\r\nSubject: Hello\r\n\r\nHi!\r\n"));
+    }
+}
@@ -0,0 +1,364 @@
+//! Module related to message compliance.
+//!
+//! This module makes raw messages comply with the [RFC5322] line length limit before they are sent
+//! or appended, so that servers neither reject nor mangle them. Messages built by the client are
+//! already compliant, since their parts are encoded when they are built.
+//!
+//! It also chooses the transfer encoding of the parts of the messages built by the client, so
+//! that non-ASCII text and binary content always arrive intact.
+//!
+//! [RFC5322]: https://datatracker.ietf.org/doc/html/rfc5322#section-2.1.1
+
+use anyhow::{anyhow, Context, Result};
+use lettre::message::{header::ContentTransferEncoding, Body};
+use log::{debug, trace};
+use mailparse::MailHeaderMap;
+
+/// Maximum length of a line, excluding the line ending.
+pub const MAX_LINE_LEN: usize = 998;
+
+/// Maximum length of an encoded line, as required by
+/// [RFC2045](https://datatracker.ietf.org/doc/html/rfc2045#section-6.7).
+const ENCODED_LINE_LEN: usize = 76;
+
+/// Make the given raw message comply with the line length limit: long header lines are folded
+/// and parts containing long lines are re-encoded, using quoted-printable for text parts and
+/// base64 for the other ones. Compliant messages are returned untouched.
+pub fn fix_long_lines(raw_msg: &[u8]) -> Result<Vec<u8>> {
+    if !has_long_lines(raw_msg) {
+        return Ok(raw_msg.to_vec());
+    }
+
+    let eol: &[u8] = if raw_msg.windows(2).any(|w| w == b"\r\n") {
+        b"\r\n"
+    } else {
+        b"\n"
+    };
+    fix_part(raw_msg, eol)
+}
+
+/// Choose the transfer encoding of a part, based on its content and on the ability of the relay
+/// to transport 8-bit data ([8BITMIME](https://datatracker.ietf.org/doc/html/rfc6152)):
+///
+/// - 7bit for ASCII text with short lines
+/// - 8bit for UTF-8 text with short lines, if the relay supports it
+/// - quoted-printable for mostly ASCII text
+/// - base64 for everything else
+pub fn choose_encoding(content: &[u8], is_text: bool, allow_8bit: bool) -> ContentTransferEncoding {
+    let is_utf8 = std::str::from_utf8(content).is_ok();
+    let has_nul_or_bare_cr = content
+        .iter()
+        .enumerate()
+        .any(|(i, b)| *b == b'\0' || (*b == b'\r' && content.get(i + 1) != Some(&b'\n')));
+
+    if !is_text || !is_utf8 || has_nul_or_bare_cr {
+        return ContentTransferEncoding::Base64;
+    }
+
+    // Unencoded lines are kept as short as encoded ones.
+    let has_long_lines = lines(content).any(|line| trim_eol(line).len() > ENCODED_LINE_LEN);
+    if !has_long_lines && content.is_ascii() {
+        return ContentTransferEncoding::SevenBit;
+    }
+    if !has_long_lines && allow_8bit {
+        return ContentTransferEncoding::EightBit;
+    }
+
+    // Quoted-printable triples the size of non-ASCII bytes, base64 adds a third to everything.
+    let non_ascii_len = content.iter().filter(|b| !b.is_ascii()).count();
+    if non_ascii_len * 3 <= content.len() {
+        ContentTransferEncoding::QuotedPrintable
+    } else {
+        ContentTransferEncoding::Base64
+    }
+}
+
+/// Build a part body using the encoding chosen by `choose_encoding`.
+pub fn encode_body(content: Vec<u8>, is_text: bool, allow_8bit: bool) -> Body {
+    let encoding = choose_encoding(&content, is_text, allow_8bit);
+    trace!("transfer encoding: {:?}", encoding);
+    // The chosen encoding always suits the content, but fall back on the automatic choice anyway.
+    Body::new_with_encoding(content, encoding).unwrap_or_else(Body::new)
+}
+
+/// Build the body of a message/rfc822 part. Such parts cannot be encoded, as required by
+/// [RFC2046](https://datatracker.ietf.org/doc/html/rfc2046#section-5.2.1), so the message is
+/// kept as is.
+pub fn encode_msg_body(content: Vec<u8>) -> Body {
+    Body::new_with_encoding(content, ContentTransferEncoding::SevenBit)
+        .or_else(|content| Body::new_with_encoding(content, ContentTransferEncoding::EightBit))
+        .or_else(|content| Body::new_with_encoding(content, ContentTransferEncoding::Binary))
+        .unwrap_or_else(Body::new)
+}
+
+fn lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes.split_inclusive(|b| *b == b'\n')
+}
+
+fn trim_eol(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn has_long_lines(bytes: &[u8]) -> bool {
+    lines(bytes).any(|line| trim_eol(line).len() > MAX_LINE_LEN)
+}
+
+fn fix_part(raw_part: &[u8], eol: &[u8]) -> Result<Vec<u8>> {
+    if !has_long_lines(raw_part) {
+        return Ok(raw_part.to_vec());
+    }
+
+    let (headers, offset) =
+        mailparse::parse_headers(raw_part).context("cannot parse message headers")?;
+    let (head, body) = raw_part.split_at(offset);
+    let ctype =
+        mailparse::parse_content_type(&headers.get_first_value("Content-Type").unwrap_or_default());
+
+    if ctype.mimetype.starts_with("multipart/") {
+        if let Some(boundary) = ctype.params.get("boundary") {
+            let mut part = fix_head(head, None, eol)?;
+            part.extend(fix_multipart_body(body, boundary, eol)?);
+            return Ok(part);
+        }
+    }
+
+    let encoding = headers
+        .get_first_value("Content-Transfer-Encoding")
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    let reencodable = matches!(encoding.as_str(), "" | "7bit" | "8bit" | "binary");
+
+    if !reencodable || !has_long_lines(body) {
+        let mut part = fix_head(head, None, eol)?;
+        part.extend(body);
+        return Ok(part);
+    }
+
+    let (encoding, body) = if ctype.mimetype.starts_with("text/") {
+        ("quoted-printable", encode_qp(body, eol))
+    } else {
+        ("base64", encode_base64(body, eol))
+    };
+    debug!("re-encode {} part using {}", ctype.mimetype, encoding);
+
+    let mut part = fix_head(head, Some(encoding), eol)?;
+    part.extend(body);
+    Ok(part)
+}
+
+/// Fold long header lines and replace the transfer encoding if a new one is given.
+fn fix_head(head: &[u8], encoding: Option<&str>, eol: &[u8]) -> Result<Vec<u8>> {
+    let mut fields: Vec<Vec<u8>> = vec![];
+    for line in lines(head) {
+        if trim_eol(line).is_empty() {
+            break;
+        }
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            if let Some(field) = fields.last_mut() {
+                field.extend(line);
+                continue;
+            }
+        }
+        fields.push(line.to_vec());
+    }
+
+    let mut fixed_head = vec![];
+    for field in fields {
+        let is_encoding_field = field
+            .splitn(2, |b| *b == b':')
+            .next()
+            .map(|key| String::from_utf8_lossy(key).trim().to_lowercase())
+            .map(|key| key == "content-transfer-encoding")
+            .unwrap_or_default();
+        if encoding.is_some() && is_encoding_field {
+            continue;
+        }
+        for line in lines(&field) {
+            fixed_head.extend(fold_line(line, eol)?);
+        }
+    }
+
+    if let Some(encoding) = encoding {
+        fixed_head.extend(format!("Content-Transfer-Encoding: {}", encoding).as_bytes());
+        fixed_head.extend(eol);
+    }
+    fixed_head.extend(eol);
+    Ok(fixed_head)
+}
+
+/// Fold a header line on whitespaces so that no line exceeds the limit.
+fn fold_line(line: &[u8], eol: &[u8]) -> Result<Vec<u8>> {
+    let content = trim_eol(line);
+    let mut folded = vec![];
+    let mut begin = 0;
+
+    while content.len() - begin > MAX_LINE_LEN {
+        let pos = content[begin..=begin + MAX_LINE_LEN]
+            .iter()
+            .rposition(|b| *b == b' ' || *b == b'\t')
+            .filter(|pos| *pos > 0)
+            .ok_or_else(|| {
+                anyhow!(
+                    "cannot fold header line longer than {} characters",
+                    MAX_LINE_LEN
+                )
+            })?;
+        folded.extend(&content[begin..begin + pos]);
+        folded.extend(eol);
+        // The whitespace starts the continuation line.
+        begin += pos;
+    }
+
+    folded.extend(&content[begin..]);
+    if line.ends_with(b"\n") {
+        folded.extend(eol);
+    }
+    Ok(folded)
+}
+
+fn fix_multipart_body(body: &[u8], boundary: &str, eol: &[u8]) -> Result<Vec<u8>> {
+    let delim = format!("--{}", boundary);
+    let close_delim = format!("--{}--", boundary);
+    let mut fixed_body = vec![];
+    let mut part: Option<Vec<u8>> = None;
+    let mut closed = false;
+
+    for line in lines(body) {
+        let mut trimmed_line = trim_eol(line);
+        while let Some((&b' ', rest)) | Some((&b'\t', rest)) = trimmed_line.split_last() {
+            trimmed_line = rest;
+        }
+        let is_delim = trimmed_line == delim.as_bytes();
+        let is_close_delim = trimmed_line == close_delim.as_bytes();
+
+        if !closed && (is_delim || is_close_delim) {
+            if let Some(part) = part.take() {
+                fixed_body.extend(fix_part(&part, eol)?);
+            }
+            fixed_body.extend(line);
+            if is_close_delim {
+                closed = true;
+            } else {
+                part = Some(vec![]);
+            }
+        } else if let Some(ref mut part) = part {
+            part.extend(line);
+        } else {
+            // Preamble and epilogue are kept as they are.
+            fixed_body.extend(line);
+        }
+    }
+
+    if let Some(part) = part {
+        fixed_body.extend(fix_part(&part, eol)?);
+    }
+
+    Ok(fixed_body)
+}
+
+/// Encode a body using quoted-printable, line by line so that line breaks are preserved.
+fn encode_qp(body: &[u8], eol: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+
+    for line in lines(body) {
+        let content = trim_eol(line);
+        let mut len = 0;
+        for (i, byte) in content.iter().enumerate() {
+            let is_last = i + 1 == content.len();
+            let chunk = match *byte {
+                b' ' | b'\t' if !is_last => vec![*byte],
+                b'!'..=b'<' | b'>'..=b'~' => vec![*byte],
+                _ => format!("={:02X}", byte).into_bytes(),
+            };
+            // Keep room for the soft line break.
+            if len + chunk.len() > ENCODED_LINE_LEN - 1 {
+                encoded.push(b'=');
+                encoded.extend(eol);
+                len = 0;
+            }
+            len += chunk.len();
+            encoded.extend(chunk);
+        }
+        if line.ends_with(b"\n") {
+            encoded.extend(eol);
+        }
+    }
+
+    encoded
+}
+
+/// Encode a body using base64. A trailing line ending belongs to the next boundary delimiter,
+/// so it is kept out of the encoded content.
+fn encode_base64(body: &[u8], eol: &[u8]) -> Vec<u8> {
+    let content = body.strip_suffix(eol).unwrap_or(body);
+    let mut encoded = vec![];
+    for chunk in base64::encode(content).as_bytes().chunks(ENCODED_LINE_LEN) {
+        encoded.extend(chunk);
+        encoded.extend(eol);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_keep_compliant_msgs_untouched() {
+        let msg = b"Subject: hello\r\n\r\nHello, world!\r\n";
+        assert_eq!(msg.to_vec(), fix_long_lines(msg).unwrap());
+    }
+
+    #[test]
+    fn it_should_reencode_long_lines() {
+        let body = "a ".repeat(600);
+        let msg = format!(
+            "Subject: hello\r\nContent-Transfer-Encoding: 7bit\r\n\r\n{}\r\n",
+            body
+        );
+        let fixed = fix_long_lines(msg.as_bytes()).unwrap();
+
+        assert!(!has_long_lines(&fixed));
+        let parsed = mailparse::parse_mail(&fixed).unwrap();
+        assert_eq!(
+            Some(String::from("quoted-printable")),
+            parsed.headers.get_first_value("Content-Transfer-Encoding")
+        );
+        assert_eq!(format!("{}\r\n", body), parsed.get_body().unwrap());
+    }
+
+    #[test]
+    fn it_should_choose_encoding() {
+        use ContentTransferEncoding::*;
+
+        assert_eq!(SevenBit, choose_encoding(b"Hello, world!\n", true, false));
+        assert_eq!(SevenBit, choose_encoding(b"Hello, world!\n", true, true));
+        assert_eq!(
+            EightBit,
+            choose_encoding("Grüß Gott!\n".as_bytes(), true, true)
+        );
+        assert_eq!(
+            QuotedPrintable,
+            choose_encoding("Grüß Gott!\n".as_bytes(), true, false)
+        );
+        assert_eq!(
+            Base64,
+            choose_encoding("こんにちは\n".as_bytes(), true, false)
+        );
+        assert_eq!(Base64, choose_encoding(b"Hello, world!\n", false, true));
+        assert_eq!(Base64, choose_encoding(&[0xff, 0xfe, 0x00], true, true));
+    }
+
+    #[test]
+    fn it_should_fold_long_headers() {
+        let to = vec!["someone@localhost"; 100].join(", ");
+        let msg = format!("To: {}\r\n\r\nHello, world!\r\n", to);
+        let fixed = fix_long_lines(msg.as_bytes()).unwrap();
+
+        assert!(!has_long_lines(&fixed));
+        let parsed = mailparse::parse_mail(&fixed).unwrap();
+        assert_eq!(Some(to), parsed.headers.get_first_value("To"));
+    }
+}
@@ -0,0 +1,124 @@
+//! Module related to CRM logging.
+//!
+//! Messages sent to the domains of a CRM rule get a blind copy to the address of the rule, like
+//! the dropbox address of a CRM or of a ticketing system, and a marker header, so that they are
+//! logged without the user thinking about it.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+
+use crate::{config::CrmRule, domain::msg::Msg};
+
+/// Find the first domain of the rule matching one of the given recipients.
+fn matching_domain<'a>(rule: &'a CrmRule, recipients: &[String]) -> Option<&'a str> {
+    rule.domains
+        .iter()
+        .map(|domain| domain.trim().trim_start_matches('@'))
+        .find(|domain| {
+            recipients.iter().any(|addr| {
+                addr.rsplit_once('@')
+                    .map(|(_, addr_domain)| addr_domain.eq_ignore_ascii_case(domain))
+                    .unwrap_or_default()
+            })
+        })
+}
+
+/// Apply the given rules to the message, telling if it changed. Blind copies and headers
+/// already present are not added twice.
+pub fn apply(msg: &Msg, rules: &[CrmRule]) -> Result<Option<Msg>> {
+    let recipients: Vec<String> = msg
+        .to
+        .iter()
+        .chain(msg.cc.iter())
+        .chain(msg.bcc.iter())
+        .flatten()
+        .map(|addr| addr.email.to_string())
+        .collect();
+    let mut crm_msg = Msg {
+        subject: msg.subject.to_owned(),
+        from: msg.from.to_owned(),
+        reply_to: msg.reply_to.to_owned(),
+        to: msg.to.to_owned(),
+        cc: msg.cc.to_owned(),
+        bcc: msg.bcc.to_owned(),
+        in_reply_to: msg.in_reply_to.to_owned(),
+        message_id: msg.message_id.to_owned(),
+        headers: msg.headers.to_owned(),
+        parts: msg.parts.clone(),
+        ..Msg::default()
+    };
+    let mut changed = false;
+
+    for rule in rules {
+        let domain = match matching_domain(rule, &recipients) {
+            Some(domain) => domain,
+            None => continue,
+        };
+        debug!("CRM rule of domain {} matched", domain);
+
+        if let Some(ref bcc) = rule.bcc {
+            let bcc = bcc
+                .parse()
+                .context(format!(r#"cannot parse CRM address "{}""#, bcc))?;
+            let bccs = crm_msg.bcc.get_or_insert_with(Vec::new);
+            if !bccs.contains(&bcc) && !recipients.contains(&bcc.email.to_string()) {
+                bccs.push(bcc);
+                changed = true;
+            }
+        }
+
+        if let Some(ref header) = rule.header {
+            let (key, val) = header
+                .split_once(':')
+                .ok_or_else(|| anyhow!(r#"cannot parse CRM header "{}""#, header))?;
+            let key = key.trim().to_owned();
+            let val = val.trim().replace("{domain}", domain);
+            if !crm_msg
+                .headers
+                .iter()
+                .any(|(k, _)| k.eq_ignore_ascii_case(&key))
+            {
+                crm_msg.headers.push((key, val));
+                changed = true;
+            }
+        }
+    }
+
+    Ok(if changed { Some(crm_msg) } else { None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_apply_crm_rules() {
+        let rules = vec![CrmRule {
+            domains: vec![String::from("@Client.com")],
+            bcc: Some(String::from("dropbox@crm.example")),
+            header: Some(String::from("X-CRM-Ref: {domain}")),
+        }];
+        let msg = Msg {
+            to: Some(vec!["alice@client.com".parse().unwrap()]),
+            ..Msg::default()
+        };
+
+        let crm_msg = apply(&msg, &rules).unwrap().unwrap();
+        assert_eq!(
+            Some(vec!["dropbox@crm.example".parse().unwrap()]),
+            crm_msg.bcc
+        );
+        assert_eq!(
+            vec![(String::from("X-CRM-Ref"), String::from("client.com"))],
+            crm_msg.headers
+        );
+        // Rules are only applied once.
+        assert!(apply(&crm_msg, &rules).unwrap().is_none());
+
+        let msg = Msg {
+            to: Some(vec!["bob@partner.com".parse().unwrap()]),
+            ..Msg::default()
+        };
+        assert!(apply(&msg, &rules).unwrap().is_none());
+    }
+}
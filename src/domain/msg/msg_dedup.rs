@@ -0,0 +1,117 @@
+//! Module related to message deduplication.
+//!
+//! Two messages are duplicates when they share the same Message-ID, or when their headers and
+//! bodies are identical, which catches duplicates without Message-ID. The oldest message, the one
+//! with the lowest identifier, is the one kept.
+//!
+//! Messages are compared by fingerprint, computed from their raw content as they are fetched, so
+//! that large mailboxes are deduplicated without keeping their bodies in memory.
+
+use anyhow::{Context, Result};
+use mailparse::MailHeaderMap;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// Represents the fingerprint of a message: its Message-ID and the hash of its content.
+#[derive(Debug, PartialEq)]
+pub struct Fingerprint {
+    pub id: u32,
+    message_id: Option<String>,
+    hash: u64,
+    subject: String,
+}
+
+impl Fingerprint {
+    /// Compute the fingerprint of the given raw message. The hash covers the headers identifying
+    /// the message and its body, leaving out the transport headers (eg. `Received`), that differ
+    /// between two deliveries of the same message.
+    pub fn from_raw(id: u32, raw_msg: &[u8]) -> Result<Self> {
+        let (headers, body_offset) = mailparse::parse_headers(raw_msg)
+            .context(format!("cannot parse headers of message {}", id))?;
+
+        let mut hasher = DefaultHasher::new();
+        for key in ["Subject", "From", "To", "Cc", "Date"].iter() {
+            headers.get_first_value(key).hash(&mut hasher);
+        }
+        raw_msg[body_offset..].hash(&mut hasher);
+
+        Ok(Self {
+            id,
+            message_id: headers
+                .get_first_value("Message-ID")
+                .map(|id| id.trim().to_owned())
+                .filter(|id| !id.is_empty()),
+            hash: hasher.finish(),
+            subject: headers.get_first_value("Subject").unwrap_or_default(),
+        })
+    }
+}
+
+/// Represents a duplicate message, with the identifier of the message it duplicates.
+#[derive(Debug, PartialEq)]
+pub struct Duplicate {
+    pub id: u32,
+    pub original_id: u32,
+    pub subject: String,
+}
+
+/// Find the duplicate messages among the given fingerprints.
+pub fn find_duplicates(mut fingerprints: Vec<Fingerprint>) -> Vec<Duplicate> {
+    fingerprints.sort_by_key(|fingerprint| fingerprint.id);
+
+    let mut message_ids: HashMap<String, u32> = HashMap::new();
+    let mut hashes: HashMap<u64, u32> = HashMap::new();
+    let mut duplicates = vec![];
+
+    for fingerprint in fingerprints {
+        let original_id = fingerprint
+            .message_id
+            .as_ref()
+            .and_then(|message_id| message_ids.get(message_id))
+            .or_else(|| hashes.get(&fingerprint.hash))
+            .cloned();
+        match original_id {
+            Some(original_id) => duplicates.push(Duplicate {
+                id: fingerprint.id,
+                original_id,
+                subject: fingerprint.subject,
+            }),
+            None => {
+                if let Some(message_id) = fingerprint.message_id {
+                    message_ids.insert(message_id, fingerprint.id);
+                }
+                hashes.insert(fingerprint.hash, fingerprint.id);
+            }
+        }
+    }
+
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_find_duplicates() {
+        let msg = |id: u32, headers: &str| {
+            let raw_msg = format!("{}\r\n\r\nHello, world!\r\n", headers);
+            Fingerprint::from_raw(id, raw_msg.as_bytes()).unwrap()
+        };
+        let fingerprints = vec![
+            msg(3, "Message-ID: <1@localhost>\r\nSubject: Hello again"),
+            msg(1, "Message-ID: <1@localhost>\r\nSubject: Hello"),
+            msg(2, "Received: from a\r\nSubject: Report"),
+            msg(4, "Received: from b\r\nSubject: Report"),
+            msg(5, "Message-ID: <5@localhost>\r\nSubject: Bye"),
+        ];
+
+        let duplicates = find_duplicates(fingerprints);
+        assert_eq!(2, duplicates.len());
+        assert_eq!((3, 1), (duplicates[0].id, duplicates[0].original_id));
+        assert_eq!((4, 2), (duplicates[1].id, duplicates[1].original_id));
+        assert_eq!("Report", duplicates[1].subject);
+    }
+}
@@ -0,0 +1,199 @@
+//! Module related to message diffs.
+//!
+//! Messages are compared line by line, with the Myers algorithm, then printed as a unified diff
+//! with 3 lines of context.
+
+use mailparse::ParsedMail;
+
+/// Define the number of unchanged lines printed around the changes.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Find the shortest edit script turning the given old lines into the given new lines.
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let idx = |k: isize| (k + max + 1) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let mut trace = vec![];
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[idx(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == prev_x {
+                Edit::Insert
+            } else {
+                Edit::Delete
+            });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    edits
+}
+
+fn hunk_range(start: usize, count: usize) -> String {
+    // An empty range starts at the line before it.
+    let start = if count == 0 { start } else { start + 1 };
+    match count {
+        1 => start.to_string(),
+        count => format!("{},{}", start, count),
+    }
+}
+
+/// Build the unified diff between the given texts. Returns an empty string when they are
+/// identical.
+pub fn diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edits(&old, &new);
+
+    // Positions of each edit in the old and new lines.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in edits.iter() {
+        positions.push((i, j));
+        match edit {
+            Edit::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    // Group the changes closer than twice the context into hunks.
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|e| edits[*e] != Edit::Equal)
+        .collect();
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for e in changes {
+        match hunks.last_mut() {
+            Some((_, end)) if e <= *end + 2 * CONTEXT => *end = e,
+            _ => hunks.push((e, e)),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT);
+        let end = (last + CONTEXT + 1).min(edits.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        for e in start..end {
+            let (i, j) = positions[e];
+            let line = match edits[e] {
+                Edit::Equal => format!(" {}", old[i]),
+                Edit::Delete => format!("-{}", old[i]),
+                Edit::Insert => format!("+{}", new[j]),
+            };
+            diff.push_str(&line);
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+/// Get the text of the given message to compare: its decoded text parts, after its decoded
+/// headers when asked.
+pub fn text(parsed: &ParsedMail, body: &str, headers: bool) -> String {
+    let mut text = String::new();
+    if headers {
+        for header in parsed.headers.iter() {
+            text.push_str(&format!("{}: {}\n", header.get_key(), header.get_value()));
+        }
+        text.push('\n');
+    }
+    text.push_str(body);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_diff_texts() {
+        let old = "Hello,\n\nThe total is 42.\nRegards\n";
+        let new = "Hello,\n\nThe total is 43.\nRegards\nJohn\n";
+        assert_eq!(
+            concat!(
+                "--- message 1\n",
+                "+++ message 2\n",
+                "@@ -1,4 +1,5 @@\n",
+                " Hello,\n",
+                " \n",
+                "-The total is 42.\n",
+                "+The total is 43.\n",
+                " Regards\n",
+                "+John\n",
+            ),
+            diff(old, new, "message 1", "message 2")
+        );
+        assert_eq!("", diff(old, old, "message 1", "message 1"));
+
+        let old: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let new: String = (1..=20)
+            .map(|n| match n {
+                2 => String::from("two\n"),
+                19 => String::from("nineteen\n"),
+                n => format!("{}\n", n),
+            })
+            .collect();
+        let diff = diff(&old, &new, "a", "b");
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n"));
+        assert!(diff.contains("@@ -16,5 +16,5 @@\n"));
+    }
+}
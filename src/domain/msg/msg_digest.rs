@@ -0,0 +1,130 @@
+//! Module related to thread digests.
+//!
+//! A digest compiles a whole conversation into a single text, ready to forward: messages are
+//! listed from the oldest to the newest, and the quoted blocks repeating content already present
+//! in the digest are dropped along with their attribution line (`On …, John wrote:`). Quotes of
+//! messages outside of the thread are kept.
+
+use std::collections::HashSet;
+
+use crate::domain::msg::Msg;
+
+/// Normalize a line for comparison, without quote markers nor surrounding spaces.
+fn normalize(line: &str) -> &str {
+    line.trim_start_matches(|c: char| c == '>' || c.is_whitespace())
+        .trim_end()
+}
+
+fn is_quoted(line: &str) -> bool {
+    line.trim_start().starts_with('>')
+}
+
+/// Drop the quoted blocks of the given text whose lines were all seen before, then record the
+/// lines of the remaining text as seen.
+pub fn strip_seen_quotes(text: &str, seen: &mut HashSet<String>) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut kept: Vec<&str> = vec![];
+    let mut i = 0;
+
+    while i < lines.len() {
+        if !is_quoted(lines[i]) {
+            kept.push(lines[i]);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < lines.len() && is_quoted(lines[i]) {
+            i += 1;
+        }
+        let block = &lines[start..i];
+        let already_seen = block
+            .iter()
+            .map(|line| normalize(line))
+            .filter(|line| !line.is_empty())
+            .all(|line| seen.contains(line));
+        if !already_seen {
+            kept.extend_from_slice(block);
+            continue;
+        }
+
+        // Drop the attribution line of the block, and the blank lines around it.
+        while kept.last().map(|line| line.trim().is_empty()) == Some(true) {
+            kept.pop();
+        }
+        if kept.last().map(|line| line.trim_end().ends_with(':')) == Some(true) {
+            kept.pop();
+        }
+        while kept.last().map(|line| line.trim().is_empty()) == Some(true) {
+            kept.pop();
+        }
+    }
+
+    for line in kept.iter().map(|line| normalize(line)) {
+        if !line.is_empty() {
+            seen.insert(line.to_owned());
+        }
+    }
+    kept.join("\n").trim_end().to_owned()
+}
+
+/// Compile the given messages into a digest. Messages are sorted by date.
+pub fn digest(msgs: &mut [Msg]) -> String {
+    msgs.sort_by_key(|msg| msg.date);
+    let subject = msgs
+        .first()
+        .map(|msg| msg.subject.as_str())
+        .unwrap_or_default();
+
+    let mut seen = HashSet::new();
+    let mut digest = String::new();
+    digest.push_str(&format!(
+        "-------- Thread Digest ({} messages) --------\n",
+        msgs.len()
+    ));
+    digest.push_str(&format!("Subject: {}\n", subject));
+
+    for (i, msg) in msgs.iter().enumerate() {
+        digest.push_str(&format!(
+            "\n-------- Message {}/{} --------\n",
+            i + 1,
+            msgs.len()
+        ));
+        if let Some(date) = msg.date {
+            digest.push_str(&format!("Date: {}\n", date.to_rfc2822()));
+        }
+        if let Some(addrs) = msg.from.as_ref() {
+            let addrs: Vec<String> = addrs.iter().map(ToString::to_string).collect();
+            digest.push_str(&format!("From: {}\n", addrs.join(", ")));
+        }
+        digest.push('\n');
+        digest.push_str(&strip_seen_quotes(&msg.join_text_plain_parts(), &mut seen));
+        digest.push('\n');
+    }
+
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_strip_seen_quotes() {
+        let mut seen = HashSet::new();
+        let first = strip_seen_quotes(
+            "Shall we meet on Monday?\n\n> Agenda:\n> - budget",
+            &mut seen,
+        );
+        assert_eq!("Shall we meet on Monday?\n\n> Agenda:\n> - budget", first);
+
+        let reply = "Monday works.\n\nOn Mon, John wrote:\n> Shall we meet on Monday?\n>\n>> Agenda:\n\nSee you.";
+        assert_eq!(
+            "Monday works.\n\nSee you.",
+            strip_seen_quotes(reply, &mut seen)
+        );
+
+        let reply = "Sure.\n\n> Monday works, but not at noon.";
+        assert_eq!(reply, strip_seen_quotes(reply, &mut seen));
+    }
+}
@@ -1,9 +1,13 @@
 use ammonia;
 use anyhow::{anyhow, Context, Error, Result};
-use chrono::{DateTime, FixedOffset};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset,
+};
 use htmlescape;
 use imap::types::Flag;
-use lettre::message::{Attachment, MultiPart, SinglePart};
+use lettre::message::{header::ContentType, Attachment, MultiPart, SinglePart};
+use mailparse::MailHeaderMap;
 use regex::Regex;
 use rfc2047_decoder;
 use serde::Serialize;
@@ -16,14 +20,23 @@ use std::{
 use crate::{
     config::Account,
     domain::{
-        imap::ImapServiceInterface,
-        mbox::Mbox,
-        msg::{msg_utils, Flags, Parts, TextHtmlPart, TextPlainPart, Tpl, TplOverride},
-        smtp::SmtpServiceInterface,
+        backend::{Backend, Sender},
+        mbox::{warn_near_quota, Mbox},
+        msg::{
+            msg_addr, msg_attachment_reminder, msg_compliance, msg_flowed, msg_html,
+            msg_mailing_list::MailingList,
+            msg_mdn,
+            msg_rewrite::Header,
+            msg_safety::{self, SendWarning},
+            msg_sig, msg_spellcheck, msg_split, msg_utils,
+            tpl_engine::{self, TplContext},
+            Flags, Parts, TextHtmlPart, TextPlainPart, Tpl, TplOverride, ATTACHMENT_HEADER,
+        },
+        sent::SentLog,
     },
-    output::OutputServiceInterface,
+    output::{output_tpl, OutputServiceInterface, TplFields, TplValue},
     ui::{
-        choice::{self, PostEditChoice, PreEditChoice},
+        choice::{self, FixAddrsChoice, PostEditChoice, PreEditChoice, SendAnywayChoice},
         editor,
     },
 };
@@ -35,9 +48,10 @@ type Addr = lettre::message::Mailbox;
 /// Representation of a message.
 #[derive(Debug, Default)]
 pub struct Msg {
-    /// The sequence number of the message.
+    /// The [UID] of the message, or its [sequence number] when UIDs were not fetched.
     ///
-    /// [RFC3501]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.2
+    /// [UID]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.1
+    /// [sequence number]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.2
     pub id: u32,
 
     /// The flags attached to the message.
@@ -58,9 +72,36 @@ pub struct Msg {
     ///
     /// [RFC3501]: https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.3
     pub date: Option<DateTime<FixedOffset>>,
+
+    /// The address asking for a read receipt, from the `Disposition-Notification-To` header.
+    pub receipt_to: Option<Addr>,
+
+    /// The mailing list the message was distributed by, from the `List-*` headers.
+    pub list: Option<MailingList>,
+
+    /// The custom headers of the message, sent as is.
+    pub headers: Vec<(String, String)>,
     pub parts: Parts,
 }
 
+/// Represents the fields of the attribution line of replies.
+struct Attribution {
+    date: String,
+    name: String,
+    email: String,
+}
+
+impl TplFields for Attribution {
+    fn tpl_field(&self, name: &str) -> Option<TplValue> {
+        match name {
+            "date" => Some(TplValue::Text(self.date.to_owned())),
+            "name" => Some(TplValue::Text(self.name.to_owned())),
+            "email" => Some(TplValue::Text(self.email.to_owned())),
+            _ => None,
+        }
+    }
+}
+
 impl Msg {
     pub fn attachments(&self) -> Vec<BinaryPart> {
         self.parts
@@ -110,17 +151,150 @@ impl Msg {
         text_parts
     }
 
+    /// Render the text HTML parts as plain text, see [`msg_html`].
+    pub fn render_text_html_parts(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::TextHtml(part) => Some(msg_html::to_text(&part.content)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     pub fn join_text_parts(&self) -> String {
         let text_parts = self.join_text_plain_parts();
         if text_parts.is_empty() {
-            self.join_text_html_parts()
+            self.render_text_html_parts()
         } else {
             text_parts
         }
     }
+    /// Quote the given text of the message, as selected by the account `reply-quote` option and
+    /// by `quote_match` (see [`msg_utils::quoted_lines`]) and prefixed with the account
+    /// `reply-quote-prefix`. The signature of the message is left out.
+    fn quote(&self, text: &str, quote_match: Option<&str>, account: &Account) -> String {
+        msg_utils::quoted_lines(
+            text.trim(),
+            &account.reply_quote,
+            quote_match,
+            account.reply_quote_context,
+        )
+        .into_iter()
+        .take_while(|line| !msg_sig::is_delim(line))
+        .map(|line| msg_utils::quote_line(line, &account.reply_quote_prefix))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    /// Build the attribution line of replies from the account `reply-attribution` format string
+    /// (see [`output_tpl`]), exposing the `date`, `name` and `email` of the sender.
+    fn attribution(&self, account: &Account) -> Result<String> {
+        let date_fmt = &account.reply_attribution_date_format;
+        if StrftimeItems::new(date_fmt).any(|item| item == Item::Error) {
+            return Err(anyhow!(
+                r#"cannot render reply attribution: invalid date format "{}""#,
+                date_fmt
+            ));
+        }
+        let sender = self
+            .reply_to
+            .as_ref()
+            .or_else(|| self.from.as_ref())
+            .and_then(|addrs| addrs.first());
+        let fields = Attribution {
+            date: self
+                .date
+                .as_ref()
+                .map(|date| date.format(date_fmt).to_string())
+                .unwrap_or_else(|| String::from("unknown date")),
+            name: sender
+                .map(|addr| {
+                    addr.name
+                        .to_owned()
+                        .unwrap_or_else(|| addr.email.to_string())
+                })
+                .unwrap_or_else(|| String::from("unknown sender")),
+            email: sender
+                .map(|addr| addr.email.to_string())
+                .unwrap_or_default(),
+        };
+        output_tpl::render(&account.reply_attribution, &fields)
+            .context("cannot render reply attribution")
+    }
+
+    /// Build the variables of the account templates rendered from this message: the account
+    /// ones, `sender.name`, `sender.email` and `original.*` (`subject`, `date`, `from`, `to`,
+    /// `cc`, `message_id`, `body`, and `quoted`, the part of the body selected for replies).
+    fn tpl_context(&self, quote_match: Option<&str>, account: &Account) -> TplContext {
+        let addrs = |addrs: &Option<Vec<Addr>>| {
+            addrs
+                .iter()
+                .flatten()
+                .map(|addr| addr.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let sender = self
+            .reply_to
+            .as_ref()
+            .or_else(|| self.from.as_ref())
+            .and_then(|addrs| addrs.first());
+        let body = self.join_text_parts();
+        let quoted = self.quote(&body, quote_match, account);
+
+        let mut ctx = TplContext::from_account(account);
+        ctx.set(
+            "sender.name",
+            sender
+                .and_then(|addr| addr.name.as_deref())
+                .unwrap_or_default(),
+        )
+        .set(
+            "sender.email",
+            sender
+                .map(|addr| addr.email.to_string())
+                .unwrap_or_default(),
+        )
+        .set("original.subject", &self.subject)
+        .set(
+            "original.date",
+            self.date
+                .map(|date| date.format("%d %b %Y, at %H:%M").to_string())
+                .unwrap_or_default(),
+        )
+        .set("original.from", addrs(&self.from))
+        .set("original.to", addrs(&self.to))
+        .set("original.cc", addrs(&self.cc))
+        .set(
+            "original.message_id",
+            self.message_id.as_deref().unwrap_or_default(),
+        )
+        .set("original.body", body.trim())
+        .set("original.quoted", quoted);
+        ctx
+    }
 
-    pub fn into_reply(mut self, all: bool, account: &Account) -> Result<Self> {
+    /// Transform the message into a reply. Only the part of the original message selected by the
+    /// account `reply-quote` option and by `quote_match` is quoted, see
+    /// [`msg_utils::quoted_lines`]. The account `reply-template`, if any, replaces the default
+    /// attribution line and quote.
+    pub fn into_reply(
+        mut self,
+        all: bool,
+        quote_match: Option<&str>,
+        account: &Account,
+    ) -> Result<Self> {
         let account_addr: Addr = account.address().parse()?;
+        let tpl_content = account
+            .reply_tpl
+            .as_deref()
+            .map(|tpl| tpl_engine::render(tpl, &self.tpl_context(quote_match, account)))
+            .transpose()
+            .context("cannot render reply template")?;
+        // The sender is about to be replaced with the account.
+        let attribution = self.attribution(account)?;
 
         // Message-Id
         self.message_id = None;
@@ -157,72 +331,27 @@ impl Msg {
         }
 
         // Subject
-        if !self.subject.starts_with("Re:") {
-            self.subject = format!("Re: {}", self.subject);
-        }
+        self.subject = msg_utils::reply_subject(&self.subject);
 
         // Text plain parts
-        let plain_content = {
-            let date = self
-                .date
-                .as_ref()
-                .map(|date| date.format("%d %b %Y, at %H:%M").to_string())
-                .unwrap_or("unknown date".into());
-            let sender = self
-                .reply_to
-                .as_ref()
-                .or(self.from.as_ref())
-                .and_then(|addrs| addrs.first())
-                .map(|addr| addr.name.to_owned().unwrap_or(addr.email.to_string()))
-                .unwrap_or("unknown sender".into());
-            let mut content = format!("\n\nOn {}, {} wrote:\n", date, sender);
-
-            let mut glue = "";
-            for line in self.join_text_plain_parts().trim().lines() {
-                if line == "-- \n" {
-                    break;
-                }
-                content.push_str(glue);
-                content.push_str(">");
-                content.push_str(if line.starts_with(">") { "" } else { " " });
-                content.push_str(line);
-                glue = "\n";
-            }
-
-            content
-        };
+        let plain_content = format!(
+            "\n\n{}\n{}",
+            attribution,
+            self.quote(&self.join_text_plain_parts(), quote_match, account)
+        );
 
         // Text HTML parts
-        let html_content = {
-            let date = self
-                .date
-                .as_ref()
-                .map(|date| date.format("%d %b %Y, at %H:%M").to_string())
-                .unwrap_or("unknown date".into());
-            let sender = self
-                .reply_to
-                .as_ref()
-                .or(self.from.as_ref())
-                .and_then(|addrs| addrs.first())
-                .map(|addr| addr.name.to_owned().unwrap_or(addr.email.to_string()))
-                .unwrap_or("unknown sender".into());
-            let mut content = format!("\n\nOn {}, {} wrote:\n", date, sender);
-
-            let mut glue = "";
-            for line in self.join_text_html_parts().trim().lines() {
-                if line == "-- \n" {
-                    break;
-                }
-                content.push_str(glue);
-                content.push_str(">");
-                content.push_str(if line.starts_with(">") { "" } else { " " });
-                content.push_str(line);
-                glue = "\n";
-            }
-
-            content
+        let html_content = format!(
+            "\n\n{}\n{}",
+            attribution,
+            self.quote(&self.join_text_html_parts(), quote_match, account)
+        );
+        let html_content = match account.html_sig.as_deref() {
+            Some(sig) => msg_sig::insert(&html_content, sig, account.sig_pos),
+            None => html_content,
         };
 
+        let plain_content = tpl_content.unwrap_or(plain_content);
         self.parts = Parts::default();
 
         if !plain_content.is_empty() {
@@ -240,8 +369,30 @@ impl Msg {
         Ok(self)
     }
 
+    /// Insert the given text at the top of the text plain part, above the quote of replies.
+    pub fn prepend_text(&mut self, text: &str) {
+        let part = self.parts.iter_mut().find_map(|part| match part {
+            Part::TextPlain(part) => Some(part),
+            _ => None,
+        });
+        match part {
+            Some(part) => part.content.insert_str(0, text),
+            None => self.parts.push(Part::TextPlain(TextPlainPart {
+                content: text.to_owned(),
+            })),
+        }
+    }
+
+    /// Transform the message into a forward. The account `forward-template`, if any, replaces
+    /// the default forward header.
     pub fn into_forward(mut self, account: &Account) -> Result<Self> {
         let account_addr: Addr = account.address().parse()?;
+        let tpl_content = account
+            .forward_tpl
+            .as_deref()
+            .map(|tpl| tpl_engine::render(tpl, &self.tpl_context(None, account)))
+            .transpose()
+            .context("cannot render forward template")?;
 
         let prev_subject = self.subject.to_owned();
         let prev_date = self.date.to_owned();
@@ -267,9 +418,7 @@ impl Msg {
         self.bcc = None;
 
         // Subject
-        if !self.subject.starts_with("Fwd:") {
-            self.subject = format!("Fwd: {}", self.subject);
-        }
+        self.subject = msg_utils::forward_subject(&self.subject);
 
         // Text plain parts
         {
@@ -304,6 +453,10 @@ impl Msg {
             self.parts
                 .replace_text_plain_parts_with(TextPlainPart { content })
         }
+        if let Some(content) = tpl_content {
+            self.parts
+                .replace_text_plain_parts_with(TextPlainPart { content })
+        }
 
         // Text HTML parts
         {
@@ -335,6 +488,9 @@ impl Msg {
             }
             content.push_str("\n");
             content.push_str(&self.join_text_html_parts());
+            if let Some(sig) = account.html_sig.as_deref() {
+                content = msg_sig::insert(&content, sig, account.sig_pos);
+            }
             self.parts
                 .replace_text_html_parts_with(TextHtmlPart { content })
         }
@@ -342,22 +498,136 @@ impl Msg {
         Ok(self)
     }
 
+    /// Build a forward wrapping the given messages, with their raw originals, as message/rfc822
+    /// attachments, so that their headers and signatures reach the recipient untouched.
+    pub fn forward_as_attachments(msgs: Vec<(Msg, Vec<u8>)>, account: &Account) -> Result<Self> {
+        let account_addr: Addr = account.address().parse()?;
+
+        let subject = match msgs.as_slice() {
+            [] => return Err(anyhow!("cannot forward: no message found")),
+            [(msg, _)] => msg_utils::forward_subject(&msg.subject),
+            [(msg, _), others @ ..] => format!(
+                "{} (and {} more)",
+                msg_utils::forward_subject(&msg.subject),
+                others.len()
+            ),
+        };
+
+        let mut filenames: Vec<String> = vec![];
+        let mut parts = vec![];
+        for (msg, raw_msg) in msgs {
+            let filename: String = msg
+                .subject
+                .chars()
+                .map(|c| if "/\\:*?\"<>|".contains(c) { '_' } else { c })
+                .take(64)
+                .collect();
+            let filename = match filename.trim() {
+                "" => String::from("message"),
+                filename => filename.to_owned(),
+            };
+            // Messages sharing a subject get numbered attachments.
+            let count = filenames.iter().filter(|name| **name == filename).count();
+            let attachment_name = match count {
+                0 => format!("{}.eml", filename),
+                count => format!("{} ({}).eml", filename, count + 1),
+            };
+            filenames.push(filename);
+            parts.push(Part::Binary(BinaryPart {
+                filename: attachment_name,
+                mime: String::from("message/rfc822"),
+                content: raw_msg,
+            }));
+        }
+
+        Ok(Self {
+            from: Some(vec![account_addr]),
+            to: Some(vec![]),
+            subject,
+            parts: Parts(parts),
+            ..Self::default()
+        })
+    }
+
     fn _edit_with_editor(&self, account: &Account) -> Result<Self> {
         let tpl = Tpl::from_msg(TplOverride::default(), self, account);
         let tpl = editor::open_with_tpl(tpl)?;
-        Self::try_from(&tpl)
+        Self::from_edited_tpl(tpl)
+    }
+
+    /// Parse the given edited template, offering to edit it again as long as its address headers
+    /// are malformed.
+    fn from_edited_tpl(mut tpl: Tpl) -> Result<Self> {
+        loop {
+            let errors = msg_addr::validate(&tpl);
+            if errors.is_empty() {
+                return Self::try_from(&tpl);
+            }
+            for error in &errors {
+                println!("{}", error);
+            }
+            match choice::fix_addrs() {
+                Ok(FixAddrsChoice::Edit) => tpl = editor::open_with_draft()?,
+                Ok(FixAddrsChoice::Quit) => {
+                    return Err(anyhow!(
+                        "cannot send message with malformed address headers, draft kept at {:?}",
+                        msg_utils::local_draft_path()
+                    ))
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+    }
+
+    /// Ask for a read receipt, sent back to the account address.
+    pub fn request_receipt(&mut self, account: &Account) {
+        self.headers
+            .push((msg_mdn::RECEIPT_HEADER.to_owned(), account.address()));
+    }
+
+    /// Check the recipients of the message against the delivery log, when the account enables
+    /// the send safety check.
+    pub fn check_recipients(&self, account: &Account) -> Result<Vec<SendWarning>> {
+        if !account.send_safety_check {
+            return Ok(vec![]);
+        }
+        let recipients: Vec<String> = self
+            .to
+            .iter()
+            .chain(self.cc.iter())
+            .chain(self.bcc.iter())
+            .flatten()
+            .map(|addr| addr.email.to_string())
+            .collect();
+        let sent_log = SentLog::load(account)?;
+        Ok(msg_safety::check(
+            &recipients,
+            sent_log.recipients(),
+            &account.internal_domains,
+        ))
     }
 
-    pub fn edit_with_editor<
-        OutputService: OutputServiceInterface,
-        ImapService: ImapServiceInterface,
-        SmtpService: SmtpServiceInterface,
-    >(
+    /// Find a mention of an attachment in the text of the message, when it has no attachment.
+    pub fn check_attachment_reminder(&self, account: &Account) -> Result<Option<String>> {
+        let has_attachments = self
+            .parts
+            .iter()
+            .any(|part| matches!(part, Part::Binary(_)));
+        if has_attachments || account.attachment_reminder_patterns.is_empty() {
+            return Ok(None);
+        }
+        msg_attachment_reminder::find_mention(
+            &self.join_text_plain_parts(),
+            &account.attachment_reminder_patterns,
+        )
+    }
+
+    pub fn edit_with_editor<OutputService: OutputServiceInterface>(
         mut self,
         account: &Account,
         output: &OutputService,
-        imap: &mut ImapService,
-        smtp: &mut SmtpService,
+        backend: &mut dyn Backend,
+        sender: &mut dyn Sender,
     ) -> Result<()> {
         let draft = msg_utils::local_draft_path();
         if draft.exists() {
@@ -366,7 +636,7 @@ impl Msg {
                     Ok(choice) => match choice {
                         PreEditChoice::Edit => {
                             let tpl = editor::open_with_draft()?;
-                            self.merge_with(Msg::try_from(&tpl)?);
+                            self.merge_with(Msg::from_edited_tpl(tpl)?);
                             break;
                         }
                         PreEditChoice::Discard => {
@@ -385,13 +655,107 @@ impl Msg {
             self.merge_with(self._edit_with_editor(account)?);
         }
 
+        self.post_edit(account, output, backend, sender)
+    }
+
+    /// Edit the given template, then send or save the message it defines. Malformed address
+    /// headers are fixed in the editor.
+    pub fn edit_tpl_with_editor<OutputService: OutputServiceInterface>(
+        tpl: Tpl,
+        account: &Account,
+        output: &OutputService,
+        backend: &mut dyn Backend,
+        sender: &mut dyn Sender,
+    ) -> Result<()> {
+        let tpl = editor::open_with_tpl(tpl)?;
+        Self::from_edited_tpl(tpl)?.post_edit(account, output, backend, sender)
+    }
+
+    /// Ask what to do with the edited message, until it is sent, saved or discarded.
+    fn post_edit<OutputService: OutputServiceInterface>(
+        mut self,
+        account: &Account,
+        output: &OutputService,
+        backend: &mut dyn Backend,
+        sender: &mut dyn Sender,
+    ) -> Result<()> {
         loop {
             match choice::post_edit() {
                 Ok(PostEditChoice::Send) => {
-                    let mbox = Mbox::from("Sent");
-                    let sent_msg = smtp.send_msg(&self)?;
-                    let flags = Flags::try_from(vec![Flag::Seen])?;
-                    imap.append_raw_msg_with_flags(&mbox, &sent_msg.formatted(), flags)?;
+                    if let Some(mention) = self.check_attachment_reminder(account)? {
+                        println!(
+                            r#"The message mentions an attachment ("{}") but has none"#,
+                            mention
+                        );
+                        match choice::send_anyway() {
+                            Ok(SendAnywayChoice::Send) => (),
+                            Ok(SendAnywayChoice::Edit) => {
+                                self.merge_with(self._edit_with_editor(account)?);
+                                continue;
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                continue;
+                            }
+                        }
+                    }
+
+                    if let Some(ref cmd) = account.spellcheck_cmd {
+                        let text = self.join_text_plain_parts();
+                        let to: Vec<String> = self
+                            .to
+                            .iter()
+                            .flatten()
+                            .map(|addr| addr.to_string())
+                            .collect();
+                        let lang = account
+                            .recipient_tpl(&to)
+                            .and_then(|tpl| tpl.lang.as_deref());
+                        let (lang, words) = msg_spellcheck::spellcheck(cmd, &text, lang)?;
+                        if !words.is_empty() {
+                            println!("Possible misspellings ({}): {}", lang, words.join(", "));
+                            match choice::send_anyway() {
+                                Ok(SendAnywayChoice::Send) => (),
+                                Ok(SendAnywayChoice::Edit) => {
+                                    self.merge_with(self._edit_with_editor(account)?);
+                                    continue;
+                                }
+                                Err(err) => {
+                                    println!("{}", err);
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+
+                    let warnings = self.check_recipients(account)?;
+                    if !warnings.is_empty() {
+                        for warning in &warnings {
+                            println!("{}", warning);
+                        }
+                        match choice::send_anyway() {
+                            Ok(SendAnywayChoice::Send) => (),
+                            Ok(SendAnywayChoice::Edit) => {
+                                self.merge_with(self._edit_with_editor(account)?);
+                                continue;
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                continue;
+                            }
+                        }
+                    }
+
+                    let mbox = Mbox::from(account.sent_folder.as_str());
+                    if account.save_sent_copy {
+                        warn_near_quota(&mbox, account, backend);
+                    }
+                    for sent_msg in msg_split::send(&self, account, sender)? {
+                        if account.save_sent_copy {
+                            let flags = Flags::try_from(vec![Flag::Seen])?;
+                            backend.append_raw(&mbox, &sent_msg, flags)?;
+                        }
+                    }
                     msg_utils::remove_local_draft()?;
                     output.print("Message successfully sent")?;
                     break;
@@ -405,10 +769,10 @@ impl Msg {
                     break;
                 }
                 Ok(PostEditChoice::RemoteDraft) => {
-                    let mbox = Mbox::from("Drafts");
+                    let mbox = Mbox::from(account.drafts_folder.as_str());
                     let flags = Flags::try_from(vec![Flag::Seen, Flag::Draft])?;
                     let tpl = Tpl::from_msg(TplOverride::default(), &self, account);
-                    imap.append_raw_msg_with_flags(&mbox, tpl.as_bytes(), flags)?;
+                    backend.append_raw(&mbox, tpl.as_bytes(), flags)?;
                     msg_utils::remove_local_draft()?;
                     output.print("Message successfully saved to Drafts")?;
                     break;
@@ -450,6 +814,17 @@ impl Msg {
         Ok(self)
     }
 
+    /// Set the given `NAME: VALUE` headers, replacing the headers of the same name.
+    pub fn add_headers(mut self, headers: Vec<&str>) -> Result<Self> {
+        for header in headers {
+            let header = Header::parse(header)?;
+            self.headers
+                .retain(|(key, _)| !key.eq_ignore_ascii_case(&header.name));
+            self.headers.push((header.name, header.value));
+        }
+        Ok(self)
+    }
+
     pub fn merge_with(&mut self, msg: Msg) {
         if msg.from.is_some() {
             self.from = msg.from;
@@ -471,6 +846,10 @@ impl Msg {
             self.subject = msg.subject;
         }
 
+        if !msg.headers.is_empty() {
+            self.headers = msg.headers;
+        }
+
         for part in msg.parts.0.into_iter() {
             match part {
                 Part::Binary(_) => self.parts.push(part),
@@ -491,6 +870,97 @@ impl Msg {
             }
         }
     }
+
+    /// Build a sendable message. The transfer encoding of each part is chosen according to its
+    /// content, 8-bit encoding being used only if `allow_8bit` is set. The text is sent as
+    /// `format=flowed` if `flowed` is set.
+    pub fn to_sendable_msg(&self, allow_8bit: bool, flowed: bool) -> Result<lettre::Message> {
+        let mut msg_builder = lettre::Message::builder()
+            .message_id(self.message_id.to_owned())
+            .subject(self.subject.to_owned());
+
+        if let Some(id) = self.in_reply_to.as_ref() {
+            msg_builder = msg_builder.in_reply_to(id.to_owned());
+        };
+
+        if let Some(addrs) = self.from.as_ref() {
+            msg_builder = addrs
+                .iter()
+                .fold(msg_builder, |builder, addr| builder.from(addr.to_owned()))
+        };
+
+        if let Some(addrs) = self.to.as_ref() {
+            msg_builder = addrs
+                .iter()
+                .fold(msg_builder, |builder, addr| builder.to(addr.to_owned()))
+        };
+
+        if let Some(addrs) = self.reply_to.as_ref() {
+            msg_builder = addrs.iter().fold(msg_builder, |builder, addr| {
+                builder.reply_to(addr.to_owned())
+            })
+        };
+
+        if let Some(addrs) = self.cc.as_ref() {
+            msg_builder = addrs
+                .iter()
+                .fold(msg_builder, |builder, addr| builder.cc(addr.to_owned()))
+        };
+
+        if let Some(addrs) = self.bcc.as_ref() {
+            msg_builder = addrs
+                .iter()
+                .fold(msg_builder, |builder, addr| builder.bcc(addr.to_owned()))
+        };
+
+        let (content_type, text) = if flowed {
+            let content_type = ContentType::parse("text/plain; charset=utf-8; format=flowed")
+                .context("cannot parse flowed content type")?;
+            (
+                content_type,
+                msg_flowed::to_flowed(&self.join_text_plain_parts()),
+            )
+        } else {
+            (ContentType::TEXT_PLAIN, self.join_text_plain_parts())
+        };
+        let text_plain_part = msg_compliance::encode_body(text.into_bytes(), true, allow_8bit);
+        let mut multipart = MultiPart::mixed().singlepart(
+            SinglePart::builder()
+                .header(content_type)
+                .body(text_plain_part),
+        );
+
+        for part in self.attachments() {
+            let filename = part.filename;
+            let is_text = part.mime.starts_with("text/");
+            let content = if part.mime == "message/rfc822" {
+                msg_compliance::encode_msg_body(part.content)
+            } else {
+                msg_compliance::encode_body(part.content, is_text, allow_8bit)
+            };
+            let mime = part.mime.parse().context(format!(
+                r#"cannot parse content type of attachment "{}""#,
+                filename
+            ))?;
+            multipart = multipart.singlepart(Attachment::new(filename).body(content, mime))
+        }
+
+        msg_builder
+            .multipart(multipart)
+            .context("cannot build sendable message")
+    }
+
+    /// Format the sendable message, prepending the custom headers the builder does not know
+    /// about.
+    pub fn format_sendable_msg(&self, sendable_msg: &lettre::Message) -> Vec<u8> {
+        let mut raw_msg: Vec<u8> = self
+            .headers
+            .iter()
+            .flat_map(|(key, val)| format!("{}: {}\r\n", key, val).into_bytes())
+            .collect();
+        raw_msg.extend(sendable_msg.formatted());
+        raw_msg
+    }
 }
 
 impl TryFrom<&Tpl> for Msg {
@@ -498,6 +968,7 @@ impl TryFrom<&Tpl> for Msg {
 
     fn try_from(tpl: &Tpl) -> Result<Msg> {
         let mut msg = Msg::default();
+        let mut attachments_paths = vec![];
 
         let parsed_msg =
             mailparse::parse_mail(tpl.as_bytes()).context("cannot parse message from template")?;
@@ -512,47 +983,31 @@ impl TryFrom<&Tpl> for Msg {
                     msg.message_id = Some(val.to_owned())
                 }
                 "From" | _ if key.eq_ignore_ascii_case("from") => {
-                    msg.from = Some(
-                        val.split(',')
-                            .filter_map(|addr| addr.parse().ok())
-                            .collect::<Vec<_>>(),
-                    );
+                    msg.from = parse_mail_addrs(&val);
                 }
                 "To" | _ if key.eq_ignore_ascii_case("to") => {
-                    msg.to = Some(
-                        val.split(',')
-                            .filter_map(|addr| addr.parse().ok())
-                            .collect::<Vec<_>>(),
-                    );
+                    msg.to = parse_mail_addrs(&val);
                 }
                 "Reply-To" | _ if key.eq_ignore_ascii_case("reply-to") => {
-                    msg.reply_to = Some(
-                        val.split(',')
-                            .filter_map(|addr| addr.parse().ok())
-                            .collect::<Vec<_>>(),
-                    );
+                    msg.reply_to = parse_mail_addrs(&val);
                 }
                 "In-Reply-To" | _ if key.eq_ignore_ascii_case("in-reply-to") => {
                     msg.in_reply_to = Some(val.to_owned())
                 }
                 "Cc" | _ if key.eq_ignore_ascii_case("cc") => {
-                    msg.cc = Some(
-                        val.split(',')
-                            .filter_map(|addr| addr.parse().ok())
-                            .collect::<Vec<_>>(),
-                    );
+                    msg.cc = parse_mail_addrs(&val);
                 }
                 "Bcc" | _ if key.eq_ignore_ascii_case("bcc") => {
-                    msg.bcc = Some(
-                        val.split(',')
-                            .filter_map(|addr| addr.parse().ok())
-                            .collect::<Vec<_>>(),
-                    );
+                    msg.bcc = parse_mail_addrs(&val);
                 }
                 "Subject" | _ if key.eq_ignore_ascii_case("subject") => {
                     msg.subject = val;
                 }
-                _ => (),
+                _ if key.eq_ignore_ascii_case(ATTACHMENT_HEADER) => attachments_paths.push(val),
+                // The MIME structure is rebuilt when sending.
+                _ if key.to_lowercase().starts_with("content-")
+                    || key.eq_ignore_ascii_case("mime-version") => {}
+                _ => msg.headers.push((key, val)),
             }
         }
 
@@ -562,6 +1017,58 @@ impl TryFrom<&Tpl> for Msg {
         let content = String::from_utf8(content).context("cannot decode body from utf-8")?;
         msg.parts.push(Part::TextPlain(TextPlainPart { content }));
 
+        msg.add_attachments(attachments_paths.iter().map(String::as_str).collect())
+    }
+}
+
+impl<'a> TryFrom<&'a mailparse::ParsedMail<'a>> for Msg {
+    type Error = Error;
+
+    fn try_from(parsed_mail: &'a mailparse::ParsedMail<'a>) -> Result<Msg> {
+        let mut msg = Msg::default();
+
+        for header in parsed_mail.get_headers() {
+            let key = header.get_key();
+            let val = header.get_value();
+
+            match key.as_str() {
+                "Message-Id" | _ if key.eq_ignore_ascii_case("message-id") => {
+                    msg.message_id = Some(val.trim().to_owned())
+                }
+                "In-Reply-To" | _ if key.eq_ignore_ascii_case("in-reply-to") => {
+                    msg.in_reply_to = Some(val.trim().to_owned())
+                }
+                "Subject" | _ if key.eq_ignore_ascii_case("subject") => {
+                    msg.subject = val.trim().to_owned();
+                }
+                "Date" | _ if key.eq_ignore_ascii_case("date") => {
+                    msg.date = DateTime::parse_from_rfc2822(val.trim()).ok();
+                }
+                "From" | _ if key.eq_ignore_ascii_case("from") => {
+                    msg.from = parse_mail_addrs(&val);
+                }
+                "Reply-To" | _ if key.eq_ignore_ascii_case("reply-to") => {
+                    msg.reply_to = parse_mail_addrs(&val);
+                }
+                "To" | _ if key.eq_ignore_ascii_case("to") => {
+                    msg.to = parse_mail_addrs(&val);
+                }
+                "Cc" | _ if key.eq_ignore_ascii_case("cc") => {
+                    msg.cc = parse_mail_addrs(&val);
+                }
+                "Bcc" | _ if key.eq_ignore_ascii_case("bcc") => {
+                    msg.bcc = parse_mail_addrs(&val);
+                }
+                _ if key.eq_ignore_ascii_case(msg_mdn::RECEIPT_HEADER) => {
+                    msg.receipt_to =
+                        parse_mail_addrs(&val).and_then(|addrs| addrs.into_iter().next());
+                }
+                _ => (),
+            }
+        }
+
+        msg.list = MailingList::from_headers(&parsed_mail.headers);
+        msg.parts = Parts::from(parsed_mail);
         Ok(msg)
     }
 }
@@ -589,60 +1096,7 @@ impl TryInto<lettre::Message> for &Msg {
     type Error = Error;
 
     fn try_into(self) -> Result<lettre::Message> {
-        let mut msg_builder = lettre::Message::builder()
-            .message_id(self.message_id.to_owned())
-            .subject(self.subject.to_owned());
-
-        if let Some(id) = self.in_reply_to.as_ref() {
-            msg_builder = msg_builder.in_reply_to(id.to_owned());
-        };
-
-        if let Some(addrs) = self.from.as_ref() {
-            msg_builder = addrs
-                .iter()
-                .fold(msg_builder, |builder, addr| builder.from(addr.to_owned()))
-        };
-
-        if let Some(addrs) = self.to.as_ref() {
-            msg_builder = addrs
-                .iter()
-                .fold(msg_builder, |builder, addr| builder.to(addr.to_owned()))
-        };
-
-        if let Some(addrs) = self.reply_to.as_ref() {
-            msg_builder = addrs.iter().fold(msg_builder, |builder, addr| {
-                builder.reply_to(addr.to_owned())
-            })
-        };
-
-        if let Some(addrs) = self.cc.as_ref() {
-            msg_builder = addrs
-                .iter()
-                .fold(msg_builder, |builder, addr| builder.cc(addr.to_owned()))
-        };
-
-        if let Some(addrs) = self.bcc.as_ref() {
-            msg_builder = addrs
-                .iter()
-                .fold(msg_builder, |builder, addr| builder.bcc(addr.to_owned()))
-        };
-
-        let mut multipart =
-            MultiPart::mixed().singlepart(SinglePart::plain(self.join_text_plain_parts()));
-
-        for part in self.attachments() {
-            let filename = part.filename;
-            let content = part.content;
-            let mime = part.mime.parse().context(format!(
-                r#"cannot parse content type of attachment "{}""#,
-                filename
-            ))?;
-            multipart = multipart.singlepart(Attachment::new(filename).body(content, mime))
-        }
-
-        msg_builder
-            .multipart(multipart)
-            .context("cannot build sendable message")
+        self.to_sendable_msg(false, false)
     }
 }
 
@@ -664,7 +1118,7 @@ impl<'a> TryFrom<&'a imap::types::Fetch> for Msg {
             .ok_or(anyhow!("cannot get envelope of message {}", fetch.message))?;
 
         // Get the sequence number
-        let id = fetch.message;
+        let id = fetch.uid.unwrap_or(fetch.message);
 
         // Get the flags
         let flags = Flags::try_from(fetch.flags())?;
@@ -733,15 +1187,25 @@ impl<'a> TryFrom<&'a imap::types::Fetch> for Msg {
         // Get the internal date
         let date = fetch.internal_date();
 
+        let parsed_mail = mailparse::parse_mail(
+            fetch
+                .body()
+                .ok_or(anyhow!("cannot get body of message {}", id))?,
+        )
+        .context(format!("cannot parse body of message {}", id))?;
+
+        // Get the read receipt address
+        let receipt_to = parsed_mail
+            .get_headers()
+            .get_first_value(msg_mdn::RECEIPT_HEADER)
+            .and_then(|val| parse_mail_addrs(&val))
+            .and_then(|addrs| addrs.into_iter().next());
+
+        // Get the mailing list
+        let list = MailingList::from_headers(&parsed_mail.headers);
+
         // Get all parts
-        let parts = Parts::from(
-            &mailparse::parse_mail(
-                fetch
-                    .body()
-                    .ok_or(anyhow!("cannot get body of message {}", id))?,
-            )
-            .context(format!("cannot parse body of message {}", id))?,
-        );
+        let parts = Parts::from(&parsed_mail);
 
         Ok(Self {
             id,
@@ -755,6 +1219,9 @@ impl<'a> TryFrom<&'a imap::types::Fetch> for Msg {
             cc,
             bcc,
             date,
+            receipt_to,
+            list,
+            headers: vec![],
             parts,
         })
     }
@@ -804,6 +1271,24 @@ pub fn parse_some_addrs(addrs: &Option<Vec<imap_proto::Address>>) -> Result<Opti
     })
 }
 
+/// Parse a raw address header value, skipping addresses that cannot be parsed.
+pub fn parse_mail_addrs(val: &str) -> Option<Vec<Addr>> {
+    let addrs = mailparse::addrparse(val).ok()?;
+    let mut parsed_addrs = vec![];
+    for addr in addrs.iter() {
+        let singles = match addr {
+            mailparse::MailAddr::Single(single) => vec![single.to_owned()],
+            mailparse::MailAddr::Group(group) => group.addrs.to_owned(),
+        };
+        for single in singles {
+            if let Ok(email) = single.addr.parse() {
+                parsed_addrs.push(Addr::new(single.display_name, email));
+            }
+        }
+    }
+    Some(parsed_addrs)
+}
+
 #[derive(Debug, Serialize)]
 pub struct PrintableMsg(pub String);
 
@@ -0,0 +1,311 @@
+//! Module related to message export and import.
+//!
+//! Messages are exported either as one `.eml` file per message or as a single [mboxrd] file.
+//! Imports accept a single `.eml` file, an mbox file, a directory of `.eml` files or a [Maildir].
+//!
+//! [mboxrd]: https://doc.dovecot.org/admin_manual/mailbox_formats/mbox/#mbox-variants
+//! [Maildir]: https://cr.yp.to/proto/maildir.html
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, Utc};
+use imap::types::Flag;
+use log::debug;
+use std::{
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::domain::msg::{Flags, Msg};
+
+/// Represents the export formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Mbox,
+    Eml,
+}
+
+impl TryFrom<&str> for ExportFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(format: &str) -> Result<Self> {
+        match format {
+            format if format.eq_ignore_ascii_case("mbox") => Ok(Self::Mbox),
+            format if format.eq_ignore_ascii_case("eml") => Ok(Self::Eml),
+            format => Err(anyhow!(r#"cannot parse export format "{}""#, format)),
+        }
+    }
+}
+
+/// Represents a message to import, with the flags found along with it.
+pub struct ImportedMsg {
+    pub raw: Vec<u8>,
+    pub flags: Flags,
+}
+
+/// Split the given content in lines, keeping their line feed.
+fn lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content
+        .split_inclusive(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+}
+
+/// Check if the given line, stripped from its leading `>`, starts with `From `.
+fn is_quoted_from_line(line: &[u8]) -> bool {
+    let unquoted = line
+        .iter()
+        .position(|b| *b != b'>')
+        .map(|i| &line[i..])
+        .unwrap_or_default();
+    unquoted.starts_with(b"From ")
+}
+
+/// Convert line endings to CRLF, as expected by IMAP servers.
+pub fn to_crlf(content: &[u8]) -> Vec<u8> {
+    let mut crlf = Vec::with_capacity(content.len());
+    for line in lines(content) {
+        match line.strip_suffix(b"\n") {
+            Some(line) => {
+                crlf.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+                crlf.extend_from_slice(b"\r\n");
+            }
+            None => crlf.extend_from_slice(line),
+        }
+    }
+    crlf
+}
+
+/// Build the mboxrd entry of the given message: a `From ` separator line followed by the raw
+/// message, with LF line endings and its `From ` lines quoted.
+pub fn to_mbox_entry(msg: &Msg, raw_msg: &[u8]) -> Vec<u8> {
+    let sender = msg
+        .from
+        .as_ref()
+        .and_then(|addrs| addrs.first())
+        .map(|addr| addr.email.to_string())
+        .unwrap_or_else(|| String::from("MAILER-DAEMON"));
+    let date = msg
+        .date
+        .map(|date| date.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+
+    let mut entry =
+        format!("From {} {}\n", sender, date.format("%a %b %e %H:%M:%S %Y")).into_bytes();
+    for line in lines(raw_msg) {
+        if is_quoted_from_line(line) {
+            entry.push(b'>');
+        }
+        match line.strip_suffix(b"\r\n") {
+            Some(line) => {
+                entry.extend_from_slice(line);
+                entry.push(b'\n');
+            }
+            None => entry.extend_from_slice(line),
+        }
+    }
+    if !entry.ends_with(b"\n") {
+        entry.push(b'\n');
+    }
+    entry.push(b'\n');
+    entry
+}
+
+/// Split the given mboxrd content in raw messages, with CRLF line endings. Plain mboxo files are
+/// read the same way, since their quoted lines cannot be told apart anyway.
+pub fn split_mbox(content: &[u8]) -> Vec<Vec<u8>> {
+    let mut msgs = vec![];
+    let mut msg: Option<Vec<u8>> = None;
+    let mut prev_blank = true;
+
+    for line in lines(content) {
+        if prev_blank && line.starts_with(b"From ") {
+            if let Some(msg) = msg.take() {
+                msgs.push(msg);
+            }
+            msg = Some(vec![]);
+            prev_blank = false;
+            continue;
+        }
+        prev_blank = line == b"\n" || line == b"\r\n";
+
+        if let Some(ref mut msg) = msg {
+            if line.starts_with(b">") && is_quoted_from_line(line) {
+                msg.extend_from_slice(&line[1..]);
+            } else {
+                msg.extend_from_slice(line);
+            }
+        }
+    }
+    msgs.extend(msg);
+
+    msgs.into_iter()
+        .map(|mut msg| {
+            // Drop the blank line separating the message from the next one.
+            if msg.ends_with(b"\r\n\r\n") {
+                msg.truncate(msg.len() - 2);
+            } else if msg.ends_with(b"\n\n") {
+                msg.truncate(msg.len() - 1);
+            }
+            to_crlf(&msg)
+        })
+        .filter(|msg| !msg.is_empty())
+        .collect()
+}
+
+/// Build the info suffix of a Maildir file name encoding the given flags, like `:2,FS`.
+pub fn maildir_info(flags: &Flags) -> String {
+    let mut info: Vec<char> = flags
+        .iter()
+        .filter_map(|flag| match flag {
+            Flag::Seen => Some('S'),
+            Flag::Answered => Some('R'),
+            Flag::Flagged => Some('F'),
+            Flag::Deleted => Some('T'),
+            Flag::Draft => Some('D'),
+            _ => None,
+        })
+        .collect();
+    // The Maildir specification requires flags in ASCII order.
+    info.sort_unstable();
+    format!(":2,{}", info.into_iter().collect::<String>())
+}
+
+/// Get the flags encoded in the name of a Maildir file, like `1637854411.M1P2.host:2,FS`.
+pub fn maildir_flags(filename: &str) -> Vec<Flag<'static>> {
+    let info = match filename.rsplit_once(":2,") {
+        Some((_, info)) => info,
+        None => return vec![],
+    };
+    info.chars()
+        .filter_map(|c| match c {
+            'S' => Some(Flag::Seen),
+            'R' => Some(Flag::Answered),
+            'F' => Some(Flag::Flagged),
+            'T' => Some(Flag::Deleted),
+            'D' => Some(Flag::Draft),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Read the messages of the given Maildir, from its `cur` and `new` directories.
+fn read_maildir(dir: &Path) -> Result<Vec<ImportedMsg>> {
+    let mut msgs = vec![];
+    for subdir in ["cur", "new"].iter() {
+        let subdir = dir.join(subdir);
+        for path in read_dir(&subdir)? {
+            let filename = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let raw = fs::read(&path).context(format!("cannot read message {:?}", path))?;
+            msgs.push(ImportedMsg {
+                raw: to_crlf(&raw),
+                flags: Flags::try_from(maildir_flags(&filename))?,
+            });
+        }
+    }
+    Ok(msgs)
+}
+
+/// List the files of the given directory, sorted by name.
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).context(format!("cannot read directory {:?}", dir))? {
+        let path = entry
+            .context(format!("cannot read directory {:?}", dir))?
+            .path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Read the messages to import from the given path: a Maildir, a directory of `.eml` files, an
+/// mbox file or a single message.
+pub fn read_msgs(path: &Path) -> Result<Vec<ImportedMsg>> {
+    if path.is_dir() {
+        if path.join("cur").is_dir() && path.join("new").is_dir() {
+            debug!("import Maildir {:?}", path);
+            return read_maildir(path);
+        }
+
+        debug!("import directory {:?}", path);
+        let mut msgs = vec![];
+        for path in read_dir(path)? {
+            if path.extension().map(|ext| ext == "eml").unwrap_or_default() {
+                let raw = fs::read(&path).context(format!("cannot read message {:?}", path))?;
+                msgs.push(ImportedMsg {
+                    raw: to_crlf(&raw),
+                    flags: Flags::default(),
+                });
+            }
+        }
+        return Ok(msgs);
+    }
+
+    let content = fs::read(path).context(format!("cannot read file {:?}", path))?;
+    let raws = if content.starts_with(b"From ") {
+        debug!("import mbox file {:?}", path);
+        split_mbox(&content)
+    } else {
+        debug!("import message {:?}", path);
+        vec![to_crlf(&content)]
+    };
+    Ok(raws
+        .into_iter()
+        .map(|raw| ImportedMsg {
+            raw,
+            flags: Flags::default(),
+        })
+        .collect())
+}
+
+/// Build the name of the mbox file exported from the given mailbox.
+pub fn mbox_filename(mbox: &str) -> String {
+    let name: String = mbox
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    format!("{}-{}.mbox", name, Local::now().format("%Y%m%d"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_round_trip_mbox() {
+        let msgs = vec![
+            &b"From: alice@localhost\r\nSubject: One\r\n\r\nFrom now on,\r\n>From the start.\r\n"[..],
+            &b"From: bob@localhost\r\nSubject: Two\r\n\r\nBye\r\n"[..],
+        ];
+
+        let mut content = vec![];
+        for raw in msgs.iter() {
+            content.extend(to_mbox_entry(&Msg::default(), raw));
+        }
+        assert!(content.starts_with(b"From MAILER-DAEMON "));
+        let content_str = String::from_utf8_lossy(&content);
+        assert!(content_str.contains("\n>From now on,\n>>From the start.\n"));
+
+        let split = split_mbox(&content);
+        assert_eq!(2, split.len());
+        assert_eq!(msgs[0], &split[0][..]);
+        assert_eq!(msgs[1], &split[1][..]);
+    }
+
+    #[test]
+    fn it_should_parse_maildir_flags() {
+        assert_eq!(
+            vec![Flag::Flagged, Flag::Seen],
+            maildir_flags("1637854411.M1P2.host:2,FS")
+        );
+        assert!(maildir_flags("1637854411.M1P2.host").is_empty());
+        let flags = Flags::try_from(maildir_flags("42.himalaya:2,SF")).unwrap();
+        assert_eq!(":2,FS", maildir_info(&flags));
+        assert_eq!(ExportFormat::Eml, ExportFormat::try_from("EML").unwrap());
+    }
+}
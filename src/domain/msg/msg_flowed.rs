@@ -0,0 +1,97 @@
+//! Module related to the [format=flowed] encoding.
+//!
+//! Flowed text is made of lines short enough for every client, long paragraphs being wrapped
+//! with soft line breaks: a line ending with a space continues on the next one. Clients
+//! supporting the format join them back and reflow the paragraph to their own width, the other
+//! ones show the short lines as they are.
+//!
+//! [format=flowed]: https://datatracker.ietf.org/doc/html/rfc3676
+
+use crate::domain::msg::msg_sig;
+
+/// Maximum length of a flowed line, excluding the soft line break, as recommended by
+/// [RFC3676](https://datatracker.ietf.org/doc/html/rfc3676#section-4.2).
+pub const FLOWED_LINE_LEN: usize = 72;
+
+/// Check if the given unquoted line needs to be space-stuffed, so that it is not mistaken for a
+/// quoted or a flowed line, nor mangled by servers escaping lines starting with `From `.
+fn needs_stuffing(line: &str) -> bool {
+    line.starts_with(' ') || line.starts_with('>') || line.starts_with("From ")
+}
+
+/// Wrap the given content in lines of the given width, all of them but the last one ending with
+/// a soft line break. Words longer than the width are left whole.
+fn wrap(content: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in content.split_inclusive(' ') {
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + word.trim_end().chars().count() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// Encode the given text as flowed text.
+pub fn to_flowed(text: &str) -> String {
+    let mut flowed = vec![];
+
+    for line in text.lines() {
+        // The signature delimiter keeps its trailing space, it is not a soft line break.
+        if msg_sig::is_delim(line) {
+            flowed.push(String::from("-- "));
+            continue;
+        }
+
+        let content = line.trim_start_matches('>');
+        let depth = line.len() - content.len();
+        // Trailing spaces would turn hard line breaks into soft ones.
+        let content = content.trim_end_matches(' ');
+
+        if depth == 0 {
+            for line in wrap(content, FLOWED_LINE_LEN) {
+                if needs_stuffing(&line) {
+                    flowed.push(format!(" {}", line));
+                } else {
+                    flowed.push(line);
+                }
+            }
+        } else {
+            let quote = ">".repeat(depth);
+            let content = content.strip_prefix(' ').unwrap_or(content);
+            if content.is_empty() {
+                flowed.push(quote);
+                continue;
+            }
+            let width = FLOWED_LINE_LEN.saturating_sub(depth + 1);
+            for line in wrap(content, width) {
+                flowed.push(format!("{} {}", quote, line));
+            }
+        }
+    }
+
+    flowed.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_encode_flowed_text() {
+        let paragraph = "word ".repeat(20);
+        assert_eq!(
+            format!("{}\n{}", "word ".repeat(14), "word ".repeat(6).trim_end()),
+            to_flowed(&paragraph)
+        );
+        assert_eq!(" From here\n  indented", to_flowed("From here\n indented"));
+        assert_eq!(
+            "> Hi\n>\n>> Hello\n-- \nBob",
+            to_flowed("> Hi   \n>\n>>Hello  \n-- \nBob")
+        );
+    }
+}
@@ -2,52 +2,98 @@
 //!
 //! This module gathers all message commands.  
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use atty::Stream;
+use chrono::{FixedOffset, Local};
 use imap::types::Flag;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use std::{
-    borrow::Cow,
+    collections::BTreeMap,
     convert::{TryFrom, TryInto},
     fs,
     io::{self, BufRead},
+    path::PathBuf,
+    thread,
+    time::Duration,
 };
-use url::Url;
 
 use crate::{
-    config::Account,
+    config::{Account, ReceiptPolicy},
     domain::{
-        imap::ImapServiceInterface,
-        mbox::Mbox,
-        msg::{Flags, Msg, Part, TextPlainPart, Tpl},
-        smtp::SmtpServiceInterface,
+        backend::{build_backend, Backend, FetchPool, Sender},
+        imap::GMAIL_EXT,
+        mbox::{warn_near_quota, Mbox},
+        metrics::{self, Metric},
+        msg::{
+            envelope_entity, msg_addr,
+            msg_arg::{
+                DedupOpts, ExportOpts, ForwardOpts, ListOpts, ReadOpts, ReplyOpts, RewriteOpts,
+                SearchOpts, WriteOpts,
+            },
+            msg_body_search, msg_bounce, msg_compliance,
+            msg_dedup::{self, Fingerprint},
+            msg_diff, msg_digest,
+            msg_export::{self, ExportFormat},
+            msg_hold, msg_hook,
+            msg_ical::{self, PartStat},
+            msg_journal::Journal,
+            msg_mailing_list::{self, MailingList, UnsubscribeTarget},
+            msg_mailto::Mailto,
+            msg_mdn, msg_patch,
+            msg_query::SearchQuery,
+            msg_rewrite::{self, Header},
+            msg_sandbox, msg_schedule, msg_smime, msg_split, msg_summary, msg_utils,
+            ColumnedEnvelopes, Envelopes, Flags, Msg, Part, Parts, SortCriteria, TextPlainPart,
+            Tpl,
+        },
+        queue::Outbox,
+        sent::SentLog,
+        webhook::{self, WebhookEvent},
+    },
+    output::{pipe_cmd, stream_cmd, OutputServiceInterface},
+    ui::{
+        choice::{self, PickedMsgChoice, ReadReceiptChoice},
+        picker,
     },
-    output::OutputServiceInterface,
 };
 
 use super::PrintableMsg;
 
+/// Define the number of messages fetched at once by exports.
+const EXPORT_CHUNK_SIZE: usize = 50;
+
+/// Number of messages fingerprinted at once by the dedup command, so that large mailboxes are
+/// never held in memory.
+const DEDUP_BATCH_SIZE: usize = 500;
+
 /// Download all attachments from the given message sequence number to the user account downloads
 /// directory.
-pub fn attachments<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+pub fn attachments<OutputService: OutputServiceInterface>(
     seq: &str,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
-    let attachments = imap.find_msg(&seq)?.attachments();
+    let mut progress = output.progress(
+        &format!(r#"Downloading attachments of message "{}""#, seq),
+        0,
+    );
+    let attachments = backend.get_msg(&seq)?.attachments();
     let attachments_len = attachments.len();
     debug!(
         r#"{} attachment(s) found for message "{}""#,
         attachments_len, seq
     );
 
+    progress.set_total(attachments_len);
     for attachment in attachments {
         let filepath = account.downloads_dir.join(&attachment.filename);
         debug!("downloading {}…", attachment.filename);
         fs::write(&filepath, &attachment.content)
             .context(format!("cannot download attachment {:?}", filepath))?;
+        progress.inc(1)?;
     }
+    progress.finish();
 
     output.print(format!(
         "{} attachment(s) successfully downloaded to {:?}",
@@ -55,230 +101,1316 @@ pub fn attachments<OutputService: OutputServiceInterface, ImapService: ImapServi
     ))
 }
 
+/// Print the number of messages of the given mailbox, or of its unseen ones. The JSON output
+/// gives both.
+pub fn count<OutputService: OutputServiceInterface>(
+    unseen: bool,
+    mbox: &Mbox,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let status = backend.get_mbox_status(mbox)?;
+    debug!("status: {:?}", status);
+    if output.is_json() {
+        output.print(status)
+    } else if unseen {
+        output.print(status.unseen)
+    } else {
+        output.print(status.messages)
+    }
+}
+
 /// Copy a message from a mailbox to another.
-pub fn copy<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
-    seq: &str,
+pub fn copy<OutputService: OutputServiceInterface>(
+    seq_range: &str,
     mbox: Option<&str>,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
     let mbox = Mbox::try_from(mbox)?;
-    let msg = imap.find_raw_msg(&seq)?;
-    let flags = Flags::try_from(vec![Flag::Seen])?;
-    imap.append_raw_msg_with_flags(&mbox, &msg, flags)?;
+    backend.copy_msgs(seq_range, &mbox)?;
     output.print(format!(
-        r#"Message {} successfully copied to folder "{}""#,
-        seq, mbox
+        r#"Message(s) {} successfully copied to folder "{}""#,
+        seq_range, mbox
     ))
 }
 
-/// Delete messages matching the given sequence range.
-pub fn delete<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+/// Pipe the given message into the given command, as raw or as decoded text parts.
+pub fn pipe(seq: &str, cmd: &str, text: bool, backend: &mut dyn Backend) -> Result<()> {
+    let input = if text {
+        backend.get_msg(seq)?.join_text_parts().into_bytes()
+    } else {
+        backend.get_raw_msg(seq)?
+    };
+    stream_cmd(cmd, &input).context(format!("cannot pipe message {}", seq))
+}
+
+/// Resend the given message unchanged to the given addresses.
+pub fn bounce<OutputService: OutputServiceInterface>(
     seq: &str,
+    addrs: Vec<&str>,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let raw_msg = backend.get_raw_msg(seq)?;
+    msg_bounce::bounce(&raw_msg, &addrs, account, sender)?;
+    output.print(format!(
+        "Message {} successfully bounced to {}",
+        seq,
+        addrs.join(", ")
+    ))
+}
+
+/// Apply the patches of the given messages with `git am`, in the order of the series.
+pub fn am<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    dir: Option<&str>,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let mut msgs = vec![];
+    for (id, raw_msg) in backend.peek_raw_msgs(seq_range)? {
+        let parsed =
+            mailparse::parse_mail(&raw_msg).context(format!("cannot parse message {}", id))?;
+        msgs.push((Msg::try_from(&parsed)?, raw_msg));
+    }
+    let patches = msg_patch::series(msgs);
+    if patches.is_empty() {
+        return Err(anyhow!(
+            r#"cannot find patches in messages "{}""#,
+            seq_range
+        ));
+    }
+    msg_patch::am(&patches, dir)?;
+    output.print(format!("{} patch(es) successfully applied", patches.len()))
+}
+
+/// Archive messages matching the given sequence range in the account archive folder. When the
+/// folder name contains date placeholders, messages are spread across one folder per date.
+///
+/// On Gmail, where folders are labels and every message stays in All Mail, messages are archived
+/// by removing their Inbox label instead, the archive folder being added as a label unless it is a
+/// Gmail system folder.
+///
+/// Refuses when some messages are on hold, unless `override_hold` is true.
+pub fn archive<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    override_hold: bool,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    msg_hold::check_hold(seq_range, "archive", override_hold, account, backend)?;
+
+    let folder = &account.archive_folder;
+    let has_placeholders = folder.contains("{year}") || folder.contains("{month}");
+
+    if !has_placeholders && backend.get_caps()?.iter().any(|cap| cap == GMAIL_EXT) {
+        let is_system_folder =
+            folder.starts_with("[Gmail]/") || folder.starts_with("[Google Mail]/");
+        if !is_system_folder {
+            backend.add_labels(seq_range, &[folder.to_owned()])?;
+        }
+        backend.remove_labels(seq_range, &[String::from("\\Inbox")])?;
+        return output.print(format!(
+            "Message(s) {} successfully archived (Inbox label removed)",
+            seq_range
+        ));
+    }
+
+    if !has_placeholders {
+        let mbox = Mbox::from(folder.as_str());
+        backend.create_mbox(&mbox)?;
+        backend.move_msg(seq_range, &mbox)?;
+    } else {
+        let mut folders: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (id, date) in backend.get_internal_dates(seq_range)? {
+            let folder = folder
+                .replace("{year}", &date.format("%Y").to_string())
+                .replace("{month}", &date.format("%m").to_string());
+            folders.entry(folder).or_default().push(id.to_string());
+        }
+
+        // Messages are copied first and deleted all at once, so that sequence numbers do not
+        // shift between two folders. Only the archived messages are expunged, not the other
+        // deleted messages of the mailbox.
+        for (folder, ids) in folders.iter() {
+            let mbox = Mbox::from(folder.as_str());
+            backend.create_mbox(&mbox)?;
+            backend.copy_msgs(&ids.join(","), &mbox)?;
+        }
+        let flags = Flags::try_from(vec![Flag::Deleted])?;
+        backend.add_flags(seq_range, &flags)?;
+        if !backend.expunge_msgs(seq_range)? {
+            warn!(
+                "cannot expunge the archived message(s) {} only, they are left flagged as deleted",
+                seq_range
+            );
+        }
+    }
+
+    output.print(format!(
+        r#"Message(s) {} successfully archived to folder "{}""#,
+        seq_range, folder
+    ))
+}
+
+/// Pipe the messages matching the given sequence range to the given learn command, one by one.
+fn learn(seq_range: &str, cmd: &str, backend: &mut dyn Backend) -> Result<()> {
+    for (id, raw_msg) in backend.peek_raw_msgs(seq_range)? {
+        debug!("pipe message {} to {:?}", id, cmd);
+        pipe_cmd(cmd, &raw_msg).context(format!("cannot learn from message {}", id))?;
+    }
+    Ok(())
+}
+
+/// Report messages matching the given sequence range as spam to the account `spam-learn-cmd`,
+/// then move them to the junk folder. Refuses to move messages on hold, unless `override_hold`
+/// is true.
+pub fn spam<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    override_hold: bool,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if mbox.name != account.junk_folder {
+        msg_hold::check_hold(seq_range, "move", override_hold, account, backend)?;
+    }
+    learn(seq_range, &account.spam_learn_cmd, backend)?;
+    if mbox.name == account.junk_folder {
+        return output.print(format!(
+            "Message(s) {} successfully reported as spam",
+            seq_range
+        ));
+    }
+    let junk = Mbox::from(account.junk_folder.as_str());
+    backend.create_mbox(&junk)?;
+    backend.move_msg(seq_range, &junk)?;
+    output.print(format!(
+        r#"Message(s) {} successfully reported as spam and moved to folder "{}""#,
+        seq_range, junk
+    ))
+}
+
+/// Report messages matching the given sequence range as legitimate to the account
+/// `ham-learn-cmd`. Messages of the junk folder are moved back to the inbox, unless they are on
+/// hold and `override_hold` is false.
+pub fn ham<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    override_hold: bool,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if mbox.name == account.junk_folder {
+        msg_hold::check_hold(seq_range, "move", override_hold, account, backend)?;
+    }
+    learn(seq_range, &account.ham_learn_cmd, backend)?;
+    if mbox.name != account.junk_folder {
+        return output.print(format!(
+            "Message(s) {} successfully reported as ham",
+            seq_range
+        ));
+    }
+    let inbox = Mbox::from("INBOX");
+    backend.move_msg(seq_range, &inbox)?;
+    output.print(format!(
+        r#"Message(s) {} successfully reported as ham and moved to folder "{}""#,
+        seq_range, inbox
+    ))
+}
+
+/// Delete messages matching the given sequence range. Messages are moved to the trash folder
+/// when one is configured, unless they already are in it or `permanent` is true.
+/// Refuses when some messages are on hold, unless `override_hold` is true.
+pub fn delete<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    permanent: bool,
+    override_hold: bool,
+    mbox: &Mbox,
+    account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
+    msg_hold::check_hold(seq_range, "delete", override_hold, account, backend)?;
+
+    if let Some(ref trash) = account.trash_folder {
+        if !permanent && trash != &mbox.name {
+            let trash = Mbox::from(trash.as_str());
+            backend.create_mbox(&trash)?;
+            backend.move_msg(seq_range, &trash)?;
+            return output.print(format!(
+                r#"Message(s) {} successfully moved to trash folder "{}""#,
+                seq_range, trash
+            ));
+        }
+    }
+
     let flags = Flags::try_from(vec![Flag::Seen, Flag::Deleted])?;
-    imap.add_flags(seq, &flags)?;
-    imap.expunge()?;
-    output.print(format!(r#"Message(s) {} successfully deleted"#, seq))
+    backend.add_flags(seq_range, &flags)?;
+    msg_hold::expunge_msgs(seq_range, override_hold, account, backend)?;
+    output.print(format!(r#"Message(s) {} successfully deleted"#, seq_range))
+}
+
+/// Put the messages matching the given sequence range on hold, or release them.
+pub fn hold<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    release: bool,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let flags = Flags::from(vec![account.hold_keyword.as_str()]);
+    if release {
+        backend.remove_flags(seq_range, &flags)?;
+        output.print(format!(
+            r#"Message(s) {} successfully released from hold"#,
+            seq_range
+        ))
+    } else {
+        backend.add_flags(seq_range, &flags)?;
+        output.print(format!(
+            r#"Message(s) {} successfully put on hold"#,
+            seq_range
+        ))
+    }
 }
 
-/// Forward the given message UID from the selected mailbox.
-pub fn forward<
-    OutputService: OutputServiceInterface,
-    ImapService: ImapServiceInterface,
-    SmtpService: SmtpServiceInterface,
->(
+/// Forward the given message UID from the selected mailbox. Several messages can be forwarded
+/// at once as attachments.
+pub fn forward<OutputService: OutputServiceInterface>(
     seq: &str,
-    attachments_paths: Vec<&str>,
+    opts: ForwardOpts,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
-    smtp: &mut SmtpService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
 ) -> Result<()> {
-    imap.find_msg(seq)?
-        .into_forward(account)?
-        .add_attachments(attachments_paths)?
-        .edit_with_editor(account, output, imap, smtp)
+    let ForwardOpts {
+        attachments_paths,
+        headers,
+        as_attachment,
+        identity,
+        receipt,
+    } = opts;
+    let account = account.with_identity(identity)?;
+    let account = account.as_ref();
+    let mut msg = if as_attachment {
+        let msgs = backend.get_msgs(seq)?;
+        let raw_msgs = backend.get_raw_msgs(seq)?;
+        Msg::forward_as_attachments(msgs.into_iter().zip(raw_msgs).collect(), account)?
+    } else if seq.contains(|c| c == ',' || c == ':' || c == '*') {
+        return Err(anyhow!(
+            r#"cannot forward messages "{}": several messages can only be forwarded as attachments"#,
+            seq
+        ));
+    } else {
+        backend.get_msg(seq)?.into_forward(account)?
+    };
+    if receipt {
+        msg.request_receipt(account);
+    }
+    msg.add_attachments(attachments_paths)?
+        .add_headers(headers)?
+        .edit_with_editor(account, output, backend, sender)
 }
 
-/// List paginated messages from the selected mailbox.
-pub fn list<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
-    page_size: Option<usize>,
-    page: usize,
+/// Delete the duplicate messages of the selected mailbox, or of the given one. With `dry_run`,
+/// only list them. Messages are fetched by batches without being marked as seen.
+pub fn dedup<OutputService: OutputServiceInterface>(
+    opts: DedupOpts,
+    mbox: &Mbox,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
-    let page_size = page_size.unwrap_or(account.default_page_size);
-    trace!("page size: {}", page_size);
+    let DedupOpts {
+        target,
+        dry_run,
+        override_hold,
+        use_seq,
+    } = opts;
+    let target_mbox = target.map(Mbox::from);
+    let mut target_backend = target_mbox
+        .as_ref()
+        .map(|mbox| build_backend(account, mbox, use_seq));
+    let is_target = target_backend.is_some();
+    let (mbox, backend) = match (target_mbox.as_ref(), target_backend.as_mut()) {
+        (Some(mbox), Some(backend)) => (mbox, backend.as_mut()),
+        _ => (mbox, backend),
+    };
 
-    let msgs = imap.get_msgs(&page_size, &page)?;
-    trace!("messages: {:#?}", msgs);
-    output.print(msgs)
+    if backend.list_envelopes(&1, &0)?.is_empty() {
+        return output.print(format!(r#"No message found in "{}""#, mbox.name));
+    }
+    let ids: Vec<u32> = backend
+        .get_flags("1:*")?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    let mut pool = FetchPool::new(account, mbox, use_seq, account.fetch_jobs);
+    let mut fingerprints = Vec::with_capacity(ids.len());
+    let mut progress = output.progress(&format!(r#"Scanning "{}""#, mbox.name), ids.len());
+    for batch in ids.chunks(DEDUP_BATCH_SIZE) {
+        for (id, raw_msg) in pool.peek_raw_msgs(batch, EXPORT_CHUNK_SIZE, backend)? {
+            fingerprints.push(Fingerprint::from_raw(id, &raw_msg)?);
+        }
+        progress.inc(batch.len())?;
+    }
+    progress.finish();
+    let duplicates = msg_dedup::find_duplicates(fingerprints);
+    debug!("{} duplicate(s) found", duplicates.len());
+
+    if duplicates.is_empty() {
+        return output.print(format!(r#"No duplicate found in "{}""#, mbox.name));
+    }
+
+    if dry_run {
+        let lines: Vec<String> = duplicates
+            .iter()
+            .map(|duplicate| {
+                format!(
+                    "Message {} duplicates message {}: {}",
+                    duplicate.id, duplicate.original_id, duplicate.subject
+                )
+            })
+            .collect();
+        return output.print(lines.join("\n"));
+    }
+
+    let ids: Vec<String> = duplicates
+        .iter()
+        .map(|duplicate| duplicate.id.to_string())
+        .collect();
+    delete(
+        &ids.join(","),
+        false,
+        override_hold,
+        mbox,
+        account,
+        output,
+        backend,
+    )?;
+    if is_target {
+        backend.logout()?;
+    }
+    Ok(())
 }
 
-/// Parse and edit a message from a [mailto] URL string.
-///
-/// [mailto]: https://en.wikipedia.org/wiki/Mailto
-pub fn mailto<
-    OutputService: OutputServiceInterface,
-    ImapService: ImapServiceInterface,
-    SmtpService: SmtpServiceInterface,
->(
-    url: &Url,
+/// Print the unified diff between the given messages, without marking them as seen.
+pub fn diff<OutputService: OutputServiceInterface>(
+    seq: &str,
+    other_seq: &str,
+    headers: bool,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let mut texts = vec![];
+    for seq in [seq, other_seq].iter() {
+        let (_, raw_msg) = backend
+            .peek_raw_msgs(seq)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!(r#"cannot find message "{}""#, seq))?;
+        let parsed = mailparse::parse_mail(&raw_msg)
+            .context(format!(r#"cannot parse message "{}""#, seq))?;
+        let body = Msg::try_from(&parsed)?.join_text_parts();
+        texts.push(msg_diff::text(&parsed, &body, headers));
+    }
+
+    let diff = msg_diff::diff(
+        &texts[0],
+        &texts[1],
+        &format!("message {}", seq),
+        &format!("message {}", other_seq),
+    );
+    if diff.is_empty() {
+        return output.print(format!(
+            "No difference found between messages {} and {}",
+            seq, other_seq
+        ));
+    }
+    output.print(PrintableMsg(diff))
+}
+
+/// Export messages to the given directory, the downloads directory by default, as one `.eml`
+/// file per message or as a single mbox file. Messages are fetched by the fetch jobs of the
+/// account, without marking them as seen.
+pub fn export<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    opts: ExportOpts,
+    mbox: &Mbox,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
-    smtp: &mut SmtpService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
-    let to: Vec<lettre::message::Mailbox> = url
-        .path()
-        .split(";")
-        .filter_map(|s| s.parse().ok())
+    let ExportOpts {
+        format,
+        dir,
+        use_seq,
+    } = opts;
+    let dir = dir
+        .map(PathBuf::from)
+        .unwrap_or_else(|| account.downloads_dir.to_owned());
+    fs::create_dir_all(&dir).context(format!("cannot create export directory {:?}", dir))?;
+
+    let ids: Vec<u32> = backend
+        .get_flags(seq_range)?
+        .into_iter()
+        .map(|(id, _)| id)
         .collect();
-    let mut cc = Vec::new();
-    let mut bcc = Vec::new();
-    let mut subject = Cow::default();
-    let mut body = Cow::default();
-
-    for (key, val) in url.query_pairs() {
-        match key.as_bytes() {
-            b"cc" => {
-                cc.push(val.parse()?);
+    debug!("{} message(s) to export", ids.len());
+    let raw_msgs = FetchPool::new(account, mbox, use_seq, account.fetch_jobs).peek_raw_msgs(
+        &ids,
+        EXPORT_CHUNK_SIZE,
+        backend,
+    )?;
+
+    match format {
+        ExportFormat::Eml => {
+            for (id, raw_msg) in raw_msgs.iter() {
+                let path = dir.join(format!("{}.eml", id));
+                fs::write(&path, raw_msg)
+                    .context(format!("cannot export message to {:?}", path))?;
             }
-            b"bcc" => {
-                bcc.push(val.parse()?);
+        }
+        ExportFormat::Mbox => {
+            let path = dir.join(msg_export::mbox_filename(&mbox.name));
+            let mut content = vec![];
+            for (id, raw_msg) in raw_msgs.iter() {
+                let parsed = mailparse::parse_mail(raw_msg)
+                    .context(format!("cannot parse message {}", id))?;
+                content.extend(msg_export::to_mbox_entry(&Msg::try_from(&parsed)?, raw_msg));
             }
-            b"subject" => {
-                subject = val;
+            fs::write(&path, content).context(format!("cannot export messages to {:?}", path))?;
+        }
+    }
+
+    output.print(format!(
+        "{} message(s) successfully exported to {:?}",
+        raw_msgs.len(),
+        dir
+    ))
+}
+
+/// Import messages from the given file or directory to the selected mailbox.
+pub fn import<OutputService: OutputServiceInterface>(
+    path: &str,
+    mbox: &Mbox,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let msgs = msg_export::read_msgs(&PathBuf::from(path))?;
+    debug!("{} message(s) to import", msgs.len());
+    let count = msgs.len();
+    for msg in msgs {
+        backend.append_raw(mbox, &msg.raw, msg.flags)?;
+    }
+
+    output.print(format!(
+        r#"{} message(s) successfully imported to "{}""#,
+        count, mbox.name
+    ))
+}
+
+/// List paginated messages from the selected mailbox, optionally sorted by the given criteria.
+/// When interactive, the user picks one of them instead.
+pub fn list<OutputService: OutputServiceInterface>(
+    opts: ListOpts,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let ListOpts {
+        page_size,
+        page,
+        sort,
+        query,
+        interactive,
+        lists,
+        patches,
+        relative_dates,
+        columns,
+    } = opts;
+    if let Some(name) = query {
+        let query = account
+            .queries
+            .get(name)
+            .ok_or_else(|| anyhow!(r#"cannot find saved query "{}""#, name))?;
+        debug!(r#"saved query "{}": {}"#, name, query);
+        let query = SearchQuery::try_from(query.as_str())
+            .context(format!(r#"cannot parse saved query "{}""#, name))?
+            .to_imap();
+        return search(
+            query,
+            None,
+            page_size,
+            page,
+            interactive,
+            relative_dates,
+            columns,
+            mbox,
+            account,
+            output,
+            backend,
+            sender,
+        );
+    }
+
+    let page_size = page_size.unwrap_or(account.default_page_size);
+    trace!("page size: {}", page_size);
+
+    if output.is_ndjson()
+        && !interactive
+        && !lists
+        && !patches
+        && !account.show_spam_score
+        && sort.is_none()
+    {
+        return backend.stream_envelopes(&page_size, &page, &mut |envelope| {
+            output.print_ndjson(envelope)
+        });
+    }
+
+    let mut msgs = match sort {
+        _ if patches => {
+            let mut candidates = backend.search_envelopes(msg_patch::PATCH_QUERY, &0, &0)?;
+            candidates
+                .0
+                .retain(|envelope| msg_patch::is_patch(&envelope.subject));
+            Envelopes(
+                candidates
+                    .0
+                    .into_iter()
+                    .skip(page * page_size)
+                    .take(page_size)
+                    .collect(),
+            )
+        }
+        Some(sort) => {
+            let sort = SortCriteria::try_from(sort)?;
+            trace!("sort criteria: {:?}", sort);
+            backend.list_sorted_envelopes(&sort, &page_size, &page)?
+        }
+        None => backend.list_envelopes(&page_size, &page)?,
+    };
+    if lists || account.show_spam_score {
+        fill_headers(&mut msgs, lists, account.show_spam_score, backend)?;
+    }
+    format_dates(&mut msgs, relative_dates, account);
+    trace!("messages: {:#?}", msgs);
+    if interactive {
+        pick(&msgs, mbox, account, output, backend, sender)
+    } else if let Some(columns) = columns {
+        output.print_items(ColumnedEnvelopes(&columns, msgs))
+    } else {
+        output.print_items(msgs)
+    }
+}
+
+/// Format the dates of the given envelopes shown in listings, with the format of the account or
+/// relatively to now.
+fn format_dates(envelopes: &mut Envelopes, relative: bool, account: &Account) {
+    for envelope in envelopes.0.iter_mut() {
+        envelope.format_date(&account.datetime_fmt, relative);
+    }
+}
+
+/// Fill in the mailing lists and/or the spam scores of the given envelopes, from the headers of
+/// their messages.
+fn fill_headers(
+    envelopes: &mut Envelopes,
+    lists: bool,
+    scores: bool,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if envelopes.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<String> = envelopes
+        .iter()
+        .map(|envelope| envelope.id.to_string())
+        .collect();
+    let headers: BTreeMap<u32, Vec<u8>> = backend
+        .get_raw_headers(&ids.join(","))?
+        .into_iter()
+        .collect();
+    for envelope in envelopes.0.iter_mut() {
+        let headers = match headers
+            .get(&envelope.id)
+            .and_then(|raw| mailparse::parse_headers(raw).ok())
+        {
+            Some((headers, _)) => headers,
+            None => continue,
+        };
+        if lists {
+            envelope.list = MailingList::from_headers(&headers).map(|list| list.name().to_owned());
+        }
+        if scores {
+            envelope.spam_score = envelope_entity::spam_score(&headers);
+        }
+    }
+    Ok(())
+}
+
+/// Append the new messages of the mailbox to the journal of the given directory, every given
+/// number of seconds when watching.
+pub fn journal<OutputService: OutputServiceInterface>(
+    dir: &str,
+    interval: Option<u64>,
+    mbox: &Mbox,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let mut journal = Journal::open(&PathBuf::from(dir))?;
+    loop {
+        let uid_validity = backend.get_uid_validity()?;
+        if let Some(prev) = journal.changed_uid_validity(&mbox.name, uid_validity) {
+            eprintln!(
+                r#"warning: UIDVALIDITY of mailbox "{}" changed ({} → {}), journaling it again from its first message"#,
+                mbox.name,
+                prev,
+                uid_validity.unwrap_or_default()
+            );
+        }
+        let last_id = journal
+            .last_id(&mbox.name, uid_validity)
+            .unwrap_or_default();
+        debug!("last journaled message: {}", last_id);
+        // The last message always matches `n:*`, even when its UID is lower than n.
+        let query = format!("UID {}:*", last_id + 1);
+        let ids: Vec<String> = backend
+            .search_envelopes(&query, &0, &0)?
+            .iter()
+            .filter(|envelope| envelope.id > last_id)
+            .map(|envelope| envelope.id.to_string())
+            .collect();
+
+        let mut appended = 0;
+        if !ids.is_empty() {
+            let mut raw_msgs = backend.peek_raw_msgs(&ids.join(","))?;
+            raw_msgs.sort_by_key(|(id, _)| *id);
+            for (id, raw_msg) in raw_msgs {
+                let parsed_msg = mailparse::parse_mail(&raw_msg)
+                    .context(format!("cannot parse message {}", id))?;
+                let mut msg = Msg::try_from(&parsed_msg)?;
+                msg.id = id;
+                journal.append(&mbox.name, uid_validity, &msg, &raw_msg)?;
+                appended += 1;
             }
-            b"body" => {
-                body = val;
+        }
+
+        let interval = match interval {
+            Some(interval) => interval,
+            None => {
+                return output.print(format!(
+                    "{} message(s) successfully appended to journal {:?}",
+                    appended, dir
+                ))
             }
-            _ => (),
+        };
+        if appended > 0 {
+            output.print(format!(
+                "{} message(s) successfully appended to journal {:?}",
+                appended, dir
+            ))?;
         }
+        thread::sleep(Duration::from_secs(interval));
     }
+}
 
-    let mut msg = Msg::default();
-
-    msg.from = Some(vec![account.address().parse()?]);
-    msg.to = if to.is_empty() { None } else { Some(to) };
-    msg.cc = if cc.is_empty() { None } else { Some(cc) };
-    msg.bcc = if bcc.is_empty() { None } else { Some(bcc) };
-    msg.subject = subject.into();
-    msg.parts.push(Part::TextPlain(TextPlainPart {
-        content: body.into(),
-    }));
-    msg.edit_with_editor(account, output, imap, smtp)
+/// Edit a message from the fields of the given [mailto] URL, then send or save it.
+///
+/// [mailto]: https://datatracker.ietf.org/doc/html/rfc6068
+pub fn mailto<OutputService: OutputServiceInterface>(
+    url: &str,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let mailto = Mailto::parse(url)?;
+    trace!("mailto: {:#?}", mailto);
+    let tpl = Tpl::from_msg(mailto.tpl_override(), &Msg::default(), account);
+    Msg::edit_tpl_with_editor(tpl, account, output, backend, sender)
 }
 
 /// Move a message from a mailbox to another.
-pub fn move_<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
-    // The sequence number of the message to move
-    seq: &str,
-    // The mailbox to move the message in
+pub fn move_<OutputService: OutputServiceInterface>(
+    // The sequence range of the messages to move
+    seq_range: &str,
+    // The mailbox to move the messages in
     mbox: Option<&str>,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
-    // Copy the message to targetted mailbox
     let mbox = Mbox::try_from(mbox)?;
-    let msg = imap.find_raw_msg(&seq)?;
-    let flags = Flags::try_from(vec![Flag::Seen])?;
-    imap.append_raw_msg_with_flags(&mbox, &msg, flags)?;
-
-    // Delete the original message
-    let flags = Flags::try_from(vec![Flag::Seen, Flag::Deleted])?;
-    imap.add_flags(seq, &flags)?;
-    imap.expunge()?;
-
+    backend.move_msg(seq_range, &mbox)?;
     output.print(format!(
-        r#"Message {} successfully moved to folder "{}""#,
-        seq, mbox
+        r#"Message(s) {} successfully moved to folder "{}""#,
+        seq_range, mbox
     ))
 }
 
-/// Read a message by its sequence number.
-pub fn read<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
-    seq: &str,
-    // TODO: use the mime to select the right body
-    _mime: String,
-    raw: bool,
+/// Read messages matching the given sequence range, one after the other.
+pub fn read<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    opts: ReadOpts,
+    account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
 ) -> Result<()> {
+    let ReadOpts {
+        // TODO: use the mime to select the right body
+        mime: _,
+        raw,
+        sandbox,
+        headers,
+        max_size,
+    } = opts;
+    // Only the headers are fetched, without flagging the messages as seen.
+    if headers {
+        let headers: Vec<String> = backend
+            .get_raw_headers(seq_range)?
+            .iter()
+            .map(|(_, raw_headers)| String::from_utf8_lossy(raw_headers).into_owned())
+            .collect();
+        return output.print(PrintableMsg(headers.join("\n")));
+    }
+
+    // Partially fetched messages are peeked, as they are not entirely read.
+    if let Some(max_size) = max_size {
+        let texts: Vec<String> = backend
+            .peek_partial_raw_msgs(seq_range, max_size)?
+            .iter()
+            .map(|(_, raw_msg, size)| {
+                let mut text = if raw {
+                    String::from_utf8_lossy(raw_msg).into_owned()
+                } else {
+                    mailparse::parse_mail(raw_msg)
+                        .ok()
+                        .and_then(|parsed_mail| Msg::try_from(&parsed_mail).ok())
+                        .map(|msg| msg.join_text_parts())
+                        .unwrap_or_else(|| String::from_utf8_lossy(raw_msg).into_owned())
+                };
+                if raw_msg.len() < *size {
+                    text.push_str(&format!(
+                        "\n\n[Message truncated: {} of {} bytes fetched]",
+                        raw_msg.len(),
+                        size
+                    ));
+                }
+                text
+            })
+            .collect();
+        return output.print(PrintableMsg(texts.join("\n")));
+    }
+
     if raw {
-        let msg = String::from_utf8(imap.find_raw_msg(&seq)?)?;
-        output.print(PrintableMsg(msg))
+        let msgs = backend
+            .get_raw_msgs(seq_range)?
+            .into_iter()
+            .map(String::from_utf8)
+            .collect::<Result<Vec<_>, _>>()?;
+        return output.print(PrintableMsg(msgs.join("\n")));
+    }
+
+    // Sandboxed messages are peeked, so that they stay unseen, and their receipts are ignored.
+    if sandbox {
+        let texts = backend
+            .peek_raw_msgs(seq_range)?
+            .iter()
+            .map(|(_, raw_msg)| msg_sandbox::render(raw_msg))
+            .collect::<Result<Vec<_>>>()?;
+        return output.print(PrintableMsg(texts.join("\n")));
+    }
+
+    let msgs = backend.get_msgs(seq_range)?;
+    let mut texts: Vec<String> = vec![];
+    for msg in msgs.iter() {
+        let mut text = msg.join_text_parts();
+        // S/MIME messages are checked from their raw content, the banner being shown first.
+        if msg_smime::is_smime(msg) {
+            let raw_msgs = backend.peek_raw_msgs(&msg.id.to_string())?;
+            if let Some((_, raw_msg)) = raw_msgs.first() {
+                if let Some(report) = msg_smime::check(raw_msg, account)? {
+                    let content = report
+                        .content
+                        .as_deref()
+                        .and_then(|content| mailparse::parse_mail(content).ok())
+                        .and_then(|parsed_mail| Msg::try_from(&parsed_mail).ok())
+                        .map(|content| content.join_text_parts());
+                    text = format!("{}\n{}", report, content.unwrap_or(text));
+                }
+            }
+        }
+        for summary in msg_ical::summaries(msg) {
+            text.push_str("\n\n");
+            text.push_str(&summary);
+        }
+        texts.push(text);
+    }
+    output.print(PrintableMsg(texts.join("\n")))?;
+    for msg in msgs.iter().filter(|msg| msg_mdn::is_pending(msg)) {
+        handle_receipt(msg, account, output, backend, sender)?;
+    }
+    Ok(())
+}
+
+/// Handle the read receipt asked by the given message, according to the account policy. The
+/// message is flagged as handled whether the receipt is sent or declined.
+fn handle_receipt<OutputService: OutputServiceInterface>(
+    msg: &Msg,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let automatic = match account.read_receipt_policy {
+        ReceiptPolicy::Never => return Ok(()),
+        ReceiptPolicy::Always => true,
+        // Nobody can answer when the output is read by another program.
+        ReceiptPolicy::Ask if output.is_json() || !atty::is(Stream::Stdin) => return Ok(()),
+        ReceiptPolicy::Ask => false,
+    };
+    let send = automatic || {
+        let to = msg
+            .receipt_to
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        loop {
+            match choice::read_receipt(&to) {
+                Ok(ReadReceiptChoice::Send) => break true,
+                Ok(ReadReceiptChoice::Ignore) => break false,
+                Err(err) => println!("{}", err),
+            }
+        }
+    };
+    if send {
+        msg_mdn::send(msg, automatic, account, sender)?;
+    }
+    backend.add_flags(&msg.id.to_string(), &msg_mdn::mdn_sent_flags()?)
+}
+
+/// Reply to the given message UID, as the given identity or else as the identity the message
+/// was sent to.
+pub fn reply<OutputService: OutputServiceInterface>(
+    seq: &str,
+    opts: ReplyOpts,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let ReplyOpts {
+        all,
+        quote_match,
+        attachments_paths,
+        headers,
+        identity,
+        suggest,
+        proposed_times,
+        receipt,
+        to_list,
+    } = opts;
+    let msg = backend.get_msg(seq)?;
+    // Reply as the identity the message was sent to, unless told otherwise.
+    let recipients: Vec<String> = msg
+        .to
+        .iter()
+        .chain(msg.cc.iter())
+        .flatten()
+        .map(|addr| addr.email.to_string())
+        .collect();
+    let identity = identity.or_else(|| {
+        account
+            .find_identity(&recipients)
+            .map(|identity| identity.email.as_str())
+    });
+    let account = account.with_identity(identity)?;
+    let account = account.as_ref();
+    // The time zone of the original message tells the one of the recipient.
+    let recipient_offset = msg.date.map(|date| *date.offset());
+    let list_post = if to_list {
+        let post = msg
+            .list
+            .as_ref()
+            .and_then(|list| list.post.to_owned())
+            .ok_or_else(|| {
+                anyhow!(
+                    "cannot reply to mailing list: message {} has no posting address",
+                    seq
+                )
+            })?;
+        Some(post)
     } else {
-        let msg = imap.find_msg(&seq)?.join_text_parts();
-        output.print(PrintableMsg(msg))
+        None
+    };
+    let mut msg = msg.into_reply(all, quote_match, account)?;
+    if let Some(post) = list_post {
+        msg.to = Some(vec![post.parse().context(format!(
+            r#"cannot parse mailing list address "{}""#,
+            post
+        ))?]);
+        msg.cc = None;
+    }
+    let mut intro = vec![];
+    if suggest {
+        let cmd = account.draft_suggest_cmd.as_deref().ok_or_else(|| {
+            anyhow!(
+                r#"cannot find "draft-suggest-cmd" in account "{}""#,
+                account.name
+            )
+        })?;
+        debug!("draft suggest cmd: {}", cmd);
+        let draft =
+            pipe_cmd(cmd, &backend.get_raw_msg(seq)?).context("cannot suggest reply draft")?;
+        intro.push(String::from_utf8_lossy(&draft).trim_end().to_owned());
+    }
+    if let Some(slots) = proposed_times {
+        intro.push(propose_times(slots, recipient_offset)?);
+    }
+    if !intro.is_empty() {
+        msg.prepend_text(&intro.join("\n\n"));
+    }
+    if receipt {
+        msg.request_receipt(account);
+    }
+    msg.add_attachments(attachments_paths)?
+        .add_headers(headers)?
+        .edit_with_editor(account, output, backend, sender)?;
+    let flags = Flags::try_from(vec![Flag::Answered])?;
+    backend.add_flags(seq, &flags)
+}
+
+/// Rewrite the headers of the given messages: each rewritten message is appended again, with
+/// the flags and the internal date of the original, then the original is deleted. Refuses when
+/// some messages are on hold, unless `override_hold` is true.
+///
+/// Originals are only expunged when the backend can expunge them alone, eg. with UIDPLUS: they
+/// are left flagged as deleted otherwise, so that the other deleted messages stay.
+pub fn rewrite<OutputService: OutputServiceInterface>(
+    seq_range: &str,
+    opts: RewriteOpts,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let RewriteOpts {
+        add,
+        remove,
+        override_hold,
+    } = opts;
+    msg_hold::check_hold(seq_range, "rewrite", override_hold, account, backend)?;
+    let add = add
+        .into_iter()
+        .map(Header::parse)
+        .collect::<Result<Vec<_>>>()?;
+    let remove: Vec<String> = remove.into_iter().map(String::from).collect();
+
+    let raw_msgs = backend.peek_raw_msgs(seq_range)?;
+    let mut flags: BTreeMap<u32, Flags> = backend.get_flags(seq_range)?.into_iter().collect();
+    let dates: BTreeMap<u32, _> = backend.get_internal_dates(seq_range)?.into_iter().collect();
+    debug!("{} message(s) to rewrite", raw_msgs.len());
+
+    let mut ids = vec![];
+    for (id, raw_msg) in raw_msgs {
+        let raw_msg = match msg_rewrite::rewrite(&raw_msg, &add, &remove) {
+            Some(raw_msg) => raw_msg,
+            None => {
+                trace!("message {} left unchanged", id);
+                continue;
+            }
+        };
+        let mut flags = flags.remove(&id).unwrap_or_default();
+        flags.remove(&Flag::Recent);
+        match dates.get(&id) {
+            Some(date) => backend.append_raw_dated(mbox, &raw_msg, flags, *date)?,
+            None => backend.append_raw(mbox, &raw_msg, flags)?,
+        }
+        ids.push(id.to_string());
+    }
+
+    if !ids.is_empty() {
+        let ids = ids.join(",");
+        backend.add_flags(&ids, &Flags::try_from(vec![Flag::Deleted])?)?;
+        // Expunging the whole mailbox would also remove the other deleted messages.
+        if !backend.expunge_msgs(&ids)? {
+            warn!(
+                "cannot expunge the original message(s) {} only, they are left flagged as deleted",
+                ids
+            );
+        }
     }
+    output.print(format!(
+        r#"{} message(s) successfully rewritten in "{}""#,
+        ids.len(),
+        mbox.name
+    ))
 }
 
-/// Reply to the given message UID.
-pub fn reply<
-    OutputService: OutputServiceInterface,
-    ImapService: ImapServiceInterface,
-    SmtpService: SmtpServiceInterface,
->(
+/// Answer the invitation of the given message UID, as the identity the message was sent to.
+pub fn rsvp<OutputService: OutputServiceInterface>(
     seq: &str,
-    all: bool,
-    attachments_paths: Vec<&str>,
+    partstat: PartStat,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
-    smtp: &mut SmtpService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
 ) -> Result<()> {
-    imap.find_msg(seq)?
-        .into_reply(all, account)?
-        .add_attachments(attachments_paths)?
-        .edit_with_editor(account, output, imap, smtp)?;
+    let msg = backend.get_msg(seq)?;
+    let event = msg_ical::find_invitation(&msg)
+        .ok_or_else(|| anyhow!("cannot find invitation in message {}", seq))?;
+    let recipients: Vec<String> = msg
+        .to
+        .iter()
+        .chain(msg.cc.iter())
+        .flatten()
+        .map(|addr| addr.email.to_string())
+        .collect();
+    let identity = account
+        .find_identity(&recipients)
+        .map(|identity| identity.email.as_str());
+    let account = account.with_identity(identity)?;
+    let account = account.as_ref();
+
+    let reply = msg_ical::reply_msg(&msg, &event, partstat, account)?;
+    let mbox = Mbox::from(account.sent_folder.as_str());
+    if account.save_sent_copy {
+        warn_near_quota(&mbox, account, backend);
+    }
+    for sent_msg in msg_split::send(&reply, account, sender)? {
+        if account.save_sent_copy {
+            let flags = Flags::try_from(vec![Flag::Seen])?;
+            backend.append_raw(&mbox, &sent_msg, flags)?;
+        }
+    }
     let flags = Flags::try_from(vec![Flag::Answered])?;
-    imap.add_flags(seq, &flags)
+    backend.add_flags(seq, &flags)?;
+    output.print(format!(
+        r#"Invitation "{}" successfully {}"#,
+        event.summary, partstat
+    ))
+}
+
+/// Unsubscribe from the mailing list of the given message UID.
+pub fn unsubscribe<OutputService: OutputServiceInterface>(
+    seq: &str,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let msg = backend.get_msg(seq)?;
+    let list = msg
+        .list
+        .as_ref()
+        .ok_or_else(|| anyhow!("cannot find mailing list of message {}", seq))?;
+    match list.unsubscribe_target() {
+        Some(UnsubscribeTarget::OneClick(url)) => msg_mailing_list::post_one_click(&url)?,
+        Some(UnsubscribeTarget::Mailto { to, subject, body }) => {
+            let mut unsubscription = Msg::default();
+            unsubscription.from = Some(vec![account.address().parse()?]);
+            unsubscription.to = Some(vec![to
+                .parse()
+                .context(format!(r#"cannot parse unsubscribe address "{}""#, to))?]);
+            unsubscription.subject = subject;
+            unsubscription
+                .parts
+                .push(Part::TextPlain(TextPlainPart { content: body }));
+            msg_split::send(&unsubscription, account, sender)?;
+        }
+        Some(UnsubscribeTarget::Link(url)) => {
+            return output.print(format!(
+                r#"Open {} to unsubscribe from "{}""#,
+                url,
+                list.name()
+            ));
+        }
+        None => {
+            return Err(anyhow!(
+                r#"cannot find how to unsubscribe from "{}""#,
+                list.name()
+            ))
+        }
+    }
+    output.print(format!(
+        r#"Successfully unsubscribed from "{}""#,
+        list.name()
+    ))
 }
 
-/// Save a raw message to the targetted mailbox.
-pub fn save<ImapService: ImapServiceInterface>(
+/// Save a raw message to the targetted mailbox, warning when it is near its quota.
+pub fn save(
     mbox: Option<&str>,
     msg: &str,
-    imap: &mut ImapService,
+    account: &Account,
+    backend: &mut dyn Backend,
 ) -> Result<()> {
     let mbox = Mbox::try_from(mbox)?;
+    let msg = msg_compliance::fix_long_lines(msg.as_bytes())?;
     let flags = Flags::try_from(vec![Flag::Seen])?;
-    imap.append_raw_msg_with_flags(&mbox, msg.as_bytes(), flags)
+    warn_near_quota(&mbox, account, backend);
+    backend.append_raw(&mbox, &msg, flags)
+}
+
+/// Let the user fuzzy-pick one of the given envelopes, then an action to run on its message.
+pub fn pick<OutputService: OutputServiceInterface>(
+    envelopes: &Envelopes,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let items: Vec<String> = envelopes
+        .iter()
+        .map(|envelope| {
+            format!(
+                "{}  {}  {}  {}  {}",
+                envelope.id,
+                envelope.flags.to_symbols_string(),
+                envelope.subject,
+                envelope.sender,
+                envelope.listed_date()
+            )
+        })
+        .collect();
+    let id = match picker::pick(&items)? {
+        Some(i) => envelopes[i].id.to_string(),
+        None => return Ok(()),
+    };
+    debug!("picked message: {}", id);
+
+    match choice::picked_msg()? {
+        PickedMsgChoice::Read => read(
+            &id,
+            String::from("text/plain"),
+            false,
+            false,
+            false,
+            None,
+            account,
+            output,
+            backend,
+            sender,
+        ),
+        PickedMsgChoice::Reply => reply(
+            &id,
+            false,
+            None,
+            vec![],
+            vec![],
+            None,
+            false,
+            None,
+            false,
+            false,
+            account,
+            output,
+            backend,
+            sender,
+        ),
+        PickedMsgChoice::Delete => delete(&id, false, false, mbox, account, output, backend),
+        PickedMsgChoice::Move => {
+            let target = choice::target_mbox()?;
+            move_(&id, Some(&target), output, backend)
+        }
+        PickedMsgChoice::Quit => Ok(()),
+    }
 }
 
-/// Paginate messages from the selected mailbox matching the specified query.
-pub fn search<OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
-    query: String,
-    page_size: Option<usize>,
-    page: usize,
+/// Paginate messages from the selected mailbox matching the specified query, and whose body
+/// contains the given terms if any. When interactive, the user picks one of them instead.
+pub fn search<OutputService: OutputServiceInterface>(
+    opts: SearchOpts,
+    mbox: &Mbox,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
 ) -> Result<()> {
+    let SearchOpts {
+        query,
+        body,
+        page_size,
+        page,
+        interactive,
+        relative_dates,
+        columns,
+    } = opts;
     let page_size = page_size.unwrap_or(account.default_page_size);
     trace!("page size: {}", page_size);
 
-    let msgs = imap.find_msgs(&query, &page_size, &page)?;
+    let mut msgs = match body {
+        Some(body) => {
+            let candidates = backend.search_envelopes(&query, &0, &0)?;
+            debug!("scan bodies of {} message(s)", candidates.0.len());
+            let mut progress = output.progress("Scanning message bodies", candidates.0.len());
+            let matches = msg_body_search::search(
+                &body,
+                candidates.0,
+                mbox,
+                account,
+                backend,
+                &mut |scanned| progress.set(scanned),
+            )?;
+            progress.finish();
+            Envelopes(
+                matches
+                    .into_iter()
+                    .skip(page * page_size)
+                    .take(page_size)
+                    .collect(),
+            )
+        }
+        None => backend.search_envelopes(&query, &page_size, &page)?,
+    };
+    if account.show_spam_score {
+        fill_headers(&mut msgs, false, true, backend)?;
+    }
+    format_dates(&mut msgs, relative_dates, account);
     trace!("messages: {:#?}", msgs);
-    output.print(msgs)
+    if interactive {
+        pick(&msgs, mbox, account, output, backend, sender)
+    } else if let Some(columns) = columns {
+        output.print_items(ColumnedEnvelopes(&columns, msgs))
+    } else {
+        output.print_items(msgs)
+    }
 }
 
-/// Send a raw message.
-pub fn send<
-    OutputService: OutputServiceInterface,
-    ImapService: ImapServiceInterface,
-    SmtpService: SmtpServiceInterface,
->(
+/// Send a raw message. When an idempotency key is given, the message is sent only if no message
+/// was sent with the same key before, according to the delivery log. With the `outbox` option,
+/// a message the sender fails to send is queued instead.
+pub fn send<OutputService: OutputServiceInterface>(
     raw_msg: &str,
+    idempotency_key: Option<&str>,
+    account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
-    smtp: &mut SmtpService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
 ) -> Result<()> {
+    if let Some(key) = idempotency_key {
+        if let Some(entry) = SentLog::load(account)?.find_by_idempotency_key(key) {
+            debug!("message already sent with idempotency key {}", key);
+            return output.print(format!(
+                r#"Message {} already sent with idempotency key "{}", skipping"#,
+                entry.message_id, key
+            ));
+        }
+    }
+
     let raw_msg = if atty::is(Stream::Stdin) || output.is_json() {
         raw_msg.replace("\r", "").replace("\n", "\r\n")
     } else {
@@ -291,31 +1423,236 @@ pub fn send<
             .join("\r\n")
     };
 
+    msg_addr::check(&raw_msg)?;
     let tpl = Tpl(raw_msg.to_string());
     let msg = Msg::try_from(&tpl)?;
+    // Raw messages are sent without asking, the warnings are only logged.
+    for warning in msg.check_recipients(account)? {
+        warn!("{}", warning);
+    }
+    if let Some(mention) = msg.check_attachment_reminder(account)? {
+        warn!(
+            r#"The message mentions an attachment ("{}") but has none"#,
+            mention
+        );
+    }
+    let subject = msg.subject.to_owned();
     let envelope: lettre::address::Envelope = msg.try_into()?;
-    smtp.send_raw_msg(&envelope, raw_msg.as_bytes())?;
+    let raw_msg = msg_compliance::fix_long_lines(raw_msg.as_bytes())?;
+    let recipients: Vec<String> = envelope.to().iter().map(ToString::to_string).collect();
+    msg_hook::pre_send_raw(account, &raw_msg)?;
+    if let Err(err) = sender.send_raw(&envelope, &raw_msg) {
+        let event = WebhookEvent::send_failure(&account.name, &subject, recipients, &err);
+        webhook::emit(account, &event);
+        metrics::incr(account, Metric::SendErrors, 1);
+        if !account.outbox {
+            return Err(err);
+        }
+        let item = Outbox::push(account, &envelope, &raw_msg, &err)?;
+        return output.print(format!(
+            "Cannot send message, queued in the outbox as {}: {:#}",
+            item.id, err
+        ));
+    }
     debug!("message sent!");
+    let event = WebhookEvent::send_success(&account.name, &subject, recipients.clone());
+    SentLog::record(
+        account,
+        &raw_msg,
+        recipients,
+        sender.last_response(),
+        idempotency_key,
+    );
+    webhook::emit(account, &event);
+    metrics::incr(account, Metric::SentMsgs, 1);
+    msg_hook::post_send(account, &raw_msg);
 
     // Save message to sent folder
-    let mbox = Mbox::from("Sent");
+    if !account.save_sent_copy {
+        return Ok(());
+    }
+    let mbox = Mbox::from(account.sent_folder.as_str());
     let flags = Flags::try_from(vec![Flag::Seen])?;
-    imap.append_raw_msg_with_flags(&mbox, raw_msg.as_bytes(), flags)
+    warn_near_quota(&mbox, account, backend);
+    backend.append_raw(&mbox, &raw_msg, flags)
+}
+
+/// Summarize a message, or the thread it belongs to, with the account summarize command.
+pub fn summarize<OutputService: OutputServiceInterface>(
+    seq: &str,
+    thread: bool,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let cmd = account.summarize_cmd.as_deref().ok_or_else(|| {
+        anyhow!(
+            r#"cannot find "summarize-cmd" in account "{}""#,
+            account.name
+        )
+    })?;
+    let msgs = if thread {
+        let seq_range = backend
+            .get_thread(seq)?
+            .iter()
+            .map(|envelope| envelope.id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        backend.get_msgs(&seq_range)?
+    } else {
+        vec![backend.get_msg(seq)?]
+    };
+    let summary = msg_summary::summarize(cmd, &msg_summary::summary_input(&msgs))?;
+    output.print(summary)
+}
+
+/// List messages of the thread the given message sequence number belongs to, or compile them
+/// into a digest message to forward.
+pub fn thread<OutputService: OutputServiceInterface>(
+    seq: &str,
+    digest: bool,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let msgs = backend.get_thread(seq)?;
+    trace!("messages: {:#?}", msgs);
+    if !digest {
+        return output.print_items(msgs);
+    }
+
+    let seq_range = msgs
+        .iter()
+        .map(|envelope| envelope.id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut msgs = backend.get_msgs(&seq_range)?;
+    let content = msg_digest::digest(&mut msgs);
+    let subject = msgs
+        .first()
+        .map(|msg| msg_utils::forward_subject(&msg.subject))
+        .unwrap_or_default();
+    let msg = Msg {
+        from: Some(vec![account.address().parse()?]),
+        to: Some(vec![]),
+        subject,
+        parts: Parts(vec![Part::TextPlain(TextPlainPart { content })]),
+        ..Msg::default()
+    };
+    msg.edit_with_editor(account, output, backend, sender)
+}
+
+/// Build the block proposing the given time slots, also given in the time zone of the
+/// recipient when known.
+fn propose_times(slots: &str, recipient_offset: Option<FixedOffset>) -> Result<String> {
+    let now = Local::now();
+    let slots = msg_schedule::parse_slots(slots, now.naive_local())?;
+    Ok(msg_schedule::format_slots(
+        &slots,
+        *now.offset(),
+        recipient_offset,
+    ))
 }
 
 /// Compose a new message.
-pub fn write<
-    OutputService: OutputServiceInterface,
-    ImapService: ImapServiceInterface,
-    SmtpService: SmtpServiceInterface,
->(
-    attachments_paths: Vec<&str>,
+pub fn write<OutputService: OutputServiceInterface>(
+    opts: WriteOpts,
     account: &Account,
     output: &OutputService,
-    imap: &mut ImapService,
-    smtp: &mut SmtpService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
 ) -> Result<()> {
-    Msg::default()
-        .add_attachments(attachments_paths)?
-        .edit_with_editor(account, output, imap, smtp)
+    let WriteOpts {
+        attachments_paths,
+        headers,
+        identity,
+        proposed_times,
+        receipt,
+    } = opts;
+    let account = account.with_identity(identity)?;
+    let account = account.as_ref();
+    let mut msg = Msg::default();
+    if let Some(slots) = proposed_times {
+        msg.prepend_text(&propose_times(slots, None)?);
+    }
+    if receipt {
+        msg.request_receipt(account);
+    }
+    msg.add_attachments(attachments_paths)?
+        .add_headers(headers)?
+        .edit_with_editor(account, output, backend, sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::backend::backend_stub::BackendStub, output::OutputService};
+
+    fn account() -> Account {
+        Account {
+            hold_keyword: String::from("$Hold"),
+            archive_folder: String::from("Archive"),
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn it_should_refuse_to_delete_held_messages() {
+        let account = account();
+        let output = OutputService::default().captured();
+        let mut backend = BackendStub::new(vec![(1, vec!["$Hold"]), (2, vec![])]);
+        let mbox = Mbox::from("INBOX");
+
+        assert!(delete("1:2", true, false, &mbox, &account, &output, &mut backend).is_err());
+        assert!(backend.expunged.is_empty());
+
+        delete("1:2", true, true, &mbox, &account, &output, &mut backend).unwrap();
+        assert_eq!(vec![1, 2], backend.expunged);
+        assert_eq!(
+            "Message(s) 1:2 successfully deleted\n",
+            output.take_captured()
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_to_trash_held_messages() {
+        let account = Account {
+            trash_folder: Some(String::from("Trash")),
+            ..account()
+        };
+        let output = OutputService::default().captured();
+        let mut backend = BackendStub::new(vec![(1, vec!["$Hold"])]);
+        let mbox = Mbox::from("INBOX");
+
+        assert!(delete("1", false, false, &mbox, &account, &output, &mut backend).is_err());
+        assert!(backend.moved.is_empty());
+    }
+
+    #[test]
+    fn it_should_refuse_to_expunge_other_held_messages_on_delete() {
+        let account = account();
+        let output = OutputService::default().captured();
+        let mut backend = BackendStub::new(vec![(1, vec!["$Hold", "deleted"]), (2, vec![])]);
+        let mbox = Mbox::from("INBOX");
+
+        assert!(delete("2", true, false, &mbox, &account, &output, &mut backend).is_err());
+        assert!(backend.expunged.is_empty());
+    }
+
+    #[test]
+    fn it_should_refuse_to_archive_held_messages() {
+        let account = account();
+        let output = OutputService::default().captured();
+        let mut backend = BackendStub::new(vec![(1, vec!["$Hold"]), (2, vec![])]);
+
+        assert!(archive("1:2", false, &account, &output, &mut backend).is_err());
+        assert!(backend.moved.is_empty());
+
+        archive("2", false, &account, &output, &mut backend).unwrap();
+        assert_eq!(
+            vec![(String::from("2"), String::from("Archive"))],
+            backend.moved
+        );
+    }
 }
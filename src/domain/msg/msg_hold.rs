@@ -0,0 +1,276 @@
+//! Module related to messages on hold.
+//!
+//! Messages carrying the account hold keyword (`$Hold` by default) are retained for legal or
+//! compliance reasons: destructive commands refuse to touch them unless `--override-hold` is
+//! given.
+
+use anyhow::{anyhow, Context, Result};
+use imap::types::Flag;
+use log::debug;
+use std::convert::TryFrom;
+
+use crate::{
+    config::Account,
+    domain::{backend::Backend, mbox::Mbox, msg::Flags},
+};
+
+/// Check if the given flags hold the given keyword. Keywords are case-insensitive.
+pub fn is_held(flags: &Flags, keyword: &str) -> bool {
+    flags.iter().any(|flag| match flag {
+        Flag::Custom(custom) => custom.eq_ignore_ascii_case(keyword),
+        _ => false,
+    })
+}
+
+/// Get the ids of the messages on hold within the given sequence range. With `deleted_only`,
+/// only the ones also flagged as deleted are returned.
+pub fn held_ids(
+    seq_range: &str,
+    deleted_only: bool,
+    account: &Account,
+    backend: &mut dyn Backend,
+) -> Result<Vec<u32>> {
+    let ids: Vec<u32> = backend
+        .get_flags(seq_range)?
+        .into_iter()
+        .filter(|(_, flags)| is_held(flags, &account.hold_keyword))
+        .filter(|(_, flags)| !deleted_only || flags.contains(&Flag::Deleted))
+        .map(|(id, _)| id)
+        .collect();
+    debug!("messages on hold within {}: {:?}", seq_range, ids);
+    Ok(ids)
+}
+
+/// Build the error returned when the given action would touch messages on hold.
+fn hold_error(action: &str, ids: &[u32]) -> anyhow::Error {
+    let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+    anyhow!(
+        "cannot {} message(s) {}: they are on hold, use --override-hold to proceed anyway",
+        action,
+        ids.join(",")
+    )
+}
+
+/// Refuse the given action if some messages of the given sequence range are on hold, unless
+/// `override_hold` is set.
+pub fn check_hold(
+    seq_range: &str,
+    action: &str,
+    override_hold: bool,
+    account: &Account,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if override_hold {
+        return Ok(());
+    }
+    let ids = held_ids(seq_range, false, account, backend)?;
+    if ids.is_empty() {
+        Ok(())
+    } else {
+        Err(hold_error(action, &ids))
+    }
+}
+
+/// Refuse to remove the hold keyword from messages as a flag or as a label, unless
+/// `override_hold` is set: messages are released from hold with `hold --release`.
+pub fn check_release<'a>(
+    keywords: impl IntoIterator<Item = &'a str>,
+    override_hold: bool,
+    account: &Account,
+) -> Result<()> {
+    let keyword = &account.hold_keyword;
+    if override_hold
+        || !keywords
+            .into_iter()
+            .any(|flag| flag.eq_ignore_ascii_case(keyword))
+    {
+        return Ok(());
+    }
+    Err(anyhow!(
+        r#"cannot remove the hold keyword "{}": use `hold --release`, or --override-hold to proceed anyway"#,
+        keyword
+    ))
+}
+
+/// Get the ids of the messages of the selected mailbox both on hold and flagged as deleted, that
+/// an expunge would remove.
+pub fn deleted_held_ids(account: &Account, backend: &mut dyn Backend) -> Result<Vec<u32>> {
+    if backend.list_envelopes(&1, &0)?.is_empty() {
+        return Ok(vec![]);
+    }
+    held_ids("1:*", true, account, backend)
+}
+
+/// Refuse to expunge the selected mailbox if some messages on hold are flagged as deleted,
+/// unless `override_hold` is set.
+pub fn check_expunge_hold(
+    override_hold: bool,
+    account: &Account,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if override_hold {
+        return Ok(());
+    }
+    let ids = deleted_held_ids(account, backend)?;
+    if ids.is_empty() {
+        Ok(())
+    } else {
+        Err(hold_error("expunge", &ids))
+    }
+}
+
+/// Permanently remove the given deleted messages. When the backend cannot expunge single
+/// messages, the whole mailbox is expunged instead, refusing when other deleted messages are on
+/// hold unless `override_hold` is set.
+pub fn expunge_msgs(
+    seq_range: &str,
+    override_hold: bool,
+    account: &Account,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if backend.expunge_msgs(seq_range)? {
+        return Ok(());
+    }
+    debug!(
+        "cannot expunge {} only, expunge the whole mailbox",
+        seq_range
+    );
+    check_expunge_hold(override_hold, account, backend)?;
+    backend.expunge()
+}
+
+/// Move the given messages by copying them, then deleting and expunging them, for servers
+/// lacking the IMAP MOVE extension. Without UIDPLUS, the whole mailbox is expunged: the move is
+/// refused when it would remove other deleted messages on hold.
+pub fn move_by_copy(
+    seq: &str,
+    mbox: &Mbox,
+    account: &Account,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    if !backend.get_caps()?.iter().any(|cap| cap == "UIDPLUS") {
+        let held = deleted_held_ids(account, backend)?;
+        if !held.is_empty() {
+            let held: Vec<String> = held.iter().map(u32::to_string).collect();
+            return Err(anyhow!(
+                r#"cannot move message(s) "{}": the server lacks the MOVE and UIDPLUS extensions, and expunging the mailbox would remove the deleted message(s) {} on hold"#,
+                seq,
+                held.join(",")
+            ));
+        }
+    }
+    backend.copy_msgs(seq, mbox)?;
+    let flags = Flags::try_from(vec![Flag::Seen, Flag::Deleted])?;
+    backend
+        .add_flags(seq, &flags)
+        .context(format!(r#"cannot delete message(s) "{}""#, seq))?;
+    // UIDPLUS expunges the moved messages only, not all the deleted ones.
+    if !backend.expunge_msgs(seq)? {
+        backend.expunge()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::backend::backend_stub::BackendStub;
+
+    fn account() -> Account {
+        Account {
+            hold_keyword: String::from("$Hold"),
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn it_should_detect_held_messages() {
+        assert!(is_held(&Flags::from(vec!["seen", "$hold"]), "$Hold"));
+        assert!(!is_held(&Flags::from(vec!["seen", "$hold"]), "$LegalHold"));
+        assert!(!is_held(&Flags::from(vec!["seen"]), "$Hold"));
+    }
+
+    #[test]
+    fn it_should_build_hold_errors() {
+        assert_eq!(
+            "cannot delete message(s) 1,3: they are on hold, use --override-hold to proceed anyway",
+            hold_error("delete", &[1, 3]).to_string()
+        );
+    }
+
+    #[test]
+    fn it_should_check_hold() {
+        let account = account();
+        let mut backend =
+            BackendStub::new(vec![(1, vec!["$Hold"]), (2, vec![]), (3, vec!["$hold"])]);
+        assert!(check_hold("2", "delete", false, &account, &mut backend).is_ok());
+        assert_eq!(
+            "cannot delete message(s) 1,3: they are on hold, use --override-hold to proceed anyway",
+            check_hold("1:3", "delete", false, &account, &mut backend)
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(check_hold("1:3", "delete", true, &account, &mut backend).is_ok());
+    }
+
+    #[test]
+    fn it_should_check_release() {
+        let account = account();
+        assert!(check_release(vec!["seen", "$hold"], false, &account).is_err());
+        assert!(check_release(vec!["seen", "$hold"], true, &account).is_ok());
+        assert!(check_release(vec!["todo"], false, &account).is_ok());
+    }
+
+    #[test]
+    fn it_should_refuse_to_expunge_deleted_held_messages() {
+        let account = account();
+        let mut backend =
+            BackendStub::new(vec![(1, vec!["$Hold", "deleted"]), (2, vec!["deleted"])]);
+        assert!(expunge_msgs("2", false, &account, &mut backend).is_err());
+        assert!(backend.expunged.is_empty());
+
+        expunge_msgs("2", true, &account, &mut backend).unwrap();
+        assert_eq!(vec![1, 2], backend.expunged);
+    }
+
+    #[test]
+    fn it_should_expunge_single_messages() {
+        let account = account();
+        let mut backend =
+            BackendStub::new(vec![(1, vec!["$Hold", "deleted"]), (2, vec!["deleted"])]);
+        backend.uid_expunge = true;
+        expunge_msgs("2", false, &account, &mut backend).unwrap();
+        assert_eq!(vec![2], backend.expunged);
+    }
+
+    #[test]
+    fn it_should_refuse_to_move_by_copy_without_uidplus() {
+        let account = account();
+        let mut backend = BackendStub::new(vec![(1, vec!["$Hold", "deleted"]), (2, vec![])]);
+        assert_eq!(
+            r#"cannot move message(s) "2": the server lacks the MOVE and UIDPLUS extensions, and expunging the mailbox would remove the deleted message(s) 1 on hold"#,
+            move_by_copy("2", &Mbox::from("Archive"), &account, &mut backend)
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(backend.copied.is_empty());
+        assert!(backend.expunged.is_empty());
+    }
+
+    #[test]
+    fn it_should_move_by_copy_with_uidplus() {
+        let account = account();
+        let mut backend = BackendStub::new(vec![(1, vec!["$Hold", "deleted"]), (2, vec![])]);
+        backend.uid_expunge = true;
+        move_by_copy("2", &Mbox::from("Archive"), &account, &mut backend).unwrap();
+        assert_eq!(
+            vec![(String::from("2"), String::from("Archive"))],
+            backend.copied
+        );
+        assert_eq!(vec![2], backend.expunged);
+        assert_eq!(
+            vec![1],
+            backend.msgs.iter().map(|(id, _)| *id).collect::<Vec<_>>()
+        );
+    }
+}
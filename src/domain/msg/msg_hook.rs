@@ -0,0 +1,96 @@
+//! Module related to send hooks.
+//!
+//! The `pre-send-cmd` hook receives the raw message on its standard input before it is sent, and
+//! can abort sending by exiting with a non-zero status: a linter can remind forgotten
+//! attachments, a spell checker can block a message full of typos. The `post-send-cmd` hook
+//! receives the message as sent, for custom archiving. Both hooks share the terminal, so that
+//! they can explain themselves.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{config::Account, domain::msg::Msg};
+
+/// Run the given hook with the given raw message on its standard input.
+fn run(cmd: &str, raw_msg: &[u8]) -> Result<()> {
+    debug!("send hook cmd: {}", cmd);
+    let mut child = if cfg!(target_os = "windows") {
+        Command::new("cmd")
+            .args(&["/C", cmd])
+            .stdin(Stdio::piped())
+            .spawn()
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::piped())
+            .spawn()
+    }
+    .context(format!(r#"cannot run send hook "{}""#, cmd))?;
+
+    // A hook may exit without reading the whole message, a broken pipe does not matter then.
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(err) = stdin.write_all(raw_msg) {
+            debug!("cannot pipe message to send hook: {}", err);
+        }
+    }
+
+    let status = child
+        .wait()
+        .context(format!(r#"cannot wait for send hook "{}""#, cmd))?;
+    if !status.success() {
+        return Err(anyhow!(r#"send hook "{}" exited with {}"#, cmd, status));
+    }
+    Ok(())
+}
+
+/// Run the `pre-send-cmd` hook of the account with the given raw message, failing when the hook
+/// rejects it.
+pub fn pre_send_raw(account: &Account, raw_msg: &[u8]) -> Result<()> {
+    match account.pre_send_cmd {
+        Some(ref cmd) => run(cmd, raw_msg).context("cannot send message: pre-send hook failed"),
+        None => Ok(()),
+    }
+}
+
+/// Run the `pre-send-cmd` hook of the account with the given message.
+pub fn pre_send(account: &Account, msg: &Msg) -> Result<()> {
+    if account.pre_send_cmd.is_none() {
+        return Ok(());
+    }
+    let sendable_msg = msg.to_sendable_msg(true, account.format_flowed)?;
+    pre_send_raw(account, &msg.format_sendable_msg(&sendable_msg))
+}
+
+/// Run the `post-send-cmd` hook of the account with the given sent message. The message is
+/// already sent, so failures are only reported.
+pub fn post_send(account: &Account, raw_msg: &[u8]) {
+    if let Some(ref cmd) = account.post_send_cmd {
+        if let Err(err) = run(cmd, raw_msg) {
+            warn!("post-send hook failed: {:#}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_run_send_hooks() {
+        let raw_msg = b"Subject: Hello\r\n\r\nHello\r\n";
+        let mut account = Account::default();
+        assert!(pre_send_raw(&account, raw_msg).is_ok());
+
+        if cfg!(target_family = "unix") {
+            account.pre_send_cmd = Some(String::from("grep -q Hello"));
+            assert!(pre_send_raw(&account, raw_msg).is_ok());
+            account.pre_send_cmd = Some(String::from("grep -q attached"));
+            assert!(pre_send_raw(&account, raw_msg).is_err());
+        }
+    }
+}
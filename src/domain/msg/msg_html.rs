@@ -0,0 +1,609 @@
+//! Module related to HTML rendering.
+//!
+//! This module renders HTML parts as plain text for the terminal. Blocks, quotes and preformatted
+//! text keep their layout, lists are rendered with bullets or numbers, and data tables are drawn
+//! with box-drawing characters. Tables used for layout, as most newsletters do, are rendered as
+//! plain blocks instead.
+
+use log::trace;
+use unicode_width::UnicodeWidthStr;
+
+/// Elements without content nor end tag.
+const VOID_ELEMENTS: [&str; 9] = [
+    "br", "hr", "img", "meta", "link", "input", "col", "wbr", "base",
+];
+/// Elements whose content is not displayed.
+const HIDDEN_ELEMENTS: [&str; 4] = ["script", "style", "head", "title"];
+/// Elements rendered on lines of their own, separated by a blank line.
+const PARAGRAPH_ELEMENTS: [&str; 12] = [
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "table",
+    "ul",
+    "ol",
+];
+/// Elements rendered on lines of their own.
+const BLOCK_ELEMENTS: [&str; 16] = [
+    "div", "section", "article", "header", "footer", "main", "nav", "aside", "address", "center",
+    "dl", "dt", "dd", "form", "fieldset", "figure",
+];
+
+/// Represents an HTML node.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Element {
+        name: String,
+        attrs: Vec<(String, String)>,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn attr(&self, key: &str) -> Option<&str> {
+        match self {
+            Node::Element { attrs, .. } => attrs
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str()),
+            Node::Text(_) => None,
+        }
+    }
+
+    fn children(&self) -> &[Node] {
+        match self {
+            Node::Element { children, .. } => children,
+            Node::Text(_) => &[],
+        }
+    }
+
+    /// Get the child elements of the given names, looking through the given containers.
+    fn find<'a>(&'a self, names: &[&str], containers: &[&str], found: &mut Vec<&'a Node>) {
+        for child in self.children() {
+            if let Node::Element { name, .. } = child {
+                if names.contains(&name.as_str()) {
+                    found.push(child);
+                } else if containers.contains(&name.as_str()) {
+                    child.find(names, containers, found);
+                }
+            }
+        }
+    }
+
+    fn contains(&self, tag: &str) -> bool {
+        self.children().iter().any(|child| match child {
+            Node::Element { name, .. } => name == tag || child.contains(tag),
+            Node::Text(_) => false,
+        })
+    }
+}
+
+fn decode(text: &str) -> String {
+    htmlescape::decode_html(text).unwrap_or_else(|_| text.to_owned())
+}
+
+/// Parse the attributes of the given tag content, like `a href="x" class=y`.
+fn parse_attrs(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = vec![];
+    let mut chars = tag.chars().peekable();
+    loop {
+        while chars.peek().map(|c| c.is_whitespace()).unwrap_or_default() {
+            chars.next();
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.peek() {
+            if c.is_whitespace() || *c == '=' {
+                break;
+            }
+            key.push(*c);
+            chars.next();
+        }
+        if key.is_empty() {
+            if chars.next().is_none() {
+                break;
+            }
+            continue;
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            match chars.peek().copied() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    chars.next();
+                    value.extend(chars.by_ref().take_while(|c| *c != quote));
+                }
+                _ => {
+                    while let Some(c) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        value.push(*c);
+                        chars.next();
+                    }
+                }
+            }
+        }
+        attrs.push((key.to_lowercase(), decode(&value)));
+    }
+    attrs
+}
+
+/// Parse the given HTML into a tree. The parser is lenient: unclosed elements are closed by their
+/// parent, and paragraphs, list items and table cells by their next sibling.
+fn parse(html: &str) -> Node {
+    // The stack of open elements, the root being the first one.
+    let mut stack: Vec<Node> = vec![Node::Element {
+        name: String::new(),
+        attrs: vec![],
+        children: vec![],
+    }];
+
+    fn close(stack: &mut Vec<Node>) {
+        if stack.len() > 1 {
+            let node = stack.pop().unwrap();
+            if let Some(Node::Element { children, .. }) = stack.last_mut() {
+                children.push(node);
+            }
+        }
+    }
+
+    fn is_open(stack: &[Node], tag: &str, until: &[&str]) -> bool {
+        for node in stack.iter().rev() {
+            if let Node::Element { name, .. } = node {
+                if name == tag {
+                    return true;
+                }
+                if until.contains(&name.as_str()) {
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    fn close_until(stack: &mut Vec<Node>, tag: &str) {
+        while let Some(Node::Element { name, .. }) = stack.last() {
+            let is_tag = name == tag;
+            close(stack);
+            if is_tag {
+                break;
+            }
+        }
+    }
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let (text, tag) = match rest.find('<') {
+            Some(i) => (&rest[..i], Some(&rest[i..])),
+            None => (rest, None),
+        };
+        if !text.is_empty() {
+            if let Some(Node::Element { children, .. }) = stack.last_mut() {
+                children.push(Node::Text(decode(text)));
+            }
+        }
+        let tag = match tag {
+            Some(tag) => tag,
+            None => break,
+        };
+
+        // A lone `<` is text, like in `1 < 2`.
+        let starts_tag = tag[1..]
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?')
+            .unwrap_or_default();
+        if !starts_tag {
+            if let Some(Node::Element { children, .. }) = stack.last_mut() {
+                children.push(Node::Text(String::from("<")));
+            }
+            rest = &tag[1..];
+            continue;
+        }
+
+        if let Some(comment) = tag.strip_prefix("<!--") {
+            rest = comment
+                .find("-->")
+                .map(|i| &comment[i + 3..])
+                .unwrap_or_default();
+            continue;
+        }
+        let end = match tag.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let content = &tag[1..end];
+        rest = &tag[end + 1..];
+
+        if content.starts_with('!') || content.starts_with('?') {
+            continue;
+        }
+        if let Some(name) = content.strip_prefix('/') {
+            let name = name.trim().to_lowercase();
+            if is_open(&stack, &name, &[]) {
+                close_until(&mut stack, &name);
+            }
+            continue;
+        }
+
+        let content = content.trim_end_matches('/');
+        let (name, attrs) = match content.find(char::is_whitespace) {
+            Some(i) => (&content[..i], parse_attrs(&content[i..])),
+            None => (content, vec![]),
+        };
+        let name = name.to_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+
+        if HIDDEN_ELEMENTS.contains(&name.as_str()) {
+            let end_tag = format!("</{}", name);
+            rest = rest
+                .to_ascii_lowercase()
+                .find(&end_tag)
+                .and_then(|i| rest[i..].find('>').map(|j| &rest[i + j + 1..]))
+                .unwrap_or_default();
+            continue;
+        }
+
+        // Close the elements implicitly ended by the new one.
+        match name.as_str() {
+            "li" if is_open(&stack, "li", &["ul", "ol"]) => close_until(&mut stack, "li"),
+            "tr" if is_open(&stack, "tr", &["table"]) => close_until(&mut stack, "tr"),
+            "td" | "th" => {
+                for cell in ["td", "th"].iter() {
+                    if is_open(&stack, cell, &["tr", "table"]) {
+                        close_until(&mut stack, cell);
+                    }
+                }
+            }
+            name if PARAGRAPH_ELEMENTS.contains(&name) || BLOCK_ELEMENTS.contains(&name) => {
+                if is_open(&stack, "p", &["div", "td", "th", "li", "blockquote"]) {
+                    close_until(&mut stack, "p");
+                }
+            }
+            _ => (),
+        }
+
+        let node = Node::Element {
+            name: name.to_owned(),
+            attrs,
+            children: vec![],
+        };
+        if VOID_ELEMENTS.contains(&name.as_str()) {
+            if let Some(Node::Element { children, .. }) = stack.last_mut() {
+                children.push(node);
+            }
+        } else {
+            stack.push(node);
+        }
+    }
+
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().unwrap()
+}
+
+/// Represents the lines being rendered, with the text of the current line.
+#[derive(Default)]
+struct Lines {
+    lines: Vec<String>,
+    line: String,
+}
+
+impl Lines {
+    fn push_text(&mut self, text: &str) {
+        // Only ASCII whitespace collapses, non-breaking spaces are kept.
+        if text.starts_with(|c: char| c.is_ascii_whitespace()) && !self.line.ends_with(' ') {
+            self.line.push(' ');
+        }
+        let mut glue = "";
+        for word in text.split_ascii_whitespace() {
+            self.line.push_str(glue);
+            self.line.push_str(word);
+            glue = " ";
+        }
+        if text.ends_with(|c: char| c.is_ascii_whitespace()) && !glue.is_empty() {
+            self.line.push(' ');
+        }
+    }
+
+    fn flush(&mut self) {
+        let line = self.line.trim().to_owned();
+        if !line.is_empty() {
+            self.lines.push(line);
+        }
+        self.line.clear();
+    }
+
+    fn push_blank(&mut self) {
+        self.flush();
+        if self.lines.last().map(|line| !line.is_empty()) == Some(true) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn push_lines(&mut self, lines: Vec<String>) {
+        self.flush();
+        self.lines.extend(lines);
+    }
+
+    fn into_lines(mut self) -> Vec<String> {
+        self.flush();
+        while self.lines.last().map(|line| line.is_empty()) == Some(true) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// Render the children of the given node as lines.
+fn render_children(node: &Node) -> Vec<String> {
+    let mut lines = Lines::default();
+    for child in node.children() {
+        render_node(child, &mut lines);
+    }
+    lines.into_lines()
+}
+
+fn render_node(node: &Node, lines: &mut Lines) {
+    let name = match node {
+        Node::Text(text) => return lines.push_text(text),
+        Node::Element { name, .. } => name.as_str(),
+    };
+
+    match name {
+        "br" => {
+            let line = lines.line.trim().to_owned();
+            lines.lines.push(line);
+            lines.line.clear();
+        }
+        "hr" => lines.push_lines(vec!["─".repeat(40)]),
+        "img" => {
+            if let Some(alt) = node.attr("alt").filter(|alt| !alt.trim().is_empty()) {
+                lines.push_text(&format!("[{}]", alt.trim()));
+            }
+        }
+        "a" => {
+            let text_start = lines.line.len();
+            for child in node.children() {
+                render_node(child, lines);
+            }
+            let text = lines
+                .line
+                .get(text_start..)
+                .unwrap_or_default()
+                .trim()
+                .to_owned();
+            match node.attr("href") {
+                Some(href) if href.starts_with("http") && text != href => {
+                    lines.push_text(&format!(" <{}>", href));
+                }
+                _ => (),
+            }
+        }
+        "pre" => {
+            lines.push_blank();
+            let mut text = String::new();
+            collect_text(node, &mut text);
+            lines.push_lines(
+                text.trim_matches('\n')
+                    .lines()
+                    .map(|line| line.trim_end().to_owned())
+                    .collect(),
+            );
+            lines.push_blank();
+        }
+        "blockquote" => {
+            lines.push_blank();
+            let quoted = render_children(node)
+                .into_iter()
+                .map(|line| {
+                    if line.is_empty() || line.starts_with('>') {
+                        format!(">{}", line)
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
+                .collect();
+            lines.push_lines(quoted);
+            lines.push_blank();
+        }
+        "ul" | "ol" => {
+            lines.push_blank();
+            lines.push_lines(render_list(node, name == "ol"));
+            lines.push_blank();
+        }
+        "table" => {
+            lines.push_blank();
+            lines.push_lines(render_table(node));
+            lines.push_blank();
+        }
+        name if PARAGRAPH_ELEMENTS.contains(&name) => {
+            lines.push_blank();
+            lines.push_lines(render_children(node));
+            lines.push_blank();
+        }
+        name if BLOCK_ELEMENTS.contains(&name) || name == "li" || name == "tr" => {
+            lines.push_lines(render_children(node));
+        }
+        "td" | "th" => {
+            for child in node.children() {
+                render_node(child, lines);
+            }
+            lines.push_text(" ");
+        }
+        _ => {
+            for child in node.children() {
+                render_node(child, lines);
+            }
+        }
+    }
+}
+
+fn collect_text(node: &Node, text: &mut String) {
+    for child in node.children() {
+        match child {
+            Node::Text(content) => text.push_str(content),
+            Node::Element { name, .. } if name == "br" => text.push('\n'),
+            child => collect_text(child, text),
+        }
+    }
+}
+
+/// Render the items of the given list, prefixed with bullets or numbers.
+fn render_list(node: &Node, ordered: bool) -> Vec<String> {
+    let mut items = vec![];
+    node.find(&["li"], &[], &mut items);
+    let mut n = node
+        .attr("start")
+        .and_then(|start| start.trim().parse::<i64>().ok())
+        .unwrap_or(1);
+
+    let mut lines = vec![];
+    for item in items {
+        let marker = if ordered {
+            format!("{}. ", n)
+        } else {
+            String::from("• ")
+        };
+        n += 1;
+        let indent = " ".repeat(UnicodeWidthStr::width(marker.as_str()));
+        // Items are kept tight, without the blank lines around their blocks.
+        let item_lines: Vec<String> = render_children(item)
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect();
+        if item_lines.is_empty() {
+            lines.push(marker.trim_end().to_owned());
+        }
+        for (i, line) in item_lines.into_iter().enumerate() {
+            if i == 0 {
+                lines.push(format!("{}{}", marker, line));
+            } else {
+                lines.push(format!("{}{}", indent, line));
+            }
+        }
+    }
+    lines
+}
+
+/// Render the given table. Data tables are drawn with box-drawing characters, layout tables
+/// (with a single column, nested tables or a presentation role) are rendered as blocks.
+fn render_table(node: &Node) -> Vec<String> {
+    let mut rows = vec![];
+    node.find(&["tr"], &["thead", "tbody", "tfoot"], &mut rows);
+    let rows: Vec<Vec<&Node>> = rows
+        .into_iter()
+        .map(|row| {
+            let mut cells = vec![];
+            row.find(&["td", "th"], &[], &mut cells);
+            cells
+        })
+        .collect();
+
+    let is_layout = node.attr("role") == Some("presentation")
+        || rows.iter().all(|cells| cells.len() <= 1)
+        || node.contains("table");
+    if is_layout {
+        trace!("render layout table as blocks");
+        let mut lines = Lines::default();
+        for cell in rows.iter().flatten() {
+            lines.push_lines(render_children(cell));
+        }
+        return lines.into_lines();
+    }
+
+    let rows: Vec<Vec<Vec<String>>> = rows
+        .iter()
+        .filter(|cells| !cells.is_empty())
+        .map(|cells| cells.iter().map(|cell| render_children(cell)).collect())
+        .collect();
+    let cols = rows.iter().map(Vec::len).max().unwrap_or_default();
+    let widths: Vec<usize> = (0..cols)
+        .map(|col| {
+            rows.iter()
+                .filter_map(|cells| cells.get(col))
+                .flatten()
+                .map(|line| UnicodeWidthStr::width(line.as_str()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let border = |left: &str, mid: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}", left, segments.join(mid), right)
+    };
+
+    let mut lines = vec![border("┌", "┬", "┐")];
+    for (i, cells) in rows.iter().enumerate() {
+        if i > 0 {
+            lines.push(border("├", "┼", "┤"));
+        }
+        let height = cells.iter().map(Vec::len).max().unwrap_or_default().max(1);
+        for n in 0..height {
+            let mut line = String::from("│");
+            for (col, width) in widths.iter().enumerate() {
+                let text = cells
+                    .get(col)
+                    .and_then(|cell| cell.get(n))
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let pad = width - UnicodeWidthStr::width(text);
+                line.push_str(&format!(" {}{} │", text, " ".repeat(pad)));
+            }
+            lines.push(line);
+        }
+    }
+    lines.push(border("└", "┴", "┘"));
+    lines
+}
+
+/// Render the given HTML as plain text.
+pub fn to_text(html: &str) -> String {
+    render_children(&parse(html)).join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_render_html_as_text() {
+        let html = r#"<html><head><style>p { color: red; }</style></head><body>
+            <p>Hello&nbsp;<b>world</b>, see <a href="https://example.com">the site</a>.
+            <ul><li>One<li>Two<ol start="3"><li>Three</ol></ul>
+            <table><tr><th>Name</th><th>Total</th></tr><tr><td>Alice</td><td>42</td></tr></table>
+            <blockquote>Quoted<br>text</blockquote>
+            <table role="presentation"><tr><td>Layout</td></tr></table>
+            </body></html>"#;
+        let expected = [
+            "Hello\u{a0}world, see the site <https://example.com>.",
+            "",
+            "• One",
+            "• Two",
+            "  3. Three",
+            "",
+            "┌───────┬───────┐",
+            "│ Name  │ Total │",
+            "├───────┼───────┤",
+            "│ Alice │ 42    │",
+            "└───────┴───────┘",
+            "",
+            "> Quoted",
+            "> text",
+            "",
+            "Layout",
+        ]
+        .join("\n");
+        assert_eq!(expected, to_text(html));
+    }
+}
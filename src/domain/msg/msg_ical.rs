@@ -0,0 +1,488 @@
+//! Module related to calendar invitations and contact cards.
+//!
+//! Invitations are [iCalendar] objects sent as `text/calendar` parts, contact cards are [vCard]
+//! objects sent as `text/vcard` parts. Both are made of the same content lines, like
+//! `ORGANIZER;CN=Alice:mailto:alice@localhost`, and are summarized when reading messages.
+//! Invitations are answered with an [iTIP] reply sent to their organizer.
+//!
+//! [iCalendar]: https://datatracker.ietf.org/doc/html/rfc5545
+//! [vCard]: https://datatracker.ietf.org/doc/html/rfc6350
+//! [iTIP]: https://datatracker.ietf.org/doc/html/rfc5546
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use std::fmt::{self, Display};
+
+use crate::{
+    config::Account,
+    domain::msg::{BinaryPart, Msg, Part, TextPlainPart},
+};
+
+/// Maximum length of a content line, folded beyond.
+const CONTENT_LINE_LEN: usize = 75;
+
+/// Represents a content line, like `NAME;PARAM=VALUE:VALUE`.
+#[derive(Debug, Clone, PartialEq)]
+struct ContentLine {
+    /// The uppercased name of the property.
+    name: String,
+    /// The parameters of the property, their names uppercased.
+    params: Vec<(String, String)>,
+    value: String,
+    /// The whole unfolded line.
+    raw: String,
+}
+
+impl ContentLine {
+    fn parse(line: &str) -> Option<Self> {
+        // The value starts at the first colon out of the quoted parameter values.
+        let mut quoted = false;
+        let (colon, _) = line.char_indices().find(|(_, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                false
+            }
+            ':' => !quoted,
+            _ => false,
+        })?;
+        let mut head = line[..colon].split(';');
+        let name = head.next()?.trim().to_uppercase();
+        let params = head
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, val)| (key.trim().to_uppercase(), val.trim_matches('"').to_owned()))
+            .collect();
+        Some(Self {
+            name,
+            params,
+            value: line[colon + 1..].to_owned(),
+            raw: line.to_owned(),
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, val)| val.as_str())
+    }
+
+    /// Get the text value, unescaped.
+    fn text(&self) -> String {
+        let mut text = String::new();
+        let mut chars = self.value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') | Some('N') => text.push('\n'),
+                    Some(c) => text.push(c),
+                    None => (),
+                },
+                c => text.push(c),
+            }
+        }
+        text
+    }
+}
+
+/// Unfold the lines of the given object, lines starting with a space or a tab continuing the
+/// previous one.
+fn content_lines(content: &str) -> Vec<ContentLine> {
+    let mut lines: Vec<String> = vec![];
+    for line in content.lines() {
+        let continuation = line.strip_prefix(' ').or_else(|| line.strip_prefix('\t'));
+        match (continuation, lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+        .iter()
+        .filter_map(|line| ContentLine::parse(line))
+        .collect()
+}
+
+/// Fold the given line in lines of at most 75 bytes, continuation lines starting with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > CONTENT_LINE_LEN {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Format the given date or date-time value for humans. UTC times are given in local time.
+fn format_time(line: &ContentLine) -> String {
+    let value = line.value.trim();
+    if let Some(utc) = value.strip_suffix('Z') {
+        if let Ok(time) = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S") {
+            return Local
+                .from_utc_datetime(&time)
+                .format("%a %d %b %Y, %H:%M")
+                .to_string();
+        }
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        let time = time.format("%a %d %b %Y, %H:%M").to_string();
+        return match line.param("TZID") {
+            Some(tzid) => format!("{} ({})", time, tzid),
+            None => time,
+        };
+    }
+    match NaiveDate::parse_from_str(value, "%Y%m%d") {
+        Ok(date) => date.format("%a %d %b %Y").to_string(),
+        Err(_) => value.to_owned(),
+    }
+}
+
+/// Represents the organizer or an attendee of an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Person {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+impl Person {
+    fn from_line(line: &ContentLine) -> Self {
+        let value = line.value.trim();
+        let email = match value.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+            _ => value,
+        };
+        Self {
+            name: line.param("CN").map(String::from),
+            email: email.to_owned(),
+        }
+    }
+}
+
+impl Display for Person {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{} <{}>", name, self.email),
+            None => write!(f, "{}", self.email),
+        }
+    }
+}
+
+/// Represents an event of a calendar object.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    /// The method of the calendar object, like `REQUEST` for invitations or `CANCEL`.
+    pub method: String,
+    pub uid: String,
+    pub summary: String,
+    pub organizer: Option<Person>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub location: Option<String>,
+    /// The lines identifying the event, copied as is in replies.
+    ids: Vec<String>,
+}
+
+impl Event {
+    /// Parse the events of the given calendar object.
+    pub fn parse_all(content: &str) -> Vec<Self> {
+        let mut events = vec![];
+        let mut method = String::new();
+        let mut event: Option<Self> = None;
+        // The components nested in the current event, like alarms.
+        let mut nested = 0;
+
+        for line in content_lines(content) {
+            match (line.name.as_str(), event.as_mut()) {
+                ("METHOD", None) => method = line.value.trim().to_uppercase(),
+                ("BEGIN", None) if line.value.trim().eq_ignore_ascii_case("VEVENT") => {
+                    event = Some(Self {
+                        method: method.to_owned(),
+                        ..Self::default()
+                    })
+                }
+                ("BEGIN", Some(_)) => nested += 1,
+                ("END", Some(_)) if nested > 0 => nested -= 1,
+                ("END", Some(_)) => events.extend(event.take()),
+                (_, Some(_)) if nested > 0 => (),
+                (name, Some(event)) => {
+                    match name {
+                        "UID" => event.uid = line.text(),
+                        "SUMMARY" => event.summary = line.text(),
+                        "LOCATION" => event.location = Some(line.text()),
+                        "ORGANIZER" => event.organizer = Some(Person::from_line(&line)),
+                        "DTSTART" => event.start = Some(format_time(&line)),
+                        "DTEND" => event.end = Some(format_time(&line)),
+                        _ => (),
+                    }
+                    if let "UID" | "SEQUENCE" | "RECURRENCE-ID" | "DTSTART" | "DTEND" | "DURATION"
+                    | "ORGANIZER" | "SUMMARY" = name
+                    {
+                        event.ids.push(line.raw);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        events
+    }
+
+    /// Build the human summary of the event.
+    pub fn summary(&self) -> String {
+        let kind = match self.method.as_str() {
+            "REQUEST" => "Invitation",
+            "CANCEL" => "Cancelled event",
+            "REPLY" => "Invitation reply",
+            _ => "Event",
+        };
+        let mut summary = format!("{}: {}", kind, self.summary);
+        if let Some(ref organizer) = self.organizer {
+            summary.push_str(&format!("\nOrganizer: {}", organizer));
+        }
+        match (&self.start, &self.end) {
+            (Some(start), Some(end)) => summary.push_str(&format!("\nWhen: {} – {}", start, end)),
+            (Some(start), None) => summary.push_str(&format!("\nWhen: {}", start)),
+            _ => (),
+        }
+        if let Some(ref location) = self.location {
+            summary.push_str(&format!("\nWhere: {}", location));
+        }
+        summary
+    }
+
+    /// Build the calendar object replying to the event as the given attendee.
+    pub fn reply(&self, attendee: &Person, partstat: PartStat) -> String {
+        let mut lines = vec![
+            String::from("BEGIN:VCALENDAR"),
+            String::from("PRODID:-//himalaya//EN"),
+            String::from("VERSION:2.0"),
+            String::from("METHOD:REPLY"),
+            String::from("BEGIN:VEVENT"),
+        ];
+        lines.extend(self.ids.iter().cloned());
+        lines.push(Utc::now().format("DTSTAMP:%Y%m%dT%H%M%SZ").to_string());
+        let name = attendee
+            .name
+            .as_ref()
+            .map(|name| format!(r#";CN="{}""#, name.replace('"', "")))
+            .unwrap_or_default();
+        lines.push(format!(
+            "ATTENDEE;PARTSTAT={}{}:mailto:{}",
+            partstat.as_str(),
+            name,
+            attendee.email
+        ));
+        lines.push(format!(
+            "COMMENT:{}",
+            escape(&format!("{} {} the invitation.", attendee, partstat))
+        ));
+        lines.push(String::from("END:VEVENT"));
+        lines.push(String::from("END:VCALENDAR"));
+        let mut reply = lines
+            .iter()
+            .map(|line| fold(line))
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        reply.push_str("\r\n");
+        reply
+    }
+}
+
+/// Represents the participation status of an invitation reply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartStat {
+    Accepted,
+    Declined,
+    Tentative,
+}
+
+impl PartStat {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Accepted => "ACCEPTED",
+            Self::Declined => "DECLINED",
+            Self::Tentative => "TENTATIVE",
+        }
+    }
+
+    /// The prefix of the subject of replies.
+    fn subject_prefix(&self) -> &str {
+        match self {
+            Self::Accepted => "Accepted",
+            Self::Declined => "Declined",
+            Self::Tentative => "Tentative",
+        }
+    }
+}
+
+impl Display for PartStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Accepted => write!(f, "accepted"),
+            Self::Declined => write!(f, "declined"),
+            Self::Tentative => write!(f, "tentatively accepted"),
+        }
+    }
+}
+
+/// Represents a contact card.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Contact {
+    pub name: String,
+    pub org: Option<String>,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+}
+
+impl Contact {
+    /// Parse the contact cards of the given vCard object.
+    pub fn parse_all(content: &str) -> Vec<Self> {
+        let mut contacts = vec![];
+        let mut contact: Option<Self> = None;
+        for line in content_lines(content) {
+            match (line.name.as_str(), contact.as_mut()) {
+                ("BEGIN", None) if line.value.trim().eq_ignore_ascii_case("VCARD") => {
+                    contact = Some(Self::default())
+                }
+                ("END", Some(_)) => contacts.extend(contact.take()),
+                ("FN", Some(contact)) => contact.name = line.text(),
+                ("ORG", Some(contact)) => contact.org = Some(line.text().replace(';', ", ")),
+                ("EMAIL", Some(contact)) => contact.emails.push(line.text()),
+                ("TEL", Some(contact)) => contact.phones.push(line.text()),
+                _ => (),
+            }
+        }
+        contacts
+    }
+
+    /// Build the human summary of the contact.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Contact: {}", self.name);
+        if let Some(ref org) = self.org {
+            summary.push_str(&format!("\nOrganization: {}", org));
+        }
+        for email in &self.emails {
+            summary.push_str(&format!("\nEmail: {}", email));
+        }
+        for phone in &self.phones {
+            summary.push_str(&format!("\nPhone: {}", phone));
+        }
+        summary
+    }
+}
+
+fn is_calendar(part: &BinaryPart) -> bool {
+    part.mime.starts_with("text/calendar") || part.filename.to_lowercase().ends_with(".ics")
+}
+
+fn is_vcard(part: &BinaryPart) -> bool {
+    let filename = part.filename.to_lowercase();
+    part.mime.starts_with("text/vcard")
+        || part.mime.starts_with("text/x-vcard")
+        || filename.ends_with(".vcf")
+        || filename.ends_with(".vcard")
+}
+
+/// Build the summaries of the invitations and contact cards of the given message.
+pub fn summaries(msg: &Msg) -> Vec<String> {
+    let mut summaries = vec![];
+    for part in msg.attachments() {
+        let content = String::from_utf8_lossy(&part.content);
+        if is_calendar(&part) {
+            summaries.extend(Event::parse_all(&content).iter().map(Event::summary));
+        } else if is_vcard(&part) {
+            summaries.extend(Contact::parse_all(&content).iter().map(Contact::summary));
+        }
+    }
+    summaries
+}
+
+/// Find the invitation of the given message.
+pub fn find_invitation(msg: &Msg) -> Option<Event> {
+    msg.attachments()
+        .iter()
+        .filter(|part| is_calendar(part))
+        .flat_map(|part| Event::parse_all(&String::from_utf8_lossy(&part.content)))
+        .find(|event| event.method == "REQUEST")
+}
+
+/// Build the message answering the given invitation of the given message, sent to its
+/// organizer.
+pub fn reply_msg(msg: &Msg, event: &Event, partstat: PartStat, account: &Account) -> Result<Msg> {
+    let organizer = event.organizer.as_ref().ok_or_else(|| {
+        anyhow!(
+            r#"cannot answer invitation "{}": it has no organizer"#,
+            event.summary
+        )
+    })?;
+    let organizer_addr = organizer.email.parse().context(format!(
+        r#"cannot parse organizer address "{}""#,
+        organizer.email
+    ))?;
+    let attendee = Person {
+        name: Some(account.from.to_owned()).filter(|name| !name.is_empty()),
+        email: account.email.to_owned(),
+    };
+
+    let mut reply = Msg::default();
+    reply.from = Some(vec![account.address().parse()?]);
+    reply.to = Some(vec![lettre::message::Mailbox::new(
+        organizer.name.to_owned(),
+        organizer_addr,
+    )]);
+    reply.subject = format!("{}: {}", partstat.subject_prefix(), event.summary);
+    reply.in_reply_to = msg.message_id.to_owned();
+    reply.parts.push(Part::TextPlain(TextPlainPart {
+        content: format!("{} {} the invitation.\n", attendee, partstat),
+    }));
+    reply.parts.push(Part::Binary(BinaryPart {
+        filename: String::from("reply.ics"),
+        mime: String::from("text/calendar; method=REPLY; charset=utf-8"),
+        content: event.reply(&attendee, partstat).into_bytes(),
+    }));
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_invitations_and_cards() {
+        let invitation = "BEGIN:VCALENDAR\r\nMETHOD:REQUEST\r\nBEGIN:VEVENT\r\nUID:42@localhost\r\nSEQUENCE:1\r\nSUMMARY:Team meeting\\, weekly\r\nORGANIZER;CN=\"Alice: boss\":mailto:alice@localhost\r\nDTSTART;TZID=Europe/Paris:20211122T100000\r\nDTEND;TZID=Europe/Paris:20211122T110000\r\nLOCATION:Room 1\r\n  and 2\r\nBEGIN:VALARM\r\nSUMMARY:Reminder\r\nEND:VALARM\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let events = Event::parse_all(invitation);
+        assert_eq!(1, events.len());
+        assert_eq!(
+            "Invitation: Team meeting, weekly\nOrganizer: Alice: boss <alice@localhost>\nWhen: Mon 22 Nov 2021, 10:00 (Europe/Paris) – Mon 22 Nov 2021, 11:00 (Europe/Paris)\nWhere: Room 1 and 2",
+            events[0].summary()
+        );
+
+        let attendee = Person {
+            name: Some(String::from("Bob")),
+            email: String::from("bob@localhost"),
+        };
+        let reply = events[0].reply(&attendee, PartStat::Accepted);
+        assert!(reply.contains("\r\nMETHOD:REPLY\r\n"));
+        assert!(reply.contains("\r\nUID:42@localhost\r\nSEQUENCE:1\r\n"));
+        assert!(
+            reply.contains("\r\nATTENDEE;PARTSTAT=ACCEPTED;CN=\"Bob\":mailto:bob@localhost\r\n")
+        );
+        assert!(reply.lines().all(|line| line.len() <= CONTENT_LINE_LEN));
+
+        let card = "BEGIN:VCARD\nVERSION:4.0\nFN:Carol Doe\nORG:ACME;Sales\nEMAIL;TYPE=work:carol@localhost\nTEL:+33 1 23 45 67 89\nEND:VCARD\n";
+        assert_eq!(
+            "Contact: Carol Doe\nOrganization: ACME, Sales\nEmail: carol@localhost\nPhone: +33 1 23 45 67 89",
+            Contact::parse_all(card)[0].summary()
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Module related to journaling exports.
+//!
+//! A journal is a directory of dated mbox files, only ever appended to, along with a `manifest`
+//! listing each journaled message with the SHA-256 hash of its raw content. Each manifest line
+//! also holds a hash chained to the previous line, so that any modified, removed or reordered
+//! entry is detected when the journal is opened again.
+//!
+//! Entries also record the UIDVALIDITY of their mailbox: when the server resets it, UIDs start
+//! over and the journal resumes from the first message of the mailbox instead of skipping the
+//! messages with a lower UID than the last journaled one.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::domain::msg::{msg_export, Msg};
+
+const MANIFEST_FILENAME: &str = "manifest";
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Represents a journaled message, one line of the manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// The journaling time, as a UNIX timestamp.
+    pub timestamp: i64,
+    pub mbox: String,
+    /// The UID of the message.
+    pub id: u32,
+    /// The name of the mbox file the message was appended to.
+    pub file: String,
+    /// The hash of the raw message.
+    pub hash: String,
+    /// The UIDVALIDITY of the mailbox, missing from entries journaled before it was recorded.
+    pub uid_validity: Option<u32>,
+    /// The hash of the entry chained to the previous one.
+    pub chain: String,
+}
+
+impl JournalEntry {
+    fn fields(&self) -> String {
+        let fields = format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.timestamp, self.mbox, self.id, self.file, self.hash
+        );
+        match self.uid_validity {
+            Some(uid_validity) => format!("{}\t{}", fields, uid_validity),
+            None => fields,
+        }
+    }
+
+    fn chain(prev: &str, fields: &str) -> String {
+        sha256(format!("{}\t{}", prev, fields).as_bytes())
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let uid_validity = match fields.len() {
+            6 => None,
+            7 => Some(fields[5].parse().ok()?),
+            _ => return None,
+        };
+        Some(Self {
+            timestamp: fields[0].parse().ok()?,
+            mbox: fields[1].to_owned(),
+            id: fields[2].parse().ok()?,
+            file: fields[3].to_owned(),
+            hash: fields[4].to_owned(),
+            uid_validity,
+            chain: fields[fields.len() - 1].to_owned(),
+        })
+    }
+}
+
+/// Represents an append-only journal.
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Open the journal of the given directory, creating it if needed, and verify its manifest.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).context(format!("cannot create journal directory {:?}", dir))?;
+        let path = dir.join(MANIFEST_FILENAME);
+        let manifest = match fs::read_to_string(&path) {
+            Ok(manifest) => manifest,
+            Err(_) if !path.exists() => String::new(),
+            Err(err) => {
+                return Err(err).context(format!("cannot read journal manifest {:?}", path))
+            }
+        };
+
+        let mut entries: Vec<JournalEntry> = vec![];
+        for (i, line) in manifest.lines().enumerate() {
+            let entry = JournalEntry::parse(line).ok_or_else(|| {
+                anyhow!("cannot parse line {} of journal manifest {:?}", i + 1, path)
+            })?;
+            let prev = entries.last().map(|entry| entry.chain.as_str());
+            if entry.chain != JournalEntry::chain(prev.unwrap_or_default(), &entry.fields()) {
+                return Err(anyhow!(
+                    "cannot verify journal manifest {:?}: line {} was tampered with",
+                    path,
+                    i + 1
+                ));
+            }
+            entries.push(entry);
+        }
+        debug!("{} journaled message(s) verified", entries.len());
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            entries,
+        })
+    }
+
+    /// Get the UID of the last message journaled from the given mailbox under the given
+    /// UIDVALIDITY. Entries without UIDVALIDITY are only taken into account when none of the
+    /// mailbox has one.
+    pub fn last_id(&self, mbox: &str, uid_validity: Option<u32>) -> Option<u32> {
+        let entries = self.entries.iter().filter(|entry| entry.mbox == mbox);
+        let recorded =
+            uid_validity.is_some() && entries.clone().any(|entry| entry.uid_validity.is_some());
+        entries
+            .filter(|entry| !recorded || entry.uid_validity == uid_validity)
+            .map(|entry| entry.id)
+            .max()
+    }
+
+    /// Get the UIDVALIDITY of the last message journaled from the given mailbox, if it differs
+    /// from the given one.
+    pub fn changed_uid_validity(&self, mbox: &str, uid_validity: Option<u32>) -> Option<u32> {
+        let prev = self
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| entry.mbox == mbox)?
+            .uid_validity?;
+        match uid_validity {
+            Some(curr) if curr != prev => Some(prev),
+            _ => None,
+        }
+    }
+
+    /// Append the given message to the mbox file of the day, then record it in the manifest.
+    pub fn append(
+        &mut self,
+        mbox: &str,
+        uid_validity: Option<u32>,
+        msg: &Msg,
+        raw_msg: &[u8],
+    ) -> Result<()> {
+        let file = msg_export::mbox_filename(mbox);
+        let path = self.dir.join(&file);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut journal| journal.write_all(&msg_export::to_mbox_entry(msg, raw_msg)))
+            .context(format!("cannot append message {} to {:?}", msg.id, path))?;
+
+        let mut entry = JournalEntry {
+            timestamp: Local::now().timestamp(),
+            mbox: mbox.to_owned(),
+            id: msg.id,
+            file,
+            hash: sha256(raw_msg),
+            uid_validity,
+            chain: String::new(),
+        };
+        let prev = self.entries.last().map(|entry| entry.chain.as_str());
+        entry.chain = JournalEntry::chain(prev.unwrap_or_default(), &entry.fields());
+
+        let path = self.dir.join(MANIFEST_FILENAME);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut manifest| {
+                writeln!(manifest, "{}\t{}", entry.fields(), entry.chain)?;
+                manifest.sync_all()
+            })
+            .context(format!("cannot record message {} in {:?}", msg.id, path))?;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn it_should_journal_messages() {
+        let dir = env::temp_dir().join(format!("himalaya-journal-test-{}", process::id()));
+        let mut journal = Journal::open(&dir).unwrap();
+        assert_eq!(None, journal.last_id("INBOX", Some(42)));
+
+        for id in [3, 5] {
+            let msg = Msg {
+                id,
+                ..Msg::default()
+            };
+            journal
+                .append("INBOX", Some(42), &msg, b"Subject: Hello\r\n\r\nHello\r\n")
+                .unwrap();
+        }
+        let journal = Journal::open(&dir).unwrap();
+        assert_eq!(Some(5), journal.last_id("INBOX", Some(42)));
+        assert_eq!(None, journal.last_id("Sent", Some(42)));
+        assert_eq!(journal.entries[0].hash, journal.entries[1].hash);
+        assert_ne!(journal.entries[0].chain, journal.entries[1].chain);
+
+        // Removing the first entry breaks the chain.
+        let path = dir.join(MANIFEST_FILENAME);
+        let manifest = fs::read_to_string(&path).unwrap();
+        let (_, tampered) = manifest.split_once('\n').unwrap();
+        fs::write(&path, tampered).unwrap();
+        assert!(Journal::open(&dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_should_resume_from_scratch_on_uid_validity_change() {
+        let dir = env::temp_dir().join(format!("himalaya-journal-uidvalidity-{}", process::id()));
+        let mut journal = Journal::open(&dir).unwrap();
+        let msg = Msg {
+            id: 7,
+            ..Msg::default()
+        };
+        journal
+            .append("INBOX", None, &msg, b"Subject: Hello\r\n\r\nHello\r\n")
+            .unwrap();
+        journal
+            .append("INBOX", Some(42), &msg, b"Subject: Hello\r\n\r\nHello\r\n")
+            .unwrap();
+
+        // Entries with and without UIDVALIDITY are both verified.
+        let journal = Journal::open(&dir).unwrap();
+        assert_eq!(None, journal.entries[0].uid_validity);
+        assert_eq!(Some(42), journal.entries[1].uid_validity);
+        assert_eq!(Some(7), journal.last_id("INBOX", Some(42)));
+        assert_eq!(None, journal.changed_uid_validity("INBOX", Some(42)));
+
+        assert_eq!(None, journal.last_id("INBOX", Some(43)));
+        assert_eq!(Some(42), journal.changed_uid_validity("INBOX", Some(43)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Module related to mailing lists.
+//!
+//! Messages distributed by a mailing list carry [list headers]: `List-Id` identifies the list,
+//! `List-Post` gives the address to write to and `List-Unsubscribe` the ways to leave it, mailto
+//! or HTTP URLs. HTTP URLs are posted to directly when the list supports [one-click]
+//! unsubscription, otherwise they need to be opened in a browser.
+//!
+//! [list headers]: https://datatracker.ietf.org/doc/html/rfc2369
+//! [one-click]: https://datatracker.ietf.org/doc/html/rfc8058
+
+use anyhow::{Context, Result};
+use log::debug;
+use mailparse::{MailHeader, MailHeaderMap};
+use std::time::Duration;
+use url::Url;
+
+const ONE_CLICK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Represents a way to unsubscribe from a mailing list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnsubscribeTarget {
+    /// An HTTPS URL to post to, without confirmation.
+    OneClick(Url),
+    /// An address to send a message to.
+    Mailto {
+        to: String,
+        subject: String,
+        body: String,
+    },
+    /// A web page to open.
+    Link(Url),
+}
+
+/// Represents the mailing list a message was distributed by.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MailingList {
+    /// The identifier of the list, like `users.lists.rust-lang.org`.
+    pub id: String,
+    /// The description of the list, like `Rust users`.
+    pub description: Option<String>,
+    /// The address to write to, unless the list does not accept posts.
+    pub post: Option<String>,
+    pub unsubscribe: Vec<Url>,
+    /// Tells if the HTTPS unsubscribe URLs can be posted to, from the `List-Unsubscribe-Post`
+    /// header.
+    pub one_click: bool,
+}
+
+/// Extract the URLs of a list header, like `<mailto:list@localhost>, <https://localhost>`.
+/// Comments and folding spaces are dropped.
+fn parse_urls(val: &str) -> Vec<Url> {
+    val.split('<')
+        .skip(1)
+        .filter_map(|url| url.split_once('>'))
+        .filter_map(|(url, _)| Url::parse(&url.split_whitespace().collect::<String>()).ok())
+        .collect()
+}
+
+impl MailingList {
+    /// Find the mailing list of a message from its headers.
+    pub fn from_headers(headers: &[MailHeader]) -> Option<Self> {
+        let post = headers
+            .get_first_value("List-Post")
+            .and_then(|val| {
+                parse_urls(&val)
+                    .into_iter()
+                    .find(|url| url.scheme() == "mailto")
+            })
+            .map(|url| url.path().to_owned());
+        let unsubscribe = headers
+            .get_first_value("List-Unsubscribe")
+            .map(|val| parse_urls(&val))
+            .unwrap_or_default();
+        let one_click = headers
+            .get_first_value("List-Unsubscribe-Post")
+            .map(|val| {
+                val.trim()
+                    .eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+            })
+            .unwrap_or_default();
+
+        let (description, id) = match headers.get_first_value("List-Id") {
+            Some(val) => match val.rsplit_once('<') {
+                Some((description, id)) => (
+                    Some(description.trim().trim_matches('"').to_owned())
+                        .filter(|description| !description.is_empty()),
+                    id.trim_end().trim_end_matches('>').to_owned(),
+                ),
+                None => (None, val.trim().to_owned()),
+            },
+            // Some lists only tell where to post or how to leave.
+            None if post.is_some() || !unsubscribe.is_empty() => {
+                (None, post.to_owned().unwrap_or_default())
+            }
+            None => return None,
+        };
+
+        Some(Self {
+            id,
+            description,
+            post,
+            unsubscribe,
+            one_click,
+        })
+    }
+
+    /// Get the name of the list shown in listings: its description, or its identifier.
+    pub fn name(&self) -> &str {
+        self.description.as_deref().unwrap_or(&self.id)
+    }
+
+    /// Pick the way to unsubscribe requiring the less interaction: one-click first, then mailto,
+    /// then a link to open.
+    pub fn unsubscribe_target(&self) -> Option<UnsubscribeTarget> {
+        if self.one_click {
+            if let Some(url) = self.unsubscribe.iter().find(|url| url.scheme() == "https") {
+                return Some(UnsubscribeTarget::OneClick(url.to_owned()));
+            }
+        }
+        if let Some(url) = self.unsubscribe.iter().find(|url| url.scheme() == "mailto") {
+            let mut subject = String::from("unsubscribe");
+            let mut body = String::new();
+            for (key, val) in url.query_pairs() {
+                match key.to_lowercase().as_str() {
+                    "subject" => subject = val.into_owned(),
+                    "body" => body = val.into_owned(),
+                    _ => (),
+                }
+            }
+            return Some(UnsubscribeTarget::Mailto {
+                to: url.path().to_owned(),
+                subject,
+                body,
+            });
+        }
+        self.unsubscribe
+            .iter()
+            .find(|url| url.scheme() == "https" || url.scheme() == "http")
+            .map(|url| UnsubscribeTarget::Link(url.to_owned()))
+    }
+}
+
+/// Unsubscribe by posting to the given one-click URL.
+pub fn post_one_click(url: &Url) -> Result<()> {
+    debug!("post one-click unsubscription to {}", url);
+    ureq::post(url.as_str())
+        .timeout(ONE_CLICK_TIMEOUT)
+        .send_form(&[("List-Unsubscribe", "One-Click")])
+        .context(format!("cannot post unsubscription to {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_list_headers() {
+        let raw = concat!(
+            "List-Id: \"Rust users\" <users.lists.rust-lang.org>\r\n",
+            "List-Post: <mailto:users@lists.rust-lang.org>\r\n",
+            "List-Unsubscribe: <https://lists.rust-lang.org/unsub/42>,\r\n",
+            " <mailto:users-leave@lists.rust-lang.org?subject=leave> (by mail)\r\n",
+            "\r\n",
+        );
+        let (headers, _) = mailparse::parse_headers(raw.as_bytes()).unwrap();
+        let mut list = MailingList::from_headers(&headers).unwrap();
+        assert_eq!("users.lists.rust-lang.org", list.id);
+        assert_eq!("Rust users", list.name());
+        assert_eq!(Some("users@lists.rust-lang.org"), list.post.as_deref());
+        assert_eq!(
+            Some(UnsubscribeTarget::Mailto {
+                to: String::from("users-leave@lists.rust-lang.org"),
+                subject: String::from("leave"),
+                body: String::new(),
+            }),
+            list.unsubscribe_target()
+        );
+
+        list.one_click = true;
+        assert_eq!(
+            Some(UnsubscribeTarget::OneClick(
+                Url::parse("https://lists.rust-lang.org/unsub/42").unwrap()
+            )),
+            list.unsubscribe_target()
+        );
+
+        let (headers, _) = mailparse::parse_headers(b"Subject: Hello\r\n\r\n").unwrap();
+        assert_eq!(None, MailingList::from_headers(&headers));
+    }
+}
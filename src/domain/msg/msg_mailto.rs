@@ -0,0 +1,188 @@
+//! Module related to mailto URLs.
+//!
+//! Mailto URLs are defined by [RFC6068]: the addresses of the path, then header fields and the
+//! body as query parameters, eg. `mailto:a@b.org,c@d.org?cc=e@f.org&subject=Hi&body=Hello`.
+//! Values are kept as written, so that malformed addresses end up in the editor instead of
+//! being dropped.
+//!
+//! Links come from untrusted sources (web pages, other messages), so they cannot set the
+//! pseudo-headers of templates: an attachment hint like `?attach=~/.ssh/id_rsa` would stage a
+//! local file for sending.
+//!
+//! [RFC6068]: https://datatracker.ietf.org/doc/html/rfc6068
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+
+use crate::domain::msg::{TplOverride, PSEUDO_HEADERS};
+
+/// Define the header fields mailto URLs cannot set, as they are either built when sending or
+/// could mislead the receiver ([RFC6068](https://datatracker.ietf.org/doc/html/rfc6068#section-7)).
+const IGNORED_HEADERS: &[&str] = &[
+    "from",
+    "sender",
+    "date",
+    "message-id",
+    "received",
+    "return-path",
+    "mime-version",
+];
+
+/// Represents the fields of a mailto URL.
+#[derive(Debug, Default, PartialEq)]
+pub struct Mailto {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    /// The other header fields, as template header lines.
+    pub headers: Vec<String>,
+}
+
+/// Decode the percent-encoded octets of the given text. Unlike forms, `+` stays as is.
+fn decode(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| {
+                    anyhow!(
+                        r#"cannot decode mailto URL: invalid escape at "{}""#,
+                        &text[i..]
+                    )
+                })?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| anyhow!("cannot decode mailto URL: invalid utf-8"))
+}
+
+/// Split the given decoded addresses, separated by commas (or semicolons, used by older links).
+fn split_addrs(addrs: &str) -> impl Iterator<Item = String> + '_ {
+    addrs
+        .split(|c| c == ',' || c == ';')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(String::from)
+}
+
+/// Keep the given header value on a single line.
+fn unfold(val: &str) -> String {
+    val.split(|c| c == '\r' || c == '\n')
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Mailto {
+    /// Parse the given mailto URL.
+    pub fn parse(url: &str) -> Result<Self> {
+        let url = url.trim();
+        let rest = match url.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &url[7..],
+            _ => return Err(anyhow!(r#"cannot parse mailto URL "{}""#, url)),
+        };
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut mailto = Self::default();
+        mailto.to.extend(split_addrs(&decode(path)?));
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, val) = param.split_once('=').unwrap_or((param, ""));
+            let name = decode(key)?;
+            let key = name.to_lowercase();
+            let val = decode(val)?;
+            match key.as_str() {
+                "to" => mailto.to.extend(split_addrs(&val)),
+                "cc" => mailto.cc.extend(split_addrs(&val)),
+                "bcc" => mailto.bcc.extend(split_addrs(&val)),
+                "subject" => mailto.subject = Some(unfold(&val)),
+                "body" => mailto.body = Some(val.replace("\r\n", "\n")),
+                key if key == "attach"
+                    || PSEUDO_HEADERS
+                        .iter()
+                        .any(|header| header.eq_ignore_ascii_case(key)) =>
+                {
+                    warn!(
+                        r#"ignore mailto header "{}": links cannot attach files nor set pseudo-headers"#,
+                        name
+                    );
+                }
+                key if IGNORED_HEADERS.contains(&key) || key.starts_with("content-") => {
+                    debug!("ignore mailto header {}", key);
+                }
+                key if key.is_empty() || key.contains(|c: char| c == ':' || c.is_whitespace()) => {
+                    debug!("ignore invalid mailto header {:?}", key);
+                }
+                _ => mailto.headers.push(format!("{}: {}", name, unfold(&val))),
+            }
+        }
+        Ok(mailto)
+    }
+
+    /// Get the template override filling the fields of the URL. Fields missing from the URL are
+    /// left to the usual template defaults.
+    pub fn tpl_override(&self) -> TplOverride {
+        let addrs = |addrs: &[String]| match addrs {
+            [] => None,
+            addrs => Some(addrs.iter().map(String::as_str).collect()),
+        };
+        TplOverride {
+            to: addrs(&self.to),
+            cc: addrs(&self.cc),
+            bcc: addrs(&self.bcc),
+            subject: self.subject.as_deref(),
+            headers: addrs(&self.headers),
+            body: self.body.as_deref(),
+            ..TplOverride::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_mailto_urls() {
+        let mailto = Mailto::parse(concat!(
+            "mailto:john+list@doe.org,%22Jane%20Doe%22%20%3Cjane@doe.org%3E",
+            "?CC=bob@doe.org&bcc=eve@doe.org;not-an-address",
+            "&subject=Hello%20world&body=Line%201%0D%0ALine%202",
+            "&In-Reply-To=%3C42@doe.org%3E&attachment=file:///tmp/a.pdf",
+            "&attach=~/.ssh/id_rsa&Sign=no&ENCRYPT=no",
+            "&from=mallory@evil.org&x-bad%0Aheader=1",
+        ))
+        .unwrap();
+        assert_eq!(
+            Mailto {
+                to: vec![
+                    String::from("john+list@doe.org"),
+                    String::from("\"Jane Doe\" <jane@doe.org>"),
+                ],
+                cc: vec![String::from("bob@doe.org")],
+                bcc: vec![String::from("eve@doe.org"), String::from("not-an-address")],
+                subject: Some(String::from("Hello world")),
+                body: Some(String::from("Line 1\nLine 2")),
+                headers: vec![String::from("In-Reply-To: <42@doe.org>")],
+            },
+            mailto
+        );
+
+        assert!(Mailto::parse("mailto:")
+            .unwrap()
+            .tpl_override()
+            .to
+            .is_none());
+        assert!(Mailto::parse("mailto:a@b.org?subject=%ZZ").is_err());
+        assert!(Mailto::parse("https://doe.org").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Module related to read receipts.
+//!
+//! A message asks for a read receipt with the `Disposition-Notification-To` header. The receipt
+//! is a [message disposition notification] (MDN) sent back to this address. Messages are flagged
+//! with the `$MDNSent` keyword once handled, so that the user is asked only once, even from
+//! another client.
+//!
+//! [message disposition notification]: https://datatracker.ietf.org/doc/html/rfc8098
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use imap::types::Flag;
+use log::debug;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::Sender,
+        msg::{Flags, Msg},
+    },
+};
+
+/// The header asking for a read receipt.
+pub const RECEIPT_HEADER: &str = "Disposition-Notification-To";
+
+/// The keyword of messages whose read receipt was handled.
+pub const MDN_SENT_KEYWORD: &str = "$MDNSent";
+
+/// Check if the read receipt of the given message is still to be handled.
+pub fn is_pending(msg: &Msg) -> bool {
+    msg.receipt_to.is_some()
+        && !msg.flags.iter().any(|flag| match flag {
+            Flag::Custom(custom) => custom.eq_ignore_ascii_case(MDN_SENT_KEYWORD),
+            _ => false,
+        })
+}
+
+/// Build the flags marking a read receipt as handled.
+pub fn mdn_sent_flags() -> Result<Flags> {
+    Flags::try_from(vec![Flag::Custom(MDN_SENT_KEYWORD.into())])
+}
+
+/// Build the raw notification telling that the given message was displayed to the given final
+/// recipient, sent from the given address. `automatic` tells if it is sent by policy rather than
+/// on the user request.
+fn raw_mdn(msg: &Msg, from: &str, to: &str, recipient: &str, automatic: bool) -> String {
+    let boundary = Uuid::new_v4().to_simple().to_string();
+    let domain = recipient.rsplit_once('@').map(|(_, domain)| domain);
+    let disposition = if automatic {
+        "automatic-action/MDN-sent-automatically; displayed"
+    } else {
+        "manual-action/MDN-sent-manually; displayed"
+    };
+    let mut report = format!(
+        "Reporting-UA: himalaya; {}\r\nFinal-Recipient: rfc822; {}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        recipient
+    );
+    if let Some(ref message_id) = msg.message_id {
+        report.push_str(&format!("Original-Message-ID: {}\r\n", message_id));
+    }
+    report.push_str(&format!("Disposition: {}\r\n", disposition));
+
+    [
+        format!("From: {}", from),
+        format!("To: {}", to),
+        format!("Subject: Read: {}", msg.subject),
+        format!("Date: {}", Local::now().to_rfc2822()),
+        format!(
+            "Message-ID: <{}@{}>",
+            Uuid::new_v4(),
+            domain.unwrap_or("localhost")
+        ),
+        String::from("MIME-Version: 1.0"),
+        format!(
+            r#"Content-Type: multipart/report; report-type=disposition-notification; boundary="{}""#,
+            boundary
+        ),
+        String::new(),
+        format!("--{}", boundary),
+        String::from("Content-Type: text/plain; charset=utf-8"),
+        String::new(),
+        format!(
+            r#"The message "{}" sent to {} has been displayed."#,
+            msg.subject, recipient
+        ),
+        String::new(),
+        format!("--{}", boundary),
+        String::from("Content-Type: message/disposition-notification"),
+        String::new(),
+        report,
+        format!("--{}--", boundary),
+        String::new(),
+    ]
+    .join("\r\n")
+}
+
+/// Send the read receipt of the given message.
+pub fn send(msg: &Msg, automatic: bool, account: &Account, sender: &mut dyn Sender) -> Result<()> {
+    let to = match msg.receipt_to {
+        Some(ref to) => to,
+        None => return Ok(()),
+    };
+    debug!("send read receipt of message {} to {}", msg.id, to);
+    let raw_msg = raw_mdn(
+        msg,
+        &account.address(),
+        &to.to_string(),
+        &account.email,
+        automatic,
+    );
+    let envelope = lettre::address::Envelope::new(
+        Some(
+            account
+                .email
+                .parse()
+                .context("cannot parse account address")?,
+        ),
+        vec![to.email.to_owned()],
+    )
+    .context("cannot create read receipt envelope")?;
+    sender
+        .send_raw(&envelope, raw_msg.as_bytes())
+        .context(format!("cannot send read receipt of message {}", msg.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_build_read_receipts() {
+        let mut msg = Msg::default();
+        msg.subject = String::from("Report");
+        msg.message_id = Some(String::from("<42@localhost>"));
+        assert!(!is_pending(&msg));
+        msg.receipt_to = Some("alice@localhost".parse().unwrap());
+        assert!(is_pending(&msg));
+
+        let raw = raw_mdn(
+            &msg,
+            "Bob <bob@localhost>",
+            "alice@localhost",
+            "bob@localhost",
+            false,
+        );
+        assert!(raw.contains("\r\nSubject: Read: Report\r\n"));
+        assert!(raw.contains("report-type=disposition-notification"));
+        assert!(raw.contains("\r\nFinal-Recipient: rfc822; bob@localhost\r\nOriginal-Message-ID: <42@localhost>\r\nDisposition: manual-action/MDN-sent-manually; displayed\r\n"));
+
+        msg.flags = mdn_sent_flags().unwrap();
+        assert!(!is_pending(&msg));
+    }
+}
@@ -0,0 +1,110 @@
+//! Module related to patches sent by email.
+//!
+//! Patches generated by `git format-patch` have their subject prefixed by a tag like `[PATCH]`,
+//! `[PATCH v2 1/3]` or `[RFC PATCH 0/5]`, the `0/N` one being the cover letter of the series.
+//! Replies (`Re: [PATCH …]`) are not patches. Patches are applied with `git am`, in the order of
+//! their number in the series.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::{
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+};
+
+use crate::domain::msg::{msg_export, Msg};
+
+/// The IMAP query matching the candidate patches. Subjects are then checked with `is_patch`.
+pub const PATCH_QUERY: &str = "SUBJECT PATCH";
+
+/// Get the words of the patch tag of the given subject, if any.
+fn patch_tag(subject: &str) -> Option<Vec<&str>> {
+    let mut subject = subject.trim_start();
+    while let Some(rest) = subject.strip_prefix('[') {
+        let (tag, rest) = rest.split_once(']')?;
+        let words: Vec<&str> = tag.split_whitespace().collect();
+        if words
+            .iter()
+            .any(|word| word.to_uppercase().starts_with("PATCH"))
+        {
+            return Some(words);
+        }
+        subject = rest.trim_start();
+    }
+    None
+}
+
+/// Check if the given subject is the one of a patch (or of a cover letter).
+pub fn is_patch(subject: &str) -> bool {
+    patch_tag(subject).is_some()
+}
+
+/// Get the number of the patch in its series, `0` being the cover letter. Patches that are not
+/// part of a series come first.
+fn patch_number(subject: &str) -> Option<u32> {
+    patch_tag(subject)?
+        .into_iter()
+        .filter_map(|word| word.split_once('/'))
+        .find_map(|(n, _)| n.parse().ok())
+}
+
+/// Keep the patches of the given messages, cover letters excepted, in the order of the series.
+pub fn series(mut msgs: Vec<(Msg, Vec<u8>)>) -> Vec<(Msg, Vec<u8>)> {
+    msgs.retain(|(msg, _)| is_patch(&msg.subject) && patch_number(&msg.subject) != Some(0));
+    msgs.sort_by_key(|(msg, _)| patch_number(&msg.subject).unwrap_or_default());
+    msgs
+}
+
+/// Apply the given patches with `git am`, in the given repository or in the current directory.
+pub fn am(patches: &[(Msg, Vec<u8>)], dir: Option<&str>) -> Result<()> {
+    let mbox: Vec<u8> = patches
+        .iter()
+        .flat_map(|(msg, raw_msg)| msg_export::to_mbox_entry(msg, raw_msg))
+        .collect();
+
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    debug!("apply {} patch(es) in {:?}", patches.len(), dir);
+    let mut child = cmd
+        .args(&["am", "--patch-format=mboxrd"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("cannot run git am")?;
+    let written = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("cannot open stdin of git am"))?
+        .write_all(&mbox);
+    match written {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            return Err(err).context("cannot write to stdin of git am");
+        }
+        _ => (),
+    }
+    let status = child.wait().context("cannot wait for git am")?;
+    if !status.success() {
+        return Err(anyhow!("git am exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_detect_patches() {
+        assert!(is_patch("[PATCH] fix typo"));
+        assert!(is_patch("[PATCHv2] fix typo"));
+        assert!(is_patch("[lists] [RFC PATCH v3 2/5] add feature"));
+        assert!(!is_patch("Re: [PATCH 1/2] fix typo"));
+        assert!(!is_patch("[lists] new release"));
+        assert!(!is_patch("Patch review"));
+
+        assert_eq!(None, patch_number("[PATCH] fix typo"));
+        assert_eq!(Some(0), patch_number("[PATCH v2 0/3] series"));
+        assert_eq!(Some(12), patch_number("[RFC PATCH 12/15] add feature"));
+    }
+}
@@ -0,0 +1,311 @@
+//! Module related to the search query language.
+//!
+//! Search queries are made of terms all messages must match, like `from:alice subject:"quarterly
+//! report" after:2024-01-01 has:attachment -flag:seen`. A term is either `key:value` or a free
+//! word searched in the whole message, and a leading `-` negates it. Values with spaces are
+//! double-quoted. Queries are parsed to a backend-agnostic form, then compiled to the criteria
+//! of each backend.
+//!
+//! Labels compile to the Gmail criterion, that the IMAP service maps to keywords on the servers
+//! lacking the X-GM-EXT-1 extension. Gmail queries, given with `--gmail`, compile to the X-GM-RAW
+//! criterion, that only Gmail servers accept.
+
+use anyhow::{anyhow, Error, Result};
+use chrono::{Duration, NaiveDate};
+use std::convert::TryFrom;
+
+use crate::{config::parse_size, domain::msg::is_keyword};
+
+/// Represents a search criterion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchCriterion {
+    From(String),
+    To(String),
+    Cc(String),
+    Bcc(String),
+    Subject(String),
+    Body(String),
+    /// Matches the headers and the body.
+    Text(String),
+    /// Matches messages received strictly after the given day.
+    After(NaiveDate),
+    /// Matches messages received strictly before the given day.
+    Before(NaiveDate),
+    On(NaiveDate),
+    Flag(String),
+    Label(String),
+    HasAttachment,
+    /// Matches messages larger than the given size, in bytes.
+    Larger(usize),
+    /// Matches messages smaller than the given size, in bytes.
+    Smaller(usize),
+    /// Matches messages matching the given Gmail query, like `from:boss has:attachment`. It has
+    /// no search key: it is given with the `--gmail` option.
+    GmailRaw(String),
+}
+
+/// Represents a search term: a criterion, possibly negated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchTerm {
+    pub criterion: SearchCriterion,
+    pub negated: bool,
+}
+
+/// Represents a search query, matching messages matching all its terms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery(pub Vec<SearchTerm>);
+
+/// Represents a token of a query, telling if it started with a quote.
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+/// Split the given query in tokens separated by spaces, outside of double quotes.
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut token: Option<Token> = None;
+    let mut in_quotes = false;
+    let mut chars = query.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                token.get_or_insert_with(|| Token {
+                    text: String::new(),
+                    quoted: true,
+                });
+            }
+            '\\' if in_quotes => {
+                if let (Some(token), Some(c)) = (token.as_mut(), chars.next()) {
+                    token.text.push(c);
+                }
+            }
+            c if c.is_whitespace() && !in_quotes => tokens.extend(token.take()),
+            c => token
+                .get_or_insert_with(|| Token {
+                    text: String::new(),
+                    quoted: false,
+                })
+                .text
+                .push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(anyhow!(
+            r#"cannot parse search query "{}": unclosed quote"#,
+            query
+        ));
+    }
+    tokens.extend(token.take());
+    Ok(tokens)
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+        anyhow!(
+            r#"cannot parse search date "{}", expected YYYY-MM-DD"#,
+            date
+        )
+    })
+}
+
+impl SearchCriterion {
+    fn parse(key: &str, value: String) -> Result<Self> {
+        if value.is_empty() {
+            return Err(anyhow!(
+                r#"cannot parse search term "{}:": missing value"#,
+                key
+            ));
+        }
+        match key.to_lowercase().as_str() {
+            "from" => Ok(Self::From(value)),
+            "to" => Ok(Self::To(value)),
+            "cc" => Ok(Self::Cc(value)),
+            "bcc" => Ok(Self::Bcc(value)),
+            "subject" => Ok(Self::Subject(value)),
+            "body" => Ok(Self::Body(value)),
+            "text" => Ok(Self::Text(value)),
+            "after" => Ok(Self::After(parse_date(&value)?)),
+            "before" => Ok(Self::Before(parse_date(&value)?)),
+            "on" => Ok(Self::On(parse_date(&value)?)),
+            "flag" => Ok(Self::Flag(value)),
+            "label" => Ok(Self::Label(value)),
+            "has" if value.eq_ignore_ascii_case("attachment") => Ok(Self::HasAttachment),
+            "has" => Err(anyhow!(r#"cannot parse search term "has:{}""#, value)),
+            "larger" => Ok(Self::Larger(parse_size(&value)?)),
+            "smaller" => Ok(Self::Smaller(parse_size(&value)?)),
+            key => Err(anyhow!(
+                r#"cannot parse search term: unknown key "{}""#,
+                key
+            )),
+        }
+    }
+
+    /// Check if the given word is a search key.
+    fn is_key(key: &str) -> bool {
+        [
+            "from", "to", "cc", "bcc", "subject", "body", "text", "after", "before", "on", "flag",
+            "label", "has", "larger", "smaller",
+        ]
+        .contains(&key.to_lowercase().as_str())
+    }
+
+    fn to_imap(&self) -> String {
+        let date = |date: &NaiveDate| date.format("%d-%b-%Y").to_string();
+        match self {
+            Self::From(value) => format!("FROM {}", quote(value)),
+            Self::To(value) => format!("TO {}", quote(value)),
+            Self::Cc(value) => format!("CC {}", quote(value)),
+            Self::Bcc(value) => format!("BCC {}", quote(value)),
+            Self::Subject(value) => format!("SUBJECT {}", quote(value)),
+            Self::Body(value) => format!("BODY {}", quote(value)),
+            Self::Text(value) => format!("TEXT {}", quote(value)),
+            // SINCE matches the given day, that `after` leaves out.
+            Self::After(day) => format!("SINCE {}", date(&(*day + Duration::days(1)))),
+            Self::Before(day) => format!("BEFORE {}", date(day)),
+            Self::On(day) => format!("ON {}", date(day)),
+            Self::Flag(flag) => match flag.to_lowercase().as_str() {
+                "seen" | "answered" | "flagged" | "deleted" | "draft" | "recent" => {
+                    flag.to_uppercase()
+                }
+                _ => format!("KEYWORD {}", flag),
+            },
+            Self::Label(label) if is_keyword(label) => format!("X-GM-LABELS {}", label),
+            Self::Label(label) => format!("X-GM-LABELS {}", quote(label)),
+            // IMAP cannot search by attachment, messages with attachments are mostly mixed
+            // multiparts.
+            Self::HasAttachment => String::from(r#"HEADER Content-Type "multipart/mixed""#),
+            Self::Larger(size) => format!("LARGER {}", size),
+            Self::Smaller(size) => format!("SMALLER {}", size),
+            Self::GmailRaw(query) => format!("X-GM-RAW {}", quote(query)),
+        }
+    }
+}
+
+/// Quote the given value as an IMAP string.
+fn quote(value: &str) -> String {
+    format!(r#""{}""#, value.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+impl SearchQuery {
+    /// Parse the query given as command line arguments. The shell already removed the quotes
+    /// around values with spaces, so they are quoted back.
+    pub fn from_args<'a>(args: impl Iterator<Item = &'a str>) -> Result<Self> {
+        let query = args
+            .map(|arg| {
+                if !arg.contains(char::is_whitespace) || arg.contains('"') {
+                    return arg.to_owned();
+                }
+                match arg.split_once(':') {
+                    Some((key, value)) if SearchCriterion::is_key(key.trim_start_matches('-')) => {
+                        format!("{}:{}", key, quote(value))
+                    }
+                    _ => quote(arg),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self::try_from(query.as_str())
+    }
+
+    /// Compile the query to IMAP SEARCH criteria.
+    pub fn to_imap(&self) -> String {
+        if self.0.is_empty() {
+            return String::from("ALL");
+        }
+        let criteria = self
+            .0
+            .iter()
+            .map(|term| {
+                if term.negated {
+                    format!("NOT {}", term.criterion.to_imap())
+                } else {
+                    term.criterion.to_imap()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Servers only accept non-ASCII criteria with a declared charset.
+        if criteria.is_ascii() {
+            criteria
+        } else {
+            format!("CHARSET UTF-8 {}", criteria)
+        }
+    }
+}
+
+impl TryFrom<&str> for SearchQuery {
+    type Error = Error;
+
+    fn try_from(query: &str) -> Result<Self> {
+        let mut terms = vec![];
+        for token in tokenize(query)? {
+            let (negated, text) = match token.text.strip_prefix('-') {
+                Some(text) if !token.quoted && !text.is_empty() => (true, text.to_owned()),
+                _ => (false, token.text),
+            };
+            let criterion = match text.split_once(':') {
+                Some((key, value)) if !token.quoted => {
+                    SearchCriterion::parse(key, value.to_owned())?
+                }
+                _ => SearchCriterion::Text(text),
+            };
+            terms.push(SearchTerm { criterion, negated });
+        }
+        Ok(Self(terms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_compile_queries_to_imap() {
+        let query = SearchQuery::try_from(
+            r#"from:alice subject:"quarterly report" after:2024-01-01 has:attachment -flag:seen"#,
+        )
+        .unwrap();
+        assert_eq!(
+            r#"FROM "alice" SUBJECT "quarterly report" SINCE 02-Jan-2024 HEADER Content-Type "multipart/mixed" NOT SEEN"#,
+            query.to_imap()
+        );
+
+        let query =
+            SearchQuery::from_args(vec!["subject:quarterly report", "-flag:$Hold"].into_iter())
+                .unwrap();
+        assert_eq!(
+            r#"SUBJECT "quarterly report" NOT KEYWORD $Hold"#,
+            query.to_imap()
+        );
+
+        assert_eq!(
+            r#"X-GM-LABELS todo NOT X-GM-LABELS "Work stuff""#,
+            SearchQuery::try_from(r#"label:todo -label:"Work stuff""#)
+                .unwrap()
+                .to_imap()
+        );
+
+        let mut query = SearchQuery::try_from("-flag:seen").unwrap();
+        query.0.push(SearchTerm {
+            criterion: SearchCriterion::GmailRaw(String::from(r#"from:boss has:attachment"#)),
+            negated: false,
+        });
+        assert_eq!(
+            r#"NOT SEEN X-GM-RAW "from:boss has:attachment""#,
+            query.to_imap()
+        );
+
+        assert_eq!("ALL", SearchQuery::default().to_imap());
+        assert_eq!(
+            r#"CHARSET UTF-8 TEXT "café" TEXT "re:hi""#,
+            SearchQuery::try_from(r#"café "re:hi""#).unwrap().to_imap()
+        );
+        assert!(SearchQuery::try_from("after:yesterday").is_err());
+        assert!(SearchQuery::try_from("size:10").is_err());
+        assert!(SearchQuery::try_from(r#"subject:"unclosed"#).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Module related to header rewriting.
+//!
+//! Messages cannot be edited on the server, so rewriting the headers of a message means
+//! appending a rewritten copy, with the flags and the internal date of the original, then
+//! deleting the original. Only the header block is touched: the body is kept byte for byte.
+
+use anyhow::{anyhow, Result};
+
+/// Represents a header to add, parsed from `Name: value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+impl Header {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let (name, value) = raw
+            .split_once(':')
+            .ok_or_else(|| anyhow!(r#"cannot parse header "{}": expected NAME: VALUE"#, raw))?;
+        let name = name.trim();
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic() && b != b':') {
+            return Err(anyhow!(r#"cannot parse header "{}": invalid name"#, raw));
+        }
+        if value.contains(&['\r', '\n'][..]) {
+            return Err(anyhow!(r#"cannot parse header "{}": invalid value"#, raw));
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            value: value.trim().to_owned(),
+        })
+    }
+}
+
+/// Split the given raw message into its header lines, folded lines included, and its body.
+/// Returns also the line ending used by the message.
+fn split(raw_msg: &[u8]) -> (Vec<&[u8]>, &[u8], &'static [u8]) {
+    let eol: &[u8] = if raw_msg.windows(2).any(|w| w == b"\r\n") {
+        b"\r\n"
+    } else {
+        b"\n"
+    };
+
+    let mut lines: Vec<&[u8]> = vec![];
+    let mut pos = 0;
+    while pos < raw_msg.len() {
+        let end = raw_msg[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| pos + i + 1)
+            .unwrap_or(raw_msg.len());
+        let line = &raw_msg[pos..end];
+        if line == b"\r\n" || line == b"\n" {
+            return (lines, &raw_msg[end..], eol);
+        }
+        match lines.last_mut() {
+            // Folded lines continue the previous header.
+            Some(last) if line.starts_with(b" ") || line.starts_with(b"\t") => {
+                *last = &raw_msg[pos - last.len()..end];
+            }
+            _ => lines.push(line),
+        }
+        pos = end;
+    }
+    (lines, &[], eol)
+}
+
+/// Get the name of the header of the given line, trailing spaces excluded.
+fn header_name(line: &[u8]) -> Option<&[u8]> {
+    let name = &line[..line.iter().position(|b| *b == b':')?];
+    let len = name
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    Some(&name[..len])
+}
+
+/// Rewrite the headers of the given raw message: the headers of the given names are removed,
+/// then the given headers are added. Returns `None` when nothing changed.
+pub fn rewrite(raw_msg: &[u8], add: &[Header], remove: &[String]) -> Option<Vec<u8>> {
+    let (lines, body, eol) = split(raw_msg);
+    let removed = |line: &[u8]| {
+        header_name(line).map_or(false, |name| {
+            remove
+                .iter()
+                .any(|remove| name.eq_ignore_ascii_case(remove.trim().as_bytes()))
+        })
+    };
+    if add.is_empty() && !lines.iter().any(|line| removed(line)) {
+        return None;
+    }
+
+    let mut rewritten = Vec::with_capacity(raw_msg.len());
+    for line in lines.iter().filter(|line| !removed(line)) {
+        rewritten.extend_from_slice(line);
+        if !line.ends_with(b"\n") {
+            rewritten.extend_from_slice(eol);
+        }
+    }
+    for header in add {
+        rewritten.extend_from_slice(format!("{}: {}", header.name, header.value).as_bytes());
+        rewritten.extend_from_slice(eol);
+    }
+    rewritten.extend_from_slice(eol);
+    rewritten.extend_from_slice(body);
+    if rewritten == raw_msg {
+        None
+    } else {
+        Some(rewritten)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_rewrite_headers() {
+        let raw_msg = concat!(
+            "From: john@localhost\r\n",
+            "List-Id: broken\r\n",
+            "  <list.localhost>\r\n",
+            "Subject: Hello\r\n",
+            "\r\n",
+            "List-Id: body lines are kept\r\n",
+        );
+        let add = vec![Header::parse("List-Id: <list.example.com>").unwrap()];
+        let remove = vec![String::from("list-id")];
+        let expected = concat!(
+            "From: john@localhost\r\n",
+            "Subject: Hello\r\n",
+            "List-Id: <list.example.com>\r\n",
+            "\r\n",
+            "List-Id: body lines are kept\r\n",
+        );
+        assert_eq!(
+            expected,
+            String::from_utf8(rewrite(raw_msg.as_bytes(), &add, &remove).unwrap()).unwrap()
+        );
+        assert_eq!(
+            None,
+            rewrite(raw_msg.as_bytes(), &[], &[String::from("X-Missing")])
+        );
+        assert!(Header::parse("Broken header").is_err());
+        assert!(Header::parse("X-Injected: a\r\nBcc: b").is_err());
+    }
+}
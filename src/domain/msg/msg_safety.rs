@@ -0,0 +1,117 @@
+//! Module related to the send safety check.
+//!
+//! Before sending, the recipients are checked against the addresses collected in the delivery
+//! log, so that the user is warned when writing to someone for the first time, or when an
+//! external address slipped among internal ones (the classic mis-CC).
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+/// Represents a reason to double-check the recipients before sending.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendWarning {
+    /// Recipients never written to before.
+    NewRecipients(Vec<String>),
+    /// External recipients alongside internal ones.
+    ExternalRecipients(Vec<String>),
+}
+
+impl Display for SendWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NewRecipients(addrs) => {
+                write!(f, "Never written to before: {}", addrs.join(", "))
+            }
+            Self::ExternalRecipients(addrs) => write!(
+                f,
+                "External recipients alongside internal ones: {}",
+                addrs.join(", ")
+            ),
+        }
+    }
+}
+
+/// Extract the domain of the given address, lowercased.
+fn domain(addr: &str) -> String {
+    addr.rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Check the given recipient addresses against the given known addresses and internal domains.
+/// Comparisons are case-insensitive.
+pub fn check<'a, I>(
+    recipients: &[String],
+    known: I,
+    internal_domains: &[String],
+) -> Vec<SendWarning>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let known: HashSet<String> = known.into_iter().map(|addr| addr.to_lowercase()).collect();
+    let internal_domains: Vec<String> = internal_domains
+        .iter()
+        .map(|domain| domain.to_lowercase())
+        .collect();
+    let mut warnings = vec![];
+
+    let new: Vec<String> = recipients
+        .iter()
+        .filter(|addr| !known.contains(&addr.to_lowercase()))
+        .cloned()
+        .collect();
+    if !new.is_empty() {
+        warnings.push(SendWarning::NewRecipients(new));
+    }
+
+    let (internal, external): (Vec<&String>, Vec<&String>) = recipients
+        .iter()
+        .partition(|addr| internal_domains.contains(&domain(addr)));
+    if !internal.is_empty() && !external.is_empty() {
+        warnings.push(SendWarning::ExternalRecipients(
+            external.into_iter().cloned().collect(),
+        ));
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_check_recipients() {
+        let known = vec![
+            String::from("alice@corp.com"),
+            String::from("bob@partner.com"),
+        ];
+        let internal = vec![String::from("Corp.com")];
+        let recipients = |addrs: &[&str]| {
+            addrs
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(check(&recipients(&["Alice@corp.com"]), &known, &internal).is_empty());
+        assert!(check(&recipients(&["bob@partner.com"]), &known, &internal).is_empty());
+        assert_eq!(
+            vec![SendWarning::NewRecipients(recipients(&["carol@corp.com"]))],
+            check(&recipients(&["carol@corp.com"]), &known, &internal)
+        );
+        assert_eq!(
+            vec![SendWarning::ExternalRecipients(recipients(&[
+                "bob@partner.com"
+            ]))],
+            check(
+                &recipients(&["alice@corp.com", "bob@partner.com"]),
+                &known,
+                &internal
+            )
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Module related to the sandboxed reading of messages.
+//!
+//! The sandbox is a paranoid rendering for suspicious messages: only text parts are decoded
+//! (HTML ones being rendered as text when no plain text part exists), links are replaced by
+//! numbered footnotes showing their real target, control characters (like terminal escape
+//! sequences) are stripped, and attachments are only listed, never decoded.
+
+use anyhow::{Context, Result};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use regex::Regex;
+
+use crate::domain::msg::msg_html;
+
+/// The headers shown above the text, the ones telling phishing apart.
+const HEADERS: [&str; 5] = ["From", "Reply-To", "Return-Path", "Date", "Subject"];
+
+/// Represents the parts of a message rendered in the sandbox.
+#[derive(Debug, Default)]
+struct SandboxParts {
+    plain: Vec<String>,
+    html: Vec<String>,
+    /// The attachments, as `filename (mime)`.
+    attachments: Vec<String>,
+}
+
+fn collect_parts(part: &ParsedMail, parts: &mut SandboxParts) {
+    if !part.subparts.is_empty() {
+        for part in part.subparts.iter() {
+            collect_parts(part, parts);
+        }
+        return;
+    }
+
+    let disp = part.get_content_disposition();
+    let mime = part.ctype.mimetype.to_lowercase();
+    match (disp.disposition, mime.as_str()) {
+        (DispositionType::Inline, "text/plain") => {
+            parts.plain.push(part.get_body().unwrap_or_default())
+        }
+        (DispositionType::Inline, "text/html") => {
+            parts.html.push(part.get_body().unwrap_or_default())
+        }
+        _ => {
+            let filename = disp
+                .params
+                .get("filename")
+                .or_else(|| part.ctype.params.get("name"))
+                .map(String::as_str)
+                .unwrap_or("noname");
+            parts
+                .attachments
+                .push(format!("{} ({})", strip_controls(filename), mime));
+        }
+    }
+}
+
+/// Remove control characters, except new lines and tabulations.
+fn strip_controls(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect()
+}
+
+/// Replace the links of the given text by numbered footnotes, listed at the end.
+fn footnote_links(text: &str) -> String {
+    let link =
+        Regex::new(r#"<?((?:https?|ftp)://[^\s<>"]+|mailto:[^\s<>"]+|www\.[^\s<>"]+)>?"#).unwrap();
+    let mut urls: Vec<String> = vec![];
+    let text = link
+        .replace_all(text, |captures: &regex::Captures| {
+            let url = captures[1].trim_end_matches(|c| ".,;:!?)".contains(c));
+            let trailing = &captures[1][url.len()..];
+            let n = match urls.iter().position(|known| known == url) {
+                Some(index) => index + 1,
+                None => {
+                    urls.push(url.to_owned());
+                    urls.len()
+                }
+            };
+            format!("[{}]{}", n, trailing)
+        })
+        .into_owned();
+
+    if urls.is_empty() {
+        return text;
+    }
+    let notes: Vec<String> = urls
+        .iter()
+        .enumerate()
+        .map(|(index, url)| format!("[{}] {}", index + 1, url))
+        .collect();
+    format!("{}\n\nLinks:\n{}", text.trim_end(), notes.join("\n"))
+}
+
+/// Render the given raw message in the sandbox.
+pub fn render(raw_msg: &[u8]) -> Result<String> {
+    let parsed = mailparse::parse_mail(raw_msg).context("cannot parse message")?;
+
+    let mut lines = vec![];
+    for key in HEADERS {
+        if let Some(val) = parsed.headers.get_first_value(key) {
+            lines.push(format!("{}: {}", key, strip_controls(&val)));
+        }
+    }
+
+    let mut parts = SandboxParts::default();
+    collect_parts(&parsed, &mut parts);
+    let text = if !parts.plain.is_empty() {
+        parts.plain.join("\n\n")
+    } else {
+        parts
+            .html
+            .iter()
+            .map(|html| msg_html::to_text(html))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    lines.push(String::new());
+    lines.push(footnote_links(&strip_controls(&text)));
+
+    if !parts.attachments.is_empty() {
+        lines.push(String::new());
+        lines.push(String::from("Attachments (not decoded):"));
+        lines.extend(parts.attachments.iter().map(|att| format!("- {}", att)));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_render_messages_in_sandbox() {
+        let raw_msg = concat!(
+            "From: Bank <security@bank.example>\r\n",
+            "Subject: Verify \x1b[31myour\x1b[0m account\r\n",
+            "Content-Type: multipart/mixed; boundary=b\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: text/html\r\n",
+            "\r\n",
+            "<p>Click <a href=\"http://evil.example/login\">here</a>.</p>\r\n",
+            "--b\r\n",
+            "Content-Type: application/octet-stream; name=invoice.exe\r\n",
+            "Content-Disposition: attachment; filename=invoice.exe\r\n",
+            "\r\n",
+            "TVqQAAMAAAAEAAAA\r\n",
+            "--b--\r\n",
+        );
+        let expected = [
+            "From: Bank <security@bank.example>",
+            "Subject: Verify [31myour[0m account",
+            "",
+            "Click here [1].",
+            "",
+            "Links:",
+            "[1] http://evil.example/login",
+            "",
+            "Attachments (not decoded):",
+            "- invoice.exe (application/octet-stream)",
+        ]
+        .join("\n");
+        assert_eq!(expected, render(raw_msg.as_bytes()).unwrap());
+    }
+}
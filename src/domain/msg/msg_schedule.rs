@@ -0,0 +1,161 @@
+//! Module related to time proposals.
+//!
+//! This module turns a short list of time slots like `mon 10-12, tue 14:30-16` into a block of
+//! dates ready to be sent, so that meetings can be scheduled without a calendar. A slot starts
+//! with a day (a weekday, `today`, `tomorrow` or a `YYYY-MM-DD` date) followed by a range of
+//! hours. Weekdays refer to their next occurrence.
+//!
+//! When the time zone of the recipient is known, each slot is also given in their time.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+/// Represents a proposed time slot, in local time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSlot {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+/// Parse the given day, telling if it is a weekday.
+fn parse_day(day: &str, today: NaiveDate) -> Result<(NaiveDate, bool)> {
+    let weekday = match day.to_lowercase().as_str() {
+        "today" => return Ok((today, false)),
+        "tomorrow" => return Ok((today + Duration::days(1), false)),
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tuesday" => Weekday::Tue,
+        "wed" | "wednesday" => Weekday::Wed,
+        "thu" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        day => {
+            return NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map(|date| (date, false))
+                .context(format!(r#"cannot parse proposed day "{}""#, day))
+        }
+    };
+    let days = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    Ok((today + Duration::days(days as i64), true))
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    let (hour, min) = time.split_once(':').unwrap_or((time, "0"));
+    match (hour.parse(), min.parse()) {
+        (Ok(hour), Ok(min)) => NaiveTime::from_hms_opt(hour, min, 0),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!(r#"cannot parse proposed time "{}""#, time))
+}
+
+/// Parse the given time slots, separated by commas. Weekdays refer to their next occurrence
+/// from `now`, today included if the slot has not started yet.
+pub fn parse_slots(slots: &str, now: NaiveDateTime) -> Result<Vec<TimeSlot>> {
+    slots
+        .split(',')
+        .map(str::trim)
+        .filter(|slot| !slot.is_empty())
+        .map(|slot| {
+            let (day, range) = slot.split_once(char::is_whitespace).ok_or_else(|| {
+                anyhow!(
+                    r#"cannot parse proposed slot "{}": expected a day and a range of hours"#,
+                    slot
+                )
+            })?;
+            let (start, end) = range.trim().split_once('-').ok_or_else(|| {
+                anyhow!(
+                    r#"cannot parse proposed slot "{}": expected a range of hours"#,
+                    slot
+                )
+            })?;
+            let (start, end) = (parse_time(start.trim())?, parse_time(end.trim())?);
+            if end <= start {
+                return Err(anyhow!(
+                    r#"cannot parse proposed slot "{}": it ends before it starts"#,
+                    slot
+                ));
+            }
+
+            let (mut date, is_weekday) = parse_day(day, now.date())?;
+            if is_weekday && date.and_time(start) <= now {
+                date = date + Duration::days(7);
+            }
+            Ok(TimeSlot {
+                start: date.and_time(start),
+                end: date.and_time(end),
+            })
+        })
+        .collect()
+}
+
+fn format_slot(slot: &TimeSlot, offset: &FixedOffset) -> String {
+    format!(
+        "{}–{} UTC{}",
+        slot.start.format("%a %d %b, %H:%M"),
+        slot.end.format("%H:%M"),
+        offset
+    )
+}
+
+/// Format the given local time slots as a block of text, giving them in the recipient time too
+/// when their offset is known and differs.
+pub fn format_slots(
+    slots: &[TimeSlot],
+    local: FixedOffset,
+    recipient: Option<FixedOffset>,
+) -> String {
+    let mut block = String::from("Would one of these times work for you?\n");
+    for slot in slots {
+        block.push_str("\n- ");
+        block.push_str(&format_slot(slot, &local));
+        if let Some(recipient) = recipient.filter(|recipient| recipient != &local) {
+            let shift =
+                Duration::seconds((recipient.local_minus_utc() - local.local_minus_utc()) as i64);
+            let recipient_slot = TimeSlot {
+                start: slot.start + shift,
+                end: slot.end + shift,
+            };
+            block.push_str(&format!(
+                " ({} for you)",
+                format_slot(&recipient_slot, &recipient)
+            ));
+        }
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_format_proposed_times() {
+        // A Wednesday
+        let now = NaiveDate::from_ymd(2021, 11, 17).and_hms(11, 0, 0);
+        let slots = parse_slots("mon 10-12, wed 9:30-10, Wed 14-16,", now).unwrap();
+        assert_eq!(
+            vec![
+                NaiveDate::from_ymd(2021, 11, 22).and_hms(10, 0, 0),
+                NaiveDate::from_ymd(2021, 11, 24).and_hms(9, 30, 0),
+                NaiveDate::from_ymd(2021, 11, 17).and_hms(14, 0, 0),
+            ],
+            slots.iter().map(|slot| slot.start).collect::<Vec<_>>()
+        );
+
+        let paris = FixedOffset::east(3600);
+        let new_york = FixedOffset::west(5 * 3600);
+        assert_eq!(
+            "Would one of these times work for you?\n\n- Mon 22 Nov, 10:00–12:00 UTC+01:00 (Mon 22 Nov, 04:00–06:00 UTC-05:00 for you)",
+            format_slots(&slots[..1], paris, Some(new_york))
+        );
+        assert_eq!(
+            "Would one of these times work for you?\n\n- Mon 22 Nov, 10:00–12:00 UTC+01:00",
+            format_slots(&slots[..1], paris, Some(paris))
+        );
+
+        assert!(parse_slots("mon", now).is_err());
+        assert!(parse_slots("someday 10-12", now).is_err());
+        assert!(parse_slots("mon 12-10", now).is_err());
+        assert!(parse_slots("mon 10-25", now).is_err());
+    }
+}
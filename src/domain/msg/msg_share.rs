@@ -0,0 +1,131 @@
+//! Module related to attachment sharing.
+//!
+//! This module uploads the attachments too big to be sent through the user-defined share command,
+//! and replaces them by the link the command prints, like webmails do for large files.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, trace};
+use std::fs;
+use uuid::Uuid;
+
+use crate::{
+    config::system_mode,
+    domain::msg::{Msg, Part, TextPlainPart},
+    output::run_cmd,
+};
+
+/// Format a size in bytes for humans.
+pub fn human_size(size: usize) -> String {
+    match size {
+        size if size >= 1 << 30 => format!("{:.1} GiB", size as f64 / (1 << 30) as f64),
+        size if size >= 1 << 20 => format!("{:.1} MiB", size as f64 / (1 << 20) as f64),
+        size if size >= 1 << 10 => format!("{:.1} KiB", size as f64 / (1 << 10) as f64),
+        size => format!("{} B", size),
+    }
+}
+
+/// Get the link printed by the share command, on its last non-empty line.
+fn parse_link(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .last()
+}
+
+/// Upload the attachments bigger than the given size, in bytes, through the given share command,
+/// and replace them by their links in the text of the message. The `{path}` and `{filename}`
+/// placeholders of the command are replaced by the path of the attachment saved in a temporary
+/// directory and by its file name.
+pub fn share_attachments(msg: &Msg, cmd: &str, max_size: usize) -> Result<Msg> {
+    let dir = system_mode::runtime_dir().join(format!("himalaya-share-{}", Uuid::new_v4()));
+    let mut links = vec![];
+    let mut parts = vec![];
+
+    for part in msg.parts.iter() {
+        match part {
+            Part::Binary(part) if part.content.len() > max_size => {
+                fs::create_dir_all(&dir)
+                    .context(format!("cannot create share directory {:?}", dir))?;
+                let path = dir.join(&part.filename);
+                fs::write(&path, &part.content)
+                    .context(format!("cannot save attachment {:?} to share it", path))?;
+
+                let cmd = cmd
+                    .replace("{path}", &path.to_string_lossy())
+                    .replace("{filename}", &part.filename);
+                debug!("share attachment {} with cmd {}", part.filename, cmd);
+                let output = run_cmd(&cmd);
+                fs::remove_file(&path).ok();
+                let output = output.context(format!(
+                    r#"cannot run share cmd for attachment "{}""#,
+                    part.filename
+                ))?;
+                trace!("share cmd output: {}", output);
+                let link = parse_link(&output).ok_or_else(|| {
+                    anyhow!(
+                        r#"cannot share attachment "{}": share cmd printed no link"#,
+                        part.filename
+                    )
+                })?;
+                links.push(format!(
+                    "- {} ({}): {}",
+                    part.filename,
+                    human_size(part.content.len()),
+                    link
+                ));
+            }
+            part => parts.push(part.clone()),
+        }
+    }
+    fs::remove_dir(&dir).ok();
+
+    let mut shared = Msg {
+        subject: msg.subject.to_owned(),
+        from: msg.from.to_owned(),
+        reply_to: msg.reply_to.to_owned(),
+        to: msg.to.to_owned(),
+        cc: msg.cc.to_owned(),
+        bcc: msg.bcc.to_owned(),
+        in_reply_to: msg.in_reply_to.to_owned(),
+        message_id: msg.message_id.to_owned(),
+        headers: msg.headers.to_owned(),
+        ..Msg::default()
+    };
+    shared.parts.0 = parts;
+    if links.is_empty() {
+        return Ok(shared);
+    }
+
+    let note = format!("\n\nShared attachments:\n{}\n", links.join("\n"));
+    match shared.parts.iter_mut().find_map(|part| match part {
+        Part::TextPlain(part) => Some(part),
+        _ => None,
+    }) {
+        Some(part) => part.content.push_str(&note),
+        None => shared.parts.insert(
+            0,
+            Part::TextPlain(TextPlainPart {
+                content: note.trim_start().to_owned(),
+            }),
+        ),
+    }
+
+    Ok(shared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_share_cmd_output() {
+        assert_eq!(
+            Some("https://share.example.org/s/42"),
+            parse_link("Uploading...\nhttps://share.example.org/s/42\n\n")
+        );
+        assert_eq!(None, parse_link("\n  \n"));
+        assert_eq!("2.5 MiB", human_size(5 << 19));
+        assert_eq!("512 B", human_size(512));
+    }
+}
@@ -0,0 +1,129 @@
+//! Module related to signatures.
+//!
+//! Signatures are separated from the body by a delimiter, the standard one being `-- ` alone on
+//! its line, so that mail clients can recognize them and leave them out of quotes. In replies
+//! and forwards, they go either below everything or above the quoted message (see
+//! [`SigPosition`]).
+
+use crate::config::SigPosition;
+
+/// The line introducing forwarded messages.
+const FORWARD_HEADER: &str = "-------- Forwarded Message --------";
+
+/// Check if the given line is a signature delimiter. The standard one holds a trailing space,
+/// but plenty of clients and editors strip it.
+pub fn is_delim(line: &str) -> bool {
+    line == "-- " || line == "--"
+}
+
+/// Prefix the given signature with the given delimiter, replacing the one it may already start
+/// with.
+pub fn with_delim(delim: &str, sig: &str) -> String {
+    let sig = sig.trim_end();
+    let sig = match sig.split_once('\n') {
+        Some((line, sig)) if is_delim(line.trim_end_matches('\r')) => sig,
+        None if is_delim(sig) => "",
+        _ => sig,
+    };
+    format!("{}{}", delim, sig)
+}
+
+fn escape_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| htmlescape::encode_minimal(line))
+        .collect::<Vec<_>>()
+        .join("<br>\n")
+}
+
+/// Build the HTML signature from the given text signature, delimiter included.
+pub fn text_to_html(sig: &str) -> String {
+    format!(r#"<div class="signature">{}</div>"#, escape_lines(sig))
+}
+
+/// Prefix the given HTML signature with the given delimiter.
+pub fn html_with_delim(delim: &str, html: &str) -> String {
+    let delim = escape_lines(delim);
+    let glue = if delim.is_empty() { "" } else { "<br>\n" };
+    format!(
+        r#"<div class="signature">{}{}{}</div>"#,
+        delim,
+        glue,
+        html.trim()
+    )
+}
+
+/// Find the position of the quoted message in the given body, attribution line included.
+fn quote_start(body: &str) -> Option<usize> {
+    let mut pos = 0;
+    let mut attribution = None;
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed.starts_with('>') || trimmed == FORWARD_HEADER {
+            return Some(attribution.unwrap_or(pos));
+        }
+        attribution = match trimmed {
+            "" => attribution,
+            line if line.ends_with(':') => Some(pos),
+            _ => None,
+        };
+        pos += line.len();
+    }
+    None
+}
+
+/// Insert the given signature in the given body, below everything or above the quoted message
+/// when there is one.
+pub fn insert(body: &str, sig: &str, pos: SigPosition) -> String {
+    let start = match pos {
+        SigPosition::AboveQuote => quote_start(body),
+        SigPosition::BelowQuote => None,
+    };
+    match start {
+        Some(start) => {
+            let (head, quote) = body.split_at(start);
+            // Keep the blank lines left to write the reply above the signature.
+            let head = if head.trim().is_empty() {
+                head.to_owned()
+            } else {
+                format!("{}\n\n", head.trim_end())
+            };
+            format!("{}{}\n\n{}", head, sig, quote)
+        }
+        None => format!("{}\n\n{}", body, sig),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_insert_signatures() {
+        assert_eq!("-- \nBob", with_delim("-- \n", "--\nBob\n"));
+        assert_eq!("-- \nBob", with_delim("-- \n", "Bob"));
+        assert_eq!(
+            "<div class=\"signature\">-- <br>\nBob &amp; co</div>",
+            text_to_html("-- \nBob & co")
+        );
+
+        let reply = "\n\nOn 1 Jan, Alice wrote:\n> Hi\n>\n> -- \n> Alice";
+        assert_eq!(
+            "\n\n-- \nBob\n\nOn 1 Jan, Alice wrote:\n> Hi\n>\n> -- \n> Alice",
+            insert(reply, "-- \nBob", SigPosition::AboveQuote)
+        );
+        assert_eq!(
+            format!("{}\n\n-- \nBob", reply),
+            insert(reply, "-- \nBob", SigPosition::BelowQuote)
+        );
+
+        let forward = "See below.\n\n-------- Forwarded Message --------\nSubject: Hi\n";
+        assert_eq!(
+            "See below.\n\n-- \nBob\n\n-------- Forwarded Message --------\nSubject: Hi\n",
+            insert(forward, "-- \nBob", SigPosition::AboveQuote)
+        );
+        assert_eq!(
+            "Hello\n\n-- \nBob",
+            insert("Hello", "-- \nBob", SigPosition::AboveQuote)
+        );
+    }
+}
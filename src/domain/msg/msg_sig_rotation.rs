@@ -0,0 +1,86 @@
+//! Module related to signature rotation.
+//!
+//! Instead of a single signature, an account can define a list of `signatures`, picked in turn
+//! (`round-robin`, each sent message moving to the next one) or by day of the week (`weekday`,
+//! the first signature on Mondays), or a `signature-cmd` whose output is the signature. The
+//! signature is picked when the account is loaded, so that it lands in the generated templates.
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Local};
+use log::{debug, warn};
+
+use crate::{
+    config::{Account, SigRotation},
+    domain::storage::build_storage,
+    output::pipe_cmd,
+};
+
+const KEY: &str = "signature-rotation";
+
+/// Read the number of messages sent since the rotation started.
+fn counter(account: &Account) -> usize {
+    build_storage(account)
+        .and_then(|storage| storage.get(KEY))
+        .ok()
+        .flatten()
+        .and_then(|counter| String::from_utf8_lossy(&counter).trim().parse().ok())
+        .unwrap_or_default()
+}
+
+/// Find the index of the signature to use among the given number of signatures.
+fn index(rotation: SigRotation, counter: usize, weekday: u32, len: usize) -> usize {
+    match rotation {
+        SigRotation::RoundRobin => counter % len,
+        SigRotation::Weekday => weekday as usize % len,
+    }
+}
+
+/// Pick the signature of the account, from the given command or else from the given
+/// signatures.
+pub fn pick(
+    account: &Account,
+    cmd: Option<&str>,
+    sigs: &[String],
+    rotation: SigRotation,
+) -> Result<Option<String>> {
+    if let Some(cmd) = cmd {
+        let sig = pipe_cmd(cmd, &[]).context("cannot run signature cmd")?;
+        return Ok(Some(String::from_utf8_lossy(&sig).into_owned()));
+    }
+    if sigs.is_empty() {
+        return Ok(None);
+    }
+    let weekday = Local::now().weekday().num_days_from_monday();
+    let index = index(rotation, counter(account), weekday, sigs.len());
+    debug!("pick signature {} of {}", index + 1, sigs.len());
+    Ok(Some(sigs[index].to_owned()))
+}
+
+/// Move to the next signature of the round-robin rotation, once a message is sent.
+pub fn advance(account: &Account) {
+    if account.sig_rotation != Some(SigRotation::RoundRobin) {
+        return;
+    }
+    let res = account.lock_cache().and_then(|_lock| {
+        let counter = (counter(account) + 1).to_string();
+        build_storage(account)?
+            .set(KEY, counter.as_bytes())
+            .context("cannot save signature rotation")
+    });
+    if let Err(err) = res {
+        warn!("cannot advance signature rotation: {:#}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_rotate_signatures() {
+        assert_eq!(0, index(SigRotation::RoundRobin, 0, 4, 3));
+        assert_eq!(1, index(SigRotation::RoundRobin, 4, 4, 3));
+        assert_eq!(4, index(SigRotation::Weekday, 2, 4, 7));
+        assert_eq!(1, index(SigRotation::Weekday, 2, 6, 5));
+    }
+}
@@ -0,0 +1,403 @@
+//! Module related to S/MIME.
+//!
+//! Messages are signed and encrypted with [S/MIME] by the `openssl` command, according to the
+//! `smime-sign` and `smime-encrypt` account options. The MIME entity of the message (its
+//! `Content-*` header fields and its body) is protected, the other header fields being kept as
+//! they are. Messages are encrypted for the certificates of the recipients found in
+//! `smime-certs-dir`, and for the one of the account, so that the copy saved in the Sent folder
+//! stays readable.
+//!
+//! Received messages are verified and decrypted the same way: the outcome is shown as a banner
+//! above the text of the message.
+//!
+//! [S/MIME]: https://datatracker.ietf.org/doc/html/rfc8551
+
+use anyhow::{anyhow, Context, Result};
+use lettre::address::Envelope;
+use log::{debug, warn};
+use mailparse::ParsedMail;
+use std::{
+    env, fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{
+    config::Account,
+    domain::msg::{Msg, Part},
+};
+
+/// Counts the temporary files written during the run.
+static TMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents the kind of S/MIME protection of a MIME entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SmimeKind {
+    Signed,
+    Enveloped,
+}
+
+/// Represents the outcome of the verification and decryption of a message.
+#[derive(Debug, Default, PartialEq)]
+pub struct SmimeReport {
+    /// The banner lines, one per protection layer.
+    pub banners: Vec<String>,
+    /// The MIME entity once decrypted or extracted from its signature, if any.
+    pub content: Option<Vec<u8>>,
+}
+
+impl fmt::Display for SmimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for banner in self.banners.iter() {
+            writeln!(f, "[S/MIME] {}", banner)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check if the given MIME type is one of the S/MIME ones.
+pub fn is_pkcs7(mime: &str) -> bool {
+    let mime = mime.to_lowercase();
+    mime.starts_with("application/pkcs7-") || mime.starts_with("application/x-pkcs7-")
+}
+
+/// Check if the given message carries an S/MIME signature or encrypted content.
+pub fn is_smime(msg: &Msg) -> bool {
+    msg.parts.iter().any(|part| match part {
+        Part::Binary(part) => {
+            let filename = part.filename.to_lowercase();
+            is_pkcs7(&part.mime) || filename.ends_with(".p7m") || filename.ends_with(".p7s")
+        }
+        _ => false,
+    })
+}
+
+/// Get the kind of S/MIME protection of the given entity, if any.
+fn kind(part: &ParsedMail) -> Option<SmimeKind> {
+    let param = |key: &str| {
+        part.ctype
+            .params
+            .get(key)
+            .map(|val| val.to_lowercase())
+            .unwrap_or_default()
+    };
+    match part.ctype.mimetype.to_lowercase().as_str() {
+        "multipart/signed" if param("protocol").contains("pkcs7-signature") => {
+            Some(SmimeKind::Signed)
+        }
+        "application/pkcs7-mime" | "application/x-pkcs7-mime" => {
+            match param("smime-type").as_str() {
+                "signed-data" => Some(SmimeKind::Signed),
+                // The type is optional, enveloped data being the most common.
+                "" | "enveloped-data" | "authenveloped-data" => Some(SmimeKind::Enveloped),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Run the given openssl command on the given input, and return its output. The error is the
+/// first line printed by openssl.
+fn run(cmd: &mut Command, input: &[u8]) -> Result<Vec<u8>> {
+    debug!("run openssl command {:?}", cmd);
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("cannot run openssl")?;
+    // The input is written aside, so that a big output cannot block openssl.
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("cannot write to openssl"))?;
+    let input = input.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().context("cannot run openssl")?;
+    writer.join().ok();
+
+    if output.status.success() {
+        return Ok(output.stdout);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+        Some(line) => Err(anyhow!("{}", line)),
+        None => Err(anyhow!("openssl exited with {}", output.status)),
+    }
+}
+
+fn openssl(args: &[&str]) -> Command {
+    let mut cmd = Command::new("openssl");
+    cmd.args(args);
+    cmd
+}
+
+/// Get the certificate and the key of the account.
+fn cert_and_key<'a>(account: &'a Account, action: &str) -> Result<(&'a Path, &'a Path)> {
+    match (account.smime_cert.as_deref(), account.smime_key.as_deref()) {
+        (Some(cert), Some(key)) => Ok((cert, key)),
+        _ => Err(anyhow!(
+            "cannot {}: `smime-cert` and `smime-key` are not defined",
+            action
+        )),
+    }
+}
+
+/// Split the given raw message between its header fields and its MIME entity. The MIME-Version
+/// header field is dropped, as openssl writes its own.
+fn split_entity(raw_msg: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let (head, body) = match raw_msg.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => (&raw_msg[..pos + 2], &raw_msg[pos + 4..]),
+        None => (raw_msg, &[][..]),
+    };
+
+    let mut fields: Vec<&[u8]> = vec![];
+    let mut start = 0;
+    for (pos, _) in head.iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+        let next = head.get(pos + 1);
+        // Folded lines belong to the field above.
+        if next != Some(&b' ') && next != Some(&b'\t') {
+            fields.push(&head[start..pos + 1]);
+            start = pos + 1;
+        }
+    }
+    if start < head.len() {
+        fields.push(&head[start..]);
+    }
+
+    let mut headers = vec![];
+    let mut entity = vec![];
+    for field in fields {
+        let name = field.split(|byte| *byte == b':').next().unwrap_or_default();
+        let name = String::from_utf8_lossy(name).trim().to_lowercase();
+        if name == "mime-version" {
+            continue;
+        } else if name.starts_with("content-") {
+            entity.extend_from_slice(field);
+        } else {
+            headers.extend_from_slice(field);
+        }
+    }
+    entity.extend_from_slice(b"\r\n");
+    entity.extend_from_slice(body);
+    (headers, entity)
+}
+
+fn sign(entity: &[u8], account: &Account) -> Result<Vec<u8>> {
+    let (cert, key) = cert_and_key(account, "sign message")?;
+    run(
+        openssl(&["smime", "-sign", "-crlfeol", "-signer"])
+            .arg(cert)
+            .arg("-inkey")
+            .arg(key),
+        entity,
+    )
+    .context("cannot sign message")
+}
+
+fn encrypt(entity: &[u8], envelope: &Envelope, account: &Account) -> Result<Vec<u8>> {
+    let dir = account
+        .smime_certs_dir
+        .as_deref()
+        .ok_or_else(|| anyhow!("cannot encrypt message: `smime-certs-dir` is not defined"))?;
+    let mut certs: Vec<PathBuf> = vec![];
+    for addr in envelope.to() {
+        let cert = dir.join(format!("{}.pem", addr.to_string().to_lowercase()));
+        if !cert.is_file() {
+            return Err(anyhow!(
+                "cannot encrypt message: missing S/MIME certificate of {} ({:?})",
+                addr,
+                cert
+            ));
+        }
+        certs.push(cert);
+    }
+    if let Some(cert) = account.smime_cert.as_ref() {
+        certs.push(cert.to_owned());
+    }
+    run(
+        openssl(&["smime", "-encrypt", "-aes256", "-crlfeol"]).args(&certs),
+        entity,
+    )
+    .context("cannot encrypt message")
+}
+
+/// Build the given message, then sign it and/or encrypt it according to the account. Returns
+/// the envelope and the raw message to send.
+pub fn protect(msg: &Msg, account: &Account) -> Result<(Envelope, Vec<u8>)> {
+    // Signed content must not be re-encoded on the way, so 8-bit parts are left out.
+    let sendable_msg = msg.to_sendable_msg(false, account.format_flowed)?;
+    let envelope = sendable_msg.envelope().to_owned();
+    let (mut raw_msg, mut entity) = split_entity(&msg.format_sendable_msg(&sendable_msg));
+    if account.smime_sign {
+        entity = sign(&entity, account)?;
+    }
+    if account.smime_encrypt {
+        entity = encrypt(&entity, &envelope, account)?;
+    }
+    raw_msg.extend(entity);
+    Ok((envelope, raw_msg))
+}
+
+/// Verify the signature of the given entity. Returns the signed content and the addresses of the
+/// signer.
+fn verify(entity: &[u8], account: &Account) -> Result<(Vec<u8>, Vec<String>)> {
+    let signer = env::temp_dir().join(format!(
+        "himalaya-smime-{}-{}.pem",
+        process::id(),
+        TMP_COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let mut cmd = openssl(&["smime", "-verify"]);
+    if let Some(ca) = account.smime_ca_cert.as_ref() {
+        cmd.arg("-CAfile").arg(ca);
+    }
+    let res = run(cmd.arg("-signer").arg(&signer), entity).and_then(|content| {
+        let emails = run(
+            openssl(&["x509", "-noout", "-email", "-in"]).arg(&signer),
+            &[],
+        )?;
+        let emails = String::from_utf8_lossy(&emails)
+            .lines()
+            .map(|email| email.trim().to_owned())
+            .filter(|email| !email.is_empty())
+            .collect();
+        Ok((content, emails))
+    });
+    fs::remove_file(&signer).ok();
+    res
+}
+
+fn decrypt(entity: &[u8], account: &Account) -> Result<Vec<u8>> {
+    let (cert, key) = cert_and_key(account, "decrypt message")?;
+    run(
+        openssl(&["smime", "-decrypt", "-recip"])
+            .arg(cert)
+            .arg("-inkey")
+            .arg(key),
+        entity,
+    )
+}
+
+fn check_signature(entity: &[u8], account: &Account, report: &mut SmimeReport) {
+    match verify(entity, account) {
+        Ok((content, signers)) if signers.is_empty() => {
+            report.banners.push(String::from("Signature verified"));
+            report.content = Some(content);
+        }
+        Ok((content, signers)) => {
+            report.banners.push(format!(
+                "Signature verified (signed by {})",
+                signers.join(", ")
+            ));
+            report.content = Some(content);
+        }
+        Err(err) => {
+            warn!("cannot verify S/MIME signature: {}", err);
+            report
+                .banners
+                .push(format!("Signature NOT verified: {}", err));
+        }
+    }
+}
+
+/// Verify and decrypt the given raw message. Returns `None` when the message is not protected
+/// with S/MIME. Verification and decryption failures are reported as banners.
+pub fn check(raw_msg: &[u8], account: &Account) -> Result<Option<SmimeReport>> {
+    let parsed_mail = mailparse::parse_mail(raw_msg).context("cannot parse message")?;
+    let mut report = SmimeReport::default();
+    match kind(&parsed_mail) {
+        None => return Ok(None),
+        Some(SmimeKind::Signed) => check_signature(raw_msg, account, &mut report),
+        Some(SmimeKind::Enveloped) => match decrypt(raw_msg, account) {
+            Ok(content) => {
+                report.banners.push(String::from("Decrypted"));
+                // Messages are usually signed, then encrypted.
+                let signed = mailparse::parse_mail(&content)
+                    .map(|part| kind(&part) == Some(SmimeKind::Signed))
+                    .unwrap_or_default();
+                if signed {
+                    check_signature(&content, account, &mut report);
+                }
+                report.content.get_or_insert(content);
+            }
+            Err(err) => report.banners.push(format!("Cannot decrypt: {}", err)),
+        },
+    }
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_split_and_detect_smime_entities() {
+        let raw_msg = concat!(
+            "From: jane@doe.org\r\n",
+            "Subject: Hello\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: text/plain;\r\n",
+            "\tcharset=utf-8\r\n",
+            "Content-Transfer-Encoding: 7bit\r\n",
+            "\r\n",
+            "Hello!\r\n",
+        );
+        let (headers, entity) = split_entity(raw_msg.as_bytes());
+        assert_eq!(
+            "From: jane@doe.org\r\nSubject: Hello\r\n",
+            String::from_utf8(headers).unwrap()
+        );
+        assert_eq!(
+            concat!(
+                "Content-Type: text/plain;\r\n",
+                "\tcharset=utf-8\r\n",
+                "Content-Transfer-Encoding: 7bit\r\n",
+                "\r\n",
+                "Hello!\r\n",
+            ),
+            String::from_utf8(entity).unwrap()
+        );
+
+        let kind_of = |ctype: &str| {
+            let raw_msg = format!("Content-Type: {}\r\n\r\n", ctype);
+            kind(&mailparse::parse_mail(raw_msg.as_bytes()).unwrap())
+        };
+        assert_eq!(None, kind_of("text/plain"));
+        assert_eq!(
+            Some(SmimeKind::Signed),
+            kind_of(r#"multipart/signed; protocol="application/pkcs7-signature"; boundary=b"#)
+        );
+        assert_eq!(
+            None,
+            kind_of(r#"multipart/signed; protocol="application/pgp-signature"; boundary=b"#)
+        );
+        assert_eq!(
+            Some(SmimeKind::Signed),
+            kind_of("application/pkcs7-mime; smime-type=signed-data")
+        );
+        assert_eq!(
+            Some(SmimeKind::Enveloped),
+            kind_of("application/x-pkcs7-mime; name=smime.p7m")
+        );
+        assert_eq!(
+            None,
+            kind_of("application/pkcs7-mime; smime-type=certs-only")
+        );
+
+        let report = SmimeReport {
+            banners: vec![
+                String::from("Decrypted"),
+                String::from("Signature verified"),
+            ],
+            content: None,
+        };
+        assert_eq!(
+            "[S/MIME] Decrypted\n[S/MIME] Signature verified\n",
+            report.to_string()
+        );
+    }
+}
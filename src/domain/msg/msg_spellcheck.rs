@@ -0,0 +1,182 @@
+//! Module related to message spell checking.
+//!
+//! This module detects the language of a message and runs the user-defined spell-check command
+//! over its text, in order to report misspellings before sending.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, trace};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Language used when the detection does not give any result.
+const DEFAULT_LANG: &str = "en";
+
+/// Most frequent words of the supported languages, identified by their ISO 639-1 code.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "you", "that", "this", "with", "for", "have", "not", "it",
+            "of", "to",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ich", "sie", "mit", "ein", "eine", "zu",
+            "auf", "für",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "est", "une", "je", "vous", "pas", "pour", "que", "dans",
+            "avec", "des",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "y", "es", "una", "que", "por", "para", "con", "no", "del",
+            "usted",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "è", "e", "la", "per", "una", "non", "sono", "con", "gli", "del",
+            "mi",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "e", "que", "não", "uma", "para", "com", "do", "da", "você", "em", "é", "um",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "is", "niet", "ik", "je", "van", "dat", "op", "met", "voor",
+            "zijn",
+        ],
+    ),
+    (
+        "sv",
+        &[
+            "och", "att", "det", "är", "en", "jag", "inte", "på", "med", "för", "som", "har", "du",
+            "av",
+        ],
+    ),
+];
+
+/// Detect the language of the given text, by counting the most frequent words of each supported
+/// language. Returns the ISO 639-1 code of the language, if any matched.
+pub fn detect_lang(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+
+    STOPWORDS
+        .iter()
+        .map(|(lang, stopwords)| {
+            let score = words
+                .iter()
+                .filter(|word| stopwords.contains(&word.as_str()))
+                .count();
+            (*lang, score)
+        })
+        .filter(|(_, score)| *score > 0)
+        .max_by_key(|(_, score)| *score)
+        .map(|(lang, _)| lang)
+}
+
+/// Run the spell-check command over the given text and return the misspelled words. The `{lang}`
+/// placeholder of the command is replaced by the given language, or by the detected one.
+///
+/// The command receives the text on its standard input and is expected to print one misspelled
+/// word per line, like `aspell --lang={lang} list` or `hunspell -d {lang} -l`.
+pub fn spellcheck<'a>(
+    cmd: &str,
+    text: &str,
+    lang: Option<&'a str>,
+) -> Result<(&'a str, Vec<String>)> {
+    let lang = lang.or_else(|| detect_lang(text)).unwrap_or(DEFAULT_LANG);
+    debug!("spellcheck language: {}", lang);
+    let cmd = cmd.replace("{lang}", lang);
+    debug!("spellcheck cmd: {}", cmd);
+
+    let mut child = if cfg!(target_os = "windows") {
+        Command::new("cmd")
+            .args(&["/C", &cmd])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(&cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+    }
+    .context(format!(r#"cannot run spellcheck cmd "{}""#, cmd))?;
+
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!(r#"cannot open stdin of spellcheck cmd "{}""#, cmd))?
+        .write_all(text.as_bytes())
+        .context(format!(
+            r#"cannot pipe message to spellcheck cmd "{}""#,
+            cmd
+        ))?;
+
+    let output = child
+        .wait_with_output()
+        .context(format!(r#"cannot wait for spellcheck cmd "{}""#, cmd))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            r#"cannot check spelling: spellcheck cmd "{}" exited with {}"#,
+            cmd,
+            output.status
+        ));
+    }
+
+    let mut words: Vec<String> = vec![];
+    for word in String::from_utf8_lossy(&output.stdout).lines() {
+        let word = word.trim();
+        if !word.is_empty() && !words.iter().any(|w| w == word) {
+            words.push(word.to_owned());
+        }
+    }
+    trace!("misspelled words: {:?}", words);
+
+    Ok((lang, words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_detect_lang() {
+        assert_eq!(
+            Some("en"),
+            detect_lang("Hello, this is the report you asked for.")
+        );
+        assert_eq!(
+            Some("de"),
+            detect_lang("Hallo, das ist der Bericht für die Sitzung.")
+        );
+        assert_eq!(
+            Some("fr"),
+            detect_lang("Bonjour, je vous envoie le rapport pour la réunion.")
+        );
+        assert_eq!(None, detect_lang("12345"));
+    }
+}
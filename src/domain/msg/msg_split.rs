@@ -0,0 +1,208 @@
+//! Module related to message splitting.
+//!
+//! This module splits the attachments too big for the server across several messages. Each
+//! of these messages carries one chunk, named like `archive.zip.001`, so that the recipient can
+//! rebuild the original file by concatenating them (eg. `cat archive.zip.0* > archive.zip`).
+
+use anyhow::Result;
+use log::debug;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::Sender,
+        metrics::{self, Metric},
+        msg::{
+            msg_crm, msg_hook, msg_share, msg_sig_rotation, msg_smime, BinaryPart, Msg, Part,
+            Parts, TextPlainPart,
+        },
+        sent::SentLog,
+        webhook::{self, WebhookEvent},
+    },
+};
+
+/// Build a message sharing the recipients and the headers of the given one.
+fn sibling(msg: &Msg, subject: String, parts: Vec<Part>) -> Msg {
+    Msg {
+        subject,
+        from: msg.from.to_owned(),
+        reply_to: msg.reply_to.to_owned(),
+        to: msg.to.to_owned(),
+        cc: msg.cc.to_owned(),
+        bcc: msg.bcc.to_owned(),
+        in_reply_to: msg.in_reply_to.to_owned(),
+        headers: msg.headers.to_owned(),
+        parts: Parts(parts),
+        ..Msg::default()
+    }
+}
+
+/// Split the attachments bigger than the given size, in bytes. Returns `None` when no attachment
+/// needs to be split. Otherwise the first message holds the text and the small attachments, and
+/// each of the following ones holds a chunk. Subjects are numbered, like `Subject [1/3]`.
+pub fn split_attachments(msg: &Msg, max_size: usize) -> Option<Vec<Msg>> {
+    let max_size = max_size.max(1);
+    let (big, small): (Vec<&Part>, Vec<&Part>) = msg.parts.iter().partition(|part| match part {
+        Part::Binary(part) => part.content.len() > max_size,
+        _ => false,
+    });
+    if big.is_empty() {
+        return None;
+    }
+
+    let mut chunks = vec![];
+    let mut note = String::from("\n\nSome attachments were split across the next messages:\n");
+    for part in big {
+        if let Part::Binary(part) = part {
+            let count = (part.content.len() + max_size - 1) / max_size;
+            debug!("split attachment {} in {} chunks", part.filename, count);
+            note.push_str(&format!(
+                "- {} ({} parts), rebuild it with `cat {}.0* > {}`\n",
+                part.filename, count, part.filename, part.filename
+            ));
+            for (i, content) in part.content.chunks(max_size).enumerate() {
+                chunks.push((
+                    format!("Part {}/{} of {}.", i + 1, count, part.filename),
+                    BinaryPart {
+                        filename: format!("{}.{:03}", part.filename, i + 1),
+                        mime: String::from("application/octet-stream"),
+                        content: content.to_vec(),
+                    },
+                ));
+            }
+        }
+    }
+
+    let total = chunks.len() + 1;
+    let numbered = |n: usize| format!("{} [{}/{}]", msg.subject, n, total);
+
+    let mut parts: Vec<Part> = small.into_iter().cloned().collect();
+    let text = msg.join_text_plain_parts();
+    parts.retain(|part| !matches!(part, Part::TextPlain(_)));
+    parts.insert(
+        0,
+        Part::TextPlain(TextPlainPart {
+            content: format!("{}{}", text, note),
+        }),
+    );
+    let mut msgs = vec![sibling(msg, numbered(1), parts)];
+
+    for (i, (text, chunk)) in chunks.into_iter().enumerate() {
+        msgs.push(sibling(
+            msg,
+            numbered(i + 2),
+            vec![
+                Part::TextPlain(TextPlainPart { content: text }),
+                Part::Binary(chunk),
+            ],
+        ));
+    }
+
+    Some(msgs)
+}
+
+/// Send the message, record it in the delivery log and notify the account webhook.
+fn send_one(msg: &Msg, account: &Account, sender: &mut dyn Sender) -> Result<Vec<u8>> {
+    let recipients: Vec<String> = vec![msg.to.as_ref(), msg.cc.as_ref(), msg.bcc.as_ref()]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|addr| addr.email.to_string())
+        .collect();
+    let res = if account.smime_sign || account.smime_encrypt {
+        msg_smime::protect(msg, account).and_then(|(envelope, raw_msg)| {
+            sender.send_raw(&envelope, &raw_msg)?;
+            Ok(raw_msg)
+        })
+    } else {
+        sender.send(msg)
+    };
+    let raw_msg = match res {
+        Ok(raw_msg) => raw_msg,
+        Err(err) => {
+            let event = WebhookEvent::send_failure(&account.name, &msg.subject, recipients, &err);
+            webhook::emit(account, &event);
+            metrics::incr(account, Metric::SendErrors, 1);
+            return Err(err);
+        }
+    };
+    let event = WebhookEvent::send_success(&account.name, &msg.subject, recipients.clone());
+    SentLog::record(account, &raw_msg, recipients, sender.last_response(), None);
+    webhook::emit(account, &event);
+    metrics::incr(account, Metric::SentMsgs, 1);
+    msg_hook::post_send(account, &raw_msg);
+    Ok(raw_msg)
+}
+
+/// Send the message, once the blind copies and headers of the matching `crm-rules` are added,
+/// its big attachments are shared as links according to the account `share-cmd` config field,
+/// then split according to the `split-attachment-size` one. The `pre-send-cmd` hook is run once,
+/// before splitting. Once sent, the signature rotation moves to the next signature. Returns the
+/// messages as sent, so that they can be saved in the Sent folder.
+pub fn send(msg: &Msg, account: &Account, sender: &mut dyn Sender) -> Result<Vec<Vec<u8>>> {
+    let crm_msg = msg_crm::apply(msg, &account.crm_rules)?;
+    let msg = crm_msg.as_ref().unwrap_or(msg);
+    let shared_msg;
+    let msg = match account.share_cmd {
+        Some(ref cmd) => {
+            shared_msg = msg_share::share_attachments(msg, cmd, account.share_attachment_size)?;
+            &shared_msg
+        }
+        None => msg,
+    };
+    msg_hook::pre_send(account, msg)?;
+
+    let sent = match account
+        .split_attachment_size
+        .and_then(|size| split_attachments(msg, size))
+    {
+        Some(msgs) => msgs
+            .iter()
+            .map(|msg| send_one(msg, account, sender))
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![send_one(msg, account, sender)?],
+    };
+    msg_sig_rotation::advance(account);
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_split_big_attachments() {
+        let msg = Msg {
+            subject: String::from("Photos"),
+            parts: Parts(vec![
+                Part::TextPlain(TextPlainPart {
+                    content: String::from("Hello"),
+                }),
+                Part::Binary(BinaryPart {
+                    filename: String::from("small.txt"),
+                    mime: String::from("text/plain"),
+                    content: vec![0; 2],
+                }),
+                Part::Binary(BinaryPart {
+                    filename: String::from("big.zip"),
+                    mime: String::from("application/zip"),
+                    content: vec![0; 9],
+                }),
+            ]),
+            ..Msg::default()
+        };
+        assert!(split_attachments(&msg, 10).is_none());
+
+        let msgs = split_attachments(&msg, 4).unwrap();
+        assert_eq!(4, msgs.len());
+        assert_eq!("Photos [1/4]", msgs[0].subject);
+        assert_eq!(2, msgs[0].parts.len());
+        assert!(msgs[0]
+            .join_text_plain_parts()
+            .contains("big.zip (3 parts)"));
+        assert_eq!("Photos [4/4]", msgs[3].subject);
+        let chunk = &msgs[3].attachments()[0];
+        assert_eq!("big.zip.003", chunk.filename);
+        assert_eq!(1, chunk.content.len());
+    }
+}
@@ -0,0 +1,76 @@
+//! Module related to message summaries.
+//!
+//! This module pipes the text of messages to the user-defined summarize command, which can be
+//! anything reading text on its standard input and printing a summary, from a simple script to a
+//! local language model.
+
+use anyhow::{Context, Result};
+use log::debug;
+
+use crate::{domain::msg::Msg, output::pipe_cmd};
+
+/// Separator between the messages of a thread.
+const MSG_SEPARATOR: &str = "\n\n--------\n\n";
+
+/// Build the text given to the summarize command: the main headers and the text of each message,
+/// oldest first.
+pub fn summary_input(msgs: &[Msg]) -> String {
+    let mut msgs: Vec<&Msg> = msgs.iter().collect();
+    msgs.sort_by_key(|msg| msg.date);
+    msgs.iter()
+        .map(|msg| {
+            let from = msg
+                .from
+                .iter()
+                .flatten()
+                .map(|addr| addr.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let date = msg.date.map(|date| date.to_rfc2822()).unwrap_or_default();
+            format!(
+                "From: {}\nDate: {}\nSubject: {}\n\n{}",
+                from,
+                date,
+                msg.subject,
+                msg.join_text_parts().trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(MSG_SEPARATOR)
+}
+
+/// Run the given summarize command over the given text, returning what it prints.
+pub fn summarize(cmd: &str, text: &str) -> Result<String> {
+    debug!("summarize cmd: {}", cmd);
+    let summary = pipe_cmd(cmd, text.as_bytes()).context("cannot summarize message")?;
+    Ok(String::from_utf8_lossy(&summary).trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::msg::{Part, TextPlainPart};
+
+    #[test]
+    fn it_should_build_summary_input() {
+        let msg = |subject: &str, date: &str, text: &str| {
+            let mut msg = Msg::default();
+            msg.subject = subject.to_owned();
+            msg.from = Some(vec!["alice@localhost".parse().unwrap()]);
+            msg.date = chrono::DateTime::parse_from_rfc2822(date).ok();
+            msg.parts.push(Part::TextPlain(TextPlainPart {
+                content: text.to_owned(),
+            }));
+            msg
+        };
+        let msgs = vec![
+            msg("Re: Lunch", "Tue, 2 Nov 2021 10:00:00 +0000", "Sure!\n"),
+            msg("Lunch", "Mon, 1 Nov 2021 10:00:00 +0000", "Lunch tomorrow?"),
+        ];
+
+        assert_eq!(
+            "From: alice@localhost\nDate: Mon, 01 Nov 2021 10:00:00 +0000\nSubject: Lunch\n\nLunch tomorrow?\n\n--------\n\nFrom: alice@localhost\nDate: Tue, 02 Nov 2021 10:00:00 +0000\nSubject: Re: Lunch\n\nSure!",
+            summary_input(&msgs)
+        );
+    }
+}
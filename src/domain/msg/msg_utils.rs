@@ -1,9 +1,11 @@
 use anyhow::{Context, Result};
 use log::{debug, trace};
-use std::{env, fs, path::PathBuf};
+use std::{fs, path::PathBuf};
+
+use crate::config::{system_mode, ReplyQuote};
 
 pub fn local_draft_path() -> PathBuf {
-    let path = env::temp_dir().join("himalaya-draft.mail");
+    let path = system_mode::runtime_dir().join("himalaya-draft.mail");
     trace!("local draft path: {:?}", path);
     path
 }
@@ -13,3 +15,176 @@ pub fn remove_local_draft() -> Result<()> {
     debug!("remove draft path at {:?}", path);
     fs::remove_file(&path).context(format!("cannot remove local draft at {:?}", path))
 }
+
+/// Localized reply prefixes, as found in subjects sent by common mail clients (English, German,
+/// Scandinavian, Dutch, Finnish, Italian, Polish, Turkish…).
+const REPLY_PREFIXES: &[&str] = &["re", "aw", "sv", "antw", "vs", "ref", "rif", "odp", "ynt"];
+
+/// Localized forward prefixes (English, German, French, Spanish, Portuguese, Swedish, Dutch…).
+const FORWARD_PREFIXES: &[&str] = &["fwd", "fw", "wg", "tr", "rv", "enc", "vb", "doorst"];
+
+/// Split the first prefix of the subject, if it belongs to the given list. Prefixes are matched
+/// case-insensitively and may carry a counter, like `Re[2]:` or `AW(3):`.
+fn split_subject_prefix<'a>(subject: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    let subject = subject.trim_start();
+    let (prefix, rest) = subject.split_once(':')?;
+    let prefix = prefix
+        .trim_end()
+        .trim_end_matches(|c: char| c.is_ascii_digit() || "[]()".contains(c));
+
+    if prefixes.iter().any(|p| p.eq_ignore_ascii_case(prefix)) {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Remove all the leading prefixes of the subject that belong to the given list.
+fn strip_subject_prefixes<'a>(subject: &'a str, prefixes: &[&str]) -> &'a str {
+    let mut subject = subject.trim();
+    while let Some(rest) = split_subject_prefix(subject, prefixes) {
+        subject = rest;
+    }
+    subject
+}
+
+/// Build the subject of a reply, collapsing chains like `Re: AW: Re:` into a single `Re:`.
+pub fn reply_subject(subject: &str) -> String {
+    format!("Re: {}", strip_subject_prefixes(subject, REPLY_PREFIXES))
+}
+
+/// Build the subject of a forward, collapsing chains of forward prefixes into a single `Fwd:` and
+/// chains of reply prefixes into a single `Re:`.
+pub fn forward_subject(subject: &str) -> String {
+    let subject = strip_subject_prefixes(subject, FORWARD_PREFIXES);
+    let base = strip_subject_prefixes(subject, REPLY_PREFIXES);
+    if base.len() == subject.len() {
+        format!("Fwd: {}", base)
+    } else {
+        format!("Fwd: Re: {}", base)
+    }
+}
+
+/// Get the subject without any reply or forward prefix, used to group messages by subject.
+pub fn base_subject(subject: &str) -> &str {
+    let prefixes = [REPLY_PREFIXES, FORWARD_PREFIXES].concat();
+    strip_subject_prefixes(subject, &prefixes)
+}
+
+/// Check if the subject starts with a reply prefix.
+pub fn is_reply_subject(subject: &str) -> bool {
+    split_subject_prefix(subject, REPLY_PREFIXES).is_some()
+}
+
+/// Select the lines of the original message to quote in a reply.
+///
+/// With [`ReplyQuote::Last`], lines quoted from previous messages of the thread are left out,
+/// together with their attribution line. When `matching` is given, only the lines containing it
+/// (case-insensitively) are kept, surrounded by `context` lines, gaps being replaced by `[…]`. If
+/// nothing matches, all the lines are kept.
+pub fn quoted_lines<'a>(
+    text: &'a str,
+    mode: &ReplyQuote,
+    matching: Option<&str>,
+    context: usize,
+) -> Vec<&'a str> {
+    let mut lines: Vec<&str> = text.lines().collect();
+
+    if let ReplyQuote::Last = mode {
+        if let Some(pos) = lines.iter().position(|line| line.starts_with('>')) {
+            lines.truncate(pos);
+            while let Some(line) = lines.last() {
+                if line.trim().is_empty() || line.trim_end().ends_with("wrote:") {
+                    lines.pop();
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    let matching = match matching {
+        Some(matching) => matching.to_lowercase(),
+        None => return lines,
+    };
+    let matches: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(&matching))
+        .map(|(i, _)| i)
+        .collect();
+    trace!("quote matches: {:?}", matches);
+    if matches.is_empty() {
+        debug!("no line matches {:?}, quoting all lines", matching);
+        return lines;
+    }
+
+    let mut window = vec![];
+    let mut next = 0;
+    for i in matches {
+        let begin = i.saturating_sub(context).max(next);
+        let end = (i + context + 1).min(lines.len());
+        if begin > next {
+            window.push("[…]");
+        }
+        window.extend_from_slice(&lines[begin..end]);
+        next = end.max(next);
+    }
+    if next < lines.len() {
+        window.push("[…]");
+    }
+    window
+}
+
+/// Quote the given line with the given prefix. The trailing spaces of the prefix are left out of
+/// empty and already quoted lines, so that nested quotes read `>>`.
+pub fn quote_line(line: &str, prefix: &str) -> String {
+    if line.is_empty() || line.starts_with('>') {
+        format!("{}{}", prefix.trim_end(), line)
+    } else {
+        format!("{}{}", prefix, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THREAD: &str = "Sounds good.\n\nOn Mon, Alice wrote:\n> See you at 10.\n> Bye";
+
+    #[test]
+    fn it_should_quote_last_msg() {
+        assert_eq!(
+            vec!["Sounds good."],
+            quoted_lines(THREAD, &ReplyQuote::Last, None, 2)
+        );
+        assert_eq!(5, quoted_lines(THREAD, &ReplyQuote::Full, None, 2).len());
+        assert_eq!("> Bye", quote_line("Bye", "> "));
+        assert_eq!(">> Bye", quote_line("> Bye", "> "));
+        assert_eq!(">", quote_line("", "> "));
+    }
+
+    #[test]
+    fn it_should_quote_lines_around_match() {
+        let text = "a\nb\nc\nneedle\nd\ne\nf";
+        assert_eq!(
+            vec!["[…]", "c", "needle", "d", "[…]"],
+            quoted_lines(text, &ReplyQuote::Full, Some("NEEDLE"), 1)
+        );
+        assert_eq!(
+            7,
+            quoted_lines(text, &ReplyQuote::Full, Some("nope"), 1).len()
+        );
+    }
+
+    #[test]
+    fn it_should_normalize_subject_prefixes() {
+        assert_eq!("Re: Hello", reply_subject("Re: AW: re[2]: SV: Hello"));
+        assert_eq!("Re: Hello", reply_subject("Hello"));
+        assert_eq!("Re: Fwd: Hello", reply_subject("AW: Fwd: Hello"));
+        assert_eq!("Re: Note: hello", reply_subject("Note: hello"));
+        assert_eq!("Fwd: Re: Hello", forward_subject("RV: WG: AW: Re: Hello"));
+        assert_eq!("Fwd: Hello", forward_subject("Fw: Hello"));
+        assert_eq!("Hello", base_subject("Re: Fwd: AW: Hello"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Module related to message part CLI.
+//!
+//! This module provides subcommands, arguments and a command matcher related to message parts.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+use crate::domain::msg::msg_arg;
+
+type Seq<'a> = &'a str;
+type Token<'a> = &'a str;
+
+/// Message part commands.
+pub enum Command<'a> {
+    List(Seq<'a>),
+    Get(Token<'a>),
+}
+
+/// Message part command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("list") {
+        debug!("list command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        return Ok(Some(Command::List(seq)));
+    }
+
+    if let Some(m) = m.subcommand_matches("get") {
+        debug!("get command matched");
+        let token = m.value_of("token").unwrap();
+        trace!("token: {}", token);
+        return Ok(Some(Command::Get(token)));
+    }
+
+    Ok(None)
+}
+
+/// Message part token argument.
+fn token_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("token")
+        .help("Specifies the part to fetch")
+        .long_help("Specifies the part to fetch, by the token given by `part list`.")
+        .value_name("TOKEN")
+        .required(true)
+}
+
+/// Message part subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("part")
+        .aliases(&["parts"])
+        .about("Handles message parts")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("list")
+                .aliases(&["lst", "l"])
+                .about("Lists parts of a message with their fetch token, without their content")
+                .arg(msg_arg::seq_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Fetches the decoded content of a single part")
+                .arg(token_arg()),
+        )]
+}
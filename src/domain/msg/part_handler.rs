@@ -0,0 +1,35 @@
+//! Module related to message part handling.
+//!
+//! This module gathers all message part commands.
+
+use anyhow::Result;
+
+use crate::{
+    domain::{
+        backend::Backend,
+        msg::{parse_part_token, PartContent},
+    },
+    output::OutputServiceInterface,
+};
+
+/// List the parts of the given message, together with the tokens needed to fetch them.
+pub fn list<OutputService: OutputServiceInterface>(
+    seq: &str,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let metas = backend.get_part_metas(seq)?;
+    output.print(metas)
+}
+
+/// Fetch the part matching the given token. Text parts are printed as is, other parts are
+/// encoded in base64.
+pub fn get<OutputService: OutputServiceInterface>(
+    token: &str,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let (seq, section) = parse_part_token(token)?;
+    let (mime, content) = backend.get_part(seq, section)?;
+    output.print(PartContent::new(token, &mime, content))
+}
@@ -0,0 +1,276 @@
+//! Module related to message part metadata.
+//!
+//! This module exposes the metadata of message parts, together with a fetch token, so that
+//! clients can list the parts of a message first and then lazily fetch only the ones they need.
+
+use anyhow::{anyhow, Context, Result};
+use imap_proto::types::{BodyParams, BodyStructure, ContentEncoding};
+use mailparse::{MailHeaderMap, ParsedMail};
+use serde::Serialize;
+use std::fmt;
+
+use crate::ui::table::{Cell, Row, Table};
+
+/// Represent the metadata of a message part.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct PartMeta {
+    /// The token to give to `part get`, made of the message id and the part section.
+    pub token: String,
+    /// The [section] of the part, like `1.2`.
+    ///
+    /// [section]: https://datatracker.ietf.org/doc/html/rfc3501#section-6.4.5
+    pub section: String,
+    pub mime: String,
+    pub filename: Option<String>,
+    /// The size of the encoded part, in bytes.
+    pub size: usize,
+    pub encoding: String,
+}
+
+impl PartMeta {
+    fn new(id: u32, section: &[u32]) -> Self {
+        let section = if section.is_empty() {
+            String::from("1")
+        } else {
+            section
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(".")
+        };
+        Self {
+            token: format!("{}:{}", id, section),
+            section,
+            ..Self::default()
+        }
+    }
+}
+
+impl Table for PartMeta {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("TOKEN").bold().underline().white())
+            .cell(Cell::new("MIME").bold().underline().white())
+            .cell(
+                Cell::new("FILENAME")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+            .cell(Cell::new("SIZE").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.token).red())
+            .cell(Cell::new(&self.mime).green())
+            .cell(
+                Cell::new(self.filename.as_deref().unwrap_or_default())
+                    .shrinkable()
+                    .blue(),
+            )
+            .cell(Cell::new(&self.size.to_string()).yellow())
+    }
+}
+
+/// Represent the metadata of all the leaf parts of a message.
+#[derive(Debug, Default, Serialize)]
+pub struct PartMetas(pub Vec<PartMeta>);
+
+impl PartMetas {
+    /// Build part metadata from an IMAP BODYSTRUCTURE, without fetching the message body.
+    pub fn from_bodystructure(id: u32, structure: &BodyStructure) -> Self {
+        fn walk(id: u32, structure: &BodyStructure, section: Vec<u32>, metas: &mut Vec<PartMeta>) {
+            let (common, other) = match structure {
+                BodyStructure::Multipart { bodies, .. } => {
+                    for (i, body) in bodies.iter().enumerate() {
+                        let mut section = section.clone();
+                        section.push(i as u32 + 1);
+                        walk(id, body, section, metas);
+                    }
+                    return;
+                }
+                BodyStructure::Basic { common, other, .. } => (common, other),
+                BodyStructure::Text { common, other, .. } => (common, other),
+                BodyStructure::Message { common, other, .. } => (common, other),
+            };
+
+            let mut meta = PartMeta::new(id, &section);
+            meta.mime = format!("{}/{}", common.ty.ty, common.ty.subtype).to_lowercase();
+            meta.filename = common
+                .disposition
+                .as_ref()
+                .and_then(|disposition| body_param(&disposition.params, "filename"))
+                .or_else(|| body_param(&common.ty.params, "name"));
+            meta.size = other.octets as usize;
+            meta.encoding = match &other.transfer_encoding {
+                ContentEncoding::SevenBit => String::from("7bit"),
+                ContentEncoding::EightBit => String::from("8bit"),
+                ContentEncoding::Binary => String::from("binary"),
+                ContentEncoding::Base64 => String::from("base64"),
+                ContentEncoding::QuotedPrintable => String::from("quoted-printable"),
+                ContentEncoding::Other(encoding) => encoding.to_lowercase(),
+            };
+            metas.push(meta);
+        }
+
+        let mut metas = vec![];
+        walk(id, structure, vec![], &mut metas);
+        Self(metas)
+    }
+
+    /// Build part metadata from a parsed message, for backends without a structure API.
+    pub fn from_parsed_mail(id: u32, parsed_mail: &ParsedMail) -> Self {
+        fn walk(id: u32, part: &ParsedMail, section: Vec<u32>, metas: &mut Vec<PartMeta>) {
+            if !part.subparts.is_empty() {
+                for (i, subpart) in part.subparts.iter().enumerate() {
+                    let mut section = section.clone();
+                    section.push(i as u32 + 1);
+                    walk(id, subpart, section, metas);
+                }
+                return;
+            }
+
+            let mut meta = PartMeta::new(id, &section);
+            meta.mime = part.ctype.mimetype.to_lowercase();
+            meta.filename = part
+                .get_content_disposition()
+                .params
+                .get("filename")
+                .or_else(|| part.ctype.params.get("name"))
+                .cloned();
+            meta.size = part
+                .get_body_raw()
+                .map(|body| body.len())
+                .unwrap_or_default();
+            meta.encoding = part
+                .headers
+                .get_first_value("Content-Transfer-Encoding")
+                .unwrap_or_else(|| String::from("7bit"))
+                .to_lowercase();
+            metas.push(meta);
+        }
+
+        let mut metas = vec![];
+        walk(id, parsed_mail, vec![], &mut metas);
+        Self(metas)
+    }
+}
+
+impl fmt::Display for PartMetas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", Table::render(&self.0))
+    }
+}
+
+/// Find the value of a BODYSTRUCTURE parameter (case-insensitive).
+fn body_param(params: &BodyParams, key: &str) -> Option<String> {
+    params
+        .as_ref()?
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.to_string())
+}
+
+/// Parse a fetch token into the message id and the part section.
+pub fn parse_part_token(token: &str) -> Result<(&str, &str)> {
+    token
+        .split_once(':')
+        .filter(|(id, section)| !id.is_empty() && !section.is_empty())
+        .ok_or_else(|| anyhow!(r#"cannot parse part token "{}""#, token))
+}
+
+/// Find the part matching the given section within a parsed message, and return its decoded
+/// content.
+pub fn find_part(parsed_mail: &ParsedMail, section: &str) -> Result<Vec<u8>> {
+    let mut part = parsed_mail;
+    if !part.subparts.is_empty() || section != "1" {
+        for n in section.split('.') {
+            let n: usize = n
+                .parse()
+                .context(format!(r#"cannot parse part section "{}""#, section))?;
+            part = part
+                .subparts
+                .get(n.max(1) - 1)
+                .ok_or_else(|| anyhow!(r#"cannot find part "{}""#, section))?;
+        }
+    }
+    part.get_body_raw()
+        .context(format!(r#"cannot decode part "{}""#, section))
+}
+
+/// Decode the content of a part fetched alone, given its transfer encoding.
+pub fn decode_part(encoding: &str, content: &[u8]) -> Result<Vec<u8>> {
+    let mut raw = format!("Content-Transfer-Encoding: {}\r\n\r\n", encoding).into_bytes();
+    raw.extend_from_slice(content);
+    mailparse::parse_mail(&raw)
+        .and_then(|part| part.get_body_raw())
+        .context("cannot decode part")
+}
+
+/// Represent the decoded content of a part. Text parts are returned as is, other parts are
+/// encoded in base64.
+#[derive(Debug, Serialize)]
+pub struct PartContent {
+    pub token: String,
+    pub mime: String,
+    /// Either `utf-8` or `base64`.
+    pub encoding: String,
+    pub content: String,
+}
+
+impl PartContent {
+    pub fn new(token: &str, mime: &str, content: Vec<u8>) -> Self {
+        let (encoding, content) = match String::from_utf8(content) {
+            Ok(content) if mime.starts_with("text/") => (String::from("utf-8"), content),
+            Ok(content) => (String::from("base64"), base64::encode(content)),
+            Err(err) => (String::from("base64"), base64::encode(err.into_bytes())),
+        };
+        Self {
+            token: token.to_owned(),
+            mime: mime.to_owned(),
+            encoding,
+            content,
+        }
+    }
+}
+
+impl fmt::Display for PartContent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_list_and_find_parts() {
+        let raw = concat!(
+            "Content-Type: multipart/mixed; boundary=b\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "Hello\r\n",
+            "--b\r\n",
+            "Content-Type: image/png; name=a.png\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "aW1n\r\n",
+            "--b--\r\n",
+        );
+        let parsed_mail = mailparse::parse_mail(raw.as_bytes()).unwrap();
+        let metas = PartMetas::from_parsed_mail(42, &parsed_mail);
+        assert_eq!(2, metas.0.len());
+        assert_eq!("42:2", metas.0[1].token);
+        assert_eq!("image/png", metas.0[1].mime);
+        assert_eq!(Some("a.png"), metas.0[1].filename.as_deref());
+        assert_eq!(b"img".to_vec(), find_part(&parsed_mail, "2").unwrap());
+        assert_eq!(b"img".to_vec(), decode_part("base64", b"aW1n").unwrap());
+        assert_eq!(("42", "1.2"), parse_part_token("42:1.2").unwrap());
+        assert!(parse_part_token("42").is_err());
+    }
+}
@@ -2,6 +2,8 @@ use mailparse::MailHeaderMap;
 use serde::Serialize;
 use std::ops::{Deref, DerefMut};
 
+use crate::domain::msg::msg_smime;
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct TextPlainPart {
     pub content: String,
@@ -88,7 +90,15 @@ fn build_parts_map_rec(part: &mailparse::ParsedMail, parts: &mut Vec<Part>) {
                     .map(String::from)
                     .unwrap_or(String::from("noname"));
                 let content = part.get_body_raw().unwrap_or_default();
-                let mime = tree_magic::from_u8(&content);
+                // Invitations, contact cards and S/MIME parts are told apart by their declared
+                // type only.
+                let mime = if is_card_or_calendar(&part.ctype.mimetype)
+                    || msg_smime::is_pkcs7(&part.ctype.mimetype)
+                {
+                    part.ctype.mimetype.to_owned()
+                } else {
+                    tree_magic::from_u8(&content)
+                };
                 parts.push(Part::Binary(BinaryPart {
                     filename,
                     mime,
@@ -105,6 +115,18 @@ fn build_parts_map_rec(part: &mailparse::ParsedMail, parts: &mut Vec<Part>) {
                             parts.push(Part::TextPlain(TextPlainPart { content }))
                         } else if ctype.starts_with("text/html") {
                             parts.push(Part::TextHtml(TextHtmlPart { content }))
+                        } else if is_card_or_calendar(&part.ctype.mimetype) {
+                            // Inline invitations and contact cards are kept as attachments.
+                            let filename = if part.ctype.mimetype == "text/calendar" {
+                                "invite.ics"
+                            } else {
+                                "contact.vcf"
+                            };
+                            parts.push(Part::Binary(BinaryPart {
+                                filename: filename.to_owned(),
+                                mime: part.ctype.mimetype.to_owned(),
+                                content: content.into_bytes(),
+                            }))
                         }
                     });
             }
@@ -115,3 +137,7 @@ fn build_parts_map_rec(part: &mailparse::ParsedMail, parts: &mut Vec<Part>) {
             .for_each(|part| build_parts_map_rec(part, parts));
     }
 }
+
+fn is_card_or_calendar(mime: &str) -> bool {
+    matches!(mime, "text/calendar" | "text/vcard" | "text/x-vcard")
+}
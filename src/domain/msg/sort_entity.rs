@@ -0,0 +1,108 @@
+//! Module related to message sorting.
+//!
+//! This module exposes sort criteria used to order message listings. The syntax follows the
+//! [RFC5256](https://datatracker.ietf.org/doc/html/rfc5256#section-3) SORT command.
+
+use anyhow::{anyhow, Error, Result};
+use std::{cmp::Ordering, convert::TryFrom, fmt};
+
+use crate::domain::msg::Envelope;
+
+/// Represents a sort key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortKey {
+    Arrival,
+    Date,
+    From,
+    Subject,
+}
+
+/// Represents a sort criterion: a key and its direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortCriterion {
+    pub key: SortKey,
+    pub reverse: bool,
+}
+
+/// Represents a list of sort criteria, ordered by priority.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortCriteria(pub Vec<SortCriterion>);
+
+impl SortCriteria {
+    /// Compare two envelopes according to the criteria. This is used when the server does not
+    /// support the SORT extension. Since envelopes only hold the internal date, both `arrival`
+    /// and `date` keys fall back to it.
+    pub fn cmp_envelopes(&self, a: &Envelope, b: &Envelope) -> Ordering {
+        for criterion in self.0.iter() {
+            let ord = match criterion.key {
+                SortKey::Arrival => a.id.cmp(&b.id),
+                SortKey::Date => a.date.cmp(&b.date),
+                SortKey::From => a.sender.to_lowercase().cmp(&b.sender.to_lowercase()),
+                SortKey::Subject => a.subject.to_lowercase().cmp(&b.subject.to_lowercase()),
+            };
+            let ord = if criterion.reverse {
+                ord.reverse()
+            } else {
+                ord
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+
+        // Like the SORT extension, use the sequence number as the final tie-breaker.
+        a.id.cmp(&b.id)
+    }
+}
+
+impl fmt::Display for SortCriteria {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut glue = "";
+        for criterion in self.0.iter() {
+            write!(f, "{}", glue)?;
+            if criterion.reverse {
+                write!(f, "REVERSE ")?;
+            }
+            match criterion.key {
+                SortKey::Arrival => write!(f, "ARRIVAL")?,
+                SortKey::Date => write!(f, "DATE")?,
+                SortKey::From => write!(f, "FROM")?,
+                SortKey::Subject => write!(f, "SUBJECT")?,
+            }
+            glue = " ";
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for SortCriteria {
+    type Error = Error;
+
+    fn try_from(criteria: &str) -> Result<Self, Self::Error> {
+        let mut reverse = false;
+        let mut sort_criteria = vec![];
+
+        for token in criteria.split(|c: char| c == ',' || c.is_whitespace()) {
+            let key = match token.to_lowercase().as_str() {
+                "" => continue,
+                "reverse" | "rev" => {
+                    reverse = true;
+                    continue;
+                }
+                "arrival" => SortKey::Arrival,
+                "date" => SortKey::Date,
+                "from" | "sender" => SortKey::From,
+                "subject" => SortKey::Subject,
+                _ => return Err(anyhow!(r#"cannot parse sort criterion "{}""#, token)),
+            };
+            sort_criteria.push(SortCriterion { key, reverse });
+            reverse = false;
+        }
+
+        if sort_criteria.is_empty() {
+            return Err(anyhow!(r#"cannot parse sort criteria "{}""#, criteria));
+        }
+
+        Ok(Self(sort_criteria))
+    }
+}
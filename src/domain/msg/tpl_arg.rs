@@ -6,28 +6,22 @@ use anyhow::Result;
 use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
 use log::{debug, trace};
 
-use crate::domain::msg::msg_arg;
+use crate::domain::msg::{
+    msg_arg::{self, QuoteMatch},
+    TplOverride,
+};
 
 type Seq<'a> = &'a str;
 type All = bool;
-
-#[derive(Debug, Default)]
-pub struct TplOverride<'a> {
-    pub subject: Option<&'a str>,
-    pub from: Option<Vec<&'a str>>,
-    pub to: Option<Vec<&'a str>>,
-    pub cc: Option<Vec<&'a str>>,
-    pub bcc: Option<Vec<&'a str>>,
-    pub headers: Option<Vec<&'a str>>,
-    pub body: Option<&'a str>,
-    pub sig: Option<&'a str>,
-}
+type RawTpl<'a> = &'a str;
+type Stdin = bool;
 
 /// Message template commands.
 pub enum Command<'a> {
     New(TplOverride<'a>),
-    Reply(Seq<'a>, All, TplOverride<'a>),
+    Reply(Seq<'a>, All, QuoteMatch<'a>, TplOverride<'a>),
     Forward(Seq<'a>, TplOverride<'a>),
+    Send(RawTpl<'a>, Stdin),
 }
 
 /// Message template command matcher.
@@ -40,7 +34,7 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
             to: m.values_of("to").map(|v| v.collect()),
             cc: m.values_of("cc").map(|v| v.collect()),
             bcc: m.values_of("bcc").map(|v| v.collect()),
-            headers: m.values_of("headers").map(|v| v.collect()),
+            headers: m.values_of("header").map(|v| v.collect()),
             body: m.value_of("body"),
             sig: m.value_of("signature"),
         };
@@ -54,18 +48,20 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         trace!(r#"seq: "{}""#, seq);
         let all = m.is_present("reply-all");
         trace!("reply all: {}", all);
+        let quote_match = m.value_of("quote-match");
+        trace!("quote match: {:?}", quote_match);
         let tpl = TplOverride {
             subject: m.value_of("subject"),
             from: m.values_of("from").map(|v| v.collect()),
             to: m.values_of("to").map(|v| v.collect()),
             cc: m.values_of("cc").map(|v| v.collect()),
             bcc: m.values_of("bcc").map(|v| v.collect()),
-            headers: m.values_of("headers").map(|v| v.collect()),
+            headers: m.values_of("header").map(|v| v.collect()),
             body: m.value_of("body"),
             sig: m.value_of("signature"),
         };
         trace!(r#"template args: "{:?}""#, tpl);
-        return Ok(Some(Command::Reply(seq, all, tpl)));
+        return Ok(Some(Command::Reply(seq, all, quote_match, tpl)));
     }
 
     if let Some(m) = m.subcommand_matches("forward") {
@@ -78,7 +74,7 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
             to: m.values_of("to").map(|v| v.collect()),
             cc: m.values_of("cc").map(|v| v.collect()),
             bcc: m.values_of("bcc").map(|v| v.collect()),
-            headers: m.values_of("headers").map(|v| v.collect()),
+            headers: m.values_of("header").map(|v| v.collect()),
             body: m.value_of("body"),
             sig: m.value_of("signature"),
         };
@@ -86,6 +82,15 @@ pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
         return Ok(Some(Command::Forward(seq, tpl)));
     }
 
+    if let Some(m) = m.subcommand_matches("send") {
+        debug!("send command matched");
+        let tpl = m.value_of("template").unwrap_or_default();
+        trace!("template: {}", tpl);
+        let stdin = m.is_present("stdin");
+        trace!("stdin: {}", stdin);
+        return Ok(Some(Command::Send(tpl, stdin)));
+    }
+
     Ok(None)
 }
 
@@ -158,6 +163,7 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
                 .about("Generates a reply message template")
                 .arg(msg_arg::seq_arg())
                 .arg(msg_arg::reply_all_arg())
+                .arg(msg_arg::quote_match_arg())
                 .args(&tpl_args()),
         )
         .subcommand(
@@ -166,5 +172,17 @@ pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
                 .about("Generates a forward message template")
                 .arg(msg_arg::seq_arg())
                 .args(&tpl_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("send")
+                .about("Sends a message built from a template")
+                .long_about("Sends a message built from a template, read from the standard input when not given, for example once edited by an editor plugin from the output of `template new`, `reply` or `forward`. Attachments can be declared with `Attachment: <path>` headers, S/MIME signing and encryption turned on or off with `Sign: yes|no` and `Encrypt: yes|no` headers, other headers are sent as is.")
+                .arg(
+                    Arg::with_name("stdin")
+                        .help("Reads the template from the standard input")
+                        .long_help("Reads the template from the standard input, even with the JSON output or from a terminal.")
+                        .long("stdin"),
+                )
+                .arg(Arg::with_name("template").raw(true).last(true)),
         )]
 }
@@ -0,0 +1,266 @@
+//! Module related to the template engine.
+//!
+//! Account templates (`new-template`, `reply-template` and `forward-template`) are rendered with
+//! a small template language:
+//!
+//! - `{{ sender.name }}` prints a variable, empty when unknown,
+//! - `{{ original.body | quote }}` pipes it through filters: `quote`, `upper`, `lower` and `trim`,
+//! - `{% if sender.name %}…{% else %}…{% endif %}` prints a branch depending on whether a
+//!   variable is set and not empty, `{% if not … %}` negating the condition.
+//!
+//! Lines holding only an `{% … %}` tag are left out of the output.
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use std::collections::BTreeMap;
+
+use crate::config::Account;
+
+/// Represents the variables available to a template, indexed by their dotted path.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TplContext(BTreeMap<String, String>);
+
+impl TplContext {
+    /// Build the variables related to the given account: `account.name` (the display name),
+    /// `account.email`, `account.address`, `signature` and `date`, the current date.
+    pub fn from_account(account: &Account) -> Self {
+        let mut ctx = Self::default();
+        ctx.set("account.name", &account.from)
+            .set("account.email", &account.email)
+            .set("account.address", account.address())
+            .set("signature", account.sig.as_deref().unwrap_or_default())
+            .set("date", Local::now().format("%d %b %Y, at %H:%M"));
+        ctx
+    }
+
+    pub fn set<K: ToString, V: ToString>(&mut self, key: K, value: V) -> &mut Self {
+        self.0.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    fn get(&self, key: &str) -> &str {
+        self.0.get(key).map(String::as_str).unwrap_or_default()
+    }
+}
+
+/// Represents a node of a parsed template.
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String, Vec<String>),
+    If {
+        negated: bool,
+        var: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// Represents a token of a template.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    /// The content of a `{{ … }}` tag.
+    Var(String),
+    /// The content of a `{% … %}` tag.
+    Block(String),
+}
+
+fn tokenize(tpl: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = tpl;
+    let mut at_line_start = true;
+
+    while !rest.is_empty() {
+        let start = match (rest.find("{{"), rest.find("{%")) {
+            (Some(var), Some(block)) => var.min(block),
+            (Some(start), None) | (None, Some(start)) => start,
+            (None, None) => {
+                tokens.push(Token::Text(rest.to_owned()));
+                break;
+            }
+        };
+        let is_block = rest[start..].starts_with("{%");
+        let close = if is_block { "%}" } else { "}}" };
+        let end = rest[start + 2..]
+            .find(close)
+            .map(|end| start + 2 + end)
+            .ok_or_else(|| {
+                anyhow!(
+                    r#"cannot render template: unclosed tag "{}""#,
+                    rest[start..].lines().next().unwrap_or_default()
+                )
+            })?;
+        let content = rest[start + 2..end].trim().to_owned();
+        let mut text = &rest[..start];
+        rest = &rest[end + 2..];
+
+        // Block tags alone on their line take the line with them.
+        let mut takes_line = false;
+        if is_block {
+            let indent = text.trim_end_matches(|c| c == ' ' || c == '\t');
+            let is_line_start = indent.ends_with('\n') || indent.is_empty() && at_line_start;
+            if is_line_start && (rest.is_empty() || rest.starts_with('\n')) {
+                text = indent;
+                rest = rest.strip_prefix('\n').unwrap_or(rest);
+                takes_line = true;
+            }
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text.to_owned()));
+        }
+        at_line_start = takes_line;
+        tokens.push(if is_block {
+            Token::Block(content)
+        } else {
+            Token::Var(content)
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Parse the given tokens until one of the given block tags, returned along with the nodes.
+fn parse<I: Iterator<Item = Token>>(
+    tokens: &mut I,
+    until: &[&str],
+) -> Result<(Vec<Node>, Option<String>)> {
+    let mut nodes = vec![];
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Var(content) => {
+                let mut parts = content.split('|').map(|part| part.trim().to_owned());
+                let var = parts.next().unwrap_or_default();
+                if var.is_empty() {
+                    return Err(anyhow!("cannot render template: empty variable tag"));
+                }
+                nodes.push(Node::Var(var, parts.collect()));
+            }
+            Token::Block(content) if until.contains(&content.as_str()) => {
+                return Ok((nodes, Some(content)));
+            }
+            Token::Block(content) => {
+                let cond = content.strip_prefix("if ").map(str::trim).ok_or_else(|| {
+                    anyhow!(r#"cannot render template: unknown tag "{}""#, content)
+                })?;
+                let (negated, var) = match cond.strip_prefix("not ") {
+                    Some(var) => (true, var.trim().to_owned()),
+                    None => (false, cond.to_owned()),
+                };
+                let (then, end) = parse(tokens, &["else", "endif"])?;
+                let otherwise = match end.as_deref() {
+                    Some("else") => match parse(tokens, &["endif"])? {
+                        (otherwise, Some(_)) => otherwise,
+                        (_, None) => return Err(anyhow!("cannot render template: missing endif")),
+                    },
+                    Some(_) => vec![],
+                    None => return Err(anyhow!("cannot render template: missing endif")),
+                };
+                nodes.push(Node::If {
+                    negated,
+                    var,
+                    then,
+                    otherwise,
+                });
+            }
+        }
+    }
+    Ok((nodes, None))
+}
+
+/// Prefix the lines of the given text with `>`, like reply quotes.
+fn quote(text: &str) -> String {
+    text.lines()
+        .map(|line| match line {
+            "" => String::from(">"),
+            line if line.starts_with('>') => format!(">{}", line),
+            line => format!("> {}", line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn apply_filter(value: String, filter: &str) -> Result<String> {
+    match filter {
+        "quote" => Ok(quote(&value)),
+        "upper" => Ok(value.to_uppercase()),
+        "lower" => Ok(value.to_lowercase()),
+        "trim" => Ok(value.trim().to_owned()),
+        filter => Err(anyhow!(
+            r#"cannot render template: unknown filter "{}""#,
+            filter
+        )),
+    }
+}
+
+fn render_nodes(nodes: &[Node], ctx: &TplContext, output: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Var(var, filters) => {
+                let mut value = ctx.get(var).to_owned();
+                for filter in filters {
+                    value = apply_filter(value, filter)?;
+                }
+                output.push_str(&value);
+            }
+            Node::If {
+                negated,
+                var,
+                then,
+                otherwise,
+            } => {
+                let is_set = !ctx.get(var).trim().is_empty();
+                if is_set != *negated {
+                    render_nodes(then, ctx, output)?;
+                } else {
+                    render_nodes(otherwise, ctx, output)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render the given template with the variables of the given context.
+pub fn render(tpl: &str, ctx: &TplContext) -> Result<String> {
+    let mut tokens = tokenize(tpl)?.into_iter();
+    let nodes = match parse(&mut tokens, &[])? {
+        (nodes, None) => nodes,
+        (_, Some(tag)) => {
+            return Err(anyhow!(
+                r#"cannot render template: unexpected tag "{}""#,
+                tag
+            ))
+        }
+    };
+    let mut output = String::new();
+    render_nodes(&nodes, ctx, &mut output)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_render_templates() {
+        let mut ctx = TplContext::default();
+        ctx.set("sender.name", "Alice")
+            .set("original.body", "Hello\n\n> Hi")
+            .set("signature", "-- \nBob");
+
+        let tpl = "{% if sender.name %}\nHi {{ sender.name | upper }},\n{% else %}\nHi,\n{% endif %}\n\n{{ original.body | quote }}\n{% if not other %}{{ signature }}{% endif %}\n";
+        assert_eq!(
+            "Hi ALICE,\n\n> Hello\n>\n>> Hi\n-- \nBob\n",
+            render(tpl, &ctx).unwrap()
+        );
+        assert_eq!("[]", render("[{{ unknown }}]", &ctx).unwrap());
+        assert!(render("{{ sender.name | shout }}", &ctx).is_err());
+        assert!(render("{% if sender.name %}Hi", &ctx).is_err());
+        assert!(render("Hi {{ sender.name", &ctx).is_err());
+        assert!(render("{% endif %}", &ctx).is_err());
+    }
+}
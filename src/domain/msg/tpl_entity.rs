@@ -1,4 +1,5 @@
-use log::trace;
+use anyhow::{anyhow, Result};
+use log::{trace, warn};
 use serde::Serialize;
 use std::{
     fmt::{self, Display},
@@ -7,9 +8,78 @@ use std::{
 
 use crate::{
     config::Account,
-    domain::msg::{Msg, TplOverride},
+    domain::msg::{
+        msg_sig,
+        tpl_engine::{self, TplContext},
+        Msg,
+    },
 };
 
+/// Represents the parts of a template overridden by the user, from the `template` commands.
+#[derive(Debug, Default)]
+pub struct TplOverride<'a> {
+    pub subject: Option<&'a str>,
+    pub from: Option<Vec<&'a str>>,
+    pub to: Option<Vec<&'a str>>,
+    pub cc: Option<Vec<&'a str>>,
+    pub bcc: Option<Vec<&'a str>>,
+    pub headers: Option<Vec<&'a str>>,
+    pub body: Option<&'a str>,
+    pub sig: Option<&'a str>,
+}
+
+/// Pseudo-header of templates declaring the path of a file to attach. It can be repeated, and it
+/// is never sent.
+pub const ATTACHMENT_HEADER: &str = "Attachment";
+
+/// Pseudo-headers of templates overriding the `smime-sign` and `smime-encrypt` account options
+/// for the message, with `yes` or `no`. They are never sent.
+pub const SIGN_HEADER: &str = "Sign";
+pub const ENCRYPT_HEADER: &str = "Encrypt";
+
+/// All the pseudo-headers of templates.
+pub const PSEUDO_HEADERS: [&str; 3] = [ATTACHMENT_HEADER, SIGN_HEADER, ENCRYPT_HEADER];
+
+/// Parse the value of a yes/no pseudo-header.
+fn parse_yes_no(key: &str, val: &str) -> Result<bool> {
+    match val.trim().to_lowercase().as_str() {
+        "yes" | "true" | "on" => Ok(true),
+        "no" | "false" | "off" => Ok(false),
+        val => Err(anyhow!(
+            r#"cannot parse header "{}" value "{}", expected yes or no"#,
+            key,
+            val
+        )),
+    }
+}
+
+/// Split a template file into the headers declared by its front-matter block and its body. The
+/// front-matter block is made of header lines between two `---` lines, at the very beginning of
+/// the file.
+pub fn split_front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let block = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+        .and_then(|rest| {
+            let end = rest.find("\n---")?;
+            let body = rest[end + 4..].trim_start_matches(|c| c == '\r' || c == '\n');
+            Some((&rest[..end], body))
+        });
+
+    match block {
+        Some((block, body)) => {
+            let headers = block
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(key, val)| (key.trim().to_owned(), val.trim().to_owned()))
+                .filter(|(key, _)| !key.is_empty())
+                .collect();
+            (headers, body)
+        }
+        None => (vec![], text),
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize)]
 pub struct Tpl(pub String);
 
@@ -32,67 +102,138 @@ impl Tpl {
         ));
 
         // To
-        tpl.push_str(&format!(
-            "To: {}\n",
-            opts.to
-                .map(|addrs| addrs.join(", "))
-                .or_else(|| msg.to.clone().map(|addrs| addrs
-                    .iter()
-                    .map(|addr| addr.to_string())
-                    .collect::<Vec<_>>()
-                    .join(", ")))
-                .unwrap_or_default()
-        ));
+        let to: Vec<String> = opts
+            .to
+            .map(|addrs| addrs.iter().map(|addr| addr.to_string()).collect())
+            .or_else(|| {
+                msg.to
+                    .as_ref()
+                    .map(|addrs| addrs.iter().map(|addr| addr.to_string()).collect())
+            })
+            .unwrap_or_default();
+        tpl.push_str(&format!("To: {}\n", to.join(", ")));
+        let recipient_tpl = account.recipient_tpl(&to);
+
+        // New messages take the front-matter headers of the recipient template, if any
+        let body = msg.join_text_plain_parts();
+        let (mut front_matter, tpl_body) = match recipient_tpl
+            .and_then(|tpl| tpl.template.as_deref())
+            .filter(|_| opts.body.is_none() && body.trim().is_empty())
+        {
+            Some(tpl_body) => {
+                let (headers, tpl_body) = split_front_matter(tpl_body);
+                (headers, Some(tpl_body))
+            }
+            None => (vec![], None),
+        };
+        let mut take_front_matter = |key: &str| {
+            let pos = front_matter
+                .iter()
+                .position(|(k, _)| k.eq_ignore_ascii_case(key))?;
+            Some(front_matter.remove(pos).1)
+        };
 
         // Cc
-        if let Some(addrs) = opts.cc.map(|addrs| addrs.join(", ")).or_else(|| {
-            msg.cc.clone().map(|addrs| {
-                addrs
-                    .iter()
-                    .map(|addr| addr.to_string())
-                    .collect::<Vec<_>>()
-                    .join(", ")
+        let front_matter_cc = take_front_matter("Cc");
+        if let Some(addrs) = opts
+            .cc
+            .map(|addrs| addrs.join(", "))
+            .or_else(|| {
+                msg.cc.clone().map(|addrs| {
+                    addrs
+                        .iter()
+                        .map(|addr| addr.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
             })
-        }) {
+            .or(front_matter_cc)
+        {
             tpl.push_str(&format!("Cc: {}\n", addrs));
         }
 
         // Bcc
-        if let Some(addrs) = opts.bcc.map(|addrs| addrs.join(", ")).or_else(|| {
-            msg.bcc.clone().map(|addrs| {
-                addrs
-                    .iter()
-                    .map(|addr| addr.to_string())
-                    .collect::<Vec<_>>()
-                    .join(", ")
+        let front_matter_bcc = take_front_matter("Bcc");
+        if let Some(addrs) = opts
+            .bcc
+            .map(|addrs| addrs.join(", "))
+            .or_else(|| {
+                msg.bcc.clone().map(|addrs| {
+                    addrs
+                        .iter()
+                        .map(|addr| addr.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
             })
-        }) {
+            .or(front_matter_bcc)
+        {
             tpl.push_str(&format!("Bcc: {}\n", addrs));
         }
 
         // Subject
+        let front_matter_subject = take_front_matter("Subject");
         tpl.push_str(&format!(
             "Subject: {}\n",
-            opts.subject.unwrap_or(&msg.subject)
+            opts.subject
+                .map(String::from)
+                .or(front_matter_subject.filter(|_| msg.subject.is_empty()))
+                .unwrap_or_else(|| msg.subject.to_owned())
         ));
 
+        // Custom headers and attachments
+        for (key, val) in msg.headers.iter().chain(front_matter.iter()) {
+            tpl.push_str(&format!("{}: {}\n", key, val));
+        }
+        for header in opts.headers.unwrap_or_default() {
+            tpl.push_str(header.trim());
+            tpl.push_str("\n");
+        }
+
+        // Default headers of the account, unless already set
+        for (key, val) in account.default_headers.iter() {
+            let prefix = format!("{}:", key.to_lowercase());
+            if !tpl
+                .lines()
+                .any(|line| line.to_lowercase().starts_with(&prefix))
+            {
+                tpl.push_str(&format!("{}: {}\n", key, val));
+            }
+        }
+
         // Headers <=> body separator
         tpl.push_str("\n");
 
-        // Body
-        if let Some(body) = opts.body {
-            tpl.push_str(body);
+        // Body, new messages start from the recipient template, or from the account one
+        let body = if let Some(body) = opts.body {
+            body.to_owned()
+        } else if let Some(tpl_body) = tpl_body {
+            tpl_body.trim_end().to_owned()
+        } else if let Some(new_tpl) = account
+            .new_tpl
+            .as_deref()
+            .filter(|_| body.trim().is_empty())
+        {
+            match tpl_engine::render(new_tpl, &TplContext::from_account(account)) {
+                Ok(body) => body.trim_end().to_owned(),
+                Err(err) => {
+                    warn!("{:?}", err);
+                    new_tpl.trim_end().to_owned()
+                }
+            }
         } else {
-            tpl.push_str(&msg.join_text_plain_parts())
-        }
+            body
+        };
 
-        // Signature
-        if let Some(sig) = opts.sig {
-            tpl.push_str("\n\n");
-            tpl.push_str(sig);
-        } else if let Some(ref sig) = account.sig {
-            tpl.push_str("\n\n");
-            tpl.push_str(sig);
+        // Signature, unless the body already holds it (eg. from an account template)
+        match opts
+            .sig
+            .or_else(|| recipient_tpl.and_then(|tpl| tpl.signature.as_deref()))
+            .or_else(|| account.sig.as_deref())
+            .filter(|sig| !body.contains(sig.trim_end()))
+        {
+            Some(sig) => tpl.push_str(&msg_sig::insert(&body, sig, account.sig_pos)),
+            None => tpl.push_str(&body),
         }
 
         tpl.push_str("\n");
@@ -103,6 +244,35 @@ impl Tpl {
     }
 }
 
+impl Tpl {
+    /// Apply the signing and encryption pseudo-headers of the template to the given account, and
+    /// remove them from the template. Only the headers, before the first empty line, are read.
+    pub fn take_protection(&mut self, account: &mut Account) -> Result<()> {
+        let mut tpl = String::with_capacity(self.0.len());
+        let mut lines = self.0.split_inclusive('\n');
+        for line in lines.by_ref() {
+            let header = line.split_once(':').filter(|_| !line.trim().is_empty());
+            match header {
+                Some((key, val)) if key.trim().eq_ignore_ascii_case(SIGN_HEADER) => {
+                    account.smime_sign = parse_yes_no(SIGN_HEADER, val)?;
+                }
+                Some((key, val)) if key.trim().eq_ignore_ascii_case(ENCRYPT_HEADER) => {
+                    account.smime_encrypt = parse_yes_no(ENCRYPT_HEADER, val)?;
+                }
+                _ => {
+                    tpl.push_str(line);
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+        tpl.extend(lines);
+        self.0 = tpl;
+        Ok(())
+    }
+}
+
 impl Deref for Tpl {
     type Target = String;
 
@@ -116,3 +286,63 @@ impl Display for Tpl {
         write!(f, "{}", self.deref())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_split_front_matter() {
+        let text = "---\nCc: boss@client.fr\nAttachment: ~/terms.pdf\n---\n\nHello,\n";
+        let (headers, body) = split_front_matter(text);
+        assert_eq!(
+            vec![
+                (String::from("Cc"), String::from("boss@client.fr")),
+                (String::from("Attachment"), String::from("~/terms.pdf")),
+            ],
+            headers
+        );
+        assert_eq!("Hello,\n", body);
+
+        let (headers, body) = split_front_matter("Hello,\n---\n");
+        assert!(headers.is_empty());
+        assert_eq!("Hello,\n---\n", body);
+    }
+
+    #[test]
+    fn it_should_add_default_headers() {
+        let mut account = Account::default();
+        account
+            .default_headers
+            .insert(String::from("Organization"), String::from("ACME"));
+        account
+            .default_headers
+            .insert(String::from("Reply-To"), String::from("team@acme.org"));
+        let msg = Msg {
+            headers: vec![(String::from("reply-to"), String::from("me@acme.org"))],
+            ..Msg::default()
+        };
+        let tpl = Tpl::from_msg(TplOverride::default(), &msg, &account);
+        assert!(tpl.contains("\nOrganization: ACME\n"));
+        assert!(tpl.contains("\nreply-to: me@acme.org\n"));
+        assert!(!tpl.contains("team@acme.org"));
+    }
+
+    #[test]
+    fn it_should_take_protection_headers() {
+        let mut account = Account {
+            smime_sign: true,
+            ..Account::default()
+        };
+        let mut tpl = Tpl(String::from(
+            "To: bob@localhost\nsign: no\nEncrypt: yes\n\nSign: keep the body\n",
+        ));
+        tpl.take_protection(&mut account).unwrap();
+        assert!(!account.smime_sign);
+        assert!(account.smime_encrypt);
+        assert_eq!("To: bob@localhost\n\nSign: keep the body\n", tpl.0);
+
+        let mut tpl = Tpl(String::from("Encrypt: maybe\n\n"));
+        assert!(tpl.take_protection(&mut account).is_err());
+    }
+}
@@ -2,13 +2,20 @@
 //!
 //! This module gathers all message template commands.  
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use atty::Stream;
+use imap::types::Flag;
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead},
+};
 
 use crate::{
     config::Account,
     domain::{
-        imap::ImapServiceInterface,
-        msg::{Msg, Tpl, TplOverride},
+        backend::{Backend, Sender},
+        mbox::{warn_near_quota, Mbox},
+        msg::{msg_addr, msg_split, Flags, Msg, Tpl, TplOverride},
     },
     output::OutputServiceInterface,
 };
@@ -25,28 +32,81 @@ pub fn new<'a, OutputService: OutputServiceInterface>(
 }
 
 /// Generate a reply message template.
-pub fn reply<'a, OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+pub fn reply<'a, OutputService: OutputServiceInterface>(
     seq: &str,
     all: bool,
+    quote_match: Option<&str>,
     opts: TplOverride<'a>,
     account: &'a Account,
     output: &'a OutputService,
-    imap: &'a mut ImapService,
+    backend: &'a mut dyn Backend,
 ) -> Result<()> {
-    let msg = imap.find_msg(seq)?.into_reply(all, account)?;
+    let msg = backend
+        .get_msg(seq)?
+        .into_reply(all, quote_match, account)?;
     let tpl = Tpl::from_msg(opts, &msg, account);
     output.print(tpl)
 }
 
 /// Generate a forward message template.
-pub fn forward<'a, OutputService: OutputServiceInterface, ImapService: ImapServiceInterface>(
+pub fn forward<'a, OutputService: OutputServiceInterface>(
     seq: &str,
     opts: TplOverride<'a>,
     account: &'a Account,
     output: &'a OutputService,
-    imap: &'a mut ImapService,
+    backend: &'a mut dyn Backend,
 ) -> Result<()> {
-    let msg = imap.find_msg(seq)?.into_forward(account)?;
+    let msg = backend.get_msg(seq)?.into_forward(account)?;
     let tpl = Tpl::from_msg(opts, &msg, account);
     output.print(tpl)
 }
+
+/// Send a message built from the given template, or from the one read from stdin. Attachments,
+/// signing and encryption pseudo-headers and custom headers declared in the template are
+/// honored.
+pub fn send<'a, OutputService: OutputServiceInterface>(
+    tpl: &str,
+    stdin: bool,
+    account: &'a Account,
+    output: &'a OutputService,
+    backend: &'a mut dyn Backend,
+    sender: &'a mut dyn Sender,
+) -> Result<()> {
+    let tpl = if !stdin && (atty::is(Stream::Stdin) || output.is_json()) {
+        tpl.replace("\r", "")
+    } else {
+        io::stdin()
+            .lock()
+            .lines()
+            .filter_map(|ln| ln.ok())
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    let mut tpl = Tpl(tpl);
+    let mut account = account.clone();
+    tpl.take_protection(&mut account)?;
+    let account = &account;
+
+    msg_addr::check(&tpl)?;
+    let mut msg = Msg::try_from(&tpl)?;
+    if msg.from.is_none() {
+        let from = account.address().parse().context(format!(
+            r#"cannot parse address of account "{}""#,
+            account.name
+        ))?;
+        msg.from = Some(vec![from]);
+    }
+
+    let mbox = Mbox::from(account.sent_folder.as_str());
+    if account.save_sent_copy {
+        warn_near_quota(&mbox, account, backend);
+    }
+    for sent_msg in msg_split::send(&msg, account, sender)? {
+        if account.save_sent_copy {
+            let flags = Flags::try_from(vec![Flag::Seen])?;
+            backend.append_raw(&mbox, &sent_msg, flags)?;
+        }
+    }
+    output.print("Message successfully sent")
+}
@@ -0,0 +1,9 @@
+//! Module related to the outbox.
+
+#[cfg(feature = "cli")]
+pub mod queue_arg;
+#[cfg(feature = "cli")]
+pub mod queue_handler;
+
+pub mod queue_entity;
+pub use queue_entity::*;
@@ -0,0 +1,147 @@
+//! Module related to outbox CLI.
+//!
+//! This module provides subcommands and a command matcher related to the outbox.
+
+use anyhow::Result;
+use clap::{self, App, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Id<'a> = &'a str;
+type OlderThan<'a> = &'a str;
+type MaxAttempts = u32;
+type Action = Option<DoctorAction>;
+type Notify = bool;
+
+/// Represents the action taken by the doctor on stuck messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DoctorAction {
+    /// Send the stuck messages again, quarantining the ones reaching the maximum attempts.
+    Retry,
+    /// Quarantine the stuck messages right away.
+    Quarantine,
+}
+
+/// Options of the doctor command.
+pub struct DoctorOpts<'a> {
+    pub older_than: OlderThan<'a>,
+    pub max_attempts: MaxAttempts,
+    pub action: Action,
+    pub notify: Notify,
+}
+
+/// Outbox commands.
+pub enum Command<'a> {
+    /// List the queued messages.
+    List,
+    /// Send the queued messages again, except the quarantined ones.
+    Flush,
+    /// Detect the messages stuck in the outbox, then optionally retry or quarantine them.
+    Doctor(DoctorOpts<'a>),
+    /// Release the given quarantined message, so that it is sent again by the next flush.
+    Release(Id<'a>),
+}
+
+/// Outbox command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("queue") {
+        if m.subcommand_matches("flush").is_some() {
+            debug!("queue flush command matched");
+            return Ok(Some(Command::Flush));
+        }
+
+        if let Some(m) = m.subcommand_matches("doctor") {
+            debug!("queue doctor command matched");
+            let older_than = m.value_of("older-than").unwrap_or("1h");
+            trace!("older than: {}", older_than);
+            let max_attempts = m
+                .value_of("max-attempts")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(5);
+            trace!("max attempts: {}", max_attempts);
+            let action = if m.is_present("retry") {
+                Some(DoctorAction::Retry)
+            } else if m.is_present("quarantine") {
+                Some(DoctorAction::Quarantine)
+            } else {
+                None
+            };
+            trace!("action: {:?}", action);
+            let notify = m.is_present("notify");
+            trace!("notify: {}", notify);
+            return Ok(Some(Command::Doctor(DoctorOpts {
+                older_than,
+                max_attempts,
+                action,
+                notify,
+            })));
+        }
+
+        if let Some(m) = m.subcommand_matches("release") {
+            debug!("queue release command matched");
+            let id = m.value_of("id").unwrap();
+            trace!("id: {}", id);
+            return Ok(Some(Command::Release(id)));
+        }
+
+        debug!("queue list command matched");
+        return Ok(Some(Command::List));
+    }
+
+    Ok(None)
+}
+
+/// Outbox subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("queue")
+        .about("Lists the messages queued in the outbox, or manages them")
+        .long_about("Lists the messages queued in the outbox, or manages them. Messages the sender fails to send are queued when the `outbox` option is enabled.")
+        .subcommand(
+            SubCommand::with_name("flush")
+                .about("Sends the queued messages again, except the quarantined ones"),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Detects the messages stuck in the outbox, and retries or quarantines them")
+                .long_about("Detects the messages queued for longer than the threshold. Stuck messages are reported, and posted to the webhook. With --retry, they are sent again, the ones failing for the maximum number of attempts being quarantined. With --quarantine, they are quarantined right away. Quarantined messages stay in the outbox, but are not sent anymore until released.")
+                .arg(
+                    Arg::with_name("older-than")
+                        .help("Threshold beyond which queued messages are stuck (90s, 30m, 2h, 1d)")
+                        .long("older-than")
+                        .value_name("AGE")
+                        .default_value("1h"),
+                )
+                .arg(
+                    Arg::with_name("max-attempts")
+                        .help("Number of failed sends after which retried messages are quarantined")
+                        .long("max-attempts")
+                        .value_name("INT")
+                        .default_value("5"),
+                )
+                .arg(
+                    Arg::with_name("retry")
+                        .help("Sends the stuck messages again")
+                        .long("retry")
+                        .conflicts_with("quarantine"),
+                )
+                .arg(
+                    Arg::with_name("quarantine")
+                        .help("Quarantines the stuck messages")
+                        .long("quarantine"),
+                )
+                .arg(
+                    Arg::with_name("notify")
+                        .help("Runs the notify command when messages are still stuck")
+                        .long("notify"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("release")
+                .about("Releases a quarantined message, so that the next flush sends it")
+                .arg(
+                    Arg::with_name("id")
+                        .help("Identifier of the queued message")
+                        .value_name("ID")
+                        .required(true),
+                ),
+        )]
+}
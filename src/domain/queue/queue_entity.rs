@@ -0,0 +1,279 @@
+//! Module related to the outbox.
+//!
+//! When the `outbox` option is enabled, messages the sender fails to send are queued in the
+//! account storage instead of being lost: each one as a raw `.eml` item, next to a `.json` item
+//! recording its envelope, its attempts and its last error. Queued messages are
+//! sent again with `queue flush`. Messages stuck for too long are quarantined by `queue doctor`:
+//! they stay in the outbox, but are not sent anymore until released with `queue release`.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, Local, TimeZone};
+use log::{debug, warn};
+use mailparse::MailHeaderMap;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+use crate::{
+    config::Account,
+    domain::storage::{build_storage, Storage},
+    ui::table::{Cell, Row, Table},
+};
+
+/// Represents a message queued in the outbox.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QueueItem {
+    pub id: String,
+    /// The queue time, as a UNIX timestamp.
+    pub queued_at: i64,
+    pub subject: String,
+    pub envelope: lettre::address::Envelope,
+    /// The number of failed sends, the first one included.
+    pub attempts: u32,
+    pub last_error: String,
+    /// Quarantined messages are kept but not sent anymore.
+    #[serde(default)]
+    pub quarantined: bool,
+}
+
+impl QueueItem {
+    /// Get the recipients of the message.
+    pub fn recipients(&self) -> Vec<String> {
+        self.envelope.to().iter().map(ToString::to_string).collect()
+    }
+
+    /// Check if the message is still waiting in the queue since the given threshold.
+    pub fn is_stuck(&self, threshold: Duration, now: i64) -> bool {
+        !self.quarantined && now - self.queued_at >= threshold.num_seconds()
+    }
+}
+
+/// Parse a queue threshold, like `90s`, `30m`, `2h` or `1d`.
+pub fn parse_threshold(threshold: &str) -> Result<Duration> {
+    let threshold = threshold.trim();
+
+    if let Some(unit) = threshold.chars().last() {
+        let amount = &threshold[..threshold.len() - unit.len_utf8()];
+        if let Ok(amount) = amount.parse::<i64>() {
+            match unit {
+                's' => return Ok(Duration::seconds(amount)),
+                'm' => return Ok(Duration::minutes(amount)),
+                'h' => return Ok(Duration::hours(amount)),
+                'd' => return Ok(Duration::days(amount)),
+                _ => (),
+            }
+        }
+    }
+
+    Err(anyhow!(
+        r#"cannot parse threshold "{}": expected a number of seconds (s), minutes (m), hours (h) or days (d)"#,
+        threshold
+    ))
+}
+
+/// Represents the outbox of an account, oldest messages first.
+#[derive(Debug, Default, Serialize)]
+pub struct Outbox(pub Vec<QueueItem>);
+
+impl Outbox {
+    fn key(id: &str, ext: &str) -> String {
+        format!("outbox/{}.{}", id, ext)
+    }
+
+    pub fn load(account: &Account) -> Result<Self> {
+        let storage = build_storage(account)?;
+
+        let mut items: Vec<QueueItem> = vec![];
+        for key in storage.keys("outbox/")? {
+            if !key.ends_with(".json") {
+                continue;
+            }
+            let item = storage
+                .get(&key)?
+                .ok_or_else(|| anyhow!("item removed meanwhile"))
+                .and_then(|content| Ok(serde_json::from_slice(&content)?));
+            match item {
+                Ok(item) => items.push(item),
+                Err(err) => warn!("cannot parse queued message {}: {}", key, err),
+            }
+        }
+        items.sort_by_key(|item| item.queued_at);
+        Ok(Self(items))
+    }
+
+    /// Queue the given message, the sender failed to send with the given error.
+    pub fn push(
+        account: &Account,
+        envelope: &lettre::address::Envelope,
+        raw_msg: &[u8],
+        err: &anyhow::Error,
+    ) -> Result<QueueItem> {
+        let (headers, _) = mailparse::parse_headers(raw_msg).unwrap_or_default();
+        let now = Local::now();
+        let item = QueueItem {
+            // The pid keeps the ids of concurrent processes apart.
+            id: format!("{}-{}", now.format("%Y%m%d%H%M%S%f"), std::process::id()),
+            queued_at: now.timestamp(),
+            subject: headers.get_first_value("Subject").unwrap_or_default(),
+            envelope: envelope.to_owned(),
+            attempts: 1,
+            last_error: format!("{:#}", err),
+            quarantined: false,
+        };
+        debug!("queue message {}", item.id);
+        let _lock = account.lock_cache()?;
+        let storage = build_storage(account)?;
+        storage
+            .set(&Self::key(&item.id, "eml"), raw_msg)
+            .context("cannot queue message")?;
+        Self::write(storage.as_ref(), &item)?;
+        Ok(item)
+    }
+
+    fn write(storage: &dyn Storage, item: &QueueItem) -> Result<()> {
+        let content = serde_json::to_vec(item).context("cannot serialize queued message")?;
+        storage
+            .set(&Self::key(&item.id, "json"), &content)
+            .context(format!("cannot save queued message {}", item.id))
+    }
+
+    /// Save the attempts and the state of the given queued message.
+    pub fn update(account: &Account, item: &QueueItem) -> Result<()> {
+        let _lock = account.lock_cache()?;
+        Self::write(build_storage(account)?.as_ref(), item)
+    }
+
+    /// Read the raw message of the given queued message.
+    pub fn raw_msg(account: &Account, item: &QueueItem) -> Result<Vec<u8>> {
+        build_storage(account)?
+            .get(&Self::key(&item.id, "eml"))?
+            .ok_or_else(|| anyhow!("cannot find raw message of queued message {}", item.id))
+    }
+
+    /// Remove the given message from the outbox, once sent.
+    pub fn remove(account: &Account, item: &QueueItem) -> Result<()> {
+        debug!("remove message {} from the outbox", item.id);
+        let _lock = account.lock_cache()?;
+        let storage = build_storage(account)?;
+        for ext in &["json", "eml"] {
+            storage
+                .remove(&Self::key(&item.id, ext))
+                .context(format!("cannot remove queued message {}", item.id))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Outbox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", Table::render(&self.0))
+    }
+}
+
+impl Table for QueueItem {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("ID").bold().underline().white())
+            .cell(Cell::new("QUEUED AT").bold().underline().white())
+            .cell(Cell::new("SUBJECT").shrinkable().bold().underline().white())
+            .cell(
+                Cell::new("RECIPIENTS")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+            .cell(Cell::new("ATTEMPTS").bold().underline().white())
+            .cell(
+                Cell::new("LAST ERROR")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+    }
+
+    fn row(&self) -> Row {
+        let date = Local
+            .timestamp(self.queued_at, 0)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let attempts = if self.quarantined {
+            format!("{} (quarantined)", self.attempts)
+        } else {
+            self.attempts.to_string()
+        };
+        Row::new()
+            .cell(Cell::new(&self.id).red())
+            .cell(Cell::new(&date).yellow())
+            .cell(Cell::new(&self.subject).shrinkable().green())
+            .cell(Cell::new(&self.recipients().join(", ")).shrinkable().blue())
+            .cell(Cell::new(&attempts).white())
+            .cell(Cell::new(&self.last_error).shrinkable().white())
+    }
+}
+
+/// Represents the report of the outbox doctor.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QueueDoctorReport {
+    /// The number of stuck messages sent again.
+    pub sent: usize,
+    /// The number of stuck messages quarantined.
+    pub quarantined: usize,
+    /// The stuck messages left in the outbox, quarantined or not.
+    pub stuck: Vec<QueueItem>,
+}
+
+impl Display for QueueDoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.stuck.is_empty() && self.sent == 0 {
+            return write!(f, "No message stuck in the outbox");
+        }
+        write!(
+            f,
+            "{} stuck message(s): {} sent, {} quarantined",
+            self.sent + self.stuck.len(),
+            self.sent,
+            self.quarantined
+        )?;
+        if !self.stuck.is_empty() {
+            write!(f, "\n\n{}", Table::render(&self.stuck))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_detect_stuck_messages() {
+        let envelope = lettre::address::Envelope::new(
+            Some("alice@localhost".parse().unwrap()),
+            vec!["bob@localhost".parse().unwrap()],
+        )
+        .unwrap();
+        let mut item = QueueItem {
+            id: String::from("1"),
+            queued_at: 1_000_000,
+            subject: String::from("Hello"),
+            envelope,
+            attempts: 1,
+            last_error: String::from("connection refused"),
+            quarantined: false,
+        };
+        assert_eq!(vec!["bob@localhost"], item.recipients());
+
+        let threshold = parse_threshold("2h").unwrap();
+        assert!(!item.is_stuck(threshold, 1_000_000 + 3600));
+        assert!(item.is_stuck(threshold, 1_000_000 + 7200));
+        item.quarantined = true;
+        assert!(!item.is_stuck(threshold, 1_000_000 + 7200));
+
+        assert_eq!(Duration::seconds(90), parse_threshold("90s").unwrap());
+        assert_eq!(Duration::days(1), parse_threshold("1d").unwrap());
+        assert!(parse_threshold("soon").is_err());
+    }
+}
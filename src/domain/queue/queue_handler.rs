@@ -0,0 +1,185 @@
+//! Module related to outbox handling.
+//!
+//! This module gathers all outbox commands.
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use imap::types::Flag;
+use log::{debug, warn};
+use std::convert::TryFrom;
+
+use crate::{
+    config::{Account, Config},
+    domain::{
+        backend::{Backend, Sender},
+        mbox::{warn_near_quota, Mbox},
+        metrics::{self, Metric},
+        msg::Flags,
+        queue::{
+            parse_threshold,
+            queue_arg::{DoctorAction, DoctorOpts},
+            Outbox, QueueDoctorReport, QueueItem,
+        },
+        sent::SentLog,
+        webhook::{self, WebhookEvent},
+    },
+    output::OutputServiceInterface,
+};
+
+/// Send the given queued message again. Sent messages are recorded and saved like the ones sent
+/// directly, then removed from the outbox. Returns false when the sender failed again, the
+/// attempt being recorded.
+fn attempt(
+    item: &mut QueueItem,
+    account: &Account,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<bool> {
+    let raw_msg = Outbox::raw_msg(account, item)?;
+    if let Err(err) = sender.send_raw(&item.envelope, &raw_msg) {
+        warn!("cannot send queued message {}: {:#}", item.id, err);
+        metrics::incr(account, Metric::SendErrors, 1);
+        item.attempts += 1;
+        item.last_error = format!("{:#}", err);
+        Outbox::update(account, item)?;
+        return Ok(false);
+    }
+    debug!("queued message {} sent", item.id);
+
+    let recipients = item.recipients();
+    SentLog::record(
+        account,
+        &raw_msg,
+        recipients.clone(),
+        sender.last_response(),
+        None,
+    );
+    let event = WebhookEvent::send_success(&account.name, &item.subject, recipients);
+    webhook::emit(account, &event);
+    metrics::incr(account, Metric::SentMsgs, 1);
+    Outbox::remove(account, item)?;
+
+    if account.save_sent_copy {
+        let mbox = Mbox::from(account.sent_folder.as_str());
+        let flags = Flags::try_from(vec![Flag::Seen])?;
+        warn_near_quota(&mbox, account, backend);
+        backend.append_raw(&mbox, &raw_msg, flags)?;
+    }
+    Ok(true)
+}
+
+/// List the messages queued in the outbox, oldest first.
+pub fn list<OutputService: OutputServiceInterface>(
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    output.print(Outbox::load(account)?)
+}
+
+/// Send the queued messages again, except the quarantined ones.
+pub fn flush<OutputService: OutputServiceInterface>(
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let items: Vec<QueueItem> = Outbox::load(account)?
+        .0
+        .into_iter()
+        .filter(|item| !item.quarantined)
+        .collect();
+    let count = items.len();
+
+    let mut sent = 0;
+    for mut item in items {
+        if attempt(&mut item, account, backend, sender)? {
+            sent += 1;
+        }
+    }
+
+    output.print(format!(
+        "{} message(s) sent, {} still queued",
+        sent,
+        count - sent
+    ))
+}
+
+/// Detect the messages queued for longer than the given threshold. Stuck messages are sent again
+/// or quarantined, depending on the given action, then the ones left are reported: posted to the
+/// webhook and, when asked, notified with the notify command.
+pub fn doctor<OutputService: OutputServiceInterface>(
+    opts: DoctorOpts,
+    config: &Config,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let DoctorOpts {
+        older_than,
+        max_attempts,
+        action,
+        notify,
+    } = opts;
+    let threshold = parse_threshold(older_than)?;
+    let now = Local::now().timestamp();
+    let stuck: Vec<QueueItem> = Outbox::load(account)?
+        .0
+        .into_iter()
+        .filter(|item| item.is_stuck(threshold, now))
+        .collect();
+    debug!("{} message(s) stuck in the outbox", stuck.len());
+
+    let mut report = QueueDoctorReport::default();
+    for mut item in stuck {
+        let quarantine = match action {
+            Some(DoctorAction::Retry) => {
+                if attempt(&mut item, account, backend, sender)? {
+                    report.sent += 1;
+                    continue;
+                }
+                item.attempts >= max_attempts
+            }
+            Some(DoctorAction::Quarantine) => true,
+            None => false,
+        };
+        if quarantine {
+            debug!("quarantine queued message {}", item.id);
+            item.quarantined = true;
+            Outbox::update(account, &item)?;
+            report.quarantined += 1;
+        }
+        let event = WebhookEvent::queue_stuck(
+            &account.name,
+            &item.subject,
+            item.recipients(),
+            &item.last_error,
+        );
+        webhook::emit(account, &event);
+        report.stuck.push(item);
+    }
+
+    if notify && !report.stuck.is_empty() {
+        let subject = format!("{} message(s) stuck in the outbox", report.stuck.len());
+        config.run_notify_cmd(subject.as_str(), account.name.as_str(), None)?;
+    }
+    output.print(report)
+}
+
+/// Release the given quarantined message. It is queued again from now, so that the doctor does
+/// not take it for a stuck message right away.
+pub fn release<OutputService: OutputServiceInterface>(
+    id: &str,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let mut item = Outbox::load(account)?
+        .0
+        .into_iter()
+        .find(|item| item.id == id)
+        .ok_or_else(|| anyhow!(r#"cannot find queued message "{}""#, id))?;
+    item.quarantined = false;
+    item.queued_at = Local::now().timestamp();
+    Outbox::update(account, &item)?;
+    output.print(format!("Message {} successfully released", id))
+}
@@ -0,0 +1,9 @@
+//! Module related to mailbox refiling.
+
+#[cfg(feature = "cli")]
+pub mod refile_arg;
+#[cfg(feature = "cli")]
+pub mod refile_handler;
+
+pub mod refile_entity;
+pub use refile_entity::*;
@@ -0,0 +1,55 @@
+//! Module related to refile CLI.
+//!
+//! This module provides the subcommand and the command matcher related to refiling.
+
+use anyhow::Result;
+use clap::{self, App, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+use crate::domain::msg::msg_arg::{self, OverrideHold};
+
+type RulesPath<'a> = &'a str;
+type DryRun = bool;
+
+/// Refile commands.
+pub enum Command<'a> {
+    /// Move the messages matching the rules of the given file.
+    Refile(RulesPath<'a>, DryRun, OverrideHold),
+}
+
+/// Refile command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("refile") {
+        debug!("refile command matched");
+        let rules_path = m.value_of("rules").unwrap();
+        trace!("rules path: {}", rules_path);
+        let dry_run = m.is_present("dry-run");
+        trace!("dry run: {}", dry_run);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Refile(rules_path, dry_run, override_hold)));
+    }
+
+    Ok(None)
+}
+
+/// Refile subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("refile")
+        .about("Moves messages between mailboxes according to rules")
+        .long_about("Moves the existing messages between mailboxes according to the rules of the given TOML file, one `[[rule]]` table per rule: a `source` and a `target` mailbox, plus optional `from`, `subject` and `older-than` (like `30d`, `6m` or `2y`) conditions. Each message is moved by the first rule it matches. Meant for one-off reorganizations of large mailboxes.")
+        .arg(
+            Arg::with_name("rules")
+                .help("Specifies the file of the refile rules")
+                .long("rules")
+                .short("r")
+                .value_name("FILE")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .help("Lists the messages to move instead of moving them")
+                .long("dry-run"),
+        )
+        .arg(msg_arg::override_hold_arg())]
+}
@@ -0,0 +1,162 @@
+//! Module related to refile rules.
+//!
+//! Refile rules are read from a TOML file, one `[[rule]]` table per rule. Each rule moves the
+//! messages of its source mailbox matching all its conditions to its target mailbox, eg.:
+//!
+//! ```toml
+//! [[rule]]
+//! source = "INBOX"
+//! target = "Archives/Newsletters"
+//! from = "@news.org"
+//! older-than = "30d"
+//! ```
+//!
+//! A rule without any condition moves all the messages of its source mailbox.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, FixedOffset, Local};
+use mailparse::{MailHeader, MailHeaderMap};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Represents a refile rule. Conditions are case-insensitive substring matches, except the age.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RefileRule {
+    /// The mailbox the messages are moved from.
+    pub source: String,
+    /// The mailbox the messages are moved to.
+    pub target: String,
+    /// Match messages whose From header contains the given text.
+    pub from: Option<String>,
+    /// Match messages whose Subject header contains the given text.
+    pub subject: Option<String>,
+    /// Match messages received before the given age, like `30d`, `8w`, `6m` or `2y`.
+    pub older_than: Option<String>,
+}
+
+/// Represents the rules of a refile file, in order.
+#[derive(Debug, Default, Deserialize)]
+pub struct RefileRules {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<RefileRule>,
+}
+
+/// Parse an age, like `30d`, `8w`, `6m` (30 days) or `2y` (365 days).
+fn parse_age(age: &str) -> Result<Duration> {
+    let age = age.trim();
+
+    if let Some(unit) = age.chars().last() {
+        let amount = &age[..age.len() - unit.len_utf8()];
+        if let Ok(amount) = amount.parse::<i64>() {
+            let days = match unit {
+                'd' => Some(amount),
+                'w' => Some(amount * 7),
+                'm' => Some(amount * 30),
+                'y' => Some(amount * 365),
+                _ => None,
+            };
+            if let Some(days) = days {
+                return Ok(Duration::days(days));
+            }
+        }
+    }
+
+    Err(anyhow!(
+        r#"cannot parse age "{}": expected a number of days (d), weeks (w), months (m) or years (y)"#,
+        age
+    ))
+}
+
+impl RefileRules {
+    /// Read the rules of the given file, checking their ages.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).context(format!("cannot read refile rules {:?}", path))?;
+        let rules: Self =
+            toml::from_str(&content).context(format!("cannot parse refile rules {:?}", path))?;
+        for rule in rules.rules.iter() {
+            if let Some(ref age) = rule.older_than {
+                parse_age(age)?;
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Get the source mailboxes of the rules, in order of appearance.
+    pub fn sources(&self) -> Vec<&str> {
+        let mut sources: Vec<&str> = vec![];
+        for rule in self.rules.iter() {
+            if !sources.contains(&rule.source.as_str()) {
+                sources.push(&rule.source);
+            }
+        }
+        sources
+    }
+}
+
+impl RefileRule {
+    /// Check if the message with the given headers and internal date matches the rule.
+    pub fn matches(
+        &self,
+        headers: &[MailHeader],
+        date: Option<&DateTime<FixedOffset>>,
+        now: &DateTime<Local>,
+    ) -> bool {
+        let contains = |key: &str, val: &Option<String>| match val {
+            Some(val) => headers
+                .get_first_value(key)
+                .map(|header| header.to_lowercase().contains(&val.to_lowercase()))
+                .unwrap_or(false),
+            None => true,
+        };
+        let is_old_enough = match (self.older_than.as_deref().map(parse_age), date) {
+            (Some(Ok(age)), Some(date)) => date.timestamp() < (*now - age).timestamp(),
+            (Some(_), _) => false,
+            (None, _) => true,
+        };
+        contains("From", &self.from) && contains("Subject", &self.subject) && is_old_enough
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_match_rules() {
+        let rules: RefileRules = toml::from_str(concat!(
+            "[[rule]]\n",
+            "source = \"INBOX\"\n",
+            "target = \"Archives/News\"\n",
+            "from = \"@news.org\"\n",
+            "older-than = \"30d\"\n",
+            "[[rule]]\n",
+            "source = \"Sent\"\n",
+            "target = \"Archives/Sent\"\n",
+            "[[rule]]\n",
+            "source = \"INBOX\"\n",
+            "target = \"Invoices\"\n",
+            "subject = \"invoice\"\n",
+        ))
+        .unwrap();
+        assert_eq!(vec!["INBOX", "Sent"], rules.sources());
+
+        let now = DateTime::parse_from_rfc3339("2021-12-24T12:00:00+01:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let old = DateTime::parse_from_rfc3339("2021-10-01T12:00:00+01:00").unwrap();
+        let recent = DateTime::parse_from_rfc3339("2021-12-20T12:00:00+01:00").unwrap();
+        let (headers, _) = mailparse::parse_headers(
+            b"From: News <hello@NEWS.org>\r\nSubject: Your invoice\r\n\r\n",
+        )
+        .unwrap();
+        assert!(rules.rules[0].matches(&headers, Some(&old), &now));
+        assert!(!rules.rules[0].matches(&headers, Some(&recent), &now));
+        assert!(!rules.rules[0].matches(&headers, None, &now));
+        assert!(rules.rules[1].matches(&headers, None, &now));
+        assert!(rules.rules[2].matches(&headers, Some(&recent), &now));
+        assert!(parse_age("6m").is_ok());
+        assert!(parse_age("soon").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Module related to refile handling.
+//!
+//! This module gathers all refile commands.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use log::{debug, trace};
+use mailparse::MailHeaderMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::build_backend,
+        mbox::Mbox,
+        msg::msg_hold,
+        refile::{RefileRule, RefileRules},
+    },
+    output::OutputServiceInterface,
+};
+
+/// Number of messages moved per MOVE command.
+const MOVE_BATCH_SIZE: usize = 100;
+
+/// Move the messages matching the rules of the given file, mailbox by mailbox. Each message is
+/// moved by the first rule it matches. With `dry_run`, only list them. Messages on hold are left
+/// in place, unless `override_hold` is true.
+pub fn refile<OutputService: OutputServiceInterface>(
+    rules_path: &str,
+    dry_run: bool,
+    override_hold: bool,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let rules = RefileRules::load(Path::new(rules_path))?;
+    let now = Local::now();
+    let mut lines = vec![];
+    let mut count = 0;
+    let mut held_count = 0;
+
+    for source in rules.sources() {
+        let source_rules: Vec<&RefileRule> = rules
+            .rules
+            .iter()
+            .filter(|rule| rule.source == source)
+            .collect();
+        let mbox = Mbox::from(source);
+        // Messages are identified by UID, so that moving some does not shift the others.
+        let mut backend = build_backend(account, &mbox, false);
+        let dates: HashMap<u32, DateTime<FixedOffset>> =
+            backend.get_internal_dates("1:*")?.into_iter().collect();
+        let held: HashSet<u32> = if override_hold || dates.is_empty() {
+            HashSet::new()
+        } else {
+            msg_hold::held_ids("1:*", false, account, backend.as_mut())?
+                .into_iter()
+                .collect()
+        };
+
+        let mut targets: Vec<(&str, Vec<u32>)> = vec![];
+        for (id, raw_headers) in backend.get_raw_headers("1:*")? {
+            let (headers, _) = mailparse::parse_headers(&raw_headers)
+                .context(format!("cannot parse headers of message {}", id))?;
+            let rule = match source_rules
+                .iter()
+                .find(|rule| rule.matches(&headers, dates.get(&id), &now))
+            {
+                // Messages already in the target mailbox stay there.
+                Some(rule) if rule.target == source => continue,
+                Some(rule) => rule,
+                None => continue,
+            };
+            if held.contains(&id) {
+                held_count += 1;
+                lines.push(format!(
+                    r#"Message {} of "{}" is on hold, left in place"#,
+                    id, source
+                ));
+                continue;
+            }
+            if dry_run {
+                lines.push(format!(
+                    r#"Message {} of "{}" would move to "{}": {}"#,
+                    id,
+                    source,
+                    rule.target,
+                    headers.get_first_value("Subject").unwrap_or_default()
+                ));
+            }
+            match targets
+                .iter_mut()
+                .find(|(target, _)| *target == rule.target)
+            {
+                Some((_, ids)) => ids.push(id),
+                None => targets.push((rule.target.as_str(), vec![id])),
+            }
+        }
+        trace!("messages to refile from {:?}: {:?}", source, targets);
+
+        for (target, ids) in targets {
+            count += ids.len();
+            if dry_run {
+                continue;
+            }
+            debug!(
+                "refile {} message(s) from {:?} to {:?}",
+                ids.len(),
+                source,
+                target
+            );
+            let target_mbox = Mbox::from(target);
+            backend.create_mbox(&target_mbox)?;
+            let mut progress = output.progress(
+                &format!(r#"Refiling "{}" to "{}""#, source, target),
+                ids.len(),
+            );
+            for batch in ids.chunks(MOVE_BATCH_SIZE) {
+                let batch: Vec<String> = batch.iter().map(|id| id.to_string()).collect();
+                backend.move_msg(&batch.join(","), &target_mbox)?;
+                progress.inc(batch.len())?;
+            }
+            progress.finish();
+        }
+        backend.logout()?;
+    }
+
+    if dry_run {
+        lines.push(format!("{} message(s) would be refiled", count));
+        return output.print(lines.join("\n"));
+    }
+    if held_count > 0 {
+        lines.push(format!(
+            "{} message(s) on hold left in place, use --override-hold to refile them",
+            held_count
+        ));
+    }
+    lines.push(format!("{} message(s) successfully refiled", count));
+    output.print(lines.join("\n"))
+}
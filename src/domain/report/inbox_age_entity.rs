@@ -0,0 +1,216 @@
+//! Module related to the inbox aging report.
+//!
+//! The report is computed from the envelopes of the inbox, so that only headers are fetched: the
+//! messages still waiting for an answer, or still unread, are counted by age bucket, and the
+//! oldest unanswered ones are listed.
+
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+use crate::{
+    domain::msg::{Envelope, Flag},
+    ui::table::{Cell, Row, Table},
+};
+
+/// The age buckets, by their upper bound in days.
+const BUCKETS: [(&str, Option<i64>); 6] = [
+    ("< 1 day", Some(1)),
+    ("1-7 days", Some(7)),
+    ("1-4 weeks", Some(28)),
+    ("1-3 months", Some(91)),
+    ("3-12 months", Some(365)),
+    ("> 1 year", None),
+];
+
+/// Represents the messages of an age bucket.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct AgeBucket {
+    pub age: String,
+    pub unanswered: usize,
+    pub unread: usize,
+}
+
+/// Represents one of the oldest unanswered messages.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct AgedMsg {
+    pub id: u32,
+    /// The age in days.
+    pub age: i64,
+    pub sender: String,
+    pub subject: String,
+}
+
+/// Represents the aging report of the inbox.
+#[derive(Debug, Default, Serialize)]
+pub struct InboxAge {
+    pub unanswered: usize,
+    pub unread: usize,
+    /// The age buckets, youngest first.
+    pub buckets: Vec<AgeBucket>,
+    /// The oldest unanswered messages, oldest first.
+    pub oldest: Vec<AgedMsg>,
+}
+
+impl InboxAge {
+    /// Compute the report of the given envelopes at the given time, listing the given number of
+    /// oldest messages. Messages without a date are left out.
+    pub fn new(envelopes: &[Envelope], now: NaiveDateTime, top: usize) -> Self {
+        let mut report = Self {
+            buckets: BUCKETS
+                .iter()
+                .map(|(age, _)| AgeBucket {
+                    age: String::from(*age),
+                    ..AgeBucket::default()
+                })
+                .collect(),
+            ..Self::default()
+        };
+
+        for envelope in envelopes {
+            let answered = envelope.flags.contains(&Flag::Answered);
+            let seen = envelope.flags.contains(&Flag::Seen);
+            if answered && seen {
+                continue;
+            }
+            // Dates are stored as printed by NaiveDateTime, like `2021-12-01 08:00:00`.
+            let date = match envelope
+                .date
+                .as_deref()
+                .and_then(|date| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").ok())
+            {
+                Some(date) => date,
+                None => continue,
+            };
+            let age = now - date;
+            let index = BUCKETS
+                .iter()
+                .position(|(_, days)| days.map(|days| age < Duration::days(days)).unwrap_or(true))
+                .unwrap_or(BUCKETS.len() - 1);
+
+            let bucket = &mut report.buckets[index];
+            if !answered {
+                bucket.unanswered += 1;
+                report.unanswered += 1;
+                report.oldest.push(AgedMsg {
+                    id: envelope.id,
+                    age: age.num_days(),
+                    sender: envelope.sender.to_owned(),
+                    subject: envelope.subject.to_owned(),
+                });
+            }
+            if !seen {
+                bucket.unread += 1;
+                report.unread += 1;
+            }
+        }
+
+        report
+            .oldest
+            .sort_by(|a, b| b.age.cmp(&a.age).then_with(|| a.id.cmp(&b.id)));
+        report.oldest.truncate(top);
+        report
+    }
+}
+
+impl Display for InboxAge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "\nINBOX: {} unanswered, {} unread message(s)",
+            self.unanswered, self.unread
+        )?;
+        writeln!(f, "\n{}", Table::render(&self.buckets))?;
+        writeln!(f, "\n{}", Table::render(&self.oldest))
+    }
+}
+
+impl Table for AgeBucket {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("AGE").bold().underline().white())
+            .cell(Cell::new("UNANSWERED").bold().underline().white())
+            .cell(Cell::new("UNREAD").bold().underline().white())
+            .cell(
+                Cell::new("HISTOGRAM")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.age).green())
+            .cell(Cell::new(&self.unanswered.to_string()).red())
+            .cell(Cell::new(&self.unread.to_string()).yellow())
+            .cell(
+                Cell::new(&"#".repeat(self.unanswered.min(50)))
+                    .shrinkable()
+                    .blue(),
+            )
+    }
+}
+
+impl Table for AgedMsg {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("ID").bold().underline().white())
+            .cell(Cell::new("DAYS").bold().underline().white())
+            .cell(Cell::new("SUBJECT").shrinkable().bold().underline().white())
+            .cell(Cell::new("SENDER").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.id.to_string()).red())
+            .cell(Cell::new(&self.age.to_string()).yellow())
+            .cell(Cell::new(&self.subject).shrinkable().green())
+            .cell(Cell::new(&self.sender).blue())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn envelope(id: u32, date: &str, flags: Vec<Flag<'static>>) -> Envelope {
+        let mut envelope = Envelope {
+            id,
+            date: Some(date.to_owned()),
+            ..Envelope::default()
+        };
+        envelope.flags.extend(flags);
+        envelope
+    }
+
+    #[test]
+    fn it_should_compute_inbox_age() {
+        let now = NaiveDate::from_ymd(2021, 12, 24).and_hms(12, 0, 0);
+        let envelopes = vec![
+            envelope(1, "2021-12-24 08:00:00", vec![]),
+            envelope(2, "2021-12-20 08:00:00", vec![Flag::Seen]),
+            envelope(3, "2020-01-01 08:00:00", vec![Flag::Seen]),
+            envelope(4, "2021-12-01 08:00:00", vec![Flag::Seen, Flag::Answered]),
+            envelope(5, "2021-12-01 08:00:00", vec![Flag::Answered]),
+        ];
+        let report = InboxAge::new(&envelopes, now, 2);
+
+        assert_eq!(3, report.unanswered);
+        assert_eq!(2, report.unread);
+        assert_eq!(
+            vec![(1, 1), (1, 0), (0, 1), (0, 0), (0, 0), (1, 0)],
+            report
+                .buckets
+                .iter()
+                .map(|bucket| (bucket.unanswered, bucket.unread))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![3, 2],
+            report.oldest.iter().map(|msg| msg.id).collect::<Vec<_>>()
+        );
+    }
+}
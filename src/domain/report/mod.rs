@@ -0,0 +1,9 @@
+//! Module related to reports.
+
+#[cfg(feature = "cli")]
+pub mod report_arg;
+#[cfg(feature = "cli")]
+pub mod report_handler;
+
+pub mod inbox_age_entity;
+pub use inbox_age_entity::*;
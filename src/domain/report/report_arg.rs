@@ -0,0 +1,52 @@
+//! Module related to report CLI.
+//!
+//! This module provides subcommands and a command matcher related to reports.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Top = usize;
+
+/// Report commands.
+pub enum Command {
+    /// Report the unanswered and unread messages of the inbox by age, with the given number of
+    /// oldest messages.
+    InboxAge(Top),
+}
+
+/// Report command matcher.
+pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
+    if let Some(m) = m.subcommand_matches("report") {
+        if let Some(m) = m.subcommand_matches("inbox-age") {
+            debug!("report inbox-age command matched");
+            let top = m
+                .value_of("top")
+                .and_then(|top| top.parse().ok())
+                .unwrap_or(10);
+            trace!("top: {}", top);
+            return Ok(Some(Command::InboxAge(top)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Report subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("report")
+        .about("Reports on mailboxes")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("inbox-age")
+                .about("Reports unanswered and unread INBOX messages by age, and the oldest ones")
+                .arg(
+                    Arg::with_name("top")
+                        .help("Defines the number of oldest messages to report")
+                        .long("top")
+                        .short("t")
+                        .value_name("N")
+                        .default_value("10"),
+                ),
+        )]
+}
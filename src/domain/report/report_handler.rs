@@ -0,0 +1,39 @@
+//! Module related to reports handling.
+//!
+//! This module gathers all report commands.
+
+use anyhow::Result;
+use chrono::Local;
+use log::debug;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{build_backend, Backend},
+        mbox::Mbox,
+        report::InboxAge,
+    },
+    output::OutputServiceInterface,
+};
+
+/// Report the unanswered and unread messages of the inbox by age, with the given number of
+/// oldest messages.
+pub fn inbox_age<OutputService: OutputServiceInterface>(
+    top: usize,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let envelopes = if mbox.name.eq_ignore_ascii_case("INBOX") {
+        backend.list_envelopes(&0, &0)?
+    } else {
+        let inbox = Mbox::from("INBOX");
+        let mut backend = build_backend(account, &inbox, false);
+        let envelopes = backend.list_envelopes(&0, &0)?;
+        backend.logout()?;
+        envelopes
+    };
+    debug!("envelopes len: {}", envelopes.0.len());
+    output.print(InboxAge::new(&envelopes.0, Local::now().naive_local(), top))
+}
@@ -0,0 +1,4 @@
+//! Module related to sendmail.
+
+pub mod sendmail_service;
+pub use sendmail_service::*;
@@ -0,0 +1,106 @@
+//! Module related to sendmail servicing.
+//!
+//! This module exposes a sender that pipes messages to an external command, typically a local MTA
+//! like `sendmail` or `msmtp`, instead of talking to an SMTP server. The recipients of the
+//! envelope are passed to the command as arguments, after `-i --`: they are not read from the
+//! headers, which do not hold the Bcc recipients nor the bounce ones.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, trace};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    config::Account,
+    domain::{backend::Sender, msg::Msg},
+};
+
+pub struct SendmailService<'a> {
+    account: &'a Account,
+}
+
+impl<'a> SendmailService<'a> {
+    fn cmd(&self) -> Result<&str> {
+        self.account.sendmail_cmd.as_deref().ok_or_else(|| {
+            anyhow!(
+                r#"cannot find "sendmail-cmd" in account "{}""#,
+                self.account.name
+            )
+        })
+    }
+
+    fn pipe(&self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()> {
+        let cmd = self.cmd()?;
+        let rcpts: Vec<String> = envelope.to().iter().map(ToString::to_string).collect();
+        if rcpts.is_empty() {
+            return Err(anyhow!("cannot send message: no recipient found"));
+        }
+        debug!("sendmail cmd: {}", cmd);
+        debug!("recipients: {:?}", rcpts);
+
+        let mut child = if cfg!(target_os = "windows") {
+            Command::new("cmd")
+                .args(&["/C", &format!("{} -i -- {}", cmd, rcpts.join(" "))])
+                .stdin(Stdio::piped())
+                .spawn()
+        } else {
+            // Recipients are passed as positional parameters, so that the shell does not
+            // interpret them.
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!(r#"{} -i -- "$@""#, cmd))
+                .arg("sh")
+                .args(&rcpts)
+                .stdin(Stdio::piped())
+                .spawn()
+        }
+        .context(format!(r#"cannot run sendmail cmd "{}""#, cmd))?;
+
+        // Local MTAs expect local line endings.
+        let msg = String::from_utf8_lossy(msg).replace("\r\n", "\n");
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!(r#"cannot open stdin of sendmail cmd "{}""#, cmd))?
+            .write_all(msg.as_bytes())
+            .context(format!(r#"cannot pipe message to sendmail cmd "{}""#, cmd))?;
+
+        let status = child
+            .wait()
+            .context(format!(r#"cannot wait for sendmail cmd "{}""#, cmd))?;
+        if !status.success() {
+            return Err(anyhow!(
+                r#"cannot send message: sendmail cmd "{}" exited with {}"#,
+                cmd,
+                status
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Sender for SendmailService<'a> {
+    fn send(&mut self, msg: &Msg) -> Result<Vec<u8>> {
+        debug!("sending message…");
+        let sendable_msg = msg.to_sendable_msg(false, self.account.format_flowed)?;
+        let raw_msg = msg.format_sendable_msg(&sendable_msg);
+        self.pipe(sendable_msg.envelope(), &raw_msg)?;
+        Ok(raw_msg)
+    }
+
+    fn send_raw(&mut self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()> {
+        debug!("sending raw message…");
+        trace!("envelope: {:?}", envelope);
+        self.pipe(envelope, msg)
+    }
+}
+
+impl<'a> From<&'a Account> for SendmailService<'a> {
+    fn from(account: &'a Account) -> Self {
+        debug!("init sendmail service");
+        Self { account }
+    }
+}
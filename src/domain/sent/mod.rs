@@ -0,0 +1,9 @@
+//! Module related to sent messages.
+
+#[cfg(feature = "cli")]
+pub mod sent_arg;
+#[cfg(feature = "cli")]
+pub mod sent_handler;
+
+pub mod sent_log_entity;
+pub use sent_log_entity::*;
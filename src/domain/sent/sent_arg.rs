@@ -0,0 +1,108 @@
+//! Module related to sent messages CLI.
+//!
+//! This module provides subcommands and a command matcher related to sent messages.
+
+use anyhow::Result;
+use clap::{self, App, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Query<'a> = Option<&'a str>;
+type To<'a> = Option<&'a str>;
+type Since<'a> = Option<&'a str>;
+type PageSize = Option<usize>;
+type Page = usize;
+
+/// Options of the sent search command.
+pub struct SearchOpts<'a> {
+    pub to: To<'a>,
+    pub since: Since<'a>,
+    pub page_size: PageSize,
+    pub page: Page,
+}
+
+/// Sent messages commands.
+pub enum Command<'a> {
+    /// List the delivery log entries matching the optional query.
+    Log(Query<'a>),
+    /// Search the Sent folder by recipient and date.
+    Search(SearchOpts<'a>),
+}
+
+/// Sent messages command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("sent") {
+        if let Some(m) = m.subcommand_matches("log") {
+            debug!("sent log command matched");
+            let query = m.value_of("query");
+            trace!("query: {:?}", query);
+            return Ok(Some(Command::Log(query)));
+        }
+
+        debug!("sent search command matched");
+        let to = m.value_of("to");
+        trace!("to: {:?}", to);
+        let since = m.value_of("since");
+        trace!("since: {:?}", since);
+        let page_size = m.value_of("page-size").and_then(|s| s.parse().ok());
+        trace!(r#"page size: {:?}"#, page_size);
+        let page = m
+            .value_of("page")
+            .unwrap_or("1")
+            .parse()
+            .ok()
+            .map(|page| 1.max(page) - 1)
+            .unwrap_or_default();
+        trace!(r#"page: {:?}"#, page);
+        return Ok(Some(Command::Search(SearchOpts {
+            to,
+            since,
+            page_size,
+            page,
+        })));
+    }
+
+    Ok(None)
+}
+
+/// Sent messages subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("sent")
+        .about("Searches the Sent folder, or manages sent messages")
+        .arg(
+            Arg::with_name("to")
+                .help("Matches messages sent to the given address")
+                .short("t")
+                .long("to")
+                .value_name("ADDR"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .help("Matches messages sent since the given age (30d, 2w, 6m, 1y) or date (2021-12-24)")
+                .long("since")
+                .value_name("WHEN"),
+        )
+        .arg(
+            Arg::with_name("page-size")
+                .help("Page size")
+                .short("s")
+                .long("size")
+                .value_name("INT"),
+        )
+        .arg(
+            Arg::with_name("page")
+                .help("Page number")
+                .short("p")
+                .long("page")
+                .value_name("INT")
+                .default_value("0"),
+        )
+        .subcommand(
+            SubCommand::with_name("log")
+                .about("Lists the messages accepted by the sender, with the server response")
+                .arg(
+                    Arg::with_name("query")
+                        .help("Filters entries by Message-ID, subject, recipient or queue ID")
+                        .value_name("QUERY"),
+                ),
+        )]
+}
@@ -0,0 +1,135 @@
+//! Module related to sent messages handling.
+//!
+//! This module gathers all sent messages commands.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, Local, NaiveDate};
+use log::{debug, trace};
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{Backend, Sender},
+        mbox::Mbox,
+        msg::{
+            msg_arg, msg_handler,
+            msg_query::{SearchCriterion, SearchQuery, SearchTerm},
+        },
+        sent::{sent_arg::SearchOpts, SentLog},
+    },
+    output::OutputServiceInterface,
+};
+
+/// List the delivery log entries of the account, newest first.
+pub fn log<OutputService: OutputServiceInterface>(
+    query: Option<&str>,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let mut sent_log = SentLog::load(account)?;
+    if let Some(query) = query {
+        sent_log = sent_log.filter(query);
+    }
+    sent_log.0.reverse();
+    trace!("sent log: {:#?}", sent_log);
+    output.print(sent_log)
+}
+
+/// Parse the start day of a search, relative to `today`. Accepted formats are ages (`30d`, `2w`,
+/// `6m`, `1y`, months and years counting 30 and 365 days) and dates (`2021-12-24`).
+fn parse_since(since: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let since = since.trim();
+
+    if let Some(unit) = since.chars().last() {
+        let amount = &since[..since.len() - unit.len_utf8()];
+        if let Ok(amount) = amount.parse::<i64>() {
+            let days = match unit {
+                'd' => Some(amount),
+                'w' => Some(amount * 7),
+                'm' => Some(amount * 30),
+                'y' => Some(amount * 365),
+                _ => None,
+            };
+            if let Some(days) = days {
+                return Ok(today - Duration::days(days));
+            }
+        }
+    }
+
+    NaiveDate::parse_from_str(since, "%Y-%m-%d").map_err(|_| {
+        anyhow!(
+            r#"cannot parse date "{}": expected an age or YYYY-MM-DD"#,
+            since
+        )
+    })
+}
+
+/// Search the Sent folder of the account by recipient and date.
+pub fn search<OutputService: OutputServiceInterface>(
+    opts: SearchOpts,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+    sender: &mut dyn Sender,
+) -> Result<()> {
+    let SearchOpts {
+        to,
+        since,
+        page_size,
+        page,
+    } = opts;
+    let mut terms = vec![];
+    if let Some(to) = to {
+        terms.push(SearchTerm {
+            criterion: SearchCriterion::To(to.to_owned()),
+            negated: false,
+        });
+    }
+    if let Some(since) = since {
+        let since =
+            parse_since(since, Local::today().naive_local()).context("cannot parse sent search")?;
+        terms.push(SearchTerm {
+            // `after` leaves the given day out.
+            criterion: SearchCriterion::After(since.pred()),
+            negated: false,
+        });
+    }
+    let query = SearchQuery(terms).to_imap();
+    debug!("sent search query: {}", query);
+
+    let opts = msg_arg::SearchOpts {
+        query,
+        page_size,
+        page,
+        ..msg_arg::SearchOpts::default()
+    };
+    msg_handler::search(opts, mbox, account, output, backend, sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_since() {
+        let today = NaiveDate::from_ymd(2021, 12, 24);
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 11, 24),
+            parse_since("30d", today).unwrap()
+        );
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 12, 10),
+            parse_since("2w", today).unwrap()
+        );
+        assert_eq!(
+            NaiveDate::from_ymd(2020, 12, 24),
+            parse_since("1y", today).unwrap()
+        );
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 6, 1),
+            parse_since("2021-06-01", today).unwrap()
+        );
+        assert!(parse_since("soon", today).is_err());
+    }
+}
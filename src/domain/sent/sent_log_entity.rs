@@ -0,0 +1,229 @@
+//! Module related to the delivery log.
+//!
+//! Each message accepted by the sender is recorded locally in the account storage, one JSON
+//! entry per line, together with the server response. It gives an authoritative local answer
+//! to "did that message actually go out?".
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+use log::{debug, warn};
+use mailparse::MailHeaderMap;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+use crate::{
+    config::Account,
+    domain::storage::build_storage,
+    ui::table::{Cell, Row, Table},
+};
+
+const KEY: &str = "sent.log";
+
+/// Represents a message accepted by the sender.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SentLogEntry {
+    /// The send time, as a UNIX timestamp.
+    pub sent_at: i64,
+    pub message_id: String,
+    pub subject: String,
+    /// The recipients accepted by the server.
+    pub recipients: Vec<String>,
+    /// The identifier the server queued the message with, when it tells it.
+    pub queue_id: Option<String>,
+    /// The final response of the server, when the sender talks to one.
+    pub response: Option<String>,
+    /// The key given by the user to make sure the message is sent only once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+impl SentLogEntry {
+    /// Build an entry from the message as sent.
+    pub fn new(raw_msg: &[u8], recipients: Vec<String>, response: Option<String>) -> Self {
+        let (headers, _) = mailparse::parse_headers(raw_msg).unwrap_or_default();
+        let header = |key: &str| headers.get_first_value(key).unwrap_or_default();
+        Self {
+            sent_at: Local::now().timestamp(),
+            message_id: header("Message-ID"),
+            subject: header("Subject"),
+            recipients,
+            queue_id: response.as_deref().and_then(parse_queue_id),
+            response,
+            idempotency_key: None,
+        }
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let mut fields = vec![&self.message_id, &self.subject];
+        fields.extend(self.recipients.iter());
+        fields.extend(self.queue_id.iter());
+        fields
+            .iter()
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+/// Extract the queue identifier from the final response of an SMTP server, like the `250 2.0.0
+/// Ok: queued as 4HTZ1b2xyz` of Postfix or the `250 OK id=1mYRuz-0003Hd-6B` of Exim.
+pub fn parse_queue_id(response: &str) -> Option<String> {
+    let words: Vec<&str> = response.split_whitespace().collect();
+    words
+        .windows(3)
+        .find(|words| words[0].eq_ignore_ascii_case("queued") && words[1] == "as")
+        .map(|words| words[2])
+        .or_else(|| {
+            words
+                .iter()
+                .find_map(|word| word.strip_prefix("id="))
+                .filter(|id| !id.is_empty())
+        })
+        .map(|id| id.trim_end_matches(|c| c == '.' || c == ')').to_owned())
+}
+
+/// Represents the delivery log of an account, oldest entries first.
+#[derive(Debug, Default, Serialize)]
+pub struct SentLog(pub Vec<SentLogEntry>);
+
+impl SentLog {
+    pub fn load(account: &Account) -> Result<Self> {
+        let content = match build_storage(account)?.get(KEY)? {
+            Some(content) => String::from_utf8_lossy(&content).into_owned(),
+            None => return Ok(Self::default()),
+        };
+
+        let mut entries = vec![];
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!("cannot parse line {} of sent log: {}", i + 1, err),
+            }
+        }
+        Ok(Self(entries))
+    }
+
+    /// Append the given entry to the log of the given account.
+    pub fn append(account: &Account, entry: &SentLogEntry) -> Result<()> {
+        debug!("record sent message {}", entry.message_id);
+        let line = serde_json::to_string(entry).context("cannot serialize sent log entry")?;
+        let _lock = account.lock_cache()?;
+        build_storage(account)?
+            .append(KEY, format!("{}\n", line).as_bytes())
+            .context("cannot write sent log")
+    }
+
+    /// Record the given message as sent. Failures are only logged, since the message is gone
+    /// anyway.
+    pub fn record(
+        account: &Account,
+        raw_msg: &[u8],
+        recipients: Vec<String>,
+        response: Option<String>,
+        idempotency_key: Option<&str>,
+    ) {
+        let mut entry = SentLogEntry::new(raw_msg, recipients, response);
+        entry.idempotency_key = idempotency_key.map(String::from);
+        if let Err(err) = Self::append(account, &entry) {
+            warn!("{:?}", err);
+        }
+    }
+
+    /// Iterate over the recipients of all the logged messages, which are the addresses the
+    /// account has already written to.
+    pub fn recipients(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().flat_map(|entry| entry.recipients.iter())
+    }
+
+    /// Find the entry of the message sent with the given idempotency key.
+    pub fn find_by_idempotency_key(&self, key: &str) -> Option<&SentLogEntry> {
+        self.0
+            .iter()
+            .find(|entry| entry.idempotency_key.as_deref() == Some(key))
+    }
+
+    /// Keep the entries matching the given query, case-insensitively, on their Message-ID,
+    /// subject, recipients or queue ID.
+    pub fn filter(self, query: &str) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .filter(|entry| entry.matches(query))
+                .collect(),
+        )
+    }
+}
+
+impl Display for SentLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", Table::render(&self.0))
+    }
+}
+
+impl Table for SentLogEntry {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("DATE").bold().underline().white())
+            .cell(Cell::new("SUBJECT").shrinkable().bold().underline().white())
+            .cell(
+                Cell::new("RECIPIENTS")
+                    .shrinkable()
+                    .bold()
+                    .underline()
+                    .white(),
+            )
+            .cell(Cell::new("QUEUE ID").bold().underline().white())
+            .cell(Cell::new("MESSAGE ID").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        let date = Local
+            .timestamp(self.sent_at, 0)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        Row::new()
+            .cell(Cell::new(&date).yellow())
+            .cell(Cell::new(&self.subject).shrinkable().green())
+            .cell(Cell::new(&self.recipients.join(", ")).shrinkable().blue())
+            .cell(Cell::new(self.queue_id.as_deref().unwrap_or_default()).red())
+            .cell(Cell::new(&self.message_id).white())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_queue_id() {
+        assert_eq!(
+            Some(String::from("4HTZ1b2xyz")),
+            parse_queue_id("250 2.0.0 Ok: queued as 4HTZ1b2xyz")
+        );
+        assert_eq!(
+            Some(String::from("1mYRuz-0003Hd-6B")),
+            parse_queue_id("250 OK id=1mYRuz-0003Hd-6B")
+        );
+        assert_eq!(None, parse_queue_id("250 2.0.0 OK"));
+
+        let entry = SentLogEntry::new(
+            b"Message-ID: <42@localhost>\r\nSubject: Hello\r\n\r\nHi!",
+            vec![String::from("bob@localhost")],
+            Some(String::from("250 2.0.0 Ok: queued as ABC")),
+        );
+        assert_eq!("<42@localhost>", entry.message_id);
+        assert_eq!("Hello", entry.subject);
+        assert_eq!(Some(String::from("ABC")), entry.queue_id);
+        assert!(entry.matches("BOB@"));
+        assert!(!entry.matches("alice"));
+
+        let mut keyed_entry = entry.clone();
+        keyed_entry.idempotency_key = Some(String::from("report-2021-12"));
+        let sent_log = SentLog(vec![entry, keyed_entry]);
+        assert!(sent_log.find_by_idempotency_key("report-2021-12").is_some());
+        assert!(sent_log.find_by_idempotency_key("report-2022-01").is_none());
+    }
+}
@@ -0,0 +1,9 @@
+//! Module related to ManageSieve.
+
+#[cfg(feature = "cli")]
+pub mod sieve_arg;
+#[cfg(feature = "cli")]
+pub mod sieve_handler;
+
+pub mod sieve_service;
+pub use sieve_service::*;
@@ -0,0 +1,96 @@
+//! Module related to Sieve CLI.
+//!
+//! This module provides subcommands and a command matcher related to server-side Sieve scripts.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+type Name<'a> = &'a str;
+type Path<'a> = Option<&'a str>;
+
+/// Sieve commands.
+pub enum Command<'a> {
+    /// List the Sieve scripts stored on the server.
+    List,
+
+    /// Print the given Sieve script.
+    Get(Name<'a>),
+
+    /// Upload a Sieve script from the given file, or from stdin.
+    Put(Name<'a>, Path<'a>),
+
+    /// Activate the given Sieve script.
+    Activate(Name<'a>),
+}
+
+/// Sieve command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("sieve") {
+        if m.subcommand_matches("list").is_some() {
+            debug!("list command matched");
+            return Ok(Some(Command::List));
+        }
+
+        if let Some(m) = m.subcommand_matches("get") {
+            debug!("get command matched");
+            let name = m.value_of("name").unwrap();
+            trace!("name: {}", name);
+            return Ok(Some(Command::Get(name)));
+        }
+
+        if let Some(m) = m.subcommand_matches("put") {
+            debug!("put command matched");
+            let name = m.value_of("name").unwrap();
+            trace!("name: {}", name);
+            let path = m.value_of("path");
+            trace!("path: {:?}", path);
+            return Ok(Some(Command::Put(name, path)));
+        }
+
+        if let Some(m) = m.subcommand_matches("activate") {
+            debug!("activate command matched");
+            let name = m.value_of("name").unwrap();
+            trace!("name: {}", name);
+            return Ok(Some(Command::Activate(name)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Sieve script name argument.
+fn name_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("name")
+        .help("Specifies the Sieve script name")
+        .value_name("NAME")
+        .required(true)
+}
+
+/// Sieve subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("sieve")
+        .about("Manages server-side Sieve scripts through ManageSieve")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("list").about("Lists Sieve scripts"))
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Prints a Sieve script")
+                .arg(name_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("put")
+                .about("Uploads a Sieve script, replacing the one with the same name")
+                .arg(name_arg())
+                .arg(
+                    Arg::with_name("path")
+                        .help("Reads the script from the given file, defaults to stdin")
+                        .value_name("PATH"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("activate")
+                .about("Activates a Sieve script, deactivating the others")
+                .arg(name_arg()),
+        )]
+}
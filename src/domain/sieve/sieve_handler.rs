@@ -0,0 +1,75 @@
+//! Module related to Sieve handling.
+//!
+//! This module gathers all Sieve actions triggered by the CLI.
+
+use anyhow::{anyhow, Context, Result};
+use atty::Stream;
+use log::trace;
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+use crate::{
+    config::Account,
+    domain::sieve::SieveService,
+    output::{OutputService, OutputServiceInterface},
+};
+
+/// List the Sieve scripts stored on the server.
+pub fn list(account: &Account, output: &OutputService) -> Result<()> {
+    let mut sieve = SieveService::from(account);
+    let scripts = sieve.list_scripts()?;
+    trace!("scripts: {:?}", scripts);
+    sieve.logout()?;
+    output.print(scripts)
+}
+
+/// Print the given Sieve script.
+pub fn get(name: &str, account: &Account, output: &OutputService) -> Result<()> {
+    let mut sieve = SieveService::from(account);
+    let script = sieve.get_script(name)?;
+    sieve.logout()?;
+    output.print(script)
+}
+
+/// Upload a Sieve script from the given file, or from stdin when piped.
+pub fn put(
+    name: &str,
+    path: Option<&str>,
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let script = match path {
+        Some(path) => {
+            fs::read_to_string(path).context(format!(r#"cannot read Sieve script "{}""#, path))?
+        }
+        None if atty::is(Stream::Stdin) => {
+            return Err(anyhow!(
+                "cannot read Sieve script: no path given and nothing piped to stdin"
+            ));
+        }
+        None => {
+            let mut script = String::new();
+            io::stdin()
+                .read_to_string(&mut script)
+                .context("cannot read Sieve script from stdin")?;
+            script
+        }
+    };
+    // Sieve scripts use CRLF line endings.
+    let script = script.replace("\r", "").replace("\n", "\r\n");
+
+    let mut sieve = SieveService::from(account);
+    sieve.put_script(name, &script)?;
+    sieve.logout()?;
+    output.print(format!(r#"Sieve script "{}" successfully uploaded"#, name))
+}
+
+/// Activate the given Sieve script.
+pub fn activate(name: &str, account: &Account, output: &OutputService) -> Result<()> {
+    let mut sieve = SieveService::from(account);
+    sieve.activate_script(name)?;
+    sieve.logout()?;
+    output.print(format!(r#"Sieve script "{}" successfully activated"#, name))
+}
@@ -0,0 +1,318 @@
+//! Module related to ManageSieve servicing.
+//!
+//! This module exposes a minimal [ManageSieve] client, able to list, get, put and activate the
+//! Sieve scripts of an account. It reuses the IMAP credentials and TLS settings of the account.
+//!
+//! [ManageSieve]: https://datatracker.ietf.org/doc/html/rfc5804
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, trace};
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+};
+
+use crate::{
+    config::{proxy, tls, Account},
+    ui::table::{Cell, Row, Table},
+};
+
+/// Represents a stream the client can talk through, either plain or TLS.
+trait SieveStream: Read + Write {}
+impl<T: Read + Write> SieveStream for T {}
+
+/// Represents a Sieve script stored on the server.
+#[derive(Debug, Serialize)]
+pub struct SieveScript {
+    pub name: String,
+    /// Whether the script is the one the server runs on incoming mail.
+    pub active: bool,
+}
+
+impl Table for SieveScript {
+    fn head() -> Row {
+        Row::new()
+            .cell(Cell::new("NAME").shrinkable().bold().underline().white())
+            .cell(Cell::new("ACTIVE").bold().underline().white())
+    }
+
+    fn row(&self) -> Row {
+        Row::new()
+            .cell(Cell::new(&self.name).shrinkable().green())
+            .cell(Cell::new(if self.active { "yes" } else { "" }).yellow())
+    }
+}
+
+/// Represents the Sieve scripts stored on the server.
+#[derive(Debug, Serialize)]
+pub struct SieveScripts(pub Vec<SieveScript>);
+
+impl fmt::Display for SieveScripts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n{}", Table::render(&self.0))
+    }
+}
+
+/// Quote a string for a ManageSieve command.
+fn quote(s: &str) -> String {
+    format!(r#""{}""#, s.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// Parse the leading string of a response line, either quoted or taken from a literal, and
+/// return it with the rest of the line.
+fn parse_str(line: &str) -> (String, &str) {
+    if let Some(rest) = line.strip_prefix('"') {
+        let mut s = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, c)) = chars.next() {
+                        s.push(c);
+                    }
+                }
+                '"' => return (s, &rest[i + 1..]),
+                c => s.push(c),
+            }
+        }
+        (s, "")
+    } else {
+        match line.split_once(' ') {
+            Some((s, rest)) => (s.to_owned(), rest),
+            None => (line.to_owned(), ""),
+        }
+    }
+}
+
+/// Parse the length of the literal ending the given line, like `{42}` or `{42+}`.
+fn literal_len(line: &str) -> Option<usize> {
+    let (_, len) = line.strip_suffix('}')?.rsplit_once('{')?;
+    len.trim_end_matches('+').parse().ok()
+}
+
+/// Parse the script list from a LISTSCRIPTS response.
+fn parse_scripts(lines: &[String]) -> Vec<SieveScript> {
+    lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (name, rest) = parse_str(line);
+            SieveScript {
+                name,
+                active: rest.trim().eq_ignore_ascii_case("ACTIVE"),
+            }
+        })
+        .collect()
+}
+
+pub struct SieveService<'a> {
+    account: &'a Account,
+    conn: Option<BufReader<Box<dyn SieveStream>>>,
+}
+
+impl<'a> SieveService<'a> {
+    fn conn(&mut self) -> Result<&mut BufReader<Box<dyn SieveStream>>> {
+        if self.conn.is_none() {
+            self.connect()?;
+        }
+        self.conn
+            .as_mut()
+            .ok_or_else(|| anyhow!("cannot get ManageSieve session"))
+    }
+
+    /// Read a response, up to its status line. Literals are inlined in the line they end.
+    /// Returns the data lines of an OK response, fails on NO and BYE responses.
+    fn read_res(&mut self) -> Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut lines = vec![];
+
+        loop {
+            let mut raw_line = String::new();
+            let len = conn
+                .read_line(&mut raw_line)
+                .context("cannot read ManageSieve response")?;
+            if len == 0 {
+                return Err(anyhow!("ManageSieve server closed the connection"));
+            }
+            let raw_line = raw_line.trim_end_matches(&['\r', '\n'][..]);
+            trace!("<< {}", raw_line);
+
+            let mut line = raw_line.to_owned();
+            if let Some(len) = literal_len(raw_line) {
+                line.truncate(raw_line.rfind('{').unwrap_or_default());
+                let mut literal = vec![0; len];
+                conn.read_exact(&mut literal)
+                    .context("cannot read ManageSieve literal")?;
+                line.push_str(&String::from_utf8_lossy(&literal));
+                let mut rest = String::new();
+                conn.read_line(&mut rest)
+                    .context("cannot read ManageSieve response")?;
+                line.push_str(rest.trim_end_matches(&['\r', '\n'][..]));
+            }
+
+            // The status is read before inlining literals, so that scripts are never taken for
+            // status lines.
+            let status = raw_line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_uppercase();
+            match status.as_str() {
+                "OK" => return Ok(lines),
+                "NO" | "BYE" => {
+                    let (msg, _) = parse_str(line[status.len()..].trim());
+                    return Err(anyhow!("ManageSieve server answered {}: {}", status, msg));
+                }
+                _ => lines.push(line),
+            }
+        }
+    }
+
+    /// Send a command and read its response.
+    fn run(&mut self, cmd: &str) -> Result<Vec<String>> {
+        debug!(">> {}", cmd.split('\n').next().unwrap_or_default());
+        let conn = self.conn()?.get_mut();
+        conn.write_all(format!("{}\r\n", cmd).as_bytes())
+            .context("cannot send ManageSieve command")?;
+        conn.flush().context("cannot send ManageSieve command")?;
+        self.read_res()
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        let tls = tls::connector(self.account, self.account.imap_insecure, false)?;
+
+        debug!("create ManageSieve session");
+        debug!("host: {}", self.account.sieve_host);
+        debug!("port: {}", self.account.sieve_port);
+        debug!("starttls: {}", self.account.sieve_starttls);
+        let host = self.account.sieve_host.as_str();
+        let tcp = proxy::connect(self.account.proxy.as_ref(), host, self.account.sieve_port)
+            .context("cannot connect to ManageSieve server")?;
+
+        let caps = if self.account.sieve_starttls {
+            let stream: Box<dyn SieveStream> = Box::new(tcp.try_clone()?);
+            self.conn = Some(BufReader::new(stream));
+            self.read_res()?;
+            self.run("STARTTLS")?;
+            let stream: Box<dyn SieveStream> = Box::new(
+                tls.connect(host, tcp)
+                    .context("cannot start TLS with ManageSieve server")?,
+            );
+            self.conn = Some(BufReader::new(stream));
+            // The server sends its capabilities again once TLS is established.
+            self.read_res()?
+        } else {
+            let stream: Box<dyn SieveStream> = Box::new(
+                tls.connect(host, tcp)
+                    .context("cannot connect to ManageSieve server")?,
+            );
+            self.conn = Some(BufReader::new(stream));
+            self.read_res()?
+        };
+        trace!("capabilities: {:?}", caps);
+
+        let supports_plain = caps.iter().any(|cap| {
+            let (name, rest) = parse_str(cap);
+            let (mechs, _) = parse_str(rest.trim());
+            name.eq_ignore_ascii_case("SASL")
+                && mechs
+                    .split_whitespace()
+                    .any(|mech| mech.eq_ignore_ascii_case("PLAIN"))
+        });
+        if !supports_plain {
+            return Err(anyhow!(
+                "cannot login to ManageSieve server: PLAIN authentication not supported"
+            ));
+        }
+
+        debug!("login: {}", self.account.imap_login);
+        let creds = format!(
+            "\0{}\0{}",
+            self.account.imap_login,
+            self.account.imap_passwd()?
+        );
+        self.run(&format!(
+            r#"AUTHENTICATE "PLAIN" {}"#,
+            quote(&base64::encode(creds))
+        ))
+        .context("cannot login to ManageSieve server")?;
+
+        Ok(())
+    }
+
+    pub fn list_scripts(&mut self) -> Result<SieveScripts> {
+        let lines = self
+            .run("LISTSCRIPTS")
+            .context("cannot list Sieve scripts")?;
+        Ok(SieveScripts(parse_scripts(&lines)))
+    }
+
+    pub fn get_script(&mut self, name: &str) -> Result<String> {
+        let lines = self
+            .run(&format!("GETSCRIPT {}", quote(name)))
+            .context(format!(r#"cannot get Sieve script "{}""#, name))?;
+        Ok(lines.join("\n"))
+    }
+
+    pub fn put_script(&mut self, name: &str, script: &str) -> Result<()> {
+        // The script is sent as a non-synchronizing literal.
+        self.run(&format!(
+            "PUTSCRIPT {} {{{}+}}\r\n{}",
+            quote(name),
+            script.len(),
+            script
+        ))
+        .context(format!(r#"cannot put Sieve script "{}""#, name))?;
+        Ok(())
+    }
+
+    /// Activate the given script. An empty name deactivates all scripts.
+    pub fn activate_script(&mut self, name: &str) -> Result<()> {
+        self.run(&format!("SETACTIVE {}", quote(name)))
+            .context(format!(r#"cannot activate Sieve script "{}""#, name))?;
+        Ok(())
+    }
+
+    pub fn logout(&mut self) -> Result<()> {
+        if self.conn.is_some() {
+            debug!("logout from ManageSieve server");
+            self.run("LOGOUT")
+                .context("cannot logout from ManageSieve server")?;
+            self.conn = None;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a Account> for SieveService<'a> {
+    fn from(account: &'a Account) -> Self {
+        debug!("init ManageSieve service");
+        Self {
+            account,
+            conn: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_responses() {
+        assert_eq!(Some(42), literal_len("{42}"));
+        assert_eq!(Some(42), literal_len(r#""name" {42+}"#));
+        assert_eq!(None, literal_len(r#""name""#));
+
+        let lines = vec![
+            String::from(r#""himalaya" ACTIVE"#),
+            String::from(r#""vacation \"2022\"""#),
+        ];
+        let scripts = parse_scripts(&lines);
+        assert_eq!("himalaya", scripts[0].name);
+        assert!(scripts[0].active);
+        assert_eq!(r#"vacation "2022""#, scripts[1].name);
+        assert!(!scripts[1].active);
+    }
+}
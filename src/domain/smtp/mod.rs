@@ -1,4 +1,6 @@
 //! Module related to SMTP.
 
+pub mod smtp_dkim;
+
 pub mod smtp_service;
 pub use smtp_service::*;
@@ -0,0 +1,214 @@
+//! Module related to DKIM signing.
+//!
+//! When the account defines a `dkim-selector` and a `dkim-key`, messages sent via SMTP get a
+//! [DKIM] signature, for setups where no MTA signs them on the way. Header fields and body are
+//! canonicalized with the relaxed algorithm, hashed with SHA-256, and the hash is signed with
+//! the RSA key by the `openssl` command.
+//!
+//! [DKIM]: https://datatracker.ietf.org/doc/html/rfc6376
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::config::Account;
+
+/// Define the header fields covered by the signature, when present.
+const SIGNED_HEADERS: &[&str] = &[
+    "from",
+    "reply-to",
+    "subject",
+    "date",
+    "to",
+    "cc",
+    "message-id",
+    "in-reply-to",
+    "references",
+    "mime-version",
+    "content-type",
+    "content-transfer-encoding",
+];
+
+/// Split the given raw header between its fields, folded lines being kept with their field.
+fn fields(head: &[u8]) -> Vec<&[u8]> {
+    let mut fields = vec![];
+    let mut start = 0;
+    for (pos, _) in head.iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+        let next = head.get(pos + 1);
+        if next != Some(&b' ') && next != Some(&b'\t') {
+            fields.push(&head[start..pos + 1]);
+            start = pos + 1;
+        }
+    }
+    if start < head.len() {
+        fields.push(&head[start..]);
+    }
+    fields
+}
+
+/// Canonicalize the given header field with the relaxed algorithm, without the final CRLF.
+fn relaxed_header(field: &[u8]) -> String {
+    let field = String::from_utf8_lossy(field);
+    let (name, val) = field.split_once(':').unwrap_or((&field, ""));
+    let val = val.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{}:{}", name.trim().to_lowercase(), val)
+}
+
+/// Canonicalize the given body with the relaxed algorithm.
+fn relaxed_body(body: &[u8]) -> Vec<u8> {
+    let mut lines: Vec<Vec<u8>> = vec![];
+    for line in body.split(|byte| *byte == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut canon_line: Vec<u8> = Vec::with_capacity(line.len());
+        for byte in line {
+            match byte {
+                b' ' | b'\t' if canon_line.last() == Some(&b' ') => (),
+                b' ' | b'\t' => canon_line.push(b' '),
+                byte => canon_line.push(*byte),
+            }
+        }
+        if canon_line.last() == Some(&b' ') {
+            canon_line.pop();
+        }
+        lines.push(canon_line);
+    }
+    while lines.last().map(Vec::is_empty).unwrap_or_default() {
+        lines.pop();
+    }
+    lines.into_iter().fold(vec![], |mut body, line| {
+        body.extend(line);
+        body.extend(b"\r\n");
+        body
+    })
+}
+
+/// Sign the given data with the given RSA key, with SHA-256.
+fn rsa_sign(data: &[u8], key: &Path) -> Result<Vec<u8>> {
+    let mut child = Command::new("openssl")
+        .args(&["dgst", "-sha256", "-sign"])
+        .arg(key)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("cannot run openssl")?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("cannot write to openssl"))?
+        .write_all(data)
+        .context("cannot write to openssl")?;
+    let output = child.wait_with_output().context("cannot run openssl")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{}", stderr.lines().next().unwrap_or_default()));
+    }
+    Ok(output.stdout)
+}
+
+/// Build the DKIM-Signature header field of the given raw message, up to the empty `b=` tag,
+/// and the canonicalized header fields it covers.
+fn unsigned_header(raw_msg: &[u8], domain: &str, selector: &str, time: i64) -> (String, String) {
+    let (head, body) = match raw_msg.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => (&raw_msg[..pos + 2], &raw_msg[pos + 4..]),
+        None => (raw_msg, &[][..]),
+    };
+    let body_hash = base64::encode(Sha256::digest(&relaxed_body(body)));
+
+    // Only the last occurrence of each field is signed.
+    let fields: Vec<String> = fields(head).into_iter().map(relaxed_header).collect();
+    let mut names = vec![];
+    let mut data = String::new();
+    for name in SIGNED_HEADERS {
+        let prefix = format!("{}:", name);
+        if let Some(field) = fields.iter().rev().find(|field| field.starts_with(&prefix)) {
+            names.push(*name);
+            data.push_str(field);
+            data.push_str("\r\n");
+        }
+    }
+
+    let header = format!(
+        concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d={}; s={}; t={};\r\n",
+            "\th={};\r\n",
+            "\tbh={};\r\n",
+            "\tb=",
+        ),
+        domain,
+        selector,
+        time,
+        names.join(":"),
+        body_hash,
+    );
+    data.push_str(&relaxed_header(header.as_bytes()));
+    (header, data)
+}
+
+/// Add the DKIM signature of the account to the given raw message. The message is returned as
+/// is when DKIM signing is not configured.
+pub fn sign(raw_msg: Vec<u8>, account: &Account) -> Result<Vec<u8>> {
+    let (selector, key) = match (
+        account.dkim_selector.as_deref(),
+        account.dkim_key.as_deref(),
+    ) {
+        (Some(selector), Some(key)) => (selector, key),
+        _ => return Ok(raw_msg),
+    };
+    debug!("sign message with DKIM selector {}", selector);
+    let (header, data) = unsigned_header(
+        &raw_msg,
+        &account.dkim_domain,
+        selector,
+        Utc::now().timestamp(),
+    );
+    let signature = rsa_sign(data.as_bytes(), key).context("cannot sign message with DKIM")?;
+    let mut signed_msg = format!("{}{}\r\n", header, base64::encode(signature)).into_bytes();
+    signed_msg.extend(raw_msg);
+    Ok(signed_msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_canonicalize_with_relaxed_algorithm() {
+        // https://datatracker.ietf.org/doc/html/rfc6376#section-3.4.5
+        assert_eq!("a:X", relaxed_header(b"A: X\r\n"));
+        assert_eq!("b:Y Z", relaxed_header(b"B : Y\t\r\n\tZ  \r\n"));
+        assert_eq!(
+            b" C\r\nD E\r\n".to_vec(),
+            relaxed_body(b" C \r\nD \t E\r\n\r\n\r\n")
+        );
+        assert!(relaxed_body(b"\r\n\r\n").is_empty());
+
+        let raw_msg = concat!(
+            "From: Jane <jane@doe.org>\r\n",
+            "To: john@doe.org\r\n",
+            "Subject: Hello\r\n",
+            "X-Mailer: himalaya\r\n",
+            "\r\n",
+            "Hello!\r\n",
+        );
+        let (header, data) = unsigned_header(raw_msg.as_bytes(), "doe.org", "mail", 42);
+        assert!(header.starts_with("DKIM-Signature: v=1; a=rsa-sha256;"));
+        assert!(header.contains("d=doe.org; s=mail; t=42;"));
+        assert!(header.contains("h=from:subject:to;"));
+        assert_eq!(
+            concat!(
+                "from:Jane <jane@doe.org>\r\n",
+                "subject:Hello\r\n",
+                "to:john@doe.org\r\n",
+            ),
+            &data[..data.find("dkim-signature:").unwrap()]
+        );
+        assert!(data.ends_with("b="));
+    }
+}
@@ -1,74 +1,205 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use lettre::{
     self,
     transport::smtp::{
-        client::{Tls, TlsParameters},
-        SmtpTransport,
+        authentication::Mechanism,
+        client::SmtpConnection,
+        extension::{ClientId, Extension},
+        response::Response,
     },
-    Transport,
 };
 use log::debug;
-use std::convert::TryInto;
+use std::{net::ToSocketAddrs, time::Duration};
 
-use crate::{config::Account, domain::msg::Msg};
+use crate::{
+    config::{tls, Account},
+    domain::{backend::Sender, msg::Msg, smtp::smtp_dkim},
+    output::output_trace::Tracer,
+};
 
-pub trait SmtpServiceInterface {
-    fn send_msg(&mut self, msg: &Msg) -> Result<lettre::Message>;
-    fn send_raw_msg(&mut self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()>;
-}
+const SMTP_TIMEOUT: Duration = Duration::from_secs(60);
 
 pub struct SmtpService<'a> {
     account: &'a Account,
-    transport: Option<SmtpTransport>,
+    /// Authenticated session, kept alive across sends of the same process.
+    conn: Option<SmtpConnection>,
+    /// Final response of the server to the last send.
+    response: Option<String>,
+    /// Trace of the session, when traces are enabled.
+    tracer: Option<Tracer>,
 }
 
 impl<'a> SmtpService<'a> {
-    fn transport(&mut self) -> Result<&SmtpTransport> {
-        if let Some(ref transport) = self.transport {
-            Ok(transport)
+    /// Record the given step of the session, when traced.
+    fn trace(&self, step: &str) {
+        if let Some(ref tracer) = self.tracer {
+            tracer.event(step);
+        }
+    }
+
+    /// Record the sending of a message with the given envelope, when traced.
+    fn trace_send(&self, envelope: &lettre::address::Envelope, len: usize) {
+        let from = envelope.from().map(ToString::to_string).unwrap_or_default();
+        let to: Vec<String> = envelope.to().iter().map(ToString::to_string).collect();
+        self.trace(&format!(
+            "send message from <{}> to <{}>, {} bytes",
+            from,
+            to.join(">, <"),
+            len
+        ));
+    }
+
+    fn connect(&self) -> Result<SmtpConnection> {
+        debug!("create SMTP session");
+        debug!("host: {}", self.account.smtp_host);
+        debug!("port: {}", self.account.smtp_port);
+        debug!("starttls: {}", self.account.smtp_starttls);
+        let tls = tls::smtp_parameters(self.account)?;
+        let hello = ClientId::default();
+        let host = self.account.smtp_host.as_str();
+        let port = self.account.smtp_port;
+        let addr = match self.account.proxy {
+            Some(ref proxy) => proxy
+                .relay(host, port)
+                .context("cannot connect to SMTP server")?,
+            None => (host, port)
+                .to_socket_addrs()
+                .context("cannot resolve SMTP server")?
+                .next()
+                .ok_or_else(|| anyhow!("cannot resolve SMTP server {}", host))?,
+        };
+
+        let mut conn = if self.account.smtp_starttls {
+            let mut conn = SmtpConnection::connect(addr, Some(SMTP_TIMEOUT), &hello, None)
+                .context("cannot connect to SMTP server")?;
+            self.trace(&format!("connected to {}:{}", host, port));
+            conn.starttls(&tls, &hello)
+                .context("cannot start TLS with SMTP server")?;
+            self.trace("started TLS");
+            conn
         } else {
-            let builder = if self.account.smtp_starttls {
-                SmtpTransport::starttls_relay(&self.account.smtp_host)
-            } else {
-                SmtpTransport::relay(&self.account.smtp_host)
-            }?;
-
-            let tls = TlsParameters::builder(self.account.smtp_host.to_owned())
-                .dangerous_accept_invalid_hostnames(self.account.smtp_insecure)
-                .dangerous_accept_invalid_certs(self.account.smtp_insecure)
-                .build()?;
-            let tls = if self.account.smtp_starttls {
-                Tls::Required(tls)
-            } else {
-                Tls::Wrapper(tls)
-            };
-
-            self.transport = Some(
-                builder
-                    .tls(tls)
-                    .port(self.account.smtp_port)
-                    .credentials(self.account.smtp_creds()?)
-                    .build(),
-            );
-
-            Ok(self.transport.as_ref().unwrap())
+            let conn = SmtpConnection::connect(addr, Some(SMTP_TIMEOUT), &hello, Some(&tls))
+                .context("cannot connect to SMTP server")?;
+            self.trace(&format!("connected to {}:{} with TLS", host, port));
+            conn
+        };
+        self.trace(&format!("server: {}", conn.server_info()));
+
+        debug!("login: {}", self.account.smtp_login);
+        debug!("passwd cmd: {}", self.account.smtp_passwd_cmd);
+        conn.auth(
+            &[Mechanism::Plain, Mechanism::Login],
+            &self.account.smtp_creds()?,
+        )
+        .context("cannot login to SMTP server")?;
+        self.trace(&format!("authenticated as {}", self.account.smtp_login));
+
+        Ok(conn)
+    }
+
+    fn format_response(response: &Response) -> String {
+        let msg: Vec<&str> = response.message().collect();
+        format!("{} {}", response.code(), msg.join(" "))
+    }
+
+    /// Get the SMTP session, opening a new one if none exists yet or if the server closed it.
+    fn conn(&mut self) -> Result<&mut SmtpConnection> {
+        let is_alive = match self.conn {
+            Some(ref mut conn) => conn.test_connected(),
+            None => false,
+        };
+
+        if !is_alive {
+            self.tracer = Tracer::start("smtp");
+            self.conn = Some(self.connect()?);
         }
+
+        self.conn
+            .as_mut()
+            .ok_or_else(|| anyhow!("cannot get SMTP session"))
     }
 }
 
-impl<'a> SmtpServiceInterface for SmtpService<'a> {
-    fn send_msg(&mut self, msg: &Msg) -> Result<lettre::Message> {
+impl<'a> Sender for SmtpService<'a> {
+    fn send(&mut self, msg: &Msg) -> Result<Vec<u8>> {
         debug!("sending message…");
-        let sendable_msg: lettre::Message = msg.try_into()?;
-        self.transport()?.send(&sendable_msg)?;
-        Ok(sendable_msg)
+        let conn = self.conn()?;
+        let allow_8bit = conn.server_info().supports_feature(Extension::EightBitMime);
+        debug!("8BITMIME supported: {}", allow_8bit);
+        let sendable_msg = msg.to_sendable_msg(allow_8bit, self.account.format_flowed)?;
+        let raw_msg = smtp_dkim::sign(msg.format_sendable_msg(&sendable_msg), self.account)?;
+        self.trace_send(sendable_msg.envelope(), raw_msg.len());
+        let conn = self.conn()?;
+        let response = conn
+            .send(sendable_msg.envelope(), &raw_msg)
+            .context("cannot send message")?;
+        self.response = Some(Self::format_response(&response));
+        self.trace(&format!(
+            "response: {}",
+            self.response.as_deref().unwrap_or_default()
+        ));
+        debug!("SMTP response: {:?}", self.response);
+        Ok(raw_msg)
     }
 
-    fn send_raw_msg(&mut self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()> {
+    fn send_raw(&mut self, envelope: &lettre::address::Envelope, msg: &[u8]) -> Result<()> {
         debug!("sending raw message…");
-        self.transport()?.send_raw(envelope, msg)?;
+        let msg = smtp_dkim::sign(msg.to_vec(), self.account)?;
+        self.conn()?;
+        // The session is open, so that its trace records the message.
+        self.trace_send(envelope, msg.len());
+        let response = self
+            .conn
+            .as_mut()
+            .ok_or_else(|| anyhow!("cannot get SMTP session"))?
+            .send(envelope, &msg)
+            .context("cannot send raw message")?;
+        self.response = Some(Self::format_response(&response));
+        self.trace(&format!(
+            "response: {}",
+            self.response.as_deref().unwrap_or_default()
+        ));
+        debug!("SMTP response: {:?}", self.response);
         Ok(())
     }
+
+    fn last_response(&self) -> Option<String> {
+        self.response.to_owned()
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.conn()?;
+        Ok(())
+    }
+
+    fn keepalive(&mut self) -> Result<()> {
+        if let Some(ref mut conn) = self.conn {
+            // Sends a NOOP, a dead session is replaced on the next send.
+            if !conn.test_connected() {
+                debug!("SMTP session closed by the server");
+                self.conn = None;
+                self.trace("session closed by the server");
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut conn) = self.conn.take() {
+            debug!("logout from SMTP server");
+            self.trace("logout");
+            conn.quit().context("cannot logout from SMTP server")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for SmtpService<'a> {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            debug!("{:?}", err);
+        }
+    }
 }
 
 impl<'a> From<&'a Account> for SmtpService<'a> {
@@ -76,7 +207,9 @@ impl<'a> From<&'a Account> for SmtpService<'a> {
         debug!("init SMTP service");
         Self {
             account,
-            transport: None,
+            conn: None,
+            response: None,
+            tracer: None,
         }
     }
 }
@@ -0,0 +1,9 @@
+//! Module related to message snoozing.
+
+#[cfg(feature = "cli")]
+pub mod snooze_arg;
+#[cfg(feature = "cli")]
+pub mod snooze_handler;
+
+pub mod snoozed_entity;
+pub use snoozed_entity::*;
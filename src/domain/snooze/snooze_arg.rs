@@ -0,0 +1,63 @@
+//! Module related to snooze CLI.
+//!
+//! This module provides subcommands and a command matcher related to message snoozing.
+
+use anyhow::Result;
+use clap::{self, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{debug, trace};
+
+use crate::domain::msg::msg_arg::{self, OverrideHold};
+
+type Seq<'a> = &'a str;
+type When<'a> = &'a str;
+
+/// Snooze commands.
+pub enum Command<'a> {
+    /// Snooze the given message until the given time.
+    Snooze(Seq<'a>, When<'a>, OverrideHold),
+
+    /// Wake up the snoozed messages that are due.
+    Wake,
+}
+
+/// Snooze command matcher.
+pub fn matches<'a>(m: &'a ArgMatches) -> Result<Option<Command<'a>>> {
+    if let Some(m) = m.subcommand_matches("snooze") {
+        if m.subcommand_matches("wake").is_some() {
+            debug!("wake command matched");
+            return Ok(Some(Command::Wake));
+        }
+
+        debug!("snooze command matched");
+        let seq = m.value_of("seq").unwrap();
+        trace!("seq: {}", seq);
+        let when = m.value_of("when").unwrap();
+        trace!("when: {}", when);
+        let override_hold = m.is_present("override-hold");
+        trace!("override hold: {}", override_hold);
+        return Ok(Some(Command::Snooze(seq, when, override_hold)));
+    }
+
+    Ok(None)
+}
+
+/// Snooze subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("snooze")
+        .about("Snoozes a message until a later time")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(msg_arg::seq_arg())
+        .arg(
+            Arg::with_name("when")
+                .help("Specifies when the message wakes up")
+                .long_help("Specifies when the message wakes up: a duration (`30m`, `2h`, `3d`, `1w`), `tomorrow`, a date (`2021-12-24`) or a date time (`2021-12-24 14:00`).")
+                .value_name("WHEN")
+                .required(true),
+        )
+        .arg(msg_arg::override_hold_arg())
+        .subcommand(
+            SubCommand::with_name("wake").about(
+                "Moves the snoozed messages that are due back to their mailbox, as unseen",
+            ),
+        )]
+}
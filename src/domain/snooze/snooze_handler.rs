@@ -0,0 +1,111 @@
+//! Module related to snooze handling.
+//!
+//! This module gathers all snooze commands.
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use imap::types::Flag;
+use log::{debug, warn};
+use std::convert::TryFrom;
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{build_backend, Backend},
+        mbox::Mbox,
+        msg::{msg_hold, Flags},
+        snooze::{parse_wake_time, SnoozedMsg, SnoozedMsgs},
+    },
+    output::OutputServiceInterface,
+};
+
+/// Move the given message to the snooze folder and record its wake time. Refuses when the
+/// message is on hold, unless `override_hold` is true.
+pub fn snooze<OutputService: OutputServiceInterface>(
+    seq: &str,
+    when: &str,
+    override_hold: bool,
+    mbox: &Mbox,
+    account: &Account,
+    output: &OutputService,
+    backend: &mut dyn Backend,
+) -> Result<()> {
+    let wake_at = parse_wake_time(when, Local::now())?;
+    msg_hold::check_hold(seq, "snooze", override_hold, account, backend)?;
+    let message_id = backend
+        .get_msg(seq)?
+        .message_id
+        .ok_or_else(|| anyhow!(r#"cannot snooze message "{}": it has no Message-ID"#, seq))?;
+
+    let snooze_mbox = Mbox::from(account.snooze_folder.as_str());
+    backend.create_mbox(&snooze_mbox)?;
+    backend.move_msg(seq, &snooze_mbox)?;
+
+    {
+        let _lock = account.lock_cache()?;
+        let mut snoozed_msgs = SnoozedMsgs::load(account)?;
+        snoozed_msgs.0.push(SnoozedMsg {
+            message_id,
+            mbox: mbox.name.to_owned(),
+            wake_at: wake_at.timestamp(),
+        });
+        snoozed_msgs.save(account)?;
+    }
+
+    output.print(format!(
+        "Message {} successfully snoozed until {}",
+        seq,
+        wake_at.format("%Y-%m-%d %H:%M")
+    ))
+}
+
+/// Move the snoozed messages that are due back to their mailbox, and flag them as unseen. Meant
+/// to be run periodically, from cron for example.
+pub fn wake<OutputService: OutputServiceInterface>(
+    account: &Account,
+    output: &OutputService,
+) -> Result<()> {
+    let due_msgs = SnoozedMsgs::load(account)?.take_due(Local::now().timestamp());
+    debug!("{} snoozed message(s) due", due_msgs.len());
+
+    if !due_msgs.is_empty() {
+        let snooze_mbox = Mbox::from(account.snooze_folder.as_str());
+        let mut backend = build_backend(account, &snooze_mbox, false);
+        let seen = Flags::try_from(vec![Flag::Seen])?;
+
+        for msg in due_msgs.iter() {
+            let query = format!(
+                r#"HEADER Message-ID "{}""#,
+                msg.message_id.replace('"', r#"\""#)
+            );
+            let ids: Vec<String> = backend
+                .search_envelopes(&query, &10, &0)?
+                .iter()
+                .map(|envelope| envelope.id.to_string())
+                .collect();
+            if ids.is_empty() {
+                warn!(
+                    r#"cannot find snoozed message "{}", forgetting it"#,
+                    msg.message_id
+                );
+                continue;
+            }
+
+            let ids = ids.join(",");
+            backend.remove_flags(&ids, &seen)?;
+            backend.move_msg(&ids, &Mbox::from(msg.mbox.as_str()))?;
+        }
+
+        backend.logout()?;
+    }
+
+    // Messages may have been snoozed by other processes meanwhile, so the state is reloaded.
+    let _lock = account.lock_cache()?;
+    let mut snoozed_msgs = SnoozedMsgs::load(account)?;
+    snoozed_msgs.0.retain(|msg| !due_msgs.contains(msg));
+    snoozed_msgs.save(account)?;
+    output.print(format!(
+        "{} snoozed message(s) successfully woken up",
+        due_msgs.len()
+    ))
+}
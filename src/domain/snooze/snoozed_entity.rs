@@ -0,0 +1,134 @@
+//! Module related to snoozed messages.
+//!
+//! Snoozed messages are moved to a dedicated folder, and their wake time is recorded locally in
+//! the account storage. Since UIDs change when messages move between folders, snoozed
+//! messages are identified by their Message-ID.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Account, domain::storage::build_storage};
+
+const KEY: &str = "snoozed.json";
+
+/// Hour messages wake up at, when only a day is given.
+const DEFAULT_WAKE_HOUR: u32 = 8;
+
+/// Represents a snoozed message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SnoozedMsg {
+    pub message_id: String,
+    /// The mailbox the message is moved back to when it wakes up.
+    pub mbox: String,
+    /// The wake time, as a UNIX timestamp.
+    pub wake_at: i64,
+}
+
+/// Represents all the snoozed messages of an account.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnoozedMsgs(pub Vec<SnoozedMsg>);
+
+impl SnoozedMsgs {
+    pub fn load(account: &Account) -> Result<Self> {
+        match build_storage(account)?.get(KEY)? {
+            Some(content) => {
+                serde_json::from_slice(&content).context("cannot parse snoozed messages")
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, account: &Account) -> Result<()> {
+        let content = serde_json::to_vec(self).context("cannot serialize snoozed messages")?;
+        build_storage(account)?
+            .set(KEY, &content)
+            .context("cannot save snoozed messages")
+    }
+
+    /// Remove and return the messages due at the given timestamp.
+    pub fn take_due(&mut self, now: i64) -> Vec<SnoozedMsg> {
+        let (due, pending) = self.0.drain(..).partition(|msg| msg.wake_at <= now);
+        self.0 = pending;
+        due
+    }
+}
+
+fn from_local(naive: NaiveDateTime) -> Result<DateTime<Local>> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| anyhow!("cannot convert {} to local time", naive))
+}
+
+/// Parse a wake time, relative to `now`. Accepted formats are durations (`30m`, `2h`, `3d`,
+/// `1w`), `tomorrow`, dates (`2021-12-24`, waking up at 8am) and date times (`2021-12-24 14:00`
+/// or RFC3339).
+pub fn parse_wake_time(when: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    let when = when.trim();
+
+    if when.eq_ignore_ascii_case("tomorrow") {
+        let tomorrow = now.date().naive_local().succ();
+        return from_local(tomorrow.and_hms(DEFAULT_WAKE_HOUR, 0, 0));
+    }
+
+    if let Some(unit) = when.chars().last() {
+        let amount = &when[..when.len() - unit.len_utf8()];
+        if let Ok(amount) = amount.parse::<i64>() {
+            let duration = match unit {
+                'm' => Some(Duration::minutes(amount)),
+                'h' => Some(Duration::hours(amount)),
+                'd' => Some(Duration::days(amount)),
+                'w' => Some(Duration::weeks(amount)),
+                _ => None,
+            };
+            if let Some(duration) = duration {
+                return Ok(now + duration);
+            }
+        }
+    }
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(when) {
+        return Ok(date.with_timezone(&Local));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(when, "%Y-%m-%d %H:%M") {
+        return from_local(date);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(when, "%Y-%m-%d") {
+        return from_local(date.and_hms(DEFAULT_WAKE_HOUR, 0, 0));
+    }
+
+    Err(anyhow!(r#"cannot parse wake time "{}""#, when))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_wake_time() {
+        let now = Local.ymd(2021, 12, 20).and_hms(10, 30, 0);
+        assert_eq!(
+            Local.ymd(2021, 12, 20).and_hms(12, 30, 0),
+            parse_wake_time("2h", now).unwrap()
+        );
+        assert_eq!(
+            Local.ymd(2021, 12, 27).and_hms(10, 30, 0),
+            parse_wake_time("1w", now).unwrap()
+        );
+        assert_eq!(
+            Local.ymd(2021, 12, 21).and_hms(8, 0, 0),
+            parse_wake_time("tomorrow", now).unwrap()
+        );
+        assert_eq!(
+            Local.ymd(2021, 12, 24).and_hms(8, 0, 0),
+            parse_wake_time("2021-12-24", now).unwrap()
+        );
+        assert_eq!(
+            Local.ymd(2021, 12, 24).and_hms(14, 0, 0),
+            parse_wake_time("2021-12-24 14:00", now).unwrap()
+        );
+        assert!(parse_wake_time("later", now).is_err());
+    }
+}
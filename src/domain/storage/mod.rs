@@ -0,0 +1,15 @@
+//! Module related to the storage of the cache and the state.
+
+pub mod storage_service;
+pub use storage_service::*;
+
+pub mod storage_file;
+pub use storage_file::*;
+
+pub mod storage_memory;
+pub use storage_memory::*;
+
+#[cfg(feature = "sqlite")]
+pub mod storage_sqlite;
+#[cfg(feature = "sqlite")]
+pub use storage_sqlite::*;
@@ -0,0 +1,127 @@
+//! Module related to the file storage.
+//!
+//! Each item is a plain file in the account cache directory, keys being paths relative to it.
+//! Nothing else is needed, and items can be inspected (or fixed) by hand.
+
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::domain::storage::Storage;
+
+/// Represents a storage keeping its items as plain files under a directory.
+#[derive(Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let mut path = self.dir.to_owned();
+        path.extend(key.split('/').filter(|part| !part.is_empty()));
+        path
+    }
+
+    fn create_parent_dir(path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("cannot create cache dir {:?}", dir))?;
+        }
+        Ok(())
+    }
+
+    fn collect_keys(dir: &Path, prefix: &str, keys: &mut Vec<String>) -> Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err).context(format!("cannot read cache dir {:?}", dir)),
+        };
+        for entry in entries {
+            let entry = entry.context(format!("cannot read cache dir {:?}", dir))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if path.is_dir() {
+                Self::collect_keys(&path, &format!("{}{}/", prefix, name), keys)?;
+            } else if !name.ends_with(".tmp") {
+                keys.push(format!("{}{}", prefix, name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(key);
+        match fs::read(&path) {
+            Ok(val) => Ok(Some(val)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).context(format!("cannot read cache file {:?}", path)),
+        }
+    }
+
+    fn set(&self, key: &str, val: &[u8]) -> Result<()> {
+        let path = self.path(key);
+        Self::create_parent_dir(&path)?;
+        // Items are written aside then renamed, so that readers never see a partial one.
+        let mut tmp_path = path.to_owned().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, val)
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .context(format!("cannot write cache file {:?}", path))
+    }
+
+    fn append(&self, key: &str, val: &[u8]) -> Result<()> {
+        let path = self.path(key);
+        Self::create_parent_dir(&path)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(val))
+            .context(format!("cannot write cache file {:?}", path))
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let path = self.path(key);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).context(format!("cannot remove cache file {:?}", path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        // Only the directory holding the prefix is walked.
+        let dir_prefix = match prefix.rfind('/') {
+            Some(i) => &prefix[..=i],
+            None => "",
+        };
+        let mut keys = vec![];
+        Self::collect_keys(&self.path(dir_prefix), dir_prefix, &mut keys)?;
+        keys.retain(|key| key.starts_with(prefix));
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn remove_all(&self, prefix: &str) -> Result<()> {
+        for key in self.keys(prefix)? {
+            self.remove(&key)?;
+        }
+        // Directories emptied by a prefix ending with a slash go with their items.
+        if prefix.ends_with('/') {
+            let dir = self.path(prefix);
+            if dir.is_dir() {
+                fs::remove_dir_all(&dir).context(format!("cannot remove cache dir {:?}", dir))?;
+            }
+        }
+        Ok(())
+    }
+}
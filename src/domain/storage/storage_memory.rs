@@ -0,0 +1,47 @@
+//! Module related to the memory storage.
+//!
+//! Items only live as long as the storage, which makes it fit for tests.
+
+use anyhow::Result;
+use std::{cell::RefCell, collections::BTreeMap};
+
+use crate::domain::storage::Storage;
+
+/// Represents a storage keeping its items in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStorage(RefCell<BTreeMap<String, Vec<u8>>>);
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.borrow().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, val: &[u8]) -> Result<()> {
+        self.0.borrow_mut().insert(key.to_owned(), val.to_vec());
+        Ok(())
+    }
+
+    fn append(&self, key: &str, val: &[u8]) -> Result<()> {
+        self.0
+            .borrow_mut()
+            .entry(key.to_owned())
+            .or_default()
+            .extend_from_slice(val);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.0.borrow_mut().remove(key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .0
+            .borrow()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
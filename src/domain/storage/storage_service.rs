@@ -0,0 +1,120 @@
+//! Module related to storage servicing.
+//!
+//! The cache and the state of an account (delivery log, outbox, snoozed messages, mailbox
+//! caches…) are kept in a storage, selected by the `storage` option: plain files in the account
+//! cache directory, or a single SQLite database there. Items are identified by slash-separated
+//! keys, like `sent.log` or `mboxes/INBOX/envelopes.json`, and hold raw bytes.
+//!
+//! Storages do not lock anything themselves: read-modify-write sequences still hold the cache
+//! lock of the account.
+
+use anyhow::Result;
+
+#[cfg(feature = "sqlite")]
+use crate::domain::storage::SqliteStorage;
+use crate::{
+    config::{Account, StorageKind},
+    domain::storage::FileStorage,
+};
+
+/// Represents a storage of cache and state items.
+pub trait Storage {
+    /// Read the given item, if it exists.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Write the given item, replacing the previous one.
+    fn set(&self, key: &str, val: &[u8]) -> Result<()>;
+    /// Append to the given item, creating it if needed.
+    fn append(&self, key: &str, val: &[u8]) -> Result<()>;
+    /// Remove the given item. Removing a missing item is not an error.
+    fn remove(&self, key: &str) -> Result<()>;
+    /// List the keys starting with the given prefix, in order.
+    fn keys(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Remove all the items whose key starts with the given prefix.
+    fn remove_all(&self, prefix: &str) -> Result<()> {
+        for key in self.keys(prefix)? {
+            self.remove(&key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Build the storage matching the account `storage` config field.
+pub fn build_storage(account: &Account) -> Result<Box<dyn Storage>> {
+    let dir = account.cache_dir()?;
+    match account.storage {
+        StorageKind::File => Ok(Box::new(FileStorage::new(dir))),
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => Ok(Box::new(SqliteStorage::open(&dir.join("state.sqlite"))?)),
+        #[cfg(not(feature = "sqlite"))]
+        StorageKind::Sqlite => Err(anyhow::anyhow!(
+            "cannot open SQLite storage: himalaya was built without the sqlite feature"
+        )),
+    }
+}
+
+/// Build the key of the given item of the given mailbox. Mailbox items are bound to the
+/// UIDVALIDITY of the mailbox, and dropped as soon as it changes.
+pub fn mbox_key(mbox: &str, key: &str) -> String {
+    format!("{}{}", mbox_prefix(mbox), key)
+}
+
+/// Build the prefix of the keys of the given mailbox.
+pub fn mbox_prefix(mbox: &str) -> String {
+    let name: String = mbox
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("mboxes/{}/", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::domain::storage::MemoryStorage;
+
+    fn check(storage: &dyn Storage) {
+        assert_eq!(None, storage.get("sent.log").unwrap());
+        storage.append("sent.log", b"a\n").unwrap();
+        storage.append("sent.log", b"b\n").unwrap();
+        assert_eq!(Some(b"a\nb\n".to_vec()), storage.get("sent.log").unwrap());
+
+        let inbox = mbox_key("INBOX", "envelopes.json");
+        let work = mbox_key("Work/Things", "msgs/1.eml");
+        assert_eq!("mboxes/Work_Things/msgs/1.eml", work);
+        storage.set(&inbox, b"{}").unwrap();
+        storage.set(&inbox, b"[]").unwrap();
+        storage.set(&work, b"Subject: Hello").unwrap();
+        assert_eq!(Some(b"[]".to_vec()), storage.get(&inbox).unwrap());
+        assert_eq!(
+            vec![inbox.clone(), work.clone()],
+            storage.keys("mboxes/").unwrap()
+        );
+
+        storage.remove_all(&mbox_prefix("Work/Things")).unwrap();
+        assert_eq!(vec![inbox.clone()], storage.keys("mboxes/").unwrap());
+        storage.remove(&inbox).unwrap();
+        storage.remove(&inbox).unwrap();
+        assert_eq!(None, storage.get(&inbox).unwrap());
+        assert_eq!(vec!["sent.log"], storage.keys("").unwrap());
+    }
+
+    #[test]
+    fn it_should_store_items() {
+        check(&MemoryStorage::default());
+        #[cfg(feature = "sqlite")]
+        check(&SqliteStorage::open_in_memory().unwrap());
+
+        let dir = env::temp_dir().join(format!("himalaya-storage-{}", process::id()));
+        check(&FileStorage::new(dir.to_owned()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! Module related to the SQLite storage.
+//!
+//! All the items live in a single SQLite database, in one key-value table. It keeps the many
+//! small items of the mailbox caches in one file, which suits systems with few inodes or slow
+//! directory listings. SQLite does not cope well with network filesystems: on network homes,
+//! prefer the file storage.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{path::Path, time::Duration};
+
+use crate::domain::storage::Storage;
+
+/// Define how long a write waits for the database to be released by another process.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Represents a storage keeping its items in an SQLite database.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    fn init(conn: Connection) -> Result<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("cannot set timeout of storage database")?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS storage (key TEXT PRIMARY KEY, value BLOB NOT NULL)",
+            [],
+        )
+        .context("cannot create storage table")?;
+        Ok(Self { conn })
+    }
+
+    /// Open the database at the given path, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn =
+            Connection::open(path).context(format!("cannot open storage database {:?}", path))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().context("cannot open storage database")?)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.conn
+            .query_row(
+                "SELECT value FROM storage WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(format!(r#"cannot read storage item "{}""#, key))
+    }
+
+    fn set(&self, key: &str, val: &[u8]) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO storage (key, value) VALUES (?1, ?2)",
+                params![key, val],
+            )
+            .context(format!(r#"cannot write storage item "{}""#, key))?;
+        Ok(())
+    }
+
+    fn append(&self, key: &str, val: &[u8]) -> Result<()> {
+        // Concatenation gives text, cast back to bytes.
+        self.conn
+            .execute(
+                "INSERT INTO storage (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = CAST(value || excluded.value AS BLOB)",
+                params![key, val],
+            )
+            .context(format!(r#"cannot write storage item "{}""#, key))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM storage WHERE key = ?1", params![key])
+            .context(format!(r#"cannot remove storage item "{}""#, key))?;
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        // Prefixes are compared as is, since `LIKE` would interpret `_` and `%`.
+        let mut stmt = self
+            .conn
+            .prepare("SELECT key FROM storage WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key")
+            .context("cannot list storage items")?;
+        let keys = stmt
+            .query_map(params![prefix], |row| row.get(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<String>>>())
+            .context(format!(r#"cannot list storage items "{}""#, prefix))?;
+        Ok(keys)
+    }
+
+    fn remove_all(&self, prefix: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM storage WHERE substr(key, 1, length(?1)) = ?1",
+                params![prefix],
+            )
+            .context(format!(r#"cannot remove storage items "{}""#, prefix))?;
+        Ok(())
+    }
+}
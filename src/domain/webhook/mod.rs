@@ -0,0 +1,7 @@
+//! Module related to webhooks.
+
+pub mod webhook_event_entity;
+pub use webhook_event_entity::*;
+
+pub mod webhook_service;
+pub use webhook_service::*;
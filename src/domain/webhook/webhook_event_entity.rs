@@ -0,0 +1,142 @@
+//! Module related to webhook events.
+//!
+//! Events are posted as JSON to the account `webhook-url`. Next to the event data, the `text`
+//! field holds a human-readable summary, so that chat services like Slack can display events
+//! without any transformation.
+
+use chrono::Local;
+use serde::Serialize;
+
+/// Represents a mail event.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebhookEvent {
+    /// The kind of event: `new-mail`, `send-success`, `send-failure` or `queue-stuck`.
+    pub event: String,
+    pub account: String,
+    /// The event time, as a UNIX timestamp.
+    pub timestamp: i64,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mbox: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl WebhookEvent {
+    fn new(event: &str, account: &str, text: String) -> Self {
+        Self {
+            event: event.to_owned(),
+            account: account.to_owned(),
+            timestamp: Local::now().timestamp(),
+            text,
+            ..Self::default()
+        }
+    }
+
+    /// Build the event of a message arrived in the given mailbox.
+    pub fn new_mail(
+        account: &str,
+        mbox: &str,
+        uid: Option<u32>,
+        subject: &str,
+        from: &str,
+    ) -> Self {
+        Self {
+            mbox: Some(mbox.to_owned()),
+            uid,
+            subject: Some(subject.to_owned()),
+            from: Some(from.to_owned()),
+            ..Self::new(
+                "new-mail",
+                account,
+                format!("New message from {} in {}: {}", from, mbox, subject),
+            )
+        }
+    }
+
+    /// Build the event of a message accepted by the sender.
+    pub fn send_success(account: &str, subject: &str, recipients: Vec<String>) -> Self {
+        let text = format!("Message sent to {}: {}", recipients.join(", "), subject);
+        Self {
+            subject: Some(subject.to_owned()),
+            recipients,
+            ..Self::new("send-success", account, text)
+        }
+    }
+
+    /// Build the event of a message the sender failed to send.
+    pub fn send_failure(
+        account: &str,
+        subject: &str,
+        recipients: Vec<String>,
+        error: &anyhow::Error,
+    ) -> Self {
+        let text = format!(
+            "Cannot send message to {}: {} ({})",
+            recipients.join(", "),
+            subject,
+            error
+        );
+        Self {
+            subject: Some(subject.to_owned()),
+            recipients,
+            error: Some(format!("{:#}", error)),
+            ..Self::new("send-failure", account, text)
+        }
+    }
+
+    /// Build the event of a message stuck in the outbox.
+    pub fn queue_stuck(account: &str, subject: &str, recipients: Vec<String>, error: &str) -> Self {
+        let text = format!(
+            "Message to {} stuck in the outbox: {} ({})",
+            recipients.join(", "),
+            subject,
+            error
+        );
+        Self {
+            subject: Some(subject.to_owned()),
+            recipients,
+            error: Some(error.to_owned()),
+            ..Self::new("queue-stuck", account, text)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_serialize_webhook_events() {
+        let event = WebhookEvent::new_mail("perso", "INBOX", Some(42), "Hello", "alice@localhost");
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!("new-mail", json["event"]);
+        assert_eq!(42, json["uid"]);
+        assert_eq!(
+            "New message from alice@localhost in INBOX: Hello",
+            json["text"]
+        );
+        assert!(json.get("recipients").is_none());
+        assert!(json.get("error").is_none());
+
+        let event = WebhookEvent::send_failure(
+            "perso",
+            "Hello",
+            vec![String::from("bob@localhost")],
+            &anyhow::anyhow!("connection refused"),
+        );
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!("send-failure", json["event"]);
+        assert_eq!("bob@localhost", json["recipients"][0]);
+        assert_eq!("connection refused", json["error"]);
+    }
+}
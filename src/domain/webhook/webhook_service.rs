@@ -0,0 +1,35 @@
+//! Module related to webhook servicing.
+//!
+//! This module posts mail events to the account webhook. Posting is best effort: a failing
+//! webhook is only logged, it never fails the operation that triggered the event.
+
+use anyhow::{Context, Result};
+use log::{debug, trace, warn};
+use std::time::Duration;
+
+use crate::{config::Account, domain::webhook::WebhookEvent};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn post(url: &str, event: &WebhookEvent) -> Result<()> {
+    let body = serde_json::to_value(event).context("cannot serialize webhook event")?;
+    trace!("webhook event: {}", body);
+    ureq::post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .send_json(body)
+        .context(format!(
+            "cannot post {} event to webhook {}",
+            event.event, url
+        ))?;
+    Ok(())
+}
+
+/// Post the given event to the account webhook, if any.
+pub fn emit(account: &Account, event: &WebhookEvent) {
+    if let Some(ref url) = account.webhook_url {
+        debug!("post {} event to webhook {}", event.event, url);
+        if let Err(err) = post(url, event) {
+            warn!("{:?}", err);
+        }
+    }
+}
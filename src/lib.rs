@@ -0,0 +1,70 @@
+//! Himalaya as a library.
+//!
+//! The binary is a thin CLI on top of this crate: the config, the backends and the senders, the
+//! message parsing and the templates can be embedded in other programs, without shelling out to
+//! the binary. The API is made of the items re-exported at the root of the crate, the modules
+//! behind them are internal and may change between releases. The entry points are:
+//!
+//! - [`Config`] and [`Account`] load the config and resolve an account
+//! - [`build_backend`] opens the mailboxes of an account, through the [`Backend`] trait
+//! - [`build_sender`] sends messages from an account, through the [`Sender`] trait
+//!
+//! The CLI is behind the default `cli` feature, and the SQLite storage behind the default
+//! `bundled-sqlite` one: embedders can disable the default features to leave out the dependencies
+//! of the CLI, then enable `sqlite` to link the system SQLite.
+//!
+//! These entry points are blocking. With the `async` feature, the [`backend_async`] module wraps
+//! them for embedders running a tokio runtime: the clients stay blocking, they are only offloaded
+//! to worker threads.
+//!
+//! ```no_run
+//! use himalaya::{build_backend, build_sender, Account, Backend, Config, Mbox, Msg, Sender, Tpl};
+//! use std::convert::TryFrom;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let config = Config::try_from((None, &[][..]))?;
+//! let account = Account::try_from((&config, None))?;
+//!
+//! let mbox = Mbox::from("INBOX");
+//! let mut backend = build_backend(&account, &mbox, false);
+//! for envelope in backend.list_envelopes(&10, &0)?.0 {
+//!     println!("{}: {}", envelope.id, envelope.subject);
+//! }
+//!
+//! let tpl = Tpl(String::from("To: bob@localhost\nSubject: Hello\n\nHello, world!"));
+//! let mut sender = build_sender(&account);
+//! sender.send(&Msg::try_from(&tpl)?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+// Without the CLI, the items only used by the commands are left unused.
+#![cfg_attr(not(feature = "cli"), allow(dead_code))]
+
+#[cfg(feature = "cli")]
+mod compl;
+mod config;
+mod domain;
+#[cfg(feature = "cli")]
+mod man;
+mod output;
+mod ui;
+
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli;
+
+pub use config::{Account, BackendKind, Config, ConfigAccountEntry};
+pub use domain::{
+    account::Quota,
+    backend::{build_backend, build_sender, Backend, Sender},
+    imap::WatchEvent,
+    mbox::{Acl, Mbox, MboxStatus, Mboxes},
+    msg::{
+        Envelope, Envelopes, Flag, Flags, Msg, PartMeta, PartMetas, SortCriteria, SortCriterion,
+        Tpl,
+    },
+};
+
+#[cfg(feature = "async")]
+pub use domain::backend::backend_async;
@@ -0,0 +1,30 @@
+//! Module related to man page CLI.
+//!
+//! This module provides subcommands and a command matcher related to the man page.
+
+use anyhow::Result;
+use clap::{self, App, ArgMatches, SubCommand};
+use log::debug;
+
+/// Man page commands.
+pub enum Command {
+    /// Generate the man page.
+    Generate,
+}
+
+/// Man page command matcher.
+pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
+    if m.subcommand_matches("man").is_some() {
+        debug!("man command matched");
+        return Ok(Some(Command::Generate));
+    }
+
+    Ok(None)
+}
+
+/// Man page subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("man")
+        .about("Generates the man page")
+        .long_about("Generates the man page in the roff format, including all the commands and the config options. For example: `himalaya man > himalaya.1`.")]
+}
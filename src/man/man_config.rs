@@ -0,0 +1,798 @@
+//! Module related to the config options documentation.
+//!
+//! The man page documents the config options from the tables of this module. The tests check
+//! them against the fields of [`Config`](crate::config::Config) and
+//! [`ConfigAccountEntry`](crate::config::ConfigAccountEntry), so that a new option cannot be left
+//! undocumented. Account options mirroring global ones are documented once, as global options.
+
+/// Represents a documented config option.
+#[derive(Debug, PartialEq)]
+pub struct ConfigOpt {
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub doc: &'static str,
+}
+
+/// The global options, in the order of the [`Config`](crate::config::Config) fields.
+pub const GLOBAL_OPTS: &[ConfigOpt] = &[
+    ConfigOpt {
+        name: "name",
+        kind: "string",
+        doc: "Define the full display name of the user.",
+    },
+    ConfigOpt {
+        name: "downloads-dir",
+        kind: "path",
+        doc: "Define the downloads directory (eg. for attachments).",
+    },
+    ConfigOpt {
+        name: "signature-delimiter",
+        kind: "string",
+        doc: "Override the default signature delimiter \"`-- \\n`\".",
+    },
+    ConfigOpt {
+        name: "signature",
+        kind: "string",
+        doc: "Define the signature, either a path to a file or the text itself.",
+    },
+    ConfigOpt {
+        name: "signature-path",
+        kind: "string",
+        doc: "Define the path of the signature file, failing when it cannot be read.",
+    },
+    ConfigOpt {
+        name: "signature-html",
+        kind: "string",
+        doc: "Define the HTML signature, either a path to a file or the HTML itself. The text signature is rendered from it when none is defined.",
+    },
+    ConfigOpt {
+        name: "signature-position",
+        kind: "string",
+        doc: "Define where the signature goes in replies and forwards.",
+    },
+    ConfigOpt {
+        name: "signatures",
+        kind: "array",
+        doc: "Define signatures used in turn, each one being either a path to a file or the text itself.",
+    },
+    ConfigOpt {
+        name: "signature-rotation",
+        kind: "string",
+        doc: "Define how the signatures are rotated (default to round-robin).",
+    },
+    ConfigOpt {
+        name: "signature-cmd",
+        kind: "string",
+        doc: "Define a command whose output is the signature, taking precedence over the signatures.",
+    },
+    ConfigOpt {
+        name: "default-page-size",
+        kind: "integer",
+        doc: "Define the default page size for listings.",
+    },
+    ConfigOpt {
+        name: "list-format",
+        kind: "string",
+        doc: "Define the format string of message listings (eg. `{id}\\t{date:%Y-%m-%d}\\t{subject}`).",
+    },
+    ConfigOpt {
+        name: "datetime-fmt",
+        kind: "string",
+        doc: "Define the format of the dates of message listings, in the local timezone (eg. `%d %b %H:%M`). Default to `%Y-%m-%d %H:%M:%S`.",
+    },
+    ConfigOpt {
+        name: "theme",
+        kind: "table",
+        doc: "Define the styles of the elements of tables (`header`, `separator`, `id`, `flags`, `flagged`, `subject`, `sender`, `date`, `size`, `unseen`), as attributes and colors separated by spaces (eg. `unseen = \"bold bright-white\"`).",
+    },
+    ConfigOpt {
+        name: "reply-quote",
+        kind: "string",
+        doc: "Define which part of the original message is quoted in replies.",
+    },
+    ConfigOpt {
+        name: "reply-quote-context",
+        kind: "integer",
+        doc: "Define the number of lines quoted around the text matched by `--quote-match`.",
+    },
+    ConfigOpt {
+        name: "reply-quote-prefix",
+        kind: "string",
+        doc: "Define the prefix of quoted lines in replies.",
+    },
+    ConfigOpt {
+        name: "reply-attribution",
+        kind: "string",
+        doc: "Define the format string of the attribution line of replies, exposing the `date`, `name` and `email` of the original sender (eg. `{name} <{email}> wrote:`).",
+    },
+    ConfigOpt {
+        name: "reply-attribution-date-format",
+        kind: "string",
+        doc: "Define the strftime format of the date of the attribution line of replies.",
+    },
+    ConfigOpt {
+        name: "format-flowed",
+        kind: "boolean",
+        doc: "Send text as `format=flowed`, so that long paragraphs reflow in the clients supporting it.",
+    },
+    ConfigOpt {
+        name: "spellcheck-cmd",
+        kind: "string",
+        doc: "Define a command run over the message before sending, printing one misspelled word per line. The `{lang}` placeholder is replaced by the detected language (eg. `aspell --lang={lang} list`).",
+    },
+    ConfigOpt {
+        name: "send-safety-check",
+        kind: "boolean",
+        doc: "Warn before sending to recipients never written to before, or to external recipients alongside internal ones, according to the delivery log.",
+    },
+    ConfigOpt {
+        name: "read-receipt-policy",
+        kind: "string",
+        doc: "Define what to do when reading a message asking for a read receipt.",
+    },
+    ConfigOpt {
+        name: "internal-domains",
+        kind: "array",
+        doc: "Define the domains considered internal by the send safety check. Default to the domain of the account email.",
+    },
+    ConfigOpt {
+        name: "attachment-reminder-patterns",
+        kind: "array",
+        doc: "Define the regexes matching a mention of an attachment, to warn before sending a message without attachments. Default to common English, French, German and Spanish words, an empty list disables the reminder.",
+    },
+    ConfigOpt {
+        name: "summarize-cmd",
+        kind: "string",
+        doc: "Define the command summarizing messages, reading their text on its standard input and printing the summary.",
+    },
+    ConfigOpt {
+        name: "draft-suggest-cmd",
+        kind: "string",
+        doc: "Define the command suggesting reply drafts with `reply --suggest`, reading the original message on its standard input and printing the body of the reply.",
+    },
+    ConfigOpt {
+        name: "recipient-templates",
+        kind: "array",
+        doc: "Define templates, signatures and languages used when writing to some recipients.",
+    },
+    ConfigOpt {
+        name: "crm-rules",
+        kind: "array",
+        doc: "Define the blind copies and marker headers added when writing to some domains.",
+    },
+    ConfigOpt {
+        name: "new-template",
+        kind: "string",
+        doc: "Define the template new messages start from, either a path to a file or the text itself.",
+    },
+    ConfigOpt {
+        name: "reply-template",
+        kind: "string",
+        doc: "Define the template of replies, replacing the default attribution line and quote.",
+    },
+    ConfigOpt {
+        name: "forward-template",
+        kind: "string",
+        doc: "Define the template of forwarded messages, replacing the default forward header.",
+    },
+    ConfigOpt {
+        name: "archive-folder",
+        kind: "string",
+        doc: "Define the folder messages are archived in (default to \"Archive\"). The `{year}` and `{month}` placeholders are replaced by the date of each message (eg. `Archive/{year}`).",
+    },
+    ConfigOpt {
+        name: "snooze-folder",
+        kind: "string",
+        doc: "Define the folder snoozed messages wait in (default to \"Snoozed\").",
+    },
+    ConfigOpt {
+        name: "trash-folder",
+        kind: "string",
+        doc: "Define the folder deleted messages are moved to. Messages are deleted permanently when not set, or when they already are in this folder.",
+    },
+    ConfigOpt {
+        name: "sent-folder",
+        kind: "string",
+        doc: "Define the folder sent messages are saved in (default to \"Sent\").",
+    },
+    ConfigOpt {
+        name: "drafts-folder",
+        kind: "string",
+        doc: "Define the folder remote drafts are saved in (default to \"Drafts\").",
+    },
+    ConfigOpt {
+        name: "junk-folder",
+        kind: "string",
+        doc: "Define the folder messages reported as spam are moved to (default to \"Junk\").",
+    },
+    ConfigOpt {
+        name: "spam-learn-cmd",
+        kind: "string",
+        doc: "Define the commands the messages reported as spam or ham are piped to, so that the spam filter learns from them (default to `sa-learn --spam` and `sa-learn --ham`, eg. `rspamc learn_spam` and `rspamc learn_ham` for rspamd).",
+    },
+    ConfigOpt {
+        name: "ham-learn-cmd",
+        kind: "string",
+        doc: "Define the command teaching the spam filter that the messages piped to it are ham, see `spam-learn-cmd`.",
+    },
+    ConfigOpt {
+        name: "show-spam-score",
+        kind: "boolean",
+        doc: "Define whether listings show the spam score of messages, from their `X-Spam-Score` header (default to false).",
+    },
+    ConfigOpt {
+        name: "save-sent-copy",
+        kind: "boolean",
+        doc: "Define whether sent messages are appended to the sent folder (default to true, except for Graph accounts, the Graph API saving them itself). Disable it for servers already saving the messages sent over SMTP.",
+    },
+    ConfigOpt {
+        name: "envelope-cache",
+        kind: "boolean",
+        doc: "Define whether listed envelopes are cached, so that listing them again only fetches the changes (default to true).",
+    },
+    ConfigOpt {
+        name: "fetch-jobs",
+        kind: "integer",
+        doc: "Define the number of sessions fetching messages in parallel for bulk operations (export, backup, body search), default to 1.",
+    },
+    ConfigOpt {
+        name: "secrets-file",
+        kind: "path",
+        doc: "Define the file holding the secrets (passwords, refresh tokens), encrypted with age (`.age`) or GPG (`.gpg`).",
+    },
+    ConfigOpt {
+        name: "secrets-decrypt-cmd",
+        kind: "string",
+        doc: "Define the command printing the decrypted secrets file, its path being given by the `HIMALAYA_SECRETS_FILE` env var (default to `age --decrypt` or `gpg --decrypt`).",
+    },
+    ConfigOpt {
+        name: "outbox",
+        kind: "boolean",
+        doc: "Define whether messages the sender fails to send are queued in the outbox, to be sent again with `queue flush` (default to false).",
+    },
+    ConfigOpt {
+        name: "quota-warning",
+        kind: "integer",
+        doc: "Define the used part of a quota, in percent, from which saving a message to a mailbox warns that it is near its quota (default to 90).",
+    },
+    ConfigOpt {
+        name: "storage",
+        kind: "string",
+        doc: "Define where the cache and the state are stored: `file` (default) or `sqlite`. State is not migrated when switching.",
+    },
+    ConfigOpt {
+        name: "hold-keyword",
+        kind: "string",
+        doc: "Define the keyword of messages on hold, that destructive commands refuse to touch without `--override-hold` (default to \"$Hold\").",
+    },
+    ConfigOpt {
+        name: "filters",
+        kind: "array",
+        doc: "Define the filter rules applied by `filter apply`, from the first to the last one.",
+    },
+    ConfigOpt {
+        name: "queries",
+        kind: "table",
+        doc: "Define named search queries, run with `list --query NAME` and listed as virtual mailboxes by `mailboxes list` (eg. `todo = \"flag:flagged -flag:seen\"`).",
+    },
+    ConfigOpt {
+        name: "default-headers",
+        kind: "table",
+        doc: "Define the headers added to the messages composed from templates, unless already set (eg. `Organization = \"ACME\"`). Account headers override the global ones of the same name.",
+    },
+    ConfigOpt {
+        name: "split-attachment-size",
+        kind: "string",
+        doc: "Define the size above which attachments are split across several messages, with an optional K, M or G suffix (eg. `10M`).",
+    },
+    ConfigOpt {
+        name: "share-cmd",
+        kind: "string",
+        doc: "Define a command uploading attachments above `share-attachment-size`, and printing their link. The `{path}` and `{filename}` placeholders are replaced by the path and the name of the attachment (eg. `rclone copy {path} cloud:mail && rclone link cloud:mail/{filename}`).",
+    },
+    ConfigOpt {
+        name: "share-attachment-size",
+        kind: "string",
+        doc: "Define the size above which attachments are shared as links (default to `10M`).",
+    },
+    ConfigOpt {
+        name: "pre-send-cmd",
+        kind: "string",
+        doc: "Define a command receiving the raw message on its standard input before sending. Sending is aborted when it exits with a non-zero status (eg. to lint the message).",
+    },
+    ConfigOpt {
+        name: "post-send-cmd",
+        kind: "string",
+        doc: "Define a command receiving the raw message on its standard input once sent (eg. to archive it).",
+    },
+    ConfigOpt {
+        name: "notify-cmd",
+        kind: "string",
+        doc: "Define the command run when a new message arrives. In notify standby mode, it can print `read` or `delete` to apply the action to the message.",
+    },
+    ConfigOpt {
+        name: "notify-rules",
+        kind: "array",
+        doc: "Define the urgency and sound of the notifications of some mailboxes or messages.",
+    },
+    ConfigOpt {
+        name: "watch-cmds",
+        kind: "array",
+        doc: "Define the commands run when the watched mailbox changes.",
+    },
+    ConfigOpt {
+        name: "webhook-url",
+        kind: "string",
+        doc: "Define an HTTP(S) URL JSON events are posted to: new messages, sends and send failures.",
+    },
+    ConfigOpt {
+        name: "metrics-file",
+        kind: "path",
+        doc: "Define the Prometheus textfile new messages, sends, errors and connection state are counted in, per account (eg. for the textfile collector of the node exporter).",
+    },
+    ConfigOpt {
+        name: "proxy",
+        kind: "string",
+        doc: "Define the proxy connections of all accounts go through, unless they define their own.",
+    },
+    ConfigOpt {
+        name: "max-retries",
+        kind: "integer",
+        doc: "Define how many times fetching, listing and searching are retried on network errors, and how many times in a row `notify` and `watch` reconnect, with an exponential backoff. Default to 3, 0 disabling retries.",
+    },
+    ConfigOpt {
+        name: "system-mode",
+        kind: "boolean",
+        doc: "Enable the system mode, hardening himalaya for shared servers.",
+    },
+];
+
+/// The account options not mirroring a global one, or documented differently.
+pub const ACCOUNT_OPTS: &[ConfigOpt] = &[
+    ConfigOpt {
+        name: "default",
+        kind: "boolean",
+        doc: "Define whether the account is the one used when no account is given (default to false).",
+    },
+    ConfigOpt {
+        name: "email",
+        kind: "string",
+        doc: "Define the email address of the account. Required.",
+    },
+    ConfigOpt {
+        name: "identities",
+        kind: "table",
+        doc: "Define the other identities messages can be sent from, by alias.",
+    },
+    ConfigOpt {
+        name: "backend",
+        kind: "string",
+        doc: "Define the backend managing the messages of the account: `imap`, IMAP for reading and SMTP for sending, or `graph`, the Microsoft Graph API for both (default to `imap`).",
+    },
+    ConfigOpt {
+        name: "provider",
+        kind: "string",
+        doc: "Define the provider whose known settings are used as defaults.",
+    },
+    ConfigOpt {
+        name: "imap-host",
+        kind: "string",
+        doc: "Define the IMAP host (default to the one of the provider). Required by the IMAP backend.",
+    },
+    ConfigOpt {
+        name: "imap-port",
+        kind: "integer",
+        doc: "Define the IMAP port (default to the one of the provider). Required by the IMAP backend.",
+    },
+    ConfigOpt {
+        name: "imap-starttls",
+        kind: "boolean",
+        doc: "Define whether the IMAP connection is upgraded with STARTTLS instead of starting with TLS (default to false).",
+    },
+    ConfigOpt {
+        name: "imap-insecure",
+        kind: "boolean",
+        doc: "Define whether invalid IMAP server certificates are accepted (default to false).",
+    },
+    ConfigOpt {
+        name: "imap-compress",
+        kind: "boolean",
+        doc: "Define whether the IMAP traffic is compressed, when the server supports the COMPRESS=DEFLATE extension (default to false). Worth it on slow links.",
+    },
+    ConfigOpt {
+        name: "imap-login",
+        kind: "string",
+        doc: "Define the IMAP login (default to the email address).",
+    },
+    ConfigOpt {
+        name: "imap-passwd-cmd",
+        kind: "string",
+        doc: "Define the command printing the IMAP password (default to `passwd-cmd`).",
+    },
+    ConfigOpt {
+        name: "smtp-host",
+        kind: "string",
+        doc: "Define the SMTP host (default to the one of the provider). Required by the IMAP backend.",
+    },
+    ConfigOpt {
+        name: "smtp-port",
+        kind: "integer",
+        doc: "Define the SMTP port (default to the one of the provider). Required by the IMAP backend.",
+    },
+    ConfigOpt {
+        name: "smtp-starttls",
+        kind: "boolean",
+        doc: "Define whether the SMTP connection is upgraded with STARTTLS instead of starting with TLS (default to false).",
+    },
+    ConfigOpt {
+        name: "smtp-insecure",
+        kind: "boolean",
+        doc: "Define whether invalid SMTP server certificates are accepted (default to false).",
+    },
+    ConfigOpt {
+        name: "smtp-login",
+        kind: "string",
+        doc: "Define the SMTP login (default to the email address).",
+    },
+    ConfigOpt {
+        name: "smtp-passwd-cmd",
+        kind: "string",
+        doc: "Define the command printing the SMTP password (default to `passwd-cmd`).",
+    },
+    ConfigOpt {
+        name: "proxy",
+        kind: "string",
+        doc: "Define the proxy connections go through, like `socks5://127.0.0.1:9050` or `http://proxy.corp:3128`.",
+    },
+    ConfigOpt {
+        name: "tls-ca-cert",
+        kind: "path",
+        doc: "Define the PEM certificate of the CA signing the server certificates, when it is not trusted by the system (eg. a private CA of a self-hosted server).",
+    },
+    ConfigOpt {
+        name: "tls-client-cert",
+        kind: "path",
+        doc: "Define the PEM certificate and the PEM (PKCS#8) key sent to servers requiring client certificates.",
+    },
+    ConfigOpt {
+        name: "tls-client-key",
+        kind: "path",
+        doc: "Define the PEM (PKCS#8) key of the client certificate, see `tls-client-cert`.",
+    },
+    ConfigOpt {
+        name: "imap-cert-fingerprint",
+        kind: "string",
+        doc: "Define the SHA-256 fingerprint of the IMAP server certificate (eg. `AB:CD:…`), trusted whoever signed it, any other certificate being rejected.",
+    },
+    ConfigOpt {
+        name: "smtp-cert-fingerprint",
+        kind: "string",
+        doc: "Define the SHA-256 fingerprint of the SMTP server certificate.",
+    },
+    ConfigOpt {
+        name: "passwd-cmd",
+        kind: "string",
+        doc: "Define the command printing the password of both IMAP and SMTP, used when `imap-passwd-cmd` or `smtp-passwd-cmd` is not defined.",
+    },
+    ConfigOpt {
+        name: "passwd-keyring",
+        kind: "string",
+        doc: "Define the system keyring entry holding the password, as `service/user` (eg. `himalaya/work`), used when no password command is defined. See `account set-password`.",
+    },
+    ConfigOpt {
+        name: "passwd-secret",
+        kind: "string",
+        doc: "Define the key of the password in the secrets file (eg. `work.passwd`), used when no password command is defined.",
+    },
+    ConfigOpt {
+        name: "sendmail-cmd",
+        kind: "string",
+        doc: "Define a command messages are piped to instead of being sent via SMTP (eg. `msmtp`). The recipients are appended as arguments, after `-i --`.",
+    },
+    ConfigOpt {
+        name: "sieve-host",
+        kind: "string",
+        doc: "Define the ManageSieve host (default to the IMAP host). The IMAP credentials are used.",
+    },
+    ConfigOpt {
+        name: "sieve-port",
+        kind: "integer",
+        doc: "Define the ManageSieve port (default to 4190).",
+    },
+    ConfigOpt {
+        name: "sieve-starttls",
+        kind: "boolean",
+        doc: "Define whether the ManageSieve connection is upgraded with STARTTLS instead of starting with TLS (default to true).",
+    },
+    ConfigOpt {
+        name: "graph-client-id",
+        kind: "string",
+        doc: "Define the Azure application (client) id used by the Graph backend.",
+    },
+    ConfigOpt {
+        name: "graph-tenant",
+        kind: "string",
+        doc: "Define the Azure tenant used by the Graph backend (default to \"common\").",
+    },
+    ConfigOpt {
+        name: "graph-refresh-token-secret",
+        kind: "string",
+        doc: "Define the key of the Graph refresh token in the secrets file, used until the token is refreshed for the first time.",
+    },
+    ConfigOpt {
+        name: "smime-cert",
+        kind: "path",
+        doc: "Define the S/MIME certificate of the account and its private key, as PEM files, used to sign messages and to decrypt the ones received.",
+    },
+    ConfigOpt {
+        name: "smime-key",
+        kind: "path",
+        doc: "Define the private key of the S/MIME certificate, as a PEM file, see `smime-cert`.",
+    },
+    ConfigOpt {
+        name: "smime-ca-cert",
+        kind: "path",
+        doc: "Define the CA certificates S/MIME signatures are verified against, as a PEM file (default to the system ones).",
+    },
+    ConfigOpt {
+        name: "smime-certs-dir",
+        kind: "path",
+        doc: "Define the directory holding the S/MIME certificates of the recipients, named after their address (eg. `jane@doe.org.pem`), used to encrypt messages.",
+    },
+    ConfigOpt {
+        name: "smime-sign",
+        kind: "boolean",
+        doc: "Define whether sent messages are signed with the S/MIME certificate (default to false).",
+    },
+    ConfigOpt {
+        name: "smime-encrypt",
+        kind: "boolean",
+        doc: "Define whether sent messages are encrypted for their recipients with S/MIME (default to false).",
+    },
+    ConfigOpt {
+        name: "dkim-selector",
+        kind: "string",
+        doc: "Define the DKIM selector messages sent via SMTP are signed with, the public key being published at `<selector>._domainkey.<domain>`.",
+    },
+    ConfigOpt {
+        name: "dkim-domain",
+        kind: "string",
+        doc: "Define the DKIM signing domain (default to the domain of the account email).",
+    },
+    ConfigOpt {
+        name: "dkim-key",
+        kind: "path",
+        doc: "Define the RSA private key of the DKIM selector, as a PEM file.",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigAccountEntry};
+
+    /// List the fields of a struct, by destructuring it without rest pattern: the list fails to
+    /// compile as soon as a field is added or removed.
+    macro_rules! fields {
+        ($ty:ident { $($field:ident),* $(,)? }) => {{
+            let $ty { $($field: _),* } = $ty::default();
+            vec![$(stringify!($field).replace('_', "-")),*]
+        }};
+    }
+
+    #[test]
+    fn it_should_document_global_opts() {
+        let fields: Vec<String> = fields!(Config {
+            name,
+            downloads_dir,
+            signature_delimiter,
+            signature,
+            signature_path,
+            signature_html,
+            signature_position,
+            signatures,
+            signature_rotation,
+            signature_cmd,
+            default_page_size,
+            list_format,
+            datetime_fmt,
+            theme,
+            reply_quote,
+            reply_quote_context,
+            reply_quote_prefix,
+            reply_attribution,
+            reply_attribution_date_format,
+            format_flowed,
+            spellcheck_cmd,
+            send_safety_check,
+            read_receipt_policy,
+            internal_domains,
+            attachment_reminder_patterns,
+            summarize_cmd,
+            draft_suggest_cmd,
+            recipient_templates,
+            crm_rules,
+            new_template,
+            reply_template,
+            forward_template,
+            archive_folder,
+            snooze_folder,
+            trash_folder,
+            sent_folder,
+            drafts_folder,
+            junk_folder,
+            spam_learn_cmd,
+            ham_learn_cmd,
+            show_spam_score,
+            save_sent_copy,
+            envelope_cache,
+            fetch_jobs,
+            secrets_file,
+            secrets_decrypt_cmd,
+            outbox,
+            quota_warning,
+            storage,
+            hold_keyword,
+            filters,
+            queries,
+            default_headers,
+            split_attachment_size,
+            share_cmd,
+            share_attachment_size,
+            pre_send_cmd,
+            post_send_cmd,
+            notify_cmd,
+            notify_rules,
+            watch_cmds,
+            webhook_url,
+            metrics_file,
+            proxy,
+            max_retries,
+            system_mode,
+            accounts,
+        })
+        .into_iter()
+        // The accounts are flattened at the root of the config.
+        .filter(|field| field != "accounts")
+        .collect();
+        let names: Vec<&str> = GLOBAL_OPTS.iter().map(|opt| opt.name).collect();
+        assert_eq!(fields, names);
+    }
+
+    #[test]
+    fn it_should_document_account_opts() {
+        let fields = fields!(ConfigAccountEntry {
+            name,
+            downloads_dir,
+            signature_delimiter,
+            signature,
+            signature_path,
+            signature_html,
+            signature_position,
+            signatures,
+            signature_rotation,
+            signature_cmd,
+            default_page_size,
+            list_format,
+            datetime_fmt,
+            max_retries,
+            reply_quote,
+            reply_quote_context,
+            reply_quote_prefix,
+            reply_attribution,
+            reply_attribution_date_format,
+            format_flowed,
+            spellcheck_cmd,
+            send_safety_check,
+            read_receipt_policy,
+            internal_domains,
+            attachment_reminder_patterns,
+            summarize_cmd,
+            draft_suggest_cmd,
+            recipient_templates,
+            crm_rules,
+            notify_rules,
+            new_template,
+            reply_template,
+            forward_template,
+            archive_folder,
+            snooze_folder,
+            trash_folder,
+            sent_folder,
+            drafts_folder,
+            junk_folder,
+            spam_learn_cmd,
+            ham_learn_cmd,
+            show_spam_score,
+            save_sent_copy,
+            envelope_cache,
+            fetch_jobs,
+            secrets_file,
+            secrets_decrypt_cmd,
+            outbox,
+            quota_warning,
+            storage,
+            hold_keyword,
+            filters,
+            queries,
+            default_headers,
+            split_attachment_size,
+            share_cmd,
+            share_attachment_size,
+            pre_send_cmd,
+            post_send_cmd,
+            watch_cmds,
+            webhook_url,
+            metrics_file,
+            default,
+            email,
+            identities,
+            backend,
+            provider,
+            imap_host,
+            imap_port,
+            imap_starttls,
+            imap_insecure,
+            imap_compress,
+            imap_login,
+            imap_passwd_cmd,
+            smtp_host,
+            smtp_port,
+            smtp_starttls,
+            smtp_insecure,
+            smtp_login,
+            smtp_passwd_cmd,
+            proxy,
+            tls_ca_cert,
+            tls_client_cert,
+            tls_client_key,
+            imap_cert_fingerprint,
+            smtp_cert_fingerprint,
+            passwd_cmd,
+            passwd_keyring,
+            passwd_secret,
+            sendmail_cmd,
+            sieve_host,
+            sieve_port,
+            sieve_starttls,
+            graph_client_id,
+            graph_tenant,
+            graph_refresh_token_secret,
+            smime_cert,
+            smime_key,
+            smime_ca_cert,
+            smime_certs_dir,
+            smime_sign,
+            smime_encrypt,
+            dkim_selector,
+            dkim_domain,
+            dkim_key,
+        });
+        for field in &fields {
+            assert!(
+                ACCOUNT_OPTS
+                    .iter()
+                    .chain(GLOBAL_OPTS)
+                    .any(|opt| opt.name == field),
+                "account option {} is not documented",
+                field
+            );
+        }
+        for opt in ACCOUNT_OPTS {
+            assert!(
+                fields.iter().any(|field| field == opt.name),
+                "account option {} does not exist",
+                opt.name
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_describe_opts_in_config_terms() {
+        let kinds = ["string", "path", "boolean", "integer", "array", "table"];
+        for opt in GLOBAL_OPTS.iter().chain(ACCOUNT_OPTS) {
+            assert!(kinds.contains(&opt.kind), "{}: {}", opt.name, opt.kind);
+            assert!(!opt.doc.contains("]("), "{}: {}", opt.name, opt.doc);
+        }
+    }
+}
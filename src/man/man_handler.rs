@@ -0,0 +1,144 @@
+//! Module related to man page handling.
+//!
+//! The commands are documented by their help, as printed by `--help`, subcommands being found
+//! in the help of their parent. The config options are documented by the tables of
+//! [`man_config`](crate::man::man_config).
+
+use anyhow::{anyhow, Context, Result};
+use clap::{App, AppSettings, ErrorKind};
+use log::debug;
+use std::io::{self, Write};
+
+use crate::man::man_config::{ConfigOpt, ACCOUNT_OPTS, GLOBAL_OPTS};
+
+/// Escape the given text for roff.
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Get the help of the subcommand at the given path, as printed by `--help`.
+fn help(app: &App, path: &[String]) -> Result<String> {
+    let mut args = vec![String::from("himalaya")];
+    args.extend_from_slice(path);
+    args.push(String::from("--help"));
+    match app.clone().get_matches_from_safe(args) {
+        Err(err) if err.kind == ErrorKind::HelpDisplayed => Ok(err.message),
+        Err(err) => Err(anyhow!("{}", err.message)),
+        Ok(_) => Err(anyhow!(r#"cannot get help of "{}""#, path.join(" "))),
+    }
+}
+
+/// Get the names of the subcommands listed by the given help.
+fn subcmd_names(help: &str) -> Vec<String> {
+    help.lines()
+        .skip_while(|line| line.trim_end() != "SUBCOMMANDS:")
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        // Wrapped descriptions are indented further than the names.
+        .filter(|line| line.starts_with("    ") && !line.starts_with("     "))
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| *name != "help")
+        .map(String::from)
+        .collect()
+}
+
+/// Write the command section of the subcommand at the given path, then the one of its own
+/// subcommands.
+fn write_cmd(man: &mut String, app: &App, path: &mut Vec<String>) -> Result<()> {
+    let cmd_help = help(app, path)?;
+    debug!("document command {:?}", path);
+    man.push_str(&format!(".SS \"himalaya {}\"\n", path.join(" ")));
+    man.push_str(&format!(".nf\n{}\n.fi\n", escape(cmd_help.trim_end())));
+    for name in subcmd_names(&cmd_help) {
+        path.push(name);
+        write_cmd(man, app, path)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Write the config section documenting the given options.
+fn write_config_opts(man: &mut String, title: &str, opts: &[ConfigOpt]) {
+    man.push_str(&format!(".SS \"{}\"\n", title));
+    for opt in opts {
+        man.push_str(&format!(
+            ".TP\n\\fB{}\\fR (\\fI{}\\fR)\n{}\n",
+            opt.name,
+            escape(opt.kind),
+            escape(opt.doc)
+        ));
+    }
+}
+
+/// Build the man page of the given [`clap::App`].
+fn build(app: App) -> Result<String> {
+    let app = app.global_setting(AppSettings::ColorNever);
+    let version = env!("CARGO_PKG_VERSION");
+    let mut man = format!(
+        ".TH HIMALAYA 1 \"\" \"himalaya {}\" \"User Commands\"\n",
+        version
+    );
+    man.push_str(".SH NAME\nhimalaya \\- CLI email client\n");
+    man.push_str(".SH SYNOPSIS\n\\fBhimalaya\\fR [\\fIOPTIONS\\fR] [\\fISUBCOMMAND\\fR]\n");
+    let app_help = help(&app, &[])?;
+    man.push_str(".SH DESCRIPTION\n");
+    man.push_str(&format!(".nf\n{}\n.fi\n", escape(app_help.trim_end())));
+
+    man.push_str(".SH COMMANDS\n");
+    for name in subcmd_names(&app_help) {
+        write_cmd(&mut man, &app, &mut vec![name])?;
+    }
+
+    man.push_str(".SH CONFIGURATION\n");
+    man.push_str(concat!(
+        "The config is a TOML file, \\fI~/.config/himalaya/config.toml\\fR by default. ",
+        "Global options are set at the root of the file, accounts in tables named after them. ",
+        "Global options can be overridden per account.\n",
+    ));
+    write_config_opts(&mut man, "Global options", GLOBAL_OPTS);
+    write_config_opts(&mut man, "Account options", ACCOUNT_OPTS);
+
+    man.push_str(".SH FILES\n.TP\n\\fI~/.config/himalaya/config.toml\\fR\nThe config file.\n");
+    Ok(man)
+}
+
+/// Print the man page of the given [`clap::App`].
+pub fn generate(app: App) -> Result<()> {
+    let man = build(app)?;
+    io::stdout()
+        .write_all(man.as_bytes())
+        .context("cannot print man page")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_get_subcmd_names() {
+        let help = concat!(
+            "USAGE:\n    himalaya [OPTIONS]\n\n",
+            "SUBCOMMANDS:\n",
+            "    expunge    Removes the deleted messages\n",
+            "               of the mailbox\n",
+            "    help       Prints this message\n",
+            "    list       Lists mailboxes\n",
+        );
+        assert_eq!(vec!["expunge", "list"], subcmd_names(help));
+    }
+
+    #[test]
+    fn it_should_escape_roff() {
+        assert_eq!("\\&.hidden\n\\efoo", escape(".hidden\n\\foo"));
+    }
+}
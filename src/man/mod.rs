@@ -0,0 +1,9 @@
+//! Module related to man page generation.
+//!
+//! This module generates the man page of himalaya from the clap app and from the documentation of
+//! the config structs, so that it stays in sync with the code. Packagers install the output of
+//! `himalaya man` as `himalaya.1`.
+
+pub mod man_arg;
+pub mod man_config;
+pub mod man_handler;
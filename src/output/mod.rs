@@ -1,9 +1,19 @@
 //! Module related to output formatting and printing.
 
+#[cfg(feature = "cli")]
 pub mod output_arg;
+pub mod output_error;
+pub mod output_interrupt;
+pub mod output_trace;
 
 pub mod output_utils;
 pub use output_utils::*;
 
+pub mod output_progress;
+pub use output_progress::*;
+
 pub mod output_service;
 pub use output_service::*;
+
+pub mod output_tpl;
+pub use output_tpl::*;
@@ -11,9 +11,15 @@ pub fn args<'a>() -> Vec<Arg<'a, 'a>> {
             .long("output")
             .short("o")
             .help("Defines the output format")
+            .long_help("Defines the output format. The ndjson format prints one JSON document per line, as soon as it is fetched, so that huge listings and watch events can be piped incrementally.")
             .value_name("FMT")
-            .possible_values(&["plain", "json"])
+            .possible_values(&["plain", "json", "ndjson"])
             .default_value("plain"),
+        Arg::with_name("format")
+            .long("format")
+            .help("Defines the format string of listings")
+            .long_help("Defines the format string of message listings, printing one line per message, for example `{id}\\t{date:%Y-%m-%d}\\t{from:30}\\t{subject}`. Fields are id, flags, subject, from, date, size, labels and thread. A field can take a width, padding or truncating its value, or a strftime format for dates. Overrides the `list-format` config option, ignored by the JSON output.")
+            .value_name("TPL"),
         Arg::with_name("log-level")
             .long("log-level")
             .alias("log")
@@ -22,5 +28,26 @@ pub fn args<'a>() -> Vec<Arg<'a, 'a>> {
             .value_name("LEVEL")
             .possible_values(&["error", "warn", "info", "debug", "trace"])
             .default_value("info"),
+        Arg::with_name("verbose")
+            .long("verbose")
+            .short("v")
+            .help("Enables logs, for example to report IMAP extensions fallbacks"),
+        Arg::with_name("debug")
+            .long("debug")
+            .help("Enables debug logs and protocol traces")
+            .long_help("Enables debug logs, and traces of the IMAP and SMTP exchanges on the standard error. Same as `--log-level debug --trace`."),
+        Arg::with_name("trace")
+            .long("trace")
+            .alias("debug-imap")
+            .help("Traces the IMAP and SMTP exchanges")
+            .long_help("Traces the IMAP and SMTP exchanges, one JSON document per line, to the given file or to the standard error, for bug reports. Credentials are redacted, message contents are not.")
+            .value_name("FILE")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true),
+        Arg::with_name("no-color")
+            .long("no-color")
+            .help("Disables colors of tables")
+            .long_help("Disables colors and styles of tables, for example when piping. The NO_COLOR env var does the same when not empty."),
     ]
 }
@@ -0,0 +1,209 @@
+//! Module related to error output.
+//!
+//! Errors are classified by kind, so that wrappers (editor plugins, scripts) can react to them
+//! without parsing messages: each kind has its own exit code, stable across versions, and the
+//! JSON outputs print errors as JSON objects holding the kind.
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, process};
+
+use crate::output::{output_interrupt::Interrupted, OutputFmt};
+
+/// Represents the kind of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The server rejected the credentials.
+    Auth,
+    /// The server cannot be reached, or the connection broke.
+    Network,
+    /// The account, mailbox, message or file does not exist.
+    NotFound,
+    /// The config, a message or an argument cannot be parsed.
+    Parse,
+    /// The operation was interrupted by Ctrl-C.
+    Interrupted,
+    Other,
+}
+
+impl ErrorKind {
+    /// Get the exit code of the kind, following the `sysexits.h` conventions.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Auth => 77,
+            Self::Network => 69,
+            Self::NotFound => 66,
+            Self::Parse => 65,
+            Self::Interrupted => 130,
+            Self::Other => 1,
+        }
+    }
+
+    /// Classify the given error, from its outermost cause to its innermost one: the context
+    /// of an error tells more than its source, eg. a login rejected by the server.
+    pub fn of(err: &Error) -> Self {
+        if let Some(err) = err.downcast_ref::<RemoteError>() {
+            return err.kind;
+        }
+        err.chain()
+            .find_map(|cause| Self::of_source(cause).or_else(|| Self::of_msg(&cause.to_string())))
+            .unwrap_or(Self::Other)
+    }
+
+    fn of_source(cause: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if cause.is::<Interrupted>() {
+            return Some(Self::Interrupted);
+        }
+        if let Some(err) = cause.downcast_ref::<io::Error>() {
+            return match err.kind() {
+                io::ErrorKind::NotFound => Some(Self::NotFound),
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::AddrNotAvailable
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut => Some(Self::Network),
+                io::ErrorKind::InvalidData => Some(Self::Parse),
+                _ => None,
+            };
+        }
+        if let Some(err) = cause.downcast_ref::<imap::Error>() {
+            return match err {
+                imap::Error::Io(_)
+                | imap::Error::Tls(_)
+                | imap::Error::TlsHandshake(_)
+                | imap::Error::ConnectionLost => Some(Self::Network),
+                imap::Error::Parse(_) => Some(Self::Parse),
+                _ => None,
+            };
+        }
+        if cause.is::<native_tls::Error>() {
+            return Some(Self::Network);
+        }
+        if cause.is::<toml::de::Error>()
+            || cause.is::<serde_json::Error>()
+            || cause.is::<mailparse::MailParseError>()
+            || cause.is::<url::ParseError>()
+            || cause.is::<chrono::ParseError>()
+        {
+            return Some(Self::Parse);
+        }
+        None
+    }
+
+    /// Classify the given message, from the wording of the errors of the project.
+    fn of_msg(msg: &str) -> Option<Self> {
+        if msg.starts_with("cannot login") || msg.starts_with("cannot authenticate") {
+            Some(Self::Auth)
+        } else if msg.starts_with("cannot connect") || msg.starts_with("cannot resolve") {
+            Some(Self::Network)
+        } else if msg.starts_with("cannot find") {
+            Some(Self::NotFound)
+        } else if msg.starts_with("cannot parse") {
+            Some(Self::Parse)
+        } else {
+            None
+        }
+    }
+}
+
+/// Represents an error, as printed by the JSON outputs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorJson {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The causes of the error, the outermost first.
+    pub causes: Vec<String>,
+}
+
+impl From<&Error> for ErrorJson {
+    fn from(err: &Error) -> Self {
+        Self {
+            kind: ErrorKind::of(err),
+            message: err.to_string(),
+            causes: err.chain().skip(1).map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// Represents an error of a known kind, received from another process.
+#[derive(Debug)]
+pub struct RemoteError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+impl From<ErrorJson> for Error {
+    fn from(json: ErrorJson) -> Self {
+        let mut layers = json.causes;
+        layers.insert(0, json.message);
+        let message = layers.pop().unwrap_or_default();
+        let err = Error::new(RemoteError {
+            kind: json.kind,
+            message,
+        });
+        layers.into_iter().rev().fold(err, Error::context)
+    }
+}
+
+/// Print the given error according to the given output format, then exit with the code of its
+/// kind.
+pub fn exit(err: Error, fmt: &OutputFmt) -> ! {
+    let json = ErrorJson::from(&err);
+    match fmt {
+        OutputFmt::Plain => eprintln!("Error: {:?}", err),
+        OutputFmt::Json | OutputFmt::Ndjson => match serde_json::to_string(&json) {
+            Ok(json) => println!(r#"{{"error":{}}}"#, json),
+            Err(_) => eprintln!("Error: {:?}", err),
+        },
+    }
+    process::exit(json.kind.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn it_should_classify_errors() {
+        let err = Err::<(), _>(anyhow!("NO [AUTHENTICATIONFAILED] invalid credentials"))
+            .context("cannot login to IMAP server")
+            .context("cannot list envelopes")
+            .unwrap_err();
+        assert_eq!(ErrorKind::Auth, ErrorKind::of(&err));
+
+        let err = Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+            .context("cannot open session")
+            .unwrap_err();
+        assert_eq!(ErrorKind::Network, ErrorKind::of(&err));
+
+        let err = anyhow!(r#"cannot find account "work""#);
+        assert_eq!(ErrorKind::NotFound, ErrorKind::of(&err));
+        assert_eq!(66, ErrorKind::of(&err).exit_code());
+
+        let err = Err::<(), _>(serde_json::from_str::<u32>("nope").unwrap_err())
+            .context("cannot read cache")
+            .unwrap_err();
+        assert_eq!(ErrorKind::Parse, ErrorKind::of(&err));
+        assert_eq!(
+            ErrorKind::Other,
+            ErrorKind::of(&anyhow!("cannot send message"))
+        );
+
+        let err = anyhow!("cannot find message").context("cannot read message");
+        let err = Error::from(ErrorJson::from(&err));
+        assert_eq!("cannot read message", err.to_string());
+        assert_eq!(ErrorKind::NotFound, ErrorKind::of(&err));
+    }
+}
@@ -0,0 +1,75 @@
+//! Module related to interruptions.
+//!
+//! Ctrl-C kills the process right away, unless a cancellable operation is running (a backup, a
+//! body search…). Then the first Ctrl-C only raises a flag, checked between the steps of the
+//! operation (FETCH batches, backed up messages…), which aborts with an [`Interrupted`] error
+//! so that the sessions are logged out cleanly. A second Ctrl-C kills the process anyway.
+
+use anyhow::Result;
+use log::warn;
+use std::{
+    error, fmt, process,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Define the exit code of a process killed by Ctrl-C, like shells do.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CANCELLABLE_OPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents the error of an operation aborted by Ctrl-C.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation interrupted")
+    }
+}
+
+impl error::Error for Interrupted {}
+
+/// Handle Ctrl-C for the rest of the run.
+#[cfg(feature = "cli")]
+pub fn init() {
+    let res = ctrlc::set_handler(|| {
+        let is_cancellable = CANCELLABLE_OPS.load(Ordering::SeqCst) > 0;
+        if !is_cancellable || INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        eprintln!("\nInterrupting, press Ctrl-C again to quit right away…");
+    });
+    if let Err(err) = res {
+        warn!("cannot handle interruptions: {}", err);
+    }
+}
+
+/// Represents a running cancellable operation. The operation stops being cancellable when
+/// dropped.
+#[derive(Debug)]
+pub struct Cancellable(());
+
+impl Drop for Cancellable {
+    fn drop(&mut self) {
+        CANCELLABLE_OPS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Make Ctrl-C interrupt the operation starting, instead of killing the process.
+pub fn cancellable() -> Cancellable {
+    CANCELLABLE_OPS.fetch_add(1, Ordering::SeqCst);
+    Cancellable(())
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fail with an [`Interrupted`] error when Ctrl-C was pressed.
+pub fn check() -> Result<()> {
+    if is_interrupted() {
+        return Err(Interrupted.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Module related to progress reporting.
+//!
+//! Long operations draw a progress bar on the standard error, unless it is not a terminal, the
+//! output is JSON or the output is captured by the daemon. Running operations with a progress
+//! bar are cancellable: each step fails once Ctrl-C has been pressed.
+
+use anyhow::Result;
+use std::io::{self, Write};
+
+use crate::output::output_interrupt::{self, Cancellable};
+
+/// Define the width of the bar, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Render the given progress, as a bar when the total is known.
+fn render(label: &str, pos: usize, total: usize) -> String {
+    if total == 0 {
+        return format!("{}…", label);
+    }
+    let filled = BAR_WIDTH * pos / total;
+    let bar = match filled {
+        0 => " ".repeat(BAR_WIDTH),
+        filled if filled >= BAR_WIDTH => "=".repeat(BAR_WIDTH),
+        filled => format!(
+            "{}>{}",
+            "=".repeat(filled - 1),
+            " ".repeat(BAR_WIDTH - filled)
+        ),
+    };
+    format!(
+        "{} [{}] {}/{} ({}%)",
+        label,
+        bar,
+        pos,
+        total,
+        100 * pos / total
+    )
+}
+
+/// Represents the progress of a long operation.
+#[derive(Debug)]
+pub struct Progress {
+    label: String,
+    pos: usize,
+    total: usize,
+    visible: bool,
+    _cancellable: Cancellable,
+}
+
+impl Progress {
+    /// Start the progress of an operation made of `total` steps, 0 meaning unknown.
+    pub fn new(label: &str, total: usize, visible: bool) -> Self {
+        let progress = Self {
+            label: label.to_owned(),
+            pos: 0,
+            total,
+            visible,
+            _cancellable: output_interrupt::cancellable(),
+        };
+        progress.draw();
+        progress
+    }
+
+    fn draw(&self) {
+        if self.visible {
+            let mut stderr = io::stderr();
+            // Erase the remainder of a longer previous line.
+            write!(
+                stderr,
+                "\r{}\x1b[K",
+                render(&self.label, self.pos, self.total)
+            )
+            .ok();
+            stderr.flush().ok();
+        }
+    }
+
+    /// Define the number of steps, once known.
+    pub fn set_total(&mut self, total: usize) {
+        self.total = total;
+        self.draw();
+    }
+
+    /// Define the number of steps done. Fails when the operation has been interrupted.
+    pub fn set(&mut self, pos: usize) -> Result<()> {
+        self.pos = pos;
+        self.draw();
+        output_interrupt::check()
+    }
+
+    /// Mark the given number of steps as done. Fails when the operation has been interrupted.
+    pub fn inc(&mut self, delta: usize) -> Result<()> {
+        self.set(self.pos + delta)
+    }
+
+    /// Stop drawing the bar, leaving it on its line.
+    pub fn finish(&mut self) {
+        if self.visible {
+            eprintln!();
+            self.visible = false;
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_render_progress() {
+        assert_eq!("Scanning…", render("Scanning", 3, 0));
+        assert_eq!(
+            format!("Scanning [{}] 0/4 (0%)", " ".repeat(30)),
+            render("Scanning", 0, 4)
+        );
+        assert_eq!(
+            format!("Scanning [{}>{}] 2/4 (50%)", "=".repeat(14), " ".repeat(15)),
+            render("Scanning", 2, 4)
+        );
+        assert_eq!(
+            format!("Scanning [{}] 4/4 (100%)", "=".repeat(30)),
+            render("Scanning", 4, 4)
+        );
+    }
+}
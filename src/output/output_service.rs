@@ -1,21 +1,29 @@
 use anyhow::{anyhow, Error, Result};
+use atty::Stream;
 use log::debug;
 use serde::Serialize;
 use std::{
+    cell::RefCell,
     convert::{TryFrom, TryInto},
     fmt,
+    io::{self, Write},
 };
 
+use crate::output::{output_tpl, Progress, TplItems};
+
 #[derive(Debug, PartialEq)]
 pub enum OutputFmt {
     Plain,
     Json,
+    /// Newline-delimited JSON: one JSON document per line, printed as soon as it comes.
+    Ndjson,
 }
 
 impl From<&str> for OutputFmt {
     fn from(fmt: &str) -> Self {
         match fmt {
             slice if slice.eq_ignore_ascii_case("json") => Self::Json,
+            slice if slice.eq_ignore_ascii_case("ndjson") => Self::Ndjson,
             _ => Self::Plain,
         }
     }
@@ -27,6 +35,7 @@ impl TryFrom<Option<&str>> for OutputFmt {
     fn try_from(fmt: Option<&str>) -> Result<Self, Self::Error> {
         match fmt {
             Some(slice) if slice.eq_ignore_ascii_case("json") => Ok(Self::Json),
+            Some(slice) if slice.eq_ignore_ascii_case("ndjson") => Ok(Self::Ndjson),
             Some(slice) if slice.eq_ignore_ascii_case("plain") => Ok(Self::Plain),
             None => Ok(Self::Plain),
             Some(slice) => Err(anyhow!("cannot parse output `{}`", slice)),
@@ -38,6 +47,7 @@ impl fmt::Display for OutputFmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let slice = match self {
             &OutputFmt::Json => "JSON",
+            &OutputFmt::Ndjson => "NDJSON",
             &OutputFmt::Plain => "PLAIN",
         };
         write!(f, "{}", slice)
@@ -59,12 +69,60 @@ impl<T: Serialize> OutputJson<T> {
 
 pub trait OutputServiceInterface {
     fn print<T: Serialize + fmt::Display>(&self, data: T) -> Result<()>;
+    /// Print the provided listing with the user-defined format string, one line per item, or
+    /// like [`OutputServiceInterface::print`] when no format string is defined.
+    fn print_items<T: Serialize + fmt::Display + TplItems>(&self, data: T) -> Result<()>;
+    /// Print the provided item as one JSON line, flushed right away so that consumers can
+    /// process it before the next one comes.
+    fn print_ndjson<T: Serialize>(&self, item: &T) -> Result<()>;
     fn is_json(&self) -> bool;
+    fn is_ndjson(&self) -> bool;
+    /// Start the progress of a long operation made of `total` steps, 0 meaning unknown.
+    fn progress(&self, label: &str, total: usize) -> Progress;
 }
 
 #[derive(Debug)]
 pub struct OutputService {
     fmt: OutputFmt,
+    /// The format string of listings, ignored by the JSON output.
+    tpl: Option<String>,
+    /// The output captured instead of printed, eg. to be sent back by the daemon.
+    capture: Option<RefCell<String>>,
+}
+
+impl OutputService {
+    /// Define the format string of listings.
+    pub fn with_tpl(mut self, tpl: Option<String>) -> Self {
+        debug!("format string: {:?}", tpl);
+        self.tpl = tpl;
+        self
+    }
+
+    /// Capture the output instead of printing it.
+    pub fn captured(mut self) -> Self {
+        self.capture = Some(RefCell::new(String::new()));
+        self
+    }
+
+    /// Take the output captured so far.
+    pub fn take_captured(&self) -> String {
+        self.capture
+            .as_ref()
+            .map(|capture| capture.replace(String::new()))
+            .unwrap_or_default()
+    }
+
+    fn write(&self, text: &str) -> Result<()> {
+        match self.capture {
+            Some(ref capture) => capture.borrow_mut().push_str(text),
+            None => {
+                let mut stdout = io::stdout();
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl OutputServiceInterface for OutputService {
@@ -72,19 +130,51 @@ impl OutputServiceInterface for OutputService {
     /// struct.
     fn print<T: Serialize + fmt::Display>(&self, data: T) -> Result<()> {
         match self.fmt {
-            OutputFmt::Plain => {
-                println!("{}", data)
-            }
-            OutputFmt::Json => {
-                print!("{}", serde_json::to_string(&OutputJson::new(data))?)
-            }
+            OutputFmt::Plain => self.write(&format!("{}\n", data))?,
+            OutputFmt::Json => self.write(&serde_json::to_string(&OutputJson::new(data))?)?,
+            // Listings are split, one item per line.
+            OutputFmt::Ndjson => match serde_json::to_value(&data)? {
+                serde_json::Value::Array(items) => {
+                    for item in items.iter() {
+                        self.print_ndjson(item)?;
+                    }
+                }
+                data => self.print_ndjson(&data)?,
+            },
         };
         Ok(())
     }
 
-    /// Returns true, if the formatting should be json.
+    fn print_items<T: Serialize + fmt::Display + TplItems>(&self, data: T) -> Result<()> {
+        match (&self.fmt, &self.tpl) {
+            (OutputFmt::Plain, Some(tpl)) => {
+                for item in data.tpl_items() {
+                    self.write(&format!("{}\n", output_tpl::render(tpl, item)?))?;
+                }
+                Ok(())
+            }
+            _ => self.print(data),
+        }
+    }
+
+    fn print_ndjson<T: Serialize>(&self, item: &T) -> Result<()> {
+        self.write(&format!("{}\n", serde_json::to_string(item)?))
+    }
+
+    /// Returns true, if the formatting should be json, streamed or not.
     fn is_json(&self) -> bool {
-        self.fmt == OutputFmt::Json
+        self.fmt == OutputFmt::Json || self.is_ndjson()
+    }
+
+    fn is_ndjson(&self) -> bool {
+        self.fmt == OutputFmt::Ndjson
+    }
+
+    /// Progress bars are only drawn for humans, reading the plain output in a terminal.
+    fn progress(&self, label: &str, total: usize) -> Progress {
+        let visible =
+            self.fmt == OutputFmt::Plain && self.capture.is_none() && atty::is(Stream::Stderr);
+        Progress::new(label, total, visible)
     }
 }
 
@@ -92,6 +182,8 @@ impl Default for OutputService {
     fn default() -> Self {
         Self {
             fmt: OutputFmt::Plain,
+            tpl: None,
+            capture: None,
         }
     }
 }
@@ -101,7 +193,11 @@ impl From<&str> for OutputService {
         debug!("init output service");
         debug!("output: `{:?}`", fmt);
         let fmt = fmt.into();
-        Self { fmt }
+        Self {
+            fmt,
+            tpl: None,
+            capture: None,
+        }
     }
 }
 
@@ -112,6 +208,10 @@ impl TryFrom<Option<&str>> for OutputService {
         debug!("init output service");
         debug!("output: `{:?}`", fmt);
         let fmt = fmt.try_into()?;
-        Ok(Self { fmt })
+        Ok(Self {
+            fmt,
+            tpl: None,
+            capture: None,
+        })
     }
 }
@@ -0,0 +1,180 @@
+//! Module related to output templating.
+//!
+//! This module renders listings with a user-defined format string, like
+//! `{id}\t{date:%Y-%m-%d}\t{from:30}\t{subject}`. A placeholder can take a width, which pads or
+//! truncates the value, or a [date format] for date fields. `{{` and `}}` print braces, `\t` and
+//! `\n` print a tab and a new line.
+//!
+//! [date format]: https://docs.rs/chrono/0.4.19/chrono/format/strftime/index.html
+
+use anyhow::{anyhow, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDateTime,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Represents the value of a field.
+pub enum TplValue {
+    Text(String),
+    Date(NaiveDateTime),
+}
+
+/// Represents an item exposing fields to format strings.
+pub trait TplFields {
+    /// Get the value of the given field, `None` when the item has no such field.
+    fn tpl_field(&self, name: &str) -> Option<TplValue>;
+}
+
+/// Represents a listing whose items can be rendered with a format string.
+pub trait TplItems {
+    fn tpl_items(&self) -> Vec<&dyn TplFields>;
+}
+
+/// Pad or truncate the given text so that it takes exactly the given width.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut fitted_width = 0;
+    if UnicodeWidthStr::width(text) > width {
+        for c in text.chars() {
+            let c_width = c.width().unwrap_or_default();
+            if fitted_width + c_width >= width {
+                break;
+            }
+            fitted_width += c_width;
+            fitted.push(c);
+        }
+        if width > 0 {
+            fitted.push('…');
+            fitted_width += 1;
+        }
+    } else {
+        fitted.push_str(text);
+        fitted_width = UnicodeWidthStr::width(text);
+    }
+    fitted.push_str(&" ".repeat(width.saturating_sub(fitted_width)));
+    fitted
+}
+
+/// Render a placeholder, given as `name` or `name:spec`.
+fn render_placeholder(placeholder: &str, item: &dyn TplFields) -> Result<String> {
+    let (name, spec) = match placeholder.split_once(':') {
+        Some((name, spec)) => (name.trim(), Some(spec)),
+        None => (placeholder.trim(), None),
+    };
+    let value = item
+        .tpl_field(name)
+        .ok_or_else(|| anyhow!(r#"cannot render format string: unknown field "{}""#, name))?;
+
+    match (value, spec) {
+        (TplValue::Text(text), None) => Ok(text),
+        (TplValue::Date(date), None) => Ok(date.format("%Y-%m-%d %H:%M:%S").to_string()),
+        (value, Some(spec)) if !spec.is_empty() && spec.chars().all(|c| c.is_ascii_digit()) => {
+            let text = match value {
+                TplValue::Text(text) => text,
+                TplValue::Date(date) => date.format("%Y-%m-%d %H:%M:%S").to_string(),
+            };
+            Ok(fit(&text, spec.parse()?))
+        }
+        (TplValue::Date(date), Some(spec)) => {
+            // Percent signs may be doubled, as in printf-like contexts.
+            let spec = spec.replace("%%", "%");
+            if StrftimeItems::new(&spec).any(|item| item == Item::Error) {
+                return Err(anyhow!(
+                    r#"cannot render format string: invalid date format "{}" for field "{}""#,
+                    spec,
+                    name
+                ));
+            }
+            Ok(date.format(&spec).to_string())
+        }
+        (TplValue::Text(_), Some(spec)) => Err(anyhow!(
+            r#"cannot render format string: invalid width "{}" for field "{}""#,
+            spec,
+            name
+        )),
+    }
+}
+
+/// Render the given item with the given format string.
+pub fn render(tpl: &str, item: &dyn TplFields) -> Result<String> {
+    let mut output = String::new();
+    let mut chars = tpl.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            return Err(anyhow!(
+                                r#"cannot render format string: unclosed placeholder "{{{}""#,
+                                placeholder
+                            ))
+                        }
+                    }
+                }
+                output.push_str(&render_placeholder(&placeholder, item)?);
+            }
+            '\\' => match chars.peek() {
+                Some('t') => {
+                    chars.next();
+                    output.push('\t');
+                }
+                Some('n') => {
+                    chars.next();
+                    output.push('\n');
+                }
+                _ => output.push(c),
+            },
+            c => output.push(c),
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    struct TestItem;
+
+    impl TplFields for TestItem {
+        fn tpl_field(&self, name: &str) -> Option<TplValue> {
+            match name {
+                "id" => Some(TplValue::Text(String::from("42"))),
+                "subject" => Some(TplValue::Text(String::from("Hello world"))),
+                "date" => Some(TplValue::Date(
+                    NaiveDate::from_ymd(2021, 10, 5).and_hms(8, 0, 0),
+                )),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_render_format_strings() {
+        assert_eq!(
+            "42\t2021-10-05\tHello…|{x}",
+            render(r"{id}\t{date:%%Y-%%m-%%d}\t{subject:6}|{{x}}", &TestItem).unwrap()
+        );
+        assert_eq!("42   |", render("{id:5}|", &TestItem).unwrap());
+        assert!(render("{from}", &TestItem).is_err());
+        assert!(render("{subject:abc}", &TestItem).is_err());
+        assert!(render("{id", &TestItem).is_err());
+        assert!(render("{date:%Q}", &TestItem).is_err());
+    }
+}
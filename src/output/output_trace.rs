@@ -0,0 +1,327 @@
+//! Module related to protocol traces.
+//!
+//! The `--trace` flag records the exchanges with the IMAP and SMTP servers, one JSON document
+//! per line, to a file or to the standard error, so that they can be attached to bug reports:
+//!
+//! ```json
+//! {"time":"2021-06-01T10:00:00.123+02:00","proto":"imap","conn":1,"dir":"client","data":"A2 SELECT INBOX"}
+//! ```
+//!
+//! IMAP sessions are traced at the stream level, one document per line sent or received, after
+//! decompression. SMTP sessions are run by lettre: their traces record the steps of the session
+//! instead, as events. Connections are numbered, so that the exchanges of concurrent sessions
+//! can be told apart.
+//!
+//! Credentials are redacted: the arguments of `LOGIN`, including the ones sent as literals, and
+//! the initial responses and challenge responses of `AUTHENTICATE`. SMTP passwords are never recorded. Message contents, however,
+//! are recorded as they are.
+
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+use log::{debug, warn};
+use serde::Serialize;
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Define the longest line recorded, longer ones being truncated.
+const MAX_LINE_LEN: usize = 4096;
+
+/// Define the replacement of redacted credentials.
+const REDACTED: &str = "***";
+
+/// The file or the standard error the traces are written to, once enabled.
+static SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// The number of the last traced connection.
+static CONNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents the direction of a traced exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dir {
+    Client,
+    Server,
+    Event,
+}
+
+/// Represents a traced exchange, as written to the sink.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    time: String,
+    proto: &'a str,
+    conn: usize,
+    dir: Dir,
+    data: &'a str,
+}
+
+/// Enable traces, written to the given file or to the standard error.
+pub fn init(path: Option<&Path>) -> Result<()> {
+    let sink: Box<dyn Write + Send> = match path {
+        Some(path) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .context(format!("cannot open trace file {:?}", path))?,
+        ),
+        None => Box::new(io::stderr()),
+    };
+    debug!("trace protocol exchanges to {:?}", path);
+    *SINK.lock().unwrap() = Some(sink);
+    Ok(())
+}
+
+/// Check if traces are enabled.
+pub fn is_enabled() -> bool {
+    SINK.lock().unwrap().is_some()
+}
+
+/// Write the given exchange to the sink, if traces are enabled.
+fn write(proto: &str, conn: usize, dir: Dir, data: &str) {
+    let mut sink = SINK.lock().unwrap();
+    let sink = match sink.as_mut() {
+        Some(sink) => sink,
+        None => return,
+    };
+    let record = Record {
+        time: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+        proto,
+        conn,
+        dir,
+        data,
+    };
+    let res = serde_json::to_writer(&mut *sink, &record)
+        .map_err(io::Error::from)
+        .and_then(|()| sink.write_all(b"\n"))
+        .and_then(|()| sink.flush());
+    if let Err(err) = res {
+        warn!("cannot write trace: {}", err);
+    }
+}
+
+/// Truncate the given line to the longest line recorded.
+fn truncate(line: &str) -> String {
+    if line.len() <= MAX_LINE_LEN {
+        return line.to_owned();
+    }
+    let mut end = MAX_LINE_LEN;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}… ({} bytes)", &line[..end], line.len())
+}
+
+/// Check if the given IMAP line ends with a literal marker, like `{6}` or `{6+}`.
+fn ends_with_literal(line: &str) -> bool {
+    line.rsplit(' ')
+        .next()
+        .and_then(|word| word.strip_prefix('{'))
+        .and_then(|word| word.strip_suffix('}'))
+        .map(|len| len.trim_end_matches('+'))
+        .map_or(false, |len| {
+            !len.is_empty() && len.chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+/// Represents the trace of a connection.
+#[derive(Debug)]
+pub struct Tracer {
+    proto: &'static str,
+    conn: usize,
+    /// Whether credentials are being sent, the client lines being redacted: the challenge
+    /// responses of an authentication, or the literals of a login.
+    in_credentials: bool,
+    /// Bytes sent, not terminated by a line break yet.
+    sent: Vec<u8>,
+    /// Bytes received, not terminated by a line break yet.
+    received: Vec<u8>,
+}
+
+impl Tracer {
+    /// Start the trace of a new connection of the given protocol, if traces are enabled.
+    pub fn start(proto: &'static str) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+        Some(Self {
+            proto,
+            conn: CONNS.fetch_add(1, Ordering::SeqCst) + 1,
+            in_credentials: false,
+            sent: vec![],
+            received: vec![],
+        })
+    }
+
+    /// Record an event of the connection.
+    pub fn event(&self, data: &str) {
+        write(self.proto, self.conn, Dir::Event, data);
+    }
+
+    /// Record the given line, sent or received.
+    pub fn line(&mut self, dir: Dir, line: &str) {
+        let line = self.redact(dir, line.trim_end_matches(&['\r', '\n'][..]));
+        write(self.proto, self.conn, dir, &truncate(&line));
+    }
+
+    /// Record the given bytes, sent or received, once their lines are complete.
+    fn record(&mut self, dir: Dir, bytes: &[u8]) {
+        let buf = match dir {
+            Dir::Server => &mut self.received,
+            _ => &mut self.sent,
+        };
+        buf.extend_from_slice(bytes);
+        let end = match buf.iter().rposition(|byte| *byte == b'\n') {
+            Some(pos) => pos + 1,
+            None => return,
+        };
+        let lines: Vec<u8> = buf.drain(..end).collect();
+        for line in String::from_utf8_lossy(&lines).split_terminator('\n') {
+            self.line(dir, line);
+        }
+    }
+
+    /// Redact the credentials of the given IMAP line.
+    fn redact(&mut self, dir: Dir, line: &str) -> String {
+        if dir == Dir::Server {
+            // The challenges of an authentication and the literals of a login are preceded by
+            // continuation requests, the credentials are sent until the tagged response.
+            self.in_credentials &= line.starts_with('+');
+            return line.to_owned();
+        }
+        if self.in_credentials {
+            return String::from(REDACTED);
+        }
+        let mut words = line.splitn(3, ' ');
+        match (words.next(), words.next(), words.next()) {
+            (Some(tag), Some(cmd), Some(args)) if cmd.eq_ignore_ascii_case("LOGIN") => {
+                // A literal (`{6}`, or `{6+}` without continuation request) is sent on the
+                // next lines.
+                self.in_credentials = ends_with_literal(args);
+                format!("{} {} {}", tag, cmd, REDACTED)
+            }
+            (Some(tag), Some(cmd), Some(args)) if cmd.eq_ignore_ascii_case("AUTHENTICATE") => {
+                self.in_credentials = true;
+                match args.split_once(' ') {
+                    Some((mechanism, _)) => format!("{} {} {} {}", tag, cmd, mechanism, REDACTED),
+                    None => line.to_owned(),
+                }
+            }
+            _ => line.to_owned(),
+        }
+    }
+}
+
+/// Represents a stream recording the lines going through it, when traced.
+pub struct TraceStream<S> {
+    stream: S,
+    tracer: Option<Tracer>,
+}
+
+impl<S> TraceStream<S> {
+    pub fn new(stream: S, tracer: Option<Tracer>) -> Self {
+        Self { stream, tracer }
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+}
+
+impl<S: Read> Read for TraceStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.stream.read(buf)?;
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.record(Dir::Server, &buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+impl<S: Write> Write for TraceStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.stream.write(buf)?;
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.record(Dir::Client, &buf[..len]);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<S> fmt::Debug for TraceStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceStream")
+            .field("traced", &self.tracer.is_some())
+            .finish()
+    }
+}
+
+impl<S> Drop for TraceStream<S> {
+    fn drop(&mut self) {
+        if let Some(ref tracer) = self.tracer {
+            tracer.event("connection closed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_redact_credentials() {
+        let mut tracer = Tracer {
+            proto: "imap",
+            conn: 1,
+            in_credentials: false,
+            sent: vec![],
+            received: vec![],
+        };
+        let mut redact = |dir, line| tracer.redact(dir, line);
+        assert_eq!(
+            "A1 LOGIN ***",
+            redact(Dir::Client, r#"A1 LOGIN "bob" "s3cr3t""#)
+        );
+        assert_eq!("A2 SELECT INBOX", redact(Dir::Client, "A2 SELECT INBOX"));
+
+        // Challenge responses are redacted until the authentication completes.
+        assert_eq!(
+            "A3 AUTHENTICATE XOAUTH2",
+            redact(Dir::Client, "A3 AUTHENTICATE XOAUTH2")
+        );
+        redact(Dir::Server, "+ ");
+        assert_eq!("***", redact(Dir::Client, "dXNlcj1ib2IBYXV0aD1CZWFyZXI="));
+        redact(Dir::Server, "A3 OK authenticated");
+        assert_eq!("A4 NOOP", redact(Dir::Client, "A4 NOOP"));
+
+        assert_eq!(
+            "A5 AUTHENTICATE PLAIN ***",
+            redact(Dir::Client, "A5 AUTHENTICATE PLAIN AGJvYgBzM2NyM3Q=")
+        );
+
+        // Literals of a login are redacted until the tagged response.
+        assert_eq!("A6 LOGIN ***", redact(Dir::Client, "A6 LOGIN {3}"));
+        redact(Dir::Server, "+ Ready for literal data");
+        assert_eq!("***", redact(Dir::Client, "bob {6}"));
+        redact(Dir::Server, "+ Ready for literal data");
+        assert_eq!("***", redact(Dir::Client, "s3cr3t"));
+        redact(Dir::Server, "A6 OK logged in");
+        assert_eq!("A7 NOOP", redact(Dir::Client, "A7 NOOP"));
+        assert!(ends_with_literal(r#""bob" {6+}"#));
+        assert!(!ends_with_literal("bob pa}"));
+
+        let long = "x".repeat(MAX_LINE_LEN + 1);
+        assert!(truncate(&long).ends_with(&format!("… ({} bytes)", MAX_LINE_LEN + 1)));
+    }
+}
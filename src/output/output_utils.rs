@@ -1,5 +1,8 @@
-use anyhow::Result;
-use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+use std::{
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+};
 
 pub fn run_cmd(cmd: &str) -> Result<String> {
     let output = if cfg!(target_os = "windows") {
@@ -10,3 +13,78 @@ pub fn run_cmd(cmd: &str) -> Result<String> {
 
     Ok(String::from_utf8(output.stdout)?)
 }
+
+/// Run the given command with the given input on its standard input, returning its standard
+/// output. Fails if the command does not exit successfully.
+pub fn pipe_cmd(cmd: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = if cfg!(target_os = "windows") {
+        Command::new("cmd")
+            .args(&["/C", cmd])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+    }
+    .context(format!(r#"cannot run cmd "{}""#, cmd))?;
+
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!(r#"cannot open stdin of cmd "{}""#, cmd))?
+        .write_all(input)
+        .context(format!(r#"cannot write to stdin of cmd "{}""#, cmd))?;
+
+    let output = child
+        .wait_with_output()
+        .context(format!(r#"cannot wait for cmd "{}""#, cmd))?;
+    if !output.status.success() {
+        return Err(anyhow!(r#"cmd "{}" exited with {}"#, cmd, output.status));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Run the given command with the given input on its standard input, leaving its output on the
+/// terminal. The command may stop reading its input early (eg. `head`).
+pub fn stream_cmd(cmd: &str, input: &[u8]) -> Result<()> {
+    let mut child = if cfg!(target_os = "windows") {
+        Command::new("cmd")
+            .args(&["/C", cmd])
+            .stdin(Stdio::piped())
+            .spawn()
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::piped())
+            .spawn()
+    }
+    .context(format!(r#"cannot run cmd "{}""#, cmd))?;
+
+    let written = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!(r#"cannot open stdin of cmd "{}""#, cmd))?
+        .write_all(input);
+    match written {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            return Err(err).context(format!(r#"cannot write to stdin of cmd "{}""#, cmd));
+        }
+        _ => (),
+    }
+
+    let status = child
+        .wait()
+        .context(format!(r#"cannot wait for cmd "{}""#, cmd))?;
+    if !status.success() {
+        return Err(anyhow!(r#"cmd "{}" exited with {}"#, cmd, status));
+    }
+
+    Ok(())
+}
@@ -90,3 +90,173 @@ pub fn post_edit() -> Result<PostEditChoice> {
         }
     }
 }
+
+pub enum SendAnywayChoice {
+    Send,
+    Edit,
+}
+
+pub fn send_anyway() -> Result<SendAnywayChoice> {
+    print!("(s)end anyway or (e)dit? ");
+    io::stdout().flush().context("cannot flush stdout")?;
+
+    let mut buf = String::new();
+    io::stdin()
+        .read_line(&mut buf)
+        .context("cannot read stdin")?;
+
+    match buf.bytes().next().map(|bytes| bytes as char) {
+        Some('s') => {
+            debug!("send anyway choice matched");
+            Ok(SendAnywayChoice::Send)
+        }
+        Some('e') => {
+            debug!("edit choice matched");
+            Ok(SendAnywayChoice::Edit)
+        }
+        Some(choice) => {
+            error!(r#"invalid choice "{}""#, choice);
+            Err(anyhow!(r#"invalid choice "{}""#, choice))
+        }
+        None => {
+            error!("empty choice");
+            Err(anyhow!("empty choice"))
+        }
+    }
+}
+
+pub enum PickedMsgChoice {
+    Read,
+    Reply,
+    Delete,
+    Move,
+    Quit,
+}
+
+pub fn picked_msg() -> Result<PickedMsgChoice> {
+    print!("(r)ead, r(e)ply, (d)elete, (m)ove or (q)uit? ");
+    io::stdout().flush().context("cannot flush stdout")?;
+
+    let mut buf = String::new();
+    io::stdin()
+        .read_line(&mut buf)
+        .context("cannot read stdin")?;
+
+    match buf.bytes().next().map(|bytes| bytes as char) {
+        Some('r') => {
+            debug!("read choice matched");
+            Ok(PickedMsgChoice::Read)
+        }
+        Some('e') => {
+            debug!("reply choice matched");
+            Ok(PickedMsgChoice::Reply)
+        }
+        Some('d') => {
+            debug!("delete choice matched");
+            Ok(PickedMsgChoice::Delete)
+        }
+        Some('m') => {
+            debug!("move choice matched");
+            Ok(PickedMsgChoice::Move)
+        }
+        Some('q') => {
+            debug!("quit choice matched");
+            Ok(PickedMsgChoice::Quit)
+        }
+        Some(choice) => {
+            error!(r#"invalid choice "{}""#, choice);
+            Err(anyhow!(r#"invalid choice "{}""#, choice))
+        }
+        None => {
+            error!("empty choice");
+            Err(anyhow!("empty choice"))
+        }
+    }
+}
+
+pub fn target_mbox() -> Result<String> {
+    print!("Target folder: ");
+    io::stdout().flush().context("cannot flush stdout")?;
+
+    let mut buf = String::new();
+    io::stdin()
+        .read_line(&mut buf)
+        .context("cannot read stdin")?;
+
+    match buf.trim() {
+        "" => {
+            error!("empty target folder");
+            Err(anyhow!("empty target folder"))
+        }
+        target => Ok(target.to_owned()),
+    }
+}
+
+pub enum ReadReceiptChoice {
+    Send,
+    Ignore,
+}
+
+pub fn read_receipt(to: &str) -> Result<ReadReceiptChoice> {
+    println!("{} asks for a read receipt:", to);
+    print!("(s)end or (i)gnore? ");
+    io::stdout().flush().context("cannot flush stdout")?;
+
+    let mut buf = String::new();
+    io::stdin()
+        .read_line(&mut buf)
+        .context("cannot read stdin")?;
+
+    match buf.bytes().next().map(|bytes| bytes as char) {
+        Some('s') => {
+            debug!("send receipt choice matched");
+            Ok(ReadReceiptChoice::Send)
+        }
+        Some('i') => {
+            debug!("ignore receipt choice matched");
+            Ok(ReadReceiptChoice::Ignore)
+        }
+        Some(choice) => {
+            error!(r#"invalid choice "{}""#, choice);
+            Err(anyhow!(r#"invalid choice "{}""#, choice))
+        }
+        None => {
+            error!("empty choice");
+            Err(anyhow!("empty choice"))
+        }
+    }
+}
+
+pub enum FixAddrsChoice {
+    Edit,
+    Quit,
+}
+
+pub fn fix_addrs() -> Result<FixAddrsChoice> {
+    print!("(e)dit or (q)uit, keeping the draft? ");
+    io::stdout().flush().context("cannot flush stdout")?;
+
+    let mut buf = String::new();
+    io::stdin()
+        .read_line(&mut buf)
+        .context("cannot read stdin")?;
+
+    match buf.bytes().next().map(|bytes| bytes as char) {
+        Some('e') => {
+            debug!("edit choice matched");
+            Ok(FixAddrsChoice::Edit)
+        }
+        Some('q') => {
+            debug!("quit choice matched");
+            Ok(FixAddrsChoice::Quit)
+        }
+        Some(choice) => {
+            error!(r#"invalid choice "{}""#, choice);
+            Err(anyhow!(r#"invalid choice "{}""#, choice))
+        }
+        None => {
+            error!("empty choice");
+            Err(anyhow!("empty choice"))
+        }
+    }
+}
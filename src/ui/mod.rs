@@ -2,6 +2,15 @@
 
 pub mod choice;
 pub mod editor;
+#[cfg(feature = "cli")]
+pub mod picker;
+#[cfg(feature = "cli")]
+pub mod prompt;
 
 pub mod table;
 pub use table::*;
+
+pub mod theme;
+
+#[cfg(feature = "cli")]
+pub mod tui;
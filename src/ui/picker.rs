@@ -0,0 +1,156 @@
+//! Module related to the interactive picker.
+//!
+//! This module provides a fuzzy selector, drawn on stderr so that the output of the action
+//! triggered on the picked item can still be piped.
+
+use anyhow::{Context, Result};
+use crossterm::{
+    cursor::{MoveTo, Show},
+    event::{self, Event, KeyCode, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use log::{debug, trace};
+use std::io::{self, Write};
+
+/// Score how well the query matches the item. The query characters must appear in order in the
+/// item, case-insensitively. Lower scores are better, `None` means no match.
+fn fuzzy_score(query: &str, item: &str) -> Option<usize> {
+    let item = item.to_lowercase();
+    let mut chars = item.chars().enumerate();
+    let mut score = 0;
+    let mut last = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let (i, _) = chars.find(|(_, c)| *c == q)?;
+        if let Some(last) = last {
+            score += i - last - 1;
+        }
+        last = Some(i);
+    }
+    Some(score)
+}
+
+/// Get the indexes of the items matching the query, best matches first.
+fn filter(query: &str, items: &[String]) -> Vec<usize> {
+    let mut matches: Vec<(usize, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_score(query, item).map(|score| (score, i)))
+        .collect();
+    matches.sort();
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+fn draw(query: &str, items: &[String], matches: &[usize], cursor: usize) -> Result<()> {
+    let (width, height) = terminal::size().context("cannot get terminal size")?;
+    let mut out = io::stderr();
+    queue!(
+        out,
+        Clear(ClearType::All),
+        MoveTo(0, 0),
+        Print(format!("> {}", query)),
+        MoveTo(0, 1),
+        Print(format!("  {}/{}", matches.len(), items.len()))
+    )
+    .context("cannot draw picker")?;
+
+    // Keep the selected item visible.
+    let rows = (height as usize).saturating_sub(2).max(1);
+    let offset = cursor.saturating_sub(rows - 1);
+    for (row, i) in matches.iter().skip(offset).take(rows).enumerate() {
+        let item: String = items[*i]
+            .chars()
+            .take((width as usize).saturating_sub(2))
+            .collect();
+        queue!(out, MoveTo(0, row as u16 + 2)).context("cannot draw picker")?;
+        if row + offset == cursor {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", item)),
+                SetAttribute(Attribute::Reset)
+            )
+            .context("cannot draw picker")?;
+        } else {
+            queue!(out, Print(format!("  {}", item))).context("cannot draw picker")?;
+        }
+    }
+
+    queue!(out, MoveTo(query.chars().count() as u16 + 2, 0)).context("cannot draw picker")?;
+    out.flush().context("cannot flush stderr")
+}
+
+fn read_pick(items: &[String]) -> Result<Option<usize>> {
+    let mut query = String::new();
+    let mut matches = filter(&query, items);
+    let mut cursor = 0;
+
+    loop {
+        draw(&query, items, &matches, cursor)?;
+        let key = match event::read().context("cannot read terminal event")? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        trace!("key: {:?}", key);
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if ctrl => return Ok(None),
+            KeyCode::Enter => return Ok(matches.get(cursor).cloned()),
+            KeyCode::Down => cursor = (cursor + 1).min(matches.len().saturating_sub(1)),
+            KeyCode::Char('n') if ctrl => {
+                cursor = (cursor + 1).min(matches.len().saturating_sub(1))
+            }
+            KeyCode::Up => cursor = cursor.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => cursor = cursor.saturating_sub(1),
+            KeyCode::Backspace => {
+                query.pop();
+                matches = filter(&query, items);
+                cursor = 0;
+            }
+            KeyCode::Char(c) => {
+                query.push(c);
+                matches = filter(&query, items);
+                cursor = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Let the user fuzzy-pick one of the given items. Returns the index of the picked item, or
+/// `None` when cancelled.
+pub fn pick(items: &[String]) -> Result<Option<usize>> {
+    terminal::enable_raw_mode().context("cannot enable terminal raw mode")?;
+    execute!(io::stderr(), EnterAlternateScreen).context("cannot enter alternate screen")?;
+
+    let picked = read_pick(items);
+
+    execute!(io::stderr(), Show, LeaveAlternateScreen).context("cannot leave alternate screen")?;
+    terminal::disable_raw_mode().context("cannot disable terminal raw mode")?;
+    debug!("picked item: {:?}", picked.as_ref().ok());
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_fuzzy_filter_items() {
+        assert_eq!(Some(0), fuzzy_score("", "hello"));
+        assert_eq!(Some(0), fuzzy_score("HEL", "hello"));
+        assert_eq!(Some(2), fuzzy_score("hlo", "hello"));
+        assert_eq!(None, fuzzy_score("olh", "hello"));
+
+        let items = vec![
+            String::from("weekly report"),
+            String::from("invoice"),
+            String::from("rep: welcome"),
+        ];
+        assert_eq!(vec![0, 2], filter("rep", &items));
+        assert_eq!(vec![2, 0], filter("wel", &items));
+    }
+}
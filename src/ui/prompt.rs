@@ -0,0 +1,51 @@
+//! Module related to secret prompts.
+
+use anyhow::{anyhow, Context, Result};
+use atty::Stream;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyModifiers},
+    terminal,
+};
+use std::io::{self, BufRead, Write};
+
+fn read_hidden() -> Result<String> {
+    let mut passwd = String::new();
+    loop {
+        let key = match event::read().context("cannot read terminal event")? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Enter => return Ok(passwd),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(anyhow!("password prompt cancelled"))
+            }
+            KeyCode::Backspace => {
+                passwd.pop();
+            }
+            KeyCode::Char(c) => passwd.push(c),
+            _ => (),
+        }
+    }
+}
+
+/// Ask for a password without echoing it. When stdin is not a terminal, the password is read
+/// from its first line.
+pub fn passwd(question: &str) -> Result<String> {
+    if !atty::is(Stream::Stdin) {
+        let mut buf = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut buf)
+            .context("cannot read stdin")?;
+        return Ok(buf.trim_end_matches(|c| c == '\r' || c == '\n').to_owned());
+    }
+
+    eprint!("{}: ", question);
+    io::stderr().flush().context("cannot flush stderr")?;
+    terminal::enable_raw_mode().context("cannot enable terminal raw mode")?;
+    let passwd = read_hidden();
+    terminal::disable_raw_mode().context("cannot disable terminal raw mode")?;
+    eprintln!();
+    passwd
+}
@@ -5,10 +5,12 @@
 //! [builder design pattern]: https://refactoring.guru/design-patterns/builder
 
 use log::trace;
-use std::fmt;
+use std::{borrow::Cow, fmt};
 use terminal_size;
 use unicode_width::UnicodeWidthStr;
 
+use crate::ui::theme;
+
 /// Define the default terminal size.
 /// It is used when the size cannot be determined by the `terminal_size` crate.
 pub const DEFAULT_TERM_WIDTH: usize = 80;
@@ -17,17 +19,33 @@ pub const DEFAULT_TERM_WIDTH: usize = 80;
 /// TODO: make this customizable.
 pub const MAX_SHRINK_WIDTH: usize = 5;
 
+/// Unicode isolate marks, keeping right-to-left text from reordering the rest of the row.
+const FIRST_STRONG_ISOLATE: char = '\u{2068}';
+const POP_DIRECTIONAL_ISOLATE: char = '\u{2069}';
+
+/// Check if the given character is a directional formatting character. Left unbalanced in a
+/// cell, they would reorder the rest of the row.
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Check if the given character belongs to a right-to-left script (Hebrew, Arabic, Syriac,
+/// Thaana, N'Ko…).
+fn is_rtl(c: char) -> bool {
+    matches!(c, '\u{0590}'..='\u{08ff}' | '\u{fb1d}'..='\u{fdff}' | '\u{fe70}'..='\u{feff}')
+}
+
 /// Wrapper around [ANSI escape codes] for styling cells.
 ///
 /// [ANSI escape codes]: https://en.wikipedia.org/wiki/ANSI_escape_code
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Style(
     /// The style/color code.
-    u8,
+    pub u8,
     /// The brightness code.
-    u8,
+    pub u8,
     /// The shade code.
-    u8,
+    pub u8,
 );
 
 impl fmt::Display for Style {
@@ -69,7 +87,12 @@ impl Cell {
     pub fn new<T: AsRef<str>>(value: T) -> Self {
         Self {
             styles: Vec::new(),
-            value: String::from(value.as_ref()).replace(&['\r', '\n', '\t'][..], ""),
+            // Control characters would break the layout, or even style the terminal.
+            value: value
+                .as_ref()
+                .chars()
+                .filter(|c| !c.is_control() && !is_bidi_control(*c))
+                .collect(),
             shrinkable: false,
         }
     }
@@ -106,6 +129,30 @@ impl Cell {
         }
     }
 
+    /// Replace the styles of the cell by the ones of the given theme element, if the theme
+    /// defines them.
+    pub fn themed(mut self, element: &str) -> Self {
+        if let Some(styles) = theme::styles(element) {
+            self.styles = styles;
+        }
+        self
+    }
+
+    /// Apply the styles of the given theme element to the cell conditionally, or the given
+    /// default ones if the theme does not define them.
+    pub fn themed_if(mut self, predicate: bool, element: &str, default: fn(Self) -> Self) -> Self {
+        if !predicate {
+            return self;
+        }
+        match theme::styles(element) {
+            Some(styles) => {
+                self.styles.extend(styles);
+                self
+            }
+            None => default(self),
+        }
+    }
+
     /// Apply the underline style to the cell.
     pub fn underline(mut self) -> Self {
         self.styles.push(Style(4, 0, 0));
@@ -151,13 +198,27 @@ impl Cell {
 
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.styles.is_empty() {
-            write!(f, "{}", self.value)?;
+        // Right-to-left text is isolated, its padding left out so that it stays on the right.
+        let value = if self.value.chars().any(is_rtl) {
+            let text = self.value.trim_end_matches(' ');
+            Cow::Owned(format!(
+                "{}{}{}{}",
+                FIRST_STRONG_ISOLATE,
+                text,
+                POP_DIRECTIONAL_ISOLATE,
+                &self.value[text.len()..]
+            ))
+        } else {
+            Cow::Borrowed(self.value.as_str())
+        };
+
+        if self.styles.is_empty() || !theme::colors_enabled() {
+            write!(f, "{}", value)?;
         } else {
             for style in &self.styles {
                 write!(f, "{}", style)?;
             }
-            write!(f, "{}", self.value)?;
+            write!(f, "{}", value)?;
             // Apply the reset style in order to avoid style overlapping between cells.
             write!(f, "{}", Style(0, 0, 0))?;
         }
@@ -204,7 +265,14 @@ where
     /// Apply styles to cells and return a list of list of printable styled cells.
     /// TODO: find a way to build an unstyled version of cells.
     fn build(items: &[Self]) -> Vec<Vec<String>> {
-        let mut table = vec![Self::head()];
+        Self::build_with_head(Self::head(), items)
+    }
+
+    /// Same as [`Table::build`], with the given head instead of the one of the table, for
+    /// tables whose columns are only known at runtime.
+    fn build_with_head(head: Row, items: &[Self]) -> Vec<Vec<String>> {
+        let head = head.0.into_iter().map(|cell| cell.themed("header"));
+        let mut table = vec![Row(head.collect())];
         let mut cell_widths: Vec<usize> =
             table[0].0.iter().map(|cell| cell.unicode_width()).collect();
         table.extend(
@@ -303,11 +371,15 @@ where
 
     /// Render the final printable table as a string.
     fn render(items: &[Self]) -> String {
-        Self::build(items)
+        Self::render_with_head(Self::head(), items)
+    }
+
+    /// Same as [`Table::render`], with the given head instead of the one of the table.
+    fn render_with_head(head: Row, items: &[Self]) -> String {
+        Self::build_with_head(head, items)
             .iter()
-            // Join cells with grey pipes.
-            // TODO: make this customizable
-            .map(|row| row.join(&Cell::new("│").ext(8).to_string()))
+            // Join cells with grey pipes, unless the theme styles them.
+            .map(|row| row.join(&Cell::new("│").ext(8).themed("separator").to_string()))
             .collect::<Vec<_>>()
             .join("\n")
     }
@@ -433,6 +505,24 @@ mod tests {
         assert_eq!(table, Table::build(&items));
     }
 
+    #[test]
+    fn wide_and_rtl_chars() {
+        let items = vec![
+            Item::new(1, "日本語", "desc"),
+            Item::new(2, "שלום", "desc"),
+            Item::new(3, "a\u{202e}b\x1b[31m", "desc"),
+        ];
+
+        let table = vec![
+            vec!["ID ", "NAME   ", "DESC "],
+            vec!["1  ", "日本語 ", "desc "],
+            vec!["2  ", "\u{2068}שלום\u{2069}   ", "desc "],
+            vec!["3  ", "ab[31m ", "desc "],
+        ];
+
+        assert_eq!(table, Table::build(&items));
+    }
+
     #[test]
     fn max_shrink_width() {
         let items = vec![
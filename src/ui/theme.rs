@@ -0,0 +1,145 @@
+//! Module related to the color theme of tables.
+//!
+//! The `theme` config section styles the elements of tables, each element taking a list of
+//! attributes and colors separated by spaces:
+//!
+//! ```toml
+//! [theme]
+//! header = "bold underline cyan"
+//! unseen = "bold bright-white"
+//! flagged = "red"
+//! sender = "italic 208"
+//! ```
+//!
+//! Attributes are `bold`, `dim`, `italic`, `underline` and `reverse`. Colors are the 8 ANSI
+//! ones (`black`, `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`), their
+//! `bright-` variants, or a number of the 256-color palette. Elements left out keep their
+//! default style.
+//!
+//! Colors are disabled by the `--no-color` flag or by the [NO_COLOR] env var, for piping.
+//!
+//! [NO_COLOR]: https://no-color.org
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use crate::ui::table::Style;
+
+/// Define the elements of tables the theme can style.
+pub const ELEMENTS: &[&str] = &[
+    "header",
+    "separator",
+    "id",
+    "flags",
+    "flagged",
+    "subject",
+    "sender",
+    "date",
+    "size",
+    "labels",
+    "unseen",
+];
+
+/// Define the colors, in the order of their ANSI codes.
+const COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// The styles of the theme elements, once loaded from the config.
+static THEME: Mutex<Option<HashMap<String, Vec<Style>>>> = Mutex::new(None);
+
+/// Whether colors are disabled.
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Parse the given style, made of attributes and colors separated by spaces.
+fn parse_style(style: &str) -> Result<Vec<Style>> {
+    style
+        .split_whitespace()
+        .map(|word| {
+            let color = |name: &str| COLORS.iter().position(|color| *color == name);
+            match word {
+                "bold" => Ok(Style(1, 0, 0)),
+                "dim" => Ok(Style(2, 0, 0)),
+                "italic" => Ok(Style(3, 0, 0)),
+                "underline" => Ok(Style(4, 0, 0)),
+                "reverse" => Ok(Style(7, 0, 0)),
+                word => {
+                    if let Some(code) = color(word) {
+                        return Ok(Style(30 + code as u8, 0, 0));
+                    }
+                    if let Some(code) = word.strip_prefix("bright-").and_then(color) {
+                        return Ok(Style(90 + code as u8, 0, 0));
+                    }
+                    word.parse::<u8>()
+                        .map(|shade| Style(38, 5, shade))
+                        .map_err(|_| anyhow!(r#"cannot parse theme style "{}""#, word))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Load the theme from the `theme` config section.
+pub fn init(theme: &HashMap<String, String>) -> Result<()> {
+    let mut styles = HashMap::new();
+    for (element, style) in theme {
+        if !ELEMENTS.contains(&element.as_str()) {
+            return Err(anyhow!(
+                r#"cannot find theme element "{}", expected one of {}"#,
+                element,
+                ELEMENTS.join(", ")
+            ));
+        }
+        styles.insert(element.to_owned(), parse_style(style)?);
+    }
+    debug!("theme: {:?}", styles);
+    *THEME.lock().unwrap() = Some(styles);
+    Ok(())
+}
+
+/// Disable colors and attributes in tables.
+pub fn disable_colors() {
+    debug!("disable colors");
+    NO_COLOR.store(true, Ordering::Relaxed);
+}
+
+/// Check if tables are styled.
+pub fn colors_enabled() -> bool {
+    !NO_COLOR.load(Ordering::Relaxed)
+}
+
+/// Get the styles of the given element, if the theme defines them.
+pub fn styles(element: &str) -> Option<Vec<Style>> {
+    THEME.lock().unwrap().as_ref()?.get(element).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_styles() {
+        let codes = |style| {
+            parse_style(style)
+                .unwrap()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("")
+        };
+        assert_eq!("\x1b[1m\x1b[4m\x1b[36m", codes("bold underline cyan"));
+        assert_eq!("\x1b[97m\x1b[38;5;208m", codes("bright-white 208"));
+        assert!(parse_style("blinking").is_err());
+
+        let mut theme = HashMap::new();
+        theme.insert(String::from("subjet"), String::from("red"));
+        assert!(init(&theme).is_err());
+    }
+}
@@ -0,0 +1,11 @@
+//! Module related to the interactive TUI.
+//!
+//! This module provides a full-screen interface with a mailbox sidebar, an envelope list and a
+//! message pager. Its keybindings trigger the existing handlers.
+
+pub mod tui_arg;
+pub mod tui_handler;
+pub mod tui_position;
+
+pub mod tui_entity;
+pub use tui_entity::*;
@@ -0,0 +1,30 @@
+//! Module related to TUI CLI.
+//!
+//! This module provides subcommands and a command matcher related to the interactive TUI.
+
+use anyhow::Result;
+use clap::{self, App, ArgMatches, SubCommand};
+use log::debug;
+
+/// TUI commands.
+pub enum Command {
+    /// Start the interactive TUI.
+    Start,
+}
+
+/// TUI command matcher.
+pub fn matches(m: &ArgMatches) -> Result<Option<Command>> {
+    if m.subcommand_matches("tui").is_some() {
+        debug!("tui command matched");
+        return Ok(Some(Command::Start));
+    }
+
+    Ok(None)
+}
+
+/// TUI subcommands.
+pub fn subcmds<'a>() -> Vec<App<'a, 'a>> {
+    vec![SubCommand::with_name("tui")
+        .about("Starts the interactive full-screen interface")
+        .long_about("Starts the interactive full-screen interface, with a mailbox sidebar, an envelope list and a message pager. Press `?` once started to list the keybindings.")]
+}
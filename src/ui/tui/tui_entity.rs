@@ -0,0 +1,315 @@
+//! Module related to the TUI state.
+//!
+//! This module exposes the state of the interactive TUI and how it is drawn on the terminal.
+
+use anyhow::{Context, Result};
+use crossterm::{
+    cursor::MoveTo,
+    queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{Clear, ClearType},
+};
+use std::io::Write;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::domain::msg::{Envelope, Envelopes, Flag};
+
+/// Define the maximum width of the mailbox sidebar.
+const MAX_SIDEBAR_WIDTH: usize = 24;
+
+/// Define the width of the sender column of the envelope list.
+const SENDER_WIDTH: usize = 20;
+
+/// Define the width of the date column of the envelope list.
+const DATE_WIDTH: usize = 16;
+
+/// Keybindings shown by the help screen.
+pub const KEYBINDINGS: &[(&str, &str)] = &[
+    ("q, esc", "close the message or quit"),
+    ("tab", "switch between the mailboxes and the envelopes"),
+    (
+        "j, k, up, down",
+        "select the next or previous item, scroll the message",
+    ),
+    ("n, p, pgdown, pgup", "show the next or previous page"),
+    ("enter", "open the selected mailbox or message"),
+    ("g", "refresh the envelopes"),
+    ("w", "write a new message"),
+    ("r, R", "reply, reply all to the selected message"),
+    ("f", "forward the selected message"),
+    ("d", "delete the selected message"),
+    ("F", "flag or unflag the selected message"),
+    ("m", "move the selected message to another mailbox"),
+    ("?", "show this help"),
+];
+
+/// Represents the part of the TUI receiving the keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    Mboxes,
+    Envelopes,
+    Pager,
+}
+
+/// Represents the state of the TUI.
+#[derive(Debug)]
+pub struct TuiState {
+    pub account: String,
+    pub mboxes: Vec<String>,
+    /// The index of the selected mailbox in the sidebar.
+    pub mbox_cursor: usize,
+    /// The index of the mailbox whose envelopes are listed.
+    pub mbox_current: usize,
+    pub envelopes: Envelopes,
+    /// The index of the selected envelope.
+    pub cursor: usize,
+    /// The page of envelopes, starting at 0.
+    pub page: usize,
+    pub pager: Vec<String>,
+    pub pager_offset: usize,
+    /// The read position key of the message shown in the pager, if any.
+    pub pager_key: Option<String>,
+    pub focus: Focus,
+    /// The message shown in the status line.
+    pub status: String,
+}
+
+/// Truncate or pad the given text so that it takes exactly the given width.
+fn fit(text: &str, width: usize) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let text_width = UnicodeWidthStr::width(text.as_str());
+
+    let (mut fitted, mut fitted_width) = (String::new(), 0);
+    if text_width <= width {
+        fitted = text;
+        fitted_width = text_width;
+    } else if width > 0 {
+        for c in text.chars() {
+            let c_width = c.width().unwrap_or_default();
+            if fitted_width + c_width >= width {
+                break;
+            }
+            fitted_width += c_width;
+            fitted.push(c);
+        }
+        fitted.push('…');
+        fitted_width += 1;
+    }
+
+    fitted.push_str(&" ".repeat(width.saturating_sub(fitted_width)));
+    fitted
+}
+
+impl TuiState {
+    pub fn new(account: &str, mboxes: Vec<String>, mbox_current: usize) -> Self {
+        Self {
+            account: account.to_owned(),
+            mboxes,
+            mbox_cursor: mbox_current,
+            mbox_current,
+            envelopes: Envelopes::default(),
+            cursor: 0,
+            page: 0,
+            pager: vec![],
+            pager_offset: 0,
+            pager_key: None,
+            focus: Focus::Envelopes,
+            status: String::from("Press ? for help"),
+        }
+    }
+
+    /// Get the envelope under the cursor, if any.
+    pub fn selected(&self) -> Option<&Envelope> {
+        self.envelopes.get(self.cursor)
+    }
+
+    /// Get the name of the mailbox whose envelopes are listed.
+    pub fn mbox(&self) -> &str {
+        &self.mboxes[self.mbox_current]
+    }
+
+    /// Move the cursor of the focused part by the given offset.
+    pub fn scroll(&mut self, offset: isize, height: usize) {
+        let shift = |pos: usize, len: usize| {
+            let pos = pos as isize + offset;
+            pos.max(0).min(len.saturating_sub(1) as isize) as usize
+        };
+        match self.focus {
+            Focus::Mboxes => self.mbox_cursor = shift(self.mbox_cursor, self.mboxes.len()),
+            Focus::Envelopes => self.cursor = shift(self.cursor, self.envelopes.len()),
+            Focus::Pager => {
+                // The last page of the message stays fully visible.
+                let len = self.pager.len().saturating_sub(height) + 1;
+                self.pager_offset = shift(self.pager_offset, len)
+            }
+        }
+    }
+
+    /// Show the given text in the pager.
+    pub fn open_pager(&mut self, text: &str) {
+        self.pager = text.lines().map(String::from).collect();
+        self.pager_offset = 0;
+        self.pager_key = None;
+        self.focus = Focus::Pager;
+    }
+
+    /// Show the given message text in the pager, at the given offset.
+    pub fn open_msg_pager(&mut self, text: &str, key: String, offset: usize, height: usize) {
+        self.open_pager(text);
+        self.pager_offset = offset.min(self.pager.len().saturating_sub(height));
+        self.pager_key = Some(key);
+    }
+
+    /// Tell if the end of the message shown in the pager is visible.
+    pub fn pager_finished(&self, height: usize) -> bool {
+        self.pager_offset + height >= self.pager.len()
+    }
+
+    /// Replace the listed envelopes, keeping the cursor in range.
+    pub fn set_envelopes(&mut self, envelopes: Envelopes) {
+        self.envelopes = envelopes;
+        self.cursor = self.cursor.min(self.envelopes.len().saturating_sub(1));
+    }
+
+    /// Get the height of the body, between the title and the status lines.
+    pub fn body_height(height: u16) -> usize {
+        (height as usize).saturating_sub(2).max(1)
+    }
+
+    /// Draw the whole TUI on the given terminal.
+    pub fn draw<W: Write>(&self, out: &mut W, width: u16, height: u16) -> Result<()> {
+        let width = width as usize;
+        let body_height = Self::body_height(height);
+
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0)).context("cannot draw TUI")?;
+        let title = format!(
+            " {} — {} — page {}",
+            self.account,
+            self.mbox(),
+            self.page + 1
+        );
+        queue!(
+            out,
+            SetAttribute(Attribute::Reverse),
+            Print(fit(&title, width)),
+            SetAttribute(Attribute::Reset)
+        )
+        .context("cannot draw TUI title")?;
+
+        if self.focus == Focus::Pager {
+            for (i, line) in self
+                .pager
+                .iter()
+                .skip(self.pager_offset)
+                .take(body_height)
+                .enumerate()
+            {
+                queue!(out, MoveTo(0, i as u16 + 1), Print(fit(line, width)))
+                    .context("cannot draw TUI pager")?;
+            }
+        } else {
+            let sidebar_width = self
+                .mboxes
+                .iter()
+                .map(|mbox| mbox.chars().count() + 2)
+                .max()
+                .unwrap_or_default()
+                .min(MAX_SIDEBAR_WIDTH)
+                .min(width / 3);
+            for row in 0..body_height {
+                queue!(out, MoveTo(0, row as u16 + 1)).context("cannot draw TUI")?;
+
+                // Keep the selected mailbox visible when the sidebar overflows.
+                let mbox_offset = self.mbox_cursor.saturating_sub(body_height - 1);
+                if let Some(mbox) = self.mboxes.get(row + mbox_offset) {
+                    let i = row + mbox_offset;
+                    let marker = if i == self.mbox_current { "▸" } else { " " };
+                    if i == self.mbox_cursor && self.focus == Focus::Mboxes {
+                        queue!(out, SetAttribute(Attribute::Reverse)).context("cannot draw TUI")?;
+                    }
+                    queue!(
+                        out,
+                        Print(fit(&format!("{}{}", marker, mbox), sidebar_width)),
+                        SetAttribute(Attribute::Reset)
+                    )
+                    .context("cannot draw TUI sidebar")?;
+                } else {
+                    queue!(out, Print(" ".repeat(sidebar_width))).context("cannot draw TUI")?;
+                }
+                queue!(
+                    out,
+                    SetForegroundColor(Color::DarkGrey),
+                    Print("│"),
+                    ResetColor
+                )
+                .context("cannot draw TUI")?;
+
+                if let Some(envelope) = self.envelopes.get(row) {
+                    self.draw_envelope(
+                        out,
+                        envelope,
+                        row,
+                        width.saturating_sub(sidebar_width + 1),
+                    )?;
+                }
+            }
+        }
+
+        queue!(
+            out,
+            MoveTo(0, height.saturating_sub(1)),
+            SetAttribute(Attribute::Reverse),
+            Print(fit(&format!(" {}", self.status), width)),
+            SetAttribute(Attribute::Reset)
+        )
+        .context("cannot draw TUI status")?;
+        out.flush().context("cannot flush TUI")
+    }
+
+    fn draw_envelope<W: Write>(
+        &self,
+        out: &mut W,
+        envelope: &Envelope,
+        row: usize,
+        width: usize,
+    ) -> Result<()> {
+        let flags = envelope.flags.to_symbols_string();
+        let flags_width = flags.chars().count();
+        let subject_width = width.saturating_sub(flags_width + SENDER_WIDTH + DATE_WIDTH + 4);
+
+        if row == self.cursor && self.focus == Focus::Envelopes {
+            queue!(out, SetAttribute(Attribute::Reverse)).context("cannot draw TUI")?;
+        }
+        if !envelope.flags.contains(&Flag::Seen) {
+            queue!(out, SetAttribute(Attribute::Bold)).context("cannot draw TUI")?;
+        }
+        queue!(
+            out,
+            Print(format!(
+                " {} {} {} {}",
+                flags,
+                fit(&envelope.subject, subject_width),
+                fit(&envelope.sender, SENDER_WIDTH),
+                fit(envelope.listed_date(), DATE_WIDTH)
+            )),
+            SetAttribute(Attribute::Reset)
+        )
+        .context("cannot draw TUI envelope")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_fit_text() {
+        assert_eq!("hello     ", fit("hello", 10));
+        assert_eq!("hell…", fit("hello world", 5));
+        assert_eq!("日本… ", fit("日本語です", 6));
+        assert_eq!("a b", fit("a\tb", 3));
+    }
+}
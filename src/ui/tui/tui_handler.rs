@@ -0,0 +1,350 @@
+//! Module related to TUI handling.
+//!
+//! This module gathers the event loop of the interactive TUI. Keybindings trigger the existing
+//! message handlers, the TUI steps aside while the editor or the prompts of these handlers run.
+
+use anyhow::{Context, Result};
+use crossterm::{
+    cursor::{Hide, Show},
+    event::{self, Event, KeyCode, KeyModifiers},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use log::{debug, trace};
+use std::io::{self, Write};
+
+use crate::{
+    config::Account,
+    domain::{
+        backend::{build_backend, build_sender},
+        mbox::Mbox,
+        msg::{
+            flag_handler,
+            msg_arg::{ForwardOpts, ReplyOpts, WriteOpts},
+            msg_handler, Flag,
+        },
+    },
+    output::OutputService,
+    ui::tui::{
+        tui_position::{self, ReadPositions},
+        Focus, TuiState, KEYBINDINGS,
+    },
+};
+
+/// Represents the full-screen terminal. The terminal is restored when dropped, even on error.
+struct Screen;
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode().context("cannot enable terminal raw mode")?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)
+            .context("cannot enter terminal alternate screen")?;
+        Ok(Self)
+    }
+
+    fn leave() -> Result<()> {
+        execute!(io::stdout(), Show, LeaveAlternateScreen)
+            .context("cannot leave terminal alternate screen")?;
+        terminal::disable_raw_mode().context("cannot disable terminal raw mode")
+    }
+
+    /// Leave the full screen while running the given action, then come back once the user read
+    /// its output. Errors are left to the caller, that shows them in the status bar.
+    fn suspend<T, F: FnOnce() -> Result<T>>(&mut self, action: F) -> Result<T> {
+        Self::leave()?;
+        let res = action();
+        print!("Press enter to go back to the TUI…");
+        io::stdout().flush().context("cannot flush stdout")?;
+        io::stdin()
+            .read_line(&mut String::new())
+            .context("cannot read stdin")?;
+        Self::enter()?;
+        res
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        if let Err(err) = Self::leave() {
+            debug!("cannot restore terminal: {:?}", err);
+        }
+    }
+}
+
+/// Read a line in the status bar. Returns `None` when cancelled with escape.
+fn prompt(state: &mut TuiState, label: &str) -> Result<Option<String>> {
+    let mut input = String::new();
+    loop {
+        state.status = format!("{}{}", label, input);
+        let (width, height) = terminal::size().context("cannot get terminal size")?;
+        state.draw(&mut io::stdout(), width, height)?;
+        if let Event::Key(key) = event::read().context("cannot read terminal event")? {
+            match key.code {
+                KeyCode::Enter => return Ok(Some(input)),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Remember where the reading of the message shown in the pager stopped.
+fn save_read_position(state: &mut TuiState, account: &Account, height: usize) -> Result<()> {
+    match state.pager_key.take() {
+        Some(key) => tui_position::save(
+            account,
+            &key,
+            state.pager_offset,
+            state.pager_finished(height),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Build the help shown in the pager.
+fn help() -> String {
+    let width = KEYBINDINGS
+        .iter()
+        .map(|(keys, _)| keys.len())
+        .max()
+        .unwrap_or_default();
+    let mut help = String::from("Keybindings:\n\n");
+    for (keys, desc) in KEYBINDINGS {
+        help.push_str(&format!("  {:width$}  {}\n", keys, desc, width = width));
+    }
+    help
+}
+
+/// Start the interactive TUI on the given mailbox.
+pub fn start(mbox: &Mbox, account: &Account, output: &OutputService) -> Result<()> {
+    let mut backend = build_backend(account, mbox, false);
+    let mut mboxes = backend.list_mboxes()?.0;
+    backend.logout()?;
+    let mbox_current = match mboxes.iter().position(|item| item.name == mbox.name) {
+        Some(i) => i,
+        None => {
+            mboxes.insert(0, Mbox::from(mbox.name.as_str()));
+            0
+        }
+    };
+    let mboxes = mboxes;
+
+    let names = mboxes.iter().map(|mbox| mbox.name.to_owned()).collect();
+    let mut state = TuiState::new(&account.name, names, mbox_current);
+    let mut backend = build_backend(account, &mboxes[mbox_current], false);
+    let mut sender = build_sender(account);
+
+    let mut screen = Screen::enter()?;
+    let mut reload = true;
+    loop {
+        let (width, height) = terminal::size().context("cannot get terminal size")?;
+        let page_size = TuiState::body_height(height);
+        if reload {
+            debug!("load page {} of mailbox {}", state.page, state.mbox());
+            match backend.list_envelopes(&page_size, &state.page) {
+                Ok(mut envelopes) => {
+                    for envelope in envelopes.0.iter_mut() {
+                        envelope.format_date(&account.datetime_fmt, false);
+                    }
+                    state.set_envelopes(envelopes)
+                }
+                Err(err) => state.status = format!("Error: {:#}", err),
+            }
+            reload = false;
+        }
+        state.draw(&mut io::stdout(), width, height)?;
+
+        let key = match event::read().context("cannot read terminal event")? {
+            Event::Key(key) => key,
+            Event::Resize(..) => {
+                reload = true;
+                continue;
+            }
+            _ => continue,
+        };
+        trace!("key: {:?}", key);
+
+        let id = state
+            .selected()
+            .map(|envelope| envelope.id.to_string())
+            .unwrap_or_default();
+        let on_msg = !id.is_empty() && state.focus != Focus::Mboxes;
+        let res = match (state.focus, key.code) {
+            (_, KeyCode::Char('c')) if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+            (Focus::Pager, KeyCode::Char('q')) | (Focus::Pager, KeyCode::Esc) => {
+                state.focus = Focus::Envelopes;
+                save_read_position(&mut state, account, page_size)
+            }
+            (_, KeyCode::Char('q')) | (_, KeyCode::Esc) => break,
+            (_, KeyCode::Char('?')) => {
+                let res = save_read_position(&mut state, account, page_size);
+                state.open_pager(&help());
+                res
+            }
+            (Focus::Mboxes, KeyCode::Tab) => {
+                state.focus = Focus::Envelopes;
+                Ok(())
+            }
+            (Focus::Envelopes, KeyCode::Tab) => {
+                state.focus = Focus::Mboxes;
+                Ok(())
+            }
+            (_, KeyCode::Char('j')) | (_, KeyCode::Down) => {
+                state.scroll(1, page_size);
+                Ok(())
+            }
+            (_, KeyCode::Char('k')) | (_, KeyCode::Up) => {
+                state.scroll(-1, page_size);
+                Ok(())
+            }
+            (Focus::Pager, KeyCode::Char(' ')) | (Focus::Pager, KeyCode::PageDown) => {
+                state.scroll(page_size as isize, page_size);
+                Ok(())
+            }
+            (Focus::Pager, KeyCode::PageUp) => {
+                state.scroll(-(page_size as isize), page_size);
+                Ok(())
+            }
+            (_, KeyCode::Char('n')) | (_, KeyCode::PageDown) => {
+                if state.envelopes.len() >= page_size {
+                    state.page += 1;
+                    state.cursor = 0;
+                    reload = true;
+                }
+                Ok(())
+            }
+            (_, KeyCode::Char('p')) | (_, KeyCode::PageUp) => {
+                if state.page > 0 {
+                    state.page -= 1;
+                    state.cursor = 0;
+                    reload = true;
+                }
+                Ok(())
+            }
+            (_, KeyCode::Char('g')) => {
+                reload = true;
+                Ok(())
+            }
+            (Focus::Mboxes, KeyCode::Enter) => {
+                if let Err(err) = backend.logout() {
+                    debug!("cannot logout from previous mailbox: {:?}", err);
+                }
+                backend = build_backend(account, &mboxes[state.mbox_cursor], false);
+                state.mbox_current = state.mbox_cursor;
+                state.page = 0;
+                state.cursor = 0;
+                state.focus = Focus::Envelopes;
+                reload = true;
+                Ok(())
+            }
+            (Focus::Envelopes, KeyCode::Enter) if on_msg => backend.get_msg(&id).map(|msg| {
+                let key = ReadPositions::key(&msg, state.mbox());
+                let offset = ReadPositions::load(account)
+                    .ok()
+                    .and_then(|positions| positions.get(&key))
+                    .unwrap_or_default();
+                state.open_msg_pager(&msg.join_text_parts(), key, offset, page_size);
+                if state.pager_offset > 0 {
+                    state.status = format!("Resumed at line {}", state.pager_offset + 1);
+                }
+                state.envelopes.0[state.cursor].flags.insert(Flag::Seen);
+            }),
+            (_, KeyCode::Char('w')) => {
+                reload = true;
+                screen.suspend(|| {
+                    msg_handler::write(
+                        WriteOpts::default(),
+                        account,
+                        output,
+                        backend.as_mut(),
+                        sender.as_mut(),
+                    )
+                })
+            }
+            (_, KeyCode::Char('r')) | (_, KeyCode::Char('R')) if on_msg => {
+                let all = key.code == KeyCode::Char('R');
+                reload = true;
+                screen.suspend(|| {
+                    msg_handler::reply(
+                        &id,
+                        ReplyOpts {
+                            all,
+                            ..ReplyOpts::default()
+                        },
+                        account,
+                        output,
+                        backend.as_mut(),
+                        sender.as_mut(),
+                    )
+                })
+            }
+            (_, KeyCode::Char('f')) if on_msg => screen.suspend(|| {
+                msg_handler::forward(
+                    &id,
+                    ForwardOpts::default(),
+                    account,
+                    output,
+                    backend.as_mut(),
+                    sender.as_mut(),
+                )
+            }),
+            (_, KeyCode::Char('d')) if on_msg => {
+                let mbox = &mboxes[state.mbox_current];
+                msg_handler::delete(&id, false, false, mbox, account, output, backend.as_mut()).map(
+                    |_| {
+                        state.status = format!("Message {} deleted", id);
+                        state.focus = Focus::Envelopes;
+                        reload = true;
+                    },
+                )
+            }
+            (_, KeyCode::Char('F')) if on_msg => {
+                let flagged = state.envelopes.0[state.cursor]
+                    .flags
+                    .contains(&Flag::Flagged);
+                reload = true;
+                if flagged {
+                    flag_handler::remove(
+                        &id,
+                        vec!["flagged"],
+                        false,
+                        account,
+                        output,
+                        backend.as_mut(),
+                    )
+                } else {
+                    flag_handler::add(&id, vec!["flagged"], output, backend.as_mut())
+                }
+            }
+            (_, KeyCode::Char('m')) if on_msg => match prompt(&mut state, "Move to: ")? {
+                Some(target) if !target.trim().is_empty() => {
+                    let target = target.trim();
+                    msg_handler::move_(&id, Some(target), output, backend.as_mut()).map(|_| {
+                        state.status = format!(r#"Message {} moved to "{}""#, id, target);
+                        state.focus = Focus::Envelopes;
+                        reload = true;
+                    })
+                }
+                _ => {
+                    state.status = String::new();
+                    Ok(())
+                }
+            },
+            _ => Ok(()),
+        };
+
+        if let Err(err) = res {
+            state.status = format!("Error: {:#}", err);
+        }
+    }
+    drop(screen);
+
+    let (_, height) = terminal::size().context("cannot get terminal size")?;
+    save_read_position(&mut state, account, TuiState::body_height(height))?;
+    backend.logout()?;
+    sender.close()
+}
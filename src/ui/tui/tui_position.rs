@@ -0,0 +1,113 @@
+//! Module related to read positions.
+//!
+//! The pager remembers where the reading of a long message stopped, so that re-opening it
+//! resumes there. Positions are kept locally in the account storage, indexed by the
+//! Message-ID of the message (or its mailbox and id when it has none). Messages read until the
+//! end, and the least recently read ones, are forgotten.
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Account,
+    domain::{msg::Msg, storage::build_storage},
+};
+
+const KEY: &str = "read-positions.json";
+
+/// Define the maximum number of read positions kept.
+const MAX_READ_POSITIONS: usize = 500;
+
+/// Represents the line at which the reading of a message stopped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadPosition {
+    pub key: String,
+    pub offset: usize,
+}
+
+/// Represents the read positions, the most recent first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadPositions(Vec<ReadPosition>);
+
+impl ReadPositions {
+    /// Build the key of the given message.
+    pub fn key(msg: &Msg, mbox: &str) -> String {
+        match msg.message_id {
+            Some(ref message_id) if !message_id.trim().is_empty() => message_id.trim().to_owned(),
+            _ => format!("{}:{}", mbox, msg.id),
+        }
+    }
+
+    /// Load the read positions of the given account. A missing or corrupted item is treated as
+    /// empty, messages being then read from the top.
+    pub fn load(account: &Account) -> Result<Self> {
+        match build_storage(account)?.get(KEY)? {
+            Some(content) => Ok(serde_json::from_slice(&content).unwrap_or_else(|err| {
+                warn!("cannot parse {}: {}", KEY, err);
+                Self::default()
+            })),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, account: &Account) -> Result<()> {
+        let content = serde_json::to_vec(self).context("cannot serialize read positions")?;
+        build_storage(account)?
+            .set(KEY, &content)
+            .context("cannot save read positions")
+    }
+
+    pub fn get(&self, key: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .find(|position| position.key == key)
+            .map(|position| position.offset)
+    }
+
+    /// Remember the given offset, or forget the message when read from the top or until the
+    /// end.
+    pub fn set(&mut self, key: &str, offset: usize, finished: bool) {
+        self.0.retain(|position| position.key != key);
+        if offset > 0 && !finished {
+            self.0.insert(
+                0,
+                ReadPosition {
+                    key: key.to_owned(),
+                    offset,
+                },
+            );
+            self.0.truncate(MAX_READ_POSITIONS);
+        }
+    }
+}
+
+/// Remember the read position of the given message, in the read positions of the account.
+pub fn save(account: &Account, key: &str, offset: usize, finished: bool) -> Result<()> {
+    let _lock = account.lock_cache()?;
+    let mut positions = ReadPositions::load(account)?;
+    positions.set(key, offset, finished);
+    positions.save(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_remember_read_positions() {
+        let mut positions = ReadPositions::default();
+        positions.set("<a@local>", 42, false);
+        positions.set("<b@local>", 7, false);
+        assert_eq!(Some(42), positions.get("<a@local>"));
+
+        positions.set("<a@local>", 84, false);
+        assert_eq!(Some(84), positions.get("<a@local>"));
+        assert_eq!("<a@local>", positions.0[0].key);
+
+        positions.set("<a@local>", 120, true);
+        positions.set("<b@local>", 0, false);
+        assert_eq!(None, positions.get("<a@local>"));
+        assert_eq!(None, positions.get("<b@local>"));
+    }
+}